@@ -68,6 +68,11 @@ pub struct UserStackMapEntry {
 )]
 pub struct UserStackMap {
     by_type: SmallVec<[(ir::Type, CompoundBitSet); 1]>,
+
+    // The offset from the SP to the start of the sized stack slots area. This
+    // isn't known until the function's frame layout is finalized at emission
+    // time, so this is `None` until then.
+    sp_to_sized_stack_slots: Option<u32>,
 }
 
 impl UserStackMap {
@@ -96,6 +101,41 @@ impl UserStackMap {
             by_type[index].1.insert(offset);
         }
 
-        UserStackMap { by_type }
+        UserStackMap {
+            by_type,
+            sp_to_sized_stack_slots: None,
+        }
+    }
+
+    /// Finalize this stack map with the offset from the SP to the start of the
+    /// sized stack slots area, which is only known once the function's frame
+    /// layout has been computed.
+    pub(crate) fn finalize(&mut self, sp_to_sized_stack_slots: u32) {
+        debug_assert!(self.sp_to_sized_stack_slots.is_none());
+        self.sp_to_sized_stack_slots = Some(sp_to_sized_stack_slots);
+    }
+
+    /// Iterate over the entries in this stack map.
+    ///
+    /// Yields pairs of the type of GC value that is stored in a stack slot and
+    /// the offset of that stack slot from the SP at this stack map's
+    /// associated safepoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this stack map has not been finalized, i.e. it was not
+    /// obtained from a `MachBufferFinalized`.
+    pub fn entries(&self) -> impl Iterator<Item = (ir::Type, u32)> + '_ {
+        let sp_to_sized_stack_slots = self
+            .sp_to_sized_stack_slots
+            .expect("stack map should be finalized");
+        self.by_type.iter().flat_map(move |(ty, bitset)| {
+            bitset.iter().map(move |slot_offset| {
+                (
+                    *ty,
+                    sp_to_sized_stack_slots + u32::try_from(slot_offset).unwrap(),
+                )
+            })
+        })
     }
 }
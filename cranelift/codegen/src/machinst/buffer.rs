@@ -1690,9 +1690,10 @@ impl<I: VCodeInst> MachBuffer<I> {
         &mut self,
         emit_state: &I::State,
         return_addr: CodeOffset,
-        stack_map: ir::UserStackMap,
+        mut stack_map: ir::UserStackMap,
     ) {
         let span = emit_state.frame_layout().active_size();
+        stack_map.finalize(emit_state.frame_layout().outgoing_args_size);
         trace!("Adding user stack map @ {return_addr:#x} spanning {span} bytes: {stack_map:?}");
 
         debug_assert!(
@@ -1766,6 +1767,15 @@ impl<T: CompilePhase> MachBufferFinalized<T> {
         mem::take(&mut self.stack_maps)
     }
 
+    /// Get the user stack maps for this code.
+    ///
+    /// Each entry is a triple of the return address of the safepoint that the
+    /// stack map is associated with, the size in bytes of the frame that the
+    /// stack map spans (from the SP up to the FP), and the stack map itself.
+    pub fn user_stack_maps(&self) -> &[(CodeOffset, u32, ir::UserStackMap)] {
+        &self.user_stack_maps
+    }
+
     /// Get the list of call sites for this code.
     pub fn call_sites(&self) -> &[MachCallSite] {
         &self.call_sites[..]
@@ -562,10 +562,10 @@ impl<'a> FunctionBuilder<'a> {
     /// Declare that the given value is a GC reference that requires inclusion
     /// in a stack map when it is live across GC safepoints.
     ///
-    /// At the current moment, values that need inclusion in stack maps are
-    /// spilled before safepoints, but they are not reloaded afterwards. This
-    /// means that moving GCs are not yet supported, however the intention is to
-    /// add this support in the near future.
+    /// Values that need inclusion in stack maps are spilled to the stack
+    /// before safepoints and every use after a safepoint is reloaded from the
+    /// stack, so the collector may update the value in place, e.g. when moving
+    /// the referenced object.
     ///
     /// # Panics
     ///
@@ -388,9 +388,12 @@ impl FunctionBuilder<'_> {
                 vals.extend(pos.func.dfg.inst_values(inst));
                 let mut replaced_any = false;
                 for val in &mut vals {
-                    if let Some(slot) = stack_slots.get(val) {
+                    // Aliases of a needs-stack-map value must be reloaded as
+                    // well, since they are the same value.
+                    let resolved = pos.func.dfg.resolve_aliases(*val);
+                    if let Some(slot) = stack_slots.get(&resolved) {
                         replaced_any = true;
-                        let ty = pos.func.dfg.value_type(*val);
+                        let ty = pos.func.dfg.value_type(resolved);
                         let old_val = *val;
                         *val = pos.ins().stack_load(ty, *slot, 0);
                         log::trace!(
@@ -1511,31 +1514,35 @@ block0(v0: i32):
     brif v0, block1, block2
 
 block1:
-    call fn0(v2), stack_map=[i32 @ ss0+0]  ; v2 = 42
-    call fn0(v2)  ; v2 = 42
-    v3 = iconst.i32 36
-    stack_store v3, ss0  ; v3 = 36
     v8 = stack_load.i32 ss0
     call fn0(v8), stack_map=[i32 @ ss0+0]
     v9 = stack_load.i32 ss0
-    jump block3(v9)
+    call fn0(v9)
+    v3 = iconst.i32 36
+    stack_store v3, ss0  ; v3 = 36
+    v10 = stack_load.i32 ss0
+    call fn0(v10), stack_map=[i32 @ ss0+0]
+    v11 = stack_load.i32 ss0
+    jump block3(v11)
 
 block2:
-    call fn0(v4), stack_map=[i32 @ ss0+0]  ; v4 = 42
-    call fn0(v4), stack_map=[i32 @ ss0+0]  ; v4 = 42
+    v12 = stack_load.i32 ss0
+    call fn0(v12), stack_map=[i32 @ ss0+0]
+    v13 = stack_load.i32 ss0
+    call fn0(v13), stack_map=[i32 @ ss0+0]
     v5 = iconst.i32 36
     stack_store v5, ss1  ; v5 = 36
-    v10 = stack_load.i32 ss1
-    call fn0(v10), stack_map=[i32 @ ss0+0, i32 @ ss1+0]
-    v11 = stack_load.i32 ss1
-    jump block3(v11)
+    v14 = stack_load.i32 ss1
+    call fn0(v14), stack_map=[i32 @ ss0+0, i32 @ ss1+0]
+    v15 = stack_load.i32 ss1
+    jump block3(v15)
 
 block3(v6: i32):
     stack_store v6, ss0
-    v12 = stack_load.i32 ss0
-    call fn0(v12), stack_map=[i32 @ ss0+0]
-    v13 = stack_load.i32 ss0
-    return v13
+    v16 = stack_load.i32 ss0
+    call fn0(v16), stack_map=[i32 @ ss0+0]
+    v17 = stack_load.i32 ss0
+    return v17
 }
             "#
             .trim()
@@ -1683,6 +1690,109 @@ block0(v0: i32):
     v2 = stack_load.i32 ss0
     v3 = stack_load.i32 ss1
     return v2, v3
+}
+            "#
+            .trim()
+        );
+    }
+
+    #[test]
+    fn needs_stack_map_and_value_aliases() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(ir::types::I32));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ir::UserFuncName::testcase("sample"), sig);
+        let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+        let name = builder
+            .func
+            .declare_imported_user_function(ir::UserExternalName {
+                namespace: 0,
+                index: 0,
+            });
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(ir::types::I32));
+        let signature = builder.func.import_signature(sig);
+        let func_ref = builder.import_function(ir::ExtFuncData {
+            name: ir::ExternalName::user(name),
+            signature,
+            colocated: true,
+        });
+
+        // Using a variable in a block that is not yet sealed creates a block
+        // parameter, which becomes an alias of `v0` once the block is sealed
+        // and all of its predecessors turn out to pass `v0`. Uses of that
+        // alias after a safepoint must be reloaded from `v0`'s stack slot, just
+        // like uses of `v0` itself.
+        //
+        //     block0(v0):
+        //       brif v0, block1, block2
+        //     block1:
+        //       jump block3
+        //     block2:
+        //       jump block3
+        //     block3:
+        //       call $foo(v0)
+        //       call $foo(v0)
+        //       return
+        let var = Variable::from_u32(0);
+        builder.declare_var(var, ir::types::I32);
+
+        let block0 = builder.create_block();
+        let block1 = builder.create_block();
+        let block2 = builder.create_block();
+        let block3 = builder.create_block();
+
+        builder.append_block_params_for_function_params(block0);
+        builder.switch_to_block(block0);
+        let v0 = builder.func.dfg.block_params(block0)[0];
+        builder.declare_value_needs_stack_map(v0);
+        builder.def_var(var, v0);
+        builder.ins().brif(v0, block1, &[], block2, &[]);
+
+        builder.switch_to_block(block1);
+        builder.ins().jump(block3, &[]);
+
+        builder.switch_to_block(block2);
+        builder.ins().jump(block3, &[]);
+
+        builder.switch_to_block(block3);
+        let val = builder.use_var(var);
+        builder.ins().call(func_ref, &[val]);
+        builder.ins().call(func_ref, &[val]);
+        builder.ins().return_(&[]);
+
+        builder.seal_all_blocks();
+        builder.finalize();
+
+        eprintln!("Actual = {}", func.display());
+        assert_eq!(
+            func.display().to_string().trim(),
+            r#"
+function %sample(i32) system_v {
+    ss0 = explicit_slot 4, align = 4
+    sig0 = (i32) system_v
+    fn0 = colocated u0:0 sig0
+
+block0(v0: i32):
+    v1 -> v0
+    stack_store v0, ss0
+    v2 = stack_load.i32 ss0
+    brif v2, block1, block2
+
+block1:
+    jump block3
+
+block2:
+    jump block3
+
+block3:
+    v3 = stack_load.i32 ss0
+    call fn0(v3), stack_map=[i32 @ ss0+0]
+    v4 = stack_load.i32 ss0
+    call fn0(v4)
+    return
 }
             "#
             .trim()
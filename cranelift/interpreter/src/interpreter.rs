@@ -11,8 +11,8 @@ use crate::step::{step, ControlFlow, StepError};
 use crate::value::{DataValueExt, ValueError};
 use cranelift_codegen::data_value::DataValue;
 use cranelift_codegen::ir::{
-    ArgumentPurpose, Block, Endianness, ExternalName, FuncRef, Function, GlobalValue,
-    GlobalValueData, LibCall, MemFlags, StackSlot, TrapCode, Type,
+    ArgumentPurpose, Block, DynamicStackSlot, Endianness, ExternalName, FuncRef, Function,
+    GlobalValue, GlobalValueData, LibCall, MemFlags, StackSlot, TrapCode, Type,
 };
 use log::trace;
 use smallvec::SmallVec;
@@ -194,6 +194,29 @@ pub enum InterpreterError {
 pub type LibCallValues = SmallVec<[DataValue; 1]>;
 pub type LibCallHandler = fn(LibCall, LibCallValues) -> Result<LibCallValues, TrapCode>;
 
+/// The [LibCallHandler] used unless another one is registered, which implements the libcalls
+/// that only operate on their arguments.
+///
+/// Other libcalls, such as `Memcpy` or `ElfTlsGetAddr`, need access to memory the interpreter
+/// doesn't have and trap with [TrapCode::UnreachableCodeReached].
+pub fn default_libcall_handler(
+    libcall: LibCall,
+    args: LibCallValues,
+) -> Result<LibCallValues, TrapCode> {
+    use LibCall::*;
+    let result = match (libcall, &args[..]) {
+        (CeilF32 | CeilF64, [a]) => a.clone().ceil(),
+        (FloorF32 | FloorF64, [a]) => a.clone().floor(),
+        (TruncF32 | TruncF64, [a]) => a.clone().trunc(),
+        (NearestF32 | NearestF64, [a]) => a.clone().nearest(),
+        (FmaF32 | FmaF64, [a, b, c]) => a.clone().fma(b.clone(), c.clone()),
+        _ => return Err(TrapCode::UnreachableCodeReached),
+    };
+    result
+        .map(|value| smallvec::smallvec![value])
+        .map_err(|_| TrapCode::BadSignature)
+}
+
 /// Maintains the [Interpreter]'s state, implementing the [State] trait.
 pub struct InterpreterState<'a> {
     pub functions: FunctionStore<'a>,
@@ -206,6 +229,23 @@ pub struct InterpreterState<'a> {
     pub native_endianness: Endianness,
 }
 
+/// The stack space of a frame: the sized stack slots followed by the dynamic stack slots.
+fn frame_size(function: &Function) -> usize {
+    let dynamic_size: usize = function
+        .dynamic_stack_slots
+        .keys()
+        .map(|slot| dynamic_stack_slot_size(function, slot))
+        .sum();
+    function.fixed_stack_size() as usize + dynamic_size
+}
+
+/// Dynamic vector types have a scale of one in the interpreter, so a dynamic stack slot is the
+/// size of its base vector type.
+fn dynamic_stack_slot_size(function: &Function, slot: DynamicStackSlot) -> usize {
+    let dyn_ty = function.dynamic_stack_slots[slot].dyn_ty;
+    function.dfg.dynamic_types[dyn_ty].base_vector_ty.bytes() as usize
+}
+
 impl Default for InterpreterState<'_> {
     fn default() -> Self {
         let native_endianness = if cfg!(target_endian = "little") {
@@ -215,7 +255,7 @@ impl Default for InterpreterState<'_> {
         };
         Self {
             functions: FunctionStore::default(),
-            libcall_handler: |_, _| Err(TrapCode::UnreachableCodeReached),
+            libcall_handler: default_libcall_handler,
             frame_stack: vec![],
             frame_offset: 0,
             stack: Vec::with_capacity(1024),
@@ -252,12 +292,12 @@ impl<'a> State<'a> for InterpreterState<'a> {
 
     fn push_frame(&mut self, function: &'a Function) {
         if let Some(frame) = self.frame_stack.iter().last() {
-            self.frame_offset += frame.function().fixed_stack_size() as usize;
+            self.frame_offset += frame_size(frame.function());
         }
 
         // Grow the stack by the space necessary for this frame
         self.stack
-            .extend(iter::repeat(0).take(function.fixed_stack_size() as usize));
+            .extend(iter::repeat(0).take(frame_size(function)));
 
         self.frame_stack.push(Frame::new(function));
     }
@@ -265,11 +305,11 @@ impl<'a> State<'a> for InterpreterState<'a> {
         if let Some(frame) = self.frame_stack.pop() {
             // Shorten the stack after exiting the frame
             self.stack
-                .truncate(self.stack.len() - frame.function().fixed_stack_size() as usize);
+                .truncate(self.stack.len() - frame_size(frame.function()));
 
             // Reset frame_offset to the start of this function
             if let Some(frame) = self.frame_stack.iter().last() {
-                self.frame_offset -= frame.function().fixed_stack_size() as usize;
+                self.frame_offset -= frame_size(frame.function());
             }
         }
     }
@@ -318,6 +358,53 @@ impl<'a> State<'a> for InterpreterState<'a> {
         Address::from_parts(size, AddressRegion::Stack, 0, final_offset)
     }
 
+    fn dynamic_stack_address(
+        &self,
+        size: AddressSize,
+        slot: DynamicStackSlot,
+    ) -> Result<Address, MemoryError> {
+        let function = self.get_current_function();
+
+        // Dynamic stack slots are placed after all of the sized stack slots
+        let slot_offset: u64 = function
+            .dynamic_stack_slots
+            .keys()
+            .filter(|k| k < &slot)
+            .map(|k| dynamic_stack_slot_size(function, k) as u64)
+            .sum();
+
+        let final_offset =
+            self.frame_offset as u64 + function.fixed_stack_size() as u64 + slot_offset;
+        Address::from_parts(size, AddressRegion::Stack, 0, final_offset)
+    }
+
+    fn frame_pointer(&self, size: AddressSize) -> Result<Address, MemoryError> {
+        Address::from_parts(size, AddressRegion::Stack, 0, self.frame_offset as u64)
+    }
+
+    fn stack_pointer(&self, size: AddressSize) -> Result<Address, MemoryError> {
+        // The stack grows upwards, so the current frame ends at the top of the stack
+        Address::from_parts(size, AddressRegion::Stack, 0, self.stack.len() as u64)
+    }
+
+    fn return_address(&self, size: AddressSize) -> Result<Option<Address>, MemoryError> {
+        // Instructions don't have addresses, so this is the address of the calling function
+        let num_frames = self.frame_stack.len();
+        let caller = match num_frames.checked_sub(2) {
+            Some(index) => self.frame_stack[index].function(),
+            None => return Ok(None),
+        };
+        // TODO: This is not optimal since we are looking up by string name
+        let index = self.functions.index_of(&caller.name.to_string()).unwrap();
+        Address::from_parts(
+            size,
+            AddressRegion::Function,
+            AddressFunctionEntry::UserFunction as u64,
+            index.as_u32() as u64,
+        )
+        .map(Some)
+    }
+
     fn checked_load(
         &self,
         addr: Address,
@@ -340,7 +427,8 @@ impl<'a> State<'a> for InterpreterState<'a> {
 
                 &self.stack[addr_start..addr_end]
             }
-            _ => unimplemented!(),
+            // Only the stack holds data in the interpreter.
+            _ => return Err(MemoryError::InvalidAddress(DataValue::try_from(addr)?)),
         };
 
         // Aligned flag is set and address is not aligned for the given type
@@ -376,7 +464,8 @@ impl<'a> State<'a> for InterpreterState<'a> {
 
                 &mut self.stack[addr_start..addr_end]
             }
-            _ => unimplemented!(),
+            // Only the stack holds data in the interpreter.
+            _ => return Err(MemoryError::InvalidAddress(DataValue::try_from(addr)?)),
         };
 
         // Aligned flag is set and address is not aligned for the given type
@@ -396,19 +485,27 @@ impl<'a> State<'a> for InterpreterState<'a> {
         name: &ExternalName,
     ) -> Result<Address, MemoryError> {
         let curr_func = self.get_current_function();
+        let unknown_symbol =
+            || MemoryError::UnknownSymbol(name.display(Some(&curr_func.params)).to_string());
         let (entry, index) = match name {
             ExternalName::User(username) => {
                 let ext_name = &curr_func.params.user_named_funcs()[*username];
 
                 // TODO: This is not optimal since we are looking up by string name
-                let index = self.functions.index_of(&ext_name.to_string()).unwrap();
+                let index = self
+                    .functions
+                    .index_of(&ext_name.to_string())
+                    .ok_or_else(unknown_symbol)?;
 
                 (AddressFunctionEntry::UserFunction, index.as_u32())
             }
 
             ExternalName::TestCase(testname) => {
                 // TODO: This is not optimal since we are looking up by string name
-                let index = self.functions.index_of(&testname.to_string()).unwrap();
+                let index = self
+                    .functions
+                    .index_of(&testname.to_string())
+                    .ok_or_else(unknown_symbol)?;
 
                 (AddressFunctionEntry::UserFunction, index.as_u32())
             }
@@ -422,7 +519,8 @@ impl<'a> State<'a> for InterpreterState<'a> {
 
                 (AddressFunctionEntry::LibCall, index as u32)
             }
-            _ => unimplemented!("function_address: {:?}", name),
+            // Known symbols refer to data, which the interpreter doesn't have.
+            ExternalName::KnownSymbol(_) => return Err(unknown_symbol()),
         };
 
         Address::from_parts(size, AddressRegion::Function, entry as u64, index as u64)
@@ -524,8 +622,31 @@ impl<'a> State<'a> for InterpreterState<'a> {
                         action_stack.push(ResolveAction::Add(dv));
                         action_stack.push(ResolveAction::Resolve(base));
                     }
-                    GlobalValueData::Symbol { .. } => unimplemented!(),
-                    GlobalValueData::DynScaleTargetConst { .. } => unimplemented!(),
+                    // Only functions have addresses in the interpreter, and they aren't
+                    // thread local.
+                    GlobalValueData::Symbol {
+                        ref name,
+                        offset,
+                        tls: false,
+                        ..
+                    } => {
+                        // FIXME handle non-64bit systems
+                        let addr = self.function_address(AddressSize::_64, name)?;
+                        let offset: i64 = offset.into();
+                        current_val = DataValue::try_from(addr)?
+                            .add(DataValue::I64(offset))
+                            .map_err(|_| MemoryError::InvalidAddress(DataValue::I64(offset)))?;
+                    }
+                    GlobalValueData::Symbol { ref name, .. } => {
+                        return Err(MemoryError::UnknownSymbol(
+                            name.display(Some(&func.params)).to_string(),
+                        ))
+                    }
+                    GlobalValueData::DynScaleTargetConst { .. } => {
+                        // Dynamic vector types have a scale of one in the interpreter.
+                        // FIXME handle non-64bit systems
+                        current_val = DataValue::I64(1);
+                    }
                 },
                 Some(ResolveAction::Add(dv)) => {
                     current_val = current_val
@@ -1067,4 +1188,169 @@ mod tests {
             ControlFlow::Trap(CraneliftTrap::User(TrapCode::HeapMisaligned))
         );
     }
+
+    #[test]
+    fn frame_and_stack_pointers() {
+        let code = "
+        function %callee() -> i64, i64, i64 {
+            ss0 = explicit_slot 8
+            ss1 = explicit_slot 4
+
+        block0:
+            v0 = get_frame_pointer.i64
+            v1 = get_stack_pointer.i64
+            v2 = stack_addr.i64 ss0
+            return v0, v1, v2
+        }
+
+        function %caller() -> i8 {
+            ss0 = explicit_slot 16
+            fn0 = %callee() -> i64, i64, i64
+
+        block0:
+            v0 = get_stack_pointer.i64
+            v1, v2, v3 = call fn0()
+            ; The frame of the callee starts where the frame of the caller ends.
+            v4 = icmp eq v0, v1
+            ; The first stack slot is at the start of the frame.
+            v5 = icmp eq v1, v3
+            ; The frame holds both stack slots.
+            v6 = isub v2, v1
+            v7 = icmp_imm eq v6, 12
+            v8 = band v4, v5
+            v9 = band v8, v7
+            return v9
+        }";
+
+        let mut env = FunctionStore::default();
+        let funcs = parse_functions(code).unwrap();
+        for func in &funcs {
+            env.add(func.name.to_string(), func);
+        }
+
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .call_by_name("%caller", &[])
+            .unwrap();
+
+        assert_eq!(result, ControlFlow::Return(smallvec![DataValue::I8(1)]));
+    }
+
+    #[test]
+    fn return_address() {
+        let code = "
+        function %callee() -> i64 {
+        block0:
+            v0 = get_return_address.i64
+            return v0
+        }
+
+        function %caller() -> i8 {
+            fn0 = %callee() -> i64
+            fn1 = %caller() -> i8
+
+        block0:
+            v0 = call fn0()
+            v1 = func_addr.i64 fn1
+            v2 = icmp eq v0, v1
+            return v2
+        }";
+
+        let mut env = FunctionStore::default();
+        let funcs = parse_functions(code).unwrap();
+        for func in &funcs {
+            env.add(func.name.to_string(), func);
+        }
+
+        // The callee returns to the function that called it.
+        let state = InterpreterState::default().with_function_store(env.clone());
+        let result = Interpreter::new(state)
+            .call_by_name("%caller", &[])
+            .unwrap();
+        assert_eq!(result, ControlFlow::Return(smallvec![DataValue::I8(1)]));
+
+        // There's no return address when called from outside the interpreter.
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state)
+            .call_by_name("%callee", &[])
+            .unwrap();
+        assert_eq!(result, ControlFlow::Return(smallvec![DataValue::I64(0)]));
+    }
+
+    #[test]
+    fn known_symbol_call_errors() {
+        let code = "function %test() {
+            fn0 = %ElfGlobalOffsetTable()
+
+        block0:
+            call fn0()
+            return
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = FunctionStore::default();
+        env.add(func.name.to_string(), &func);
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state).call_by_name("%test", &[]);
+
+        match result {
+            Err(InterpreterError::StepError(StepError::MemoryError(
+                MemoryError::UnknownSymbol(_),
+            ))) => {}
+            _ => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn known_symbol_value_errors() {
+        let code = "function %test() -> i64 {
+            gv0 = symbol %ElfGlobalOffsetTable
+
+        block0:
+            v0 = symbol_value.i64 gv0
+            return v0
+        }";
+
+        let func = parse_functions(code).unwrap().into_iter().next().unwrap();
+        let mut env = FunctionStore::default();
+        env.add(func.name.to_string(), &func);
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state).call_by_name("%test", &[]);
+
+        match result {
+            Err(InterpreterError::StepError(StepError::MemoryError(
+                MemoryError::UnknownSymbol(_),
+            ))) => {}
+            _ => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn symbol_value_of_function() {
+        let code = "function %callee() -> i8 {
+        block0:
+            v0 = iconst.i8 7
+            return v0
+        }
+
+        function %test() -> i8 {
+            gv0 = symbol %callee
+            sig0 = () -> i8
+
+        block0:
+            v0 = symbol_value.i64 gv0
+            v1 = call_indirect sig0, v0()
+            return v1
+        }";
+
+        let mut env = FunctionStore::default();
+        let funcs = parse_functions(code).unwrap();
+        for func in &funcs {
+            env.add(func.name.to_string(), func);
+        }
+        let state = InterpreterState::default().with_function_store(env);
+        let result = Interpreter::new(state).call_by_name("%test", &[]).unwrap();
+
+        assert_eq!(result, ControlFlow::Return(smallvec![DataValue::I8(7)]));
+    }
 }
@@ -5,8 +5,8 @@ use crate::frame::Frame;
 use crate::interpreter::LibCallHandler;
 use cranelift_codegen::data_value::DataValue;
 use cranelift_codegen::ir::{
-    types, ExternalName, FuncRef, Function, GlobalValue, LibCall, MemFlags, Signature, StackSlot,
-    Type, Value,
+    types, DynamicStackSlot, ExternalName, FuncRef, Function, GlobalValue, LibCall, MemFlags,
+    Signature, StackSlot, Type, Value,
 };
 use cranelift_codegen::isa::CallConv;
 use smallvec::SmallVec;
@@ -49,6 +49,19 @@ pub trait State<'a> {
         slot: StackSlot,
         offset: u64,
     ) -> Result<Address, MemoryError>;
+    /// Computes the stack address for this dynamic stack slot.
+    fn dynamic_stack_address(
+        &self,
+        size: AddressSize,
+        slot: DynamicStackSlot,
+    ) -> Result<Address, MemoryError>;
+    /// Computes the address of the start of the current frame's stack space.
+    fn frame_pointer(&self, size: AddressSize) -> Result<Address, MemoryError>;
+    /// Computes the address of the end of the current frame's stack space.
+    fn stack_pointer(&self, size: AddressSize) -> Result<Address, MemoryError>;
+    /// Computes the address of the function that the current function returns to, or `None` if
+    /// it was called from outside of the interpreter.
+    fn return_address(&self, size: AddressSize) -> Result<Option<Address>, MemoryError>;
     /// Retrieve a value `V` from memory at the given `address`, checking if it belongs either to the
     /// stack or to one of the heaps; the number of bytes loaded corresponds to the specified [Type].
     fn checked_load(
@@ -140,4 +153,6 @@ pub enum MemoryError {
     MisalignedLoad { addr: Address, load_size: usize },
     #[error("Store of {store_size} bytes is misaligned at address {addr:?}")]
     MisalignedStore { addr: Address, store_size: usize },
+    #[error("Symbol {0} has no address in the interpreter")]
+    UnknownSymbol(String),
 }
@@ -51,7 +51,7 @@ where
     I: InstructionContext,
 {
     let inst = inst_context.data();
-    let ctrl_ty = inst_context.controlling_type().unwrap();
+    let ctrl_ty = fixed_vector_type(inst_context.controlling_type().unwrap());
     trace!(
         "Step: {}{}",
         inst.opcode(),
@@ -171,6 +171,8 @@ where
             .expect("load with notrap flag should not trap"),
         MemoryError::MisalignedLoad { .. } => TrapCode::HeapMisaligned,
         MemoryError::MisalignedStore { .. } => TrapCode::HeapMisaligned,
+        // Returned as a `StepError` before getting here, see `symbol_or_memtrap`.
+        MemoryError::UnknownSymbol(_) => TrapCode::HeapOutOfBounds,
     };
 
     // Assigns or traps depending on the value of the result
@@ -185,6 +187,13 @@ where
         Err(e) => ControlFlow::Trap(CraneliftTrap::User(memerror_to_trap(e))),
     };
 
+    // Like `assign_or_memtrap`, but symbols that can't be resolved are an error of the
+    // interpreter rather than a trap of the program.
+    let symbol_or_memtrap = |res: Result<DataValue, MemoryError>| match res {
+        Err(e @ MemoryError::UnknownSymbol(_)) => Err(StepError::MemoryError(e)),
+        res => Ok(assign_or_memtrap(res)),
+    };
+
     let calculate_addr =
         |addr_ty: Type, imm: DataValue, args: SmallVec<[DataValue; 1]>| -> ValueResult<u64> {
             let imm = imm.convert(ValueConversionKind::ZeroExtend(addr_ty))?;
@@ -200,7 +209,6 @@ where
     // instruction's results.
     let unary =
         |op: fn(DataValue) -> ValueResult<DataValue>, arg: DataValue| -> ValueResult<ControlFlow> {
-            let ctrl_ty = inst_context.controlling_type().unwrap();
             let res = unary_arith(arg, ctrl_ty, op)?;
             Ok(assign(res))
         };
@@ -211,7 +219,6 @@ where
                   left: DataValue,
                   right: DataValue|
      -> ValueResult<ControlFlow> {
-        let ctrl_ty = inst_context.controlling_type().unwrap();
         let res = binary_arith(left, right, ctrl_ty, op)?;
         Ok(assign(res))
     };
@@ -221,7 +228,6 @@ where
                            left: DataValue,
                            right: DataValue|
      -> ValueResult<ControlFlow> {
-        let ctrl_ty = inst_context.controlling_type().unwrap();
         let res = binary_arith(left, right, ctrl_ty, op);
         assign_or_trap(res)
     };
@@ -283,13 +289,6 @@ where
             Ok(match func_ref {
                 InterpreterFunctionRef::Function(func) => make_ctrl_flow(func, args),
                 InterpreterFunctionRef::LibCall(libcall) => {
-                    debug_assert!(
-                        !matches!(
-                            inst.opcode(),
-                            Opcode::ReturnCall | Opcode::ReturnCallIndirect,
-                        ),
-                        "Cannot tail call to libcalls"
-                    );
                     let libcall_handler = state.get_libcall_handler();
 
                     // We don't transfer control to a libcall, we just execute it and return the results
@@ -300,10 +299,16 @@ where
                     };
 
                     // Check that what the handler returned is what we expect.
-                    if validate_signature_params(&signature.returns[..], &res[..]) {
-                        ControlFlow::Assign(res)
-                    } else {
+                    if !validate_signature_params(&signature.returns[..], &res[..]) {
                         ControlFlow::Trap(CraneliftTrap::User(TrapCode::BadSignature))
+                    } else if matches!(
+                        inst.opcode(),
+                        Opcode::ReturnCall | Opcode::ReturnCallIndirect
+                    ) {
+                        // A tail call to a libcall returns its results to the caller.
+                        ControlFlow::Return(res)
+                    } else {
+                        ControlFlow::Assign(res)
                     }
                 }
             })
@@ -383,7 +388,11 @@ where
                     InterpreterFunctionRef::Function(function)
                 }
                 ExternalName::LibCall(libcall) => InterpreterFunctionRef::LibCall(libcall),
-                ExternalName::KnownSymbol(_) => unimplemented!(),
+                // Known symbols refer to data, so there is no function to call.
+                ExternalName::KnownSymbol(_) => {
+                    let name = ext_data.name.display(Some(&curr_func.params)).to_string();
+                    return Err(MemoryError::UnknownSymbol(name).into());
+                }
             };
 
             let make_control_flow = match inst.opcode() {
@@ -428,13 +437,13 @@ where
                 .ok_or(StepError::UnknownFunction(func_ref))?;
 
             let addr_ty = inst_context.controlling_type().unwrap();
-            assign_or_memtrap({
+            symbol_or_memtrap({
                 AddressSize::try_from(addr_ty).and_then(|addr_size| {
                     let addr = state.function_address(addr_size, &ext_data.name)?;
                     let dv = DataValue::try_from(addr)?;
                     Ok(dv.into())
                 })
-            })
+            })?
         }
         Opcode::Load
         | Opcode::Uload8
@@ -449,21 +458,28 @@ where
         | Opcode::Sload16x4
         | Opcode::Uload32x2
         | Opcode::Sload32x2 => {
-            let ctrl_ty = inst_context.controlling_type().unwrap();
+            // The widening vector loads are controlled by the address type instead.
+            let res_ty = match inst.opcode() {
+                Opcode::Uload8x8 | Opcode::Sload8x8 => types::I16X8,
+                Opcode::Uload16x4 | Opcode::Sload16x4 => types::I32X4,
+                Opcode::Uload32x2 | Opcode::Sload32x2 => types::I64X2,
+                _ => ctrl_ty,
+            };
+            let lane_ty = res_ty.lane_type();
             let (load_ty, kind) = match inst.opcode() {
                 Opcode::Load => (ctrl_ty, None),
-                Opcode::Uload8 => (types::I8, Some(ValueConversionKind::ZeroExtend(ctrl_ty))),
-                Opcode::Sload8 => (types::I8, Some(ValueConversionKind::SignExtend(ctrl_ty))),
-                Opcode::Uload16 => (types::I16, Some(ValueConversionKind::ZeroExtend(ctrl_ty))),
-                Opcode::Sload16 => (types::I16, Some(ValueConversionKind::SignExtend(ctrl_ty))),
-                Opcode::Uload32 => (types::I32, Some(ValueConversionKind::ZeroExtend(ctrl_ty))),
-                Opcode::Sload32 => (types::I32, Some(ValueConversionKind::SignExtend(ctrl_ty))),
-                Opcode::Uload8x8
-                | Opcode::Sload8x8
-                | Opcode::Uload16x4
-                | Opcode::Sload16x4
-                | Opcode::Uload32x2
-                | Opcode::Sload32x2 => unimplemented!(),
+                Opcode::Uload8 => (types::I8, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload8 => (types::I8, Some(ValueConversionKind::SignExtend(lane_ty))),
+                Opcode::Uload16 => (types::I16, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload16 => (types::I16, Some(ValueConversionKind::SignExtend(lane_ty))),
+                Opcode::Uload32 => (types::I32, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload32 => (types::I32, Some(ValueConversionKind::SignExtend(lane_ty))),
+                Opcode::Uload8x8 => (types::I8X8, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload8x8 => (types::I8X8, Some(ValueConversionKind::SignExtend(lane_ty))),
+                Opcode::Uload16x4 => (types::I16X4, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload16x4 => (types::I16X4, Some(ValueConversionKind::SignExtend(lane_ty))),
+                Opcode::Uload32x2 => (types::I32X2, Some(ValueConversionKind::ZeroExtend(lane_ty))),
+                Opcode::Sload32x2 => (types::I32X2, Some(ValueConversionKind::SignExtend(lane_ty))),
                 _ => unreachable!(),
             };
 
@@ -477,7 +493,13 @@ where
             match (loaded, kind) {
                 (ControlFlow::Assign(ret), Some(c)) => ControlFlow::Assign(
                     ret.into_iter()
-                        .map(|loaded| loaded.convert(c.clone()))
+                        .map(|loaded| {
+                            let lanes = extractlanes(&loaded, load_ty)?
+                                .into_iter()
+                                .map(|lane| lane.convert(c.clone()))
+                                .collect::<ValueResult<SimdVec<DataValue>>>()?;
+                            vectorizelanes(&lanes, res_ty)
+                        })
                         .collect::<ValueResult<SmallVec<[DataValue; 1]>>>()?,
                 ),
                 (cf, _) => cf,
@@ -538,12 +560,59 @@ where
                 })
             })
         }
-        Opcode::DynamicStackAddr => unimplemented!("DynamicStackSlot"),
-        Opcode::DynamicStackLoad => unimplemented!("DynamicStackLoad"),
-        Opcode::DynamicStackStore => unimplemented!("DynamicStackStore"),
+        Opcode::DynamicStackAddr => {
+            let slot = if let InstructionData::DynamicStackLoad {
+                dynamic_stack_slot, ..
+            } = inst
+            {
+                dynamic_stack_slot
+            } else {
+                unreachable!()
+            };
+            assign_or_memtrap({
+                AddressSize::try_from(ctrl_ty).and_then(|addr_size| {
+                    let addr = state.dynamic_stack_address(addr_size, slot)?;
+                    let dv = DataValue::try_from(addr)?;
+                    Ok(dv.into())
+                })
+            })
+        }
+        Opcode::DynamicStackLoad => {
+            let slot = if let InstructionData::DynamicStackLoad {
+                dynamic_stack_slot, ..
+            } = inst
+            {
+                dynamic_stack_slot
+            } else {
+                unreachable!()
+            };
+            let mem_flags = MemFlags::new();
+            assign_or_memtrap({
+                state
+                    .dynamic_stack_address(AddressSize::_64, slot)
+                    .and_then(|addr| state.checked_load(addr, ctrl_ty, mem_flags))
+            })
+        }
+        Opcode::DynamicStackStore => {
+            let slot = if let InstructionData::DynamicStackStore {
+                dynamic_stack_slot, ..
+            } = inst
+            {
+                dynamic_stack_slot
+            } else {
+                unreachable!()
+            };
+            let arg = arg(0);
+            let mem_flags = MemFlags::new();
+            continue_or_memtrap({
+                state
+                    .dynamic_stack_address(AddressSize::_64, slot)
+                    .and_then(|addr| state.checked_store(addr, arg, mem_flags))
+            })
+        }
         Opcode::GlobalValue | Opcode::SymbolValue | Opcode::TlsValue => {
             if let InstructionData::UnaryGlobalValue { global_value, .. } = inst {
-                assign_or_memtrap(state.resolve_global_value(global_value))
+                symbol_or_memtrap(state.resolve_global_value(global_value))?
             } else {
                 unreachable!()
             }
@@ -560,7 +629,7 @@ where
         Opcode::F64const => assign(imm()),
         Opcode::F128const => assign(imm()),
         Opcode::Vconst => assign(imm()),
-        Opcode::Null => unimplemented!("Null"),
+        Opcode::Null => assign(DataValueExt::int(0, ctrl_ty.as_int())?),
         Opcode::Nop => ControlFlow::Continue,
         Opcode::Select | Opcode::SelectSpectreGuard => choose(arg(0).into_bool()?, arg(1), arg(2)),
         Opcode::Bitselect => assign(bitselect(arg(0), arg(1), arg(2))?),
@@ -855,29 +924,52 @@ where
         Opcode::Fneg => unary(DataValueExt::neg, arg(0))?,
         Opcode::Fabs => unary(DataValueExt::abs, arg(0))?,
         Opcode::Fcopysign => binary(DataValueExt::copysign, arg(0), arg(1))?,
-        Opcode::Fmin => assign(match (arg(0), arg(1)) {
-            (a, _) if a.is_nan()? => a,
-            (_, b) if b.is_nan()? => b,
-            (a, b) if a.is_zero()? && b.is_zero()? && a.is_negative()? => a,
-            (a, b) if a.is_zero()? && b.is_zero()? && b.is_negative()? => b,
-            (a, b) => a.smin(b)?,
-        }),
-        Opcode::Fmax => assign(match (arg(0), arg(1)) {
-            (a, _) if a.is_nan()? => a,
-            (_, b) if b.is_nan()? => b,
-            (a, b) if a.is_zero()? && b.is_zero()? && a.is_negative()? => b,
-            (a, b) if a.is_zero()? && b.is_zero()? && b.is_negative()? => a,
-            (a, b) => a.smax(b)?,
-        }),
+        Opcode::Fmin => binary(
+            |a, b| {
+                Ok(match (a, b) {
+                    (a, _) if a.is_nan()? => a,
+                    (_, b) if b.is_nan()? => b,
+                    (a, b) if a.is_zero()? && b.is_zero()? && a.is_negative()? => a,
+                    (a, b) if a.is_zero()? && b.is_zero()? && b.is_negative()? => b,
+                    (a, b) => a.smin(b)?,
+                })
+            },
+            arg(0),
+            arg(1),
+        )?,
+        Opcode::Fmax => binary(
+            |a, b| {
+                Ok(match (a, b) {
+                    (a, _) if a.is_nan()? => a,
+                    (_, b) if b.is_nan()? => b,
+                    (a, b) if a.is_zero()? && b.is_zero()? && a.is_negative()? => b,
+                    (a, b) if a.is_zero()? && b.is_zero()? && b.is_negative()? => a,
+                    (a, b) => a.smax(b)?,
+                })
+            },
+            arg(0),
+            arg(1),
+        )?,
         Opcode::Ceil => unary(DataValueExt::ceil, arg(0))?,
         Opcode::Floor => unary(DataValueExt::floor, arg(0))?,
         Opcode::Trunc => unary(DataValueExt::trunc, arg(0))?,
         Opcode::Nearest => unary(DataValueExt::nearest, arg(0))?,
-        Opcode::IsNull => unimplemented!("IsNull"),
-        Opcode::IsInvalid => unimplemented!("IsInvalid"),
+        // Null references are represented by `0` and invalid references by `-1`.
+        Opcode::IsNull => assign(DataValueExt::bool(
+            arg(0).into_int_signed()? == 0,
+            false,
+            types::I8,
+        )?),
+        Opcode::IsInvalid => assign(DataValueExt::bool(
+            arg(0).into_int_signed()? == -1,
+            false,
+            types::I8,
+        )?),
         Opcode::Bitcast | Opcode::ScalarToVector => {
             let input_ty = inst_context.type_of(inst_context.args()[0]).unwrap();
-            let lanes = &if input_ty.is_vector() {
+            let is_vector_bitcast =
+                input_ty.is_vector() || (inst.opcode() == Opcode::Bitcast && ctrl_ty.is_vector());
+            if is_vector_bitcast {
                 assert_eq!(
                     inst.memflags()
                         .expect("byte order flag to be set")
@@ -885,11 +977,24 @@ where
                     Endianness::Little,
                     "Only little endian bitcasts on vectors are supported"
                 );
+            }
+            let lanes = &if input_ty.is_vector() {
                 extractlanes(&arg(0), ctrl_ty)?
+            } else if is_vector_bitcast {
+                // Split the scalar into the lanes of the vector.
+                let mut bytes = [0; 16];
+                arg(0).write_to_slice_le(&mut bytes);
+                extractlanes(&DataValueExt::vector(bytes, ctrl_ty)?, ctrl_ty)?
             } else {
+                // References are represented by integers of the same width.
+                let lane_ty = if ctrl_ty.is_ref() {
+                    ctrl_ty.as_int()
+                } else {
+                    ctrl_ty.lane_type()
+                };
                 extractlanes(&arg(0), input_ty)?
                     .into_iter()
-                    .map(|x| DataValue::convert(x, ValueConversionKind::Exact(ctrl_ty.lane_type())))
+                    .map(|x| DataValue::convert(x, ValueConversionKind::Exact(lane_ty)))
                     .collect::<ValueResult<SimdVec<DataValue>>>()?
             };
             assign(match inst.opcode() {
@@ -1276,16 +1381,120 @@ where
             assign(binary_pairwise(arg(0), arg(1), ctrl_ty, DataValueExt::add)?)
         }
         Opcode::ExtractVector => {
-            unimplemented!("ExtractVector not supported");
-        }
-        Opcode::GetFramePointer => unimplemented!("GetFramePointer"),
-        Opcode::GetStackPointer => unimplemented!("GetStackPointer"),
-        Opcode::GetReturnAddress => unimplemented!("GetReturnAddress"),
-        Opcode::X86Pshufb => unimplemented!("X86Pshufb"),
-        Opcode::X86Blendv => unimplemented!("X86Blendv"),
-        Opcode::X86Pmulhrsw => unimplemented!("X86Pmulhrsw"),
-        Opcode::X86Pmaddubsw => unimplemented!("X86Pmaddubsw"),
-        Opcode::X86Cvtt2dq => unimplemented!("X86Cvtt2dq"),
+            // Dynamic vectors hold a single fixed vector since their scale is one.
+            if imm().into_int_unsigned()? != 0 {
+                return Err(ValueError::InvalidValue(ctrl_ty).into());
+            }
+            assign(arg(0))
+        }
+        Opcode::GetFramePointer => assign_or_memtrap({
+            AddressSize::try_from(ctrl_ty).and_then(|addr_size| {
+                let addr = state.frame_pointer(addr_size)?;
+                let dv = DataValue::try_from(addr)?;
+                Ok(dv.into())
+            })
+        }),
+        Opcode::GetStackPointer => assign_or_memtrap({
+            AddressSize::try_from(ctrl_ty).and_then(|addr_size| {
+                let addr = state.stack_pointer(addr_size)?;
+                let dv = DataValue::try_from(addr)?;
+                Ok(dv.into())
+            })
+        }),
+        Opcode::GetReturnAddress => assign_or_memtrap({
+            AddressSize::try_from(ctrl_ty).and_then(|addr_size| {
+                // There's no caller to return to when called from outside the interpreter.
+                let dv = match state.return_address(addr_size)? {
+                    Some(addr) => DataValue::try_from(addr)?,
+                    None => DataValue::from_integer(0, ctrl_ty)
+                        .map_err(|_| MemoryError::InvalidAddressType(ctrl_ty))?,
+                };
+                Ok(dv.into())
+            })
+        }),
+        Opcode::X86Pshufb => {
+            let x = DataValueExt::into_array(&arg(0))?;
+            let y = DataValueExt::into_array(&arg(1))?;
+            let mut new = [0u8; 16];
+            for i in 0..new.len() {
+                if y[i] & 0x80 == 0 {
+                    new[i] = x[(y[i] & 0x0f) as usize];
+                } // else leave as 0
+            }
+            assign(DataValueExt::vector(new, types::I8X16)?)
+        }
+        Opcode::X86Blendv => {
+            // The top bit of each lane of the condition selects between `x` and `y`.
+            let c = extractlanes(&arg(0), ctrl_ty.as_int())?;
+            let x = extractlanes(&arg(1), ctrl_ty)?;
+            let y = extractlanes(&arg(2), ctrl_ty)?;
+            let new_vec = c
+                .into_iter()
+                .zip(x.into_iter().zip(y))
+                .map(|(c, (x, y))| Ok(if c.into_int_signed()? < 0 { x } else { y }))
+                .collect::<ValueResult<SimdVec<_>>>()?;
+            assign(vectorizelanes(&new_vec, ctrl_ty)?)
+        }
+        Opcode::X86Pmulhrsw => {
+            // Like `sqmul_round_sat` but wrapping instead of saturating, which only differs when
+            // both lanes are the minimum value.
+            let lane_type = ctrl_ty.lane_type();
+            let arg0 = extractlanes(&arg(0), ctrl_ty)?;
+            let arg1 = extractlanes(&arg(1), ctrl_ty)?;
+            let new_vec = arg0
+                .into_iter()
+                .zip(arg1)
+                .map(|(x, y)| {
+                    let x = x.into_int_signed()?;
+                    let y = y.into_int_signed()?;
+                    let z = (x * y + (1 << (lane_type.bits() - 2))) >> (lane_type.bits() - 1);
+                    DataValueExt::int(z, lane_type)
+                })
+                .collect::<ValueResult<SimdVec<_>>>()?;
+            assign(vectorizelanes(&new_vec, ctrl_ty)?)
+        }
+        Opcode::X86Pmaddubsw => {
+            // The signed lanes of `x` are multiplied with the unsigned lanes of `y`.
+            let x = extractlanes(&arg(0), types::I8X16)?;
+            let y = extractlanes(&arg(1), types::I8X16)?;
+            let products = x
+                .into_iter()
+                .zip(y)
+                .map(|(x, y)| Ok(x.into_int_signed()? * (y.into_int_unsigned()? as i128)))
+                .collect::<ValueResult<SimdVec<_>>>()?;
+            let (min, max) = (i16::MIN as i128, i16::MAX as i128);
+            let new_vec = products
+                .chunks(2)
+                .map(|pair| DataValueExt::int((pair[0] + pair[1]).clamp(min, max), types::I16))
+                .collect::<ValueResult<SimdVec<_>>>()?;
+            assign(vectorizelanes(&new_vec, types::I16X8)?)
+        }
+        Opcode::X86Cvtt2dq => {
+            // NaN and out of bounds lanes are converted to the minimum integer value.
+            let in_ty = inst_context.type_of(inst_context.args()[0]).unwrap();
+            let (min, max) = ctrl_ty.bounds(true);
+            let (min, max) = (min as i128, max as i128);
+            let cvt = |x: DataValue| -> ValueResult<DataValue> {
+                let x = if x.is_nan()? {
+                    min
+                } else {
+                    let x = x.into_float()?.trunc();
+                    if x < min as f64 || x > max as f64 {
+                        min
+                    } else {
+                        x as i128
+                    }
+                };
+                DataValueExt::int(x, ctrl_ty.lane_type())
+            };
+            let x = extractlanes(&arg(0), in_ty)?;
+            assign(vectorizelanes(
+                &x.into_iter()
+                    .map(cvt)
+                    .collect::<ValueResult<SimdVec<DataValue>>>()?,
+                ctrl_ty,
+            )?)
+        }
     })
 }
 
@@ -1422,13 +1631,7 @@ pub(crate) fn extractlanes(
         return Ok(lanes);
     }
 
-    let iterations = match lane_type {
-        types::I8 => 1,
-        types::I16 | types::F16 => 2,
-        types::I32 | types::F32 => 4,
-        types::I64 | types::F64 => 8,
-        _ => unimplemented!("vectors with lanes wider than 64-bits are currently unsupported."),
-    };
+    let iterations = lane_type.bytes();
 
     let x = x.into_array()?;
     for i in 0..vector_type.lane_count() {
@@ -1437,11 +1640,8 @@ pub(crate) fn extractlanes(
             lane += (x[((i * iterations) + j) as usize] as i128) << (8 * j);
         }
 
-        let lane_val: DataValue = if lane_type.is_float() {
-            DataValueExt::float(lane as u64, lane_type)?
-        } else {
-            DataValueExt::int(lane, lane_type)?
-        };
+        let lane_val = DataValue::int(lane, lane_type.as_int())?
+            .convert(ValueConversionKind::Exact(lane_type))?;
         lanes.push(lane_val);
     }
     return Ok(lanes);
@@ -1461,13 +1661,7 @@ fn vectorizelanes(x: &[DataValue], vector_type: types::Type) -> ValueResult<Data
 /// Convert a Rust array of [Value] back into a `Value::vector`.
 fn vectorizelanes_all(x: &[DataValue], vector_type: types::Type) -> ValueResult<DataValue> {
     let lane_type = vector_type.lane_type();
-    let iterations = match lane_type {
-        types::I8 => 1,
-        types::I16 | types::F16 => 2,
-        types::I32 | types::F32 => 4,
-        types::I64 | types::F64 => 8,
-        _ => unimplemented!("vectors with lanes wider than 64-bits are currently unsupported."),
-    };
+    let iterations = lane_type.bytes() as usize;
     let mut result: [u8; 16] = [0; 16];
     for (i, val) in x.iter().enumerate() {
         let lane_val: i128 = val
@@ -1482,6 +1676,15 @@ fn vectorizelanes_all(x: &[DataValue], vector_type: types::Type) -> ValueResult<
     DataValueExt::vector(result, vector_type)
 }
 
+/// Dynamic vector types are interpreted with a scale of one, i.e. as their base vector type.
+fn fixed_vector_type(ty: Type) -> Type {
+    if ty.is_dynamic_vector() {
+        ty.dynamic_to_vector().unwrap()
+    } else {
+        ty
+    }
+}
+
 /// Performs a lanewise fold on a vector type
 fn fold_vector<F>(v: DataValue, ty: types::Type, init: DataValue, op: F) -> ValueResult<DataValue>
 where
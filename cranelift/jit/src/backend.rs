@@ -1,7 +1,12 @@
 //! Defines `JITModule`.
 
+use crate::tls::{self, TlsDescriptor};
+#[cfg(all(unix, feature = "unwind"))]
+use crate::unwind::UnwindRegistration;
 use crate::{compiled_blob::CompiledBlob, memory::BranchProtection, memory::Memory};
 use cranelift_codegen::binemit::Reloc;
+#[cfg(feature = "unwind")]
+use cranelift_codegen::isa::unwind::{systemv, UnwindInfo};
 use cranelift_codegen::isa::{OwnedTargetIsa, TargetIsa};
 use cranelift_codegen::settings::Configurable;
 use cranelift_codegen::{ir, settings, FinalizedMachReloc};
@@ -31,6 +36,11 @@ pub struct JITBuilder {
     lookup_symbols: Vec<Box<dyn Fn(&str) -> Option<*const u8> + Send>>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
     hotswap_enabled: bool,
+    #[cfg(feature = "unwind")]
+    unwind_info: bool,
+    perf_map: bool,
+    #[cfg(feature = "gdb-jit")]
+    gdb_jit: bool,
 }
 
 impl JITBuilder {
@@ -57,6 +67,11 @@ impl JITBuilder {
         libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
     ) -> ModuleResult<Self> {
         let mut flag_builder = settings::builder();
+        if tls::SUPPORTED {
+            // The JIT resolves TLS relocations of the general dynamic model
+            // itself, see `JITModule::new_tls_descriptor`.
+            flag_builder.set("tls_model", "elf_gd").unwrap();
+        }
         for (name, value) in flags {
             flag_builder.set(name, value)?;
         }
@@ -95,6 +110,11 @@ impl JITBuilder {
             lookup_symbols,
             libcall_names,
             hotswap_enabled: false,
+            #[cfg(feature = "unwind")]
+            unwind_info: false,
+            perf_map: false,
+            #[cfg(feature = "gdb-jit")]
+            gdb_jit: false,
         }
     }
 
@@ -154,6 +174,42 @@ impl JITBuilder {
         self.hotswap_enabled = enabled;
         self
     }
+
+    /// Enable or disable registering the unwind information of compiled functions with the
+    /// system unwinder, which allows panics and exceptions to unwind through JIT frames and
+    /// native tools to produce backtraces through them.
+    ///
+    /// The unwind information is registered by [`JITModule::finalize_definitions`] and
+    /// deregistered by [`JITModule::free_memory`]. This requires the `unwind_info` setting,
+    /// which is enabled by default, and is currently only supported for System V unwind
+    /// information on Unix platforms; it has no effect elsewhere.
+    #[cfg(feature = "unwind")]
+    pub fn unwind_info(&mut self, enabled: bool) -> &mut Self {
+        self.unwind_info = enabled;
+        self
+    }
+
+    /// Enable or disable writing the address, size and name of compiled functions to
+    /// `/tmp/perf-$PID.map`, which the Linux `perf` tool uses to symbolize JIT code.
+    ///
+    /// The map is also written if the `PERF_BUILDID_DIR` environment variable is set.
+    pub fn perf_map(&mut self, enabled: bool) -> &mut Self {
+        self.perf_map = enabled;
+        self
+    }
+
+    /// Enable or disable registering compiled functions with debuggers through GDB's JIT
+    /// interface, so that they show up by name in backtraces and can have breakpoints set on
+    /// them.
+    ///
+    /// Functions are registered by [`JITModule::finalize_definitions`] and deregistered by
+    /// [`JITModule::free_memory`]. This is currently only supported on Linux on x86_64 and
+    /// aarch64; it has no effect elsewhere.
+    #[cfg(feature = "gdb-jit")]
+    pub fn gdb_jit(&mut self, enabled: bool) -> &mut Self {
+        self.gdb_jit = enabled;
+        self
+    }
 }
 
 /// A pending update to the GOT.
@@ -167,6 +223,56 @@ struct GotUpdate {
 
 unsafe impl Send for GotUpdate {}
 
+/// A function awaiting registration with the unwinder and debuggers.
+///
+/// Which fields are used depends on the platform and the `unwind` and `gdb-jit` features.
+#[cfg_attr(not(all(unix, feature = "gdb-jit")), allow(dead_code))]
+struct PendingRegistration {
+    id: FuncId,
+    ptr: *const u8,
+    size: usize,
+    #[cfg(feature = "unwind")]
+    unwind_info: Option<systemv::UnwindInfo>,
+}
+
+unsafe impl Send for PendingRegistration {}
+
+/// The registrations of a finalized function with the unwinder and debuggers.
+struct FunctionRegistration {
+    /// The address of the registered definition.
+    ptr: usize,
+    #[cfg(all(unix, feature = "unwind"))]
+    _unwind: Option<UnwindRegistration>,
+    #[cfg(feature = "gdb-jit")]
+    _gdb: Option<crate::gdb::GdbRegistration>,
+}
+
+/// The registrations of finalized functions, including the definitions replaced through
+/// [`JITModule::prepare_for_function_redefine`].
+///
+/// Like the memory of the module, these are leaked by default, as the functions they describe
+/// remain valid for the remainder of the program's life.
+#[derive(Default)]
+struct Registrations {
+    functions: HashMap<FuncId, Vec<FunctionRegistration>>,
+}
+
+impl Registrations {
+    /// Deregisters all functions that would be leaked otherwise.
+    fn clear(&mut self) {
+        self.functions.clear();
+    }
+}
+
+impl Drop for Registrations {
+    fn drop(&mut self) {
+        // leak registrations along with the memory of the functions
+        std::mem::take(&mut self.functions)
+            .into_values()
+            .for_each(std::mem::forget);
+    }
+}
+
 /// A wrapper that impls Send for the contents.
 ///
 /// SAFETY: This must not be used for any types where it would be UB for them to be Send
@@ -181,6 +287,11 @@ unsafe impl<T> Send for SendWrapper<T> {}
 pub struct JITModule {
     isa: OwnedTargetIsa,
     hotswap_enabled: bool,
+    #[cfg(feature = "unwind")]
+    unwind_info_enabled: bool,
+    perf_map_enabled: bool,
+    #[cfg(feature = "gdb-jit")]
+    gdb_jit_enabled: bool,
     symbols: RefCell<HashMap<String, SendWrapper<*const u8>>>,
     lookup_symbols: Vec<Box<dyn Fn(&str) -> Option<*const u8> + Send>>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String + Send + Sync>,
@@ -189,15 +300,22 @@ pub struct JITModule {
     function_got_entries: SecondaryMap<FuncId, Option<SendWrapper<NonNull<AtomicPtr<u8>>>>>,
     function_plt_entries: SecondaryMap<FuncId, Option<SendWrapper<NonNull<[u8; 16]>>>>,
     data_object_got_entries: SecondaryMap<DataId, Option<SendWrapper<NonNull<AtomicPtr<u8>>>>>,
+    data_object_tls_descriptors: SecondaryMap<DataId, Option<SendWrapper<NonNull<TlsDescriptor>>>>,
     libcall_got_entries: HashMap<ir::LibCall, SendWrapper<NonNull<AtomicPtr<u8>>>>,
     libcall_plt_entries: HashMap<ir::LibCall, SendWrapper<NonNull<[u8; 16]>>>,
     compiled_functions: SecondaryMap<FuncId, Option<CompiledBlob>>,
+    /// Definitions replaced through `prepare_for_function_redefine`, which may still be executing
+    replaced_functions: HashMap<FuncId, Vec<CompiledBlob>>,
     compiled_data_objects: SecondaryMap<DataId, Option<CompiledBlob>>,
     functions_to_finalize: Vec<FuncId>,
     data_objects_to_finalize: Vec<DataId>,
 
     /// Updates to the GOT awaiting relocations to be made and region protections to be set
     pending_got_updates: Vec<GotUpdate>,
+
+    /// Functions awaiting registration once region protections are set
+    pending_registrations: Vec<PendingRegistration>,
+    registrations: Registrations,
 }
 
 /// A handle to allow freeing memory allocated by the `Module`.
@@ -217,6 +335,10 @@ impl JITModule {
     /// from that module are currently executing and none of the `fn` pointers
     /// are called afterwards.
     pub unsafe fn free_memory(mut self) {
+        self.registrations.clear();
+        for descriptor in self.data_object_tls_descriptors.values().flatten() {
+            (*descriptor.0.as_ptr()).release();
+        }
         self.memory.code.free_memory();
         self.memory.readonly.free_memory();
         self.memory.writable.free_memory();
@@ -293,6 +415,25 @@ impl JITModule {
         self.data_object_got_entries[id] = Some(SendWrapper(got_entry));
     }
 
+    /// Creates the descriptor that TLS relocations against a data object are
+    /// resolved to, see the `tls` module.
+    fn new_tls_descriptor(&mut self, id: DataId) {
+        assert!(tls::SUPPORTED, "JIT doesn't support TLS on this platform");
+        let descriptor = self
+            .memory
+            .writable
+            .allocate(
+                std::mem::size_of::<TlsDescriptor>(),
+                std::mem::align_of::<TlsDescriptor>().try_into().unwrap(),
+            )
+            .unwrap()
+            .cast::<TlsDescriptor>();
+        unsafe {
+            std::ptr::write(descriptor, TlsDescriptor::new());
+        }
+        self.data_object_tls_descriptors[id] = Some(SendWrapper(NonNull::new(descriptor).unwrap()));
+    }
+
     unsafe fn write_plt_entry_bytes(plt_ptr: *mut [u8; 16], got_ptr: NonNull<AtomicPtr<u8>>) {
         assert!(
             cfg!(target_arch = "x86_64"),
@@ -347,13 +488,43 @@ impl JITModule {
             }
             ModuleRelocTarget::LibCall(ref libcall) => {
                 let sym = (self.libcall_names)(*libcall);
-                self.lookup_symbol(&sym)
+                self.lookup_libcall(*libcall)
                     .unwrap_or_else(|| panic!("can't resolve libcall {}", sym))
             }
             _ => panic!("invalid name"),
         }
     }
 
+    fn lookup_libcall(&self, libcall: ir::LibCall) -> Option<*const u8> {
+        if libcall == ir::LibCall::ElfTlsGetAddr && tls::SUPPORTED {
+            // TLS data objects of the JIT aren't known to the dynamic linker,
+            // so `__tls_get_addr` is replaced by our own implementation.
+            return Some(tls::tls_get_addr as *const u8);
+        }
+        self.lookup_symbol(&(self.libcall_names)(libcall))
+    }
+
+    fn get_tls_descriptor(&self, name: &ModuleRelocTarget) -> *const u8 {
+        match *name {
+            ModuleRelocTarget::User { .. } if !ModuleDeclarations::is_function(name) => {
+                let data_id = DataId::from_name(name);
+                if self.compiled_data_objects[data_id].is_none() {
+                    let decl = self.declarations.get_data_decl(data_id);
+                    panic!(
+                        "can't resolve TLS symbol {}, TLS data must be defined in the JIT module",
+                        decl.linkage_name(data_id)
+                    );
+                }
+                self.data_object_tls_descriptors[data_id]
+                    .expect("TLS relocation against non-TLS data")
+                    .0
+                    .as_ptr()
+                    .cast::<u8>()
+            }
+            _ => panic!("TLS relocations can only have data objects as target"),
+        }
+    }
+
     /// Returns the given function's entry in the Global Offset Table.
     ///
     /// Panics if there's no entry in the table for the given function.
@@ -410,8 +581,8 @@ impl JITModule {
 
     /// Returns the address of a finalized function.
     ///
-    /// The pointer remains valid until either [`JITModule::free_memory`] is called or in the future
-    /// some way of deallocating this individual function is used.
+    /// The pointer remains valid until either [`JITModule::free_memory`] or
+    /// [`JITModule::free_function`] is called.
     pub fn get_finalized_function(&self, func_id: FuncId) -> *const u8 {
         let info = &self.compiled_functions[func_id];
         assert!(
@@ -425,8 +596,11 @@ impl JITModule {
 
     /// Returns the address and size of a finalized data object.
     ///
-    /// The pointer remains valid until either [`JITModule::free_memory`] is called or in the future
-    /// some way of deallocating this individual data object is used.
+    /// The pointer remains valid until either [`JITModule::free_memory`] or
+    /// [`JITModule::free_data`] is called.
+    ///
+    /// For TLS data objects this is the initial contents that the storage of each thread is
+    /// initialized from on its first access.
     pub fn get_finalized_data(&self, data_id: DataId) -> (*const u8, usize) {
         let info = &self.compiled_data_objects[data_id];
         assert!(
@@ -446,7 +620,8 @@ impl JITModule {
         // are profiling with perf and saving binaries to PERF_BUILDID_DIR
         // for post-profile analysis, write information about each function
         // we define.
-        if cfg!(unix) && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
+        if cfg!(unix) && (self.perf_map_enabled || ::std::env::var_os("PERF_BUILDID_DIR").is_some())
+        {
             let mut map_file = ::std::fs::OpenOptions::new()
                 .create(true)
                 .append(true)
@@ -476,6 +651,7 @@ impl JITModule {
                 |name| self.get_address(name),
                 |name| self.get_got_address(name).as_ptr().cast(),
                 |name| self.get_plt_address(name),
+                |name| self.get_tls_descriptor(name),
             );
         }
 
@@ -489,6 +665,7 @@ impl JITModule {
                 |name| self.get_address(name),
                 |name| self.get_got_address(name).as_ptr().cast(),
                 |name| self.get_plt_address(name),
+                |name| self.get_tls_descriptor(name),
             );
         }
 
@@ -499,9 +676,54 @@ impl JITModule {
         for update in self.pending_got_updates.drain(..) {
             unsafe { update.entry.as_ref() }.store(update.ptr as *mut _, Ordering::SeqCst);
         }
+
+        self.register_functions();
         Ok(())
     }
 
+    /// Registers the functions finalized since the last call with the unwinder and debuggers.
+    ///
+    /// Each function is registered on its own so that it can be deregistered when it's freed.
+    fn register_functions(&mut self) {
+        for pending in std::mem::take(&mut self.pending_registrations) {
+            let registration = FunctionRegistration {
+                ptr: pending.ptr as usize,
+                // SAFETY: the function stays in memory until `free_function` or `free_memory`,
+                // which drop the registration first.
+                #[cfg(all(unix, feature = "unwind"))]
+                _unwind: pending.unwind_info.and_then(|unwind_info| unsafe {
+                    UnwindRegistration::new(&*self.isa, &[(pending.ptr, unwind_info)])
+                }),
+                #[cfg(feature = "gdb-jit")]
+                _gdb: if self.gdb_jit_enabled {
+                    let decl = self.declarations.get_function_decl(pending.id);
+                    let name = decl.linkage_name(pending.id).into_owned();
+                    crate::gdb::register(&*self.isa, &[(pending.ptr, pending.size, name)])
+                } else {
+                    None
+                },
+            };
+            self.registrations
+                .functions
+                .entry(pending.id)
+                .or_default()
+                .push(registration);
+        }
+    }
+
+    /// Whether finalized functions are registered with the unwinder or debuggers.
+    fn registration_enabled(&self) -> bool {
+        #[cfg(feature = "gdb-jit")]
+        if self.gdb_jit_enabled {
+            return true;
+        }
+        #[cfg(feature = "unwind")]
+        if self.unwind_info_enabled {
+            return true;
+        }
+        false
+    }
+
     /// Create a new `JITModule`.
     pub fn new(builder: JITBuilder) -> Self {
         if builder.hotswap_enabled {
@@ -520,6 +742,11 @@ impl JITModule {
         let mut module = Self {
             isa: builder.isa,
             hotswap_enabled: builder.hotswap_enabled,
+            #[cfg(feature = "unwind")]
+            unwind_info_enabled: builder.unwind_info,
+            perf_map_enabled: builder.perf_map,
+            #[cfg(feature = "gdb-jit")]
+            gdb_jit_enabled: builder.gdb_jit,
             symbols: RefCell::new(builder.symbols),
             lookup_symbols: builder.lookup_symbols,
             libcall_names: builder.libcall_names,
@@ -533,13 +760,17 @@ impl JITModule {
             function_got_entries: SecondaryMap::new(),
             function_plt_entries: SecondaryMap::new(),
             data_object_got_entries: SecondaryMap::new(),
+            data_object_tls_descriptors: SecondaryMap::new(),
             libcall_got_entries: HashMap::new(),
             libcall_plt_entries: HashMap::new(),
             compiled_functions: SecondaryMap::new(),
+            replaced_functions: HashMap::new(),
             compiled_data_objects: SecondaryMap::new(),
             functions_to_finalize: Vec::new(),
             data_objects_to_finalize: Vec::new(),
             pending_got_updates: Vec::new(),
+            pending_registrations: Vec::new(),
+            registrations: Registrations::default(),
         };
 
         // Pre-create a GOT and PLT entry for each libcall.
@@ -549,8 +780,7 @@ impl JITModule {
             &[] // Not PIC, so no GOT and PLT entries necessary
         };
         for &libcall in all_libcalls {
-            let sym = (module.libcall_names)(libcall);
-            let addr = if let Some(addr) = module.lookup_symbol(&sym) {
+            let addr = if let Some(addr) = module.lookup_libcall(libcall) {
                 addr
             } else {
                 continue;
@@ -588,12 +818,136 @@ impl JITModule {
             )));
         }
 
-        self.compiled_functions[func_id] = None;
+        // The previous definition may still be executing, so it's only freed by
+        // `free_replaced_definitions` or `free_function`.
+        let replaced = self.compiled_functions[func_id].take().unwrap();
+        self.replaced_functions
+            .entry(func_id)
+            .or_default()
+            .push(replaced);
+
+        Ok(())
+    }
+
+    /// Free the code of the definitions of a function replaced through
+    /// [`JITModule::prepare_for_function_redefine`], keeping the current definition.
+    ///
+    /// This deregisters the replaced definitions from the unwinder and debuggers. The memory is
+    /// reused for later definitions.
+    ///
+    /// # Safety
+    ///
+    /// The replaced definitions must not be executing anymore and must not be called afterwards
+    /// through `fn` pointers obtained before they were replaced. Calls from other functions go
+    /// through the function's GOT entry, which points to the current definition once it's
+    /// finalized.
+    pub unsafe fn free_replaced_definitions(&mut self, func_id: FuncId) -> ModuleResult<()> {
+        let replaced = self.replaced_functions.remove(&func_id).unwrap_or_default();
+        let current = self.compiled_functions[func_id]
+            .as_ref()
+            .map(|blob| blob.ptr as usize);
 
-        // FIXME return some kind of handle that allows for deallocating the function
+        self.pending_registrations
+            .retain(|f| f.id != func_id || Some(f.ptr as usize) == current);
+        if let Some(registrations) = self.registrations.functions.get_mut(&func_id) {
+            registrations.retain(|r| Some(r.ptr) == current);
+        }
 
+        for blob in replaced {
+            self.memory.code.free(blob.ptr, blob.size)?;
+        }
         Ok(())
     }
+
+    /// Free the code of a function, after which it can be defined again.
+    ///
+    /// This also frees the definitions of the function replaced through
+    /// [`JITModule::prepare_for_function_redefine`] and deregisters the function from the
+    /// unwinder and debuggers. The memory is reused for later definitions.
+    ///
+    /// # Safety
+    ///
+    /// Because this function invalidates any pointers to the function, it should only be used
+    /// when the function isn't currently executing and isn't called afterwards, neither through
+    /// a `fn` pointer nor from other functions, until it's defined and finalized again. With
+    /// hotswap support enabled, calls from other functions go through the function's GOT entry,
+    /// which is cleared until then.
+    pub unsafe fn free_function(&mut self, func_id: FuncId) -> ModuleResult<()> {
+        let compiled = self.compiled_functions[func_id].take();
+        let replaced = self.replaced_functions.remove(&func_id).unwrap_or_default();
+        if compiled.is_none() && replaced.is_empty() {
+            let decl = self.declarations.get_function_decl(func_id);
+            return Err(ModuleError::Backend(anyhow::anyhow!(
+                "Tried to free not yet defined function {}",
+                decl.linkage_name(func_id),
+            )));
+        }
+
+        self.functions_to_finalize.retain(|&id| id != func_id);
+        self.pending_registrations.retain(|f| f.id != func_id);
+        self.registrations.functions.remove(&func_id);
+        if let Some(got_entry) = self.function_got_entries[func_id] {
+            self.pending_got_updates
+                .retain(|update| update.entry != got_entry.0);
+            got_entry
+                .0
+                .as_ref()
+                .store(ptr::null_mut(), Ordering::SeqCst);
+        }
+
+        for blob in compiled.into_iter().chain(replaced) {
+            self.memory.code.free(blob.ptr, blob.size)?;
+        }
+        Ok(())
+    }
+
+    /// Free the contents of a data object, after which it can be defined again.
+    ///
+    /// The memory is reused for later definitions. For TLS data objects this also frees the copies
+    /// of all threads, threads that access the data object after it's defined again start out with
+    /// the new contents.
+    ///
+    /// # Safety
+    ///
+    /// Because this function invalidates any pointers to the data object, it should only be used
+    /// when the data object isn't accessed afterwards, neither through a pointer nor from
+    /// functions, until it's defined and finalized again.
+    pub unsafe fn free_data(&mut self, data_id: DataId) -> ModuleResult<()> {
+        let decl = self.declarations.get_data_decl(data_id);
+        let compiled = match self.compiled_data_objects[data_id].take() {
+            Some(compiled) => compiled,
+            None => {
+                return Err(ModuleError::Backend(anyhow::anyhow!(
+                    "Tried to free not yet defined data object {}",
+                    decl.linkage_name(data_id),
+                )));
+            }
+        };
+
+        self.data_objects_to_finalize.retain(|&id| id != data_id);
+        if let Some(got_entry) = self.data_object_got_entries[data_id] {
+            self.pending_got_updates
+                .retain(|update| update.entry != got_entry.0);
+            got_entry
+                .0
+                .as_ref()
+                .store(ptr::null_mut(), Ordering::SeqCst);
+        }
+        if let Some(descriptor) = self.data_object_tls_descriptors[data_id] {
+            (*descriptor.0.as_ptr()).undefine();
+        }
+
+        if compiled.size == 0 {
+            // Empty data objects don't have any memory allocated for them.
+            return Ok(());
+        }
+        let memory = if decl.writable {
+            &mut self.memory.writable
+        } else {
+            &mut self.memory.readonly
+        };
+        memory.free(compiled.ptr, compiled.size)
+    }
 }
 
 impl Module for JITModule {
@@ -641,10 +995,12 @@ impl Module for JITModule {
         writable: bool,
         tls: bool,
     ) -> ModuleResult<DataId> {
-        assert!(!tls, "JIT doesn't yet support TLS");
         let (id, linkage) = self
             .declarations
             .declare_data(name, linkage, writable, tls)?;
+        if tls && self.data_object_tls_descriptors[id].is_none() {
+            self.new_tls_descriptor(id);
+        }
         if self.data_object_got_entries[id].is_none() && self.isa.flags().is_pic() {
             // FIXME populate got entries with a null pointer when defined
             let val = if linkage == Linkage::Import {
@@ -658,8 +1014,10 @@ impl Module for JITModule {
     }
 
     fn declare_anonymous_data(&mut self, writable: bool, tls: bool) -> ModuleResult<DataId> {
-        assert!(!tls, "JIT doesn't yet support TLS");
         let id = self.declarations.declare_anonymous_data(writable, tls)?;
+        if tls {
+            self.new_tls_descriptor(id);
+        }
         if self.isa.flags().is_pic() {
             self.new_data_got_entry(id, std::ptr::null());
         }
@@ -731,6 +1089,25 @@ impl Module for JITModule {
             .map(|reloc| ModuleReloc::from_mach_reloc(reloc, &ctx.func, id))
             .collect();
 
+        if self.registration_enabled() {
+            #[cfg(feature = "unwind")]
+            let unwind_info = if self.unwind_info_enabled {
+                match compiled_code.create_unwind_info(self.isa())? {
+                    Some(UnwindInfo::SystemV(info)) => Some(info),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            self.pending_registrations.push(PendingRegistration {
+                id,
+                ptr,
+                size,
+                #[cfg(feature = "unwind")]
+                unwind_info,
+            });
+        }
+
         self.record_function_for_perf(ptr, size, &decl.linkage_name(id));
         self.compiled_functions[id] = Some(CompiledBlob { ptr, size, relocs });
 
@@ -761,6 +1138,7 @@ impl Module for JITModule {
                     },
                     |name| self.get_got_address(name).as_ptr().cast(),
                     |name| self.get_plt_address(name),
+                    |name| self.get_tls_descriptor(name),
                 );
         } else {
             self.functions_to_finalize.push(id);
@@ -808,6 +1186,17 @@ impl Module for JITModule {
             ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, size);
         }
 
+        if self.registration_enabled() {
+            // There's no unwind information for functions defined from bytes.
+            self.pending_registrations.push(PendingRegistration {
+                id,
+                ptr,
+                size,
+                #[cfg(feature = "unwind")]
+                unwind_info: None,
+            });
+        }
+
         self.record_function_for_perf(ptr, size, &decl.linkage_name(id));
         self.compiled_functions[id] = Some(CompiledBlob {
             ptr,
@@ -833,6 +1222,7 @@ impl Module for JITModule {
                     |name| unreachable!("non GOT or PLT relocation in function {} to {}", id, name),
                     |name| self.get_got_address(name).as_ptr().cast(),
                     |name| self.get_plt_address(name),
+                    |name| self.get_tls_descriptor(name),
                 );
         } else {
             self.functions_to_finalize.push(id);
@@ -855,8 +1245,6 @@ impl Module for JITModule {
             ));
         }
 
-        assert!(!decl.tls, "JIT doesn't yet support TLS");
-
         let &DataDescription {
             ref init,
             function_decls: _,
@@ -921,6 +1309,12 @@ impl Module for JITModule {
         };
         let relocs = data.all_relocs(pointer_reloc).collect::<Vec<_>>();
 
+        if decl.tls {
+            let descriptor = self.data_object_tls_descriptors[id].unwrap().0;
+            let align = align.unwrap_or(WRITABLE_DATA_ALIGNMENT);
+            unsafe { (*descriptor.as_ptr()).define(ptr, size, align.try_into().unwrap()) };
+        }
+
         self.compiled_data_objects[id] = Some(CompiledBlob { ptr, size, relocs });
         self.data_objects_to_finalize.push(id);
         if self.isa.flags().is_pic() {
@@ -25,6 +25,7 @@ impl CompiledBlob {
         get_address: impl Fn(&ModuleRelocTarget) -> *const u8,
         get_got_entry: impl Fn(&ModuleRelocTarget) -> *const u8,
         get_plt_entry: impl Fn(&ModuleRelocTarget) -> *const u8,
+        get_tls_descriptor: impl Fn(&ModuleRelocTarget) -> *const u8,
     ) {
         use std::ptr::write_unaligned;
 
@@ -123,6 +124,53 @@ impl CompiledBlob {
                     let mask = !(0x1ff << 10);
                     unsafe { modify_inst32(at as *mut u32, |ldr| (ldr & mask) | imm9) };
                 }
+                Reloc::ElfX86_64TlsGd => {
+                    // The `__tls_get_addr` call following this `lea` is
+                    // resolved to `tls_get_addr`, which takes the descriptor.
+                    let base = get_tls_descriptor(name);
+                    let what = unsafe { base.offset(isize::try_from(addend).unwrap()) };
+                    let pcrel = i32::try_from((what as isize) - (at as isize)).unwrap();
+                    unsafe { write_unaligned(at as *mut i32, pcrel) };
+                }
+                Reloc::Aarch64TlsDescAdrPage21 => {
+                    // Set the immediate value of an ADRP to bits [32:12] of the descriptor
+                    assert_eq!(addend, 0);
+                    let what = get_tls_descriptor(name);
+                    let what_page = (what as usize) & !0xfff;
+                    let at_page = (at as usize) & !0xfff;
+                    let pcrel = (what_page as isize).checked_sub(at_page as isize).unwrap();
+                    assert!(
+                        (-1 << 32) <= pcrel && pcrel < (1 << 32),
+                        "can't reach TLS descriptor page with ±4GB `adrp` instruction"
+                    );
+                    let val = pcrel >> 12;
+
+                    let immlo = ((val as u32) & 0b11) << 29;
+                    let immhi = (((val as u32) >> 2) & 0x7ffff) << 5;
+                    let mask = !((0x7ffff << 5) | (0b11 << 29));
+                    unsafe { modify_inst32(at as *mut u32, |adrp| (adrp & mask) | immlo | immhi) };
+                }
+                Reloc::Aarch64TlsDescLd64Lo12 => {
+                    // Set the LD/ST immediate field to bits 11:3 of the descriptor
+                    assert_eq!(addend, 0);
+                    let what = get_tls_descriptor(name) as u32;
+                    assert_eq!(what & 0b111, 0);
+                    let imm9 = ((what & 0xfff) >> 3) << 10;
+                    let mask = !(0x1ff << 10);
+                    unsafe { modify_inst32(at as *mut u32, |ldr| (ldr & mask) | imm9) };
+                }
+                Reloc::Aarch64TlsDescAddLo12 => {
+                    // Set the ADD immediate field to bits 11:0 of the descriptor
+                    assert_eq!(addend, 0);
+                    let what = get_tls_descriptor(name) as u32;
+                    let imm12 = (what & 0xfff) << 10;
+                    let mask = !(0xfff << 10);
+                    unsafe { modify_inst32(at as *mut u32, |add| (add & mask) | imm12) };
+                }
+                Reloc::Aarch64TlsDescCall => {
+                    // Only marks the `blr` of the TLSDESC sequence for linker
+                    // relaxations, there's nothing to patch.
+                }
                 Reloc::RiscvCallPlt => {
                     // A R_RISCV_CALL_PLT relocation expects auipc+jalr instruction pair.
                     // It is the equivalent of two relocations:
@@ -0,0 +1,233 @@
+//! Registration of JIT-compiled functions with debuggers through GDB's JIT
+//! interface.
+//!
+//! Debuggers learn about code that isn't part of a loaded object file from
+//! in-memory object files that are registered through
+//! `__jit_debug_register_code`. The object file created here only contains a
+//! symbol table naming the functions, which is enough for backtraces and
+//! breakpoints on function names.
+
+use cranelift_codegen::isa::TargetIsa;
+use object::elf;
+use object::write::elf::{FileHeader, SectionHeader, Sym, Writer};
+use object::Endianness;
+
+// Registration is only implemented for hosts where the symbols of the JIT
+// interface can be defined below.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod supported {
+    use wasmtime_jit_debug::gdb_jit_int::GdbJitImageRegistration;
+
+    /// An object file registered with the debugger until this is dropped.
+    pub(crate) struct GdbRegistration {
+        _registration: GdbJitImageRegistration,
+    }
+
+    pub(crate) fn register(image: Vec<u8>) -> Option<GdbRegistration> {
+        Some(GdbRegistration {
+            _registration: GdbJitImageRegistration::register(image),
+        })
+    }
+
+    // `wasmtime-jit-debug` expects the embedder to define the symbols of the
+    // JIT interface, which Wasmtime does in C. They're defined weakly here so
+    // that the definitions of Wasmtime are used if it's linked in as well.
+    #[cfg(target_arch = "x86_64")]
+    std::arch::global_asm!(
+        ".pushsection .text.wasmtime_jit_debug_descriptor,\"ax\",%progbits",
+        concat!(
+            ".weak wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!()
+        ),
+        concat!(
+            ".type wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!(),
+            ", %function"
+        ),
+        concat!(
+            "wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!(),
+            ":"
+        ),
+        "mov rax, qword ptr [rip + __jit_debug_descriptor@GOTPCREL]",
+        "ret",
+        ".popsection",
+    );
+
+    #[cfg(target_arch = "aarch64")]
+    std::arch::global_asm!(
+        ".pushsection .text.wasmtime_jit_debug_descriptor,\"ax\",%progbits",
+        concat!(
+            ".weak wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!()
+        ),
+        concat!(
+            ".type wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!(),
+            ", %function"
+        ),
+        concat!(
+            "wasmtime_jit_debug_descriptor",
+            wasmtime_versioned_export_macros::versioned_suffix!(),
+            ":"
+        ),
+        "adrp x0, :got:__jit_debug_descriptor",
+        "ldr x0, [x0, :got_lo12:__jit_debug_descriptor]",
+        "ret",
+        ".popsection",
+    );
+
+    // The debugger sets a breakpoint on `__jit_debug_register_code` and reads
+    // `__jit_debug_descriptor` when it's hit.
+    std::arch::global_asm!(
+        ".pushsection .text.__jit_debug_register_code,\"ax\",%progbits",
+        ".weak __jit_debug_register_code",
+        ".type __jit_debug_register_code, %function",
+        "__jit_debug_register_code:",
+        "ret",
+        ".popsection",
+        ".pushsection .data.__jit_debug_descriptor,\"aw\",%progbits",
+        ".weak __jit_debug_descriptor",
+        ".type __jit_debug_descriptor, %object",
+        ".p2align 3",
+        "__jit_debug_descriptor:",
+        // version, action_flag, relevant_entry, first_entry
+        ".4byte 1",
+        ".4byte 0",
+        ".8byte 0",
+        ".8byte 0",
+        ".size __jit_debug_descriptor, 24",
+        ".popsection",
+    );
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod supported {
+    pub(crate) enum GdbRegistration {}
+
+    pub(crate) fn register(_image: Vec<u8>) -> Option<GdbRegistration> {
+        None
+    }
+}
+
+pub(crate) use supported::GdbRegistration;
+
+/// Creates an object file naming the functions at the given addresses and
+/// registers it with the debugger.
+///
+/// Returns `None` if registration isn't supported on the host.
+pub(crate) fn register(
+    isa: &dyn TargetIsa,
+    functions: &[(*const u8, usize, String)],
+) -> Option<GdbRegistration> {
+    if functions.is_empty() {
+        return None;
+    }
+    supported::register(image(isa, functions))
+}
+
+/// Creates an ELF executable with a `.text` section spanning the functions
+/// and a symbol for each of them.
+///
+/// The section has no contents, as the debugger reads the code from memory.
+fn image(isa: &dyn TargetIsa, functions: &[(*const u8, usize, String)]) -> Vec<u8> {
+    let triple = isa.triple();
+    let e_machine = match triple.architecture {
+        target_lexicon::Architecture::X86_64 => elf::EM_X86_64,
+        target_lexicon::Architecture::Aarch64(_) => elf::EM_AARCH64,
+        target_lexicon::Architecture::Riscv64(_) => elf::EM_RISCV,
+        target_lexicon::Architecture::S390x => elf::EM_S390,
+        _ => elf::EM_NONE,
+    };
+    let endian = match triple.endianness().unwrap() {
+        target_lexicon::Endianness::Little => Endianness::Little,
+        target_lexicon::Endianness::Big => Endianness::Big,
+    };
+    let start = functions
+        .iter()
+        .map(|(ptr, _, _)| *ptr as u64)
+        .min()
+        .unwrap();
+    let end = functions
+        .iter()
+        .map(|(ptr, size, _)| *ptr as u64 + *size as u64)
+        .max()
+        .unwrap();
+
+    let mut buffer = Vec::new();
+    let mut writer = Writer::new(endian, true, &mut buffer);
+    writer.reserve_file_header();
+
+    writer.reserve_null_section_index();
+    let text_name = writer.add_section_name(b".text");
+    let text_index = writer.reserve_section_index();
+
+    writer.reserve_null_symbol_index();
+    let symbols = functions
+        .iter()
+        .map(|(ptr, size, name)| {
+            let name = writer.add_string(name.as_bytes());
+            writer.reserve_symbol_index(Some(text_index));
+            (name, *ptr as u64, *size as u64)
+        })
+        .collect::<Vec<_>>();
+
+    writer.reserve_symtab_section_index();
+    writer.reserve_symtab();
+    writer.reserve_strtab_section_index();
+    writer.reserve_strtab();
+    writer.reserve_shstrtab_section_index();
+    writer.reserve_shstrtab();
+    writer.reserve_section_headers();
+
+    writer
+        .write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_EXEC,
+            e_machine,
+            e_entry: 0,
+            e_flags: 0,
+        })
+        .unwrap();
+
+    writer.write_null_symbol();
+    for (name, value, size) in symbols {
+        writer.write_symbol(&Sym {
+            name: Some(name),
+            section: Some(text_index),
+            st_info: (elf::STB_GLOBAL << 4) | elf::STT_FUNC,
+            st_other: elf::STV_DEFAULT,
+            st_shndx: 0,
+            st_value: value,
+            st_size: size,
+        });
+    }
+    writer.write_strtab();
+    writer.write_shstrtab();
+
+    writer.write_null_section_header();
+    writer.write_section_header(&SectionHeader {
+        name: Some(text_name),
+        sh_type: elf::SHT_NOBITS,
+        sh_flags: (elf::SHF_ALLOC | elf::SHF_EXECINSTR).into(),
+        sh_addr: start,
+        sh_offset: 0,
+        sh_size: end - start,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: 1,
+        sh_entsize: 0,
+    });
+    writer.write_symtab_section_header(1);
+    writer.write_strtab_section_header();
+    writer.write_shstrtab_section_header();
+
+    buffer
+}
@@ -7,7 +7,12 @@
 
 mod backend;
 mod compiled_blob;
+#[cfg(feature = "gdb-jit")]
+mod gdb;
 mod memory;
+mod tls;
+#[cfg(all(unix, feature = "unwind"))]
+mod unwind;
 
 pub use crate::backend::{JITBuilder, JITModule};
 
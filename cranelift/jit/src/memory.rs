@@ -5,33 +5,23 @@ use memmap2::MmapMut;
 
 #[cfg(not(any(feature = "selinux-fix", windows)))]
 use std::alloc;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::c_void;
 use std::io;
 use std::mem;
-use std::ptr;
 use wasmtime_jit_icache_coherence as icache_coherence;
 
 /// A simple struct consisting of a pointer and length.
 struct PtrLen {
+    /// Owns the mapping, which is unmapped when this is dropped.
     #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
-    map: Option<MmapMut>,
+    _map: MmapMut,
 
     ptr: *mut u8,
     len: usize,
 }
 
 impl PtrLen {
-    /// Create a new empty `PtrLen`.
-    fn new() -> Self {
-        Self {
-            #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
-            map: None,
-
-            ptr: ptr::null_mut(),
-            len: 0,
-        }
-    }
-
     /// Create a new `PtrLen` pointing to at least `size` bytes of memory,
     /// suitably sized and aligned for memory protection.
     #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
@@ -42,7 +32,7 @@ impl PtrLen {
             // around compile time borrow errors.
             Self {
                 ptr: mmap.as_mut_ptr(),
-                map: Some(mmap),
+                _map: mmap,
                 len: alloc_size,
             }
         })
@@ -76,7 +66,7 @@ impl PtrLen {
         // VirtualAlloc always rounds up to the next multiple of the page size
         let ptr = unsafe {
             VirtualAlloc(
-                ptr::null_mut(),
+                std::ptr::null_mut(),
                 size,
                 MEM_COMMIT | MEM_RESERVE,
                 PAGE_READWRITE,
@@ -120,15 +110,38 @@ pub(crate) enum BranchProtection {
     BTI,
 }
 
+/// The minimum size of the regions of memory requested from the system.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The state of a page that contains live allocations.
+struct Page {
+    /// The number of live allocations overlapping the page.
+    live: usize,
+}
+
 /// JIT memory manager. This manages pages of suitably aligned and
 /// accessible memory. Memory will be leaked by default to have
 /// function pointers remain valid for the remainder of the
 /// program's life.
+///
+/// Allocations are carved out of chunks of memory using a first-fit free
+/// list. Pages are writable until they are protected, after which the free
+/// space left in them can't be used anymore. Once all allocations in a
+/// protected page have been freed, the page is made writable again and
+/// returned to the free list, so memory is never writable and executable at
+/// the same time.
 pub(crate) struct Memory {
-    allocations: Vec<PtrLen>,
-    already_protected: usize,
-    current: PtrLen,
-    position: usize,
+    /// The chunks of memory allocated from the system, by start address.
+    allocations: BTreeMap<usize, PtrLen>,
+    /// Writable ranges of memory that are available for allocation, as a map
+    /// from start to end address.
+    free: BTreeMap<usize, usize>,
+    /// Pages that contain live allocations, by address.
+    pages: BTreeMap<usize, Page>,
+    /// Pages that contain live allocations and haven't been protected since
+    /// they were last writable, kept apart from `pages` so that protecting
+    /// them doesn't have to visit every live page.
+    unprotected: BTreeSet<usize>,
     branch_protection: BranchProtection,
 }
 
@@ -137,52 +150,187 @@ unsafe impl Send for Memory {}
 impl Memory {
     pub(crate) fn new(branch_protection: BranchProtection) -> Self {
         Self {
-            allocations: Vec::new(),
-            already_protected: 0,
-            current: PtrLen::new(),
-            position: 0,
+            allocations: BTreeMap::new(),
+            free: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            unprotected: BTreeSet::new(),
             branch_protection,
         }
     }
 
-    fn finish_current(&mut self) {
-        self.allocations
-            .push(mem::replace(&mut self.current, PtrLen::new()));
-        self.position = 0;
-    }
-
     pub(crate) fn allocate(&mut self, size: usize, align: u64) -> io::Result<*mut u8> {
         let align = usize::try_from(align).expect("alignment too big");
-        if self.position % align != 0 {
-            self.position += align - self.position % align;
-            debug_assert!(self.position % align == 0);
+        // Zero-sized allocations still take up a byte so that they can be
+        // freed like any other allocation.
+        let size = size.max(1);
+
+        let start = match self.find_free(size, align) {
+            Some(start) => start,
+            None => {
+                // Chunks are page aligned, so make room for aligning the
+                // allocation if it needs a larger alignment than that.
+                let padding = align.saturating_sub(region::page::size());
+                let chunk = PtrLen::with_size((size + padding).max(CHUNK_SIZE))?;
+                let (start, len) = (chunk.ptr as usize, chunk.len);
+                self.allocations.insert(start, chunk);
+                self.insert_free(start, start + len);
+                self.find_free(size, align)
+                    .expect("new chunk must fit the allocation")
+            }
+        };
+
+        self.remove_free(start, start + size);
+        for page in pages(start, start + size) {
+            self.pages
+                .entry(page)
+                .or_insert_with(|| {
+                    self.unprotected.insert(page);
+                    Page { live: 0 }
+                })
+                .live += 1;
         }
+        Ok(start as *mut u8)
+    }
+
+    /// Frees an allocation of `size` bytes at `ptr` made by `allocate`.
+    ///
+    /// The memory is available for new allocations right away if it hasn't
+    /// been protected yet. Otherwise the pages of the allocation are made
+    /// writable and reused once they don't contain any live allocations.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must not be used anymore, in particular no code in it
+    /// may be executing.
+    pub(crate) unsafe fn free(&mut self, ptr: *mut u8, size: usize) -> ModuleResult<()> {
+        let start = ptr as usize;
+        let end = start + size.max(1);
+        let page_size = region::page::size();
 
-        if size <= self.current.len - self.position {
-            // TODO: Ensure overflow is not possible.
-            let ptr = unsafe { self.current.ptr.add(self.position) };
-            self.position += size;
-            return Ok(ptr);
+        for page in pages(start, end) {
+            let state = self
+                .pages
+                .get_mut(&page)
+                .expect("freed memory must be allocated");
+            state.live -= 1;
+            let protected = !self.unprotected.contains(&page);
+            if state.live == 0 {
+                if protected {
+                    region::protect(page as *const u8, page_size, region::Protection::READ_WRITE)
+                        .map_err(|e| {
+                        ModuleError::Backend(
+                            anyhow::Error::new(e).context("unable to make memory writable"),
+                        )
+                    })?;
+                }
+                self.pages.remove(&page);
+                self.unprotected.remove(&page);
+                self.insert_free(page, page + page_size);
+            } else if !protected {
+                self.insert_free(start.max(page), end.min(page + page_size));
+            }
         }
+        Ok(())
+    }
 
-        self.finish_current();
+    /// Returns the address of the first free range that fits an allocation.
+    fn find_free(&self, size: usize, align: usize) -> Option<usize> {
+        self.free.iter().find_map(|(&start, &end)| {
+            let aligned = start.checked_next_multiple_of(align)?;
+            (aligned.checked_add(size)? <= end).then_some(aligned)
+        })
+    }
 
-        // TODO: Allocate more at a time.
-        self.current = PtrLen::with_size(size)?;
-        self.position = size;
+    /// Removes `start..end` from the free range that contains it.
+    fn remove_free(&mut self, start: usize, end: usize) {
+        let (&free_start, &free_end) = self
+            .free
+            .range(..=start)
+            .next_back()
+            .expect("allocation must be in a free range");
+        debug_assert!(end <= free_end);
+        self.free.remove(&free_start);
+        if free_start < start {
+            self.free.insert(free_start, start);
+        }
+        if end < free_end {
+            self.free.insert(end, free_end);
+        }
+    }
 
-        Ok(self.current.ptr)
+    /// Removes all free ranges overlapping `start..end`.
+    fn remove_free_overlapping(&mut self, start: usize, end: usize) {
+        let overlapping = self
+            .free
+            .range(..end)
+            .rev()
+            .take_while(|(_, &free_end)| free_end > start)
+            .map(|(&free_start, &free_end)| (free_start, free_end))
+            .collect::<Vec<_>>();
+        for (free_start, free_end) in overlapping {
+            self.free.remove(&free_start);
+            if free_start < start {
+                self.free.insert(free_start, start);
+            }
+            if end < free_end {
+                self.free.insert(end, free_end);
+            }
+        }
+    }
+
+    /// Adds `start..end` to the free ranges, merging it with the ranges it
+    /// overlaps or is adjacent to within the same chunk.
+    fn insert_free(&mut self, mut start: usize, mut end: usize) {
+        if let Some((&prev_start, &prev_end)) = self.free.range(..=start).next_back() {
+            if prev_end > start || (prev_end == start && !self.allocations.contains_key(&start)) {
+                self.free.remove(&prev_start);
+                start = prev_start;
+                end = end.max(prev_end);
+            }
+        }
+        while let Some((&next_start, &next_end)) = self.free.range(start..).next() {
+            if next_start > end || (next_start == end && self.allocations.contains_key(&end)) {
+                break;
+            }
+            self.free.remove(&next_start);
+            end = end.max(next_end);
+        }
+        self.free.insert(start, end);
+    }
+
+    /// Returns the ranges of pages with live allocations that haven't been
+    /// protected yet, and marks them as protected.
+    fn take_unprotected(&mut self) -> Vec<(*mut u8, usize)> {
+        let page_size = region::page::size();
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for page in mem::take(&mut self.unprotected) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == page && !self.allocations.contains_key(&page) => {
+                    *end += page_size;
+                }
+                _ => ranges.push((page, page + page_size)),
+            }
+        }
+
+        // The rest of the protected pages can't be allocated from anymore.
+        for &(start, end) in &ranges {
+            self.remove_free_overlapping(start, end);
+        }
+        ranges
+            .into_iter()
+            .map(|(start, end)| (start as *mut u8, end - start))
+            .collect()
     }
 
     /// Set all memory allocated in this `Memory` up to now as readable and executable.
     pub(crate) fn set_readable_and_executable(&mut self) -> ModuleResult<()> {
-        self.finish_current();
+        let ranges = self.take_unprotected();
 
         // Clear all the newly allocated code from cache if the processor requires it
         //
         // Do this before marking the memory as R+X, technically we should be able to do it after
         // but there are some CPU's that have had errata about doing this with read only memory.
-        for &PtrLen { ptr, len, .. } in self.non_protected_allocations_iter() {
+        for &(ptr, len) in &ranges {
             unsafe {
                 icache_coherence::clear_cache(ptr as *const c_void, len)
                     .expect("Failed cache clear")
@@ -218,22 +366,19 @@ impl Memory {
             Ok(())
         };
 
-        for &PtrLen { ptr, len, .. } in self.non_protected_allocations_iter() {
+        for &(ptr, len) in &ranges {
             set_region_readable_and_executable(ptr, len)?;
         }
 
         // Flush any in-flight instructions from the pipeline
         icache_coherence::pipeline_flush_mt().expect("Failed pipeline flush");
 
-        self.already_protected = self.allocations.len();
         Ok(())
     }
 
     /// Set all memory allocated in this `Memory` up to now as readonly.
     pub(crate) fn set_readonly(&mut self) -> ModuleResult<()> {
-        self.finish_current();
-
-        for &PtrLen { ptr, len, .. } in self.non_protected_allocations_iter() {
+        for (ptr, len) in self.take_unprotected() {
             unsafe {
                 region::protect(ptr, len, region::Protection::READ).map_err(|e| {
                     ModuleError::Backend(
@@ -243,34 +388,30 @@ impl Memory {
             }
         }
 
-        self.already_protected = self.allocations.len();
         Ok(())
     }
 
-    /// Iterates non protected memory allocations that are of not zero bytes in size.
-    fn non_protected_allocations_iter(&self) -> impl Iterator<Item = &PtrLen> {
-        let iter = self.allocations[self.already_protected..].iter();
-
-        #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
-        return iter.filter(|&PtrLen { ref map, len, .. }| *len != 0 && map.is_some());
-
-        #[cfg(any(target_os = "windows", not(feature = "selinux-fix")))]
-        return iter.filter(|&PtrLen { len, .. }| *len != 0);
-    }
-
     /// Frees all allocated memory regions that would be leaked otherwise.
     /// Likely to invalidate existing function pointers, causing unsafety.
     pub(crate) unsafe fn free_memory(&mut self) {
         self.allocations.clear();
-        self.already_protected = 0;
+        self.free.clear();
+        self.pages.clear();
+        self.unprotected.clear();
     }
 }
 
 impl Drop for Memory {
     fn drop(&mut self) {
         // leak memory to guarantee validity of function pointers
-        mem::replace(&mut self.allocations, Vec::new())
-            .into_iter()
+        mem::take(&mut self.allocations)
+            .into_values()
             .for_each(mem::forget);
     }
 }
+
+/// Returns the addresses of the pages overlapping `start..end`.
+fn pages(start: usize, end: usize) -> impl Iterator<Item = usize> {
+    let page_size = region::page::size();
+    (region::page::floor(start)..end).step_by(page_size)
+}
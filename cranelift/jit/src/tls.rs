@@ -0,0 +1,325 @@
+//! Thread-local storage for JIT-compiled code.
+//!
+//! Code compiled with the `elf_gd` TLS model expects the dynamic linker to
+//! hand out the address of a thread-local variable, either through a call to
+//! `__tls_get_addr` on x86_64 or through a TLS descriptor on aarch64. JIT
+//! compiled code isn't known to the dynamic linker, so instead each TLS data
+//! object gets a [`TlsDescriptor`] in the writable memory of the module, and
+//! the TLS relocations are resolved against that descriptor. The descriptor is
+//! passed to [`tls_get_addr`], which lazily allocates the storage of the data
+//! object for the current thread and initializes it from the data object's
+//! definition.
+//!
+//! The storage of all threads is owned by a process-wide registry, so that it
+//! can be freed when the data object is freed, and each thread caches the
+//! addresses of its storage so that accesses don't need to take a lock. Keys
+//! of released descriptors are reused, which bounds the size of the registry
+//! and of the caches by the number of live TLS data objects.
+
+use std::alloc::{self, Layout};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Whether the JIT supports TLS data objects on the host.
+pub(crate) const SUPPORTED: bool = cfg!(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+));
+
+/// The storage of the TLS data objects of all threads.
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    slots: Vec::new(),
+    free_keys: Vec::new(),
+});
+
+/// Hands out the generation of each definition of a TLS data object.
+///
+/// Generations are never reused, so that a thread can tell whether the
+/// storage it cached for a key belongs to the current definition.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Hands out the ids that the registry uses to tell threads apart.
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+struct Registry {
+    /// The storage of each key, indexed by key.
+    slots: Vec<Slot>,
+    /// Keys of released descriptors, which are handed out again.
+    free_keys: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Slot {
+    /// The generation of the current definition, storage of other
+    /// generations is stale.
+    generation: u64,
+    /// The storage of each thread that accessed the current definition.
+    blocks: HashMap<u64, TlsBlock>,
+}
+
+impl Registry {
+    fn lock() -> std::sync::MutexGuard<'static, Registry> {
+        // The registry is consistent even if a thread panicked while holding
+        // the lock, as it doesn't call out to code that could panic while an
+        // update is in progress.
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Describes a TLS data object to [`tls_get_addr`].
+///
+/// The layout of the first field is fixed by the aarch64 TLSDESC sequence,
+/// which loads the function to call from the start of the descriptor.
+#[repr(C)]
+pub(crate) struct TlsDescriptor {
+    /// The function called by the aarch64 TLSDESC sequence.
+    resolver: *const u8,
+    /// Identifies the storage of this data object in the registry.
+    key: usize,
+    /// Identifies the current definition, see [`NEXT_GENERATION`].
+    generation: u64,
+    /// The initial contents of the storage, or null if the data object
+    /// isn't defined yet.
+    template: *const u8,
+    size: usize,
+    align: usize,
+}
+
+impl TlsDescriptor {
+    /// Creates a descriptor for a data object that isn't defined yet.
+    pub(crate) fn new() -> Self {
+        let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        let mut registry = Registry::lock();
+        let key = match registry.free_keys.pop() {
+            Some(key) => key,
+            None => {
+                registry.slots.push(Slot::default());
+                registry.slots.len() - 1
+            }
+        };
+        registry.slots[key].generation = generation;
+        Self {
+            resolver: resolver(),
+            key,
+            generation,
+            template: ptr::null(),
+            size: 0,
+            align: 1,
+        }
+    }
+
+    /// Sets the definition of the data object, which is copied into the
+    /// storage of each thread on first access.
+    pub(crate) fn define(&mut self, template: *const u8, size: usize, align: usize) {
+        self.template = template;
+        self.size = size;
+        self.align = align;
+    }
+
+    /// Removes the definition of the data object and frees the storage of
+    /// all threads. Threads that access it after it's defined again start
+    /// out with the new definition.
+    pub(crate) fn undefine(&mut self) {
+        self.template = ptr::null();
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+        let mut registry = Registry::lock();
+        let slot = &mut registry.slots[self.key];
+        slot.generation = self.generation;
+        let blocks = std::mem::take(&mut slot.blocks);
+        drop(registry);
+        drop(blocks);
+    }
+
+    /// Frees the storage of all threads and makes the key of the descriptor
+    /// available to new descriptors. The descriptor must not be used
+    /// afterwards.
+    pub(crate) fn release(&mut self) {
+        self.undefine();
+        Registry::lock().free_keys.push(self.key);
+    }
+}
+
+/// The storage of a TLS data object in one thread.
+struct TlsBlock {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// The storage is only accessed by the thread it belongs to, the registry only
+// frees it.
+unsafe impl Send for TlsBlock {}
+
+impl Drop for TlsBlock {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// The addresses of the storage of the current thread, indexed by key.
+struct ThreadCache {
+    /// Identifies the thread in the registry.
+    thread: u64,
+    /// The generation and address of the storage of each key, if this
+    /// thread has accessed it.
+    entries: Vec<Option<(u64, *mut u8)>>,
+}
+
+impl Drop for ThreadCache {
+    /// Frees the storage of the thread when it exits.
+    fn drop(&mut self) {
+        let mut registry = Registry::lock();
+        let mut blocks = Vec::new();
+        for (key, entry) in self.entries.iter().enumerate() {
+            if let Some((generation, _)) = *entry {
+                let slot = &mut registry.slots[key];
+                if slot.generation == generation {
+                    blocks.extend(slot.blocks.remove(&self.thread));
+                }
+            }
+        }
+        drop(registry);
+        drop(blocks);
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<ThreadCache> = RefCell::new(ThreadCache {
+        thread: NEXT_THREAD.fetch_add(1, Ordering::Relaxed),
+        entries: Vec::new(),
+    });
+}
+
+/// Returns the address of the current thread's storage for the data object
+/// described by `desc`, allocating it on first access.
+///
+/// On x86_64 the `__tls_get_addr` call emitted for `tls_value` is redirected
+/// here, which works because the argument of `__tls_get_addr` is the address
+/// named by the TLS relocation.
+///
+/// Accessing a data object that isn't defined, either because it was never
+/// defined or because it was freed, aborts the process: this is called from
+/// JIT-compiled code, which can't be unwound through.
+pub(crate) unsafe extern "C" fn tls_get_addr(desc: *const TlsDescriptor) -> *mut u8 {
+    let desc = &*desc;
+    if desc.template.is_null() {
+        eprintln!("cranelift-jit: TLS data object accessed while it isn't defined");
+        std::process::abort();
+    }
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.entries.len() <= desc.key {
+            cache.entries.resize(desc.key + 1, None);
+        }
+        if let Some((generation, ptr)) = cache.entries[desc.key] {
+            if generation == desc.generation {
+                return ptr;
+            }
+        }
+
+        // Allocate at least one byte, as allocating zero bytes is UB.
+        let layout = Layout::from_size_align(desc.size.max(1), desc.align).unwrap();
+        let ptr = alloc::alloc(layout);
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        ptr::copy_nonoverlapping(desc.template, ptr.as_ptr(), desc.size);
+
+        let mut registry = Registry::lock();
+        let slot = &mut registry.slots[desc.key];
+        debug_assert_eq!(slot.generation, desc.generation);
+        slot.blocks.insert(cache.thread, TlsBlock { ptr, layout });
+        cache.entries[desc.key] = Some((desc.generation, ptr.as_ptr()));
+        ptr.as_ptr()
+    })
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "aarch64")))]
+fn resolver() -> *const u8 {
+    ptr::null()
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+fn resolver() -> *const u8 {
+    extern "C" {
+        fn cranelift_jit_tlsdesc_resolver();
+    }
+    cranelift_jit_tlsdesc_resolver as *const u8
+}
+
+// The resolver of the aarch64 TLSDESC sequence. It's called with the address
+// of the descriptor in x0 and returns the offset of the variable from the
+// thread pointer in x0. Unlike a regular function it must preserve all other
+// registers, so everything the call to `tls_get_addr` may clobber is saved.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+std::arch::global_asm!(
+    ".p2align 4",
+    ".hidden cranelift_jit_tlsdesc_resolver",
+    ".global cranelift_jit_tlsdesc_resolver",
+    ".type cranelift_jit_tlsdesc_resolver, %function",
+    "cranelift_jit_tlsdesc_resolver:",
+    "hint #34", // bti c
+    "stp x29, x30, [sp, -16]!",
+    "mov x29, sp",
+    "stp x1, x2, [sp, -16]!",
+    "stp x3, x4, [sp, -16]!",
+    "stp x5, x6, [sp, -16]!",
+    "stp x7, x8, [sp, -16]!",
+    "stp x9, x10, [sp, -16]!",
+    "stp x11, x12, [sp, -16]!",
+    "stp x13, x14, [sp, -16]!",
+    "stp x15, x16, [sp, -16]!",
+    "stp x17, x18, [sp, -16]!",
+    "stp q0, q1, [sp, -32]!",
+    "stp q2, q3, [sp, -32]!",
+    "stp q4, q5, [sp, -32]!",
+    "stp q6, q7, [sp, -32]!",
+    "stp q8, q9, [sp, -32]!",
+    "stp q10, q11, [sp, -32]!",
+    "stp q12, q13, [sp, -32]!",
+    "stp q14, q15, [sp, -32]!",
+    "stp q16, q17, [sp, -32]!",
+    "stp q18, q19, [sp, -32]!",
+    "stp q20, q21, [sp, -32]!",
+    "stp q22, q23, [sp, -32]!",
+    "stp q24, q25, [sp, -32]!",
+    "stp q26, q27, [sp, -32]!",
+    "stp q28, q29, [sp, -32]!",
+    "stp q30, q31, [sp, -32]!",
+    "mrs x1, nzcv",
+    "stp x1, xzr, [sp, -16]!",
+    "bl {tls_get_addr}",
+    "mrs x1, tpidr_el0",
+    "sub x0, x0, x1",
+    "ldp x1, xzr, [sp], 16",
+    "msr nzcv, x1",
+    "ldp q30, q31, [sp], 32",
+    "ldp q28, q29, [sp], 32",
+    "ldp q26, q27, [sp], 32",
+    "ldp q24, q25, [sp], 32",
+    "ldp q22, q23, [sp], 32",
+    "ldp q20, q21, [sp], 32",
+    "ldp q18, q19, [sp], 32",
+    "ldp q16, q17, [sp], 32",
+    "ldp q14, q15, [sp], 32",
+    "ldp q12, q13, [sp], 32",
+    "ldp q10, q11, [sp], 32",
+    "ldp q8, q9, [sp], 32",
+    "ldp q6, q7, [sp], 32",
+    "ldp q4, q5, [sp], 32",
+    "ldp q2, q3, [sp], 32",
+    "ldp q0, q1, [sp], 32",
+    "ldp x17, x18, [sp], 16",
+    "ldp x15, x16, [sp], 16",
+    "ldp x13, x14, [sp], 16",
+    "ldp x11, x12, [sp], 16",
+    "ldp x9, x10, [sp], 16",
+    "ldp x7, x8, [sp], 16",
+    "ldp x5, x6, [sp], 16",
+    "ldp x3, x4, [sp], 16",
+    "ldp x1, x2, [sp], 16",
+    "ldp x29, x30, [sp], 16",
+    "ret",
+    ".size cranelift_jit_tlsdesc_resolver, . - cranelift_jit_tlsdesc_resolver",
+    tls_get_addr = sym tls_get_addr,
+);
@@ -0,0 +1,136 @@
+//! Registration of unwind information for JIT-compiled functions.
+//!
+//! Unwinders find the unwind information of code that isn't part of a loaded
+//! object file through `__register_frame`, which is given an in-memory
+//! `.eh_frame` section describing the code.
+
+use cranelift_codegen::isa::unwind::systemv;
+use cranelift_codegen::isa::TargetIsa;
+use gimli::write::{Address, EhFrame, EndianVec, FrameTable, Writer};
+use gimli::RunTimeEndian;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+/// The `.eh_frame` section of a set of functions, registered with the
+/// unwinder until this is dropped.
+pub(crate) struct UnwindRegistration {
+    /// Keeps the section alive while it's registered.
+    _eh_frame: Box<[u8]>,
+    /// The pointers passed to `__register_frame`.
+    registrations: Vec<*const u8>,
+}
+
+unsafe impl Send for UnwindRegistration {}
+
+extern "C" {
+    // libunwind or libgcc import
+    fn __register_frame(fde: *const u8);
+    fn __deregister_frame(fde: *const u8);
+}
+
+/// There are two primary unwinders on Unix platforms: libunwind and libgcc.
+///
+/// Their `__register_frame` functions take different arguments: libunwind
+/// takes a pointer to an individual FDE while libgcc takes a null-terminated
+/// list of FDEs. Following LLVM's `RTDyldMemoryManager.cpp`, libunwind is
+/// detected by the presence of `__unw_add_dynamic_fde`.
+fn using_libunwind() -> bool {
+    static USING_LIBUNWIND: AtomicUsize = AtomicUsize::new(LIBUNWIND_UNKNOWN);
+
+    const LIBUNWIND_UNKNOWN: usize = 0;
+    const LIBUNWIND_YES: usize = 1;
+    const LIBUNWIND_NO: usize = 2;
+
+    // On macOS the libgcc interface is never used so libunwind is always used.
+    if cfg!(target_os = "macos") {
+        return true;
+    }
+
+    match USING_LIBUNWIND.load(Relaxed) {
+        LIBUNWIND_YES => true,
+        LIBUNWIND_NO => false,
+        LIBUNWIND_UNKNOWN => {
+            let looks_like_libunwind = unsafe {
+                !libc::dlsym(ptr::null_mut(), c"__unw_add_dynamic_fde".as_ptr()).is_null()
+            };
+            USING_LIBUNWIND.store(
+                if looks_like_libunwind {
+                    LIBUNWIND_YES
+                } else {
+                    LIBUNWIND_NO
+                },
+                Relaxed,
+            );
+            looks_like_libunwind
+        }
+        _ => unreachable!(),
+    }
+}
+
+impl UnwindRegistration {
+    /// Creates an `.eh_frame` section for the functions at the given
+    /// addresses and registers it with the unwinder.
+    ///
+    /// Returns `None` if the ISA doesn't support System V unwind information.
+    ///
+    /// # Safety
+    ///
+    /// The functions must stay in memory until the registration is dropped.
+    pub(crate) unsafe fn new(
+        isa: &dyn TargetIsa,
+        functions: &[(*const u8, systemv::UnwindInfo)],
+    ) -> Option<Self> {
+        let cie = isa.create_systemv_cie()?;
+        let mut table = FrameTable::default();
+        let cie_id = table.add_cie(cie);
+        for (ptr, unwind_info) in functions {
+            table.add_fde(cie_id, unwind_info.to_fde(Address::Constant(*ptr as u64)));
+        }
+
+        let endian = match isa.triple().endianness().unwrap() {
+            target_lexicon::Endianness::Little => RunTimeEndian::Little,
+            target_lexicon::Endianness::Big => RunTimeEndian::Big,
+        };
+        let mut eh_frame = EhFrame(EndianVec::new(endian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        // libgcc expects the list of FDEs to be terminated by an empty entry.
+        eh_frame.0.write_u32(0).unwrap();
+        let eh_frame = eh_frame.0.into_vec().into_boxed_slice();
+
+        let mut registrations = Vec::new();
+        if using_libunwind() {
+            // Register each FDE individually, skipping the CIE at the start
+            // and the terminator at the end.
+            let start = eh_frame.as_ptr();
+            let end = start.add(eh_frame.len() - 4);
+            let mut current = start;
+            while current < end {
+                let len = current.cast::<u32>().read_unaligned() as usize;
+                if current != start {
+                    __register_frame(current);
+                    registrations.push(current);
+                }
+                // The length doesn't include the length field itself.
+                current = current.add(len + 4);
+            }
+        } else {
+            __register_frame(eh_frame.as_ptr());
+            registrations.push(eh_frame.as_ptr());
+        }
+
+        Some(Self {
+            _eh_frame: eh_frame,
+            registrations,
+        })
+    }
+}
+
+impl Drop for UnwindRegistration {
+    fn drop(&mut self) {
+        // libgcc keeps registrations in a list sorted by decreasing address, so
+        // deregistering backwards avoids quadratic behavior.
+        for fde in self.registrations.iter().rev() {
+            unsafe { __deregister_frame(*fde) };
+        }
+    }
+}
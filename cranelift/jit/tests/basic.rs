@@ -237,3 +237,197 @@ fn empty_data_object() {
     data.define(Box::new([]));
     module.define_data(data_id, &data).unwrap();
 }
+
+#[test]
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn tls_data_object() {
+    let mut module = JITModule::new(JITBuilder::new(default_libcall_names()).unwrap());
+    let int = module.target_config().pointer_type();
+
+    let data_id = module
+        .declare_data("counter", Linkage::Local, true, true)
+        .unwrap();
+    let mut data = DataDescription::new();
+    data.define(10i64.to_ne_bytes().into());
+    module.define_data(data_id, &data).unwrap();
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I64));
+    let func_id = module
+        .declare_function("increment", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(UserFuncName::user(0, func_id.as_u32()), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.switch_to_block(block);
+
+        let gv = module.declare_data_in_func(data_id, &mut bcx.func);
+        let addr = bcx.ins().tls_value(int, gv);
+        let value = bcx.ins().load(types::I64, MemFlags::trusted(), addr, 0);
+        let value = bcx.ins().iadd_imm(value, 1);
+        bcx.ins().store(MemFlags::trusted(), value, addr, 0);
+        bcx.ins().return_(&[value]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions().unwrap();
+
+    let increment = module.get_finalized_function(func_id);
+    let increment: extern "C" fn() -> i64 = unsafe { std::mem::transmute(increment) };
+    assert_eq!(increment(), 11);
+    assert_eq!(increment(), 12);
+
+    // Each thread starts out with the initial contents of the data object.
+    let other = std::thread::spawn(move || (increment(), increment()));
+    assert_eq!(other.join().unwrap(), (11, 12));
+    assert_eq!(increment(), 13);
+
+    // Freeing the data object frees the copies of all threads.
+    unsafe { module.free_data(data_id).unwrap() };
+    let mut data = DataDescription::new();
+    data.define(20i64.to_ne_bytes().into());
+    module.define_data(data_id, &data).unwrap();
+    module.finalize_definitions().unwrap();
+    assert_eq!(increment(), 21);
+
+    unsafe { module.free_memory() };
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn unwind_info_and_perf_map() {
+    extern "C" {
+        fn _Unwind_Find_FDE(pc: *const u8, bases: *mut [usize; 3]) -> *const u8;
+    }
+
+    let mut builder = JITBuilder::new(default_libcall_names()).unwrap();
+    builder.perf_map(true);
+    #[cfg(feature = "unwind")]
+    builder.unwind_info(true);
+    #[cfg(feature = "gdb-jit")]
+    builder.gdb_jit(true);
+    let mut module = JITModule::new(builder);
+
+    let func_id = define_simple_function(&mut module);
+    module.finalize_definitions().unwrap();
+
+    let code = module.get_finalized_function(func_id);
+    let find_fde = || unsafe { _Unwind_Find_FDE(code, &mut [0; 3]) };
+    assert_eq!(find_fde().is_null(), cfg!(not(feature = "unwind")));
+
+    let map = std::fs::read_to_string(format!("/tmp/perf-{}.map", std::process::id())).unwrap();
+    assert!(map
+        .lines()
+        .any(|line| line.starts_with(&format!("{:x} ", code as usize)) && line.ends_with(" abc")));
+
+    unsafe { module.free_memory() };
+    assert!(find_fde().is_null());
+}
+
+fn define_constant_function(module: &mut JITModule, func_id: FuncId, value: i64) {
+    let mut ctx = module.make_context();
+    ctx.func.signature.returns.push(AbiParam::new(types::I64));
+    ctx.func.name = UserFuncName::user(0, func_id.as_u32());
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let block = bcx.create_block();
+        bcx.switch_to_block(block);
+        let value = bcx.ins().iconst(types::I64, value);
+        bcx.ins().return_(&[value]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+}
+
+#[test]
+fn free_function() {
+    let mut builder = JITBuilder::new(default_libcall_names()).unwrap();
+    builder.hotswap(true);
+    let mut module = JITModule::new(builder);
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I64));
+    let func_id = module
+        .declare_function("constant", Linkage::Local, &sig)
+        .unwrap();
+
+    // Freeing the function before redefining it lets its memory be reused, so recompiling it
+    // over and over only ever uses a few addresses.
+    let mut addresses = std::collections::HashSet::new();
+    for i in 0..1000 {
+        define_constant_function(&mut module, func_id, i);
+        module.finalize_definitions().unwrap();
+        let code = module.get_finalized_function(func_id);
+        let constant: extern "C" fn() -> i64 = unsafe { std::mem::transmute(code) };
+        assert_eq!(constant(), i);
+        addresses.insert(code);
+        unsafe { module.free_function(func_id).unwrap() };
+    }
+    assert!(addresses.len() <= 2, "{addresses:?}");
+
+    // Definitions replaced through hotswapping are freed along with the function.
+    define_constant_function(&mut module, func_id, 1);
+    module.prepare_for_function_redefine(func_id).unwrap();
+    define_constant_function(&mut module, func_id, 2);
+    module.finalize_definitions().unwrap();
+    assert_eq!(
+        module.read_got_entry(func_id),
+        module.get_finalized_function(func_id)
+    );
+    unsafe { module.free_function(func_id).unwrap() };
+    assert!(module.read_got_entry(func_id).is_null());
+    assert!(unsafe { module.free_function(func_id) }.is_err());
+
+    define_constant_function(&mut module, func_id, 3);
+    module.finalize_definitions().unwrap();
+    assert!(addresses.contains(&module.get_finalized_function(func_id)));
+
+    // Replaced definitions can be freed on their own, so hotswapping a function over and over
+    // only ever uses a few addresses too.
+    let mut addresses = std::collections::HashSet::new();
+    for i in 0..1000 {
+        module.prepare_for_function_redefine(func_id).unwrap();
+        define_constant_function(&mut module, func_id, i);
+        module.finalize_definitions().unwrap();
+        unsafe { module.free_replaced_definitions(func_id).unwrap() };
+        let code = module.get_finalized_function(func_id);
+        let constant: extern "C" fn() -> i64 = unsafe { std::mem::transmute(code) };
+        assert_eq!(constant(), i);
+        assert_eq!(module.read_got_entry(func_id), code);
+        addresses.insert(code);
+    }
+    assert!(addresses.len() <= 3, "{addresses:?}");
+}
+
+#[test]
+fn free_data() {
+    let mut module = JITModule::new(JITBuilder::new(default_libcall_names()).unwrap());
+
+    let data_id = module
+        .declare_data("data", Linkage::Local, false, false)
+        .unwrap();
+    let mut addresses = std::collections::HashSet::new();
+    for i in 0..1000u64 {
+        let mut data = DataDescription::new();
+        data.define(i.to_ne_bytes().into());
+        module.define_data(data_id, &data).unwrap();
+        module.finalize_definitions().unwrap();
+        let (ptr, size) = module.get_finalized_data(data_id);
+        assert_eq!(size, 8);
+        assert_eq!(unsafe { ptr.cast::<u64>().read_unaligned() }, i);
+        addresses.insert(ptr);
+        unsafe { module.free_data(data_id).unwrap() };
+    }
+    assert!(addresses.len() <= 2, "{addresses:?}");
+    assert!(unsafe { module.free_data(data_id) }.is_err());
+}
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 mod bugpoint;
 mod cat;
 mod compile;
+mod debug;
 mod disasm;
 mod interpret;
 mod print_cfg;
@@ -19,6 +20,7 @@ enum Commands {
     Test(TestOptions),
     Run(run::Options),
     Interpret(interpret::Options),
+    Debug(debug::Options),
     Cat(cat::Options),
     PrintCfg(print_cfg::Options),
     Compile(compile::Options),
@@ -80,6 +82,7 @@ fn main() -> anyhow::Result<()> {
         Commands::Cat(c) => cat::run(&c)?,
         Commands::Run(r) => run::run(&r)?,
         Commands::Interpret(i) => interpret::run(&i)?,
+        Commands::Debug(d) => debug::run(&d)?,
         Commands::PrintCfg(p) => print_cfg::run(&p)?,
         Commands::Compile(c) => compile::run(&c)?,
         Commands::Bugpoint(b) => bugpoint::run(&b)?,
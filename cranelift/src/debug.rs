@@ -0,0 +1,752 @@
+//! CLI tool to step through Cranelift IR functions in the interpreter.
+
+use crate::run::create_target_isa;
+use crate::utils::read_to_string;
+use anyhow::{anyhow, bail, ensure, Result};
+use clap::Parser;
+use cranelift_codegen::data_value::{DataValue, DisplayDataValues};
+use cranelift_codegen::ir::{Block, DynamicStackSlot, Function, Inst, StackSlot, Value, ValueDef};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::packed_option::ReservedValue;
+use cranelift_codegen::write::write_block_header;
+use cranelift_entity::EntityRef;
+use cranelift_filetests::TestFileCompiler;
+use cranelift_interpreter::address::AddressSize;
+use cranelift_interpreter::environment::FunctionStore;
+use cranelift_interpreter::instruction::DfgInstructionContext;
+use cranelift_interpreter::interpreter::{InterpreterError, InterpreterState};
+use cranelift_interpreter::state::State;
+use cranelift_interpreter::step::{step, ControlFlow, CraneliftTrap};
+use cranelift_reader::{parse_run_command, parse_test, ParseOptions, RunCommand, TestFile};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use target_lexicon::Triple;
+
+/// Step through clif code in the interpreter
+#[derive(Parser)]
+pub struct Options {
+    /// Specify an input file to be used.
+    file: PathBuf,
+
+    /// The function to call and its arguments, e.g. `%add(1, 2)`. Defaults to the first `run` or
+    /// `print` command in the file.
+    invocation: Option<String>,
+
+    /// Set a breakpoint before starting, e.g. `block1`, `inst3`, `v2` or `%add:block1`
+    #[arg(short, long = "break")]
+    breakpoints: Vec<String>,
+}
+
+const HELP: &str = "\
+step, s              execute the next instruction, stepping into calls
+next, n              execute the next instruction, stepping over calls
+finish               run until the current function returns
+continue, c          run until a breakpoint is hit or the function finishes
+break, b [LOCATION]  set a breakpoint on a block, instruction or the definition of a value,
+                     e.g. `block1`, `inst3`, `v2` or `%add:block1`; lists breakpoints if
+                     no location is given
+delete, d ID         delete a breakpoint
+print, p NAME        print an SSA value, stack slot or dynamic stack slot of the current
+                     frame, e.g. `v1`, `ss0` or `dss0`
+values               print all of the assigned SSA values of the current frame
+backtrace, bt        print the call stack
+list, l              print the current function, marking the next instruction
+native               run the function natively and compare the result with the
+                     interpreter's; the function must not trap
+restart              run the function again from the start, keeping breakpoints
+quit, q              exit the debugger
+An empty line repeats the previous command.";
+
+/// Run a function from a file in the interpreter, one instruction at a time, reading debugger
+/// commands from stdin.
+pub fn run(options: &Options) -> Result<()> {
+    let contents = read_to_string(&options.file)?;
+    debug_file_contents(
+        &contents,
+        options.invocation.as_deref(),
+        &options.breakpoints,
+        &mut io::stdin().lock(),
+        &mut io::stdout(),
+    )
+}
+
+/// Main body of `run` separated for testing.
+fn debug_file_contents(
+    contents: &str,
+    invocation: Option<&str>,
+    breakpoints: &[String],
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let options = ParseOptions {
+        // use the host's default calling convention so the functions can also run natively
+        default_calling_convention: CallConv::triple_default(&Triple::host()),
+        ..ParseOptions::default()
+    };
+    let test_file = parse_test(contents, options)?;
+    let (function, arguments) = match invocation {
+        Some(invocation) => parse_invocation(&test_file, invocation)?,
+        None => first_run_command(&test_file)?,
+    };
+
+    let mut debugger = Debugger::new(&test_file, function, arguments);
+    for breakpoint in breakpoints {
+        debugger.add_breakpoint(breakpoint, output)?;
+    }
+    let arguments: Vec<String> = debugger.arguments.iter().map(|a| a.to_string()).collect();
+    writeln!(output, "{}({})", function.name, arguments.join(", "))?;
+    debugger.print_position(output)?;
+
+    let mut line = String::new();
+    let mut previous = String::new();
+    loop {
+        write!(output, "(debug) ")?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+        let command = match line.trim() {
+            "" => previous.clone(),
+            command => command.to_string(),
+        };
+        if command == "q" || command == "quit" {
+            return Ok(());
+        }
+        if let Err(e) = debugger.command(&command, output) {
+            writeln!(output, "error: {}", e)?;
+        }
+        previous = command;
+    }
+}
+
+/// Parse an invocation like `%add(1, 2)` of one of the functions in `test_file`.
+fn parse_invocation<'a>(
+    test_file: &'a TestFile,
+    invocation: &str,
+) -> Result<(&'a Function, Vec<DataValue>)> {
+    let name = invocation.split('(').next().unwrap_or_default().trim();
+    let function = test_file
+        .functions
+        .iter()
+        .map(|(function, _)| function)
+        .find(|function| function.name.to_string() == name)
+        .ok_or_else(|| anyhow!("no function named {}", name))?;
+    match parse_run_command(&format!("print: {}", invocation), &function.signature)? {
+        Some(RunCommand::Print(invocation)) => Ok((function, invocation.args)),
+        _ => bail!("unable to parse the invocation: {}", invocation),
+    }
+}
+
+/// Find the first function in `test_file` with a `run` or `print` command and its arguments.
+fn first_run_command<'a>(test_file: &'a TestFile) -> Result<(&'a Function, Vec<DataValue>)> {
+    for (function, details) in &test_file.functions {
+        for comment in &details.comments {
+            match parse_run_command(comment.text, &function.signature)? {
+                Some(RunCommand::Print(invocation)) | Some(RunCommand::Run(invocation, ..)) => {
+                    return Ok((function, invocation.args))
+                }
+                None => {}
+            }
+        }
+    }
+    bail!("no function invocation given and the file has no `run` or `print` commands")
+}
+
+/// Compile `test_file` for the host and call `function` natively.
+fn run_natively(
+    test_file: &TestFile,
+    function: &Function,
+    arguments: &[DataValue],
+) -> Result<Vec<DataValue>> {
+    let isa = create_target_isa(&test_file.isa_spec)?;
+    let mut tfc = TestFileCompiler::new(isa);
+    tfc.add_testfile(test_file)?;
+    let compiled = tfc.compile()?;
+    let trampoline = compiled.get_trampoline(function).unwrap();
+    Ok(trampoline.call(arguments))
+}
+
+/// How the interpreted function finished.
+enum Outcome {
+    Returned(Vec<DataValue>),
+    Trapped(CraneliftTrap),
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Returned(values) => write!(f, "returned: {}", DisplayDataValues(values)),
+            Outcome::Trapped(trap) => write!(f, "trapped: {}", trap),
+            Outcome::Failed(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// Where execution is stopped at a breakpoint.
+enum Location {
+    Block(Block),
+    Inst(Inst),
+}
+
+struct Breakpoint<'a> {
+    id: usize,
+    function: &'a Function,
+    location: Location,
+}
+
+impl Breakpoint<'_> {
+    /// Whether execution should stop before executing `inst` of `function`.
+    fn matches(&self, function: &Function, inst: Inst) -> bool {
+        std::ptr::eq(self.function, function)
+            && match self.location {
+                Location::Block(block) => function.layout.first_inst(block) == Some(inst),
+                Location::Inst(i) => i == inst,
+            }
+    }
+}
+
+impl fmt::Display for Breakpoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Location::Block(block) => write!(f, "{}:{}", self.function.name, block),
+            Location::Inst(inst) => write!(f, "{}:{}", self.function.name, inst),
+        }
+    }
+}
+
+/// Drives the interpreter one instruction at a time, keeping the position of each frame so
+/// execution can be stopped anywhere.
+struct Debugger<'a> {
+    test_file: &'a TestFile<'a>,
+    function: &'a Function,
+    arguments: Vec<DataValue>,
+    state: InterpreterState<'a>,
+    /// The next instruction to execute in each frame of `state`; for a caller this is the call
+    /// instruction.
+    positions: Vec<Inst>,
+    outcome: Option<Outcome>,
+    breakpoints: Vec<Breakpoint<'a>>,
+    next_breakpoint_id: usize,
+}
+
+impl<'a> Debugger<'a> {
+    fn new(test_file: &'a TestFile<'a>, function: &'a Function, arguments: Vec<DataValue>) -> Self {
+        let mut env = FunctionStore::default();
+        for (func, _) in &test_file.functions {
+            env.add(func.name.to_string(), func);
+        }
+        let mut debugger = Self {
+            test_file,
+            function,
+            arguments,
+            state: InterpreterState::default().with_function_store(env),
+            positions: vec![],
+            outcome: None,
+            breakpoints: vec![],
+            next_breakpoint_id: 1,
+        };
+        debugger.enter(function, &debugger.arguments.clone());
+        debugger
+    }
+
+    /// Run the function again from the start.
+    fn restart(&mut self) {
+        let env = self.state.functions.clone();
+        self.state = InterpreterState::default().with_function_store(env);
+        self.positions.clear();
+        self.outcome = None;
+        self.enter(self.function, &self.arguments.clone());
+    }
+
+    /// Push a frame for a call to `function`.
+    fn enter(&mut self, function: &'a Function, arguments: &[DataValue]) {
+        let first_block = function
+            .layout
+            .entry_block()
+            .expect("to have a first block");
+        self.state.push_frame(function);
+        self.state
+            .current_frame_mut()
+            .set_all(function.dfg.block_params(first_block), arguments.to_vec());
+        self.positions.push(Inst::reserved_value());
+        self.continue_at(function.layout.first_inst(first_block));
+    }
+
+    /// Continue the current frame at `inst`, which is `None` past the end of a block.
+    fn continue_at(&mut self, inst: Option<Inst>) {
+        match inst {
+            Some(inst) => *self.positions.last_mut().unwrap() = inst,
+            None => self.outcome = Some(Outcome::Failed(InterpreterError::Unreachable.to_string())),
+        }
+    }
+
+    /// Execute the next instruction.
+    fn step_once(&mut self) {
+        let function = self.state.get_current_function();
+        let inst = *self.positions.last().unwrap();
+        let inst_context = DfgInstructionContext::new(inst, &function.dfg);
+        let control_flow = match step(&mut self.state, inst_context) {
+            Ok(control_flow) => control_flow,
+            Err(e) => {
+                self.outcome = Some(Outcome::Failed(e.to_string()));
+                return;
+            }
+        };
+        match control_flow {
+            ControlFlow::Assign(values) => {
+                self.state
+                    .current_frame_mut()
+                    .set_all(function.dfg.inst_results(inst), values.to_vec());
+                self.continue_at(function.layout.next_inst(inst));
+            }
+            ControlFlow::Continue => self.continue_at(function.layout.next_inst(inst)),
+            ControlFlow::ContinueAt(block, block_arguments) => {
+                self.state
+                    .current_frame_mut()
+                    .set_all(function.dfg.block_params(block), block_arguments.to_vec());
+                self.continue_at(function.layout.first_inst(block));
+            }
+            ControlFlow::Call(callee, arguments) => self.enter(callee, &arguments),
+            ControlFlow::ReturnCall(callee, arguments) => {
+                self.state.pop_frame();
+                self.positions.pop();
+                self.enter(callee, &arguments);
+            }
+            ControlFlow::Return(values) => {
+                self.state.pop_frame();
+                self.positions.pop();
+                match self.positions.last() {
+                    Some(&call) => {
+                        let caller = self.state.get_current_function();
+                        self.state
+                            .current_frame_mut()
+                            .set_all(caller.dfg.inst_results(call), values.to_vec());
+                        self.continue_at(caller.layout.next_inst(call));
+                    }
+                    None => self.outcome = Some(Outcome::Returned(values.to_vec())),
+                }
+            }
+            ControlFlow::Trap(trap) => self.outcome = Some(Outcome::Trapped(trap)),
+        }
+    }
+
+    /// Execute instructions until `stop` returns true, a breakpoint is hit or the function
+    /// finishes.
+    fn resume(&mut self, output: &mut dyn Write, stop: impl Fn(&Self) -> bool) -> Result<()> {
+        ensure!(
+            self.outcome.is_none(),
+            "the function has finished; use `restart` to run it again"
+        );
+        loop {
+            self.step_once();
+            if self.outcome.is_some() || stop(self) || self.breakpoint().is_some() {
+                break;
+            }
+        }
+        if let Some(breakpoint) = self.breakpoint() {
+            writeln!(output, "breakpoint {} at {}", breakpoint.id, breakpoint)?;
+        }
+        self.print_position(output)
+    }
+
+    /// The breakpoint at the next instruction, if execution is stopped at one.
+    fn breakpoint(&self) -> Option<&Breakpoint<'a>> {
+        if self.outcome.is_some() {
+            return None;
+        }
+        let inst = *self.positions.last()?;
+        let function = self.state.get_current_function();
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.matches(function, inst))
+    }
+
+    /// The function of the current frame.
+    fn current_function(&self) -> Result<&'a Function> {
+        ensure!(
+            !self.state.frame_stack.is_empty(),
+            "the function is not running; use `restart` to run it again"
+        );
+        Ok(self.state.get_current_function())
+    }
+
+    /// Print the next instruction, or how the function finished.
+    fn print_position(&self, output: &mut dyn Write) -> Result<()> {
+        match (&self.outcome, self.positions.last()) {
+            (Some(outcome), _) => writeln!(output, "{}", outcome)?,
+            (None, Some(&inst)) => {
+                let function = self.state.get_current_function();
+                writeln!(output, "{}", describe_inst(function, inst))?
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Execute a single debugger command.
+    fn command(&mut self, command: &str, output: &mut dyn Write) -> Result<()> {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        match name {
+            "s" | "step" => self.resume(output, |_| true),
+            "n" | "next" => {
+                let depth = self.positions.len();
+                self.resume(output, |d| d.positions.len() <= depth)
+            }
+            "finish" => {
+                let depth = self.positions.len();
+                self.resume(output, |d| d.positions.len() < depth)
+            }
+            "c" | "continue" => self.resume(output, |_| false),
+            "b" | "break" if argument.is_empty() => {
+                for breakpoint in &self.breakpoints {
+                    writeln!(output, "{}: {}", breakpoint.id, breakpoint)?;
+                }
+                Ok(())
+            }
+            "b" | "break" => self.add_breakpoint(argument, output),
+            "d" | "delete" => {
+                let id: usize = argument
+                    .parse()
+                    .map_err(|_| anyhow!("expected a breakpoint number"))?;
+                let len = self.breakpoints.len();
+                self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+                ensure!(self.breakpoints.len() < len, "no breakpoint number {}", id);
+                Ok(())
+            }
+            "p" | "print" => self.print(argument, output),
+            "values" => self.print_values(output),
+            "bt" | "backtrace" => {
+                let frames = self.state.frame_stack.iter().zip(&self.positions);
+                for (depth, (frame, &inst)) in frames.rev().enumerate() {
+                    writeln!(
+                        output,
+                        "#{} {}",
+                        depth,
+                        describe_inst(frame.function(), inst)
+                    )?;
+                }
+                Ok(())
+            }
+            "l" | "list" => self.list(output),
+            "native" => self.compare_natively(output),
+            "restart" => {
+                self.restart();
+                self.print_position(output)
+            }
+            "h" | "help" => Ok(writeln!(output, "{}", HELP)?),
+            _ => bail!("unknown command `{}`; try `help`", name),
+        }
+    }
+
+    /// Add a breakpoint at a location like `block1`, `inst3`, `v2` or `%add:block1`.
+    fn add_breakpoint(&mut self, location: &str, output: &mut dyn Write) -> Result<()> {
+        let (function, location) = match location.split_once(':') {
+            Some((name, location)) => {
+                let function = self
+                    .state
+                    .functions
+                    .get_by_name(name)
+                    .ok_or_else(|| anyhow!("no function named {}", name))?;
+                (function, location)
+            }
+            None => (self.current_function().unwrap_or(self.function), location),
+        };
+
+        let location = if let Some(block) = parse_entity::<Block>(location, "block") {
+            ensure!(
+                function.layout.is_block_inserted(block),
+                "no block {} in {}",
+                block,
+                function.name
+            );
+            Location::Block(block)
+        } else if let Some(inst) = parse_entity::<Inst>(location, "inst") {
+            ensure!(
+                function.layout.inst_block(inst).is_some(),
+                "no instruction {} in {}",
+                inst,
+                function.name
+            );
+            Location::Inst(inst)
+        } else if let Some(value) = parse_entity::<Value>(location, "v") {
+            ensure!(
+                function.dfg.value_is_valid(value),
+                "no value {} in {}",
+                value,
+                function.name
+            );
+            match function.dfg.value_def(function.dfg.resolve_aliases(value)) {
+                ValueDef::Result(inst, _) => Location::Inst(inst),
+                ValueDef::Param(block, _) => Location::Block(block),
+                ValueDef::Union(..) => bail!("{} is not defined by an instruction", value),
+            }
+        } else {
+            bail!("expected a block, instruction or value, e.g. `block1`, `inst3` or `v2`")
+        };
+
+        let breakpoint = Breakpoint {
+            id: self.next_breakpoint_id,
+            function,
+            location,
+        };
+        self.next_breakpoint_id += 1;
+        writeln!(output, "breakpoint {} at {}", breakpoint.id, breakpoint)?;
+        self.breakpoints.push(breakpoint);
+        Ok(())
+    }
+
+    /// Print an SSA value, stack slot or dynamic stack slot of the current frame.
+    fn print(&mut self, name: &str, output: &mut dyn Write) -> Result<()> {
+        let function = self.current_function()?;
+        if let Some(value) = parse_entity::<Value>(name, "v") {
+            ensure!(
+                function.dfg.value_is_valid(value),
+                "no value {} in {}",
+                value,
+                function.name
+            );
+            let ty = function.dfg.value_type(value);
+            let index = function.dfg.resolve_aliases(value).index();
+            match &self.state.current_frame_mut().entries_mut()[index] {
+                Some(data) => writeln!(output, "{}: {} = {}", value, ty, data)?,
+                None => writeln!(output, "{}: {} is not assigned", value, ty)?,
+            }
+        } else if let Some(slot) = parse_entity::<StackSlot>(name, "ss") {
+            ensure!(
+                function.sized_stack_slots.is_valid(slot),
+                "no stack slot {} in {}",
+                slot,
+                function.name
+            );
+            let size = function.sized_stack_slots[slot].size as usize;
+            let bytes = match size {
+                0 => &[][..],
+                _ => {
+                    let address = self.state.stack_address(AddressSize::_64, slot, 0)?;
+                    &self.state.stack[address.offset as usize..][..size]
+                }
+            };
+            writeln!(output, "{}: {}", slot, display_bytes(bytes))?;
+        } else if let Some(slot) = parse_entity::<DynamicStackSlot>(name, "dss") {
+            ensure!(
+                function.dynamic_stack_slots.is_valid(slot),
+                "no dynamic stack slot {} in {}",
+                slot,
+                function.name
+            );
+            // Dynamic vector types have a scale of one in the interpreter.
+            let dyn_ty = function.dynamic_stack_slots[slot].dyn_ty;
+            let size = function.dfg.dynamic_types[dyn_ty].base_vector_ty.bytes() as usize;
+            let address = self.state.dynamic_stack_address(AddressSize::_64, slot)?;
+            let bytes = &self.state.stack[address.offset as usize..][..size];
+            writeln!(output, "{}: {}", slot, display_bytes(bytes))?;
+        } else {
+            bail!("expected a value, stack slot or dynamic stack slot, e.g. `v1`, `ss0` or `dss0`");
+        }
+        Ok(())
+    }
+
+    /// Print all of the assigned SSA values of the current frame.
+    fn print_values(&mut self, output: &mut dyn Write) -> Result<()> {
+        let function = self.current_function()?;
+        let entries = self.state.current_frame_mut().entries_mut();
+        for value in function.dfg.values() {
+            if let Some(data) = &entries[value.index()] {
+                writeln!(
+                    output,
+                    "{}: {} = {}",
+                    value,
+                    function.dfg.value_type(value),
+                    data
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the current function, marking the next instruction with `=>` and breakpoints with
+    /// `b`.
+    fn list(&self, output: &mut dyn Write) -> Result<()> {
+        let function = self.current_function().unwrap_or(self.function);
+        let next = match self.outcome {
+            None => self.positions.last().copied(),
+            Some(_) => None,
+        };
+        writeln!(
+            output,
+            "function {}{} {{",
+            function.name, function.signature
+        )?;
+        for block in function.layout.blocks() {
+            let mut header = String::new();
+            write_block_header(&mut header, function, block, 4)?;
+            write!(output, "{}", header)?;
+            for inst in function.layout.block_insts(block) {
+                let marker = if next == Some(inst) {
+                    "=>"
+                } else if self.breakpoints.iter().any(|b| b.matches(function, inst)) {
+                    "b"
+                } else {
+                    ""
+                };
+                writeln!(
+                    output,
+                    "{:<3}{:>8}  {}",
+                    marker,
+                    inst.to_string(),
+                    function.dfg.display_inst(inst)
+                )?;
+            }
+        }
+        writeln!(output, "}}")?;
+        Ok(())
+    }
+
+    /// Run the function natively and compare the result with the interpreter's.
+    fn compare_natively(&self, output: &mut dyn Write) -> Result<()> {
+        let native = run_natively(self.test_file, self.function, &self.arguments)?;
+        writeln!(output, "native: {}", DisplayDataValues(&native))?;
+        match &self.outcome {
+            Some(Outcome::Returned(values)) => {
+                writeln!(output, "interpreter: {}", DisplayDataValues(values))?;
+                if *values == native {
+                    writeln!(output, "the results match")?;
+                } else {
+                    writeln!(output, "the results differ")?;
+                }
+            }
+            Some(outcome) => writeln!(output, "interpreter {}", outcome)?,
+            None => writeln!(output, "interpreter: still running")?,
+        }
+        Ok(())
+    }
+}
+
+/// Describe an instruction and where it is, e.g. `%add:inst2 in block0: v2 = iadd v0, v1`.
+fn describe_inst(function: &Function, inst: Inst) -> String {
+    match function.layout.inst_block(inst) {
+        Some(block) => format!(
+            "{}:{} in {}: {}",
+            function.name,
+            inst,
+            block,
+            function.dfg.display_inst(inst)
+        ),
+        None => format!("{}:{}", function.name, inst),
+    }
+}
+
+/// Parse an entity name like `block1` with the given prefix.
+fn parse_entity<E: EntityRef>(name: &str, prefix: &str) -> Option<E> {
+    let number: u32 = name.strip_prefix(prefix)?.parse().ok()?;
+    (number != u32::MAX).then(|| E::new(number as usize))
+}
+
+fn display_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CODE: &str = "
+        function %add(i32, i32) -> i32 {
+            ss0 = explicit_slot 4
+
+        block0(v0: i32, v1: i32):
+            v2 = iadd v0, v1
+            stack_store v2, ss0
+            v3 = iconst.i32 1
+            brif v3, block1(v2), block1(v0)
+
+        block1(v4: i32):
+            v5 = imul_imm v4, 2
+            return v5
+        }
+        ; run: %add(1, 2) == 6
+
+        function %double_add(i32) -> i32 {
+            fn0 = %add(i32, i32) -> i32
+
+        block0(v0: i32):
+            v1 = call fn0(v0, v0)
+            v2 = iadd_imm v1, 1
+            return v2
+        }
+        ";
+
+    fn debug(invocation: Option<&str>, breakpoints: &[&str], commands: &str) -> String {
+        let breakpoints: Vec<String> = breakpoints.iter().map(|b| b.to_string()).collect();
+        let mut output = vec![];
+        debug_file_contents(
+            CODE,
+            invocation,
+            &breakpoints,
+            &mut commands.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_and_print() {
+        let output = debug(None, &[], "s\n\np v2\np v5\np ss0\ns\ns\np v4\nc\nq\n");
+        assert_eq!(
+            output,
+            "%add(1, 2)
+%add:inst0 in block0: v2 = iadd.i32 v0, v1
+(debug) %add:inst1 in block0: stack_store.i32 v2, ss0
+(debug) %add:inst2 in block0: v3 = iconst.i32 1
+(debug) v2: i32 = 3
+(debug) v5: i32 is not assigned
+(debug) ss0: 03 00 00 00
+(debug) %add:inst3 in block0: brif.i32 v3, block1(v2), block1(v0)  ; v3 = 1
+(debug) %add:inst4 in block1: v5 = imul_imm.i32 v4, 2
+(debug) v4: i32 = 3
+(debug) returned: 6
+(debug) "
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_calls() {
+        let output = debug(
+            Some("%double_add(5)"),
+            &["%add:block1"],
+            "c\nbt\nfinish\ns\nrestart\nd 1\nn\nn\nc\nnative\nq\n",
+        );
+        assert_eq!(
+            output,
+            "breakpoint 1 at %add:block1
+%double_add(5)
+%double_add:inst0 in block0: v1 = call fn0(v0, v0)
+(debug) breakpoint 1 at %add:block1
+%add:inst4 in block1: v5 = imul_imm.i32 v4, 2
+(debug) #0 %add:inst4 in block1: v5 = imul_imm.i32 v4, 2
+#1 %double_add:inst0 in block0: v1 = call fn0(v0, v0)
+(debug) %double_add:inst1 in block0: v2 = iadd_imm.i32 v1, 1
+(debug) %double_add:inst2 in block0: return v2
+(debug) %double_add:inst0 in block0: v1 = call fn0(v0, v0)
+(debug) (debug) %double_add:inst1 in block0: v2 = iadd_imm.i32 v1, 1
+(debug) %double_add:inst2 in block0: return v2
+(debug) returned: 21
+(debug) native: 21
+interpreter: 21
+the results match
+(debug) "
+        );
+    }
+}
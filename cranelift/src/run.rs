@@ -105,7 +105,7 @@ fn run_file_contents(file_contents: String) -> Result<()> {
 }
 
 /// Build an ISA based on the current machine running this code (the host)
-fn create_target_isa(isa_spec: &IsaSpec) -> Result<OwnedTargetIsa> {
+pub(crate) fn create_target_isa(isa_spec: &IsaSpec) -> Result<OwnedTargetIsa> {
     let builder = host_isa_builder().map_err(|s| anyhow::anyhow!("{}", s))?;
     match *isa_spec {
         IsaSpec::None(ref flags) => {
@@ -20,7 +20,9 @@ use cranelift_entity::PrimaryMap;
 use cranelift_frontend::FunctionBuilder;
 use std::boxed::Box;
 use std::string::ToString;
-use wasmparser::{FuncValidator, FunctionBody, Operator, ValidatorResources, WasmFeatures};
+use wasmparser::{
+    FuncValidator, FunctionBody, Operator, ValidatorResources, WasmFeatures, WasmModuleResources,
+};
 use wasmtime_types::{ConstExpr, ModuleInternedTypeIndex};
 
 /// The value of a WebAssembly global variable.
@@ -540,6 +542,23 @@ pub trait FuncEnvironment: TargetEnvironment {
         Ok(())
     }
 
+    /// Optional callback invoked before `op`, located at `offset` within the
+    /// original wasm module, is validated and translated.
+    ///
+    /// Unlike [`FuncEnvironment::before_translate_operator`] the `validator`
+    /// here still describes the types of locals and of the operand stack as
+    /// they are just before `op` executes.
+    fn before_validate_operator(
+        &mut self,
+        _op: &Operator,
+        _offset: usize,
+        _validator: &FuncValidator<impl WasmModuleResources>,
+        _builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        Ok(())
+    }
+
     /// Optional callback for the `FunctionEnvironment` performing this translation to maintain
     /// internal state or prepare custom state for the operator to translate
     fn before_translate_operator(
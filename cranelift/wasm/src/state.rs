@@ -247,6 +247,13 @@ impl FuncTranslationState {
     pub fn reachable(&self) -> bool {
         self.reachable
     }
+
+    /// The values on the WebAssembly operand stack at this point, from the
+    /// bottom of the stack to the top.
+    #[inline]
+    pub fn operand_stack(&self) -> &[Value] {
+        &self.stack
+    }
 }
 
 impl FuncTranslationState {
@@ -0,0 +1,24 @@
+/**
+ * \file wasmtime/component.h
+ *
+ * Wasmtime APIs for interacting with WebAssembly components.
+ *
+ * These APIs are only available when the C API is built with the
+ * `component-model` feature, which is signaled by the
+ * `WASMTIME_FEATURE_COMPONENT_MODEL` define.
+ */
+
+#ifndef WASMTIME_COMPONENT_H
+#define WASMTIME_COMPONENT_H
+
+// clang-format off
+// IWYU pragma: begin_exports
+#include <wasmtime/component/component.h>
+#include <wasmtime/component/func.h>
+#include <wasmtime/component/instance.h>
+#include <wasmtime/component/linker.h>
+#include <wasmtime/component/val.h>
+// IWYU pragma: end_exports
+// clang-format on
+
+#endif // WASMTIME_COMPONENT_H
@@ -0,0 +1,147 @@
+/**
+ * \file wasmtime/component/component.h
+ *
+ * APIs for interacting with compiled components in Wasmtime.
+ */
+
+#ifndef WASMTIME_COMPONENT_COMPONENT_H
+#define WASMTIME_COMPONENT_COMPONENT_H
+
+#include <wasm.h>
+#include <wasmtime/conf.h>
+#include <wasmtime/error.h>
+
+#ifdef WASMTIME_FEATURE_COMPONENT_MODEL
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/**
+ * \typedef wasmtime_component_t
+ * \brief Convenience alias for #wasmtime_component
+ *
+ * \struct wasmtime_component
+ * \brief A compiled Wasmtime component.
+ *
+ * This type corresponds to the `wasmtime::component::Component` type in Rust.
+ * It represents a compiled WebAssembly component which is ready to be
+ * instantiated with a #wasmtime_component_linker_t. It is safe to use a
+ * component across multiple threads simultaneously.
+ */
+typedef struct wasmtime_component wasmtime_component_t;
+
+#ifdef WASMTIME_FEATURE_COMPILER
+
+/**
+ * \brief Compiles a WebAssembly component binary into a #wasmtime_component_t
+ *
+ * \param engine the engine to compile the component with
+ * \param buf the binary (or, with the `wat` feature, text) component
+ * \param len the byte length of `buf`
+ * \param component_out where to store the compiled component on success
+ *
+ * On success `NULL` is returned and `component_out` is filled in with a
+ * component owned by the caller. On failure an error is returned and
+ * `component_out` is unmodified.
+ *
+ * This function does not take ownership of any of its arguments.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_new(const wasm_engine_t *engine, const uint8_t *buf,
+                       size_t len, wasmtime_component_t **component_out);
+
+/**
+ * \brief Serializes a compiled component into a byte vector.
+ *
+ * On success `NULL` is returned and `ret` is filled in with the serialized
+ * component, which may later be passed to #wasmtime_component_deserialize. The
+ * caller owns the returned byte vector.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_serialize(const wasmtime_component_t *component,
+                             wasm_byte_vec_t *ret);
+
+#endif // WASMTIME_FEATURE_COMPILER
+
+/**
+ * \brief Builds a component from serialized data.
+ *
+ * This function does not take ownership of any of its arguments, but the
+ * returned error and component are owned by the caller.
+ *
+ * This function is not safe to receive arbitrary user input. Only data
+ * produced by #wasmtime_component_serialize with the same version of Wasmtime
+ * and the same engine configuration should be passed in here.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_deserialize(const wasm_engine_t *engine, const uint8_t *buf,
+                               size_t len,
+                               wasmtime_component_t **component_out);
+
+/**
+ * \brief Same as #wasmtime_component_deserialize except that the serialized
+ * component is read from the file at `path`.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_deserialize_file(const wasm_engine_t *engine,
+                                    const char *path,
+                                    wasmtime_component_t **component_out);
+
+/**
+ * \brief Creates a shallow clone of the specified component, increasing the
+ * internal reference count.
+ */
+WASM_API_EXTERN wasmtime_component_t *
+wasmtime_component_clone(const wasmtime_component_t *component);
+
+/**
+ * \brief Deletes a #wasmtime_component_t created by this API.
+ */
+WASM_API_EXTERN void wasmtime_component_delete(wasmtime_component_t *component);
+
+/**
+ * \typedef wasmtime_component_export_index_t
+ * \brief Convenience alias for #wasmtime_component_export_index
+ *
+ * \struct wasmtime_component_export_index
+ * \brief A pre-computed index of an export of a component.
+ *
+ * This type corresponds to `wasmtime::component::ComponentExportIndex` in
+ * Rust and allows looking up exports of an instantiated component without
+ * performing string lookups at runtime.
+ */
+typedef struct wasmtime_component_export_index
+    wasmtime_component_export_index_t;
+
+/**
+ * \brief Looks up an export of `component` by name.
+ *
+ * \param component the component to search
+ * \param instance_export_index the exported instance to look within, or `NULL`
+ *        to look at the root exports of the component
+ * \param name the name of the export
+ * \param name_len the byte length of `name`
+ *
+ * \return the index of the export, owned by the caller, or `NULL` if no export
+ * was found.
+ */
+WASM_API_EXTERN wasmtime_component_export_index_t *
+wasmtime_component_get_export_index(
+    const wasmtime_component_t *component,
+    const wasmtime_component_export_index_t *instance_export_index,
+    const char *name, size_t name_len);
+
+/**
+ * \brief Deletes a #wasmtime_component_export_index_t.
+ */
+WASM_API_EXTERN void wasmtime_component_export_index_delete(
+    wasmtime_component_export_index_t *export_index);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // WASMTIME_FEATURE_COMPONENT_MODEL
+
+#endif // WASMTIME_COMPONENT_COMPONENT_H
@@ -0,0 +1,65 @@
+/**
+ * \file wasmtime/component/func.h
+ *
+ * APIs for calling functions exported from components.
+ */
+
+#ifndef WASMTIME_COMPONENT_FUNC_H
+#define WASMTIME_COMPONENT_FUNC_H
+
+#include <wasm.h>
+#include <wasmtime/component/val.h>
+#include <wasmtime/conf.h>
+#include <wasmtime/error.h>
+#include <wasmtime/store.h>
+
+#ifdef WASMTIME_FEATURE_COMPONENT_MODEL
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// \brief Representation of a function exported from a component instance.
+///
+/// This type corresponds to `wasmtime::component::Func` in Rust. Like
+/// #wasmtime_func_t it is an index into a #wasmtime_store_t, has no
+/// destructor, and is only valid within the store that owns it.
+typedef struct wasmtime_component_func {
+  /// Internal identifier of what store this belongs to, never zero.
+  uint64_t store_id;
+  /// Internal index within the store.
+  size_t index;
+} wasmtime_component_func_t;
+
+/**
+ * \brief Calls a component function.
+ *
+ * \param context the store which owns `func`
+ * \param func the function to call
+ * \param args the arguments to the function
+ * \param args_len the number of arguments provided
+ * \param results where to write the results of the function
+ * \param results_len the number of results expected
+ *
+ * Arguments are type-checked against the signature of `func` and an error is
+ * returned if they don't match, if the call traps, or if the number of
+ * `results` is wrong. On success `NULL` is returned and `results` are filled
+ * in. Any post-return cleanup of the callee is performed before this
+ * function returns.
+ *
+ * This function does not take ownership of `args`. Ownership of the values in
+ * `results` is given to the caller who must deallocate them with
+ * #wasmtime_component_val_delete. On failure `results` are not written to.
+ */
+WASM_API_EXTERN wasmtime_error_t *wasmtime_component_func_call(
+    wasmtime_context_t *context, const wasmtime_component_func_t *func,
+    const wasmtime_component_val_t *args, size_t args_len,
+    wasmtime_component_val_t *results, size_t results_len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // WASMTIME_FEATURE_COMPONENT_MODEL
+
+#endif // WASMTIME_COMPONENT_FUNC_H
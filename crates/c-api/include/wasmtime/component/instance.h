@@ -0,0 +1,73 @@
+/**
+ * \file wasmtime/component/instance.h
+ *
+ * APIs for interacting with instantiated components in Wasmtime.
+ */
+
+#ifndef WASMTIME_COMPONENT_INSTANCE_H
+#define WASMTIME_COMPONENT_INSTANCE_H
+
+#include <wasm.h>
+#include <wasmtime/component/component.h>
+#include <wasmtime/component/func.h>
+#include <wasmtime/conf.h>
+#include <wasmtime/store.h>
+
+#ifdef WASMTIME_FEATURE_COMPONENT_MODEL
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// \brief Representation of an instantiated component in Wasmtime.
+///
+/// Like #wasmtime_instance_t this is an index into a #wasmtime_store_t and has
+/// no destructor. Instances are created with
+/// #wasmtime_component_linker_instantiate and are only valid within the store
+/// that they were instantiated in.
+typedef struct wasmtime_component_instance {
+  /// Internal identifier of what store this belongs to, never zero.
+  uint64_t store_id;
+  /// Internal index within the store.
+  size_t index;
+} wasmtime_component_instance_t;
+
+/**
+ * \brief Looks up an export of `instance` by name.
+ *
+ * This is the same as #wasmtime_component_get_export_index except that it
+ * works on an instantiated component.
+ *
+ * \return the index of the export, owned by the caller, or `NULL` if no export
+ * was found.
+ */
+WASM_API_EXTERN wasmtime_component_export_index_t *
+wasmtime_component_instance_get_export_index(
+    wasmtime_context_t *context, const wasmtime_component_instance_t *instance,
+    const wasmtime_component_export_index_t *instance_export_index,
+    const char *name, size_t name_len);
+
+/**
+ * \brief Looks up an exported function of `instance`.
+ *
+ * \param context the store that owns `instance`
+ * \param instance the instance to look within
+ * \param export_index the export to look up, for example from
+ *        #wasmtime_component_instance_get_export_index
+ * \param func_out where to store the function, if found
+ *
+ * \return `true` if the export was found and is a function, or `false`
+ * otherwise in which case `func_out` is unmodified.
+ */
+WASM_API_EXTERN bool wasmtime_component_instance_get_func(
+    wasmtime_context_t *context, const wasmtime_component_instance_t *instance,
+    const wasmtime_component_export_index_t *export_index,
+    wasmtime_component_func_t *func_out);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // WASMTIME_FEATURE_COMPONENT_MODEL
+
+#endif // WASMTIME_COMPONENT_INSTANCE_H
@@ -0,0 +1,198 @@
+/**
+ * \file wasmtime/component/linker.h
+ *
+ * Wasmtime API for a name-based linker used to instantiate components.
+ */
+
+#ifndef WASMTIME_COMPONENT_LINKER_H
+#define WASMTIME_COMPONENT_LINKER_H
+
+#include <wasm.h>
+#include <wasmtime/component/component.h>
+#include <wasmtime/component/instance.h>
+#include <wasmtime/component/val.h>
+#include <wasmtime/conf.h>
+#include <wasmtime/error.h>
+#include <wasmtime/module.h>
+#include <wasmtime/store.h>
+
+#ifdef WASMTIME_FEATURE_COMPONENT_MODEL
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/**
+ * \typedef wasmtime_component_linker_t
+ * \brief Convenience alias for #wasmtime_component_linker
+ *
+ * \struct wasmtime_component_linker
+ * \brief Object used to define the imports of components and instantiate
+ * them.
+ *
+ * This type corresponds to the `wasmtime::component::Linker` type in Rust.
+ * Definitions are added to the linker through a
+ * #wasmtime_component_linker_instance_t acquired with
+ * #wasmtime_component_linker_root.
+ */
+typedef struct wasmtime_component_linker wasmtime_component_linker_t;
+
+/**
+ * \typedef wasmtime_component_linker_instance_t
+ * \brief Convenience alias for #wasmtime_component_linker_instance
+ *
+ * \struct wasmtime_component_linker_instance
+ * \brief A handle used to define items within the root of, or an instance
+ * nested within, a #wasmtime_component_linker_t.
+ *
+ * This type corresponds to `wasmtime::component::LinkerInstance` in Rust.
+ * While a #wasmtime_component_linker_instance_t is alive the linker (or parent
+ * instance) it was created from is mutably borrowed and must not be used.
+ * Delete it with #wasmtime_component_linker_instance_delete to release the
+ * borrow.
+ */
+typedef struct wasmtime_component_linker_instance
+    wasmtime_component_linker_instance_t;
+
+/**
+ * \brief Creates a new component linker for the specified engine.
+ *
+ * This function does not take ownership of the engine argument, and the caller
+ * is expected to delete the returned linker.
+ */
+WASM_API_EXTERN wasmtime_component_linker_t *
+wasmtime_component_linker_new(const wasm_engine_t *engine);
+
+/**
+ * \brief Deletes a #wasmtime_component_linker_t.
+ */
+WASM_API_EXTERN void
+wasmtime_component_linker_delete(wasmtime_component_linker_t *linker);
+
+/**
+ * \brief Configures whether this linker allows later definitions to shadow
+ * previous definitions.
+ *
+ * By default this setting is `false`.
+ */
+WASM_API_EXTERN void
+wasmtime_component_linker_allow_shadowing(wasmtime_component_linker_t *linker,
+                                          bool allow_shadowing);
+
+/**
+ * \brief Returns a handle to define items in the root of the linker.
+ *
+ * The returned value is owned by the caller and must be deleted with
+ * #wasmtime_component_linker_instance_delete before `linker` is used again.
+ */
+WASM_API_EXTERN wasmtime_component_linker_instance_t *
+wasmtime_component_linker_root(wasmtime_component_linker_t *linker);
+
+/**
+ * \brief Instantiates a component using the definitions in `linker`.
+ *
+ * \param linker the linker used to resolve the imports of `component`
+ * \param context the store to instantiate the component into
+ * \param component the component to instantiate
+ * \param instance_out where to store the instance on success
+ *
+ * \return `NULL` on success, or an error if an import could not be resolved,
+ * had the wrong type, or instantiation trapped.
+ */
+WASM_API_EXTERN wasmtime_error_t *wasmtime_component_linker_instantiate(
+    const wasmtime_component_linker_t *linker, wasmtime_context_t *context,
+    const wasmtime_component_t *component,
+    wasmtime_component_instance_t *instance_out);
+
+#ifdef WASMTIME_FEATURE_WASI
+
+/**
+ * \brief Defines all WASI preview2 interfaces, such as `wasi:cli`, in
+ * this linker.
+ *
+ * Components instantiated with this linker use the WASI configuration of the
+ * store they are instantiated into which must be set with
+ * #wasmtime_context_set_wasi beforehand, otherwise calls to WASI will abort.
+ *
+ * \return `NULL` on success, or an error if an item is already defined and
+ * shadowing is disallowed.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_linker_add_wasip2(wasmtime_component_linker_t *linker);
+
+#endif // WASMTIME_FEATURE_WASI
+
+/**
+ * \brief Deletes a #wasmtime_component_linker_instance_t, releasing the borrow
+ * on its parent.
+ */
+WASM_API_EXTERN void wasmtime_component_linker_instance_delete(
+    wasmtime_component_linker_instance_t *linker_instance);
+
+/**
+ * \brief Defines a nested instance named `name` within `linker_instance`.
+ *
+ * \param linker_instance the instance to define the new instance within
+ * \param name the name of the new instance, for example `wasi:cli/env@0.2.0`
+ * \param name_len the byte length of `name`
+ * \param linker_instance_out where to store a handle to the new instance
+ *
+ * On success the returned handle is owned by the caller and mutably borrows
+ * `linker_instance` until it is deleted.
+ */
+WASM_API_EXTERN wasmtime_error_t *
+wasmtime_component_linker_instance_add_instance(
+    wasmtime_component_linker_instance_t *linker_instance, const char *name,
+    size_t name_len,
+    wasmtime_component_linker_instance_t **linker_instance_out);
+
+/**
+ * \brief Defines a core wasm module named `name` within `linker_instance`.
+ *
+ * This does not take ownership of `module`.
+ */
+WASM_API_EXTERN wasmtime_error_t *wasmtime_component_linker_instance_add_module(
+    wasmtime_component_linker_instance_t *linker_instance, const char *name,
+    size_t name_len, const wasmtime_module_t *module);
+
+/**
+ * \brief Callback signature for host functions defined with
+ * #wasmtime_component_linker_instance_add_func.
+ *
+ * The `args` are owned by the caller and only valid for the duration of the
+ * call. The callback must write a value to each of the `results`, ownership
+ * of which is transferred to Wasmtime. Returning a non-`NULL` error, which is
+ * owned by Wasmtime afterwards, raises it as a trap in the calling component.
+ */
+typedef wasmtime_error_t *(*wasmtime_component_func_callback_t)(
+    void *env, wasmtime_context_t *context,
+    const wasmtime_component_val_t *args, size_t args_len,
+    wasmtime_component_val_t *results, size_t results_len);
+
+/**
+ * \brief Defines a host function named `name` within `linker_instance`.
+ *
+ * \param linker_instance the instance to define the function within
+ * \param name the name of the function
+ * \param name_len the byte length of `name`
+ * \param callback the host callback to invoke when the function is called
+ * \param data host-specific data passed to `callback` as `env`
+ * \param finalizer optional finalizer for `data`, run when the linker and all
+ *        components instantiated with it no longer need `callback`
+ *
+ * The type of the function is not declared up-front. Instead values are
+ * dynamically type-checked against the import of the component when it's
+ * called, similar to `wasmtime::component::LinkerInstance::func_new` in Rust.
+ */
+WASM_API_EXTERN wasmtime_error_t *wasmtime_component_linker_instance_add_func(
+    wasmtime_component_linker_instance_t *linker_instance, const char *name,
+    size_t name_len, wasmtime_component_func_callback_t callback, void *data,
+    void (*finalizer)(void *));
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // WASMTIME_FEATURE_COMPONENT_MODEL
+
+#endif // WASMTIME_COMPONENT_LINKER_H
@@ -0,0 +1,277 @@
+/**
+ * \file wasmtime/component/val.h
+ *
+ * APIs for working with component model values.
+ */
+
+#ifndef WASMTIME_COMPONENT_VAL_H
+#define WASMTIME_COMPONENT_VAL_H
+
+#include <wasm.h>
+#include <wasmtime/conf.h>
+
+#ifdef WASMTIME_FEATURE_COMPONENT_MODEL
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+struct wasmtime_component_val;
+struct wasmtime_component_valrecord_entry;
+
+/// \brief Declares a vector type named `name` of `type` elements along with
+/// the functions to manage it, analogous to `WASM_DECLARE_VEC` in `wasm.h`.
+///
+/// Note that `name##_new` takes ownership of the elements it's given.
+#define WASMTIME_COMPONENT_DECLARE_VEC(name, type)                             \
+  /** \brief A vector of `type` elements. */                                  \
+  typedef struct name {                                                        \
+    /** \brief The number of elements in this vector. */                      \
+    size_t size;                                                               \
+    /** \brief The elements of this vector. */                                \
+    type *data;                                                                \
+  } name##_t;                                                                  \
+                                                                               \
+  /** \brief Initializes `out` to an empty vector. */                         \
+  WASM_API_EXTERN void name##_new_empty(name##_t *out);                       \
+  /** \brief Initializes `out` to a vector of `size` default elements. */     \
+  WASM_API_EXTERN void name##_new_uninitialized(name##_t *out, size_t size);  \
+  /** \brief Initializes `out` by moving `size` elements out of `ptr`. */     \
+  WASM_API_EXTERN void name##_new(name##_t *out, size_t size,                 \
+                                  const type *ptr);                            \
+  /** \brief Initializes `out` with a deep copy of `src`. */                  \
+  WASM_API_EXTERN void name##_copy(name##_t *out, const name##_t *src);       \
+  /** \brief Deallocates `value` and all of its elements. */                  \
+  WASM_API_EXTERN void name##_delete(name##_t *value);
+
+// clang-format off
+WASMTIME_COMPONENT_DECLARE_VEC(wasmtime_component_vallist, struct wasmtime_component_val)
+WASMTIME_COMPONENT_DECLARE_VEC(wasmtime_component_valrecord, struct wasmtime_component_valrecord_entry)
+WASMTIME_COMPONENT_DECLARE_VEC(wasmtime_component_valflags, wasm_name_t)
+// clang-format on
+
+#undef WASMTIME_COMPONENT_DECLARE_VEC
+
+/// \brief Discriminant used in #wasmtime_component_val_t::kind
+typedef uint8_t wasmtime_component_valkind_t;
+
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `bool`
+#define WASMTIME_COMPONENT_BOOL 0
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `s8`
+#define WASMTIME_COMPONENT_S8 1
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `u8`
+#define WASMTIME_COMPONENT_U8 2
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `s16`
+#define WASMTIME_COMPONENT_S16 3
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `u16`
+#define WASMTIME_COMPONENT_U16 4
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `s32`
+#define WASMTIME_COMPONENT_S32 5
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `u32`
+#define WASMTIME_COMPONENT_U32 6
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `s64`
+#define WASMTIME_COMPONENT_S64 7
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `u64`
+#define WASMTIME_COMPONENT_U64 8
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `f32`
+#define WASMTIME_COMPONENT_F32 9
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `f64`
+#define WASMTIME_COMPONENT_F64 10
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `char`
+#define WASMTIME_COMPONENT_CHAR 11
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `string`
+#define WASMTIME_COMPONENT_STRING 12
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `list`
+#define WASMTIME_COMPONENT_LIST 13
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `record`
+#define WASMTIME_COMPONENT_RECORD 14
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `tuple`
+#define WASMTIME_COMPONENT_TUPLE 15
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `variant`
+#define WASMTIME_COMPONENT_VARIANT 16
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is an `enum`
+#define WASMTIME_COMPONENT_ENUM 17
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is an `option`
+#define WASMTIME_COMPONENT_OPTION 18
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `result`
+#define WASMTIME_COMPONENT_RESULT 19
+/// \brief Value of #wasmtime_component_valkind_t meaning that
+/// #wasmtime_component_val_t is a `flags`
+#define WASMTIME_COMPONENT_FLAGS 20
+
+/// \brief Payload of a `variant` value.
+typedef struct wasmtime_component_valvariant {
+  /// \brief The name of the case of the variant.
+  wasm_name_t discriminant;
+  /// \brief The payload of the case, or `NULL` if the case has no payload.
+  ///
+  /// Allocate this with #wasmtime_component_val_new.
+  struct wasmtime_component_val *val;
+} wasmtime_component_valvariant_t;
+
+/// \brief Payload of a `result` value.
+typedef struct wasmtime_component_valresult {
+  /// \brief Whether this is the `ok` case of the result.
+  bool is_ok;
+  /// \brief The payload of the result, or `NULL` if the case has no payload.
+  ///
+  /// Allocate this with #wasmtime_component_val_new.
+  struct wasmtime_component_val *val;
+} wasmtime_component_valresult_t;
+
+/// \brief Container for the payload of a #wasmtime_component_val_t.
+typedef union wasmtime_component_valunion {
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_BOOL
+  bool boolean;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_S8
+  int8_t s8;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_U8
+  uint8_t u8;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_S16
+  int16_t s16;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_U16
+  uint16_t u16;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_S32
+  int32_t s32;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_U32
+  uint32_t u32;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_S64
+  int64_t s64;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_U64
+  uint64_t u64;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_F32
+  float f32;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_F64
+  double f64;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_CHAR, a Unicode scalar value.
+  uint32_t character;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_STRING, which must be valid UTF-8.
+  wasm_name_t string;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_LIST
+  wasmtime_component_vallist_t list;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_RECORD
+  wasmtime_component_valrecord_t record;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_TUPLE
+  wasmtime_component_vallist_t tuple;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_VARIANT
+  wasmtime_component_valvariant_t variant;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_ENUM, the name of the case.
+  wasm_name_t enumeration;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_OPTION, `NULL` for `none`.
+  ///
+  /// Allocate this with #wasmtime_component_val_new.
+  struct wasmtime_component_val *option;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_RESULT
+  wasmtime_component_valresult_t result;
+  /// \brief Field used if #wasmtime_component_val_t::kind is
+  /// #WASMTIME_COMPONENT_FLAGS, the names of the flags which are set.
+  wasmtime_component_valflags_t flags;
+} wasmtime_component_valunion_t;
+
+/**
+ * \typedef wasmtime_component_val_t
+ * \brief Convenience alias for #wasmtime_component_val
+ *
+ * \struct wasmtime_component_val
+ * \brief A value of a component model type.
+ *
+ * This type corresponds to `wasmtime::component::Val` in Rust. Values own
+ * their payloads: strings, names and vectors must be allocated with the
+ * corresponding `*_new` functions of this API (for example
+ * #wasm_name_new or #wasmtime_component_vallist_new) and boxed payloads with
+ * #wasmtime_component_val_new. An owned value is deallocated with
+ * #wasmtime_component_val_delete.
+ *
+ * Resources are not yet supported by this API.
+ */
+typedef struct wasmtime_component_val {
+  /// \brief Discriminant of which field of #of is valid.
+  wasmtime_component_valkind_t kind;
+  /// \brief Container for the payload of this value.
+  wasmtime_component_valunion_t of;
+} wasmtime_component_val_t;
+
+/// \brief A field of a `record` value.
+typedef struct wasmtime_component_valrecord_entry {
+  /// \brief The name of the field.
+  wasm_name_t name;
+  /// \brief The value of the field.
+  wasmtime_component_val_t val;
+} wasmtime_component_valrecord_entry_t;
+
+/**
+ * \brief Moves `val` into a new heap allocation, for use as the payload of an
+ * `option`, `result` or `variant`.
+ *
+ * After this call `val` is left as the `bool` value `false`. The returned
+ * pointer is owned by the caller, typically by way of the value it is stored
+ * within, and can be deallocated with #wasmtime_component_val_free.
+ */
+WASM_API_EXTERN wasmtime_component_val_t *
+wasmtime_component_val_new(wasmtime_component_val_t *val);
+
+/**
+ * \brief Deallocates a value returned by #wasmtime_component_val_new.
+ */
+WASM_API_EXTERN void wasmtime_component_val_free(wasmtime_component_val_t *val);
+
+/**
+ * \brief Initializes `dst` with a deep copy of `src`.
+ */
+WASM_API_EXTERN void
+wasmtime_component_val_clone(const wasmtime_component_val_t *src,
+                             wasmtime_component_val_t *dst);
+
+/**
+ * \brief Deallocates the payload of `val`, leaving it as the `bool` value
+ * `false`.
+ */
+WASM_API_EXTERN void wasmtime_component_val_delete(wasmtime_component_val_t *val);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // WASMTIME_FEATURE_COMPONENT_MODEL
+
+#endif // WASMTIME_COMPONENT_VAL_H
@@ -0,0 +1,91 @@
+use crate::{handle_result, wasm_engine_t, wasmtime_error_t};
+use anyhow::Context;
+use std::ffi::{c_char, CStr};
+use wasmtime::component::{Component, ComponentExportIndex};
+
+#[derive(Clone)]
+#[repr(C)]
+pub struct wasmtime_component_t {
+    pub(crate) component: Component,
+}
+
+wasmtime_c_api_macros::declare_own!(wasmtime_component_t);
+
+#[no_mangle]
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+pub unsafe extern "C" fn wasmtime_component_new(
+    engine: &wasm_engine_t,
+    buf: *const u8,
+    len: usize,
+    component_out: &mut *mut wasmtime_component_t,
+) -> Option<Box<wasmtime_error_t>> {
+    let bytes = crate::slice_from_raw_parts(buf, len);
+    handle_result(Component::new(&engine.engine, bytes), |component| {
+        *component_out = Box::into_raw(Box::new(wasmtime_component_t { component }));
+    })
+}
+
+#[no_mangle]
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+pub extern "C" fn wasmtime_component_serialize(
+    component: &wasmtime_component_t,
+    ret: &mut crate::wasm_byte_vec_t,
+) -> Option<Box<wasmtime_error_t>> {
+    handle_result(component.component.serialize(), |buf| ret.set_buffer(buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_deserialize(
+    engine: &wasm_engine_t,
+    buf: *const u8,
+    len: usize,
+    component_out: &mut *mut wasmtime_component_t,
+) -> Option<Box<wasmtime_error_t>> {
+    let bytes = crate::slice_from_raw_parts(buf, len);
+    handle_result(Component::deserialize(&engine.engine, bytes), |component| {
+        *component_out = Box::into_raw(Box::new(wasmtime_component_t { component }));
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_deserialize_file(
+    engine: &wasm_engine_t,
+    path: *const c_char,
+    component_out: &mut *mut wasmtime_component_t,
+) -> Option<Box<wasmtime_error_t>> {
+    let path = CStr::from_ptr(path);
+    let result = path
+        .to_str()
+        .context("input path is not valid utf-8")
+        .and_then(|path| Component::deserialize_file(&engine.engine, path));
+    handle_result(result, |component| {
+        *component_out = Box::into_raw(Box::new(wasmtime_component_t { component }));
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_clone(
+    component: &wasmtime_component_t,
+) -> Box<wasmtime_component_t> {
+    Box::new(component.clone())
+}
+
+#[repr(C)]
+pub struct wasmtime_component_export_index_t {
+    pub(crate) export_index: ComponentExportIndex,
+}
+
+wasmtime_c_api_macros::declare_own!(wasmtime_component_export_index_t);
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_get_export_index(
+    component: &wasmtime_component_t,
+    instance_export_index: Option<&wasmtime_component_export_index_t>,
+    name: *const u8,
+    name_len: usize,
+) -> Option<Box<wasmtime_component_export_index_t>> {
+    let name = std::str::from_utf8(crate::slice_from_raw_parts(name, name_len)).ok()?;
+    let instance = instance_export_index.map(|i| &i.export_index);
+    let (_, export_index) = component.component.export_index(instance, name)?;
+    Some(Box::new(wasmtime_component_export_index_t { export_index }))
+}
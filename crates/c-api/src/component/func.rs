@@ -0,0 +1,44 @@
+use crate::{wasmtime_component_val_t, wasmtime_error_t, WasmtimeStoreContextMut};
+use anyhow::Result;
+use std::mem::MaybeUninit;
+use wasmtime::component::{Func, Val};
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_func_call(
+    mut context: WasmtimeStoreContextMut<'_>,
+    func: &Func,
+    args: *const wasmtime_component_val_t,
+    args_len: usize,
+    results: *mut MaybeUninit<wasmtime_component_val_t>,
+    results_len: usize,
+) -> Option<Box<wasmtime_error_t>> {
+    let args = crate::slice_from_raw_parts(args, args_len);
+    let results = crate::slice_from_raw_parts_mut(results, results_len);
+    crate::handle_result(call(func, &mut context, args, results), |()| {})
+}
+
+fn call(
+    func: &Func,
+    context: &mut WasmtimeStoreContextMut<'_>,
+    args: &[wasmtime_component_val_t],
+    results: &mut [MaybeUninit<wasmtime_component_val_t>],
+) -> Result<()> {
+    let args = args
+        .iter()
+        .map(|arg| arg.to_val())
+        .collect::<Result<Vec<_>>>()?;
+    let mut vals = vec![Val::Bool(false); results.len()];
+    func.call(&mut *context, &args, &mut vals)?;
+    func.post_return(&mut *context)?;
+
+    // Convert all results before writing any of them so that on failure none
+    // of the results need to be deallocated by the caller.
+    let vals = vals
+        .iter()
+        .map(wasmtime_component_val_t::from_val)
+        .collect::<Result<Vec<_>>>()?;
+    for (slot, val) in results.iter_mut().zip(vals) {
+        crate::initialize(slot, val);
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::{wasmtime_component_export_index_t, WasmtimeStoreContextMut};
+use wasmtime::component::{Func, Instance};
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_instance_get_export_index(
+    context: WasmtimeStoreContextMut<'_>,
+    instance: &Instance,
+    instance_export_index: Option<&wasmtime_component_export_index_t>,
+    name: *const u8,
+    name_len: usize,
+) -> Option<Box<wasmtime_component_export_index_t>> {
+    let name = std::str::from_utf8(crate::slice_from_raw_parts(name, name_len)).ok()?;
+    let instance_export_index = instance_export_index.map(|i| &i.export_index);
+    let export_index = instance.get_export(context, instance_export_index, name)?;
+    Some(Box::new(wasmtime_component_export_index_t { export_index }))
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_instance_get_func(
+    context: WasmtimeStoreContextMut<'_>,
+    instance: &Instance,
+    export_index: &wasmtime_component_export_index_t,
+    func_out: &mut Func,
+) -> bool {
+    match instance.get_func(context, &export_index.export_index) {
+        Some(func) => {
+            *func_out = func;
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,183 @@
+use crate::linker::to_str;
+use crate::{
+    bad_utf8, handle_result, wasm_engine_t, wasmtime_component_t, wasmtime_component_val_t,
+    wasmtime_error_t, wasmtime_module_t, WasmtimeStoreContextMut, WasmtimeStoreData,
+};
+use anyhow::Result;
+use std::ffi::c_void;
+use std::str;
+use wasmtime::component::{Instance, Linker, LinkerInstance, Val};
+
+#[repr(C)]
+pub struct wasmtime_component_linker_t {
+    pub(crate) linker: Linker<WasmtimeStoreData>,
+}
+
+wasmtime_c_api_macros::declare_own!(wasmtime_component_linker_t);
+
+/// A view into a nested instance of a [`wasmtime_component_linker_t`].
+///
+/// This mutably borrows the linker (or parent instance) it was created from so
+/// the C API requires that the parent isn't used again until this is deleted.
+#[repr(C)]
+pub struct wasmtime_component_linker_instance_t<'a> {
+    pub(crate) linker_instance: LinkerInstance<'a, WasmtimeStoreData>,
+}
+
+wasmtime_c_api_macros::declare_own!(wasmtime_component_linker_instance_t);
+
+pub type wasmtime_component_func_callback_t = extern "C" fn(
+    *mut c_void,
+    WasmtimeStoreContextMut<'_>,
+    *const wasmtime_component_val_t,
+    usize,
+    *mut wasmtime_component_val_t,
+    usize,
+) -> Option<Box<wasmtime_error_t>>;
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_linker_new(
+    engine: &wasm_engine_t,
+) -> Box<wasmtime_component_linker_t> {
+    Box::new(wasmtime_component_linker_t {
+        linker: Linker::new(&engine.engine),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_linker_allow_shadowing(
+    linker: &mut wasmtime_component_linker_t,
+    allow_shadowing: bool,
+) {
+    linker.linker.allow_shadowing(allow_shadowing);
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_linker_root(
+    linker: &mut wasmtime_component_linker_t,
+) -> Box<wasmtime_component_linker_instance_t<'_>> {
+    Box::new(wasmtime_component_linker_instance_t {
+        linker_instance: linker.linker.root(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_linker_instantiate(
+    linker: &wasmtime_component_linker_t,
+    context: WasmtimeStoreContextMut<'_>,
+    component: &wasmtime_component_t,
+    instance_out: &mut Instance,
+) -> Option<Box<wasmtime_error_t>> {
+    let result = linker.linker.instantiate(context, &component.component);
+    handle_result(result, |instance| *instance_out = instance)
+}
+
+#[cfg(feature = "wasi")]
+#[no_mangle]
+pub extern "C" fn wasmtime_component_linker_add_wasip2(
+    linker: &mut wasmtime_component_linker_t,
+) -> Option<Box<wasmtime_error_t>> {
+    handle_result(
+        wasmtime_wasi::add_to_linker_sync(&mut linker.linker),
+        |()| (),
+    )
+}
+
+/// Components share the WASI configuration of the store with core modules
+/// which is configured through `wasmtime_context_set_wasi`.
+#[cfg(feature = "wasi")]
+impl wasmtime_wasi::WasiView for WasmtimeStoreData {
+    fn table(&mut self) -> &mut wasmtime::component::ResourceTable {
+        self.wasi
+            .as_mut()
+            .expect("wasi context must be populated")
+            .table()
+    }
+
+    fn ctx(&mut self) -> &mut wasmtime_wasi::WasiCtx {
+        self.wasi
+            .as_mut()
+            .expect("wasi context must be populated")
+            .ctx()
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_linker_instance_add_instance<'a>(
+    linker_instance: &'a mut wasmtime_component_linker_instance_t<'_>,
+    name: *const u8,
+    name_len: usize,
+    linker_instance_out: &mut *mut wasmtime_component_linker_instance_t<'a>,
+) -> Option<Box<wasmtime_error_t>> {
+    let name = to_str!(name, name_len);
+    let result = linker_instance.linker_instance.instance(name);
+    handle_result(result, |linker_instance| {
+        *linker_instance_out = Box::into_raw(Box::new(wasmtime_component_linker_instance_t {
+            linker_instance,
+        }));
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_linker_instance_add_module(
+    linker_instance: &mut wasmtime_component_linker_instance_t<'_>,
+    name: *const u8,
+    name_len: usize,
+    module: &wasmtime_module_t,
+) -> Option<Box<wasmtime_error_t>> {
+    let name = to_str!(name, name_len);
+    let result = linker_instance.linker_instance.module(name, &module.module);
+    handle_result(result, |()| ())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_component_linker_instance_add_func(
+    linker_instance: &mut wasmtime_component_linker_instance_t<'_>,
+    name: *const u8,
+    name_len: usize,
+    callback: wasmtime_component_func_callback_t,
+    data: *mut c_void,
+    finalizer: Option<extern "C" fn(*mut c_void)>,
+) -> Option<Box<wasmtime_error_t>> {
+    let name = to_str!(name, name_len);
+    let foreign = crate::ForeignData { data, finalizer };
+    let result = linker_instance
+        .linker_instance
+        .func_new(name, move |context, params, results| {
+            let _ = &foreign; // move entire foreign into this closure
+            call_host_func(callback, foreign.data, context, params, results)
+        });
+    handle_result(result, |()| ())
+}
+
+fn call_host_func(
+    callback: wasmtime_component_func_callback_t,
+    data: *mut c_void,
+    context: WasmtimeStoreContextMut<'_>,
+    params: &[Val],
+    results: &mut [Val],
+) -> Result<()> {
+    let params = params
+        .iter()
+        .map(wasmtime_component_val_t::from_val)
+        .collect::<Result<Vec<_>>>()?;
+    let mut c_results = (0..results.len())
+        .map(|_| wasmtime_component_val_t::default())
+        .collect::<Vec<_>>();
+
+    if let Some(err) = callback(
+        data,
+        context,
+        params.as_ptr(),
+        params.len(),
+        c_results.as_mut_ptr(),
+        c_results.len(),
+    ) {
+        return Err((*err).into());
+    }
+
+    for (result, c_result) in results.iter_mut().zip(&c_results) {
+        *result = c_result.to_val()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use crate::vec::declare_vecs;
+use crate::wasm_name_t;
+use anyhow::{anyhow, bail, Result};
+use std::mem;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::slice;
+use wasmtime::component::Val;
+
+declare_vecs! {
+    (
+        name: wasmtime_component_vallist_t,
+        ty: wasmtime_component_val_t,
+        new: wasmtime_component_vallist_new,
+        empty: wasmtime_component_vallist_new_empty,
+        uninit: wasmtime_component_vallist_new_uninitialized,
+        copy: wasmtime_component_vallist_copy,
+        delete: wasmtime_component_vallist_delete,
+    )
+    (
+        name: wasmtime_component_valrecord_t,
+        ty: wasmtime_component_valrecord_entry_t,
+        new: wasmtime_component_valrecord_new,
+        empty: wasmtime_component_valrecord_new_empty,
+        uninit: wasmtime_component_valrecord_new_uninitialized,
+        copy: wasmtime_component_valrecord_copy,
+        delete: wasmtime_component_valrecord_delete,
+    )
+    (
+        name: wasmtime_component_valflags_t,
+        ty: wasm_name_t,
+        new: wasmtime_component_valflags_new,
+        empty: wasmtime_component_valflags_new_empty,
+        uninit: wasmtime_component_valflags_new_uninitialized,
+        copy: wasmtime_component_valflags_copy,
+        delete: wasmtime_component_valflags_delete,
+    )
+}
+
+/// The C representation of a [`Val`].
+///
+/// The layout of this `#[repr(C, u8)]` enum is a `uint8_t` discriminant
+/// followed by a union of all payloads, which is how it's defined in
+/// `wasmtime/component/val.h`.
+#[repr(C, u8)]
+#[derive(Clone)]
+pub enum wasmtime_component_val_t {
+    Bool(bool),
+    S8(i8),
+    U8(u8),
+    S16(i16),
+    U16(u16),
+    S32(i32),
+    U32(u32),
+    S64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Char(u32),
+    String(wasm_name_t),
+    List(wasmtime_component_vallist_t),
+    Record(wasmtime_component_valrecord_t),
+    Tuple(wasmtime_component_vallist_t),
+    Variant(wasmtime_component_valvariant_t),
+    Enum(wasm_name_t),
+    Option(Option<Box<wasmtime_component_val_t>>),
+    Result(wasmtime_component_valresult_t),
+    Flags(wasmtime_component_valflags_t),
+}
+
+impl Default for wasmtime_component_val_t {
+    fn default() -> Self {
+        wasmtime_component_val_t::Bool(false)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Default)]
+pub struct wasmtime_component_valrecord_entry_t {
+    name: wasm_name_t,
+    val: wasmtime_component_val_t,
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct wasmtime_component_valvariant_t {
+    discriminant: wasm_name_t,
+    val: Option<Box<wasmtime_component_val_t>>,
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct wasmtime_component_valresult_t {
+    is_ok: bool,
+    val: Option<Box<wasmtime_component_val_t>>,
+}
+
+impl wasmtime_component_val_t {
+    pub(crate) fn from_val(val: &Val) -> Result<wasmtime_component_val_t> {
+        Ok(match val {
+            Val::Bool(b) => wasmtime_component_val_t::Bool(*b),
+            Val::S8(v) => wasmtime_component_val_t::S8(*v),
+            Val::U8(v) => wasmtime_component_val_t::U8(*v),
+            Val::S16(v) => wasmtime_component_val_t::S16(*v),
+            Val::U16(v) => wasmtime_component_val_t::U16(*v),
+            Val::S32(v) => wasmtime_component_val_t::S32(*v),
+            Val::U32(v) => wasmtime_component_val_t::U32(*v),
+            Val::S64(v) => wasmtime_component_val_t::S64(*v),
+            Val::U64(v) => wasmtime_component_val_t::U64(*v),
+            Val::Float32(v) => wasmtime_component_val_t::F32(*v),
+            Val::Float64(v) => wasmtime_component_val_t::F64(*v),
+            Val::Char(c) => wasmtime_component_val_t::Char(u32::from(*c)),
+            Val::String(s) => wasmtime_component_val_t::String(wasm_name_t::from_name(s.clone())),
+            Val::List(vals) => wasmtime_component_val_t::List(from_vals(vals)?),
+            Val::Record(fields) => wasmtime_component_val_t::Record(
+                fields
+                    .iter()
+                    .map(|(name, val)| {
+                        Ok(wasmtime_component_valrecord_entry_t {
+                            name: wasm_name_t::from_name(name.clone()),
+                            val: wasmtime_component_val_t::from_val(val)?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .into(),
+            ),
+            Val::Tuple(vals) => wasmtime_component_val_t::Tuple(from_vals(vals)?),
+            Val::Variant(discriminant, val) => {
+                wasmtime_component_val_t::Variant(wasmtime_component_valvariant_t {
+                    discriminant: wasm_name_t::from_name(discriminant.clone()),
+                    val: from_payload(val)?,
+                })
+            }
+            Val::Enum(name) => wasmtime_component_val_t::Enum(wasm_name_t::from_name(name.clone())),
+            Val::Option(val) => wasmtime_component_val_t::Option(from_payload(val)?),
+            Val::Result(result) => {
+                let (is_ok, val) = match result {
+                    Ok(val) => (true, val),
+                    Err(val) => (false, val),
+                };
+                wasmtime_component_val_t::Result(wasmtime_component_valresult_t {
+                    is_ok,
+                    val: from_payload(val)?,
+                })
+            }
+            Val::Flags(names) => wasmtime_component_val_t::Flags(
+                names
+                    .iter()
+                    .map(|name| wasm_name_t::from_name(name.clone()))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            Val::Resource(_) => bail!("resources are not yet supported in the C API"),
+        })
+    }
+
+    pub(crate) fn to_val(&self) -> Result<Val> {
+        Ok(match self {
+            wasmtime_component_val_t::Bool(b) => Val::Bool(*b),
+            wasmtime_component_val_t::S8(v) => Val::S8(*v),
+            wasmtime_component_val_t::U8(v) => Val::U8(*v),
+            wasmtime_component_val_t::S16(v) => Val::S16(*v),
+            wasmtime_component_val_t::U16(v) => Val::U16(*v),
+            wasmtime_component_val_t::S32(v) => Val::S32(*v),
+            wasmtime_component_val_t::U32(v) => Val::U32(*v),
+            wasmtime_component_val_t::S64(v) => Val::S64(*v),
+            wasmtime_component_val_t::U64(v) => Val::U64(*v),
+            wasmtime_component_val_t::F32(v) => Val::Float32(*v),
+            wasmtime_component_val_t::F64(v) => Val::Float64(*v),
+            wasmtime_component_val_t::Char(c) => Val::Char(
+                char::from_u32(*c).ok_or_else(|| anyhow!("invalid character value {c:#x}"))?,
+            ),
+            wasmtime_component_val_t::String(s) => Val::String(to_string(s)?),
+            wasmtime_component_val_t::List(vals) => Val::List(to_vals(vals)?),
+            wasmtime_component_val_t::Record(fields) => Val::Record(
+                fields
+                    .as_slice()
+                    .iter()
+                    .map(|entry| Ok((to_string(&entry.name)?, entry.val.to_val()?)))
+                    .collect::<Result<_>>()?,
+            ),
+            wasmtime_component_val_t::Tuple(vals) => Val::Tuple(to_vals(vals)?),
+            wasmtime_component_val_t::Variant(variant) => {
+                Val::Variant(to_string(&variant.discriminant)?, to_payload(&variant.val)?)
+            }
+            wasmtime_component_val_t::Enum(name) => Val::Enum(to_string(name)?),
+            wasmtime_component_val_t::Option(val) => Val::Option(to_payload(val)?),
+            wasmtime_component_val_t::Result(result) => {
+                let val = to_payload(&result.val)?;
+                Val::Result(if result.is_ok { Ok(val) } else { Err(val) })
+            }
+            wasmtime_component_val_t::Flags(names) => Val::Flags(
+                names
+                    .as_slice()
+                    .iter()
+                    .map(to_string)
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+fn from_vals(vals: &[Val]) -> Result<wasmtime_component_vallist_t> {
+    Ok(vals
+        .iter()
+        .map(wasmtime_component_val_t::from_val)
+        .collect::<Result<Vec<_>>>()?
+        .into())
+}
+
+fn from_payload(val: &Option<Box<Val>>) -> Result<Option<Box<wasmtime_component_val_t>>> {
+    match val {
+        Some(val) => Ok(Some(Box::new(wasmtime_component_val_t::from_val(val)?))),
+        None => Ok(None),
+    }
+}
+
+fn to_vals(vals: &wasmtime_component_vallist_t) -> Result<Vec<Val>> {
+    vals.as_slice().iter().map(|val| val.to_val()).collect()
+}
+
+fn to_payload(val: &Option<Box<wasmtime_component_val_t>>) -> Result<Option<Box<Val>>> {
+    match val {
+        Some(val) => Ok(Some(Box::new(val.to_val()?))),
+        None => Ok(None),
+    }
+}
+
+fn to_string(name: &wasm_name_t) -> Result<String> {
+    match std::str::from_utf8(name.as_slice()) {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => bail!("input was not valid utf-8"),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_val_new(
+    val: &mut wasmtime_component_val_t,
+) -> Box<wasmtime_component_val_t> {
+    Box::new(mem::take(val))
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_val_free(_val: Option<Box<wasmtime_component_val_t>>) {}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_val_clone(
+    src: &wasmtime_component_val_t,
+    dst: &mut MaybeUninit<wasmtime_component_val_t>,
+) {
+    crate::initialize(dst, src.clone());
+}
+
+#[no_mangle]
+pub extern "C" fn wasmtime_component_val_delete(val: &mut wasmtime_component_val_t) {
+    drop(mem::take(val));
+}
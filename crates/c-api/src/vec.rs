@@ -79,6 +79,12 @@ macro_rules! declare_vecs {
             }
         }
 
+        impl$(<$lt>)? Default for $name $(<$lt>)? {
+            fn default() -> Self {
+                Vec::new().into()
+            }
+        }
+
         impl$(<$lt>)? Clone for $name $(<$lt>)? {
             fn clone(&self) -> Self {
                 self.as_slice().to_vec().into()
@@ -139,6 +145,9 @@ macro_rules! declare_vecs {
     )*};
 }
 
+#[cfg(feature = "component-model")]
+pub(crate) use declare_vecs;
+
 declare_vecs! {
     (
         name: wasm_byte_vec_t,
@@ -0,0 +1,685 @@
+/*
+Tests of the component model C API: instantiating a component with host
+functions defined in a linker and passing each kind of
+`wasmtime_component_val_t` into and out of it.
+
+You can compile and run this test on Linux with:
+
+   cargo build --release -p wasmtime-c-api
+   cc crates/c-api/tests/component.c \
+       -I crates/c-api/include \
+       target/release/libwasmtime.a \
+       -lpthread -ldl -lm \
+       -o component
+   ./component
+
+You can also build using cmake:
+
+mkdir build && cd build && cmake ../crates/c-api/tests && cmake --build .
+ctest
+*/
+
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <wasm.h>
+#include <wasmtime.h>
+
+// Each `roundtrip-*` export of this component returns its argument, and
+// `call-repeat` forwards its arguments to the host's `repeat` function.
+//
+// Arguments of the core functions are stored at their canonical ABI offsets
+// in a return area at address 8, the strings and lists they point to having
+// been allocated with `realloc` by the caller.
+static const char *component_wat =
+    "(component\n"
+    "  (import \"host\" (instance $host\n"
+    "    (export \"repeat\"\n"
+    "      (func (param \"s\" string) (param \"n\" u32) (result string)))\n"
+    "  ))\n"
+    "\n"
+    "  (core module $libc\n"
+    "    (memory (export \"memory\") 1)\n"
+    "    (global $heap (mut i32) (i32.const 1024))\n"
+    "    (func (export \"realloc\")\n"
+    "      (param $old i32) (param $old_size i32)\n"
+    "      (param $align i32) (param $new_size i32)\n"
+    "      (result i32)\n"
+    "      (local $ret i32)\n"
+    "      (local.set $ret\n"
+    "        (i32.and\n"
+    "          (i32.add (global.get $heap)\n"
+    "                   (i32.sub (local.get $align) (i32.const 1)))\n"
+    "          (i32.sub (i32.const 0) (local.get $align))))\n"
+    "      (global.set $heap\n"
+    "        (i32.add (local.get $ret) (local.get $new_size)))\n"
+    "      (local.get $ret))\n"
+    "  )\n"
+    "  (core instance $libc (instantiate $libc))\n"
+    "  (core func $repeat (canon lower (func $host \"repeat\")\n"
+    "    (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "\n"
+    "  (core module $m\n"
+    "    (import \"libc\" \"memory\" (memory 1))\n"
+    "    (import \"host\" \"repeat\" (func $repeat (param i32 i32 i32 i32)))\n"
+    "\n"
+    "    (func (export \"primitives\")\n"
+    "      (param i32 i32 i32 i32 i32 i32 i32 i64 i64 f32 f64 i32)\n"
+    "      (result i32)\n"
+    "      (i32.store8 offset=0 (i32.const 8) (local.get 0))\n"
+    "      (i32.store8 offset=1 (i32.const 8) (local.get 1))\n"
+    "      (i32.store8 offset=2 (i32.const 8) (local.get 2))\n"
+    "      (i32.store16 offset=4 (i32.const 8) (local.get 3))\n"
+    "      (i32.store16 offset=6 (i32.const 8) (local.get 4))\n"
+    "      (i32.store offset=8 (i32.const 8) (local.get 5))\n"
+    "      (i32.store offset=12 (i32.const 8) (local.get 6))\n"
+    "      (i64.store offset=16 (i32.const 8) (local.get 7))\n"
+    "      (i64.store offset=24 (i32.const 8) (local.get 8))\n"
+    "      (f32.store offset=32 (i32.const 8) (local.get 9))\n"
+    "      (f64.store offset=40 (i32.const 8) (local.get 10))\n"
+    "      (i32.store offset=48 (i32.const 8) (local.get 11))\n"
+    "      (i32.const 8))\n"
+    "\n"
+    "    (func (export \"pointer-and-length\") (param i32 i32) (result i32)\n"
+    "      (i32.store offset=0 (i32.const 8) (local.get 0))\n"
+    "      (i32.store offset=4 (i32.const 8) (local.get 1))\n"
+    "      (i32.const 8))\n"
+    "\n"
+    "    (func (export \"person\") (param i32 i32 i32 i32 i64) (result i32)\n"
+    "      (i32.store offset=0 (i32.const 8) (local.get 0))\n"
+    "      (i32.store offset=4 (i32.const 8) (local.get 1))\n"
+    "      (i32.store offset=8 (i32.const 8) (local.get 2))\n"
+    "      (i32.store offset=12 (i32.const 8) (local.get 3))\n"
+    "      (i64.store offset=16 (i32.const 8) (local.get 4))\n"
+    "      (i32.const 8))\n"
+    "\n"
+    "    (func (export \"shape\") (param i32 i32 i32) (result i32)\n"
+    "      (i32.store8 offset=0 (i32.const 8) (local.get 0))\n"
+    "      (i32.store offset=4 (i32.const 8) (local.get 1))\n"
+    "      (i32.store offset=8 (i32.const 8) (local.get 2))\n"
+    "      (i32.const 8))\n"
+    "\n"
+    "    (func (export \"tuple\") (param i32 i32 i32 i32 i32 i32 i32)\n"
+    "      (result i32)\n"
+    "      (i32.store8 offset=0 (i32.const 8) (local.get 0))\n"
+    "      (i32.store8 offset=4 (i32.const 8) (local.get 1))\n"
+    "      (i32.store offset=8 (i32.const 8) (local.get 2))\n"
+    "      (i32.store8 offset=12 (i32.const 8) (local.get 3))\n"
+    "      (i32.store offset=16 (i32.const 8) (local.get 4))\n"
+    "      (i32.store offset=20 (i32.const 8) (local.get 5))\n"
+    "      (i32.store8 offset=24 (i32.const 8) (local.get 6))\n"
+    "      (i32.const 8))\n"
+    "\n"
+    "    (func (export \"call-repeat\") (param i32 i32 i32) (result i32)\n"
+    "      (call $repeat (local.get 0) (local.get 1) (local.get 2)\n"
+    "        (i32.const 8))\n"
+    "      (i32.const 8))\n"
+    "  )\n"
+    "  (core instance $i (instantiate $m\n"
+    "    (with \"libc\" (instance $libc))\n"
+    "    (with \"host\" (instance (export \"repeat\" (func $repeat))))\n"
+    "  ))\n"
+    "\n"
+    "  (type $primitives' (record\n"
+    "    (field \"b\" bool) (field \"s8\" s8) (field \"u8\" u8)\n"
+    "    (field \"s16\" s16) (field \"u16\" u16) (field \"s32\" s32)\n"
+    "    (field \"u32\" u32) (field \"s64\" s64) (field \"u64\" u64)\n"
+    "    (field \"f32\" float32) (field \"f64\" float64) (field \"c\" char)))\n"
+    "  (export $primitives \"primitives\" (type $primitives'))\n"
+    "  (type $person' (record\n"
+    "    (field \"name\" string) (field \"tags\" (list string))\n"
+    "    (field \"id\" s64)))\n"
+    "  (export $person \"person\" (type $person'))\n"
+    "  (type $shape' (variant\n"
+    "    (case \"none\") (case \"num\" u32) (case \"text\" string)))\n"
+    "  (export $shape \"shape\" (type $shape'))\n"
+    "  (type $color' (enum \"red\" \"green\" \"blue\"))\n"
+    "  (export $color \"color\" (type $color'))\n"
+    "  (type $perms' (flags \"read\" \"write\" \"exec\"))\n"
+    "  (export $perms \"perms\" (type $perms'))\n"
+    "\n"
+    "  (func (export \"roundtrip-primitives\")\n"
+    "    (param \"x\" $primitives) (result $primitives)\n"
+    "    (canon lift (core func $i \"primitives\")\n"
+    "      (memory $libc \"memory\")))\n"
+    "  (func (export \"roundtrip-string\")\n"
+    "    (param \"x\" string) (result string)\n"
+    "    (canon lift (core func $i \"pointer-and-length\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "  (func (export \"roundtrip-list\")\n"
+    "    (param \"x\" (list string)) (result (list string))\n"
+    "    (canon lift (core func $i \"pointer-and-length\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "  (func (export \"roundtrip-person\")\n"
+    "    (param \"x\" $person) (result $person)\n"
+    "    (canon lift (core func $i \"person\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "  (func (export \"roundtrip-shape\")\n"
+    "    (param \"x\" $shape) (result $shape)\n"
+    "    (canon lift (core func $i \"shape\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "  (func (export \"roundtrip-tuple\")\n"
+    "    (param \"x\" (tuple $color (option u32) (result u32 (error string))\n"
+    "                        $perms))\n"
+    "    (result (tuple $color (option u32) (result u32 (error string))\n"
+    "                   $perms))\n"
+    "    (canon lift (core func $i \"tuple\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    "  (func (export \"call-repeat\")\n"
+    "    (param \"s\" string) (param \"n\" u32) (result string)\n"
+    "    (canon lift (core func $i \"call-repeat\")\n"
+    "      (memory $libc \"memory\") (realloc (func $libc \"realloc\"))))\n"
+    ")\n";
+
+static void exit_with_error(const char *message, wasmtime_error_t *error);
+
+// Bookkeeping of the host's `repeat` function, used as its `env`.
+struct repeat_env {
+  int calls;
+  int finalized;
+};
+
+static wasmtime_error_t *repeat_callback(void *env,
+                                         wasmtime_context_t *context,
+                                         const wasmtime_component_val_t *args,
+                                         size_t nargs,
+                                         wasmtime_component_val_t *results,
+                                         size_t nresults) {
+  (void)context;
+  struct repeat_env *repeat_env = env;
+  repeat_env->calls++;
+
+  assert(nargs == 2);
+  assert(nresults == 1);
+  assert(args[0].kind == WASMTIME_COMPONENT_STRING);
+  assert(args[1].kind == WASMTIME_COMPONENT_U32);
+  const wasm_name_t *s = &args[0].of.string;
+  uint32_t n = args[1].of.u32;
+  if (n > 100)
+    return wasmtime_error_new("repeat count is too large");
+
+  // Results are owned by the caller once this function returns.
+  results[0].kind = WASMTIME_COMPONENT_STRING;
+  wasm_byte_vec_new_uninitialized(&results[0].of.string, s->size * n);
+  for (uint32_t i = 0; i < n; i++)
+    memcpy(results[0].of.string.data + i * s->size, s->data, s->size);
+  return NULL;
+}
+
+static void repeat_finalizer(void *env) {
+  struct repeat_env *repeat_env = env;
+  repeat_env->finalized++;
+}
+
+static bool name_eq(const wasm_name_t *a, const wasm_name_t *b) {
+  return a->size == b->size &&
+         (a->size == 0 || memcmp(a->data, b->data, a->size) == 0);
+}
+
+static bool contains(const wasm_byte_vec_t *haystack, const char *needle) {
+  size_t len = strlen(needle);
+  for (size_t i = 0; i + len <= haystack->size; i++)
+    if (memcmp(haystack->data + i, needle, len) == 0)
+      return true;
+  return false;
+}
+
+static bool val_eq(const wasmtime_component_val_t *a,
+                   const wasmtime_component_val_t *b);
+
+static bool payload_eq(const wasmtime_component_val_t *a,
+                       const wasmtime_component_val_t *b) {
+  if (a == NULL || b == NULL)
+    return a == b;
+  return val_eq(a, b);
+}
+
+static bool vallist_eq(const wasmtime_component_vallist_t *a,
+                       const wasmtime_component_vallist_t *b) {
+  if (a->size != b->size)
+    return false;
+  for (size_t i = 0; i < a->size; i++)
+    if (!val_eq(&a->data[i], &b->data[i]))
+      return false;
+  return true;
+}
+
+static bool val_eq(const wasmtime_component_val_t *a,
+                   const wasmtime_component_val_t *b) {
+  if (a->kind != b->kind)
+    return false;
+
+  switch (a->kind) {
+  case WASMTIME_COMPONENT_BOOL:
+    return a->of.boolean == b->of.boolean;
+  case WASMTIME_COMPONENT_S8:
+    return a->of.s8 == b->of.s8;
+  case WASMTIME_COMPONENT_U8:
+    return a->of.u8 == b->of.u8;
+  case WASMTIME_COMPONENT_S16:
+    return a->of.s16 == b->of.s16;
+  case WASMTIME_COMPONENT_U16:
+    return a->of.u16 == b->of.u16;
+  case WASMTIME_COMPONENT_S32:
+    return a->of.s32 == b->of.s32;
+  case WASMTIME_COMPONENT_U32:
+    return a->of.u32 == b->of.u32;
+  case WASMTIME_COMPONENT_S64:
+    return a->of.s64 == b->of.s64;
+  case WASMTIME_COMPONENT_U64:
+    return a->of.u64 == b->of.u64;
+  case WASMTIME_COMPONENT_F32:
+    return a->of.f32 == b->of.f32;
+  case WASMTIME_COMPONENT_F64:
+    return a->of.f64 == b->of.f64;
+  case WASMTIME_COMPONENT_CHAR:
+    return a->of.character == b->of.character;
+  case WASMTIME_COMPONENT_STRING:
+    return name_eq(&a->of.string, &b->of.string);
+  case WASMTIME_COMPONENT_LIST:
+    return vallist_eq(&a->of.list, &b->of.list);
+  case WASMTIME_COMPONENT_RECORD:
+    if (a->of.record.size != b->of.record.size)
+      return false;
+    for (size_t i = 0; i < a->of.record.size; i++) {
+      const wasmtime_component_valrecord_entry_t *x = &a->of.record.data[i];
+      const wasmtime_component_valrecord_entry_t *y = &b->of.record.data[i];
+      if (!name_eq(&x->name, &y->name) || !val_eq(&x->val, &y->val))
+        return false;
+    }
+    return true;
+  case WASMTIME_COMPONENT_TUPLE:
+    return vallist_eq(&a->of.tuple, &b->of.tuple);
+  case WASMTIME_COMPONENT_VARIANT:
+    return name_eq(&a->of.variant.discriminant, &b->of.variant.discriminant) &&
+           payload_eq(a->of.variant.val, b->of.variant.val);
+  case WASMTIME_COMPONENT_ENUM:
+    return name_eq(&a->of.enumeration, &b->of.enumeration);
+  case WASMTIME_COMPONENT_OPTION:
+    return payload_eq(a->of.option, b->of.option);
+  case WASMTIME_COMPONENT_RESULT:
+    return a->of.result.is_ok == b->of.result.is_ok &&
+           payload_eq(a->of.result.val, b->of.result.val);
+  case WASMTIME_COMPONENT_FLAGS:
+    if (a->of.flags.size != b->of.flags.size)
+      return false;
+    for (size_t i = 0; i < a->of.flags.size; i++)
+      if (!name_eq(&a->of.flags.data[i], &b->of.flags.data[i]))
+        return false;
+    return true;
+  }
+  abort();
+}
+
+static wasmtime_component_val_t new_string(const char *s) {
+  wasmtime_component_val_t val;
+  val.kind = WASMTIME_COMPONENT_STRING;
+  wasm_name_new_from_string(&val.of.string, s);
+  return val;
+}
+
+static wasmtime_component_val_t new_u32(uint32_t n) {
+  wasmtime_component_val_t val;
+  val.kind = WASMTIME_COMPONENT_U32;
+  val.of.u32 = n;
+  return val;
+}
+
+static wasmtime_component_val_t new_variant(const char *discriminant,
+                                            wasmtime_component_val_t *payload) {
+  wasmtime_component_val_t val;
+  val.kind = WASMTIME_COMPONENT_VARIANT;
+  wasm_name_new_from_string(&val.of.variant.discriminant, discriminant);
+  val.of.variant.val = payload ? wasmtime_component_val_new(payload) : NULL;
+  return val;
+}
+
+static void set_field(wasmtime_component_valrecord_entry_t *entry,
+                      const char *name, wasmtime_component_val_t val) {
+  wasm_name_new_from_string(&entry->name, name);
+  entry->val = val;
+}
+
+static wasmtime_component_func_t
+lookup_func(wasmtime_context_t *context, const wasmtime_component_t *component,
+            const wasmtime_component_instance_t *instance, const char *name) {
+  wasmtime_component_export_index_t *index =
+      wasmtime_component_get_export_index(component, NULL, name, strlen(name));
+  assert(index != NULL);
+  wasmtime_component_func_t func;
+  bool found =
+      wasmtime_component_instance_get_func(context, instance, index, &func);
+  assert(found);
+  wasmtime_component_export_index_delete(index);
+  return func;
+}
+
+// Calls the export `name` with `arg`, checks that it's returned unchanged and
+// deallocates `arg`.
+static void roundtrip(wasmtime_context_t *context,
+                      const wasmtime_component_t *component,
+                      const wasmtime_component_instance_t *instance,
+                      const char *name, wasmtime_component_val_t *arg) {
+  wasmtime_component_func_t func =
+      lookup_func(context, component, instance, name);
+
+  wasmtime_component_val_t expected;
+  wasmtime_component_val_clone(arg, &expected);
+  assert(val_eq(arg, &expected));
+
+  wasmtime_component_val_t result;
+  wasmtime_error_t *error =
+      wasmtime_component_func_call(context, &func, arg, 1, &result, 1);
+  if (error != NULL)
+    exit_with_error(name, error);
+
+  // Arguments are only borrowed by calls so `arg` is still intact.
+  if (!val_eq(&result, &expected) || !val_eq(arg, &expected)) {
+    fprintf(stderr, "error: %s didn't return its argument\n", name);
+    exit(1);
+  }
+
+  wasmtime_component_val_delete(&result);
+  wasmtime_component_val_delete(&expected);
+  wasmtime_component_val_delete(arg);
+  assert(arg->kind == WASMTIME_COMPONENT_BOOL && !arg->of.boolean);
+}
+
+static void test_primitives(wasmtime_context_t *context,
+                            const wasmtime_component_t *component,
+                            const wasmtime_component_instance_t *instance) {
+  wasmtime_component_valrecord_entry_t fields[12];
+  wasmtime_component_val_t val;
+
+  val.kind = WASMTIME_COMPONENT_BOOL;
+  val.of.boolean = true;
+  set_field(&fields[0], "b", val);
+  val.kind = WASMTIME_COMPONENT_S8;
+  val.of.s8 = -8;
+  set_field(&fields[1], "s8", val);
+  val.kind = WASMTIME_COMPONENT_U8;
+  val.of.u8 = 200;
+  set_field(&fields[2], "u8", val);
+  val.kind = WASMTIME_COMPONENT_S16;
+  val.of.s16 = -1600;
+  set_field(&fields[3], "s16", val);
+  val.kind = WASMTIME_COMPONENT_U16;
+  val.of.u16 = 60000;
+  set_field(&fields[4], "u16", val);
+  val.kind = WASMTIME_COMPONENT_S32;
+  val.of.s32 = -320000;
+  set_field(&fields[5], "s32", val);
+  val.kind = WASMTIME_COMPONENT_U32;
+  val.of.u32 = 4000000000u;
+  set_field(&fields[6], "u32", val);
+  val.kind = WASMTIME_COMPONENT_S64;
+  val.of.s64 = INT64_MIN + 1;
+  set_field(&fields[7], "s64", val);
+  val.kind = WASMTIME_COMPONENT_U64;
+  val.of.u64 = UINT64_MAX;
+  set_field(&fields[8], "u64", val);
+  val.kind = WASMTIME_COMPONENT_F32;
+  val.of.f32 = 1.5f;
+  set_field(&fields[9], "f32", val);
+  val.kind = WASMTIME_COMPONENT_F64;
+  val.of.f64 = -2.25;
+  set_field(&fields[10], "f64", val);
+  val.kind = WASMTIME_COMPONENT_CHAR;
+  val.of.character = 0x1F600;
+  set_field(&fields[11], "c", val);
+
+  wasmtime_component_val_t record;
+  record.kind = WASMTIME_COMPONENT_RECORD;
+  wasmtime_component_valrecord_new(&record.of.record, 12, fields);
+  roundtrip(context, component, instance, "roundtrip-primitives", &record);
+}
+
+static void
+test_strings_and_lists(wasmtime_context_t *context,
+                       const wasmtime_component_t *component,
+                       const wasmtime_component_instance_t *instance) {
+  wasmtime_component_val_t string = new_string("hello, \xe2\x98\x83");
+  roundtrip(context, component, instance, "roundtrip-string", &string);
+
+  wasmtime_component_val_t items[3] = {
+      new_string("a"),
+      new_string(""),
+      new_string("a longer string"),
+  };
+  wasmtime_component_val_t list;
+  list.kind = WASMTIME_COMPONENT_LIST;
+  wasmtime_component_vallist_new(&list.of.list, 3, items);
+
+  // Copies of vectors are deep and owned separately.
+  wasmtime_component_val_t copy;
+  copy.kind = WASMTIME_COMPONENT_LIST;
+  wasmtime_component_vallist_copy(&copy.of.list, &list.of.list);
+  assert(val_eq(&list, &copy));
+  assert(copy.of.list.data[0].of.string.data !=
+         list.of.list.data[0].of.string.data);
+  wasmtime_component_vallist_delete(&copy.of.list);
+
+  roundtrip(context, component, instance, "roundtrip-list", &list);
+
+  list.kind = WASMTIME_COMPONENT_LIST;
+  wasmtime_component_vallist_new_empty(&list.of.list);
+  roundtrip(context, component, instance, "roundtrip-list", &list);
+}
+
+static void test_records(wasmtime_context_t *context,
+                         const wasmtime_component_t *component,
+                         const wasmtime_component_instance_t *instance) {
+  wasmtime_component_val_t tags[2] = {new_string("admin"), new_string("ops")};
+  wasmtime_component_val_t tag_list;
+  tag_list.kind = WASMTIME_COMPONENT_LIST;
+  wasmtime_component_vallist_new(&tag_list.of.list, 2, tags);
+
+  wasmtime_component_val_t id;
+  id.kind = WASMTIME_COMPONENT_S64;
+  id.of.s64 = -42;
+
+  wasmtime_component_valrecord_entry_t fields[3];
+  set_field(&fields[0], "name", new_string("alice"));
+  set_field(&fields[1], "tags", tag_list);
+  set_field(&fields[2], "id", id);
+
+  wasmtime_component_val_t person;
+  person.kind = WASMTIME_COMPONENT_RECORD;
+  wasmtime_component_valrecord_new(&person.of.record, 3, fields);
+  roundtrip(context, component, instance, "roundtrip-person", &person);
+}
+
+static void test_variants(wasmtime_context_t *context,
+                          const wasmtime_component_t *component,
+                          const wasmtime_component_instance_t *instance) {
+  wasmtime_component_val_t shape = new_variant("none", NULL);
+  roundtrip(context, component, instance, "roundtrip-shape", &shape);
+
+  wasmtime_component_val_t num = new_u32(42);
+  shape = new_variant("num", &num);
+  // The payload was moved out of `num`.
+  assert(num.kind == WASMTIME_COMPONENT_BOOL && !num.of.boolean);
+  roundtrip(context, component, instance, "roundtrip-shape", &shape);
+
+  wasmtime_component_val_t text = new_string("payload");
+  shape = new_variant("text", &text);
+  roundtrip(context, component, instance, "roundtrip-shape", &shape);
+
+  // Boxed values can also be deallocated on their own.
+  wasmtime_component_val_t unused = new_string("unused");
+  wasmtime_component_val_free(wasmtime_component_val_new(&unused));
+}
+
+static void test_tuples(wasmtime_context_t *context,
+                        const wasmtime_component_t *component,
+                        const wasmtime_component_instance_t *instance) {
+  wasmtime_component_val_t fields[4];
+  wasmtime_component_val_t payload;
+  wasmtime_component_val_t tuple;
+  tuple.kind = WASMTIME_COMPONENT_TUPLE;
+
+  // `(green, some(7), ok(9), {read, exec})`
+  fields[0].kind = WASMTIME_COMPONENT_ENUM;
+  wasm_name_new_from_string(&fields[0].of.enumeration, "green");
+  fields[1].kind = WASMTIME_COMPONENT_OPTION;
+  payload = new_u32(7);
+  fields[1].of.option = wasmtime_component_val_new(&payload);
+  fields[2].kind = WASMTIME_COMPONENT_RESULT;
+  fields[2].of.result.is_ok = true;
+  payload = new_u32(9);
+  fields[2].of.result.val = wasmtime_component_val_new(&payload);
+  fields[3].kind = WASMTIME_COMPONENT_FLAGS;
+  wasm_name_t flags[2];
+  wasm_name_new_from_string(&flags[0], "read");
+  wasm_name_new_from_string(&flags[1], "exec");
+  wasmtime_component_valflags_new(&fields[3].of.flags, 2, flags);
+  wasmtime_component_vallist_new(&tuple.of.tuple, 4, fields);
+  roundtrip(context, component, instance, "roundtrip-tuple", &tuple);
+
+  // `(blue, none, err("bad"), {})`
+  tuple.kind = WASMTIME_COMPONENT_TUPLE;
+  fields[0].kind = WASMTIME_COMPONENT_ENUM;
+  wasm_name_new_from_string(&fields[0].of.enumeration, "blue");
+  fields[1].kind = WASMTIME_COMPONENT_OPTION;
+  fields[1].of.option = NULL;
+  fields[2].kind = WASMTIME_COMPONENT_RESULT;
+  fields[2].of.result.is_ok = false;
+  payload = new_string("bad");
+  fields[2].of.result.val = wasmtime_component_val_new(&payload);
+  fields[3].kind = WASMTIME_COMPONENT_FLAGS;
+  wasmtime_component_valflags_new_empty(&fields[3].of.flags);
+  wasmtime_component_vallist_new(&tuple.of.tuple, 4, fields);
+  roundtrip(context, component, instance, "roundtrip-tuple", &tuple);
+}
+
+static void test_host_func(wasmtime_context_t *context,
+                           const wasmtime_component_t *component,
+                           const wasmtime_component_instance_t *instance,
+                           const struct repeat_env *repeat_env) {
+  wasmtime_component_export_index_t *index =
+      wasmtime_component_instance_get_export_index(context, instance, NULL,
+                                                   "call-repeat", 11);
+  assert(index != NULL);
+  wasmtime_component_func_t func;
+  bool found =
+      wasmtime_component_instance_get_func(context, instance, index, &func);
+  assert(found);
+  wasmtime_component_export_index_delete(index);
+  assert(wasmtime_component_get_export_index(component, NULL, "missing", 7) ==
+         NULL);
+
+  wasmtime_component_val_t args[2] = {new_string("ab"), new_u32(3)};
+  wasmtime_component_val_t result;
+  wasmtime_error_t *error =
+      wasmtime_component_func_call(context, &func, args, 2, &result, 1);
+  if (error != NULL)
+    exit_with_error("failed to call call-repeat", error);
+  assert(repeat_env->calls == 1);
+  wasmtime_component_val_t expected = new_string("ababab");
+  assert(val_eq(&result, &expected));
+  wasmtime_component_val_delete(&expected);
+  wasmtime_component_val_delete(&result);
+
+  // Errors of host functions are propagated to the caller. This traps, so the
+  // instance can't be used afterwards.
+  args[1].of.u32 = 1000;
+  error = wasmtime_component_func_call(context, &func, args, 2, &result, 1);
+  assert(error != NULL);
+  assert(repeat_env->calls == 2);
+  wasm_byte_vec_t message;
+  wasmtime_error_message(error, &message);
+  assert(contains(&message, "repeat count is too large"));
+  wasm_byte_vec_delete(&message);
+  wasmtime_error_delete(error);
+
+  wasmtime_component_val_delete(&args[0]);
+}
+
+int main() {
+  wasm_engine_t *engine = wasm_engine_new();
+  assert(engine != NULL);
+  wasmtime_store_t *store = wasmtime_store_new(engine, NULL, NULL);
+  assert(store != NULL);
+  wasmtime_context_t *context = wasmtime_store_context(store);
+
+  wasm_byte_vec_t wasm;
+  wasmtime_error_t *error =
+      wasmtime_wat2wasm(component_wat, strlen(component_wat), &wasm);
+  if (error != NULL)
+    exit_with_error("failed to parse wat", error);
+  wasmtime_component_t *component = NULL;
+  error = wasmtime_component_new(engine, (const uint8_t *)wasm.data, wasm.size,
+                                 &component);
+  if (error != NULL)
+    exit_with_error("failed to compile component", error);
+  wasm_byte_vec_delete(&wasm);
+
+  // Use a deserialized copy of the component to test that too.
+  wasm_byte_vec_t serialized;
+  error = wasmtime_component_serialize(component, &serialized);
+  if (error != NULL)
+    exit_with_error("failed to serialize component", error);
+  wasmtime_component_delete(component);
+  error = wasmtime_component_deserialize(
+      engine, (const uint8_t *)serialized.data, serialized.size, &component);
+  if (error != NULL)
+    exit_with_error("failed to deserialize component", error);
+  wasm_byte_vec_delete(&serialized);
+
+  // Define the `host` instance the component imports.
+  struct repeat_env repeat_env = {0, 0};
+  wasmtime_component_linker_t *linker = wasmtime_component_linker_new(engine);
+  wasmtime_component_linker_instance_t *root =
+      wasmtime_component_linker_root(linker);
+  wasmtime_component_linker_instance_t *host = NULL;
+  error = wasmtime_component_linker_instance_add_instance(root, "host", 4,
+                                                          &host);
+  if (error != NULL)
+    exit_with_error("failed to define host instance", error);
+  error = wasmtime_component_linker_instance_add_func(
+      host, "repeat", 6, repeat_callback, &repeat_env, repeat_finalizer);
+  if (error != NULL)
+    exit_with_error("failed to define repeat", error);
+
+  // Defining a name twice is an error, and the rejected function's data is
+  // finalized right away.
+  error = wasmtime_component_linker_instance_add_func(
+      host, "repeat", 6, repeat_callback, &repeat_env, repeat_finalizer);
+  assert(error != NULL);
+  wasmtime_error_delete(error);
+  assert(repeat_env.finalized == 1);
+
+  wasmtime_component_linker_instance_delete(host);
+  wasmtime_component_linker_instance_delete(root);
+
+  wasmtime_component_instance_t instance;
+  error = wasmtime_component_linker_instantiate(linker, context, component,
+                                                &instance);
+  if (error != NULL)
+    exit_with_error("failed to instantiate component", error);
+
+  test_primitives(context, component, &instance);
+  test_strings_and_lists(context, component, &instance);
+  test_records(context, component, &instance);
+  test_variants(context, component, &instance);
+  test_tuples(context, component, &instance);
+  test_host_func(context, component, &instance, &repeat_env);
+
+  wasmtime_component_linker_delete(linker);
+  wasmtime_store_delete(store);
+  assert(repeat_env.finalized == 2);
+  wasmtime_component_delete(component);
+  wasm_engine_delete(engine);
+  return 0;
+}
+
+static void exit_with_error(const char *message, wasmtime_error_t *error) {
+  fprintf(stderr, "error: %s\n", message);
+  wasm_byte_vec_t error_message;
+  wasmtime_error_message(error, &error_message);
+  wasmtime_error_delete(error);
+  fprintf(stderr, "%.*s\n", (int)error_message.size, error_message.data);
+  wasm_byte_vec_delete(&error_message);
+  exit(1);
+}
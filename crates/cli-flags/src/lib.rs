@@ -140,6 +140,9 @@ wasmtime_option_group! {
         pub parallel_compilation: Option<bool>,
         /// Whether to enable proof-carrying code (PCC)-based validation.
         pub pcc: Option<bool>,
+        /// The garbage collector to use for GC references, either `drc` or
+        /// `copying`.
+        pub collector: Option<wasmtime::Collector>,
 
         #[prefixed = "cranelift"]
         /// Set a cranelift-specific option. Use `wasmtime settings` to see
@@ -286,6 +289,56 @@ wasmtime_option_group! {
         pub http: Option<bool>,
         /// Enable support for WASI runtime config API (experimental)
         pub runtime_config: Option<bool>,
+        /// Trust the certificate authorities in the given PEM file for
+        /// outgoing wasi-http `https` requests, in addition to the default
+        /// certificate authorities.
+        pub http_ca_bundle: Option<String>,
+        /// Whether to trust the default certificate authorities for outgoing
+        /// wasi-http `https` requests (defaults to true).
+        ///
+        /// Disable this with `http-ca-bundle` to only trust a private
+        /// certificate authority.
+        pub http_default_roots: Option<bool>,
+        /// Present the client certificate chain in the given PEM file to
+        /// servers of outgoing wasi-http `https` requests.
+        ///
+        /// Requires `http-client-key`.
+        pub http_client_cert: Option<String>,
+        /// The PEM-encoded private key of `http-client-cert`.
+        pub http_client_key: Option<String>,
+        /// Only allow outgoing wasi-http requests to authorities matching the
+        /// given pattern, such as `example.com`, `*.example.com:443`, or
+        /// `*:8080`.
+        pub http_allow_authority: Vec<String>,
+        /// Deny outgoing wasi-http requests to authorities matching the given
+        /// pattern, even if they're also allowed.
+        pub http_deny_authority: Vec<String>,
+        /// Only allow outgoing wasi-http requests to the given port.
+        pub http_allow_port: Vec<u16>,
+        /// Only allow outgoing wasi-http requests with the given scheme,
+        /// either `http` or `https`.
+        pub http_allow_scheme: Vec<String>,
+        /// Only allow outgoing wasi-http requests with the given method.
+        pub http_allow_method: Vec<String>,
+        /// The maximum size, in bytes, of the body of an outgoing wasi-http
+        /// request.
+        pub http_max_body_size: Option<u64>,
+        /// The maximum number of outgoing wasi-http requests that may be sent
+        /// per second.
+        pub http_max_requests_per_second: Option<u32>,
+        /// Whether to keep the connections of outgoing wasi-http requests
+        /// open to reuse them for later requests (defaults to true for
+        /// `wasmtime serve` and to false otherwise).
+        pub http_connection_pool: Option<bool>,
+        /// How long an unused connection is kept open in the connection pool
+        /// (defaults to 90s).
+        pub http_pool_idle_timeout: Option<Duration>,
+        /// The maximum number of unused connections kept open to each
+        /// authority in the connection pool (defaults to 32).
+        pub http_pool_max_idle_per_host: Option<usize>,
+        /// The maximum number of connections to each authority that may be
+        /// in use at once by requests sent through the connection pool.
+        pub http_pool_max_connections_per_host: Option<usize>,
         /// Inherit environment variables and file descriptors following the
         /// systemd listen fd specification (UNIX only)
         pub listenfd: Option<bool>,
@@ -469,6 +522,11 @@ impl CommonOptions {
             target => config.target(target)?,
             _ => err,
         }
+        match_feature! {
+            ["gc" : self.codegen.collector]
+            collector => config.collector(collector),
+            _ => err,
+        }
         match_feature! {
             ["cranelift" : self.codegen.cranelift_debug_verifier]
             enable => config.cranelift_debug_verifier(enable),
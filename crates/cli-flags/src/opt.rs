@@ -297,6 +297,17 @@ impl WasmtimeOptionValue for String {
     }
 }
 
+impl WasmtimeOptionValue for u16 {
+    const VAL_HELP: &'static str = "=N";
+    fn parse(val: Option<&str>) -> Result<Self> {
+        let val = String::parse(val)?;
+        match val.strip_prefix("0x") {
+            Some(hex) => Ok(u16::from_str_radix(hex, 16)?),
+            None => Ok(val.parse()?),
+        }
+    }
+}
+
 impl WasmtimeOptionValue for u32 {
     const VAL_HELP: &'static str = "=N";
     fn parse(val: Option<&str>) -> Result<Self> {
@@ -382,6 +393,17 @@ impl WasmtimeOptionValue for wasmtime::Strategy {
     }
 }
 
+impl WasmtimeOptionValue for wasmtime::Collector {
+    const VAL_HELP: &'static str = "=drc|copying";
+    fn parse(val: Option<&str>) -> Result<Self> {
+        match String::parse(val)?.as_str() {
+            "drc" => Ok(wasmtime::Collector::DeferredReferenceCounting),
+            "copying" => Ok(wasmtime::Collector::Copying),
+            other => bail!("unknown collector `{other}` only `drc` and `copying` accepted",),
+        }
+    }
+}
+
 impl WasmtimeOptionValue for WasiNnGraph {
     const VAL_HELP: &'static str = "=<format>::<dir>";
     fn parse(val: Option<&str>) -> Result<Self> {
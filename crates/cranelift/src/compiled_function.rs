@@ -3,7 +3,9 @@ use cranelift_codegen::{
     ir, isa::unwind::CfaUnwindInfo, isa::unwind::UnwindInfo, Final, MachBufferFinalized,
     MachSrcLoc, ValueLabelsRanges,
 };
-use wasmtime_environ::{FilePos, InstructionAddressMap, PrimaryMap, TrapInformation};
+use wasmtime_environ::{
+    FilePos, FunctionFrameState, InstructionAddressMap, PrimaryMap, TrapInformation,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 /// Metadata to translate from binary offsets back to the original
@@ -50,6 +52,8 @@ pub struct CompiledFunctionMetadata {
     pub start_srcloc: FilePos,
     /// End source location.
     pub end_srcloc: FilePos,
+    /// Description of the frame state slot, if any.
+    pub frame_state: Option<FunctionFrameState>,
 }
 
 /// Compiled function: machine code body, jump table offsets, and unwind information.
@@ -155,6 +159,16 @@ impl CompiledFunction {
         self.metadata.cfa_unwind_info = Some(unwind);
     }
 
+    /// Get the description of the function's frame state slot, if it has one.
+    pub fn frame_state(&self) -> Option<&FunctionFrameState> {
+        self.metadata.frame_state.as_ref()
+    }
+
+    /// Set the description of the function's frame state slot.
+    pub fn set_frame_state(&mut self, frame_state: FunctionFrameState) {
+        self.metadata.frame_state = Some(frame_state);
+    }
+
     /// Set the sized stack slots.
     pub fn set_sized_stack_slots(&mut self, slots: ir::StackSlots) {
         self.metadata.sized_stack_slots = slots;
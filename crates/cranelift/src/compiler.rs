@@ -4,6 +4,8 @@ use crate::DEBUG_ASSERT_TRAP_CODE;
 use crate::{array_call_signature, CompiledFunction, ModuleTextBuilder};
 use crate::{builder::LinkOptions, wasm_call_signature, BuiltinFunctionSignatures};
 use anyhow::{Context as _, Result};
+use cranelift_bitset::CompoundBitSet;
+use cranelift_codegen::binemit::CodeOffset;
 use cranelift_codegen::ir::{self, InstBuilder, MemFlags, UserExternalName, UserFuncName, Value};
 use cranelift_codegen::isa::{
     unwind::{UnwindInfo, UnwindInfoKind},
@@ -25,10 +27,10 @@ use std::path;
 use std::sync::{Arc, Mutex};
 use wasmparser::{FuncValidatorAllocations, FunctionBody};
 use wasmtime_environ::{
-    AddressMapSection, BuiltinFunctionIndex, CacheStore, CompileError, FlagValue, FunctionBodyData,
-    FunctionLoc, ModuleTranslation, ModuleTypesBuilder, PtrSize, RelocationTarget,
-    StackMapInformation, StaticModuleIndex, TrapEncodingBuilder, Tunables, VMOffsets,
-    WasmFunctionInfo,
+    AddressMapSection, BuiltinFunctionIndex, CacheStore, Collector, CompileError, FlagValue,
+    FrameStateSection, FunctionBodyData, FunctionFrameState, FunctionLoc, ModuleTranslation,
+    ModuleTypesBuilder, PtrSize, RelocationTarget, StackMapInformation, StaticModuleIndex,
+    TrapEncodingBuilder, Tunables, VMOffsets, WasmFunctionInfo,
 };
 
 #[cfg(feature = "component-model")]
@@ -209,6 +211,7 @@ impl wasmtime_environ::Compiler for Compiler {
             &mut context.func,
             &mut func_env,
         )?;
+        let frame_state = func_env.take_frame_state();
 
         if let Some(path) = &self.clif_dir {
             use std::io::Write;
@@ -221,7 +224,7 @@ impl wasmtime_environ::Compiler for Compiler {
             write!(output, "{}", context.func.display()).unwrap();
         }
 
-        let (info, func) = compiler.finish_with_info(Some((&body, &self.tunables)))?;
+        let (info, func) = compiler.finish_with_info(Some((&body, &self.tunables)), frame_state)?;
 
         let timing = cranelift_codegen::timing::take_current();
         log::debug!("{:?} translated in {:?}", func_index, timing.total());
@@ -378,6 +381,7 @@ impl wasmtime_environ::Compiler for Compiler {
         }
         let mut addrs = AddressMapSection::default();
         let mut traps = TrapEncodingBuilder::default();
+        let mut frame_states = FrameStateSection::default();
 
         let mut ret = Vec::with_capacity(funcs.len());
         for (i, (sym, func)) in funcs.iter().enumerate() {
@@ -388,6 +392,9 @@ impl wasmtime_environ::Compiler for Compiler {
                 addrs.push(range.clone(), &addr.instructions);
             }
             traps.push(range.clone(), &func.traps().collect::<Vec<_>>());
+            if let Some(frame_state) = func.frame_state() {
+                frame_states.push(range.clone(), frame_state);
+            }
             builder.append_padding(self.linkopts.padding_between_functions);
             let info = FunctionLoc {
                 start: u32::try_from(range.start).unwrap(),
@@ -402,6 +409,7 @@ impl wasmtime_environ::Compiler for Compiler {
             addrs.append_to(obj);
         }
         traps.append_to(obj);
+        frame_states.append_to(obj);
 
         Ok(ret)
     }
@@ -792,7 +800,7 @@ impl FunctionCompiler<'_> {
     }
 
     fn finish(self) -> Result<CompiledFunction, CompileError> {
-        let (info, func) = self.finish_with_info(None)?;
+        let (info, func) = self.finish_with_info(None, None)?;
         assert!(info.stack_maps.is_empty());
         Ok(func)
     }
@@ -800,6 +808,7 @@ impl FunctionCompiler<'_> {
     fn finish_with_info(
         mut self,
         body_and_tunables: Option<(&FunctionBody<'_>, &Tunables)>,
+        frame_state: Option<(ir::StackSlot, FunctionFrameState)>,
     ) -> Result<(WasmFunctionInfo, CompiledFunction), CompileError> {
         let context = &mut self.cx.codegen_context;
         let isa = &*self.compiler.isa;
@@ -834,6 +843,17 @@ impl FunctionCompiler<'_> {
             );
         }
 
+        // The frame state slot is located relative to the frame pointer, which
+        // is at the top of the frame on all architectures except s390x, which
+        // doesn't use a frame pointer.
+        if let Some((slot, mut frame_state)) = frame_state {
+            if isa.triple().architecture != target_lexicon::Architecture::S390x {
+                let slot_offset = compiled_code.sized_stackslot_offsets[slot];
+                frame_state.slot_offset = compiled_code.frame_size - slot_offset;
+                compiled_function.set_frame_state(frame_state);
+            }
+        }
+
         if isa.flags().unwind_info() {
             let unwind = compiled_code
                 .create_unwind_info(isa)
@@ -865,8 +885,20 @@ impl FunctionCompiler<'_> {
             }
         }
 
-        let stack_maps =
-            mach_stack_maps_to_stack_maps(compiled_code.buffer.take_stack_maps().into_iter());
+        let stack_maps = match self.compiler.tunables.collector {
+            // The copying collector moves objects, so the frontend spills GC
+            // references and reloads them around safepoints. None of them are
+            // ever live in a register, and thus in the register allocator's
+            // stack maps, across a call.
+            Some(Collector::Copying) => {
+                let regalloc_maps = compiled_code.buffer.take_stack_maps();
+                debug_assert!(regalloc_maps.is_empty());
+                clif_to_env_stack_maps(compiled_code.buffer.user_stack_maps())?
+            }
+            Some(Collector::DeferredReferenceCounting) | None => {
+                mach_stack_maps_to_stack_maps(compiled_code.buffer.take_stack_maps().into_iter())
+            }
+        };
         compiled_function
             .set_sized_stack_slots(std::mem::take(&mut context.func.sized_stack_slots));
         self.compiler.contexts.lock().unwrap().push(self.cx);
@@ -905,6 +937,36 @@ fn mach_stack_maps_to_stack_maps(
     stack_maps
 }
 
+/// Convert the frontend's user stack maps into Wasmtime's representation, which
+/// has one bit per 8-byte word of the frame, starting from the SP.
+///
+/// Returns an error if a GC reference isn't a word-sized, word-aligned stack
+/// slot, which can happen when targeting 32-bit architectures where references
+/// are `r32`s.
+fn clif_to_env_stack_maps(
+    user_stack_maps: &[(CodeOffset, u32, ir::UserStackMap)],
+) -> Result<Vec<StackMapInformation>, CompileError> {
+    let mut stack_maps = Vec::with_capacity(user_stack_maps.len());
+    for (code_offset, frame_size, stack_map) in user_stack_maps {
+        let mapped_words = *frame_size / 8;
+        let mut bits = CompoundBitSet::with_capacity(usize::try_from(mapped_words).unwrap());
+        for (ty, offset) in stack_map.entries() {
+            if ty.bytes() != 8 || offset % 8 != 0 {
+                return Err(CompileError::Codegen(format!(
+                    "unsupported GC reference in stack map: type `{ty}` at offset {offset}; \
+                     the copying collector requires word-sized, word-aligned references"
+                )));
+            }
+            bits.insert(usize::try_from(offset / 8).unwrap());
+        }
+        stack_maps.push(StackMapInformation {
+            code_offset: *code_offset,
+            stack_map: wasmtime_environ::StackMap::new(mapped_words, bits),
+        });
+    }
+    Ok(stack_maps)
+}
+
 fn declare_and_call(
     builder: &mut FunctionBuilder,
     signature: ir::Signature,
@@ -0,0 +1,323 @@
+//! Recording of Wasm locals and operand stack values into a "frame state
+//! slot" so they can be recovered from native stack frames, for example when
+//! creating a core dump.
+//!
+//! See `wasmtime_environ::FunctionFrameState` for how this is described to the
+//! runtime.
+
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_entity::EntityRef;
+use cranelift_frontend::{FunctionBuilder, Variable};
+use cranelift_wasm::FuncTranslationState;
+use wasmparser::{FuncValidator, Operator, ValType, WasmModuleResources};
+use wasmtime_environ::{
+    frame_state_value_offset, FrameValueType, FunctionFrameState, FRAME_STATE_VMCTX_OFFSET,
+};
+
+/// The maximum number of locals and operand stack values which are recorded
+/// at any one instruction, to bound the size of the frame state slot. Any
+/// values beyond this are recorded as missing.
+const MAX_VALUES: u32 = 4096;
+
+/// Builder of the frame state of a single function as it's translated.
+#[derive(Default)]
+pub(crate) struct FrameStateBuilder {
+    slot: Option<ir::StackSlot>,
+    state: FunctionFrameState,
+}
+
+impl FrameStateBuilder {
+    /// Records the current values of locals and of the operand stack, along
+    /// with `vmctx`, into this function's frame state slot if `op` may trap or
+    /// call another function.
+    ///
+    /// This must be called before `op` is validated as it uses the `validator`
+    /// to learn the types of the values being recorded.
+    pub fn before_operator(
+        &mut self,
+        op: &Operator,
+        offset: usize,
+        validator: &FuncValidator<impl WasmModuleResources>,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+        vmctx: impl FnOnce(&mut FunctionBuilder) -> ir::Value,
+    ) {
+        if !state.reachable() || !may_trap_or_call(op) {
+            return;
+        }
+        let stack = state.operand_stack();
+        let height = validator.operand_stack_height() as usize;
+        if stack.len() != height {
+            return;
+        }
+        let Ok(offset) = u32::try_from(offset) else {
+            return;
+        };
+
+        if self.slot.is_none() {
+            self.state.locals = (0..validator.len_locals())
+                .map(|i| match i {
+                    MAX_VALUES.. => FrameValueType::Missing,
+                    _ => frame_value_type(validator.get_local_type(i)),
+                })
+                .collect();
+            self.slot = Some(builder.func.create_sized_stack_slot(ir::StackSlotData::new(
+                ir::StackSlotKind::ExplicitSlot,
+                frame_state_value_offset(0),
+                3,
+            )));
+        }
+        let slot = self.slot.unwrap();
+
+        let vmctx = vmctx(builder);
+        builder.ins().stack_store(
+            vmctx,
+            slot,
+            i32::try_from(FRAME_STATE_VMCTX_OFFSET).unwrap(),
+        );
+
+        let mut index = 0;
+        for (i, ty) in self.state.locals.iter().enumerate() {
+            if *ty != FrameValueType::Missing {
+                let val = builder.use_var(Variable::new(i));
+                store(builder, slot, index, val);
+            }
+            index += 1;
+        }
+
+        let mut types = Vec::with_capacity(stack.len());
+        for (i, val) in stack.iter().enumerate() {
+            let ty = match index {
+                MAX_VALUES.. => FrameValueType::Missing,
+                _ => frame_value_type(validator.get_operand_type(height - i - 1).flatten()),
+            };
+            if ty != FrameValueType::Missing {
+                store(builder, slot, index, *val);
+            }
+            types.push(ty);
+            index += 1;
+        }
+
+        let size = frame_state_value_offset(index.min(MAX_VALUES));
+        let data = &mut builder.func.sized_stack_slots[slot];
+        data.size = data.size.max(size);
+        self.state.points.push((offset, types));
+    }
+
+    /// Returns the stack slot and frame state recorded for this function, if
+    /// any values were recorded.
+    ///
+    /// The `slot_offset` of the returned frame state is not yet known and must
+    /// be filled in once the function's frame has been laid out.
+    pub fn finish(self) -> Option<(ir::StackSlot, FunctionFrameState)> {
+        Some((self.slot?, self.state))
+    }
+}
+
+fn store(builder: &mut FunctionBuilder, slot: ir::StackSlot, index: u32, val: ir::Value) {
+    let offset = i32::try_from(frame_state_value_offset(index)).unwrap();
+    builder.ins().stack_store(val, slot, offset);
+}
+
+fn frame_value_type(ty: Option<ValType>) -> FrameValueType {
+    match ty {
+        Some(ValType::I32) => FrameValueType::I32,
+        Some(ValType::I64) => FrameValueType::I64,
+        Some(ValType::F32) => FrameValueType::F32,
+        Some(ValType::F64) => FrameValueType::F64,
+        _ => FrameValueType::Missing,
+    }
+}
+
+/// Returns whether `op` may trap or call another function, in which case its
+/// frame may show up in a backtrace.
+///
+/// Note that `loop` isn't included here even though fuel and epoch checks may
+/// trap at the loop header, since values recorded before the loop would be
+/// stale in later iterations.
+fn may_trap_or_call(op: &Operator) -> bool {
+    match op {
+        // Calls, and instructions which always trap.
+        Operator::Unreachable
+        | Operator::Call { .. }
+        | Operator::CallIndirect { .. }
+        | Operator::CallRef { .. }
+        | Operator::Throw { .. }
+        | Operator::ThrowRef
+        | Operator::Rethrow { .. }
+
+        // Accesses to linear memories and tables, which may be out of bounds.
+        | Operator::I32Load { .. }
+        | Operator::I64Load { .. }
+        | Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::I32Load8S { .. }
+        | Operator::I32Load8U { .. }
+        | Operator::I32Load16S { .. }
+        | Operator::I32Load16U { .. }
+        | Operator::I64Load8S { .. }
+        | Operator::I64Load8U { .. }
+        | Operator::I64Load16S { .. }
+        | Operator::I64Load16U { .. }
+        | Operator::I64Load32S { .. }
+        | Operator::I64Load32U { .. }
+        | Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::V128Load { .. }
+        | Operator::V128Load8x8S { .. }
+        | Operator::V128Load8x8U { .. }
+        | Operator::V128Load16x4S { .. }
+        | Operator::V128Load16x4U { .. }
+        | Operator::V128Load32x2S { .. }
+        | Operator::V128Load32x2U { .. }
+        | Operator::V128Load8Splat { .. }
+        | Operator::V128Load16Splat { .. }
+        | Operator::V128Load32Splat { .. }
+        | Operator::V128Load64Splat { .. }
+        | Operator::V128Load32Zero { .. }
+        | Operator::V128Load64Zero { .. }
+        | Operator::V128Store { .. }
+        | Operator::V128Load8Lane { .. }
+        | Operator::V128Load16Lane { .. }
+        | Operator::V128Load32Lane { .. }
+        | Operator::V128Load64Lane { .. }
+        | Operator::V128Store8Lane { .. }
+        | Operator::V128Store16Lane { .. }
+        | Operator::V128Store32Lane { .. }
+        | Operator::V128Store64Lane { .. }
+        | Operator::MemoryGrow { .. }
+        | Operator::MemoryInit { .. }
+        | Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::MemoryDiscard { .. }
+        | Operator::TableGet { .. }
+        | Operator::TableSet { .. }
+        | Operator::TableGrow { .. }
+        | Operator::TableInit { .. }
+        | Operator::TableCopy { .. }
+        | Operator::TableFill { .. }
+
+        // Arithmetic which traps on invalid inputs.
+        | Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I32TruncF32S
+        | Operator::I32TruncF32U
+        | Operator::I32TruncF64S
+        | Operator::I32TruncF64U
+        | Operator::I64TruncF32S
+        | Operator::I64TruncF32U
+        | Operator::I64TruncF64S
+        | Operator::I64TruncF64U
+
+        // GC instructions which may trap on null or out-of-bounds accesses,
+        // or which may call into the runtime to allocate.
+        | Operator::StructNew { .. }
+        | Operator::StructNewDefault { .. }
+        | Operator::StructGet { .. }
+        | Operator::StructGetS { .. }
+        | Operator::StructGetU { .. }
+        | Operator::StructSet { .. }
+        | Operator::ArrayNew { .. }
+        | Operator::ArrayNewDefault { .. }
+        | Operator::ArrayNewFixed { .. }
+        | Operator::ArrayNewData { .. }
+        | Operator::ArrayNewElem { .. }
+        | Operator::ArrayGet { .. }
+        | Operator::ArrayGetS { .. }
+        | Operator::ArrayGetU { .. }
+        | Operator::ArraySet { .. }
+        | Operator::ArrayLen
+        | Operator::ArrayFill { .. }
+        | Operator::ArrayCopy { .. }
+        | Operator::ArrayInitData { .. }
+        | Operator::ArrayInitElem { .. }
+        | Operator::RefAsNonNull
+        | Operator::RefCastNonNull { .. }
+        | Operator::RefCastNullable { .. }
+        | Operator::I31GetS
+        | Operator::I31GetU
+
+        // Atomics, which may additionally trap on unaligned accesses.
+        | Operator::MemoryAtomicNotify { .. }
+        | Operator::MemoryAtomicWait32 { .. }
+        | Operator::MemoryAtomicWait64 { .. }
+        | Operator::I32AtomicLoad { .. }
+        | Operator::I64AtomicLoad { .. }
+        | Operator::I32AtomicLoad8U { .. }
+        | Operator::I32AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad8U { .. }
+        | Operator::I64AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad32U { .. }
+        | Operator::I32AtomicStore { .. }
+        | Operator::I64AtomicStore { .. }
+        | Operator::I32AtomicStore8 { .. }
+        | Operator::I32AtomicStore16 { .. }
+        | Operator::I64AtomicStore8 { .. }
+        | Operator::I64AtomicStore16 { .. }
+        | Operator::I64AtomicStore32 { .. }
+        | Operator::I32AtomicRmwAdd { .. }
+        | Operator::I64AtomicRmwAdd { .. }
+        | Operator::I32AtomicRmw8AddU { .. }
+        | Operator::I32AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw8AddU { .. }
+        | Operator::I64AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw32AddU { .. }
+        | Operator::I32AtomicRmwSub { .. }
+        | Operator::I64AtomicRmwSub { .. }
+        | Operator::I32AtomicRmw8SubU { .. }
+        | Operator::I32AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw8SubU { .. }
+        | Operator::I64AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw32SubU { .. }
+        | Operator::I32AtomicRmwAnd { .. }
+        | Operator::I64AtomicRmwAnd { .. }
+        | Operator::I32AtomicRmw8AndU { .. }
+        | Operator::I32AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw8AndU { .. }
+        | Operator::I64AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw32AndU { .. }
+        | Operator::I32AtomicRmwOr { .. }
+        | Operator::I64AtomicRmwOr { .. }
+        | Operator::I32AtomicRmw8OrU { .. }
+        | Operator::I32AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw8OrU { .. }
+        | Operator::I64AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw32OrU { .. }
+        | Operator::I32AtomicRmwXor { .. }
+        | Operator::I64AtomicRmwXor { .. }
+        | Operator::I32AtomicRmw8XorU { .. }
+        | Operator::I32AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw8XorU { .. }
+        | Operator::I64AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw32XorU { .. }
+        | Operator::I32AtomicRmwXchg { .. }
+        | Operator::I64AtomicRmwXchg { .. }
+        | Operator::I32AtomicRmw8XchgU { .. }
+        | Operator::I32AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw8XchgU { .. }
+        | Operator::I64AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw32XchgU { .. }
+        | Operator::I32AtomicRmwCmpxchg { .. }
+        | Operator::I64AtomicRmwCmpxchg { .. }
+        | Operator::I32AtomicRmw8CmpxchgU { .. }
+        | Operator::I32AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw8CmpxchgU { .. }
+        | Operator::I64AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw32CmpxchgU { .. } => true,
+
+        _ => false,
+    }
+}
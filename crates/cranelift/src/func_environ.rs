@@ -1,3 +1,4 @@
+use crate::frame_state::FrameStateBuilder;
 use crate::{gc, BuiltinFunctionSignatures};
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir;
@@ -17,10 +18,10 @@ use cranelift_wasm::{
     TypeIndex, WasmHeapTopType, WasmHeapType, WasmResult,
 };
 use std::mem;
-use wasmparser::Operator;
+use wasmparser::{FuncValidator, Operator, WasmModuleResources};
 use wasmtime_environ::{
-    BuiltinFunctionIndex, MemoryPlan, MemoryStyle, Module, ModuleTranslation, ModuleTypesBuilder,
-    PtrSize, TableStyle, Tunables, TypeConvert, VMOffsets,
+    BuiltinFunctionIndex, FunctionFrameState, MemoryPlan, MemoryStyle, Module, ModuleTranslation,
+    ModuleTypesBuilder, PtrSize, TableStyle, Tunables, TypeConvert, VMOffsets,
 };
 use wasmtime_environ::{FUNCREF_INIT_BIT, FUNCREF_MASK};
 
@@ -107,7 +108,7 @@ pub struct FuncEnvironment<'module_environment> {
     /// Offsets to struct fields accessed by JIT code.
     pub(crate) offsets: VMOffsets<u8>,
 
-    tunables: &'module_environment Tunables,
+    pub(crate) tunables: &'module_environment Tunables,
 
     /// A function-local variable which stores the cached value of the amount of
     /// fuel remaining to execute. If used this is modified frequently so it's
@@ -136,6 +137,10 @@ pub struct FuncEnvironment<'module_environment> {
 
     fuel_consumed: i64,
 
+    /// Records locals and operand stack values into the frame when
+    /// `Tunables::generate_frame_state` is enabled.
+    frame_state: FrameStateBuilder,
+
     #[cfg(feature = "wmemcheck")]
     wmemcheck: bool,
 }
@@ -174,6 +179,8 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             // functions should consume at least some fuel.
             fuel_consumed: 1,
 
+            frame_state: FrameStateBuilder::default(),
+
             #[cfg(feature = "wmemcheck")]
             wmemcheck,
             #[cfg(feature = "wmemcheck")]
@@ -211,6 +218,13 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         })
     }
 
+    /// Returns the frame state slot of the function translated with this
+    /// environment along with a description of its contents, if one was
+    /// needed.
+    pub(crate) fn take_frame_state(&mut self) -> Option<(ir::StackSlot, FunctionFrameState)> {
+        mem::take(&mut self.frame_state).finish()
+    }
+
     pub(crate) fn vmctx_val(&mut self, pos: &mut FuncCursor<'_>) -> ir::Value {
         let pointer_type = self.pointer_type();
         let vmctx = self.vmctx(&mut pos.func);
@@ -2531,6 +2545,25 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 
+    fn before_validate_operator(
+        &mut self,
+        op: &Operator,
+        offset: usize,
+        validator: &FuncValidator<impl WasmModuleResources>,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if self.tunables.generate_frame_state {
+            let pointer_type = self.pointer_type();
+            let vmctx = self.vmctx(builder.func);
+            self.frame_state
+                .before_operator(op, offset, validator, builder, state, |builder| {
+                    builder.ins().global_value(pointer_type, vmctx)
+                });
+        }
+        Ok(())
+    }
+
     fn before_translate_operator(
         &mut self,
         op: &Operator,
@@ -2606,7 +2639,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         if self.tunables.consume_fuel && state.reachable() {
             self.fuel_function_exit(builder);
         }
-        Ok(())
+        gc::gc_compiler(self).finish_function(self, builder)
     }
 
     fn relaxed_simd_deterministic(&self) -> bool {
@@ -195,4 +195,18 @@ pub trait GcCompiler {
         new_val: ir::Value,
         flags: ir::MemFlags,
     ) -> WasmResult<()>;
+
+    /// Perform any collector-specific processing of a function after all of
+    /// its Wasm operators have been translated.
+    ///
+    /// This is used, for example, by moving collectors to request that GC
+    /// references are included in stack maps. Does nothing by default.
+    fn finish_function(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+    ) -> WasmResult<()> {
+        let _ = (func_env, builder);
+        Ok(())
+    }
 }
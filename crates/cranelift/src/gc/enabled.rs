@@ -5,11 +5,14 @@ use cranelift_frontend::FunctionBuilder;
 use cranelift_wasm::{
     TargetEnvironment, WasmHeapTopType, WasmHeapType, WasmRefType, WasmResult, WasmValType,
 };
-use wasmtime_environ::{PtrSize, I31_DISCRIMINANT, NON_NULL_NON_I31_MASK};
+use wasmtime_environ::{Collector, PtrSize, I31_DISCRIMINANT, NON_NULL_NON_I31_MASK};
 
-/// Get the default GC compiler.
-pub fn gc_compiler(_func_env: &FuncEnvironment<'_>) -> Box<dyn GcCompiler> {
-    Box::new(DrcCompiler)
+/// Get the GC compiler for the configured collector.
+pub fn gc_compiler(func_env: &FuncEnvironment<'_>) -> Box<dyn GcCompiler> {
+    match func_env.tunables.collector {
+        Some(Collector::Copying) => Box::new(CopyingCompiler),
+        Some(Collector::DeferredReferenceCounting) | None => Box::new(DrcCompiler),
+    }
 }
 
 pub fn unbarriered_load_gc_ref(
@@ -646,3 +649,56 @@ impl GcCompiler for DrcCompiler {
         Ok(())
     }
 }
+
+/// The GC compiler for the semi-space copying collector.
+///
+/// The copying collector finds every live GC reference precisely, through the
+/// Wasm stack maps and the runtime's GC roots, and updates them in place when
+/// it moves objects, so it doesn't need any GC barriers at all.
+struct CopyingCompiler;
+
+impl GcCompiler for CopyingCompiler {
+    fn translate_read_gc_reference(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        src: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<ir::Value> {
+        unbarriered_load_gc_ref(func_env, builder, ty.heap_type, src, flags)
+    }
+
+    fn translate_write_gc_reference(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        dst: ir::Value,
+        new_val: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<()> {
+        unbarriered_store_gc_ref(func_env, builder, ty.heap_type, dst, new_val, flags)
+    }
+
+    fn finish_function(
+        &mut self,
+        _func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+    ) -> WasmResult<()> {
+        // Every value of a reference type is a GC reference, so make sure that
+        // it is spilled to the stack and included in stack maps whenever it is
+        // live across a safepoint. Uses after a safepoint are reloaded from the
+        // stack, which allows the collector to move the referenced object.
+        let gc_refs = builder
+            .func
+            .dfg
+            .values()
+            .filter(|v| builder.func.dfg.value_type(*v).is_ref())
+            .collect::<Vec<_>>();
+        for val in gc_refs {
+            builder.declare_value_needs_stack_map(val);
+        }
+        Ok(())
+    }
+}
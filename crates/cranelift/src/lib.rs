@@ -29,6 +29,7 @@ pub use compiled_function::*;
 mod builder;
 mod compiler;
 mod debug;
+mod frame_state;
 mod func_environ;
 mod gc;
 
@@ -0,0 +1,160 @@
+use crate::obj::ELF_WASMTIME_FRAME_STATE;
+use crate::prelude::*;
+use crate::FunctionFrameState;
+use object::write::{Object, StandardSegment};
+use object::{LittleEndian, SectionKind, U32Bytes};
+use std::ops::Range;
+
+/// A helper structure to build the `ELF_WASMTIME_FRAME_STATE` section of a
+/// wasmtime compilation image.
+///
+/// This structure is incrementally fed the frame state of individual compiled
+/// functions and handles all the encoding internally, allowing usage of
+/// `lookup_frame_state` with the resulting section.
+#[derive(Default)]
+pub struct FrameStateSection {
+    starts: Vec<U32Bytes<LittleEndian>>,
+    ends: Vec<U32Bytes<LittleEndian>>,
+    offsets: Vec<U32Bytes<LittleEndian>>,
+    body: Vec<u8>,
+}
+
+impl FrameStateSection {
+    /// Appends the frame state of the function at `func`, relative to the start
+    /// of the text section, into this section.
+    ///
+    /// This is required to be called in-order for increasing ranges of `func`.
+    pub fn push(&mut self, func: Range<u64>, state: &FunctionFrameState) {
+        let func_start = u32::try_from(func.start).unwrap();
+        let func_end = u32::try_from(func.end).unwrap();
+        assert!(self
+            .ends
+            .last()
+            .map_or(true, |e| e.get(LittleEndian) <= func_start));
+        if state.points.is_empty() {
+            return;
+        }
+
+        self.starts.push(U32Bytes::new(LittleEndian, func_start));
+        self.ends.push(U32Bytes::new(LittleEndian, func_end));
+        self.offsets.push(self.offset());
+
+        // NB: this matches the encoding expected by `lookup_frame_state`.
+        self.push_u32(state.slot_offset);
+        self.push_types(&state.locals);
+        self.push_u32(u32::try_from(state.points.len()).unwrap());
+        for (wasm_offset, _) in state.points.iter() {
+            self.push_u32(*wasm_offset);
+        }
+
+        // Reserve space for the offset of each point's operand stack types
+        // and then fill it in as the types are appended.
+        let stack_offsets = self.body.len();
+        self.body.resize(stack_offsets + 4 * state.points.len(), 0);
+        for (i, (_, stack)) in state.points.iter().enumerate() {
+            let offset = self.offset();
+            let pos = stack_offsets + 4 * i;
+            self.body[pos..pos + 4].copy_from_slice(object::bytes_of(&offset));
+            self.push_types(stack);
+        }
+    }
+
+    fn offset(&self) -> U32Bytes<LittleEndian> {
+        U32Bytes::new(LittleEndian, u32::try_from(self.body.len()).unwrap())
+    }
+
+    fn push_u32(&mut self, val: u32) {
+        self.body.extend_from_slice(&val.to_le_bytes());
+    }
+
+    fn push_types(&mut self, types: &[crate::FrameValueType]) {
+        self.push_u32(u32::try_from(types.len()).unwrap());
+        self.body.extend(types.iter().map(|ty| *ty as u8));
+    }
+
+    /// Finishes encoding this section into the `Object` provided.
+    pub fn append_to(self, obj: &mut Object) {
+        if self.starts.is_empty() {
+            return;
+        }
+        let section = obj.add_section(
+            obj.segment_name(StandardSegment::Data).to_vec(),
+            ELF_WASMTIME_FRAME_STATE.as_bytes().to_vec(),
+            SectionKind::ReadOnlyData,
+        );
+
+        // NB: this matches the encoding expected by `lookup_frame_state`.
+        let amt = u32::try_from(self.starts.len()).unwrap();
+        obj.append_section_data(section, &amt.to_le_bytes(), 1);
+        obj.append_section_data(section, object::bytes_of_slice(&self.starts), 1);
+        obj.append_section_data(section, object::bytes_of_slice(&self.ends), 1);
+        obj.append_section_data(section, object::bytes_of_slice(&self.offsets), 1);
+        obj.append_section_data(section, &self.body, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lookup_frame_state, FrameValueType};
+    use object::read::{Object as _, ObjectSection};
+
+    #[test]
+    fn roundtrip() {
+        let mut section = FrameStateSection::default();
+        section.push(
+            0..10,
+            &FunctionFrameState {
+                slot_offset: 32,
+                locals: vec![FrameValueType::I32, FrameValueType::Missing],
+                points: vec![
+                    (100, vec![]),
+                    (104, vec![FrameValueType::F64, FrameValueType::I64]),
+                ],
+            },
+        );
+        section.push(10..20, &FunctionFrameState::default());
+        section.push(
+            20..30,
+            &FunctionFrameState {
+                slot_offset: 64,
+                locals: vec![],
+                points: vec![(200, vec![FrameValueType::F32])],
+            },
+        );
+
+        let mut obj = Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        section.append_to(&mut obj);
+        let bytes = obj.write().unwrap();
+        let file = object::File::parse(&bytes[..]).unwrap();
+        let data = file
+            .section_by_name(ELF_WASMTIME_FRAME_STATE)
+            .unwrap()
+            .data()
+            .unwrap();
+
+        let state = lookup_frame_state(data, 5, 104).unwrap();
+        assert_eq!(state.slot_offset(), 32);
+        assert_eq!(
+            state.locals().collect::<Vec<_>>(),
+            [FrameValueType::I32, FrameValueType::Missing]
+        );
+        assert_eq!(
+            state.stack().collect::<Vec<_>>(),
+            [FrameValueType::F64, FrameValueType::I64]
+        );
+        assert_eq!(lookup_frame_state(data, 0, 100).unwrap().stack().len(), 0);
+        assert!(lookup_frame_state(data, 5, 102).is_none());
+        assert!(lookup_frame_state(data, 15, 100).is_none());
+
+        let state = lookup_frame_state(data, 29, 200).unwrap();
+        assert_eq!(state.slot_offset(), 64);
+        assert_eq!(state.locals().len(), 0);
+        assert_eq!(state.stack().collect::<Vec<_>>(), [FrameValueType::F32]);
+        assert!(lookup_frame_state(data, 30, 200).is_none());
+    }
+}
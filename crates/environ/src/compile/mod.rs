@@ -17,12 +17,14 @@ use std::path;
 use std::sync::Arc;
 
 mod address_map;
+mod frame_state;
 mod module_artifacts;
 mod module_environ;
 mod module_types;
 mod trap_encoding;
 
 pub use self::address_map::*;
+pub use self::frame_state::*;
 pub use self::module_artifacts::*;
 pub use self::module_environ::*;
 pub use self::module_types::*;
@@ -0,0 +1,171 @@
+//! Support for recovering the values of Wasm locals and operand stacks from
+//! native stack frames.
+//!
+//! When `Tunables::generate_frame_state` is enabled compiled functions reserve
+//! a "frame state slot" in their stack frame. Before each Wasm instruction that
+//! may trap or call another function the compiled code stores the current
+//! `VMContext` along with the values of all locals and of the operand stack
+//! into this slot. The `ELF_WASMTIME_FRAME_STATE` section then describes, for
+//! each such instruction, where the slot lives and what types of values it
+//! holds so the runtime can read them back out, for example when creating a
+//! core dump after a trap.
+
+use crate::prelude::*;
+use object::{Bytes, LittleEndian, U32Bytes};
+
+/// The type of a value recorded in a frame state slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameValueType {
+    /// The value was not recorded, for example because it's a reference or a
+    /// `v128`.
+    Missing = 0,
+    /// A Wasm `i32`.
+    I32 = 0x7f,
+    /// A Wasm `i64`.
+    I64 = 0x7e,
+    /// A Wasm `f32`.
+    F32 = 0x7d,
+    /// A Wasm `f64`.
+    F64 = 0x7c,
+}
+
+impl FrameValueType {
+    /// Converts a byte back into a `FrameValueType`, treating unknown bytes as
+    /// `Missing`.
+    pub fn from_u8(byte: u8) -> FrameValueType {
+        match byte {
+            0x7f => FrameValueType::I32,
+            0x7e => FrameValueType::I64,
+            0x7d => FrameValueType::F32,
+            0x7c => FrameValueType::F64,
+            _ => FrameValueType::Missing,
+        }
+    }
+}
+
+/// Offset within a frame state slot of the `VMContext` of the instance the
+/// frame belongs to.
+pub const FRAME_STATE_VMCTX_OFFSET: u32 = 0;
+
+/// Returns the offset within a frame state slot of the `index`th recorded
+/// value, where locals come first followed by the operand stack from bottom to
+/// top.
+///
+/// Every value occupies 8 bytes regardless of its type and is stored in the
+/// target's native endianness at the start of that space.
+pub fn frame_state_value_offset(index: u32) -> u32 {
+    8 + 8 * index
+}
+
+/// Frame state information for a function, as produced by a compiler.
+#[derive(Debug, Default, Clone)]
+pub struct FunctionFrameState {
+    /// The number of bytes below the frame pointer at which the function's
+    /// frame state slot starts.
+    pub slot_offset: u32,
+
+    /// The types of the function's locals, including parameters.
+    pub locals: Vec<FrameValueType>,
+
+    /// The Wasm instructions, as offsets within the original Wasm module, for
+    /// which the frame state slot is populated along with the types on the
+    /// operand stack at each instruction.
+    ///
+    /// This is sorted by offset.
+    pub points: Vec<(u32, Vec<FrameValueType>)>,
+}
+
+/// Frame state recorded for a single Wasm instruction, as read back from the
+/// `ELF_WASMTIME_FRAME_STATE` section.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameState<'a> {
+    slot_offset: u32,
+    locals: &'a [u8],
+    stack: &'a [u8],
+}
+
+impl<'a> FrameState<'a> {
+    /// The number of bytes below the frame pointer at which the frame state
+    /// slot starts.
+    pub fn slot_offset(&self) -> u32 {
+        self.slot_offset
+    }
+
+    /// The types of the locals recorded in the slot.
+    pub fn locals(&self) -> impl ExactSizeIterator<Item = FrameValueType> + 'a {
+        self.locals.iter().map(|b| FrameValueType::from_u8(*b))
+    }
+
+    /// The types of the operand stack values recorded in the slot, from the
+    /// bottom of the stack to the top.
+    pub fn stack(&self) -> impl ExactSizeIterator<Item = FrameValueType> + 'a {
+        self.stack.iter().map(|b| FrameValueType::from_u8(*b))
+    }
+}
+
+/// Decodes the provided frame state section and attempts to find the frame
+/// state recorded for the Wasm instruction at `wasm_offset` within the function
+/// containing the native `text_offset`.
+///
+/// The `section` provided is expected to have been built by
+/// `FrameStateSection`. The `text_offset` is relative to the start of the text
+/// section and `wasm_offset` is an offset within the original Wasm module, such
+/// as one found with `lookup_file_pos`.
+pub fn lookup_frame_state(
+    section: &[u8],
+    text_offset: usize,
+    wasm_offset: u32,
+) -> Option<FrameState<'_>> {
+    // NB: this matches the encoding written by `FrameStateSection::append_to`.
+    let mut section = Bytes(section);
+    let count = read_u32(&mut section)?;
+    let starts = read_u32s(&mut section, count)?;
+    let ends = read_u32s(&mut section, count)?;
+    let offsets = read_u32s(&mut section, count)?;
+    let body = section;
+
+    let text_offset = u32::try_from(text_offset).ok()?;
+    let index = match starts.binary_search_by_key(&text_offset, |v| v.get(LittleEndian)) {
+        Ok(i) => i,
+        Err(0) => return None,
+        Err(i) => i - 1,
+    };
+    if text_offset >= ends.get(index)?.get(LittleEndian) {
+        return None;
+    }
+
+    let offset = usize::try_from(offsets.get(index)?.get(LittleEndian)).ok()?;
+    let mut func = Bytes(body.0.get(offset..)?);
+    let slot_offset = u32::try_from(read_u32(&mut func)?).ok()?;
+    let num_locals = read_u32(&mut func)?;
+    let locals = func.read_bytes(num_locals).ok()?.0;
+    let num_points = read_u32(&mut func)?;
+    let points = read_u32s(&mut func, num_points)?;
+    let stacks = read_u32s(&mut func, num_points)?;
+
+    let index = points
+        .binary_search_by_key(&wasm_offset, |v| v.get(LittleEndian))
+        .ok()?;
+    let stack_offset = usize::try_from(stacks.get(index)?.get(LittleEndian)).ok()?;
+    let mut stack = Bytes(body.0.get(stack_offset..)?);
+    let len = read_u32(&mut stack)?;
+    let stack = stack.read_bytes(len).ok()?.0;
+
+    Some(FrameState {
+        slot_offset,
+        locals,
+        stack,
+    })
+}
+
+fn read_u32(bytes: &mut Bytes<'_>) -> Option<usize> {
+    let val = bytes.read::<U32Bytes<LittleEndian>>().ok()?;
+    usize::try_from(val.get(LittleEndian)).ok()
+}
+
+fn read_u32s<'a>(bytes: &mut Bytes<'a>, count: usize) -> Option<&'a [U32Bytes<LittleEndian>]> {
+    let (ret, rest) = object::slice_from_bytes::<U32Bytes<LittleEndian>>(bytes.0, count).ok()?;
+    *bytes = Bytes(rest);
+    Some(ret)
+}
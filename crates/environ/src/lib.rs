@@ -17,6 +17,7 @@ pub use wasmtime_types::prelude;
 mod address_map;
 mod builtin;
 mod demangling;
+mod frame_state;
 mod gc;
 mod module;
 mod module_artifacts;
@@ -32,6 +33,7 @@ mod vmoffsets;
 pub use crate::address_map::*;
 pub use crate::builtin::*;
 pub use crate::demangling::*;
+pub use crate::frame_state::*;
 pub use crate::gc::*;
 pub use crate::module::*;
 pub use crate::module_artifacts::*;
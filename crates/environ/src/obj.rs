@@ -71,6 +71,31 @@ pub const ELF_WASMTIME_ADDRMAP: &str = ".wasmtime.addrmap";
 /// to the 32-bit encodings for offsets this doesn't support images >=4gb.
 pub const ELF_WASMTIME_TRAPS: &str = ".wasmtime.traps";
 
+/// A custom binary-encoded section of wasmtime compilation artifacts which
+/// describes the "frame state slots" of compiled functions, used to recover
+/// the values of Wasm locals and operand stacks from native stack frames.
+///
+/// This section is only present when `Tunables::generate_frame_state` is
+/// enabled. Its encoding is custom to Wasmtime:
+///
+/// * First the section has a 32-bit little endian integer indicating how many
+///   functions are described by the section.
+/// * Next are three arrays, each of the same length as read before, of 32-bit
+///   little-endian integers. These are the start and end offsets of each
+///   function within the text section and the offset of each function's
+///   description within the remainder of the section.
+/// * A function's description starts with the offset below the frame pointer
+///   of its frame state slot, followed by the count and then bytes of the
+///   types of its locals. After that is a count of instructions at which the
+///   slot is populated, an array of their offsets within the original wasm
+///   module, and an array of offsets of each instruction's operand stack
+///   types. The operand stack types are again a count followed by bytes.
+///
+/// This section is decoded by `lookup_frame_state`. Note that at this time
+/// this section has an alignment of 1 and due to the 32-bit encodings doesn't
+/// support images >=4gb.
+pub const ELF_WASMTIME_FRAME_STATE: &str = ".wasmtime.framestate";
+
 /// A custom section which consists of just 1 byte which is either 0 or 1 as to
 /// whether BTI is enabled.
 pub const ELF_WASM_BTI: &str = ".wasmtime.bti";
@@ -52,6 +52,11 @@ pub struct Tunables {
     /// offsets in the original file is generated.
     pub generate_address_map: bool,
 
+    /// Indicates whether compiled code records the values of Wasm locals and
+    /// of the operand stack in its stack frames, along with the metadata needed
+    /// to find them, so that they can be recovered after a trap.
+    pub generate_frame_state: bool,
+
     /// Flag for the component module whether adapter modules have debug
     /// assertions baked into them.
     pub debug_adapter_modules: bool,
@@ -65,6 +70,28 @@ pub struct Tunables {
 
     /// Whether or not Wasm functions target the winch abi.
     pub winch_callable: bool,
+
+    /// The garbage collector that compiled code is emitting GC barriers for,
+    /// if any.
+    pub collector: Option<Collector>,
+}
+
+/// The garbage collector implementation to use.
+#[derive(Clone, Copy, Hash, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum Collector {
+    /// The deferred reference-counting collector.
+    DeferredReferenceCounting,
+    /// The semi-space copying collector.
+    Copying,
+}
+
+impl core::fmt::Display for Collector {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Collector::DeferredReferenceCounting => write!(f, "deferred reference-counting"),
+            Collector::Copying => write!(f, "copying"),
+        }
+    }
 }
 
 impl Tunables {
@@ -113,10 +140,12 @@ impl Tunables {
             guard_before_linear_memory: true,
             table_lazy_init: true,
             generate_address_map: true,
+            generate_frame_state: false,
             debug_adapter_modules: false,
             relaxed_simd_deterministic: false,
             tail_callable: false,
             winch_callable: false,
+            collector: None,
         }
     }
 
@@ -22,6 +22,122 @@ pub type HyperIncomingBody = BoxBody<Bytes, types::ErrorCode>;
 /// Common type for outgoing bodies.
 pub type HyperOutgoingBody = BoxBody<Bytes, types::ErrorCode>;
 
+/// Configuration for how request and response bodies are buffered and limited.
+///
+/// The configuration of a store is set with
+/// [`WasiHttpCtx::set_body_config`](crate::WasiHttpCtx::set_body_config) and
+/// applies both to the bodies of outgoing requests and their responses and to
+/// the bodies of incoming requests and the responses sent for them.
+#[derive(Clone, Debug)]
+pub struct BodyConfig {
+    pub(crate) chunk_size: usize,
+    pub(crate) buffered_chunks: usize,
+    pub(crate) max_request_body_size: Option<u64>,
+    pub(crate) max_response_body_size: Option<u64>,
+    pub(crate) read_idle_timeout: Duration,
+    pub(crate) write_idle_timeout: Option<Duration>,
+}
+
+impl BodyConfig {
+    /// Create the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The largest number of bytes that a guest may write to an outgoing body
+    /// at once, as reported by `check-write`.
+    ///
+    /// Defaults to 1 MiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunk_size(&mut self, size: usize) -> &mut Self {
+        assert!(size > 0, "body chunk size must be nonzero");
+        self.chunk_size = size;
+        self
+    }
+
+    /// The number of chunks written to an outgoing body which may be buffered
+    /// before writes block until they've been sent.
+    ///
+    /// Defaults to 2.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunks` is zero.
+    pub fn buffered_chunks(&mut self, chunks: usize) -> &mut Self {
+        assert!(
+            chunks > 0,
+            "the number of buffered body chunks must be nonzero"
+        );
+        self.buffered_chunks = chunks;
+        self
+    }
+
+    /// The maximum size, in bytes, of request bodies.
+    ///
+    /// Writing more than this to the body of an outgoing request, or reading
+    /// more than this from the body of an incoming request, fails with
+    /// `HTTP-request-body-size`. Defaults to `None`, which means there's no
+    /// limit.
+    pub fn max_request_body_size(&mut self, max: Option<u64>) -> &mut Self {
+        self.max_request_body_size = max;
+        self
+    }
+
+    /// The maximum size, in bytes, of response bodies.
+    ///
+    /// Writing more than this to the body of an outgoing response, or reading
+    /// more than this from the body of an incoming response, fails with
+    /// `HTTP-response-body-size`. Defaults to `None`, which means there's no
+    /// limit.
+    pub fn max_response_body_size(&mut self, max: Option<u64>) -> &mut Self {
+        self.max_response_body_size = max;
+        self
+    }
+
+    /// How long to wait for the next chunk of an incoming body before failing
+    /// with `connection-read-timeout`.
+    ///
+    /// This applies to the bodies of incoming requests, and to the bodies of
+    /// responses to outgoing requests whose options don't set a
+    /// `between-bytes-timeout`. Defaults to 600 seconds.
+    pub fn read_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.read_idle_timeout = timeout;
+        self
+    }
+
+    /// How long to wait for the guest to write the next chunk of an outgoing
+    /// body before failing the body with `connection-write-timeout`.
+    ///
+    /// Defaults to `None`, which means the guest may take arbitrarily long.
+    pub fn write_idle_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.write_idle_timeout = timeout;
+        self
+    }
+
+    fn max_body_size(&self, context: StreamContext) -> Option<u64> {
+        match context {
+            StreamContext::Request => self.max_request_body_size,
+            StreamContext::Response => self.max_response_body_size,
+        }
+    }
+}
+
+impl Default for BodyConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024 * 1024,
+            buffered_chunks: 2,
+            max_request_body_size: None,
+            max_response_body_size: None,
+            read_idle_timeout: Duration::from_secs(600),
+            write_idle_timeout: None,
+        }
+    }
+}
+
 /// The concrete type behind a `was:http/types/incoming-body` resource.
 #[derive(Debug)]
 pub struct HostIncomingBody {
@@ -42,6 +158,23 @@ impl HostIncomingBody {
         }
     }
 
+    /// Fail reading this body with the [`StreamContext`]'s body size error once
+    /// more than `max` bytes have been received.
+    ///
+    /// Returns an error if the stream of this body has already been taken, as
+    /// the limit can't be applied to it anymore.
+    pub fn limit_size(&mut self, context: StreamContext, max: u64) -> anyhow::Result<()> {
+        match &mut self.body {
+            IncomingBodyState::Start(body) => {
+                body.limit = Some((context, max));
+                Ok(())
+            }
+            IncomingBodyState::InBodyStream(_) => {
+                Err(anyhow!("size limit set after the body stream was taken"))
+            }
+        }
+    }
+
     /// Retain a worker task that needs to be kept alive while this body is being read.
     pub fn retain_worker(&mut self, worker: AbortOnDropJoinHandle<()>) {
         assert!(self.worker.is_none());
@@ -98,6 +231,11 @@ struct BodyWithTimeout {
     /// Maximal duration between when a frame is first requested and when it's
     /// allowed to arrive.
     between_bytes_timeout: Duration,
+    /// The maximum number of data bytes which may be received, and whether
+    /// this is a request or response body for reporting errors.
+    limit: Option<(StreamContext, u64)>,
+    /// The number of data bytes received so far.
+    received: u64,
 }
 
 impl BodyWithTimeout {
@@ -105,6 +243,8 @@ impl BodyWithTimeout {
         BodyWithTimeout {
             inner,
             between_bytes_timeout,
+            limit: None,
+            received: 0,
             reset_sleep: true,
             timeout: Box::pin(wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
                 tokio::time::sleep(Duration::new(0, 0))
@@ -143,6 +283,16 @@ impl Body for BodyWithTimeout {
         // arrives then the sleep timer will be reset on the next frame.
         let result = Pin::new(&mut me.inner).poll_frame(cx);
         me.reset_sleep = result.is_ready();
+
+        // Count the data received and fail once it exceeds the limit, if any.
+        if let Poll::Ready(Some(Ok(frame))) = &result {
+            if let (Some(data), Some((context, max))) = (frame.data_ref(), me.limit) {
+                me.received += data.len() as u64;
+                if me.received > max {
+                    return Poll::Ready(Some(Err(context.as_body_size_error(me.received))));
+                }
+            }
+        }
         result
     }
 }
@@ -387,14 +537,18 @@ impl Subscribe for HostFutureTrailers {
 
 #[derive(Debug, Clone)]
 struct WrittenState {
-    expected: u64,
+    /// The size of the body given by its `Content-Length` header, if any.
+    expected: Option<u64>,
+    /// The maximum size of the body allowed by the [`BodyConfig`], if any.
+    max: Option<u64>,
     written: Arc<std::sync::atomic::AtomicU64>,
 }
 
 impl WrittenState {
-    fn new(expected_size: u64) -> Self {
+    fn new(expected: Option<u64>, max: Option<u64>) -> Self {
         Self {
-            expected: expected_size,
+            expected,
+            max,
             written: Arc::new(std::sync::atomic::AtomicU64::new(0)),
         }
     }
@@ -411,7 +565,14 @@ impl WrittenState {
         let old = self
             .written
             .fetch_add(len, std::sync::atomic::Ordering::Relaxed);
-        old + len <= self.expected
+        self.expected.map_or(true, |expected| old + len <= expected)
+    }
+
+    /// Returns whether writing `len` more bytes would exceed the maximum size
+    /// of the body.
+    fn exceeds_max(&self, len: usize) -> bool {
+        self.max
+            .map_or(false, |max| self.written() + len as u64 > max)
     }
 }
 
@@ -420,19 +581,34 @@ pub struct HostOutgoingBody {
     /// The output stream that the body is written to.
     body_output_stream: Option<Box<dyn HostOutputStream>>,
     context: StreamContext,
-    written: Option<WrittenState>,
+    written: WrittenState,
     finish_sender: Option<tokio::sync::oneshot::Sender<FinishMessage>>,
 }
 
 impl HostOutgoingBody {
-    /// Create a new `HostOutgoingBody`
+    /// Create a new `HostOutgoingBody` of `size` bytes, if known, which is
+    /// buffered and limited according to the default [`BodyConfig`].
     pub fn new(context: StreamContext, size: Option<u64>) -> (Self, HyperOutgoingBody) {
-        let written = size.map(WrittenState::new);
+        Self::with_config(context, size, &BodyConfig::default())
+    }
+
+    /// Create a new `HostOutgoingBody` of `size` bytes, if known, which is
+    /// buffered and limited according to `config`.
+    pub fn with_config(
+        context: StreamContext,
+        size: Option<u64>,
+        config: &BodyConfig,
+    ) -> (Self, HyperOutgoingBody) {
+        let written = WrittenState::new(size, config.max_body_size(context));
 
         use tokio::sync::oneshot::error::RecvError;
         struct BodyImpl {
-            body_receiver: mpsc::Receiver<Bytes>,
+            body_receiver: mpsc::Receiver<Result<Bytes, types::ErrorCode>>,
             finish_receiver: Option<oneshot::Receiver<FinishMessage>>,
+            /// How long to wait for the guest to write, along with the timer
+            /// and whether it needs to be reset, if there's a limit.
+            write_timeout: Option<(Duration, Pin<Box<tokio::time::Sleep>>)>,
+            reset_sleep: bool,
         }
         impl Body for BodyImpl {
             type Data = Bytes;
@@ -441,9 +617,31 @@ impl HostOutgoingBody {
                 mut self: Pin<&mut Self>,
                 cx: &mut Context<'_>,
             ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+                let me = &mut *self;
+                if let Some((timeout, sleep)) = &mut me.write_timeout {
+                    if me.reset_sleep {
+                        sleep.as_mut().reset(tokio::time::Instant::now() + *timeout);
+                        me.reset_sleep = false;
+                    }
+                    if let Poll::Ready(()) = sleep.as_mut().poll(cx) {
+                        return Poll::Ready(Some(Err(types::ErrorCode::ConnectionWriteTimeout)));
+                    }
+                }
+
+                let result = self.as_mut().poll_body(cx);
+                self.reset_sleep = result.is_ready();
+                result
+            }
+        }
+        impl BodyImpl {
+            fn poll_body(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Option<Result<Frame<Bytes>, types::ErrorCode>>> {
                 match self.as_mut().body_receiver.poll_recv(cx) {
                     Poll::Pending => Poll::Pending,
-                    Poll::Ready(Some(frame)) => Poll::Ready(Some(Ok(Frame::data(frame)))),
+                    Poll::Ready(Some(Ok(frame))) => Poll::Ready(Some(Ok(Frame::data(frame)))),
+                    Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
 
                     // This means that the `body_sender` end of the channel has been dropped.
                     Poll::Ready(None) => {
@@ -472,17 +670,24 @@ impl HostOutgoingBody {
             }
         }
 
-        let (body_sender, body_receiver) = mpsc::channel(2);
+        let (body_sender, body_receiver) = mpsc::channel(config.buffered_chunks);
         let (finish_sender, finish_receiver) = oneshot::channel();
+        let write_timeout = config.write_idle_timeout.map(|timeout| {
+            let sleep = wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
+                tokio::time::sleep(Duration::new(0, 0))
+            });
+            (timeout, Box::pin(sleep))
+        });
         let body_impl = BodyImpl {
             body_receiver,
             finish_receiver: Some(finish_receiver),
+            write_timeout,
+            reset_sleep: true,
         }
         .boxed();
 
-        // TODO: this capacity constant is arbitrary, and should be configurable
         let output_stream =
-            BodyWriteStream::new(context, 1024 * 1024, body_sender, written.clone());
+            BodyWriteStream::new(context, config.chunk_size, body_sender, written.clone());
 
         (
             Self {
@@ -511,12 +716,15 @@ impl HostOutgoingBody {
             .take()
             .expect("outgoing-body trailer_sender consumed by a non-owning function");
 
-        if let Some(w) = self.written {
-            let written = w.written();
-            if written != w.expected {
-                let _ = sender.send(FinishMessage::Abort);
-                return Err(self.context.as_body_size_error(written));
-            }
+        let written = self.written.written();
+        if self
+            .written
+            .expected
+            .map_or(false, |expected| written != expected)
+            || self.written.max.map_or(false, |max| written > max)
+        {
+            let _ = sender.send(FinishMessage::Abort);
+            return Err(self.context.as_body_size_error(written));
         }
 
         let message = if let Some(ts) = trailers {
@@ -577,9 +785,11 @@ impl StreamContext {
 #[derive(Debug)]
 struct BodyWriteStream {
     context: StreamContext,
-    writer: mpsc::Sender<Bytes>,
+    writer: mpsc::Sender<Result<Bytes, types::ErrorCode>>,
     write_budget: usize,
-    written: Option<WrittenState>,
+    written: WrittenState,
+    /// Whether the body has been failed because it grew too large.
+    failed: bool,
 }
 
 impl BodyWriteStream {
@@ -587,8 +797,8 @@ impl BodyWriteStream {
     fn new(
         context: StreamContext,
         write_budget: usize,
-        writer: mpsc::Sender<Bytes>,
-        written: Option<WrittenState>,
+        writer: mpsc::Sender<Result<Bytes, types::ErrorCode>>,
+        written: WrittenState,
     ) -> Self {
         // at least one capacity is required to send a message
         assert!(writer.max_capacity() >= 1);
@@ -597,6 +807,7 @@ impl BodyWriteStream {
             writer,
             write_budget,
             written,
+            failed: false,
         }
     }
 }
@@ -604,18 +815,33 @@ impl BodyWriteStream {
 #[async_trait::async_trait]
 impl HostOutputStream for BodyWriteStream {
     fn write(&mut self, bytes: Bytes) -> Result<(), StreamError> {
+        if self.failed {
+            return Err(StreamError::Closed);
+        }
+
         let len = bytes.len();
-        match self.writer.try_send(bytes) {
+
+        // If this write would make the body too large then fail the body
+        // instead, both here and for hyper, which is sending it. The call to
+        // `check_write` guarantees there's capacity to send the error. The
+        // bytes are still counted so that `finish` fails too.
+        if self.written.exceeds_max(len) {
+            self.written.update(len);
+            let error = self.context.as_body_size_error(self.written.written());
+            self.failed = true;
+            let _ = self.writer.try_send(Err(error.clone()));
+            return Err(StreamError::LastOperationFailed(anyhow!(error)));
+        }
+
+        match self.writer.try_send(Ok(bytes)) {
             // If the message was sent then it's queued up now in hyper to get
             // received.
             Ok(()) => {
-                if let Some(written) = self.written.as_ref() {
-                    if !written.update(len) {
-                        let total = written.written();
-                        return Err(StreamError::LastOperationFailed(anyhow!(self
-                            .context
-                            .as_body_size_error(total))));
-                    }
+                if !self.written.update(len) {
+                    let total = self.written.written();
+                    return Err(StreamError::LastOperationFailed(anyhow!(self
+                        .context
+                        .as_body_size_error(total))));
                 }
 
                 Ok(())
@@ -636,7 +862,7 @@ impl HostOutputStream for BodyWriteStream {
     fn flush(&mut self) -> Result<(), StreamError> {
         // Flushing doesn't happen in this body stream since we're currently
         // only tracking sending bytes over to hyper.
-        if self.writer.is_closed() {
+        if self.failed || self.writer.is_closed() {
             Err(StreamError::Closed)
         } else {
             Ok(())
@@ -644,7 +870,7 @@ impl HostOutputStream for BodyWriteStream {
     }
 
     fn check_write(&mut self) -> Result<usize, StreamError> {
-        if self.writer.is_closed() {
+        if self.failed || self.writer.is_closed() {
             Err(StreamError::Closed)
         } else if self.writer.capacity() == 0 {
             // If there is no more capacity in this sender channel then don't
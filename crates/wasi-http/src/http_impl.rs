@@ -24,6 +24,7 @@ where
         request_id: Resource<HostOutgoingRequest>,
         options: Option<Resource<types::RequestOptions>>,
     ) -> crate::HttpResult<Resource<HostFutureIncomingResponse>> {
+        let read_idle_timeout = self.ctx().body_config().read_idle_timeout;
         let opts = options.and_then(|opts| self.table().get(&opts).ok());
 
         let connect_timeout = opts
@@ -36,7 +37,7 @@ where
 
         let between_bytes_timeout = opts
             .and_then(|opts| opts.between_bytes_timeout)
-            .unwrap_or(std::time::Duration::from_secs(600));
+            .unwrap_or(read_idle_timeout);
 
         let req = self.table().delete(request_id)?;
         let mut builder = hyper::Request::builder();
@@ -89,10 +90,16 @@ where
                 .boxed()
         });
 
-        let request = builder
+        let mut request = builder
             .body(body)
             .map_err(|err| internal_error(err.to_string()))?;
 
+        if let Some(policy) = self.ctx().outgoing_policy() {
+            request = policy.enforce(request)?;
+        }
+
+        let connection_pool = self.ctx().connection_pool().cloned();
+        let tls_config = self.ctx().tls_config().cloned();
         let future = self.send_request(
             request,
             OutgoingRequestConfig {
@@ -100,6 +107,8 @@ where
                 connect_timeout,
                 first_byte_timeout,
                 between_bytes_timeout,
+                connection_pool,
+                tls_config,
             },
         )?;
 
@@ -1,6 +1,7 @@
 //! I/O utility for bridging between `tokio::io` and `hyper::rt`.
 
-use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper::rt::{Executor, Read, ReadBufCursor, Write};
+use std::future::Future;
 use std::io::Error;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -53,3 +54,18 @@ impl<T: AsyncWrite + Unpin> Write for TokioIo<T> {
         Pin::new(&mut self.inner).poll_shutdown(cx)
     }
 }
+
+/// An [`hyper::rt::Executor`] which spawns tasks onto the current tokio
+/// runtime, as needed to drive HTTP/2 connections.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioExecutor;
+
+impl<F> Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        tokio::task::spawn(fut);
+    }
+}
@@ -223,6 +223,9 @@ mod types_impl;
 
 pub mod body;
 pub mod io;
+pub mod policy;
+pub mod pool;
+pub mod tls;
 pub mod types;
 
 pub mod bindings;
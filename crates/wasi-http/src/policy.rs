@@ -0,0 +1,484 @@
+//! Policies restricting the outgoing requests that a guest may make.
+//!
+//! By default a guest with access to `wasi:http/outgoing-handler` may send any
+//! request to any authority. An [`OutgoingRequestPolicy`] declares which
+//! requests are allowed instead: which authorities, ports, schemes, and
+//! methods may be used, how large request bodies may be, and how many requests
+//! may be sent per second. Requests which aren't allowed fail with
+//! [`ErrorCode::HttpRequestDenied`], or [`ErrorCode::HttpRequestBodySize`] for
+//! bodies which are too large, without being sent.
+//!
+//! A policy is enforced for the requests of a store once it has been set with
+//! [`WasiHttpCtx::set_outgoing_policy`](crate::WasiHttpCtx::set_outgoing_policy):
+//!
+//! ```
+//! use wasmtime_wasi_http::policy::OutgoingRequestPolicy;
+//! use wasmtime_wasi_http::WasiHttpCtx;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut policy = OutgoingRequestPolicy::new();
+//! policy
+//!     .allow_authority("*.example.com")?
+//!     .deny_authority("internal.example.com")?
+//!     .allow_scheme("https")?
+//!     .allow_method(http::Method::GET)
+//!     .max_body_size(Some(1 << 20))
+//!     .max_requests_per_second(Some(10));
+//!
+//! let mut ctx = WasiHttpCtx::new();
+//! ctx.set_outgoing_policy(policy);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bindings::http::types::ErrorCode;
+use crate::body::HyperOutgoingBody;
+use anyhow::{bail, Context as _, Result};
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::BodyExt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+/// A declarative policy for the outgoing requests of a guest.
+///
+/// Every kind of restriction is optional: while nothing has been allowed for
+/// one of authorities, ports, schemes, or methods, any value of it is allowed.
+/// Denied authorities take precedence over allowed ones.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Clone, Debug, Default)]
+pub struct OutgoingRequestPolicy {
+    allowed_authorities: Vec<AuthorityPattern>,
+    denied_authorities: Vec<AuthorityPattern>,
+    allowed_ports: Vec<u16>,
+    allowed_schemes: Vec<http::uri::Scheme>,
+    allowed_methods: Vec<http::Method>,
+    max_body_size: Option<u64>,
+    rate_limit: Option<Arc<RateLimit>>,
+}
+
+impl OutgoingRequestPolicy {
+    /// Create a policy which allows every request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow requests to authorities matching `pattern`.
+    ///
+    /// A pattern is a host, optionally followed by `:port`. The host may be
+    /// `*`, which matches any host, or start with `*.`, which matches any
+    /// subdomain of the rest of the pattern. Without a port, the pattern
+    /// matches any port. For example `example.com`, `*.example.com:443`, and
+    /// `*:8080` are all valid patterns.
+    pub fn allow_authority(&mut self, pattern: &str) -> Result<&mut Self> {
+        self.allowed_authorities
+            .push(AuthorityPattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Deny requests to authorities matching `pattern`, even if they're also
+    /// allowed.
+    ///
+    /// Patterns are the same as for [`OutgoingRequestPolicy::allow_authority`].
+    pub fn deny_authority(&mut self, pattern: &str) -> Result<&mut Self> {
+        self.denied_authorities
+            .push(AuthorityPattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Allow requests to `port`.
+    ///
+    /// Requests without an explicit port use the default port of their
+    /// scheme.
+    pub fn allow_port(&mut self, port: u16) -> &mut Self {
+        self.allowed_ports.push(port);
+        self
+    }
+
+    /// Allow requests with `scheme`, which is either `http` or `https`.
+    pub fn allow_scheme(&mut self, scheme: &str) -> Result<&mut Self> {
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "http" => http::uri::Scheme::HTTP,
+            "https" => http::uri::Scheme::HTTPS,
+            _ => bail!("unsupported scheme `{scheme}`, expected `http` or `https`"),
+        };
+        self.allowed_schemes.push(scheme);
+        Ok(self)
+    }
+
+    /// Allow requests with `method`.
+    pub fn allow_method(&mut self, method: http::Method) -> &mut Self {
+        self.allowed_methods.push(method);
+        self
+    }
+
+    /// The maximum size, in bytes, of request bodies.
+    ///
+    /// Requests whose `content-length` exceeds this are denied, and the body
+    /// of a request which is streamed fails once it exceeds this. Defaults to
+    /// `None`, which means there's no limit.
+    pub fn max_body_size(&mut self, max: Option<u64>) -> &mut Self {
+        self.max_body_size = max;
+        self
+    }
+
+    /// The maximum number of requests that may be sent per second.
+    ///
+    /// Bursts of up to this many requests are allowed, after which requests
+    /// are denied until enough time has passed. The limit is shared between
+    /// clones of this policy, so a policy that has been cloned into many
+    /// stores limits their requests in total. Defaults to `None`, which means
+    /// there's no limit.
+    pub fn max_requests_per_second(&mut self, max: Option<u32>) -> &mut Self {
+        self.rate_limit = max.map(|max| Arc::new(RateLimit::new(max)));
+        self
+    }
+
+    /// Check whether `request` is allowed by this policy.
+    ///
+    /// Allowed requests count towards the
+    /// [rate limit](OutgoingRequestPolicy::max_requests_per_second), if any.
+    /// This doesn't limit the size of a streamed body, see
+    /// [`OutgoingRequestPolicy::enforce`] for that.
+    pub fn check<B>(&self, request: &hyper::Request<B>) -> Result<(), ErrorCode> {
+        let uri = request.uri();
+        let scheme = uri.scheme().cloned().unwrap_or(http::uri::Scheme::HTTPS);
+        let authority = uri.authority().ok_or(ErrorCode::HttpRequestUriInvalid)?;
+        let port = authority
+            .port_u16()
+            .unwrap_or(if scheme == http::uri::Scheme::HTTP {
+                80
+            } else {
+                443
+            });
+        let host = authority.host();
+
+        let deny = |reason: &str| {
+            tracing::debug!("outgoing request to {authority} denied: {reason}");
+            Err(ErrorCode::HttpRequestDenied)
+        };
+
+        if !self.allowed_schemes.is_empty() && !self.allowed_schemes.contains(&scheme) {
+            return deny("scheme not allowed");
+        }
+        if !self.allowed_ports.is_empty() && !self.allowed_ports.contains(&port) {
+            return deny("port not allowed");
+        }
+        if self
+            .denied_authorities
+            .iter()
+            .any(|p| p.matches(host, port))
+        {
+            return deny("authority denied");
+        }
+        if !self.allowed_authorities.is_empty()
+            && !self
+                .allowed_authorities
+                .iter()
+                .any(|p| p.matches(host, port))
+        {
+            return deny("authority not allowed");
+        }
+        if !self.allowed_methods.is_empty() && !self.allowed_methods.contains(request.method()) {
+            return deny("method not allowed");
+        }
+
+        if let Some(max) = self.max_body_size {
+            let content_length = request
+                .headers()
+                .get(hyper::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if let Some(len) = content_length.filter(|len| *len > max) {
+                tracing::debug!("outgoing request to {authority} denied: body too large");
+                return Err(ErrorCode::HttpRequestBodySize(Some(len)));
+            }
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            if !rate_limit.try_acquire() {
+                return deny("rate limit exceeded");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// [Check](OutgoingRequestPolicy::check) whether `request` is allowed by
+    /// this policy, and limit the size of its body.
+    pub fn enforce(
+        &self,
+        request: hyper::Request<HyperOutgoingBody>,
+    ) -> Result<hyper::Request<HyperOutgoingBody>, ErrorCode> {
+        self.check(&request)?;
+        match self.max_body_size {
+            Some(max) => Ok(request.map(|body| {
+                LimitedBody {
+                    body,
+                    max,
+                    written: 0,
+                }
+                .boxed()
+            })),
+            None => Ok(request),
+        }
+    }
+}
+
+/// A pattern matching the authorities of requests.
+#[derive(Clone, Debug)]
+struct AuthorityPattern {
+    host: HostPattern,
+    port: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+enum HostPattern {
+    Any,
+    Exact(String),
+    /// Matches subdomains of a domain, stored with its leading `.`.
+    Subdomain(String),
+}
+
+impl AuthorityPattern {
+    fn parse(pattern: &str) -> Result<AuthorityPattern> {
+        // IPv6 addresses are written in brackets, and contain colons of their
+        // own.
+        let (host, port) = if pattern.starts_with('[') {
+            match pattern.find(']') {
+                Some(end) => match &pattern[end + 1..] {
+                    "" => (&pattern[..=end], None),
+                    rest => match rest.strip_prefix(':') {
+                        Some(port) => (&pattern[..=end], Some(port)),
+                        None => bail!("invalid authority pattern `{pattern}`"),
+                    },
+                },
+                None => bail!("invalid authority pattern `{pattern}`"),
+            }
+        } else {
+            match pattern.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (pattern, None),
+            }
+        };
+
+        let port = port
+            .map(|port| port.parse::<u16>())
+            .transpose()
+            .with_context(|| format!("invalid port in authority pattern `{pattern}`"))?;
+
+        let host = if host == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = host.strip_prefix("*.") {
+            HostPattern::Subdomain(format!(".{}", normalize_host(domain)))
+        } else if host.is_empty() || host.contains('*') {
+            bail!("invalid host in authority pattern `{pattern}`")
+        } else {
+            HostPattern::Exact(normalize_host(host))
+        };
+
+        Ok(AuthorityPattern { host, port })
+    }
+
+    fn matches(&self, host: &str, port: u16) -> bool {
+        if self.port.is_some_and(|p| p != port) {
+            return false;
+        }
+        let host = normalize_host(host);
+        match &self.host {
+            HostPattern::Any => true,
+            HostPattern::Exact(h) => *h == host,
+            HostPattern::Subdomain(suffix) => host.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+/// Normalize `host` so that different spellings of the same host compare
+/// equal.
+///
+/// Hosts are compared case-insensitively, and a single trailing `.`, which
+/// makes a domain name fully qualified, is ignored. IP addresses are written in
+/// their canonical form, including IPv4 addresses in the shorthand, octal, and
+/// hexadecimal notations that resolvers accept, such as `127.1` or
+/// `0x7f.0.0.1`, and IPv4-mapped IPv6 addresses.
+fn normalize_host(host: &str) -> String {
+    let host = host.to_ascii_lowercase();
+    let host = host.strip_suffix('.').unwrap_or(&host);
+    if let Some(ip) = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .and_then(|h| h.parse::<Ipv6Addr>().ok())
+    {
+        return match ip.to_ipv4_mapped() {
+            Some(ip) => ip.to_string(),
+            None => format!("[{ip}]"),
+        };
+    }
+    match parse_ipv4(host) {
+        Some(ip) => ip.to_string(),
+        None => host.to_string(),
+    }
+}
+
+/// Parse an IPv4 address in any of the notations accepted by `inet_aton`.
+///
+/// An address has one to four parts, each of which is decimal, octal with a
+/// leading `0`, or hexadecimal with a leading `0x`. The last part fills all of
+/// the remaining bytes of the address.
+fn parse_ipv4(host: &str) -> Option<Ipv4Addr> {
+    let parts = host.split('.').collect::<Vec<_>>();
+    if parts.len() > 4 {
+        return None;
+    }
+    let mut addr = 0u32;
+    for (i, part) in parts.iter().enumerate() {
+        let (digits, radix) = if let Some(hex) = part.strip_prefix("0x") {
+            (hex, 16)
+        } else if part.len() > 1 && part.starts_with('0') {
+            (&part[1..], 8)
+        } else if !part.is_empty() {
+            (*part, 10)
+        } else {
+            return None;
+        };
+        if !digits.chars().all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        let value = if digits.is_empty() {
+            0
+        } else {
+            u32::from_str_radix(digits, radix).ok()?
+        };
+        if i + 1 < parts.len() {
+            if value > 0xff {
+                return None;
+            }
+            addr |= value << (8 * (3 - i));
+        } else {
+            let remaining = 4 - i;
+            if remaining < 4 && value >= 1 << (8 * remaining) {
+                return None;
+            }
+            addr |= value;
+        }
+    }
+    Some(Ipv4Addr::from(addr))
+}
+
+/// A token bucket which refills at a fixed number of tokens per second.
+#[derive(Debug)]
+struct RateLimit {
+    per_second: u32,
+    state: Mutex<RateLimitState>,
+}
+
+#[derive(Debug)]
+struct RateLimitState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    fn new(per_second: u32) -> Self {
+        RateLimit {
+            per_second,
+            state: Mutex::new(RateLimitState {
+                tokens: f64::from(per_second),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let max = f64::from(self.per_second);
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * max).min(max);
+        state.updated = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A request body which fails once more than `max` bytes have been sent.
+struct LimitedBody {
+    body: HyperOutgoingBody,
+    max: u64,
+    written: u64,
+}
+
+impl Body for LimitedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.body).poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|f| f.as_ref().ok()?.data_ref()) {
+            self.written += u64::try_from(data.len()).unwrap();
+            if self.written > self.max {
+                let size = self.written;
+                return Poll::Ready(Some(Err(ErrorCode::HttpRequestBodySize(Some(size)))));
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn rate_limit_refills() {
+        let limit = RateLimit::new(2);
+        let start = limit.state.lock().unwrap().updated;
+        assert!(limit.try_acquire_at(start));
+        assert!(limit.try_acquire_at(start));
+        assert!(!limit.try_acquire_at(start));
+        assert!(!limit.try_acquire_at(start + Duration::from_millis(400)));
+        assert!(limit.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(!limit.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(limit.try_acquire_at(start + Duration::from_secs(10)));
+        assert!(limit.try_acquire_at(start + Duration::from_secs(10)));
+        assert!(!limit.try_acquire_at(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn normalize_hosts() {
+        assert_eq!(normalize_host("Example.COM."), "example.com");
+        assert_eq!(normalize_host("example.com.."), "example.com.");
+        assert_eq!(normalize_host("127.0.0.1."), "127.0.0.1");
+        assert_eq!(normalize_host("127.1"), "127.0.0.1");
+        assert_eq!(normalize_host("0x7f.0.0.1"), "127.0.0.1");
+        assert_eq!(normalize_host("0177.0.0.01"), "127.0.0.1");
+        assert_eq!(normalize_host("2130706433"), "127.0.0.1");
+        assert_eq!(normalize_host("256.0.0.1"), "256.0.0.1");
+        assert_eq!(normalize_host("[0:0::1]"), "[::1]");
+        assert_eq!(normalize_host("[::FFFF:127.0.0.1]"), "127.0.0.1");
+        assert_eq!(normalize_host("1.example"), "1.example");
+    }
+}
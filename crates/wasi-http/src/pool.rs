@@ -0,0 +1,386 @@
+//! A pool of keep-alive connections for outgoing requests.
+//!
+//! By default every outgoing request opens a new connection, including a new
+//! TLS handshake for `https` requests, which is closed once the response has
+//! been received. A [`ConnectionPool`] instead keeps connections open after
+//! their response has been read so that later requests to the same authority
+//! can reuse them. Connections which negotiated HTTP/2 are additionally shared
+//! between concurrent requests.
+//!
+//! A pool is used for the requests of a store once it has been configured with
+//! [`WasiHttpCtx::set_connection_pool`](crate::WasiHttpCtx::set_connection_pool).
+//! Pools are cheaply cloneable handles, so one pool may be shared between all
+//! of the stores of an embedding:
+//!
+//! ```
+//! use std::time::Duration;
+//! use wasmtime_wasi_http::pool::{ConnectionPool, ConnectionPoolConfig};
+//! use wasmtime_wasi_http::WasiHttpCtx;
+//!
+//! let mut config = ConnectionPoolConfig::new();
+//! config
+//!     .idle_timeout(Duration::from_secs(30))
+//!     .max_connections_per_host(Some(16));
+//! let pool = ConnectionPool::new(config);
+//!
+//! // ... then for each new store ...
+//! let mut ctx = WasiHttpCtx::new();
+//! ctx.set_connection_pool(pool.clone());
+//! ```
+
+use crate::bindings::http::types::ErrorCode;
+use crate::body::{HyperIncomingBody, HyperOutgoingBody};
+use crate::hyper_request_error;
+use crate::tls::TlsConfig;
+use crate::types::{connect, SendRequest};
+use http_body_util::BodyExt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use wasmtime_wasi::runtime::AbortOnDropJoinHandle;
+
+/// Configuration for a [`ConnectionPool`].
+#[derive(Clone, Debug)]
+pub struct ConnectionPoolConfig {
+    idle_timeout: Duration,
+    max_idle_per_host: usize,
+    max_connections_per_host: Option<usize>,
+}
+
+impl ConnectionPoolConfig {
+    /// Create the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a connection may sit unused in the pool before it's closed.
+    ///
+    /// Defaults to 90 seconds.
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// The maximum number of unused connections kept open to each authority.
+    ///
+    /// Connections which become unused while this many connections to the
+    /// same authority are already unused are closed instead of being returned
+    /// to the pool. Defaults to 32.
+    pub fn max_idle_per_host(&mut self, max: usize) -> &mut Self {
+        self.max_idle_per_host = max;
+        self
+    }
+
+    /// The maximum number of connections that may be in use for requests to
+    /// each authority at once.
+    ///
+    /// Requests made while this many requests to the same authority are in
+    /// progress wait for one of them to complete, up to the request's connect
+    /// timeout. A request is in progress until its response body has been
+    /// read or dropped. Defaults to `None`, which means there's no limit.
+    pub fn max_connections_per_host(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_connections_per_host = max;
+        self
+    }
+}
+
+impl Default for ConnectionPoolConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(90),
+            max_idle_per_host: 32,
+            max_connections_per_host: None,
+        }
+    }
+}
+
+/// Statistics about the connections of a [`ConnectionPool`].
+///
+/// Obtained via [`ConnectionPool::stats`].
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct ConnectionPoolStats {
+    /// The total number of connections that have been opened by the pool.
+    pub connections_opened: u64,
+    /// The total number of requests that were sent on a connection reused from
+    /// the pool rather than a new connection.
+    pub connections_reused: u64,
+    /// The total number of connections that were closed because they were
+    /// unused for longer than the idle timeout.
+    pub connections_expired: u64,
+    /// The number of requests currently in progress on the pool's connections.
+    pub active_connections: usize,
+    /// The number of connections currently waiting in the pool to be used.
+    ///
+    /// HTTP/2 connections, which may be used by many requests at once, stay in
+    /// the pool while they're in use.
+    pub idle_connections: usize,
+}
+
+/// A pool of connections which are kept alive and reused between outgoing
+/// requests to the same authority.
+///
+/// See the [module documentation](self) for more information.
+#[derive(Clone, Debug)]
+pub struct ConnectionPool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    config: ConnectionPoolConfig,
+    hosts: Mutex<HashMap<PoolKey, Host>>,
+    connections_opened: AtomicU64,
+    connections_reused: AtomicU64,
+    connections_expired: AtomicU64,
+    active_connections: AtomicUsize,
+}
+
+/// Connections are only shared between requests with the same scheme,
+/// authority, and TLS configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PoolKey {
+    tls_config: Option<u64>,
+    authority: String,
+}
+
+#[derive(Debug, Default)]
+struct Host {
+    idle: Vec<IdleConnection>,
+    permits: Option<Arc<Semaphore>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    sender: SendRequest,
+    _worker: Arc<AbortOnDropJoinHandle<()>>,
+}
+
+impl Connection {
+    /// Get another handle to this connection, if it can be used by many
+    /// requests at once.
+    fn multiplexed(&self) -> Option<Connection> {
+        Some(Connection {
+            sender: self.sender.multiplexed()?,
+            _worker: self._worker.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct IdleConnection {
+    conn: Connection,
+    since: Instant,
+}
+
+impl ConnectionPool {
+    /// Create a new, empty pool.
+    pub fn new(config: ConnectionPoolConfig) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                config,
+                hosts: Mutex::new(HashMap::new()),
+                connections_opened: AtomicU64::new(0),
+                connections_reused: AtomicU64::new(0),
+                connections_expired: AtomicU64::new(0),
+                active_connections: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Get statistics about this pool's connections.
+    pub fn stats(&self) -> ConnectionPoolStats {
+        let idle_connections = {
+            let hosts = self.inner.hosts.lock().unwrap();
+            hosts.values().map(|host| host.idle.len()).sum()
+        };
+        ConnectionPoolStats {
+            connections_opened: self.inner.connections_opened.load(Ordering::Relaxed),
+            connections_reused: self.inner.connections_reused.load(Ordering::Relaxed),
+            connections_expired: self.inner.connections_expired.load(Ordering::Relaxed),
+            active_connections: self.inner.active_connections.load(Ordering::Relaxed),
+            idle_connections,
+        }
+    }
+
+    /// Send `request` to `authority` over a connection from this pool, opening
+    /// a new connection if there isn't an unused one.
+    ///
+    /// An HTTP/1.1 connection is returned to the pool once the response's body
+    /// has been read, while an HTTP/2 connection is put in the pool as soon as
+    /// it's opened.
+    pub(crate) async fn send_request(
+        &self,
+        authority: String,
+        tls_config: Option<&TlsConfig>,
+        connect_timeout: Duration,
+        first_byte_timeout: Duration,
+        request: hyper::Request<HyperOutgoingBody>,
+    ) -> Result<hyper::Response<HyperIncomingBody>, ErrorCode> {
+        let key = PoolKey {
+            tls_config: tls_config.map(|c| c.id()),
+            authority,
+        };
+
+        let permit = match self.permits(&key) {
+            Some(permits) => Some(
+                timeout(connect_timeout, permits.acquire_owned())
+                    .await
+                    .map_err(|_| ErrorCode::ConnectionTimeout)?
+                    .expect("pool semaphores are never closed"),
+            ),
+            None => None,
+        };
+
+        let mut conn = match self.checkout(&key) {
+            Some(conn) => {
+                self.inner
+                    .connections_reused
+                    .fetch_add(1, Ordering::Relaxed);
+                conn
+            }
+            None => {
+                let (sender, worker) = connect(&key.authority, tls_config, connect_timeout).await?;
+                self.inner
+                    .connections_opened
+                    .fetch_add(1, Ordering::Relaxed);
+                let conn = Connection {
+                    sender,
+                    _worker: Arc::new(worker),
+                };
+                if let Some(shared) = conn.multiplexed() {
+                    self.checkin(key.clone(), shared);
+                }
+                conn
+            }
+        };
+
+        let active = ActiveConnection::new(self.clone(), permit);
+        let resp = timeout(first_byte_timeout, conn.sender.send_request(request))
+            .await
+            .map_err(|_| ErrorCode::ConnectionReadTimeout)?
+            .map_err(hyper_request_error)?;
+
+        // A multiplexed connection is already in the pool, so this request is
+        // only finished with it once the response body has been dropped.
+        if conn.multiplexed().is_some() {
+            return Ok(resp.map(|body| {
+                body.map_err(hyper_request_error)
+                    .map_frame(move |frame| {
+                        let _active = &active;
+                        frame
+                    })
+                    .boxed()
+            }));
+        }
+
+        // The connection becomes ready for another request once this
+        // response's body has been read, at which point it can go back into
+        // the pool. If the body is dropped early, or the server asks for the
+        // connection to be closed, then it's discarded instead. This task is
+        // detached rather than tied to the response so that the connection
+        // isn't torn down along with the response.
+        let pool = self.clone();
+        tokio::task::spawn(async move {
+            let _active = active;
+            if conn.sender.ready().await.is_ok() {
+                pool.checkin(key, conn);
+            }
+        });
+
+        Ok(resp.map(|body| body.map_err(hyper_request_error).boxed()))
+    }
+
+    fn permits(&self, key: &PoolKey) -> Option<Arc<Semaphore>> {
+        let max = self.inner.config.max_connections_per_host?;
+        let mut hosts = self.inner.hosts.lock().unwrap();
+        let host = hosts.entry(key.clone()).or_default();
+        Some(
+            host.permits
+                .get_or_insert_with(|| Arc::new(Semaphore::new(max)))
+                .clone(),
+        )
+    }
+
+    /// Take an unused connection to `key` out of the pool, if there is one.
+    fn checkout(&self, key: &PoolKey) -> Option<Connection> {
+        let now = Instant::now();
+        let idle_timeout = self.inner.config.idle_timeout;
+        let mut hosts = self.inner.hosts.lock().unwrap();
+
+        // Close any connections, to any host, which have been unused for too
+        // long or which the server has closed in the meantime.
+        let mut expired = 0;
+        for host in hosts.values_mut() {
+            host.idle.retain(|idle| {
+                let keep = now.duration_since(idle.since) < idle_timeout;
+                if !keep {
+                    expired += 1;
+                }
+                keep && !idle.conn.sender.is_closed()
+            });
+        }
+        hosts.retain(|_, host| !host.idle.is_empty() || host.permits.is_some());
+        self.inner
+            .connections_expired
+            .fetch_add(expired, Ordering::Relaxed);
+
+        let host = hosts.get_mut(key)?;
+        while let Some(idle) = host.idle.last_mut() {
+            if !idle.conn.sender.is_ready() {
+                host.idle.pop();
+                continue;
+            }
+            // HTTP/2 connections stay in the pool for other requests to share.
+            if let Some(conn) = idle.conn.multiplexed() {
+                idle.since = now;
+                return Some(conn);
+            }
+            return host.idle.pop().map(|idle| idle.conn);
+        }
+        None
+    }
+
+    /// Return a connection to `key` to the pool once it's no longer in use.
+    fn checkin(&self, key: PoolKey, conn: Connection) {
+        let mut hosts = self.inner.hosts.lock().unwrap();
+        let host = hosts.entry(key).or_default();
+        if host.idle.len() < self.inner.config.max_idle_per_host {
+            host.idle.push(IdleConnection {
+                conn,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+/// A connection's place in a pool's count of active connections, and its
+/// permit if the pool limits the connections per host.
+struct ActiveConnection {
+    pool: ConnectionPool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ActiveConnection {
+    fn new(pool: ConnectionPool, permit: Option<OwnedSemaphorePermit>) -> Self {
+        pool.inner
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        Self {
+            pool,
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.pool
+            .inner
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,530 @@
+//! TLS configuration for outgoing requests, and termination of TLS for
+//! incoming connections.
+//!
+//! By default outgoing `https` requests trust the Mozilla root certificates
+//! bundled via the `webpki-roots` crate and don't present a client certificate.
+//! A [`TlsConfig`] can be used to trust additional certificate authorities,
+//! such as a private CA for internal services, and to present a client
+//! certificate to servers which require mutual TLS. Different settings may be
+//! used for particular authorities via [`TlsConfig::authority`].
+//!
+//! A configuration is used for the requests of a store once it has been set
+//! with [`WasiHttpCtx::set_tls_config`](crate::WasiHttpCtx::set_tls_config):
+//!
+//! ```no_run
+//! use wasmtime_wasi_http::tls::TlsConfig;
+//! use wasmtime_wasi_http::WasiHttpCtx;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut internal = TlsConfig::new();
+//! internal
+//!     .webpki_roots(false)
+//!     .add_root_certificates_pem(&std::fs::read("internal-ca.pem")?)?
+//!     .client_auth_pem(
+//!         &std::fs::read("client.pem")?,
+//!         &std::fs::read("client.key")?,
+//!     )?;
+//!
+//! let mut config = TlsConfig::new();
+//! config.authority("api.internal:443", internal);
+//!
+//! let mut ctx = WasiHttpCtx::new();
+//! ctx.set_tls_config(config);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Servers which accept connections themselves, rather than behind a proxy
+//! which terminates TLS for them, can use a [`TlsAcceptor`] to serve `https`.
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// TLS settings for outgoing `https` requests.
+///
+/// This is a cheaply cloneable handle.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    inner: Arc<TlsConfigInner>,
+}
+
+#[derive(Clone, Debug)]
+struct TlsConfigInner {
+    /// Identifies these exact settings, so that pooled connections are only
+    /// reused by requests with the same settings.
+    id: u64,
+    webpki_roots: bool,
+    root_certs: Vec<Vec<u8>>,
+    client_auth: Option<ClientAuth>,
+    authorities: HashMap<String, TlsConfig>,
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    client_config: std::sync::OnceLock<Arc<rustls::ClientConfig>>,
+}
+
+#[derive(Clone)]
+struct ClientAuth {
+    cert_chain: Vec<Vec<u8>>,
+    key: PrivateKey,
+}
+
+// Don't print private keys in debug output.
+impl fmt::Debug for ClientAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientAuth")
+            .field("cert_chain", &self.cert_chain.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
+enum PrivateKey {
+    Pkcs1(Vec<u8>),
+    Pkcs8(Vec<u8>),
+    Sec1(Vec<u8>),
+}
+
+fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl TlsConfig {
+    /// Create the default configuration, which trusts the `webpki-roots`
+    /// certificate authorities and doesn't use a client certificate.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(TlsConfigInner {
+                id: next_id(),
+                webpki_roots: true,
+                root_certs: Vec::new(),
+                client_auth: None,
+                authorities: HashMap::new(),
+                #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+                client_config: Default::default(),
+            }),
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut TlsConfigInner {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.id = next_id();
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        {
+            inner.client_config = Default::default();
+        }
+        inner
+    }
+
+    /// Whether to trust the certificate authorities bundled via the
+    /// `webpki-roots` crate.
+    ///
+    /// Defaults to `true`. Disable this to only trust the certificate
+    /// authorities added to this configuration.
+    pub fn webpki_roots(&mut self, enable: bool) -> &mut Self {
+        self.inner_mut().webpki_roots = enable;
+        self
+    }
+
+    /// Trust the certificate authorities in the given PEM-encoded bundle, in
+    /// addition to any already trusted.
+    ///
+    /// Returns an error if the bundle doesn't contain any certificates or if
+    /// any of them are invalid.
+    pub fn add_root_certificates_pem(&mut self, pem: &[u8]) -> Result<&mut Self> {
+        let certs = pem_certificates(pem)?;
+        if certs.is_empty() {
+            bail!("no certificates found in PEM bundle");
+        }
+        for cert in certs {
+            self.add_root_certificate_der(&cert)?;
+        }
+        Ok(self)
+    }
+
+    /// Trust the given DER-encoded certificate authority, in addition to any
+    /// already trusted.
+    pub fn add_root_certificate_der(&mut self, der: &[u8]) -> Result<&mut Self> {
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        rustls::RootCertStore::empty()
+            .add(rustls::pki_types::CertificateDer::from(der))
+            .context("invalid root certificate")?;
+        self.inner_mut().root_certs.push(der.to_vec());
+        Ok(self)
+    }
+
+    /// Present the given client certificate chain, signed with the given
+    /// private key, to servers which request one.
+    ///
+    /// Both are PEM-encoded. The first certificate in the chain is the client
+    /// certificate itself. The key may be a PKCS #1, PKCS #8, or SEC1 private
+    /// key.
+    pub fn client_auth_pem(&mut self, cert_chain_pem: &[u8], key_pem: &[u8]) -> Result<&mut Self> {
+        let cert_chain = pem_certificates(cert_chain_pem)?;
+        if cert_chain.is_empty() {
+            bail!("no certificates found in client certificate chain");
+        }
+
+        let key = PrivateKey::from_pem(key_pem)?;
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        rustls::crypto::ring::sign::any_supported_type(&key.to_der())
+            .context("unsupported client private key")?;
+
+        self.inner_mut().client_auth = Some(ClientAuth { cert_chain, key });
+        Ok(self)
+    }
+
+    /// Use `config` instead of this configuration for requests to
+    /// `authority`.
+    ///
+    /// The authority is either `host:port`, which matches requests to that
+    /// host and port, or just `host`, which matches requests to that host on
+    /// any port. Note that the port of an `https` request without an explicit
+    /// port is 443.
+    pub fn authority(&mut self, authority: &str, config: TlsConfig) -> &mut Self {
+        self.inner_mut()
+            .authorities
+            .insert(authority.to_string(), config);
+        self
+    }
+
+    /// The configuration to use for a request to `authority`, which is in the
+    /// form `host:port`.
+    pub(crate) fn for_authority(&self, authority: &str) -> &TlsConfig {
+        let authorities = &self.inner.authorities;
+        let host = authority
+            .rsplit_once(':')
+            .map_or(authority, |(host, _port)| host);
+        authorities
+            .get(authority)
+            .or_else(|| authorities.get(host))
+            .unwrap_or(self)
+    }
+
+    /// The default configuration used by requests that aren't given one.
+    ///
+    /// This is shared by all such requests so that their connections can be
+    /// pooled together and the `rustls` configuration is only built once.
+    pub(crate) fn shared_default() -> &'static TlsConfig {
+        static DEFAULT: std::sync::OnceLock<TlsConfig> = std::sync::OnceLock::new();
+        DEFAULT.get_or_init(TlsConfig::new)
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// The `rustls` configuration for these settings, which is built the first
+    /// time it's needed.
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    pub(crate) fn client_config(&self) -> Arc<rustls::ClientConfig> {
+        use rustls::pki_types::CertificateDer;
+
+        self.inner
+            .client_config
+            .get_or_init(|| {
+                let inner = &self.inner;
+
+                // derived from https://github.com/rustls/rustls/blob/main/examples/src/bin/simpleclient.rs
+                let mut root_cert_store = rustls::RootCertStore::empty();
+                if inner.webpki_roots {
+                    root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                }
+                for der in &inner.root_certs {
+                    root_cert_store
+                        .add(CertificateDer::from(der.clone()))
+                        .expect("root certificates are validated when added");
+                }
+
+                let builder =
+                    rustls::ClientConfig::builder().with_root_certificates(root_cert_store);
+                let mut config = match &inner.client_auth {
+                    Some(auth) => builder
+                        .with_client_auth_cert(
+                            auth.cert_chain
+                                .iter()
+                                .map(|der| CertificateDer::from(der.clone()))
+                                .collect(),
+                            auth.key.to_der(),
+                        )
+                        .expect("client keys are validated when added"),
+                    None => builder.with_no_client_auth(),
+                };
+                config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                Arc::new(config)
+            })
+            .clone()
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrivateKey {
+    /// Parse the single private key in `pem`.
+    fn from_pem(pem: &[u8]) -> Result<PrivateKey> {
+        let mut keys =
+            pem_blocks(pem)?
+                .into_iter()
+                .filter_map(|(label, der)| match label.as_str() {
+                    "RSA PRIVATE KEY" => Some(PrivateKey::Pkcs1(der)),
+                    "PRIVATE KEY" => Some(PrivateKey::Pkcs8(der)),
+                    "EC PRIVATE KEY" => Some(PrivateKey::Sec1(der)),
+                    _ => None,
+                });
+        match (keys.next(), keys.next()) {
+            (Some(key), None) => Ok(key),
+            (None, _) => bail!("no private key found in PEM file"),
+            (Some(_), Some(_)) => bail!("more than one private key found in PEM file"),
+        }
+    }
+
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    fn to_der(&self) -> rustls::pki_types::PrivateKeyDer<'static> {
+        use rustls::pki_types::{PrivatePkcs1KeyDer, PrivatePkcs8KeyDer, PrivateSec1KeyDer};
+        match self {
+            PrivateKey::Pkcs1(der) => PrivatePkcs1KeyDer::from(der.clone()).into(),
+            PrivateKey::Pkcs8(der) => PrivatePkcs8KeyDer::from(der.clone()).into(),
+            PrivateKey::Sec1(der) => PrivateSec1KeyDer::from(der.clone()).into(),
+        }
+    }
+}
+
+/// Terminates TLS for incoming connections.
+///
+/// Connections negotiate HTTP/2 or HTTP/1.1 via ALPN. The certificate can be
+/// replaced while connections are being accepted, for example once it's been
+/// renewed, with [`TlsAcceptor::set_certificate_pem`]. This is a cheaply
+/// cloneable handle, and a replaced certificate is used by all of its clones.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    acceptor: tokio_rustls::TlsAcceptor,
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    cert: Arc<ServerCert>,
+    #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+    unsupported: std::convert::Infallible,
+}
+
+impl TlsAcceptor {
+    /// Create an acceptor which presents the given certificate chain, signed
+    /// with the given private key.
+    ///
+    /// Both are PEM-encoded. The first certificate in the chain is the
+    /// server's certificate itself. The key may be a PKCS #1, PKCS #8, or SEC1
+    /// private key.
+    pub fn new(cert_chain_pem: &[u8], key_pem: &[u8]) -> Result<Self> {
+        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+        {
+            let _ = (cert_chain_pem, key_pem);
+            bail!("unsupported architecture for SSL");
+        }
+
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        {
+            let cert = Arc::new(ServerCert {
+                key: std::sync::RwLock::new(certified_key(cert_chain_pem, key_pem)?),
+            });
+            let mut config = rustls::ServerConfig::builder()
+                .with_no_client_auth()
+                .with_cert_resolver(cert.clone());
+            config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+            Ok(TlsAcceptor {
+                acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(config)),
+                cert,
+            })
+        }
+    }
+
+    /// Present the given certificate chain and private key, in the same form
+    /// as for [`TlsAcceptor::new`], to the connections accepted from now on.
+    ///
+    /// If these are invalid then an error is returned and the current
+    /// certificate is kept.
+    pub fn set_certificate_pem(&self, cert_chain_pem: &[u8], key_pem: &[u8]) -> Result<()> {
+        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+        {
+            let _ = (cert_chain_pem, key_pem);
+            match self.unsupported {}
+        }
+
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        {
+            let key = certified_key(cert_chain_pem, key_pem)?;
+            *self.cert.key.write().unwrap() = key;
+            Ok(())
+        }
+    }
+
+    /// Perform the TLS handshake for a newly-accepted connection.
+    pub async fn accept<IO>(&self, io: IO) -> io::Result<TlsStream<IO>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+        {
+            let _ = io;
+            match self.unsupported {}
+        }
+
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        {
+            let stream = self.acceptor.accept(io).await?;
+            Ok(TlsStream { stream })
+        }
+    }
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsAcceptor").finish_non_exhaustive()
+    }
+}
+
+/// The certificate presented by a [`TlsAcceptor`], which is looked up for
+/// each connection so that it can be replaced.
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+#[derive(Debug)]
+struct ServerCert {
+    key: std::sync::RwLock<Arc<rustls::sign::CertifiedKey>>,
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+impl rustls::server::ResolvesServerCert for ServerCert {
+    fn resolve(
+        &self,
+        _client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(self.key.read().unwrap().clone())
+    }
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+fn certified_key(cert_chain_pem: &[u8], key_pem: &[u8]) -> Result<Arc<rustls::sign::CertifiedKey>> {
+    let cert_chain = pem_certificates(cert_chain_pem)?;
+    if cert_chain.is_empty() {
+        bail!("no certificates found in certificate chain");
+    }
+    let key = PrivateKey::from_pem(key_pem)?;
+    let key = rustls::crypto::ring::sign::any_supported_type(&key.to_der())
+        .context("unsupported private key")?;
+    Ok(Arc::new(rustls::sign::CertifiedKey::new(
+        cert_chain
+            .into_iter()
+            .map(rustls::pki_types::CertificateDer::from)
+            .collect(),
+        key,
+    )))
+}
+
+/// A connection accepted by a [`TlsAcceptor`].
+#[derive(Debug)]
+pub struct TlsStream<IO> {
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    stream: tokio_rustls::server::TlsStream<IO>,
+    #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+    stream: IO,
+}
+
+impl<IO> TlsStream<IO> {
+    /// Whether the client negotiated HTTP/2 via ALPN.
+    pub fn is_http2(&self) -> bool {
+        #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+        return self.stream.get_ref().1.alpn_protocol() == Some(b"h2");
+        #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+        return false;
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// The DER contents of each `CERTIFICATE` block in `pem`.
+fn pem_certificates(pem: &[u8]) -> Result<Vec<Vec<u8>>> {
+    Ok(pem_blocks(pem)?
+        .into_iter()
+        .filter(|(label, _)| label == "CERTIFICATE")
+        .map(|(_, der)| der)
+        .collect())
+}
+
+/// Parse the label and DER contents of each block in `pem`, ignoring any text
+/// between blocks.
+fn pem_blocks(pem: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let pem = std::str::from_utf8(pem).context("PEM file is not valid UTF-8")?;
+    let mut blocks = Vec::new();
+    let mut lines = pem.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let label = match line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|l| l.strip_suffix("-----"))
+        {
+            Some(label) => label,
+            None => continue,
+        };
+        let end = format!("-----END {label}-----");
+        let mut base64 = String::new();
+        loop {
+            match lines.next() {
+                Some(line) if line == end => break,
+                Some(line) => base64.push_str(line),
+                None => bail!("unterminated `{label}` block in PEM file"),
+            }
+        }
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(&base64)
+            .with_context(|| format!("invalid base64 in `{label}` block in PEM file"))?;
+        blocks.push((label.to_string(), der));
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_default_is_reused() {
+        let a = TlsConfig::shared_default();
+        let b = TlsConfig::shared_default();
+        assert_eq!(a.id(), b.id());
+        assert_eq!(a.clone().id(), b.id());
+        assert_ne!(TlsConfig::default().id(), a.id());
+        assert_eq!(a.for_authority("example.com:443").id(), a.id());
+    }
+}
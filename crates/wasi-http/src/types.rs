@@ -1,12 +1,15 @@
 //! Implements the base structure (i.e. [WasiHttpCtx]) that will provide the
 //! implementation of the wasi-http API.
 
-use crate::io::TokioIo;
+use crate::io::{TokioExecutor, TokioIo};
 use crate::{
     bindings::http::types::{self, Method, Scheme},
-    body::{HostIncomingBody, HyperIncomingBody, HyperOutgoingBody},
+    body::{BodyConfig, HostIncomingBody, HyperIncomingBody, HyperOutgoingBody, StreamContext},
     error::dns_error,
     hyper_request_error,
+    policy::OutgoingRequestPolicy,
+    pool::ConnectionPool,
+    tls::TlsConfig,
 };
 use anyhow::bail;
 use bytes::Bytes;
@@ -23,13 +26,74 @@ use wasmtime_wasi::{runtime::AbortOnDropJoinHandle, Subscribe};
 /// Capture the state necessary for use in the wasi-http API implementation.
 #[derive(Debug)]
 pub struct WasiHttpCtx {
-    _priv: (),
+    connection_pool: Option<ConnectionPool>,
+    tls_config: Option<TlsConfig>,
+    outgoing_policy: Option<OutgoingRequestPolicy>,
+    body_config: BodyConfig,
 }
 
 impl WasiHttpCtx {
     /// Create a new context.
     pub fn new() -> Self {
-        Self { _priv: () }
+        Self {
+            connection_pool: None,
+            tls_config: None,
+            outgoing_policy: None,
+            body_config: BodyConfig::default(),
+        }
+    }
+
+    /// Send outgoing requests over connections from `pool`, rather than
+    /// opening a new connection for each request.
+    ///
+    /// A pool may be shared between many contexts, for example between all of
+    /// the stores created by a server, so that connections are reused across
+    /// them.
+    pub fn set_connection_pool(&mut self, pool: ConnectionPool) {
+        self.connection_pool = Some(pool);
+    }
+
+    /// The pool that outgoing requests are sent with, if any.
+    pub fn connection_pool(&self) -> Option<&ConnectionPool> {
+        self.connection_pool.as_ref()
+    }
+
+    /// Use `config` for the TLS connections of outgoing `https` requests.
+    ///
+    /// Without a configuration, the certificate authorities bundled via the
+    /// `webpki-roots` crate are trusted and no client certificate is used.
+    pub fn set_tls_config(&mut self, config: TlsConfig) {
+        self.tls_config = Some(config);
+    }
+
+    /// The TLS configuration that outgoing requests are sent with, if any.
+    pub fn tls_config(&self) -> Option<&TlsConfig> {
+        self.tls_config.as_ref()
+    }
+
+    /// Only allow the outgoing requests that `policy` allows.
+    ///
+    /// The policy is enforced before requests are passed to
+    /// [`WasiHttpView::send_request`], so it also applies to embedders which
+    /// override how requests are sent.
+    pub fn set_outgoing_policy(&mut self, policy: OutgoingRequestPolicy) {
+        self.outgoing_policy = Some(policy);
+    }
+
+    /// The policy that outgoing requests are checked against, if any.
+    pub fn outgoing_policy(&self) -> Option<&OutgoingRequestPolicy> {
+        self.outgoing_policy.as_ref()
+    }
+
+    /// Buffer and limit the bodies of requests and responses according to
+    /// `config`.
+    pub fn set_body_config(&mut self, config: BodyConfig) {
+        self.body_config = config;
+    }
+
+    /// The configuration that bodies are buffered and limited with.
+    pub fn body_config(&self) -> &BodyConfig {
+        &self.body_config
     }
 }
 
@@ -92,11 +156,12 @@ pub trait WasiHttpView: Send {
     {
         let (parts, body) = req.into_parts();
         let body = body.map_err(crate::hyper_response_error).boxed();
-        let body = HostIncomingBody::new(
-            body,
-            // TODO: this needs to be plumbed through
-            std::time::Duration::from_millis(600 * 1000),
-        );
+        let config = self.ctx().body_config();
+        let max_size = config.max_request_body_size;
+        let mut body = HostIncomingBody::new(body, config.read_idle_timeout);
+        if let Some(max) = max_size {
+            body.limit_size(StreamContext::Request, max)?;
+        }
         let incoming_req = HostIncomingRequest::new(self, parts, scheme, Some(body))?;
         Ok(self.table().push(incoming_req)?)
     }
@@ -272,6 +337,10 @@ pub(crate) fn remove_forbidden_headers(
 }
 
 /// Configuration for an outgoing request.
+///
+/// More configuration may be added in the future, so this is created with
+/// [`OutgoingRequestConfig::new`] rather than a struct literal.
+#[non_exhaustive]
 pub struct OutgoingRequestConfig {
     /// Whether to use TLS for the request.
     pub use_tls: bool,
@@ -281,6 +350,54 @@ pub struct OutgoingRequestConfig {
     pub first_byte_timeout: Duration,
     /// The timeout between chunks of a streaming body
     pub between_bytes_timeout: Duration,
+    /// The pool to take a connection from, if any.
+    ///
+    /// This is the [`WasiHttpCtx::connection_pool`] of the context that the
+    /// request was made with. If this is `None` then a new connection is opened
+    /// for the request and closed once its response has been received.
+    pub connection_pool: Option<ConnectionPool>,
+    /// The TLS configuration for the request, if any.
+    ///
+    /// This is the [`WasiHttpCtx::tls_config`] of the context that the request
+    /// was made with. If this is `None` then the default configuration is
+    /// used.
+    pub tls_config: Option<TlsConfig>,
+}
+
+impl OutgoingRequestConfig {
+    /// Create the configuration for a request that opens a new connection
+    /// with the default TLS configuration.
+    ///
+    /// The connection pool and TLS configuration can be set afterwards with
+    /// [`OutgoingRequestConfig::connection_pool`] and
+    /// [`OutgoingRequestConfig::tls_config`].
+    pub fn new(
+        use_tls: bool,
+        connect_timeout: Duration,
+        first_byte_timeout: Duration,
+        between_bytes_timeout: Duration,
+    ) -> Self {
+        Self {
+            use_tls,
+            connect_timeout,
+            first_byte_timeout,
+            between_bytes_timeout,
+            connection_pool: None,
+            tls_config: None,
+        }
+    }
+
+    /// Set the pool to take a connection from, if any.
+    pub fn connection_pool(&mut self, pool: Option<ConnectionPool>) -> &mut Self {
+        self.connection_pool = pool;
+        self
+    }
+
+    /// Set the TLS configuration for the request, if any.
+    pub fn tls_config(&mut self, config: Option<TlsConfig>) -> &mut Self {
+        self.tls_config = config;
+        self
+    }
 }
 
 /// The default implementation of how an outgoing request is sent.
@@ -302,12 +419,14 @@ pub fn default_send_request(
 ///
 /// This is called from [default_send_request] to actually send the request.
 pub async fn default_send_request_handler(
-    mut request: hyper::Request<HyperOutgoingBody>,
+    request: hyper::Request<HyperOutgoingBody>,
     OutgoingRequestConfig {
         use_tls,
         connect_timeout,
         first_byte_timeout,
         between_bytes_timeout,
+        connection_pool,
+        tls_config,
     }: OutgoingRequestConfig,
 ) -> Result<IncomingResponse, types::ErrorCode> {
     let authority = if let Some(authority) = request.uri().authority() {
@@ -320,7 +439,127 @@ pub async fn default_send_request_handler(
     } else {
         return Err(types::ErrorCode::HttpRequestUriInvalid);
     };
-    let tcp_stream = timeout(connect_timeout, TcpStream::connect(&authority))
+
+    let tls_config = if use_tls {
+        let tls_config = tls_config
+            .as_ref()
+            .unwrap_or_else(|| TlsConfig::shared_default());
+        Some(tls_config.for_authority(&authority).clone())
+    } else {
+        None
+    };
+
+    // A pooled connection outlives this response, so it's driven by the pool
+    // rather than by a worker owned by the response.
+    let (resp, worker) = match connection_pool {
+        Some(pool) => {
+            let resp = pool
+                .send_request(
+                    authority,
+                    tls_config.as_ref(),
+                    connect_timeout,
+                    first_byte_timeout,
+                    request,
+                )
+                .await?;
+            (resp, None)
+        }
+        None => {
+            let (mut sender, worker) =
+                connect(&authority, tls_config.as_ref(), connect_timeout).await?;
+            let resp = timeout(first_byte_timeout, sender.send_request(request))
+                .await
+                .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
+                .map_err(hyper_request_error)?;
+            let resp = resp.map(|body| body.map_err(hyper_request_error).boxed());
+            (resp, Some(worker))
+        }
+    };
+
+    Ok(IncomingResponse {
+        resp,
+        worker,
+        between_bytes_timeout,
+    })
+}
+
+/// A handle to send requests on a connection, which speaks HTTP/2 if it was
+/// negotiated via ALPN and HTTP/1.1 otherwise.
+#[derive(Debug)]
+pub(crate) enum SendRequest {
+    Http1(hyper::client::conn::http1::SendRequest<HyperOutgoingBody>),
+    Http2(hyper::client::conn::http2::SendRequest<HyperOutgoingBody>),
+}
+
+impl SendRequest {
+    pub(crate) async fn send_request(
+        &mut self,
+        mut request: hyper::Request<HyperOutgoingBody>,
+    ) -> hyper::Result<hyper::Response<hyper::body::Incoming>> {
+        match self {
+            SendRequest::Http1(sender) => {
+                // at this point, the request contains the scheme and the authority, but
+                // the http packet should only include those if addressing a proxy, so
+                // remove them here, since SendRequest::send_request does not do it for us
+                *request.uri_mut() = http::Uri::builder()
+                    .path_and_query(
+                        request
+                            .uri()
+                            .path_and_query()
+                            .map(|p| p.as_str())
+                            .unwrap_or("/"),
+                    )
+                    .build()
+                    .expect("comes from valid request");
+                sender.send_request(request).await
+            }
+            // HTTP/2 sends the scheme and authority as pseudo-headers, so the
+            // URI is left as-is.
+            SendRequest::Http2(sender) => sender.send_request(request).await,
+        }
+    }
+
+    /// Wait for the connection to be able to send another request.
+    pub(crate) async fn ready(&mut self) -> hyper::Result<()> {
+        match self {
+            SendRequest::Http1(sender) => sender.ready().await,
+            SendRequest::Http2(sender) => sender.ready().await,
+        }
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        match self {
+            SendRequest::Http1(sender) => sender.is_ready(),
+            SendRequest::Http2(sender) => sender.is_ready(),
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        match self {
+            SendRequest::Http1(sender) => sender.is_closed(),
+            SendRequest::Http2(sender) => sender.is_closed(),
+        }
+    }
+
+    /// Get another handle to this connection, if it can send many requests at
+    /// once.
+    pub(crate) fn multiplexed(&self) -> Option<SendRequest> {
+        match self {
+            SendRequest::Http1(_) => None,
+            SendRequest::Http2(sender) => Some(SendRequest::Http2(sender.clone())),
+        }
+    }
+}
+
+/// Open a new connection to `authority`, using TLS with `tls_config` if it's
+/// given, returning the handle to send requests on it along with the task
+/// driving the connection.
+pub(crate) async fn connect(
+    authority: &str,
+    tls_config: Option<&TlsConfig>,
+    connect_timeout: Duration,
+) -> Result<(SendRequest, AbortOnDropJoinHandle<()>), types::ErrorCode> {
+    let tcp_stream = timeout(connect_timeout, TcpStream::connect(authority))
         .await
         .map_err(|_| types::ErrorCode::ConnectionTimeout)?
         .map_err(|e| match e.kind() {
@@ -339,9 +578,10 @@ pub async fn default_send_request_handler(
             }
         })?;
 
-    let (mut sender, worker) = if use_tls {
+    if let Some(tls_config) = tls_config {
         #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
         {
+            let _ = tls_config;
             return Err(crate::bindings::http::types::ErrorCode::InternalError(
                 Some("unsupported architecture for SSL".to_string()),
             ));
@@ -351,16 +591,9 @@ pub async fn default_send_request_handler(
         {
             use rustls::pki_types::ServerName;
 
-            // derived from https://github.com/rustls/rustls/blob/main/examples/src/bin/simpleclient.rs
-            let root_cert_store = rustls::RootCertStore {
-                roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-            };
-            let config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_cert_store)
-                .with_no_client_auth();
-            let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+            let connector = tokio_rustls::TlsConnector::from(tls_config.client_config());
             let mut parts = authority.split(":");
-            let host = parts.next().unwrap_or(&authority);
+            let host = parts.next().unwrap_or(authority);
             let domain = ServerName::try_from(host)
                 .map_err(|e| {
                     tracing::warn!("dns lookup error: {e:?}");
@@ -371,33 +604,27 @@ pub async fn default_send_request_handler(
                 tracing::warn!("tls protocol error: {e:?}");
                 types::ErrorCode::TlsProtocolError
             })?;
-            let stream = TokioIo::new(stream);
-
-            let (sender, conn) = timeout(
-                connect_timeout,
-                hyper::client::conn::http1::handshake(stream),
-            )
-            .await
-            .map_err(|_| types::ErrorCode::ConnectionTimeout)?
-            .map_err(hyper_request_error)?;
-
-            let worker = wasmtime_wasi::runtime::spawn(async move {
-                match conn.await {
-                    Ok(()) => {}
-                    // TODO: shouldn't throw away this error and ideally should
-                    // surface somewhere.
-                    Err(e) => tracing::warn!("dropping error {e}"),
-                }
-            });
-
-            (sender, worker)
+            let http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+            handshake(TokioIo::new(stream), http2, connect_timeout).await
         }
     } else {
-        let tcp_stream = TokioIo::new(tcp_stream);
+        handshake(TokioIo::new(tcp_stream), false, connect_timeout).await
+    }
+}
+
+/// Perform the HTTP handshake on a newly-opened connection.
+async fn handshake<T>(
+    io: T,
+    http2: bool,
+    connect_timeout: Duration,
+) -> Result<(SendRequest, AbortOnDropJoinHandle<()>), types::ErrorCode>
+where
+    T: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    if http2 {
         let (sender, conn) = timeout(
             connect_timeout,
-            // TODO: we should plumb the builder through the http context, and use it here
-            hyper::client::conn::http1::handshake(tcp_stream),
+            hyper::client::conn::http2::handshake(TokioExecutor, io),
         )
         .await
         .map_err(|_| types::ErrorCode::ConnectionTimeout)?
@@ -406,39 +633,33 @@ pub async fn default_send_request_handler(
         let worker = wasmtime_wasi::runtime::spawn(async move {
             match conn.await {
                 Ok(()) => {}
-                // TODO: same as above, shouldn't throw this error away.
+                // TODO: shouldn't throw away this error and ideally should
+                // surface somewhere.
                 Err(e) => tracing::warn!("dropping error {e}"),
             }
         });
 
-        (sender, worker)
-    };
-
-    // at this point, the request contains the scheme and the authority, but
-    // the http packet should only include those if addressing a proxy, so
-    // remove them here, since SendRequest::send_request does not do it for us
-    *request.uri_mut() = http::Uri::builder()
-        .path_and_query(
-            request
-                .uri()
-                .path_and_query()
-                .map(|p| p.as_str())
-                .unwrap_or("/"),
+        Ok((SendRequest::Http2(sender), worker))
+    } else {
+        let (sender, conn) = timeout(
+            connect_timeout,
+            // TODO: we should plumb the builder through the http context, and use it here
+            hyper::client::conn::http1::handshake(io),
         )
-        .build()
-        .expect("comes from valid request");
-
-    let resp = timeout(first_byte_timeout, sender.send_request(request))
         .await
-        .map_err(|_| types::ErrorCode::ConnectionReadTimeout)?
-        .map_err(hyper_request_error)?
-        .map(|body| body.map_err(hyper_request_error).boxed());
+        .map_err(|_| types::ErrorCode::ConnectionTimeout)?
+        .map_err(hyper_request_error)?;
 
-    Ok(IncomingResponse {
-        resp,
-        worker: Some(worker),
-        between_bytes_timeout,
-    })
+        let worker = wasmtime_wasi::runtime::spawn(async move {
+            match conn.await {
+                Ok(()) => {}
+                // TODO: same as above, shouldn't throw this error away.
+                Err(e) => tracing::warn!("dropping error {e}"),
+            }
+        });
+
+        Ok((SendRequest::Http1(sender), worker))
+    }
 }
 
 impl From<http::Method> for types::Method {
@@ -391,6 +391,7 @@ where
         &mut self,
         request: Resource<HostOutgoingRequest>,
     ) -> wasmtime::Result<Result<Resource<HostOutgoingBody>, ()>> {
+        let config = self.ctx().body_config().clone();
         let req = self
             .table()
             .get_mut(&request)
@@ -405,7 +406,8 @@ where
             Err(e) => return Ok(Err(e)),
         };
 
-        let (host_body, hyper_body) = HostOutgoingBody::new(StreamContext::Request, size);
+        let (host_body, hyper_body) =
+            HostOutgoingBody::with_config(StreamContext::Request, size, &config);
 
         req.body = Some(hyper_body);
 
@@ -751,6 +753,7 @@ where
         &mut self,
         id: Resource<HostOutgoingResponse>,
     ) -> wasmtime::Result<Result<Resource<HostOutgoingBody>, ()>> {
+        let config = self.ctx().body_config().clone();
         let resp = self.table().get_mut(&id)?;
 
         if resp.body.is_some() {
@@ -762,7 +765,7 @@ where
             Err(e) => return Ok(Err(e)),
         };
 
-        let (host, body) = HostOutgoingBody::new(StreamContext::Response, size);
+        let (host, body) = HostOutgoingBody::with_config(StreamContext::Response, size, &config);
 
         resp.body.replace(body);
 
@@ -859,11 +862,15 @@ where
 
         remove_forbidden_headers(self, &mut parts.headers);
 
+        let max_size = self.ctx().body_config().max_response_body_size;
         let resp = self.table().push(HostIncomingResponse {
             status: parts.status.as_u16(),
             headers: parts.headers,
             body: Some({
                 let mut body = HostIncomingBody::new(body, resp.between_bytes_timeout);
+                if let Some(max) = max_size {
+                    body.limit_size(StreamContext::Response, max)?;
+                }
                 if let Some(worker) = resp.worker {
                     body.retain_worker(worker);
                 }
@@ -553,6 +553,167 @@ async fn wasi_http_without_port() -> Result<()> {
     Ok(())
 }
 
+#[test_log::test(tokio::test)]
+async fn connection_pool_reuses_connections() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use wasmtime_wasi_http::pool::{ConnectionPool, ConnectionPoolConfig};
+
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), 0)).await?;
+    let addr = listener.local_addr()?;
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let server = {
+        let connections = connections.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                connections.fetch_add(1, Ordering::SeqCst);
+                task::spawn(async move {
+                    let service = service_fn(|_request| async {
+                        Ok::<_, hyper::Error>(hyper::Response::new(body::full(Bytes::from_static(
+                            b"hello",
+                        ))))
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .keep_alive(true)
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        eprintln!("error serving connection: {e:?}");
+                    }
+                });
+
+                // Help rustc with type inference:
+                if false {
+                    return Ok::<_, anyhow::Error>(());
+                }
+            }
+        })
+    };
+
+    let pool = ConnectionPool::new(ConnectionPoolConfig::new());
+    for _ in 0..3 {
+        let request = hyper::Request::get(format!("http://{addr}/"))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())?;
+        let timeout = Duration::from_secs(10);
+        let mut config = OutgoingRequestConfig::new(false, timeout, timeout, timeout);
+        config.connection_pool(Some(pool.clone()));
+        let response = types::default_send_request_handler(request, config).await?;
+        let body = response.resp.into_body().collect().await?.to_bytes();
+        assert_eq!(body, "hello");
+
+        // Wait for the connection to be returned to the pool, which happens in
+        // the background once the response body has been read.
+        while pool.stats().idle_connections == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.connections_opened, 1);
+    assert_eq!(stats.connections_reused, 2);
+    assert_eq!(stats.active_connections, 0);
+    assert_eq!(stats.idle_connections, 1);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    server.abort();
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+#[test_log::test(tokio::test)]
+async fn connection_pool_reuses_tls_connections() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use wasmtime_wasi_http::pool::{ConnectionPool, ConnectionPoolConfig};
+    use wasmtime_wasi_http::tls::TlsConfig;
+
+    const CA: &[u8] = include_bytes!("tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("tls/server.crt.der");
+    const SERVER_KEY: &[u8] = include_bytes!("tls/server.key.der");
+
+    let server_config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![SERVER_CERT.to_vec().into()],
+            rustls::pki_types::PrivatePkcs8KeyDer::from(SERVER_KEY.to_vec()).into(),
+        )?;
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), 0)).await?;
+    let port = listener.local_addr()?.port();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let server = {
+        let connections = connections.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                connections.fetch_add(1, Ordering::SeqCst);
+                let acceptor = acceptor.clone();
+                task::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => return eprintln!("error accepting connection: {e:?}"),
+                    };
+                    let service = service_fn(|_request| async {
+                        Ok::<_, hyper::Error>(hyper::Response::new(body::full(Bytes::from_static(
+                            b"hello",
+                        ))))
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .keep_alive(true)
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        eprintln!("error serving connection: {e:?}");
+                    }
+                });
+
+                // Help rustc with type inference:
+                if false {
+                    return Ok::<_, anyhow::Error>(());
+                }
+            }
+        })
+    };
+
+    let mut tls_config = TlsConfig::new();
+    tls_config
+        .webpki_roots(false)
+        .add_root_certificates_pem(CA)?;
+
+    // Each request gets its own clone of the configuration, as requests made
+    // through a `WasiHttpCtx` do, which still shares the pooled connection.
+    let pool = ConnectionPool::new(ConnectionPoolConfig::new());
+    for _ in 0..2 {
+        let request = hyper::Request::get(format!("https://localhost:{port}/"))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())?;
+        let timeout = Duration::from_secs(10);
+        let mut config = OutgoingRequestConfig::new(true, timeout, timeout, timeout);
+        config
+            .connection_pool(Some(pool.clone()))
+            .tls_config(Some(tls_config.clone()));
+        let response = types::default_send_request_handler(request, config).await?;
+        let body = response.resp.into_body().collect().await?.to_bytes();
+        assert_eq!(body, "hello");
+
+        while pool.stats().idle_connections == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.connections_opened, 1);
+    assert_eq!(stats.connections_reused, 1);
+    assert_eq!(stats.idle_connections, 1);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    server.abort();
+    Ok(())
+}
+
 mod body {
     use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
     use hyper::body::Bytes;
@@ -566,3 +727,417 @@ mod body {
         BoxBody::new(Empty::new().map_err(|_| unreachable!()))
     }
 }
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+#[test_log::test(tokio::test)]
+async fn tls_config_custom_roots_and_client_auth() -> Result<()> {
+    use hyper::server::conn::http2;
+    use std::time::Duration;
+    use wasmtime_wasi_http::io::TokioExecutor;
+    use wasmtime_wasi_http::tls::TlsConfig;
+
+    const CA: &[u8] = include_bytes!("tls/ca.pem");
+    const CA_DER: &[u8] = include_bytes!("tls/ca.crt.der");
+    const CLIENT_CERT: &[u8] = include_bytes!("tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("tls/client.key");
+    const SERVER_CERT: &[u8] = include_bytes!("tls/server.crt.der");
+    const SERVER_KEY: &[u8] = include_bytes!("tls/server.key.der");
+
+    // A server which only accepts clients with a certificate signed by the
+    // test CA, and which prefers HTTP/2.
+    let mut client_roots = rustls::RootCertStore::empty();
+    client_roots.add(CA_DER.to_vec().into())?;
+    let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(client_roots)).build()?;
+    let mut server_config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![SERVER_CERT.to_vec().into()],
+            rustls::pki_types::PrivatePkcs8KeyDer::from(SERVER_KEY.to_vec()).into(),
+        )?;
+    server_config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), 0)).await?;
+    let port = listener.local_addr()?.port();
+    let _server = task::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let acceptor = acceptor.clone();
+            task::spawn(async move {
+                let stream = match acceptor.accept(stream).await {
+                    Ok(stream) => stream,
+                    Err(e) => return eprintln!("error accepting connection: {e:?}"),
+                };
+                let service = service_fn(|_request| async {
+                    Ok::<_, hyper::Error>(hyper::Response::new(body::full(Bytes::from_static(
+                        b"hello",
+                    ))))
+                });
+                if let Err(e) = http2::Builder::new(TokioExecutor)
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    eprintln!("error serving connection: {e:?}");
+                }
+            });
+
+            // Help rustc with type inference:
+            if false {
+                return Ok::<_, anyhow::Error>(());
+            }
+        }
+    });
+
+    let send = |tls_config: Option<TlsConfig>| {
+        let request = hyper::Request::get(format!("https://localhost:{port}/"))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())
+            .unwrap();
+        let timeout = Duration::from_secs(10);
+        let mut config = OutgoingRequestConfig::new(true, timeout, timeout, timeout);
+        config.tls_config(tls_config);
+        types::default_send_request_handler(request, config)
+    };
+
+    // The test CA isn't trusted by default.
+    let err = send(None).await.unwrap_err();
+    assert!(matches!(err, ErrorCode::TlsProtocolError), "{err:?}");
+
+    // Trusting the CA isn't enough without a client certificate. With TLS 1.3
+    // the server only rejects the connection after the client has finished
+    // the handshake, so this fails as the request is sent.
+    let mut config = TlsConfig::new();
+    config.webpki_roots(false).add_root_certificates_pem(CA)?;
+    assert!(send(Some(config.clone())).await.is_err());
+
+    // Settings for another authority aren't used for this one.
+    let mut other = TlsConfig::new();
+    other.authority("example.com", config.clone());
+    config.client_auth_pem(CLIENT_CERT, CLIENT_KEY)?;
+    other.authority("localhost:1", config.clone());
+    assert!(send(Some(other)).await.is_err());
+
+    for config in [
+        config.clone(),
+        TlsConfig::new()
+            .authority("localhost", config.clone())
+            .clone(),
+    ] {
+        let response = send(Some(config)).await?;
+        assert_eq!(response.resp.version(), hyper::Version::HTTP_2);
+        let body = response.resp.into_body().collect().await?.to_bytes();
+        assert_eq!(body, "hello");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+#[test_log::test(tokio::test)]
+async fn outgoing_tls_negotiates_http2_with_alpn() -> Result<()> {
+    use hyper::server::conn::http2;
+    use std::time::Duration;
+    use wasmtime_wasi_http::io::TokioExecutor;
+    use wasmtime_wasi_http::tls::TlsConfig;
+
+    const CA: &[u8] = include_bytes!("tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("tls/server.crt.der");
+    const SERVER_KEY: &[u8] = include_bytes!("tls/server.key.der");
+
+    // Start a server offering `alpn_protocols`, which serves HTTP/2 if it was
+    // negotiated and HTTP/1.1 otherwise.
+    async fn server(alpn_protocols: &[&[u8]]) -> Result<u16> {
+        let mut server_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![SERVER_CERT.to_vec().into()],
+                rustls::pki_types::PrivatePkcs8KeyDer::from(SERVER_KEY.to_vec()).into(),
+            )?;
+        server_config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), 0)).await?;
+        let port = listener.local_addr()?.port();
+        task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                task::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => return eprintln!("error accepting connection: {e:?}"),
+                    };
+                    let http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                    let service = service_fn(|_request| async {
+                        Ok::<_, hyper::Error>(hyper::Response::new(body::full(Bytes::from_static(
+                            b"hello",
+                        ))))
+                    });
+                    let result = if http2 {
+                        http2::Builder::new(TokioExecutor)
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await
+                    } else {
+                        http1::Builder::new()
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await
+                    };
+                    if let Err(e) = result {
+                        eprintln!("error serving connection: {e:?}");
+                    }
+                });
+            }
+        });
+        Ok(port)
+    }
+
+    let mut tls_config = TlsConfig::new();
+    tls_config
+        .webpki_roots(false)
+        .add_root_certificates_pem(CA)?;
+
+    for (alpn_protocols, version) in [
+        (&[&b"h2"[..], b"http/1.1"][..], hyper::Version::HTTP_2),
+        (&[&b"http/1.1"[..]][..], hyper::Version::HTTP_11),
+        (&[][..], hyper::Version::HTTP_11),
+    ] {
+        let port = server(alpn_protocols).await?;
+        let request = hyper::Request::get(format!("https://localhost:{port}/"))
+            .body(body::empty().map_err(|_| unreachable!()).boxed())?;
+        let timeout = Duration::from_secs(10);
+        let mut config = OutgoingRequestConfig::new(true, timeout, timeout, timeout);
+        config.tls_config(Some(tls_config.clone()));
+        let response = types::default_send_request_handler(request, config).await?;
+        assert_eq!(response.resp.version(), version);
+        let body = response.resp.into_body().collect().await?.to_bytes();
+        assert_eq!(body, "hello");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tls_config_rejects_invalid_pem() {
+    use wasmtime_wasi_http::tls::TlsConfig;
+
+    let mut config = TlsConfig::new();
+    assert!(config.add_root_certificates_pem(b"").is_err());
+    assert!(config
+        .add_root_certificates_pem(b"-----BEGIN CERTIFICATE-----\nnot base64!\n")
+        .is_err());
+    assert!(config
+        .add_root_certificates_pem(
+            b"-----BEGIN CERTIFICATE-----\naGVsbG8=\n-----END CERTIFICATE-----\n"
+        )
+        .is_err());
+    assert!(config
+        .client_auth_pem(
+            include_bytes!("tls/client.pem"),
+            include_bytes!("tls/ca.pem")
+        )
+        .is_err());
+}
+
+#[test]
+fn outgoing_policy_allows_and_denies_requests() -> Result<()> {
+    use wasmtime_wasi_http::policy::OutgoingRequestPolicy;
+
+    let request = |method: Method, uri: &str| {
+        hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+    };
+    let allowed = |policy: &OutgoingRequestPolicy, uri: &str| match policy
+        .check(&request(Method::GET, uri))
+    {
+        Ok(()) => true,
+        Err(ErrorCode::HttpRequestDenied) => false,
+        Err(e) => panic!("unexpected error: {e:?}"),
+    };
+
+    let mut policy = OutgoingRequestPolicy::new();
+    assert!(allowed(&policy, "http://anywhere.com/"));
+
+    policy
+        .allow_authority("*.example.com")?
+        .allow_authority("localhost:8080")?
+        .deny_authority("internal.example.com")?;
+    assert!(allowed(&policy, "https://api.example.com/"));
+    assert!(allowed(&policy, "http://API.example.com:1234/"));
+    assert!(allowed(&policy, "http://localhost:8080/"));
+    assert!(!allowed(&policy, "http://localhost/"));
+    assert!(!allowed(&policy, "https://example.com/"));
+    assert!(!allowed(&policy, "https://internal.example.com/"));
+    assert!(!allowed(&policy, "https://internal.example.com./"));
+    assert!(!allowed(&policy, "https://INTERNAL.example.com.:443/"));
+    assert!(!allowed(&policy, "https://anywhere.com/"));
+    assert!(allowed(&policy, "https://api.example.com./"));
+
+    let mut policy = OutgoingRequestPolicy::new();
+    policy
+        .deny_authority("127.0.0.1")?
+        .deny_authority("[::1]")?;
+    assert!(!allowed(&policy, "http://127.0.0.1./"));
+    assert!(!allowed(&policy, "http://127.1/"));
+    assert!(!allowed(&policy, "http://[0::1]/"));
+    assert!(allowed(&policy, "http://127.0.0.2/"));
+
+    let mut policy = OutgoingRequestPolicy::new();
+    policy.allow_authority("*.example.com")?;
+    policy.allow_scheme("https")?.allow_port(443);
+    assert!(allowed(&policy, "https://api.example.com/"));
+    assert!(!allowed(&policy, "http://api.example.com:443/"));
+    assert!(!allowed(&policy, "https://api.example.com:8443/"));
+
+    policy.allow_method(Method::GET);
+    assert!(policy
+        .check(&request(Method::GET, "https://api.example.com/"))
+        .is_ok());
+    assert!(matches!(
+        policy.check(&request(Method::POST, "https://api.example.com/")),
+        Err(ErrorCode::HttpRequestDenied)
+    ));
+
+    assert!(OutgoingRequestPolicy::new().allow_authority("*").is_ok());
+    assert!(OutgoingRequestPolicy::new()
+        .allow_authority("[::1]:80")
+        .is_ok());
+    assert!(OutgoingRequestPolicy::new()
+        .allow_authority("a.*.com")
+        .is_err());
+    assert!(OutgoingRequestPolicy::new()
+        .allow_authority("example.com:http")
+        .is_err());
+    assert!(OutgoingRequestPolicy::new().allow_scheme("ftp").is_err());
+    Ok(())
+}
+
+#[test]
+fn outgoing_policy_limits_rate() -> Result<()> {
+    use wasmtime_wasi_http::policy::OutgoingRequestPolicy;
+
+    let mut policy = OutgoingRequestPolicy::new();
+    policy.max_requests_per_second(Some(2));
+    let shared = policy.clone();
+
+    let request = hyper::Request::get("http://example.com/").body(())?;
+    assert!(policy.check(&request).is_ok());
+    assert!(shared.check(&request).is_ok());
+    assert!(matches!(
+        policy.check(&request),
+        Err(ErrorCode::HttpRequestDenied)
+    ));
+    assert!(matches!(
+        shared.check(&request),
+        Err(ErrorCode::HttpRequestDenied)
+    ));
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn outgoing_policy_limits_body_size() -> Result<()> {
+    use wasmtime_wasi_http::policy::OutgoingRequestPolicy;
+
+    let mut policy = OutgoingRequestPolicy::new();
+    policy.max_body_size(Some(4));
+
+    let request = hyper::Request::post("http://example.com/")
+        .header(hyper::header::CONTENT_LENGTH, "5")
+        .body(())?;
+    assert!(matches!(
+        policy.check(&request),
+        Err(ErrorCode::HttpRequestBodySize(Some(5)))
+    ));
+
+    let request = hyper::Request::post("http://example.com/").body(
+        body::full(Bytes::from_static(b"hello"))
+            .map_err(|_| unreachable!())
+            .boxed(),
+    )?;
+    let body = policy.enforce(request)?.into_body();
+    assert!(matches!(
+        body.collect().await,
+        Err(ErrorCode::HttpRequestBodySize(Some(5)))
+    ));
+
+    let request = hyper::Request::post("http://example.com/").body(
+        body::full(Bytes::from_static(b"hi"))
+            .map_err(|_| unreachable!())
+            .boxed(),
+    )?;
+    let body = policy.enforce(request)?.into_body();
+    assert_eq!(body.collect().await?.to_bytes(), "hi");
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn body_config_limits_outgoing_body_size() -> Result<()> {
+    use wasmtime_wasi_http::body::{BodyConfig, HostOutgoingBody, StreamContext};
+
+    let mut config = BodyConfig::new();
+    config
+        .chunk_size(4)
+        .buffered_chunks(4)
+        .max_request_body_size(Some(8));
+
+    let (mut host_body, hyper_body) =
+        HostOutgoingBody::with_config(StreamContext::Request, None, &config);
+    let mut stream = host_body.take_output_stream().unwrap();
+    assert_eq!(stream.check_write()?, 4);
+    stream.write(Bytes::from_static(b"abcd"))?;
+    stream.write(Bytes::from_static(b"efgh"))?;
+    assert!(stream.check_write().is_ok());
+    assert!(stream.write(Bytes::from_static(b"i")).is_err());
+    drop(stream);
+
+    assert!(matches!(
+        host_body.finish(None),
+        Err(ErrorCode::HttpRequestBodySize(Some(9)))
+    ));
+    assert!(matches!(
+        hyper_body.collect().await,
+        Err(ErrorCode::HttpRequestBodySize(Some(9)))
+    ));
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn body_config_limits_incoming_body_size() -> Result<()> {
+    use wasmtime_wasi::{HostInputStream, StreamError, Subscribe};
+    use wasmtime_wasi_http::body::{HostIncomingBody, StreamContext};
+
+    let body = body::full(Bytes::from_static(b"hello"))
+        .map_err(|_| unreachable!())
+        .boxed();
+    let mut host_body = HostIncomingBody::new(body, std::time::Duration::from_secs(10));
+    host_body.limit_size(StreamContext::Response, 4)?;
+
+    let mut stream = host_body.take_stream().unwrap();
+    // The limit can't be changed once the stream is taken.
+    assert!(host_body.limit_size(StreamContext::Response, 8).is_err());
+    stream.ready().await;
+    match stream.read(16) {
+        Err(StreamError::LastOperationFailed(e)) => assert!(matches!(
+            e.downcast_ref::<ErrorCode>(),
+            Some(ErrorCode::HttpResponseBodySize(Some(5)))
+        )),
+        other => panic!("unexpected read result: {other:?}"),
+    }
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn body_config_times_out_idle_writes() -> Result<()> {
+    use wasmtime_wasi_http::body::{BodyConfig, HostOutgoingBody, StreamContext};
+
+    let mut config = BodyConfig::new();
+    config.write_idle_timeout(Some(std::time::Duration::from_millis(50)));
+
+    let (_host_body, mut hyper_body) =
+        HostOutgoingBody::with_config(StreamContext::Response, None, &config);
+    assert!(matches!(
+        hyper_body.frame().await,
+        Some(Err(ErrorCode::ConnectionWriteTimeout))
+    ));
+    Ok(())
+}
@@ -3,15 +3,17 @@ use crate::{
         host::{monotonic_clock, wall_clock},
         HostMonotonicClock, HostWallClock,
     },
-    filesystem::{Dir, OpenMode},
-    network::{SocketAddrCheck, SocketAddrUse},
+    filesystem::{Descriptor, Dir, OpenMode, VirtualDir, WasiDir},
+    network::{SocketAddrCheck, SocketAddrUse, SocketBackend},
     pipe, random, stdio,
     stdio::{StdinStream, StdoutStream},
-    DirPerms, FilePerms,
+    trace::Trace,
+    DirPerms, FilePerms, OverlayDir,
 };
 use anyhow::Result;
 use cap_rand::{Rng, RngCore, SeedableRng};
 use cap_std::ambient_authority;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::sync::Arc;
 use std::{future::Future, pin::Pin};
@@ -44,8 +46,9 @@ pub struct WasiCtxBuilder {
     stderr: Box<dyn StdoutStream>,
     env: Vec<(String, String)>,
     args: Vec<String>,
-    preopens: Vec<(Dir, String)>,
+    preopens: Vec<(Descriptor, String)>,
     socket_addr_check: SocketAddrCheck,
+    socket_backend: Option<Arc<dyn SocketBackend>>,
     random: Box<dyn RngCore + Send>,
     insecure_random: Box<dyn RngCore + Send>,
     insecure_random_seed: u128,
@@ -53,6 +56,7 @@ pub struct WasiCtxBuilder {
     monotonic_clock: Box<dyn HostMonotonicClock + Send>,
     allowed_network_uses: AllowedNetworkUses,
     allow_blocking_current_thread: bool,
+    trace: Trace,
     built: bool,
 }
 
@@ -95,6 +99,7 @@ impl WasiCtxBuilder {
             args: Vec::new(),
             preopens: Vec::new(),
             socket_addr_check: SocketAddrCheck::default(),
+            socket_backend: None,
             random: random::thread_rng(),
             insecure_random,
             insecure_random_seed,
@@ -102,6 +107,7 @@ impl WasiCtxBuilder {
             monotonic_clock: monotonic_clock(),
             allowed_network_uses: AllowedNetworkUses::default(),
             allow_blocking_current_thread: false,
+            trace: Trace::Off,
             built: false,
         }
     }
@@ -340,18 +346,99 @@ impl WasiCtxBuilder {
             open_mode |= OpenMode::WRITE;
         }
         self.preopens.push((
-            Dir::new(
+            Descriptor::Dir(Dir::new(
                 dir,
                 dir_perms,
                 file_perms,
                 open_mode,
                 self.allow_blocking_current_thread,
-            ),
+            )),
             guest_path.as_ref().to_owned(),
         ));
         Ok(self)
     }
 
+    /// Configures a "preopened directory" backed by `dir` rather than a
+    /// directory on the host.
+    ///
+    /// This behaves the same as [`WasiCtxBuilder::preopened_dir`] except that
+    /// all filesystem operations within `guest_path` are serviced by the
+    /// [`WasiDir`] implementation provided. This can be used, for example,
+    /// with [`MemoryDir`](crate::MemoryDir) to give WebAssembly a synthesized
+    /// filesystem that doesn't touch the host at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::{WasiCtxBuilder, DirPerms, FilePerms, MemoryDir};
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let data = MemoryDir::new();
+    /// data.write_file("input.txt", "some input")?;
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    ///
+    /// // Make a host directory available as `.` and an in-memory one as
+    /// // `/data`.
+    /// wasi.preopened_dir("./host-directory", ".", DirPerms::all(), FilePerms::all())?;
+    /// wasi.preopened_virtual_dir(data.clone(), "/data", DirPerms::all(), FilePerms::all());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_virtual_dir(
+        &mut self,
+        dir: impl WasiDir,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> &mut Self {
+        self.preopens.push((
+            Descriptor::VirtualDir(VirtualDir::new(Arc::new(dir), dir_perms, file_perms)),
+            guest_path.as_ref().to_owned(),
+        ));
+        self
+    }
+
+    /// Configures a "preopened directory" which is a copy-on-write overlay of
+    /// `host_path`.
+    ///
+    /// This behaves like [`WasiCtxBuilder::preopened_dir`] from WebAssembly's
+    /// perspective, except that the host directory is never modified. All
+    /// writes, creations, deletions, and renames are instead kept in memory
+    /// and discarded when the context is dropped. To inspect the changes made
+    /// by WebAssembly create an [`OverlayDir`](crate::OverlayDir) and pass it
+    /// to [`WasiCtxBuilder::preopened_virtual_dir`] instead.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if `host_path` cannot be opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::{WasiCtxBuilder, DirPerms, FilePerms};
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let mut wasi = WasiCtxBuilder::new();
+    ///
+    /// // Let the guest "modify" `./project` without touching it on the host.
+    /// wasi.preopened_overlay_dir("./project", ".", DirPerms::all(), FilePerms::all())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_overlay_dir(
+        &mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> Result<&mut Self> {
+        let dir = OverlayDir::new(host_path)?;
+        Ok(self.preopened_virtual_dir(dir, guest_path, dir_perms, file_perms))
+    }
+
     /// Set the generator for the `wasi:random/random` number generator to the
     /// custom generator specified.
     ///
@@ -453,6 +540,69 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Creates the guest's sockets on `backend` rather than the host's
+    /// network, and resolves names through it.
+    ///
+    /// Which addresses the guest may use is still governed by
+    /// [`WasiCtxBuilder::socket_addr_check`], so this is typically combined
+    /// with [`WasiCtxBuilder::inherit_network`]. See [`LoopbackNetwork`] for
+    /// an in-process network.
+    ///
+    /// [`LoopbackNetwork`]: crate::LoopbackNetwork
+    pub fn socket_backend(&mut self, backend: impl SocketBackend) -> &mut Self {
+        self.socket_backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Records the results of WASI calls which aren't determined by the guest
+    /// to `trace`, so that the execution can later be reproduced with
+    /// [`WasiCtxBuilder::replay`].
+    ///
+    /// The results of reading clocks, generating random numbers, reading from
+    /// input streams, polling, and reading file data and metadata are
+    /// recorded. Each result is written, and the writer flushed, as soon as
+    /// the call completes, so `trace` will often want to be buffered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trace header can't be written to `trace`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::WasiCtxBuilder;
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let trace = std::fs::File::create("guest.trace")?;
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.inherit_stdio().record(trace)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn record(&mut self, trace: impl Write + Send + 'static) -> Result<&mut Self> {
+        self.trace = Trace::record(Box::new(trace))?;
+        Ok(self)
+    }
+
+    /// Replays a trace written by [`WasiCtxBuilder::record`].
+    ///
+    /// The results of reading clocks, generating random numbers, reading from
+    /// input streams, and polling are taken from `trace` instead of the host.
+    /// Filesystem operations are still performed, but the file data and
+    /// metadata they return are replaced with those from `trace`. The guest
+    /// traps if it makes different calls than were recorded, for example
+    /// because it was given different arguments.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `trace` doesn't start with a valid trace header.
+    pub fn replay(&mut self, trace: impl Read + Send + 'static) -> Result<&mut Self> {
+        self.trace = Trace::replay(Box::new(trace))?;
+        Ok(self)
+    }
+
     /// Uses the configured context so far to construct the final [`WasiCtx`].
     ///
     /// Note that each `WasiCtxBuilder` can only be used to "build" once, and
@@ -474,6 +624,7 @@ impl WasiCtxBuilder {
             args,
             preopens,
             socket_addr_check,
+            socket_backend,
             random,
             insecure_random,
             insecure_random_seed,
@@ -481,6 +632,7 @@ impl WasiCtxBuilder {
             monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            trace,
             built: _,
         } = mem::replace(self, Self::new());
         self.built = true;
@@ -493,6 +645,7 @@ impl WasiCtxBuilder {
             args,
             preopens,
             socket_addr_check,
+            socket_backend,
             random,
             insecure_random,
             insecure_random_seed,
@@ -500,6 +653,7 @@ impl WasiCtxBuilder {
             monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            trace,
         }
     }
 
@@ -647,13 +801,15 @@ pub struct WasiCtx {
     pub(crate) monotonic_clock: Box<dyn HostMonotonicClock + Send>,
     pub(crate) env: Vec<(String, String)>,
     pub(crate) args: Vec<String>,
-    pub(crate) preopens: Vec<(Dir, String)>,
+    pub(crate) preopens: Vec<(Descriptor, String)>,
     pub(crate) stdin: Box<dyn StdinStream>,
     pub(crate) stdout: Box<dyn StdoutStream>,
     pub(crate) stderr: Box<dyn StdoutStream>,
     pub(crate) socket_addr_check: SocketAddrCheck,
+    pub(crate) socket_backend: Option<Arc<dyn SocketBackend>>,
     pub(crate) allowed_network_uses: AllowedNetworkUses,
     pub(crate) allow_blocking_current_thread: bool,
+    pub(crate) trace: Trace,
 }
 
 impl WasiCtx {
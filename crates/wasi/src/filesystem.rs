@@ -1,12 +1,21 @@
 use crate::bindings::filesystem::types;
 use crate::runtime::{spawn_blocking, AbortOnDropJoinHandle};
-use crate::{HostOutputStream, StreamError, Subscribe, TrappableError};
+use crate::{
+    HostInputStream, HostOutputStream, StreamError, StreamResult, Subscribe, TrappableError,
+};
 use anyhow::anyhow;
 use bytes::{Bytes, BytesMut};
+use std::any::Any;
 use std::io;
 use std::mem;
 use std::sync::Arc;
 
+mod memory;
+mod overlay;
+
+pub use self::memory::{MemoryDir, MemoryFile};
+pub use self::overlay::OverlayDir;
+
 pub type FsResult<T> = Result<T, FsError>;
 
 pub type FsError = TrappableError<types::ErrorCode>;
@@ -23,37 +32,101 @@ impl From<io::Error> for FsError {
     }
 }
 
+#[derive(Clone)]
 pub enum Descriptor {
     File(File),
     Dir(Dir),
+    VirtualFile(VirtualFile),
+    VirtualDir(VirtualDir),
 }
 
 impl Descriptor {
+    /// Returns the host file this descriptor refers to, if any.
     pub fn file(&self) -> Result<&File, types::ErrorCode> {
         match self {
             Descriptor::File(f) => Ok(f),
-            Descriptor::Dir(_) => Err(types::ErrorCode::BadDescriptor),
+            _ => Err(types::ErrorCode::BadDescriptor),
         }
     }
 
+    /// Returns the host directory this descriptor refers to, if any.
     pub fn dir(&self) -> Result<&Dir, types::ErrorCode> {
         match self {
             Descriptor::Dir(d) => Ok(d),
-            Descriptor::File(_) => Err(types::ErrorCode::NotDirectory),
+            _ => Err(types::ErrorCode::NotDirectory),
+        }
+    }
+
+    /// Returns the file this descriptor refers to, whether it's a host or
+    /// virtual file.
+    pub(crate) fn any_file(&self) -> Result<FileRef<'_>, types::ErrorCode> {
+        match self {
+            Descriptor::File(f) => Ok(FileRef::Host(f)),
+            Descriptor::VirtualFile(f) => Ok(FileRef::Virtual(f)),
+            Descriptor::Dir(_) | Descriptor::VirtualDir(_) => Err(types::ErrorCode::BadDescriptor),
+        }
+    }
+
+    /// Returns the directory this descriptor refers to, whether it's a host
+    /// or virtual directory.
+    pub(crate) fn any_dir(&self) -> Result<DirRef<'_>, types::ErrorCode> {
+        match self {
+            Descriptor::Dir(d) => Ok(DirRef::Host(d)),
+            Descriptor::VirtualDir(d) => Ok(DirRef::Virtual(d)),
+            Descriptor::File(_) | Descriptor::VirtualFile(_) => Err(types::ErrorCode::NotDirectory),
         }
     }
 
     pub fn is_file(&self) -> bool {
         match self {
-            Descriptor::File(_) => true,
-            Descriptor::Dir(_) => false,
+            Descriptor::File(_) | Descriptor::VirtualFile(_) => true,
+            Descriptor::Dir(_) | Descriptor::VirtualDir(_) => false,
         }
     }
 
     pub fn is_dir(&self) -> bool {
         match self {
-            Descriptor::File(_) => false,
-            Descriptor::Dir(_) => true,
+            Descriptor::File(_) | Descriptor::VirtualFile(_) => false,
+            Descriptor::Dir(_) | Descriptor::VirtualDir(_) => true,
+        }
+    }
+}
+
+/// A borrowed file descriptor, see [`Descriptor::any_file`].
+#[derive(Copy, Clone)]
+pub(crate) enum FileRef<'a> {
+    Host(&'a File),
+    Virtual(&'a VirtualFile),
+}
+
+impl FileRef<'_> {
+    pub fn perms(self) -> FilePerms {
+        match self {
+            FileRef::Host(f) => f.perms,
+            FileRef::Virtual(f) => f.perms,
+        }
+    }
+}
+
+/// A borrowed directory descriptor, see [`Descriptor::any_dir`].
+#[derive(Copy, Clone)]
+pub(crate) enum DirRef<'a> {
+    Host(&'a Dir),
+    Virtual(&'a VirtualDir),
+}
+
+impl DirRef<'_> {
+    pub fn perms(self) -> DirPerms {
+        match self {
+            DirRef::Host(d) => d.perms,
+            DirRef::Virtual(d) => d.perms,
+        }
+    }
+
+    pub fn file_perms(self) -> FilePerms {
+        match self {
+            DirRef::Host(d) => d.file_perms,
+            DirRef::Virtual(d) => d.file_perms,
         }
     }
 }
@@ -233,6 +306,335 @@ impl Dir {
     }
 }
 
+/// A directory whose contents are provided by an embedder-defined
+/// implementation rather than a directory on the host.
+///
+/// Virtual directories are made available to the guest with
+/// [`WasiCtxBuilder::preopened_virtual_dir`] and can be preopened alongside,
+/// or instead of, host directories. An in-memory implementation is provided
+/// with [`MemoryDir`].
+///
+/// Paths passed to these methods are relative to this directory exactly as
+/// the guest provided them. Implementations are responsible for rejecting
+/// paths that would escape this directory, for example absolute paths or
+/// paths containing too many `..` components. Permissions configured through
+/// [`DirPerms`] and [`FilePerms`] are enforced before any method here is
+/// called.
+///
+/// Methods are invoked directly on the thread executing WebAssembly, so
+/// implementations should not block for extended periods of time.
+///
+/// [`WasiCtxBuilder::preopened_virtual_dir`]: crate::WasiCtxBuilder::preopened_virtual_dir
+pub trait WasiDir: Send + Sync + 'static {
+    /// Returns `self` as [`Any`] so [`WasiDir::rename_at`] and
+    /// [`WasiDir::link_at`] can determine whether `new_dir` belongs to the
+    /// same filesystem as `self`.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Opens the file or directory at `path`.
+    ///
+    /// The `oflags` describe whether the file should be created, truncated,
+    /// or whether it must be a directory.
+    fn open_at(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+        oflags: types::OpenFlags,
+    ) -> FsResult<WasiNode>;
+
+    /// Returns metadata for this directory.
+    fn stat(&self) -> FsResult<types::DescriptorStat>;
+
+    /// Returns metadata for the file or directory at `path`.
+    fn stat_at(&self, path: &str, follow_symlinks: bool) -> FsResult<types::DescriptorStat>;
+
+    /// Updates the timestamps of this directory.
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()>;
+
+    /// Updates the timestamps of the file or directory at `path`.
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()>;
+
+    /// Returns all entries of this directory, excluding `.` and `..`.
+    fn read_directory(&self) -> FsResult<Vec<types::DirectoryEntry>>;
+
+    /// Creates a new directory at `path`.
+    fn create_directory_at(&self, path: &str) -> FsResult<()>;
+
+    /// Removes the empty directory at `path`.
+    fn remove_directory_at(&self, path: &str) -> FsResult<()>;
+
+    /// Removes the non-directory at `path`.
+    fn unlink_file_at(&self, path: &str) -> FsResult<()>;
+
+    /// Moves `old_path` in this directory to `new_path` in `new_dir`.
+    ///
+    /// Implementations should return `cross-device` if `new_dir` isn't part of
+    /// the same filesystem.
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> FsResult<()>;
+
+    /// Creates a hard link at `new_path` in `new_dir` to `old_path`.
+    ///
+    /// By default this returns `unsupported`.
+    fn link_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> FsResult<()> {
+        let _ = (old_path, new_dir, new_path);
+        Err(types::ErrorCode::Unsupported.into())
+    }
+
+    /// Creates a symbolic link at `dest_path` whose contents are `src_path`.
+    ///
+    /// By default this returns `unsupported`.
+    fn symlink_at(&self, src_path: &str, dest_path: &str) -> FsResult<()> {
+        let _ = (src_path, dest_path);
+        Err(types::ErrorCode::Unsupported.into())
+    }
+
+    /// Reads the contents of the symbolic link at `path`.
+    ///
+    /// By default this assumes the filesystem has no symbolic links, so it
+    /// returns `invalid` for any `path` that exists.
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        self.stat_at(path, false)?;
+        Err(types::ErrorCode::Invalid.into())
+    }
+
+    /// Returns a hash of the identity of this directory.
+    fn metadata_hash(&self) -> FsResult<types::MetadataHashValue>;
+
+    /// Returns a hash of the identity of the file or directory at `path`.
+    fn metadata_hash_at(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> FsResult<types::MetadataHashValue>;
+
+    /// Synchronizes this directory to storage, which by default does nothing.
+    fn sync(&self) -> FsResult<()> {
+        Ok(())
+    }
+}
+
+/// A file whose contents are provided by an embedder-defined implementation
+/// rather than a file on the host.
+///
+/// Files are created by opening them through [`WasiDir::open_at`]. See
+/// [`WasiDir`] for more information.
+pub trait WasiFile: Send + Sync + 'static {
+    /// Returns metadata for this file.
+    fn stat(&self) -> FsResult<types::DescriptorStat>;
+
+    /// Truncates or extends this file to `size` bytes.
+    fn set_size(&self, size: u64) -> FsResult<()>;
+
+    /// Updates the timestamps of this file.
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()>;
+
+    /// Reads from this file at `offset` into `buf`, returning the number of
+    /// bytes read. Zero is returned at the end of the file.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> FsResult<usize>;
+
+    /// Writes `buf` to this file at `offset`, returning the number of bytes
+    /// written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> FsResult<usize>;
+
+    /// Writes `buf` to the end of this file, returning the number of bytes
+    /// written.
+    fn append(&self, buf: &[u8]) -> FsResult<usize>;
+
+    /// Returns a hash of the identity of this file.
+    fn metadata_hash(&self) -> FsResult<types::MetadataHashValue>;
+
+    /// Synchronizes this file to storage, which by default does nothing.
+    fn sync(&self) -> FsResult<()> {
+        Ok(())
+    }
+}
+
+/// The result of [`WasiDir::open_at`].
+pub enum WasiNode {
+    File(Arc<dyn WasiFile>),
+    Dir(Arc<dyn WasiDir>),
+}
+
+/// A file opened through a [`WasiDir`].
+#[derive(Clone)]
+pub struct VirtualFile {
+    /// The implementation of this file.
+    pub file: Arc<dyn WasiFile>,
+    /// Permissions to enforce on access to the file, inherited from the
+    /// directory it was opened from.
+    pub perms: FilePerms,
+    /// The mode the file was opened under.
+    pub open_mode: OpenMode,
+}
+
+impl VirtualFile {
+    pub(crate) fn read_at(&self, buf: &mut [u8], offset: u64) -> FsResult<usize> {
+        self.check_mode(OpenMode::READ)?;
+        self.file.read_at(buf, offset)
+    }
+
+    pub(crate) fn write_at(&self, buf: &[u8], offset: u64) -> FsResult<usize> {
+        self.check_mode(OpenMode::WRITE)?;
+        self.file.write_at(buf, offset)
+    }
+
+    pub(crate) fn append(&self, buf: &[u8]) -> FsResult<usize> {
+        self.check_mode(OpenMode::WRITE)?;
+        self.file.append(buf)
+    }
+
+    pub(crate) fn set_size(&self, size: u64) -> FsResult<()> {
+        self.check_mode(OpenMode::WRITE)?;
+        self.file.set_size(size)
+    }
+
+    /// Emulates the operating system rejecting reads of files not opened for
+    /// reading, and writes of files not opened for writing.
+    fn check_mode(&self, mode: OpenMode) -> FsResult<()> {
+        if self.open_mode.contains(mode) {
+            Ok(())
+        } else {
+            Err(types::ErrorCode::BadDescriptor.into())
+        }
+    }
+}
+
+/// A directory implemented by a [`WasiDir`].
+#[derive(Clone)]
+pub struct VirtualDir {
+    /// The implementation of this directory.
+    pub dir: Arc<dyn WasiDir>,
+    /// Permissions to enforce on access to this directory and any directories
+    /// opened under it.
+    pub perms: DirPerms,
+    /// Permissions to enforce on any files opened under this directory.
+    pub file_perms: FilePerms,
+    /// The mode the directory was opened under.
+    pub open_mode: OpenMode,
+}
+
+impl VirtualDir {
+    pub fn new(dir: Arc<dyn WasiDir>, perms: DirPerms, file_perms: FilePerms) -> Self {
+        let mut open_mode = OpenMode::empty();
+        if perms.contains(DirPerms::READ) {
+            open_mode |= OpenMode::READ;
+        }
+        if perms.contains(DirPerms::MUTATE) {
+            open_mode |= OpenMode::WRITE;
+        }
+        VirtualDir {
+            dir,
+            perms,
+            file_perms,
+            open_mode,
+        }
+    }
+}
+
+/// Converts an error from a [`WasiFile`] into one suitable for a stream,
+/// retaining the `error-code` so `filesystem-error-code` can recover it.
+fn virtual_stream_error(err: FsError) -> StreamError {
+    match err.downcast() {
+        Ok(code) => StreamError::LastOperationFailed(code.into()),
+        Err(e) => StreamError::Trap(e),
+    }
+}
+
+pub(crate) struct VirtualFileInputStream {
+    file: VirtualFile,
+    position: u64,
+}
+
+impl VirtualFileInputStream {
+    pub fn new(file: &VirtualFile, position: u64) -> Self {
+        Self {
+            file: file.clone(),
+            position,
+        }
+    }
+}
+
+impl HostInputStream for VirtualFileInputStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        let mut buf = BytesMut::zeroed(size);
+        let n = self
+            .file
+            .read_at(&mut buf, self.position)
+            .map_err(virtual_stream_error)?;
+        if n == 0 && size > 0 {
+            return Err(StreamError::Closed);
+        }
+        buf.truncate(n);
+        self.position += n as u64;
+        Ok(buf.freeze())
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for VirtualFileInputStream {
+    async fn ready(&mut self) {}
+}
+
+pub(crate) struct VirtualFileOutputStream {
+    file: VirtualFile,
+    mode: FileOutputMode,
+}
+
+impl VirtualFileOutputStream {
+    pub fn write_at(file: &VirtualFile, position: u64) -> Self {
+        Self {
+            file: file.clone(),
+            mode: FileOutputMode::Position(position),
+        }
+    }
+
+    pub fn append(file: &VirtualFile) -> Self {
+        Self {
+            file: file.clone(),
+            mode: FileOutputMode::Append,
+        }
+    }
+}
+
+impl HostOutputStream for VirtualFileOutputStream {
+    fn write(&mut self, mut buf: Bytes) -> StreamResult<()> {
+        while !buf.is_empty() {
+            let nwritten = match &mut self.mode {
+                FileOutputMode::Position(p) => {
+                    let n = self.file.write_at(&buf, *p).map_err(virtual_stream_error)?;
+                    *p += n as u64;
+                    n
+                }
+                FileOutputMode::Append => self.file.append(&buf).map_err(virtual_stream_error)?,
+            };
+            if nwritten == 0 {
+                return Err(StreamError::LastOperationFailed(
+                    types::ErrorCode::InsufficientSpace.into(),
+                ));
+            }
+            let _ = buf.split_to(nwritten);
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(FILE_WRITE_CAPACITY)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for VirtualFileOutputStream {
+    async fn ready(&mut self) {}
+}
+
 pub struct FileInputStream {
     file: File,
     position: u64,
@@ -0,0 +1,996 @@
+//! An in-memory filesystem implementing [`WasiDir`] and [`WasiFile`].
+
+use super::{FsResult, WasiDir, WasiFile, WasiNode};
+use crate::bindings::clocks::wall_clock;
+use crate::bindings::filesystem::types::{
+    DescriptorStat, DescriptorType, DirectoryEntry, ErrorCode, MetadataHashValue, NewTimestamp,
+    OpenFlags,
+};
+use anyhow::Context;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A directory in an in-memory filesystem.
+///
+/// A `MemoryDir` is a handle to a directory which may be shared: cloning it
+/// produces another handle to the same directory. This enables an embedder
+/// to populate a filesystem before preopening it with
+/// [`WasiCtxBuilder::preopened_virtual_dir`] and to inspect what the guest
+/// wrote to it afterwards.
+///
+/// The filesystem supports directories, regular files, hard links, and
+/// timestamps. Symbolic links are not supported.
+///
+/// The total size of the files in the filesystem is limited by its capacity,
+/// which is 1 GiB for filesystems created with [`MemoryDir::new`] and can be
+/// configured with [`MemoryDir::with_capacity`]. Writes that would exceed it
+/// fail with `insufficient-space`, or `file-too-large` if the file alone
+/// would be larger than the capacity.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::{DirPerms, FilePerms, MemoryDir, WasiCtxBuilder};
+///
+/// # fn main() -> wasmtime::Result<()> {
+/// let root = MemoryDir::new();
+/// root.write_file("etc/motd", "hello from memory\n")?;
+///
+/// let mut wasi = WasiCtxBuilder::new();
+/// wasi.preopened_virtual_dir(root.clone(), "/", DirPerms::all(), FilePerms::all());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`WasiCtxBuilder::preopened_virtual_dir`]: crate::WasiCtxBuilder::preopened_virtual_dir
+#[derive(Clone)]
+pub struct MemoryDir(Handle);
+
+/// A regular file in an in-memory filesystem, opened from a [`MemoryDir`].
+#[derive(Clone)]
+pub struct MemoryFile(Handle);
+
+/// The capacity of a filesystem created with [`MemoryDir::new`].
+const DEFAULT_CAPACITY: u64 = 1 << 30;
+
+struct Fs {
+    inodes: HashMap<u64, Inode>,
+    next_ino: u64,
+    /// Maximum total size of all files, in bytes.
+    capacity: u64,
+    /// Total size of all files, in bytes.
+    used: u64,
+}
+
+struct Inode {
+    kind: Kind,
+    /// Number of directory entries referring to this inode.
+    nlink: u64,
+    /// Number of live [`Handle`]s referring to this inode.
+    handles: usize,
+    atime: SystemTime,
+    mtime: SystemTime,
+    ctime: SystemTime,
+}
+
+enum Kind {
+    Dir(BTreeMap<String, u64>),
+    File(Vec<u8>),
+}
+
+/// A reference to an inode which keeps it alive even after it has been
+/// unlinked, as is the case for files that are open while being removed.
+struct Handle {
+    fs: Arc<Mutex<Fs>>,
+    ino: u64,
+}
+
+/// Where a path leads, as resolved by [`Fs::target`].
+enum Target<'a> {
+    /// The path ends in `.` or `..` and resolved to this directory.
+    Dir(u64),
+    /// The path names `name` in the directory `parent`, which may or may not
+    /// exist.
+    Entry {
+        parent: u64,
+        name: &'a str,
+        trailing_slash: bool,
+    },
+}
+
+impl Inode {
+    fn new(kind: Kind) -> Inode {
+        let now = SystemTime::now();
+        Inode {
+            kind,
+            nlink: 1,
+            handles: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    fn descriptor_type(&self) -> DescriptorType {
+        match self.kind {
+            Kind::Dir(_) => DescriptorType::Directory,
+            Kind::File(_) => DescriptorType::RegularFile,
+        }
+    }
+
+    fn stat(&self) -> DescriptorStat {
+        DescriptorStat {
+            type_: self.descriptor_type(),
+            link_count: self.nlink,
+            size: match &self.kind {
+                Kind::Dir(_) => 0,
+                Kind::File(data) => data.len() as u64,
+            },
+            data_access_timestamp: datetime_from(self.atime),
+            data_modification_timestamp: datetime_from(self.mtime),
+            status_change_timestamp: datetime_from(self.ctime),
+        }
+    }
+
+    fn set_times(&mut self, atim: NewTimestamp, mtim: NewTimestamp) -> FsResult<()> {
+        let now = SystemTime::now();
+        if let Some(t) = systemtime_from(atim, now)? {
+            self.atime = t;
+        }
+        if let Some(t) = systemtime_from(mtim, now)? {
+            self.mtime = t;
+        }
+        self.ctime = now;
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<&BTreeMap<String, u64>, ErrorCode> {
+        match &self.kind {
+            Kind::Dir(entries) => Ok(entries),
+            Kind::File(_) => Err(ErrorCode::NotDirectory),
+        }
+    }
+
+    fn entries_mut(&mut self) -> Result<&mut BTreeMap<String, u64>, ErrorCode> {
+        match &mut self.kind {
+            Kind::Dir(entries) => Ok(entries),
+            Kind::File(_) => Err(ErrorCode::NotDirectory),
+        }
+    }
+
+    fn data_mut(&mut self) -> &mut Vec<u8> {
+        match &mut self.kind {
+            Kind::File(data) => data,
+            Kind::Dir(_) => unreachable!("file handle refers to a directory"),
+        }
+    }
+
+    fn touch(&mut self) {
+        let now = SystemTime::now();
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+impl Fs {
+    fn inode(&self, ino: u64) -> &Inode {
+        &self.inodes[&ino]
+    }
+
+    fn inode_mut(&mut self, ino: u64) -> &mut Inode {
+        self.inodes.get_mut(&ino).unwrap()
+    }
+
+    fn is_dir(&self, ino: u64) -> bool {
+        matches!(self.inode(ino).kind, Kind::Dir(_))
+    }
+
+    /// Walks `path` starting at the directory `start`.
+    ///
+    /// Paths are not allowed to be absolute nor to use `..` to leave `start`.
+    fn target<'a>(&self, start: u64, path: &'a str) -> FsResult<Target<'a>> {
+        if path.is_empty() {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let trailing_slash = path.ends_with('/');
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        let mut stack = vec![start];
+        while let Some(component) = components.next() {
+            let last = components.peek().is_none();
+            let cur = *stack.last().unwrap();
+            match component {
+                "." => {}
+                ".." => {
+                    if stack.len() == 1 {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                    stack.pop();
+                }
+                name if last => {
+                    self.inode(cur).entries()?;
+                    return Ok(Target::Entry {
+                        parent: cur,
+                        name,
+                        trailing_slash,
+                    });
+                }
+                name => {
+                    let ino = *self
+                        .inode(cur)
+                        .entries()?
+                        .get(name)
+                        .ok_or(ErrorCode::NoEntry)?;
+                    // Only directories may be followed by more components,
+                    // even if they're `..`.
+                    if !self.is_dir(ino) {
+                        return Err(ErrorCode::NotDirectory.into());
+                    }
+                    stack.push(ino);
+                }
+            }
+        }
+        let dir = *stack.last().unwrap();
+        self.inode(dir).entries()?;
+        Ok(Target::Dir(dir))
+    }
+
+    /// Returns the inode that `path` refers to, relative to `start`.
+    fn lookup(&self, start: u64, path: &str) -> FsResult<u64> {
+        match self.target(start, path)? {
+            Target::Dir(ino) => Ok(ino),
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => {
+                let ino = *self
+                    .inode(parent)
+                    .entries()?
+                    .get(name)
+                    .ok_or(ErrorCode::NoEntry)?;
+                if trailing_slash && !self.is_dir(ino) {
+                    return Err(ErrorCode::NotDirectory.into());
+                }
+                Ok(ino)
+            }
+        }
+    }
+
+    /// Allocates a new inode and links it into `parent` as `name`.
+    fn create(&mut self, parent: u64, name: &str, kind: Kind) -> FsResult<u64> {
+        if self.inode(parent).nlink == 0 {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.inodes.insert(ino, Inode::new(kind));
+        let parent = self.inode_mut(parent);
+        parent.entries_mut()?.insert(name.to_string(), ino);
+        parent.touch();
+        Ok(ino)
+    }
+
+    /// Removes the entry `name` from `parent`, dropping the inode it refers to
+    /// if nothing else refers to it.
+    fn unlink(&mut self, parent: u64, name: &str) {
+        let parent = self.inode_mut(parent);
+        let ino = parent.entries_mut().unwrap().remove(name).unwrap();
+        parent.touch();
+        let inode = self.inode_mut(ino);
+        inode.nlink -= 1;
+        inode.ctime = SystemTime::now();
+        self.release(ino);
+    }
+
+    fn release(&mut self, ino: u64) {
+        let inode = self.inode(ino);
+        if inode.nlink == 0 && inode.handles == 0 {
+            if let Some(Inode {
+                kind: Kind::File(data),
+                ..
+            }) = self.inodes.remove(&ino)
+            {
+                self.used -= data.len() as u64;
+            }
+        }
+    }
+
+    /// Accounts for a file changing size from `old_len` to `new_len` bytes,
+    /// failing if that would exceed the capacity of the filesystem.
+    fn reserve(&mut self, old_len: usize, new_len: u64) -> FsResult<()> {
+        if new_len > self.capacity {
+            return Err(ErrorCode::FileTooLarge.into());
+        }
+        let used = self.used - old_len as u64 + new_len;
+        if used > self.capacity {
+            return Err(ErrorCode::InsufficientSpace.into());
+        }
+        self.used = used;
+        Ok(())
+    }
+
+    /// Resizes the file `ino` to `len` bytes, filling any new space with
+    /// zeros.
+    fn resize(&mut self, ino: u64, len: u64) -> FsResult<()> {
+        let old_len = self.inode_mut(ino).data_mut().len();
+        self.reserve(old_len, len)?;
+        let len = usize::try_from(len).map_err(|_| ErrorCode::FileTooLarge)?;
+        self.inode_mut(ino).data_mut().resize(len, 0);
+        Ok(())
+    }
+
+    /// Returns whether `ino` is `dir` or is contained anywhere within it.
+    fn is_within(&self, ino: u64, dir: u64) -> bool {
+        if ino == dir {
+            return true;
+        }
+        match &self.inode(dir).kind {
+            Kind::Dir(entries) => entries
+                .values()
+                .any(|&child| self.is_dir(child) && self.is_within(ino, child)),
+            Kind::File(_) => false,
+        }
+    }
+}
+
+impl Handle {
+    fn new(fs: &Arc<Mutex<Fs>>, state: &mut Fs, ino: u64) -> Handle {
+        state.inode_mut(ino).handles += 1;
+        Handle {
+            fs: fs.clone(),
+            ino,
+        }
+    }
+
+    fn node(&self, state: &mut Fs, ino: u64) -> WasiNode {
+        let handle = Handle::new(&self.fs, state, ino);
+        if state.is_dir(ino) {
+            WasiNode::Dir(Arc::new(MemoryDir(handle)))
+        } else {
+            WasiNode::File(Arc::new(MemoryFile(handle)))
+        }
+    }
+
+    fn metadata_hash(&self, ino: u64) -> MetadataHashValue {
+        use std::hash::Hasher;
+        // Mirror the host implementation by hashing a "device", here the
+        // identity of this filesystem, along with the inode number.
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hasher.write_usize(Arc::as_ptr(&self.fs) as usize);
+        hasher.write_u64(ino);
+        let lower = hasher.finish();
+        let upper = lower ^ 4614256656552045848u64;
+        MetadataHashValue { lower, upper }
+    }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Handle {
+        let mut state = self.fs.lock().unwrap();
+        Handle::new(&self.fs, &mut state, self.ino)
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let mut state = self.fs.lock().unwrap();
+        state.inode_mut(self.ino).handles -= 1;
+        state.release(self.ino);
+    }
+}
+
+impl MemoryDir {
+    /// Creates a new, empty, in-memory filesystem with a capacity of 1 GiB
+    /// and returns its root directory.
+    pub fn new() -> MemoryDir {
+        MemoryDir::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new, empty, in-memory filesystem whose files may take up to
+    /// `capacity` bytes in total and returns its root directory.
+    pub fn with_capacity(capacity: u64) -> MemoryDir {
+        let mut state = Fs {
+            inodes: HashMap::new(),
+            next_ino: 1,
+            capacity,
+            used: 0,
+        };
+        state
+            .inodes
+            .insert(0, Inode::new(Kind::Dir(BTreeMap::new())));
+        let fs = Arc::new(Mutex::new(state));
+        let mut state = fs.lock().unwrap();
+        let handle = Handle::new(&fs, &mut state, 0);
+        drop(state);
+        MemoryDir(handle)
+    }
+
+    /// Creates the directory `path`, along with any missing parent
+    /// directories, and returns a handle to it.
+    pub fn create_dir(&self, path: &str) -> anyhow::Result<MemoryDir> {
+        let mut state = self.0.fs.lock().unwrap();
+        let ino = create_dir_all(&mut state, self.0.ino, path)
+            .with_context(|| format!("failed to create directory `{path}`"))?;
+        Ok(MemoryDir(Handle::new(&self.0.fs, &mut state, ino)))
+    }
+
+    /// Same as [`MemoryDir::create_dir`] but without returning a handle.
+    pub(crate) fn create_dir_all(&self, path: &str) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        create_dir_all(&mut state, self.0.ino, path)?;
+        Ok(())
+    }
+
+    /// Writes `contents` to the file `path`, replacing it if it already exists
+    /// and creating any missing parent directories.
+    pub fn write_file(&self, path: &str, contents: impl Into<Vec<u8>>) -> anyhow::Result<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        let result = (|| -> FsResult<()> {
+            let (dir, name) = match path.rsplit_once('/') {
+                Some((dir, name)) => (create_dir_all(&mut state, self.0.ino, dir)?, name),
+                None => (self.0.ino, path),
+            };
+            let (parent, name) = match state.target(dir, name)? {
+                Target::Entry {
+                    parent,
+                    name,
+                    trailing_slash: false,
+                } => (parent, name),
+                _ => return Err(ErrorCode::IsDirectory.into()),
+            };
+            let ino = match state.inode(parent).entries()?.get(name) {
+                Some(&ino) if state.is_dir(ino) => return Err(ErrorCode::IsDirectory.into()),
+                Some(&ino) => ino,
+                None => state.create(parent, name, Kind::File(Vec::new()))?,
+            };
+            let contents = contents.into();
+            let old_len = state.inode_mut(ino).data_mut().len();
+            state.reserve(old_len, contents.len() as u64)?;
+            let inode = state.inode_mut(ino);
+            *inode.data_mut() = contents;
+            inode.touch();
+            Ok(())
+        })();
+        result.with_context(|| format!("failed to write file `{path}`"))
+    }
+
+    /// Returns the contents of the file `path`.
+    pub fn read_file(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let state = self.0.fs.lock().unwrap();
+        let result = state
+            .lookup(self.0.ino, path)
+            .and_then(|ino| match &state.inode(ino).kind {
+                Kind::File(data) => Ok(data.clone()),
+                Kind::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+            });
+        result.with_context(|| format!("failed to read file `{path}`"))
+    }
+}
+
+fn create_dir_all(state: &mut Fs, start: u64, path: &str) -> FsResult<u64> {
+    if path.starts_with('/') {
+        return Err(ErrorCode::NotPermitted.into());
+    }
+    let mut dir = start;
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        dir = match state.target(dir, component)? {
+            Target::Dir(ino) => ino,
+            Target::Entry { parent, name, .. } => match state.inode(parent).entries()?.get(name) {
+                Some(&ino) if state.is_dir(ino) => ino,
+                Some(_) => return Err(ErrorCode::NotDirectory.into()),
+                None => state.create(parent, name, Kind::Dir(BTreeMap::new()))?,
+            },
+        };
+    }
+    Ok(dir)
+}
+
+impl Default for MemoryDir {
+    fn default() -> MemoryDir {
+        MemoryDir::new()
+    }
+}
+
+impl WasiDir for MemoryDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn open_at(&self, path: &str, _follow_symlinks: bool, oflags: OpenFlags) -> FsResult<WasiNode> {
+        let mut state = self.0.fs.lock().unwrap();
+        let exclusive = oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE);
+        let ino = match state.target(self.0.ino, path)? {
+            Target::Dir(ino) => {
+                if exclusive {
+                    return Err(ErrorCode::Exist.into());
+                }
+                ino
+            }
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => match state.inode(parent).entries()?.get(name) {
+                Some(_) if exclusive => return Err(ErrorCode::Exist.into()),
+                Some(&ino) => {
+                    if !state.is_dir(ino)
+                        && (trailing_slash || oflags.contains(OpenFlags::DIRECTORY))
+                    {
+                        return Err(ErrorCode::NotDirectory.into());
+                    }
+                    ino
+                }
+                None if oflags.contains(OpenFlags::CREATE) => {
+                    if trailing_slash {
+                        return Err(ErrorCode::IsDirectory.into());
+                    }
+                    state.create(parent, name, Kind::File(Vec::new()))?
+                }
+                None => return Err(ErrorCode::NoEntry.into()),
+            },
+        };
+        if oflags.contains(OpenFlags::TRUNCATE) {
+            if state.is_dir(ino) {
+                return Err(ErrorCode::IsDirectory.into());
+            }
+            if !state.inode_mut(ino).data_mut().is_empty() {
+                state.resize(ino, 0)?;
+                state.inode_mut(ino).touch();
+            }
+        }
+        Ok(self.0.node(&mut state, ino))
+    }
+
+    fn stat(&self) -> FsResult<DescriptorStat> {
+        let state = self.0.fs.lock().unwrap();
+        Ok(state.inode(self.0.ino).stat())
+    }
+
+    fn stat_at(&self, path: &str, _follow_symlinks: bool) -> FsResult<DescriptorStat> {
+        let state = self.0.fs.lock().unwrap();
+        let ino = state.lookup(self.0.ino, path)?;
+        Ok(state.inode(ino).stat())
+    }
+
+    fn set_times(&self, atim: NewTimestamp, mtim: NewTimestamp) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        state.inode_mut(self.0.ino).set_times(atim, mtim)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        _follow_symlinks: bool,
+        atim: NewTimestamp,
+        mtim: NewTimestamp,
+    ) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        let ino = state.lookup(self.0.ino, path)?;
+        state.inode_mut(ino).set_times(atim, mtim)
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<DirectoryEntry>> {
+        let state = self.0.fs.lock().unwrap();
+        Ok(state
+            .inode(self.0.ino)
+            .entries()?
+            .iter()
+            .map(|(name, &ino)| DirectoryEntry {
+                type_: state.inode(ino).descriptor_type(),
+                name: name.clone(),
+            })
+            .collect())
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        match state.target(self.0.ino, path)? {
+            Target::Dir(_) => Err(ErrorCode::Exist.into()),
+            Target::Entry { parent, name, .. } => {
+                if state.inode(parent).entries()?.contains_key(name) {
+                    return Err(ErrorCode::Exist.into());
+                }
+                state.create(parent, name, Kind::Dir(BTreeMap::new()))?;
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        let (parent, name) = match state.target(self.0.ino, path)? {
+            Target::Dir(_) => return Err(ErrorCode::Invalid.into()),
+            Target::Entry { parent, name, .. } => (parent, name),
+        };
+        let ino = *state
+            .inode(parent)
+            .entries()?
+            .get(name)
+            .ok_or(ErrorCode::NoEntry)?;
+        if !state.inode(ino).entries()?.is_empty() {
+            return Err(ErrorCode::NotEmpty.into());
+        }
+        state.unlink(parent, name);
+        Ok(())
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        let (parent, name) = match state.target(self.0.ino, path)? {
+            Target::Dir(_) => return Err(ErrorCode::IsDirectory.into()),
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => {
+                let ino = *state
+                    .inode(parent)
+                    .entries()?
+                    .get(name)
+                    .ok_or(ErrorCode::NoEntry)?;
+                if state.is_dir(ino) {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+                if trailing_slash {
+                    return Err(ErrorCode::NotDirectory.into());
+                }
+                (parent, name)
+            }
+        };
+        state.unlink(parent, name);
+        Ok(())
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> FsResult<()> {
+        let new_dir = same_fs(self, new_dir)?;
+        let mut state = self.0.fs.lock().unwrap();
+        let (old_parent, old_name, old_slash) = match state.target(self.0.ino, old_path)? {
+            Target::Dir(_) => return Err(ErrorCode::Invalid.into()),
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => (parent, name, trailing_slash),
+        };
+        let (new_parent, new_name, new_slash) = match state.target(new_dir.0.ino, new_path)? {
+            Target::Dir(_) => return Err(ErrorCode::Invalid.into()),
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => (parent, name, trailing_slash),
+        };
+        let ino = *state
+            .inode(old_parent)
+            .entries()?
+            .get(old_name)
+            .ok_or(ErrorCode::NoEntry)?;
+        let is_dir = state.is_dir(ino);
+        if !is_dir && (old_slash || new_slash) {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        if is_dir && state.is_within(new_parent, ino) {
+            return Err(ErrorCode::Invalid.into());
+        }
+        if state.inode(new_parent).nlink == 0 {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        match state.inode(new_parent).entries()?.get(new_name) {
+            Some(&existing) if existing == ino => return Ok(()),
+            Some(&existing) => {
+                match (is_dir, &state.inode(existing).kind) {
+                    (true, Kind::Dir(entries)) if !entries.is_empty() => {
+                        return Err(ErrorCode::NotEmpty.into())
+                    }
+                    (true, Kind::File(_)) => return Err(ErrorCode::NotDirectory.into()),
+                    (false, Kind::Dir(_)) => return Err(ErrorCode::IsDirectory.into()),
+                    _ => {}
+                }
+                state.unlink(new_parent, new_name);
+            }
+            None => {}
+        }
+        let old = state.inode_mut(old_parent);
+        old.entries_mut()?.remove(old_name);
+        old.touch();
+        let new = state.inode_mut(new_parent);
+        new.entries_mut()?.insert(new_name.to_string(), ino);
+        new.touch();
+        state.inode_mut(ino).ctime = SystemTime::now();
+        Ok(())
+    }
+
+    fn link_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> FsResult<()> {
+        let new_dir = same_fs(self, new_dir)?;
+        let mut state = self.0.fs.lock().unwrap();
+        let ino = state.lookup(self.0.ino, old_path)?;
+        if state.is_dir(ino) {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        match state.target(new_dir.0.ino, new_path)? {
+            Target::Dir(_) => Err(ErrorCode::Exist.into()),
+            Target::Entry {
+                parent,
+                name,
+                trailing_slash,
+            } => {
+                if state.inode(parent).entries()?.contains_key(name) {
+                    return Err(ErrorCode::Exist.into());
+                }
+                if trailing_slash {
+                    return Err(ErrorCode::NotDirectory.into());
+                }
+                if state.inode(parent).nlink == 0 {
+                    return Err(ErrorCode::NoEntry.into());
+                }
+                let dir = state.inode_mut(parent);
+                dir.entries_mut()?.insert(name.to_string(), ino);
+                dir.touch();
+                let inode = state.inode_mut(ino);
+                inode.nlink += 1;
+                inode.ctime = SystemTime::now();
+                Ok(())
+            }
+        }
+    }
+
+    fn metadata_hash(&self) -> FsResult<MetadataHashValue> {
+        Ok(self.0.metadata_hash(self.0.ino))
+    }
+
+    fn metadata_hash_at(&self, path: &str, _follow_symlinks: bool) -> FsResult<MetadataHashValue> {
+        let state = self.0.fs.lock().unwrap();
+        let ino = state.lookup(self.0.ino, path)?;
+        Ok(self.0.metadata_hash(ino))
+    }
+}
+
+/// Returns `new_dir` as a [`MemoryDir`] if it's part of the same filesystem
+/// as `dir`.
+fn same_fs<'a>(dir: &MemoryDir, new_dir: &'a dyn WasiDir) -> FsResult<&'a MemoryDir> {
+    match new_dir.as_any().downcast_ref::<MemoryDir>() {
+        Some(new_dir) if Arc::ptr_eq(&dir.0.fs, &new_dir.0.fs) => Ok(new_dir),
+        _ => Err(ErrorCode::CrossDevice.into()),
+    }
+}
+
+impl WasiFile for MemoryFile {
+    fn stat(&self) -> FsResult<DescriptorStat> {
+        let state = self.0.fs.lock().unwrap();
+        Ok(state.inode(self.0.ino).stat())
+    }
+
+    fn set_size(&self, size: u64) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        state.resize(self.0.ino, size)?;
+        state.inode_mut(self.0.ino).touch();
+        Ok(())
+    }
+
+    fn set_times(&self, atim: NewTimestamp, mtim: NewTimestamp) -> FsResult<()> {
+        let mut state = self.0.fs.lock().unwrap();
+        state.inode_mut(self.0.ino).set_times(atim, mtim)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> FsResult<usize> {
+        let mut state = self.0.fs.lock().unwrap();
+        let inode = state.inode_mut(self.0.ino);
+        inode.atime = SystemTime::now();
+        let data = inode.data_mut();
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..][..n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> FsResult<usize> {
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(ErrorCode::FileTooLarge)?;
+        let mut state = self.0.fs.lock().unwrap();
+        if (state.inode_mut(self.0.ino).data_mut().len() as u64) < end {
+            state.resize(self.0.ino, end)?;
+        }
+        let inode = state.inode_mut(self.0.ino);
+        inode.data_mut()[offset as usize..][..buf.len()].copy_from_slice(buf);
+        inode.touch();
+        Ok(buf.len())
+    }
+
+    fn append(&self, buf: &[u8]) -> FsResult<usize> {
+        let mut state = self.0.fs.lock().unwrap();
+        let len = state.inode_mut(self.0.ino).data_mut().len();
+        state.resize(self.0.ino, len as u64 + buf.len() as u64)?;
+        let inode = state.inode_mut(self.0.ino);
+        inode.data_mut()[len..].copy_from_slice(buf);
+        inode.touch();
+        Ok(buf.len())
+    }
+
+    fn metadata_hash(&self) -> FsResult<MetadataHashValue> {
+        Ok(self.0.metadata_hash(self.0.ino))
+    }
+}
+
+fn systemtime_from(t: NewTimestamp, now: SystemTime) -> FsResult<Option<SystemTime>> {
+    match t {
+        NewTimestamp::NoChange => Ok(None),
+        NewTimestamp::Now => Ok(Some(now)),
+        NewTimestamp::Timestamp(t) => SystemTime::UNIX_EPOCH
+            .checked_add(Duration::new(t.seconds, t.nanoseconds))
+            .map(Some)
+            .ok_or_else(|| ErrorCode::Overflow.into()),
+    }
+}
+
+fn datetime_from(t: SystemTime) -> Option<wall_clock::Datetime> {
+    let d = t.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    Some(wall_clock::Datetime {
+        seconds: d.as_secs(),
+        nanoseconds: d.subsec_nanos(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn open(dir: &MemoryDir, path: &str, oflags: OpenFlags) -> FsResult<WasiNode> {
+        dir.open_at(path, true, oflags)
+    }
+
+    fn code<T>(r: FsResult<T>) -> ErrorCode {
+        match r {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let root = MemoryDir::new();
+        root.create_directory_at("a").unwrap();
+        let file = match open(&root, "a/b.txt", OpenFlags::CREATE).unwrap() {
+            WasiNode::File(f) => f,
+            WasiNode::Dir(_) => panic!("expected a file"),
+        };
+        assert_eq!(file.write_at(b"hello", 0).unwrap(), 5);
+        assert_eq!(file.append(b" world").unwrap(), 6);
+        assert_eq!(root.read_file("a/b.txt").unwrap(), b"hello world");
+
+        let mut buf = [0; 32];
+        assert_eq!(file.read_at(&mut buf, 6).unwrap(), 5);
+        assert_eq!(&buf[..5], b"world");
+        assert_eq!(file.read_at(&mut buf, 100).unwrap(), 0);
+
+        let stat = root.stat_at("a/b.txt", true).unwrap();
+        assert_eq!(stat.type_, DescriptorType::RegularFile);
+        assert_eq!(stat.size, 11);
+
+        let names: Vec<_> = root
+            .read_directory()
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.type_))
+            .collect();
+        assert_eq!(names, [("a".to_string(), DescriptorType::Directory)]);
+
+        assert_eq!(code(root.create_directory_at("a")), ErrorCode::Exist);
+        assert_eq!(code(root.remove_directory_at("a")), ErrorCode::NotEmpty);
+        assert_eq!(code(root.unlink_file_at("a")), ErrorCode::IsDirectory);
+        assert_eq!(
+            code(root.stat_at("a/b.txt/", true)),
+            ErrorCode::NotDirectory
+        );
+        assert_eq!(
+            code(open(
+                &root,
+                "a/b.txt",
+                OpenFlags::CREATE | OpenFlags::EXCLUSIVE
+            )),
+            ErrorCode::Exist
+        );
+    }
+
+    #[test]
+    fn paths_cannot_escape() {
+        let root = MemoryDir::new();
+        let sub = root.create_dir("sub/dir").unwrap();
+        assert_eq!(code(root.stat_at("/sub", true)), ErrorCode::NotPermitted);
+        assert_eq!(code(root.stat_at("..", true)), ErrorCode::NotPermitted);
+        assert_eq!(code(sub.stat_at("../x", true)), ErrorCode::NotPermitted);
+        assert!(root.stat_at("sub/dir/../dir/./", true).is_ok());
+        root.write_file("sub/file", "").unwrap();
+        assert_eq!(
+            code(root.stat_at("sub/file/../dir", true)),
+            ErrorCode::NotDirectory
+        );
+        assert!(matches!(
+            open(&root, "sub/..", OpenFlags::empty()).unwrap(),
+            WasiNode::Dir(_)
+        ));
+    }
+
+    #[test]
+    fn rename_and_unlink() {
+        let root = MemoryDir::new();
+        root.write_file("x/one", "1").unwrap();
+        let y = root.create_dir("y").unwrap();
+
+        root.rename_at("x/one", &y, "two").unwrap();
+        assert_eq!(code(root.stat_at("x/one", true)), ErrorCode::NoEntry);
+        assert_eq!(root.read_file("y/two").unwrap(), b"1");
+
+        assert_eq!(code(root.rename_at("x", &root, "x/x")), ErrorCode::Invalid);
+        root.rename_at("x", &root, "z").unwrap();
+        assert_eq!(code(root.rename_at("z", &root, "y")), ErrorCode::NotEmpty);
+
+        let other = MemoryDir::new();
+        assert_eq!(
+            code(root.rename_at("z", &other, "z")),
+            ErrorCode::CrossDevice
+        );
+
+        // Files remain readable after being unlinked while open.
+        let file = match open(&root, "y/two", OpenFlags::empty()).unwrap() {
+            WasiNode::File(f) => f,
+            WasiNode::Dir(_) => panic!("expected a file"),
+        };
+        root.unlink_file_at("y/two").unwrap();
+        let mut buf = [0; 1];
+        assert_eq!(file.read_at(&mut buf, 0).unwrap(), 1);
+        assert_eq!(file.stat().unwrap().link_count, 0);
+    }
+
+    #[test]
+    fn capacity() {
+        let root = MemoryDir::with_capacity(10);
+        let file = match open(&root, "f", OpenFlags::CREATE).unwrap() {
+            WasiNode::File(f) => f,
+            WasiNode::Dir(_) => panic!("expected a file"),
+        };
+        assert_eq!(code(file.write_at(b"x", 1 << 40)), ErrorCode::FileTooLarge);
+        assert_eq!(code(file.set_size(1 << 40)), ErrorCode::FileTooLarge);
+        assert_eq!(code(file.write_at(b"x", u64::MAX)), ErrorCode::FileTooLarge);
+        assert_eq!(file.stat().unwrap().size, 0);
+
+        file.set_size(6).unwrap();
+        root.write_file("g", "1234").unwrap();
+        assert_eq!(code(file.append(b"x")), ErrorCode::InsufficientSpace);
+        assert!(root.write_file("h", "x").is_err());
+
+        // Space is reclaimed once files are removed or truncated.
+        root.unlink_file_at("g").unwrap();
+        assert_eq!(file.append(b"1234").unwrap(), 4);
+        open(&root, "f", OpenFlags::TRUNCATE).unwrap();
+        root.write_file("g", "0123456789").unwrap();
+    }
+
+    #[test]
+    fn timestamps() {
+        let root = MemoryDir::new();
+        root.write_file("f", "").unwrap();
+        let time = wall_clock::Datetime {
+            seconds: 1_000_000,
+            nanoseconds: 42,
+        };
+        root.set_times_at(
+            "f",
+            true,
+            NewTimestamp::Timestamp(time),
+            NewTimestamp::NoChange,
+        )
+        .unwrap();
+        let stat = root.stat_at("f", true).unwrap();
+        let atime = stat.data_access_timestamp.unwrap();
+        assert_eq!((atime.seconds, atime.nanoseconds), (1_000_000, 42));
+        assert_ne!(stat.data_modification_timestamp.unwrap().seconds, 1_000_000);
+    }
+}
@@ -0,0 +1,1058 @@
+//! A copy-on-write overlay of a host directory, implementing [`WasiDir`].
+
+use super::{FsResult, MemoryDir, WasiDir, WasiFile, WasiNode};
+use crate::bindings::filesystem::types::{
+    DescriptorStat, DescriptorType, DirectoryEntry, ErrorCode, MetadataHashValue, NewTimestamp,
+    OpenFlags,
+};
+use crate::host::filesystem::{calculate_metadata_hash, descriptorstat_from, descriptortype_from};
+use cap_std::ambient_authority;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use system_interface::fs::FileIoExt;
+
+/// A directory on the host overlaid with a private, in-memory, upper layer.
+///
+/// Reads of an `OverlayDir` fall through to the host directory it was
+/// created from, but the host directory is never modified. Instead all writes,
+/// creations, deletions, and renames are recorded in an upper layer, a
+/// [`MemoryDir`], which shadows the host directory. Files are copied into the
+/// upper layer the first time they're modified.
+///
+/// After WebAssembly has finished running the upper layer can be inspected
+/// with [`OverlayDir::upper`] and [`OverlayDir::removed_paths`], or simply
+/// dropped to discard all changes.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::{DirPerms, FilePerms, OverlayDir, WasiCtxBuilder};
+///
+/// # fn main() {}
+/// # fn foo() -> wasmtime::Result<()> {
+/// let src = OverlayDir::new("./src")?;
+///
+/// let mut wasi = WasiCtxBuilder::new();
+/// wasi.preopened_virtual_dir(src.clone(), "/src", DirPerms::all(), FilePerms::all());
+///
+/// // ... run WebAssembly ...
+///
+/// let generated = src.upper().read_file("generated.rs")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct OverlayDir {
+    overlay: Arc<Overlay>,
+    /// The path of this directory relative to the root of the overlay.
+    ///
+    /// Paths within the overlay are normalized to be `/`-separated without
+    /// any `.` or `..` components, and the root is the empty string.
+    path: String,
+}
+
+struct Overlay {
+    lower: cap_std::fs::Dir,
+    upper: MemoryDir,
+    /// Paths removed from the overlay. Anything in the lower layer at or
+    /// beneath one of these paths is hidden.
+    ///
+    /// This lock is additionally held for the duration of all operations on
+    /// the overlay as they typically need to consult both layers.
+    whiteouts: Mutex<BTreeSet<String>>,
+    /// The lower files which are open, by their current path, so that all of
+    /// their descriptors see the copy in the upper layer once one of them
+    /// is copied up.
+    open_files: Mutex<HashMap<String, Weak<Mutex<LowerFile>>>>,
+}
+
+/// A file opened from the lower layer of an [`OverlayDir`], which is copied
+/// to the upper layer when first modified.
+struct OverlayFile {
+    overlay: Arc<Overlay>,
+    shared: Arc<Mutex<LowerFile>>,
+    lower: cap_std::fs::File,
+}
+
+/// The state shared by all [`OverlayFile`]s opened on the same lower file.
+struct LowerFile {
+    /// The current path of the file, or `None` if it has been removed.
+    path: Option<String>,
+    /// The copy of the file in the upper layer, once it has been copied up.
+    upper: Option<Arc<dyn WasiFile>>,
+}
+
+/// What a path within the overlay refers to.
+enum Entry {
+    Upper(DescriptorStat),
+    Lower(cap_std::fs::Metadata),
+}
+
+type Whiteouts<'a> = MutexGuard<'a, BTreeSet<String>>;
+
+/// The maximum number of symlinks followed while resolving a path, as on
+/// Linux.
+const MAX_SYMLINKS: usize = 40;
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        match self {
+            Entry::Upper(stat) => stat.type_ == DescriptorType::Directory,
+            Entry::Lower(meta) => meta.is_dir(),
+        }
+    }
+
+    fn stat(self) -> DescriptorStat {
+        match self {
+            Entry::Upper(stat) => stat,
+            Entry::Lower(meta) => descriptorstat_from(meta),
+        }
+    }
+}
+
+impl OverlayDir {
+    /// Creates an overlay of the directory `host_path` with an empty upper
+    /// layer.
+    pub fn new(host_path: impl AsRef<Path>) -> io::Result<OverlayDir> {
+        let dir = cap_std::fs::Dir::open_ambient_dir(host_path, ambient_authority())?;
+        Ok(OverlayDir::from_cap_std(dir))
+    }
+
+    /// Same as [`OverlayDir::new`] except for an already opened directory.
+    pub fn from_cap_std(dir: cap_std::fs::Dir) -> OverlayDir {
+        OverlayDir {
+            overlay: Arc::new(Overlay {
+                lower: dir,
+                upper: MemoryDir::new(),
+                whiteouts: Mutex::new(BTreeSet::new()),
+                open_files: Mutex::new(HashMap::new()),
+            }),
+            path: String::new(),
+        }
+    }
+
+    /// Returns the root of the upper layer of this overlay.
+    ///
+    /// This contains every directory and file which was created or modified
+    /// through the overlay. Note that directories are only present here if
+    /// something within them was modified.
+    pub fn upper(&self) -> &MemoryDir {
+        &self.overlay.upper
+    }
+
+    /// Returns the paths, relative to the root of this overlay, which were
+    /// removed or renamed and now hide the corresponding path in the host
+    /// directory.
+    pub fn removed_paths(&self) -> Vec<String> {
+        self.overlay
+            .whiteouts
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Resolves `path` relative to this directory, returning the normalized
+    /// path and whether it had a trailing slash.
+    ///
+    /// As on the host, every component but the last must be a directory in
+    /// the merged view. Symlinks in the lower layer are resolved in place, so
+    /// that a following `..` leads to the parent of the symlink's target.
+    fn join(&self, wo: &Whiteouts<'_>, path: &str) -> FsResult<(String, bool)> {
+        if path.is_empty() {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let mut resolved = self.path.clone();
+        let base = resolved.len();
+        // The components left to resolve, in reverse order.
+        let mut pending: Vec<String> = path
+            .split('/')
+            .rev()
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect();
+        let mut symlinks = 0;
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "." => {}
+                ".." => {
+                    if resolved.len() == base {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                    resolved.truncate(resolved.rfind('/').unwrap_or(0));
+                }
+                name if pending.is_empty() => resolved = child_path(&resolved, name),
+                name => {
+                    let child = child_path(&resolved, name);
+                    match self.overlay.existing(wo, &child, false)? {
+                        entry if entry.is_dir() => resolved = child,
+                        Entry::Lower(meta) if meta.is_symlink() => {
+                            symlinks += 1;
+                            if symlinks > MAX_SYMLINKS {
+                                return Err(ErrorCode::Loop.into());
+                            }
+                            let target = self
+                                .overlay
+                                .lower
+                                .read_link(lower_path(&child))?
+                                .into_os_string()
+                                .into_string()
+                                .map_err(|_| ErrorCode::IllegalByteSequence)?;
+                            if target.starts_with('/') {
+                                return Err(ErrorCode::NotPermitted.into());
+                            }
+                            pending.extend(
+                                target
+                                    .split('/')
+                                    .rev()
+                                    .filter(|c| !c.is_empty())
+                                    .map(str::to_string),
+                            );
+                        }
+                        _ => return Err(ErrorCode::NotDirectory.into()),
+                    }
+                }
+            }
+        }
+        Ok((resolved, path.ends_with('/')))
+    }
+
+    fn lock(&self) -> Whiteouts<'_> {
+        self.overlay.whiteouts.lock().unwrap()
+    }
+
+    fn same_overlay<'a>(&self, other: &'a dyn WasiDir) -> FsResult<&'a OverlayDir> {
+        match other.as_any().downcast_ref::<OverlayDir>() {
+            Some(other) if Arc::ptr_eq(&self.overlay, &other.overlay) => Ok(other),
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
+    }
+}
+
+impl Overlay {
+    fn lower_visible(&self, wo: &Whiteouts<'_>, path: &str) -> bool {
+        let mut end = 0;
+        loop {
+            let next = path[end..].find('/').map_or(path.len(), |i| end + i);
+            if wo.contains(&path[..next]) {
+                return false;
+            }
+            if next == path.len() {
+                return true;
+            }
+            end = next + 1;
+        }
+    }
+
+    fn lower_metadata(
+        &self,
+        wo: &Whiteouts<'_>,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> FsResult<Option<cap_std::fs::Metadata>> {
+        if !self.lower_visible(wo, path) {
+            return Ok(None);
+        }
+        let result = if follow_symlinks {
+            self.lower.metadata(lower_path(path))
+        } else {
+            self.lower.symlink_metadata(lower_path(path))
+        };
+        match result {
+            Ok(meta) => Ok(Some(meta)),
+            Err(e) => match ErrorCode::from(&e) {
+                ErrorCode::NoEntry | ErrorCode::NotDirectory => Ok(None),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    fn upper_stat(&self, path: &str) -> FsResult<Option<DescriptorStat>> {
+        match self.upper.stat_at(upper_path(path), false) {
+            Ok(stat) => Ok(Some(stat)),
+            Err(e) => match e.downcast_ref() {
+                Some(ErrorCode::NoEntry | ErrorCode::NotDirectory) => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Returns what `path` refers to, preferring the upper layer.
+    fn entry(
+        &self,
+        wo: &Whiteouts<'_>,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> FsResult<Option<Entry>> {
+        if let Some(stat) = self.upper_stat(path)? {
+            return Ok(Some(Entry::Upper(stat)));
+        }
+        Ok(self
+            .lower_metadata(wo, path, follow_symlinks)?
+            .map(Entry::Lower))
+    }
+
+    /// Returns the entry for `path`, requiring it to exist.
+    fn existing(&self, wo: &Whiteouts<'_>, path: &str, follow_symlinks: bool) -> FsResult<Entry> {
+        self.entry(wo, path, follow_symlinks)?
+            .ok_or_else(|| ErrorCode::NoEntry.into())
+    }
+
+    /// Requires that the parent of `path` is a directory, returning the
+    /// parent's path.
+    fn parent_dir<'a>(&self, wo: &Whiteouts<'_>, path: &'a str) -> FsResult<&'a str> {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        if !self.existing(wo, parent, true)?.is_dir() {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        Ok(parent)
+    }
+
+    /// Returns the merged contents of the directory `path`.
+    fn read_dir(
+        &self,
+        wo: &Whiteouts<'_>,
+        path: &str,
+    ) -> FsResult<BTreeMap<String, DescriptorType>> {
+        let mut entries = BTreeMap::new();
+        if let Some(meta) = self.lower_metadata(wo, path, true)? {
+            if meta.is_dir() {
+                for entry in self.lower.read_dir(lower_path(path))? {
+                    let entry = entry?;
+                    let name = entry
+                        .file_name()
+                        .into_string()
+                        .map_err(|_| ErrorCode::IllegalByteSequence)?;
+                    if self.lower_visible(wo, &child_path(path, &name)) {
+                        entries.insert(name, descriptortype_from(entry.file_type()?));
+                    }
+                }
+            }
+        }
+        if self.upper_stat(path)?.is_some() {
+            if let WasiNode::Dir(dir) =
+                self.upper
+                    .open_at(upper_path(path), false, OpenFlags::DIRECTORY)?
+            {
+                for entry in dir.read_directory()? {
+                    entries.insert(entry.name, entry.type_);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Ensures the directory `path` is present in the upper layer.
+    fn copy_up_dir(&self, path: &str) -> FsResult<()> {
+        if path.is_empty() {
+            return Ok(());
+        }
+        self.upper.create_dir_all(path)
+    }
+
+    /// Ensures the file `path` is present in the upper layer, copying it from
+    /// `lower` if necessary, and returns it.
+    fn copy_up_file(&self, path: &str, lower: &cap_std::fs::File) -> FsResult<Arc<dyn WasiFile>> {
+        if self.upper_stat(path)?.is_some() {
+            return match self.upper.open_at(path, false, OpenFlags::empty())? {
+                WasiNode::File(file) => Ok(file),
+                WasiNode::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+            };
+        }
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        self.copy_up_dir(parent)?;
+        let file = copy_file(&self.upper, path, lower)?;
+        if let Some(shared) = self.open_file(path) {
+            shared.lock().unwrap().upper = Some(file.clone());
+        }
+        Ok(file)
+    }
+
+    /// Returns the state shared by the descriptors open on the lower file
+    /// `path`, if any.
+    fn open_file(&self, path: &str) -> Option<Arc<Mutex<LowerFile>>> {
+        self.open_files.lock().unwrap().get(path)?.upgrade()
+    }
+
+    /// Opens the lower file `path`.
+    fn open_lower_file(self: &Arc<Self>, path: String) -> FsResult<OverlayFile> {
+        let lower = self.lower.open(lower_path(&path))?;
+        let mut open_files = self.open_files.lock().unwrap();
+        let shared = match open_files.get(&path).and_then(Weak::upgrade) {
+            Some(shared) => shared,
+            None => {
+                let shared = Arc::new(Mutex::new(LowerFile {
+                    path: Some(path.clone()),
+                    upper: None,
+                }));
+                open_files.insert(path, Arc::downgrade(&shared));
+                shared
+            }
+        };
+        Ok(OverlayFile {
+            overlay: self.clone(),
+            shared,
+            lower,
+        })
+    }
+
+    /// Records that the open lower files at or beneath `path` were removed.
+    fn remove_open_files(&self, path: &str) {
+        let mut open_files = self.open_files.lock().unwrap();
+        open_files.retain(|file_path, shared| {
+            if !is_at_or_beneath(file_path, path) {
+                return true;
+            }
+            if let Some(shared) = shared.upgrade() {
+                shared.lock().unwrap().path = None;
+            }
+            false
+        });
+    }
+
+    /// Records that the open lower files at or beneath `old_path` were
+    /// renamed to be at or beneath `new_path`.
+    fn rename_open_files(&self, old_path: &str, new_path: &str) {
+        let mut open_files = self.open_files.lock().unwrap();
+        let renamed: Vec<String> = open_files
+            .keys()
+            .filter(|file_path| is_at_or_beneath(file_path, old_path))
+            .cloned()
+            .collect();
+        for file_path in renamed {
+            let shared = open_files.remove(&file_path).unwrap();
+            let file_path = format!("{new_path}{}", &file_path[old_path.len()..]);
+            if let Some(file) = shared.upgrade() {
+                file.lock().unwrap().path = Some(file_path.clone());
+                open_files.insert(file_path, shared);
+            }
+        }
+    }
+
+    /// Same as [`Overlay::copy_up_file`] but opens the lower file by path.
+    fn copy_up_path(&self, wo: &Whiteouts<'_>, path: &str) -> FsResult<Arc<dyn WasiFile>> {
+        match self.existing(wo, path, true)? {
+            Entry::Upper(_) => {
+                match self
+                    .upper
+                    .open_at(upper_path(path), true, OpenFlags::empty())?
+                {
+                    WasiNode::File(file) => Ok(file),
+                    WasiNode::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+                }
+            }
+            Entry::Lower(meta) if meta.is_dir() => {
+                self.copy_up_dir(path)?;
+                Err(ErrorCode::IsDirectory.into())
+            }
+            Entry::Lower(_) => self.copy_up_file(path, &self.lower.open(lower_path(path))?),
+        }
+    }
+
+    /// Recursively copies everything at and beneath `path` into the upper
+    /// layer.
+    fn copy_up_tree(&self, wo: &Whiteouts<'_>, path: &str) -> FsResult<()> {
+        if !self.existing(wo, path, true)?.is_dir() {
+            self.copy_up_path(wo, path)?;
+            return Ok(());
+        }
+        self.copy_up_dir(path)?;
+        for (name, _) in self.read_dir(wo, path)? {
+            self.copy_up_tree(wo, &child_path(path, &name))?;
+        }
+        Ok(())
+    }
+
+    /// Removes `path`, which must exist, from both layers.
+    fn remove(&self, wo: &mut Whiteouts<'_>, path: &str, entry: &Entry) -> FsResult<()> {
+        if let Entry::Upper(stat) = entry {
+            if stat.type_ == DescriptorType::Directory {
+                self.upper.remove_directory_at(path)?;
+            } else {
+                self.upper.unlink_file_at(path)?;
+            }
+        }
+        if self.lower_metadata(wo, path, false)?.is_some() {
+            wo.insert(path.to_string());
+        }
+        self.remove_open_files(path);
+        Ok(())
+    }
+
+    fn metadata_hash(&self, path: &str, entry: Entry) -> FsResult<MetadataHashValue> {
+        match entry {
+            Entry::Upper(_) => self.upper.metadata_hash_at(upper_path(path), false),
+            Entry::Lower(meta) => Ok(calculate_metadata_hash(&meta)),
+        }
+    }
+}
+
+/// Returns the path to use for the upper layer, which is relative to its
+/// root.
+fn upper_path(path: &str) -> &str {
+    if path.is_empty() {
+        "."
+    } else {
+        path
+    }
+}
+
+fn lower_path(path: &str) -> &Path {
+    Path::new(upper_path(path))
+}
+
+fn child_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn is_at_or_beneath(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Creates the file `path` in `dir` with the contents and timestamps of
+/// `lower`, and returns it.
+fn copy_file(
+    dir: &MemoryDir,
+    path: &str,
+    lower: &cap_std::fs::File,
+) -> FsResult<Arc<dyn WasiFile>> {
+    let meta = lower.metadata()?;
+    let mut contents = Vec::new();
+    lower.read_to_end_at(&mut contents, 0)?;
+    let file = match dir.open_at(path, false, OpenFlags::CREATE)? {
+        WasiNode::File(file) => file,
+        WasiNode::Dir(_) => return Err(ErrorCode::IsDirectory.into()),
+    };
+    file.write_at(&contents, 0)?;
+    let stat = descriptorstat_from(meta);
+    let timestamp = |t: Option<_>| t.map_or(NewTimestamp::NoChange, NewTimestamp::Timestamp);
+    file.set_times(
+        timestamp(stat.data_access_timestamp),
+        timestamp(stat.data_modification_timestamp),
+    )?;
+    Ok(file)
+}
+
+impl WasiDir for OverlayDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn open_at(&self, path: &str, follow_symlinks: bool, oflags: OpenFlags) -> FsResult<WasiNode> {
+        let wo = self.lock();
+        let (path, trailing_slash) = self.join(&wo, path)?;
+        let o = &self.overlay;
+        let entry = o.entry(&wo, &path, follow_symlinks)?;
+        match entry {
+            Some(_) if oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+                Err(ErrorCode::Exist.into())
+            }
+            Some(entry) if entry.is_dir() => {
+                if oflags.contains(OpenFlags::TRUNCATE) {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+                Ok(WasiNode::Dir(Arc::new(OverlayDir {
+                    overlay: o.clone(),
+                    path,
+                })))
+            }
+            Some(_) if trailing_slash || oflags.contains(OpenFlags::DIRECTORY) => {
+                Err(ErrorCode::NotDirectory.into())
+            }
+            Some(Entry::Upper(_)) => o.upper.open_at(&path, follow_symlinks, oflags),
+            Some(Entry::Lower(_)) => {
+                if oflags.contains(OpenFlags::TRUNCATE) {
+                    let lower = o.lower.open(lower_path(&path))?;
+                    let file = o.copy_up_file(&path, &lower)?;
+                    file.set_size(0)?;
+                    return Ok(WasiNode::File(file));
+                }
+                Ok(WasiNode::File(Arc::new(o.open_lower_file(path)?)))
+            }
+            None if oflags.contains(OpenFlags::CREATE) => {
+                if trailing_slash {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+                let parent = o.parent_dir(&wo, &path)?;
+                o.copy_up_dir(parent)?;
+                o.upper.open_at(&path, follow_symlinks, oflags)
+            }
+            None => Err(ErrorCode::NoEntry.into()),
+        }
+    }
+
+    fn stat(&self) -> FsResult<DescriptorStat> {
+        let wo = self.lock();
+        Ok(self.overlay.existing(&wo, &self.path, true)?.stat())
+    }
+
+    fn stat_at(&self, path: &str, follow_symlinks: bool) -> FsResult<DescriptorStat> {
+        let wo = self.lock();
+        let (path, trailing_slash) = self.join(&wo, path)?;
+        let entry = self.overlay.existing(&wo, &path, follow_symlinks)?;
+        if trailing_slash && !entry.is_dir() {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        Ok(entry.stat())
+    }
+
+    fn set_times(&self, atim: NewTimestamp, mtim: NewTimestamp) -> FsResult<()> {
+        self.set_times_at(".", true, atim, mtim)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+        atim: NewTimestamp,
+        mtim: NewTimestamp,
+    ) -> FsResult<()> {
+        let wo = self.lock();
+        let (path, _) = self.join(&wo, path)?;
+        let o = &self.overlay;
+        if o.existing(&wo, &path, follow_symlinks)?.is_dir() {
+            o.copy_up_dir(&path)?;
+            o.upper.set_times_at(upper_path(&path), true, atim, mtim)
+        } else {
+            o.copy_up_path(&wo, &path)?.set_times(atim, mtim)
+        }
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<DirectoryEntry>> {
+        let wo = self.lock();
+        Ok(self
+            .overlay
+            .read_dir(&wo, &self.path)?
+            .into_iter()
+            .map(|(name, type_)| DirectoryEntry { type_, name })
+            .collect())
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        let wo = self.lock();
+        let (path, _) = self.join(&wo, path)?;
+        let o = &self.overlay;
+        if o.entry(&wo, &path, false)?.is_some() {
+            return Err(ErrorCode::Exist.into());
+        }
+        let parent = o.parent_dir(&wo, &path)?;
+        o.copy_up_dir(parent)?;
+        o.upper.create_directory_at(&path)
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut wo = self.lock();
+        let (path, _) = self.join(&wo, path)?;
+        if path == self.path {
+            return Err(ErrorCode::Invalid.into());
+        }
+        let o = &self.overlay;
+        let entry = o.existing(&wo, &path, false)?;
+        if !entry.is_dir() {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        if !o.read_dir(&wo, &path)?.is_empty() {
+            return Err(ErrorCode::NotEmpty.into());
+        }
+        o.remove(&mut wo, &path, &entry)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        let mut wo = self.lock();
+        let (path, trailing_slash) = self.join(&wo, path)?;
+        let o = &self.overlay;
+        let entry = o.existing(&wo, &path, false)?;
+        if entry.is_dir() {
+            return Err(ErrorCode::IsDirectory.into());
+        }
+        if trailing_slash {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        o.remove(&mut wo, &path, &entry)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> FsResult<()> {
+        let new_dir = self.same_overlay(new_dir)?;
+        let mut wo = self.lock();
+        let (old_path, old_slash) = self.join(&wo, old_path)?;
+        let (new_path, new_slash) = new_dir.join(&wo, new_path)?;
+        if old_path.is_empty() || new_path.is_empty() {
+            return Err(ErrorCode::Invalid.into());
+        }
+        let o = &self.overlay;
+
+        let src = o.existing(&wo, &old_path, false)?;
+        if !src.is_dir() && (old_slash || new_slash) {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        if old_path == new_path {
+            return Ok(());
+        }
+        if src.is_dir() && new_path.starts_with(&format!("{old_path}/")) {
+            return Err(ErrorCode::Invalid.into());
+        }
+        let new_parent = o.parent_dir(&wo, &new_path)?;
+        if let Some(dest) = o.entry(&wo, &new_path, false)? {
+            match (src.is_dir(), dest.is_dir()) {
+                (true, true) if !o.read_dir(&wo, &new_path)?.is_empty() => {
+                    return Err(ErrorCode::NotEmpty.into())
+                }
+                (true, false) => return Err(ErrorCode::NotDirectory.into()),
+                (false, true) => return Err(ErrorCode::IsDirectory.into()),
+                _ => {}
+            }
+            o.remove(&mut wo, &new_path, &dest)?;
+        }
+
+        o.copy_up_tree(&wo, &old_path)?;
+        o.copy_up_dir(new_parent)?;
+        o.upper.rename_at(&old_path, &o.upper, &new_path)?;
+        o.rename_open_files(&old_path, &new_path);
+        if o.lower_metadata(&wo, &old_path, false)?.is_some() {
+            wo.insert(old_path);
+        }
+        Ok(())
+    }
+
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        let wo = self.lock();
+        let (path, _) = self.join(&wo, path)?;
+        let o = &self.overlay;
+        match o.existing(&wo, &path, false)? {
+            Entry::Upper(_) => Err(ErrorCode::Invalid.into()),
+            Entry::Lower(_) => Ok(o
+                .lower
+                .read_link(lower_path(&path))?
+                .into_os_string()
+                .into_string()
+                .map_err(|_| ErrorCode::IllegalByteSequence)?),
+        }
+    }
+
+    fn metadata_hash(&self) -> FsResult<MetadataHashValue> {
+        let wo = self.lock();
+        let entry = self.overlay.existing(&wo, &self.path, true)?;
+        self.overlay.metadata_hash(&self.path, entry)
+    }
+
+    fn metadata_hash_at(&self, path: &str, follow_symlinks: bool) -> FsResult<MetadataHashValue> {
+        let wo = self.lock();
+        let (path, _) = self.join(&wo, path)?;
+        let entry = self.overlay.existing(&wo, &path, follow_symlinks)?;
+        self.overlay.metadata_hash(&path, entry)
+    }
+}
+
+impl OverlayFile {
+    /// Returns the copy of this file in the upper layer, if it has one.
+    fn upper(&self) -> Option<Arc<dyn WasiFile>> {
+        self.shared.lock().unwrap().upper.clone()
+    }
+
+    fn copy_up(&self) -> FsResult<Arc<dyn WasiFile>> {
+        let wo = self.overlay.whiteouts.lock().unwrap();
+        if let Some(file) = self.upper() {
+            return Ok(file);
+        }
+        let path = self.shared.lock().unwrap().path.clone();
+        match path {
+            Some(path) if self.overlay.lower_visible(&wo, &path) => {
+                self.overlay.copy_up_file(&path, &self.lower)
+            }
+            // The file was removed while open, so it's copied to an unlinked
+            // file which doesn't bring it back into the overlay.
+            _ => {
+                let dir = MemoryDir::new();
+                let file = copy_file(&dir, "file", &self.lower)?;
+                dir.unlink_file_at("file")?;
+                self.shared.lock().unwrap().upper = Some(file.clone());
+                Ok(file)
+            }
+        }
+    }
+}
+
+impl Drop for OverlayFile {
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.shared) > 1 {
+            return;
+        }
+        let path = self.shared.lock().unwrap().path.clone();
+        if let Some(path) = path {
+            let mut open_files = self.overlay.open_files.lock().unwrap();
+            if open_files
+                .get(&path)
+                .is_some_and(|shared| shared.as_ptr() == Arc::as_ptr(&self.shared))
+            {
+                open_files.remove(&path);
+            }
+        }
+    }
+}
+
+impl WasiFile for OverlayFile {
+    fn stat(&self) -> FsResult<DescriptorStat> {
+        match self.upper() {
+            Some(file) => file.stat(),
+            None => Ok(descriptorstat_from(self.lower.metadata()?)),
+        }
+    }
+
+    fn set_size(&self, size: u64) -> FsResult<()> {
+        self.copy_up()?.set_size(size)
+    }
+
+    fn set_times(&self, atim: NewTimestamp, mtim: NewTimestamp) -> FsResult<()> {
+        self.copy_up()?.set_times(atim, mtim)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> FsResult<usize> {
+        match self.upper() {
+            Some(file) => file.read_at(buf, offset),
+            None => Ok(self.lower.read_at(buf, offset)?),
+        }
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> FsResult<usize> {
+        self.copy_up()?.write_at(buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> FsResult<usize> {
+        self.copy_up()?.append(buf)
+    }
+
+    fn metadata_hash(&self) -> FsResult<MetadataHashValue> {
+        match self.upper() {
+            Some(file) => file.metadata_hash(),
+            None => Ok(calculate_metadata_hash(&self.lower.metadata()?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn code<T>(r: FsResult<T>) -> ErrorCode {
+        match r {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    fn file(dir: &OverlayDir, path: &str, oflags: OpenFlags) -> Arc<dyn WasiFile> {
+        match dir.open_at(path, true, oflags).unwrap() {
+            WasiNode::File(f) => f,
+            WasiNode::Dir(_) => panic!("expected a file"),
+        }
+    }
+
+    fn dir(dir: &OverlayDir, path: &str) -> OverlayDir {
+        match dir.open_at(path, true, OpenFlags::DIRECTORY).unwrap() {
+            WasiNode::Dir(d) => d.as_any().downcast_ref::<OverlayDir>().unwrap().clone(),
+            WasiNode::File(_) => panic!("expected a directory"),
+        }
+    }
+
+    fn read(file: &dyn WasiFile) -> Vec<u8> {
+        let mut buf = vec![0; 128];
+        let n = file.read_at(&mut buf, 0).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    fn names(dir: &OverlayDir) -> Vec<String> {
+        dir.read_directory()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect()
+    }
+
+    fn setup() -> (tempfile::TempDir, OverlayDir) {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("sub")).unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "lower a").unwrap();
+        std::fs::write(tmp.path().join("sub/b.txt"), "lower b").unwrap();
+        let overlay = OverlayDir::new(tmp.path()).unwrap();
+        (tmp, overlay)
+    }
+
+    #[test]
+    fn writes_do_not_reach_host() {
+        let (tmp, overlay) = setup();
+
+        let a = file(&overlay, "a.txt", OpenFlags::empty());
+        assert_eq!(read(&*a), b"lower a");
+        a.write_at(b"upper", 0).unwrap();
+        assert_eq!(read(&*a), b"upper a");
+        assert_eq!(
+            read(&*file(&overlay, "a.txt", OpenFlags::empty())),
+            b"upper a"
+        );
+        assert_eq!(std::fs::read(tmp.path().join("a.txt")).unwrap(), b"lower a");
+        assert_eq!(overlay.upper().read_file("a.txt").unwrap(), b"upper a");
+
+        let b = file(&overlay, "sub/b.txt", OpenFlags::TRUNCATE);
+        assert_eq!(read(&*b), b"");
+        assert_eq!(
+            std::fs::read(tmp.path().join("sub/b.txt")).unwrap(),
+            b"lower b"
+        );
+
+        let c = file(&overlay, "sub/c.txt", OpenFlags::CREATE);
+        c.append(b"new").unwrap();
+        assert_eq!(names(&overlay), ["a.txt", "sub"]);
+        assert!(!tmp.path().join("sub/c.txt").exists());
+        assert_eq!(overlay.upper().read_file("sub/c.txt").unwrap(), b"new");
+
+        assert_eq!(
+            code(overlay.open_at("../x", true, OpenFlags::empty())),
+            ErrorCode::NotPermitted
+        );
+    }
+
+    #[test]
+    fn removals_and_renames() {
+        let (tmp, overlay) = setup();
+
+        overlay.unlink_file_at("a.txt").unwrap();
+        assert_eq!(code(overlay.stat_at("a.txt", true)), ErrorCode::NoEntry);
+        assert!(tmp.path().join("a.txt").exists());
+        assert_eq!(overlay.removed_paths(), ["a.txt"]);
+
+        assert_eq!(
+            code(overlay.remove_directory_at("sub")),
+            ErrorCode::NotEmpty
+        );
+        overlay.rename_at("sub", &overlay, "moved").unwrap();
+        assert_eq!(names(&overlay), ["moved"]);
+        let moved = &dir(&overlay, "moved");
+        assert_eq!(names(moved), ["b.txt"]);
+        assert_eq!(read(&*file(moved, "b.txt", OpenFlags::empty())), b"lower b");
+        assert!(tmp.path().join("sub/b.txt").exists());
+
+        moved.unlink_file_at("b.txt").unwrap();
+        overlay.remove_directory_at("moved").unwrap();
+        assert!(names(&overlay).is_empty());
+
+        overlay.create_directory_at("sub").unwrap();
+        assert!(names(&dir(&overlay, "sub")).is_empty());
+
+        let other = OverlayDir::new(tmp.path()).unwrap();
+        assert_eq!(
+            code(overlay.rename_at("sub", &other, "x")),
+            ErrorCode::CrossDevice
+        );
+    }
+
+    #[test]
+    fn open_files_share_copy_up() {
+        let (tmp, overlay) = setup();
+
+        let a1 = file(&overlay, "a.txt", OpenFlags::empty());
+        let a2 = file(&overlay, "a.txt", OpenFlags::empty());
+        a1.write_at(b"upper", 0).unwrap();
+        assert_eq!(read(&*a2), b"upper a");
+
+        // Writes through descriptors of removed files don't bring them back.
+        overlay.unlink_file_at("a.txt").unwrap();
+        a2.append(b"!").unwrap();
+        assert_eq!(read(&*a1), b"upper a!");
+        assert_eq!(code(overlay.stat_at("a.txt", true)), ErrorCode::NoEntry);
+
+        let b = file(&overlay, "sub/b.txt", OpenFlags::empty());
+        overlay.unlink_file_at("sub/b.txt").unwrap();
+        b.write_at(b"upper", 0).unwrap();
+        assert_eq!(read(&*b), b"upper b");
+        assert!(names(&dir(&overlay, "sub")).is_empty());
+        assert!(overlay.upper().read_file("sub/b.txt").is_err());
+
+        // Descriptors follow renames.
+        std::fs::write(tmp.path().join("sub/c.txt"), "lower c").unwrap();
+        let c = file(&overlay, "sub/c.txt", OpenFlags::empty());
+        overlay.rename_at("sub", &overlay, "moved").unwrap();
+        c.write_at(b"upper", 0).unwrap();
+        assert_eq!(
+            overlay.upper().read_file("moved/c.txt").unwrap(),
+            b"upper c"
+        );
+        assert_eq!(code(overlay.stat_at("sub", true)), ErrorCode::NoEntry);
+        assert_eq!(
+            std::fs::read(tmp.path().join("sub/c.txt")).unwrap(),
+            b"lower c"
+        );
+    }
+
+    #[test]
+    fn dot_dot_requires_directories() {
+        let (_tmp, overlay) = setup();
+
+        assert_eq!(
+            code(overlay.stat_at("a.txt/..", true)),
+            ErrorCode::NotDirectory
+        );
+        assert_eq!(
+            code(overlay.stat_at("missing/..", true)),
+            ErrorCode::NoEntry
+        );
+        assert_eq!(
+            code(overlay.open_at("a.txt/../sub", true, OpenFlags::DIRECTORY)),
+            ErrorCode::NotDirectory
+        );
+        assert_eq!(names(&dir(&overlay, "sub/..")), ["a.txt", "sub"]);
+
+        // Entries only in the upper layer, and removed from the lower layer,
+        // are resolved the same way.
+        file(&overlay, "new.txt", OpenFlags::CREATE);
+        overlay.create_directory_at("made").unwrap();
+        assert_eq!(
+            code(overlay.stat_at("new.txt/..", true)),
+            ErrorCode::NotDirectory
+        );
+        assert!(overlay.stat_at("made/../a.txt", true).is_ok());
+        overlay.rename_at("sub", &overlay, "moved").unwrap();
+        assert_eq!(code(overlay.stat_at("sub/..", true)), ErrorCode::NoEntry);
+        assert!(overlay.stat_at("moved/../a.txt", true).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn dot_dot_after_symlink() {
+        let (tmp, overlay) = setup();
+        std::fs::create_dir_all(tmp.path().join("deep/inner")).unwrap();
+        std::fs::write(tmp.path().join("deep/inner/x.txt"), "lower x").unwrap();
+        std::os::unix::fs::symlink("deep/inner", tmp.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("..", tmp.path().join("sub/up")).unwrap();
+        std::os::unix::fs::symlink("cycle", tmp.path().join("cycle")).unwrap();
+
+        // `..` leads to the parent of the symlink's target, as on the host.
+        assert_eq!(
+            read(&*file(&overlay, "link/../inner/x.txt", OpenFlags::empty())),
+            b"lower x"
+        );
+        assert_eq!(names(&dir(&overlay, "link/..")), ["inner"]);
+        assert_eq!(
+            code(overlay.stat_at("link/../x.txt", true)),
+            ErrorCode::NoEntry
+        );
+        assert_eq!(names(&dir(&overlay, "sub/up/deep")), ["inner"]);
+
+        // Symlinks can't be used to escape the directory.
+        let sub = dir(&overlay, "sub");
+        assert_eq!(code(sub.stat_at("up/a.txt", true)), ErrorCode::NotPermitted);
+        assert_eq!(code(overlay.stat_at("cycle/x", true)), ErrorCode::Loop);
+    }
+}
@@ -5,6 +5,7 @@ use crate::bindings::{
     clocks::wall_clock::{self, Datetime},
 };
 use crate::poll::{subscribe, Subscribe};
+use crate::trace::EventKind;
 use crate::{Pollable, WasiImpl, WasiView};
 use cap_std::time::SystemTime;
 use std::time::Duration;
@@ -29,18 +30,24 @@ where
     T: WasiView,
 {
     fn now(&mut self) -> anyhow::Result<Datetime> {
-        let now = self.ctx().wall_clock.now();
-        Ok(Datetime {
-            seconds: now.as_secs(),
-            nanoseconds: now.subsec_nanos(),
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::WallClockNow, || {
+            let now = ctx.wall_clock.now();
+            Datetime {
+                seconds: now.as_secs(),
+                nanoseconds: now.subsec_nanos(),
+            }
         })
     }
 
     fn resolution(&mut self) -> anyhow::Result<Datetime> {
-        let res = self.ctx().wall_clock.resolution();
-        Ok(Datetime {
-            seconds: res.as_secs(),
-            nanoseconds: res.subsec_nanos(),
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::WallClockResolution, || {
+            let res = ctx.wall_clock.resolution();
+            Datetime {
+                seconds: res.as_secs(),
+                nanoseconds: res.subsec_nanos(),
+            }
         })
     }
 }
@@ -69,15 +76,20 @@ where
     T: WasiView,
 {
     fn now(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.now())
+        let ctx = self.ctx();
+        ctx.trace
+            .value(EventKind::MonotonicClockNow, || ctx.monotonic_clock.now())
     }
 
     fn resolution(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.resolution())
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::MonotonicClockResolution, || {
+            ctx.monotonic_clock.resolution()
+        })
     }
 
     fn subscribe_instant(&mut self, when: Instant) -> anyhow::Result<Resource<Pollable>> {
-        let clock_now = self.ctx().monotonic_clock.now();
+        let clock_now = monotonic_clock::Host::now(self)?;
         let duration = if when > clock_now {
             Duration::from_nanos(when - clock_now)
         } else {
@@ -5,9 +5,12 @@ use crate::bindings::filesystem::types::{
 };
 use crate::bindings::io::streams::{InputStream, OutputStream};
 use crate::filesystem::{
-    Descriptor, Dir, File, FileInputStream, FileOutputStream, OpenMode, ReaddirIterator,
+    Descriptor, Dir, DirRef, File, FileInputStream, FileOutputStream, FileRef, OpenMode,
+    ReaddirIterator, VirtualDir, VirtualFile, VirtualFileInputStream, VirtualFileOutputStream,
+    WasiNode,
 };
-use crate::{DirPerms, FilePerms, FsError, FsResult, WasiImpl, WasiView};
+use crate::trace::{EventKind, TraceValue};
+use crate::{DirPerms, FilePerms, FsError, FsResult, WasiCtx, WasiImpl, WasiView};
 use anyhow::Context;
 use wasmtime::component::Resource;
 
@@ -24,7 +27,7 @@ where
         for (dir, name) in self.ctx().preopens.clone() {
             let fd = self
                 .table()
-                .push(Descriptor::Dir(dir))
+                .push(dir)
                 .with_context(|| format!("failed to push preopen {name}"))?;
             results.push((fd, name));
         }
@@ -48,10 +51,14 @@ where
         let err = self.table().get(&err)?;
 
         // Currently `err` always comes from the stream implementation which
-        // uses standard reads/writes so only check for `std::io::Error` here.
+        // uses standard reads/writes so only check for `std::io::Error` here,
+        // or the `ErrorCode` reported by virtual files.
         if let Some(err) = err.downcast_ref::<std::io::Error>() {
             return Ok(Some(ErrorCode::from(err)));
         }
+        if let Some(code) = err.downcast_ref::<ErrorCode>() {
+            return Ok(Some(*code));
+        }
 
         Ok(None)
     }
@@ -81,7 +88,12 @@ where
             Advice::NoReuse => A::NoReuse,
         };
 
-        let f = self.table().get(&fd)?.file()?;
+        let f = match self.table().get(&fd)?.any_file()? {
+            FileRef::Host(f) => f,
+            // Advice is only a hint, so there's nothing to do for virtual
+            // files.
+            FileRef::Virtual(_) => return Ok(()),
+        };
         f.spawn_blocking(move |f| f.advise(offset, len, advice))
             .await?;
         Ok(())
@@ -111,6 +123,8 @@ where
                 d.spawn_blocking(|d| Ok(d.open(std::path::Component::CurDir)?.sync_data()?))
                     .await
             }
+            Descriptor::VirtualFile(f) => f.file.sync(),
+            Descriptor::VirtualDir(d) => d.dir.sync(),
         }
     }
 
@@ -159,6 +173,26 @@ where
                 }
                 Ok(flags)
             }
+            Descriptor::VirtualFile(f) => {
+                let mut flags = DescriptorFlags::empty();
+                if f.open_mode.contains(OpenMode::READ) {
+                    flags |= DescriptorFlags::READ;
+                }
+                if f.open_mode.contains(OpenMode::WRITE) {
+                    flags |= DescriptorFlags::WRITE;
+                }
+                Ok(flags)
+            }
+            Descriptor::VirtualDir(d) => {
+                let mut flags = DescriptorFlags::empty();
+                if d.open_mode.contains(OpenMode::READ) {
+                    flags |= DescriptorFlags::READ;
+                }
+                if d.open_mode.contains(OpenMode::WRITE) {
+                    flags |= DescriptorFlags::MUTATE_DIRECTORY;
+                }
+                Ok(flags)
+            }
         }
     }
 
@@ -173,7 +207,8 @@ where
                 let meta = f.spawn_blocking(|f| f.metadata()).await?;
                 Ok(descriptortype_from(meta.file_type()))
             }
-            Descriptor::Dir(_) => Ok(types::DescriptorType::Directory),
+            Descriptor::VirtualFile(f) => Ok(f.file.stat()?.type_),
+            Descriptor::Dir(_) | Descriptor::VirtualDir(_) => Ok(types::DescriptorType::Directory),
         }
     }
 
@@ -182,11 +217,14 @@ where
         fd: Resource<types::Descriptor>,
         size: types::Filesize,
     ) -> FsResult<()> {
-        let f = self.table().get(&fd)?.file()?;
-        if !f.perms.contains(FilePerms::WRITE) {
+        let f = self.table().get(&fd)?.any_file()?;
+        if !f.perms().contains(FilePerms::WRITE) {
             Err(ErrorCode::NotPermitted)?;
         }
-        f.spawn_blocking(move |f| f.set_len(size)).await?;
+        match f {
+            FileRef::Host(f) => f.spawn_blocking(move |f| f.set_len(size)).await?,
+            FileRef::Virtual(f) => f.set_size(size)?,
+        }
         Ok(())
     }
 
@@ -218,6 +256,18 @@ where
                 d.spawn_blocking(|d| d.set_times(atim, mtim)).await?;
                 Ok(())
             }
+            Descriptor::VirtualFile(f) => {
+                if !f.perms.contains(FilePerms::WRITE) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                f.file.set_times(atim, mtim)
+            }
+            Descriptor::VirtualDir(d) => {
+                if !d.perms.contains(DirPerms::MUTATE) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                d.dir.set_times(atim, mtim)
+            }
         }
     }
 
@@ -227,36 +277,8 @@ where
         len: types::Filesize,
         offset: types::Filesize,
     ) -> FsResult<(Vec<u8>, bool)> {
-        use std::io::IoSliceMut;
-        use system_interface::fs::FileIoExt;
-
-        let table = self.table();
-
-        let f = table.get(&fd)?.file()?;
-        if !f.perms.contains(FilePerms::READ) {
-            return Err(ErrorCode::NotPermitted.into());
-        }
-
-        let (mut buffer, r) = f
-            .spawn_blocking(move |f| {
-                let mut buffer = vec![0; len.try_into().unwrap_or(usize::MAX)];
-                let r = f.read_vectored_at(&mut [IoSliceMut::new(&mut buffer)], offset);
-                (buffer, r)
-            })
-            .await;
-
-        let (bytes_read, state) = match r? {
-            0 => (0, true),
-            n => (n, false),
-        };
-
-        buffer.truncate(
-            bytes_read
-                .try_into()
-                .expect("bytes read into memory as u64 fits in usize"),
-        );
-
-        Ok((buffer, state))
+        let result = self.host_read(fd, len, offset).await;
+        substitute(self.ctx(), EventKind::FileRead, result)
     }
 
     async fn write(
@@ -269,14 +291,18 @@ where
         use system_interface::fs::FileIoExt;
 
         let table = self.table();
-        let f = table.get(&fd)?.file()?;
-        if !f.perms.contains(FilePerms::WRITE) {
+        let f = table.get(&fd)?.any_file()?;
+        if !f.perms().contains(FilePerms::WRITE) {
             return Err(ErrorCode::NotPermitted.into());
         }
 
-        let bytes_written = f
-            .spawn_blocking(move |f| f.write_vectored_at(&[IoSlice::new(&buf)], offset))
-            .await?;
+        let bytes_written = match f {
+            FileRef::Host(f) => {
+                f.spawn_blocking(move |f| f.write_vectored_at(&[IoSlice::new(&buf)], offset))
+                    .await?
+            }
+            FileRef::Virtual(f) => f.write_at(&buf, offset)?,
+        };
 
         Ok(types::Filesize::try_from(bytes_written).expect("usize fits in Filesize"))
     }
@@ -286,7 +312,16 @@ where
         fd: Resource<types::Descriptor>,
     ) -> FsResult<Resource<types::DirectoryEntryStream>> {
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
+        let d = match table.get(&fd)?.any_dir()? {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                if !d.perms.contains(DirPerms::READ) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                let entries = d.dir.read_directory()?;
+                return Ok(table.push(ReaddirIterator::new(entries.into_iter().map(Ok)))?);
+            }
+        };
         if !d.perms.contains(DirPerms::READ) {
             return Err(ErrorCode::NotPermitted.into());
         }
@@ -370,6 +405,8 @@ where
                 d.spawn_blocking(|d| Ok(d.open(std::path::Component::CurDir)?.sync_all()?))
                     .await
             }
+            Descriptor::VirtualFile(f) => f.file.sync(),
+            Descriptor::VirtualDir(d) => d.dir.sync(),
         }
     }
 
@@ -379,28 +416,20 @@ where
         path: String,
     ) -> FsResult<()> {
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::MUTATE) {
+        let d = table.get(&fd)?.any_dir()?;
+        if !d.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        d.spawn_blocking(move |d| d.create_dir(&path)).await?;
+        match d {
+            DirRef::Host(d) => d.spawn_blocking(move |d| d.create_dir(&path)).await?,
+            DirRef::Virtual(d) => d.dir.create_directory_at(&path)?,
+        }
         Ok(())
     }
 
     async fn stat(&mut self, fd: Resource<types::Descriptor>) -> FsResult<types::DescriptorStat> {
-        let descriptor = self.table().get(&fd)?;
-        match descriptor {
-            Descriptor::File(f) => {
-                // No permissions check on stat: if opened, allowed to stat it
-                let meta = f.spawn_blocking(|f| f.metadata()).await?;
-                Ok(descriptorstat_from(meta))
-            }
-            Descriptor::Dir(d) => {
-                // No permissions check on stat: if opened, allowed to stat it
-                let meta = d.spawn_blocking(|d| d.dir_metadata()).await?;
-                Ok(descriptorstat_from(meta))
-            }
-        }
+        let result = self.host_stat(fd).await;
+        substitute(self.ctx(), EventKind::Stat, result)
     }
 
     async fn stat_at(
@@ -409,18 +438,8 @@ where
         path_flags: types::PathFlags,
         path: String,
     ) -> FsResult<types::DescriptorStat> {
-        let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::READ) {
-            return Err(ErrorCode::NotPermitted.into());
-        }
-
-        let meta = if symlink_follow(path_flags) {
-            d.spawn_blocking(move |d| d.metadata(&path)).await?
-        } else {
-            d.spawn_blocking(move |d| d.symlink_metadata(&path)).await?
-        };
-        Ok(descriptorstat_from(meta))
+        let result = self.host_stat_at(fd, path_flags, path).await;
+        substitute(self.ctx(), EventKind::Stat, result)
     }
 
     async fn set_times_at(
@@ -434,7 +453,17 @@ where
         use cap_fs_ext::DirExt;
 
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
+        let d = match table.get(&fd)?.any_dir()? {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                if !d.perms.contains(DirPerms::MUTATE) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                return d
+                    .dir
+                    .set_times_at(&path, symlink_follow(path_flags), atim, mtim);
+            }
+        };
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
@@ -472,21 +501,29 @@ where
         new_path: String,
     ) -> FsResult<()> {
         let table = self.table();
-        let old_dir = table.get(&fd)?.dir()?;
-        if !old_dir.perms.contains(DirPerms::MUTATE) {
+        let old_dir = table.get(&fd)?.any_dir()?;
+        if !old_dir.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let new_dir = table.get(&new_descriptor)?.dir()?;
-        if !new_dir.perms.contains(DirPerms::MUTATE) {
+        let new_dir = table.get(&new_descriptor)?.any_dir()?;
+        if !new_dir.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
         if symlink_follow(old_path_flags) {
             return Err(ErrorCode::Invalid.into());
         }
-        let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
-        old_dir
-            .spawn_blocking(move |d| d.hard_link(&old_path, &new_dir_handle, &new_path))
-            .await?;
+        match (old_dir, new_dir) {
+            (DirRef::Host(old_dir), DirRef::Host(new_dir)) => {
+                let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
+                old_dir
+                    .spawn_blocking(move |d| d.hard_link(&old_path, &new_dir_handle, &new_path))
+                    .await?;
+            }
+            (DirRef::Virtual(old_dir), DirRef::Virtual(new_dir)) => {
+                old_dir.dir.link_at(&old_path, &*new_dir.dir, &new_path)?;
+            }
+            _ => return Err(ErrorCode::CrossDevice.into()),
+        }
         Ok(())
     }
 
@@ -504,12 +541,12 @@ where
 
         let allow_blocking_current_thread = self.ctx().allow_blocking_current_thread;
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::READ) {
+        let d = table.get(&fd)?.any_dir()?;
+        if !d.perms().contains(DirPerms::READ) {
             Err(ErrorCode::NotPermitted)?;
         }
 
-        if !d.perms.contains(DirPerms::MUTATE) {
+        if !d.perms().contains(DirPerms::MUTATE) {
             if oflags.contains(OpenFlags::CREATE) || oflags.contains(OpenFlags::TRUNCATE) {
                 Err(ErrorCode::NotPermitted)?;
             }
@@ -578,13 +615,39 @@ where
 
         // Now enforce this WasiCtx's permissions before letting the OS have
         // its shot:
-        if !d.perms.contains(DirPerms::MUTATE) && create {
+        if !d.perms().contains(DirPerms::MUTATE) && create {
             Err(ErrorCode::NotPermitted)?;
         }
-        if !d.file_perms.contains(FilePerms::WRITE) && open_mode.contains(OpenMode::WRITE) {
+        if !d.file_perms().contains(FilePerms::WRITE) && open_mode.contains(OpenMode::WRITE) {
             Err(ErrorCode::NotPermitted)?;
         }
 
+        let d = match d {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                let desc = match d.dir.open_at(&path, symlink_follow(path_flags), oflags)? {
+                    WasiNode::Dir(_) if flags.contains(DescriptorFlags::WRITE) => {
+                        Err(ErrorCode::IsDirectory)?
+                    }
+                    WasiNode::Dir(dir) => Descriptor::VirtualDir(VirtualDir {
+                        dir,
+                        perms: d.perms,
+                        file_perms: d.file_perms,
+                        open_mode,
+                    }),
+                    WasiNode::File(_) if oflags.contains(OpenFlags::DIRECTORY) => {
+                        Err(ErrorCode::NotDirectory)?
+                    }
+                    WasiNode::File(file) => Descriptor::VirtualFile(VirtualFile {
+                        file,
+                        perms: d.file_perms,
+                        open_mode,
+                    }),
+                };
+                return Ok(table.push(desc)?);
+            }
+        };
+
         // Represents each possible outcome from the spawn_blocking operation.
         // This makes sure we don't have to give spawn_blocking any way to
         // manipulate the table.
@@ -652,7 +715,15 @@ where
         path: String,
     ) -> FsResult<String> {
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
+        let d = match table.get(&fd)?.any_dir()? {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                if !d.perms.contains(DirPerms::READ) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                return d.dir.readlink_at(&path);
+            }
+        };
         if !d.perms.contains(DirPerms::READ) {
             return Err(ErrorCode::NotPermitted.into());
         }
@@ -669,11 +740,14 @@ where
         path: String,
     ) -> FsResult<()> {
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::MUTATE) {
+        let d = table.get(&fd)?.any_dir()?;
+        if !d.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.spawn_blocking(move |d| d.remove_dir(&path)).await?)
+        match d {
+            DirRef::Host(d) => Ok(d.spawn_blocking(move |d| d.remove_dir(&path)).await?),
+            DirRef::Virtual(d) => d.dir.remove_directory_at(&path),
+        }
     }
 
     async fn rename_at(
@@ -684,18 +758,26 @@ where
         new_path: String,
     ) -> FsResult<()> {
         let table = self.table();
-        let old_dir = table.get(&fd)?.dir()?;
-        if !old_dir.perms.contains(DirPerms::MUTATE) {
+        let old_dir = table.get(&fd)?.any_dir()?;
+        if !old_dir.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let new_dir = table.get(&new_fd)?.dir()?;
-        if !new_dir.perms.contains(DirPerms::MUTATE) {
+        let new_dir = table.get(&new_fd)?.any_dir()?;
+        if !new_dir.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
-        Ok(old_dir
-            .spawn_blocking(move |d| d.rename(&old_path, &new_dir_handle, &new_path))
-            .await?)
+        match (old_dir, new_dir) {
+            (DirRef::Host(old_dir), DirRef::Host(new_dir)) => {
+                let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
+                Ok(old_dir
+                    .spawn_blocking(move |d| d.rename(&old_path, &new_dir_handle, &new_path))
+                    .await?)
+            }
+            (DirRef::Virtual(old_dir), DirRef::Virtual(new_dir)) => {
+                old_dir.dir.rename_at(&old_path, &*new_dir.dir, &new_path)
+            }
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
     }
 
     async fn symlink_at(
@@ -709,12 +791,16 @@ where
         use cap_fs_ext::DirExt;
 
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::MUTATE) {
+        let d = table.get(&fd)?.any_dir()?;
+        if !d.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.spawn_blocking(move |d| d.symlink(&src_path, &dest_path))
-            .await?)
+        match d {
+            DirRef::Host(d) => Ok(d
+                .spawn_blocking(move |d| d.symlink(&src_path, &dest_path))
+                .await?),
+            DirRef::Virtual(d) => d.dir.symlink_at(&src_path, &dest_path),
+        }
     }
 
     async fn unlink_file_at(
@@ -725,12 +811,16 @@ where
         use cap_fs_ext::DirExt;
 
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
-        if !d.perms.contains(DirPerms::MUTATE) {
+        let d = table.get(&fd)?.any_dir()?;
+        if !d.perms().contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.spawn_blocking(move |d| d.remove_file_or_symlink(&path))
-            .await?)
+        match d {
+            DirRef::Host(d) => Ok(d
+                .spawn_blocking(move |d| d.remove_file_or_symlink(&path))
+                .await?),
+            DirRef::Virtual(d) => d.dir.unlink_file_at(&path),
+        }
     }
 
     fn read_via_stream(
@@ -739,17 +829,22 @@ where
         offset: types::Filesize,
     ) -> FsResult<Resource<InputStream>> {
         // Trap if fd lookup fails:
-        let f = self.table().get(&fd)?.file()?;
+        let f = self.table().get(&fd)?.any_file()?;
 
-        if !f.perms.contains(FilePerms::READ) {
+        if !f.perms().contains(FilePerms::READ) {
             Err(types::ErrorCode::BadDescriptor)?;
         }
 
         // Create a stream view for it.
-        let reader = FileInputStream::new(f, offset);
+        let reader = match f {
+            FileRef::Host(f) => InputStream::File(FileInputStream::new(f, offset)),
+            FileRef::Virtual(f) => {
+                InputStream::Host(Box::new(VirtualFileInputStream::new(f, offset)))
+            }
+        };
 
         // Insert the stream view into the table. Trap if the table is full.
-        let index = self.table().push(InputStream::File(reader))?;
+        let index = self.table().push(reader)?;
 
         Ok(index)
     }
@@ -760,15 +855,17 @@ where
         offset: types::Filesize,
     ) -> FsResult<Resource<OutputStream>> {
         // Trap if fd lookup fails:
-        let f = self.table().get(&fd)?.file()?;
+        let f = self.table().get(&fd)?.any_file()?;
 
-        if !f.perms.contains(FilePerms::WRITE) {
+        if !f.perms().contains(FilePerms::WRITE) {
             Err(types::ErrorCode::BadDescriptor)?;
         }
 
         // Create a stream view for it.
-        let writer = FileOutputStream::write_at(f, offset);
-        let writer: OutputStream = Box::new(writer);
+        let writer: OutputStream = match f {
+            FileRef::Host(f) => Box::new(FileOutputStream::write_at(f, offset)),
+            FileRef::Virtual(f) => Box::new(VirtualFileOutputStream::write_at(f, offset)),
+        };
 
         // Insert the stream view into the table. Trap if the table is full.
         let index = self.table().push(writer)?;
@@ -781,15 +878,17 @@ where
         fd: Resource<types::Descriptor>,
     ) -> FsResult<Resource<OutputStream>> {
         // Trap if fd lookup fails:
-        let f = self.table().get(&fd)?.file()?;
+        let f = self.table().get(&fd)?.any_file()?;
 
-        if !f.perms.contains(FilePerms::WRITE) {
+        if !f.perms().contains(FilePerms::WRITE) {
             Err(types::ErrorCode::BadDescriptor)?;
         }
 
         // Create a stream view for it.
-        let appender = FileOutputStream::append(f);
-        let appender: OutputStream = Box::new(appender);
+        let appender: OutputStream = match f {
+            FileRef::Host(f) => Box::new(FileOutputStream::append(f)),
+            FileRef::Virtual(f) => Box::new(VirtualFileOutputStream::append(f)),
+        };
 
         // Insert the stream view into the table. Trap if the table is full.
         let index = self.table().push(appender)?;
@@ -803,6 +902,14 @@ where
         b: Resource<types::Descriptor>,
     ) -> anyhow::Result<bool> {
         use cap_fs_ext::MetadataExt;
+        let hash_a = virtual_metadata_hash(self.table().get(&a)?)?;
+        let hash_b = virtual_metadata_hash(self.table().get(&b)?)?;
+        match (hash_a, hash_b) {
+            (Some(a), Some(b)) => return Ok(a.lower == b.lower && a.upper == b.upper),
+            (None, None) => {}
+            // Virtual descriptors are never the same object as host ones.
+            _ => return Ok(false),
+        }
         let descriptor_a = self.table().get(&a)?;
         let meta_a = get_descriptor_metadata(descriptor_a).await?;
         let descriptor_b = self.table().get(&b)?;
@@ -827,19 +934,151 @@ where
     async fn metadata_hash(
         &mut self,
         fd: Resource<types::Descriptor>,
+    ) -> FsResult<types::MetadataHashValue> {
+        let result = self.host_metadata_hash(fd).await;
+        substitute(self.ctx(), EventKind::MetadataHash, result)
+    }
+    async fn metadata_hash_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: types::PathFlags,
+        path: String,
+    ) -> FsResult<types::MetadataHashValue> {
+        let result = self.host_metadata_hash_at(fd, path_flags, path).await;
+        substitute(self.ctx(), EventKind::MetadataHash, result)
+    }
+}
+
+/// Implementations of the `descriptor` methods whose results are recorded, or
+/// substituted when replaying, by the wrappers in `HostDescriptor`.
+impl<T> WasiImpl<T>
+where
+    T: WasiView,
+{
+    async fn host_read(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        len: types::Filesize,
+        offset: types::Filesize,
+    ) -> FsResult<(Vec<u8>, bool)> {
+        use std::io::IoSliceMut;
+        use system_interface::fs::FileIoExt;
+
+        let table = self.table();
+
+        let f = match table.get(&fd)?.any_file()? {
+            FileRef::Host(f) => f,
+            FileRef::Virtual(f) => {
+                if !f.perms.contains(FilePerms::READ) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                let mut buffer = vec![0; len.try_into().unwrap_or(usize::MAX)];
+                let bytes_read = f.read_at(&mut buffer, offset)?;
+                buffer.truncate(bytes_read);
+                let end = bytes_read == 0;
+                return Ok((buffer, end));
+            }
+        };
+        if !f.perms.contains(FilePerms::READ) {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+
+        let (mut buffer, r) = f
+            .spawn_blocking(move |f| {
+                let mut buffer = vec![0; len.try_into().unwrap_or(usize::MAX)];
+                let r = f.read_vectored_at(&mut [IoSliceMut::new(&mut buffer)], offset);
+                (buffer, r)
+            })
+            .await;
+
+        let (bytes_read, state) = match r? {
+            0 => (0, true),
+            n => (n, false),
+        };
+
+        buffer.truncate(
+            bytes_read
+                .try_into()
+                .expect("bytes read into memory as u64 fits in usize"),
+        );
+
+        Ok((buffer, state))
+    }
+
+    async fn host_stat(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+    ) -> FsResult<types::DescriptorStat> {
+        let descriptor = self.table().get(&fd)?;
+        match descriptor {
+            Descriptor::File(f) => {
+                // No permissions check on stat: if opened, allowed to stat it
+                let meta = f.spawn_blocking(|f| f.metadata()).await?;
+                Ok(descriptorstat_from(meta))
+            }
+            Descriptor::Dir(d) => {
+                // No permissions check on stat: if opened, allowed to stat it
+                let meta = d.spawn_blocking(|d| d.dir_metadata()).await?;
+                Ok(descriptorstat_from(meta))
+            }
+            Descriptor::VirtualFile(f) => f.file.stat(),
+            Descriptor::VirtualDir(d) => d.dir.stat(),
+        }
+    }
+
+    async fn host_stat_at(
+        &mut self,
+        fd: Resource<types::Descriptor>,
+        path_flags: types::PathFlags,
+        path: String,
+    ) -> FsResult<types::DescriptorStat> {
+        let table = self.table();
+        let d = match table.get(&fd)?.any_dir()? {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                if !d.perms.contains(DirPerms::READ) {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                return d.dir.stat_at(&path, symlink_follow(path_flags));
+            }
+        };
+        if !d.perms.contains(DirPerms::READ) {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+
+        let meta = if symlink_follow(path_flags) {
+            d.spawn_blocking(move |d| d.metadata(&path)).await?
+        } else {
+            d.spawn_blocking(move |d| d.symlink_metadata(&path)).await?
+        };
+        Ok(descriptorstat_from(meta))
+    }
+
+    async fn host_metadata_hash(
+        &mut self,
+        fd: Resource<types::Descriptor>,
     ) -> FsResult<types::MetadataHashValue> {
         let descriptor_a = self.table().get(&fd)?;
+        if let Some(hash) = virtual_metadata_hash(descriptor_a)? {
+            return Ok(hash);
+        }
         let meta = get_descriptor_metadata(descriptor_a).await?;
         Ok(calculate_metadata_hash(&meta))
     }
-    async fn metadata_hash_at(
+
+    async fn host_metadata_hash_at(
         &mut self,
         fd: Resource<types::Descriptor>,
         path_flags: types::PathFlags,
         path: String,
     ) -> FsResult<types::MetadataHashValue> {
         let table = self.table();
-        let d = table.get(&fd)?.dir()?;
+        let d = match table.get(&fd)?.any_dir()? {
+            DirRef::Host(d) => d,
+            DirRef::Virtual(d) => {
+                return d.dir.metadata_hash_at(&path, symlink_follow(path_flags));
+            }
+        };
         // No permissions check on metadata: if dir opened, allowed to stat it
         let meta = d
             .spawn_blocking(move |d| {
@@ -854,6 +1093,18 @@ where
     }
 }
 
+/// Record the result of a `kind` filesystem operation, or substitute the
+/// recorded result when replaying.
+fn substitute<V: TraceValue>(
+    ctx: &mut WasiCtx,
+    kind: EventKind,
+    result: FsResult<V>,
+) -> FsResult<V> {
+    ctx.trace
+        .substitute(kind, result)
+        .unwrap_or_else(|e| Err(FsError::trap(e)))
+}
+
 #[async_trait::async_trait]
 impl<T> HostDirectoryEntryStream for WasiImpl<T>
 where
@@ -884,10 +1135,23 @@ async fn get_descriptor_metadata(fd: &types::Descriptor) -> FsResult<cap_std::fs
             // No permissions check on metadata: if opened, allowed to stat it
             Ok(d.spawn_blocking(|d| d.dir_metadata()).await?)
         }
+        Descriptor::VirtualFile(_) | Descriptor::VirtualDir(_) => {
+            unreachable!("virtual descriptors don't have host metadata")
+        }
+    }
+}
+
+/// Returns the metadata hash of `fd` if it's a virtual descriptor, or `None`
+/// if it's backed by the host.
+fn virtual_metadata_hash(fd: &types::Descriptor) -> FsResult<Option<types::MetadataHashValue>> {
+    match fd {
+        Descriptor::VirtualFile(f) => Ok(Some(f.file.metadata_hash()?)),
+        Descriptor::VirtualDir(d) => Ok(Some(d.dir.metadata_hash()?)),
+        Descriptor::File(_) | Descriptor::Dir(_) => Ok(None),
     }
 }
 
-fn calculate_metadata_hash(meta: &cap_std::fs::Metadata) -> types::MetadataHashValue {
+pub(crate) fn calculate_metadata_hash(meta: &cap_std::fs::Metadata) -> types::MetadataHashValue {
     use cap_fs_ext::MetadataExt;
     // Without incurring any deps, std provides us with a 64 bit hash
     // function:
@@ -1018,7 +1282,7 @@ impl From<std::num::TryFromIntError> for ErrorCode {
     }
 }
 
-fn descriptortype_from(ft: cap_std::fs::FileType) -> types::DescriptorType {
+pub(crate) fn descriptortype_from(ft: cap_std::fs::FileType) -> types::DescriptorType {
     use cap_fs_ext::FileTypeExt;
     use types::DescriptorType;
     if ft.is_dir() {
@@ -1058,7 +1322,7 @@ fn datetime_from(t: std::time::SystemTime) -> wall_clock::Datetime {
     wall_clock::Datetime::try_from(cap_std::time::SystemTime::from_std(t)).unwrap()
 }
 
-fn descriptorstat_from(meta: cap_std::fs::Metadata) -> types::DescriptorStat {
+pub(crate) fn descriptorstat_from(meta: cap_std::fs::Metadata) -> types::DescriptorStat {
     use cap_fs_ext::MetadataExt;
     types::DescriptorStat {
         type_: descriptortype_from(meta.file_type()),
@@ -2,10 +2,33 @@ use crate::{
     bindings::io::error,
     bindings::io::streams::{self, InputStream, OutputStream},
     poll::subscribe,
-    Pollable, StreamError, StreamResult, WasiImpl, WasiView,
+    trace::{EventKind, TraceValue},
+    Pollable, StreamError, StreamResult, WasiCtx, WasiImpl, WasiView,
 };
 use wasmtime::component::Resource;
 
+/// The recorded result of the next `kind` stream operation, if replaying.
+fn replay<T>(ctx: &mut WasiCtx, kind: EventKind) -> Result<Option<StreamResult<T>>, StreamError>
+where
+    T: TraceValue,
+{
+    ctx.trace.replay_value(kind).map_err(StreamError::Trap)
+}
+
+/// Record `result` as the result of a `kind` stream operation, if recording.
+fn record<T>(
+    ctx: &mut WasiCtx,
+    kind: EventKind,
+    result: &StreamResult<T>,
+) -> Result<(), StreamError>
+where
+    T: TraceValue,
+{
+    ctx.trace
+        .record_value(kind, result)
+        .map_err(StreamError::Trap)
+}
+
 impl<T> error::Host for WasiImpl<T> where T: WasiView {}
 
 impl<T> streams::Host for WasiImpl<T>
@@ -194,13 +217,21 @@ where
     }
 
     async fn read(&mut self, stream: Resource<InputStream>, len: u64) -> StreamResult<Vec<u8>> {
+        if let Some(result) = replay(self.ctx(), EventKind::StreamRead)? {
+            self.table().get(&stream)?;
+            return result;
+        }
         let len = len.try_into().unwrap_or(usize::MAX);
-        let bytes = match self.table().get_mut(&stream)? {
-            InputStream::Host(s) => s.read(len)?,
-            InputStream::File(s) => s.read(len).await?,
-        };
-        debug_assert!(bytes.len() <= len);
-        Ok(bytes.into())
+        let result = match self.table().get_mut(&stream)? {
+            InputStream::Host(s) => s.read(len),
+            InputStream::File(s) => s.read(len).await,
+        }
+        .map(|bytes| {
+            debug_assert!(bytes.len() <= len);
+            bytes.into()
+        });
+        record(self.ctx(), EventKind::StreamRead, &result)?;
+        result
     }
 
     async fn blocking_read(
@@ -208,19 +239,29 @@ where
         stream: Resource<InputStream>,
         len: u64,
     ) -> StreamResult<Vec<u8>> {
-        if let InputStream::Host(s) = self.table().get_mut(&stream)? {
-            s.ready().await;
+        // When replaying the stream's data comes from the trace, so there's no
+        // need to wait for it.
+        if !self.ctx().trace.is_replaying() {
+            if let InputStream::Host(s) = self.table().get_mut(&stream)? {
+                s.ready().await;
+            }
         }
         self.read(stream, len).await
     }
 
     async fn skip(&mut self, stream: Resource<InputStream>, len: u64) -> StreamResult<u64> {
+        if let Some(result) = replay(self.ctx(), EventKind::StreamSkip)? {
+            self.table().get(&stream)?;
+            return result;
+        }
         let len = len.try_into().unwrap_or(usize::MAX);
-        let written = match self.table().get_mut(&stream)? {
-            InputStream::Host(s) => s.skip(len)?,
-            InputStream::File(s) => s.skip(len).await?,
-        };
-        Ok(written.try_into().expect("usize always fits in u64"))
+        let result = match self.table().get_mut(&stream)? {
+            InputStream::Host(s) => s.skip(len),
+            InputStream::File(s) => s.skip(len).await,
+        }
+        .map(|written| written.try_into().expect("usize always fits in u64"));
+        record(self.ctx(), EventKind::StreamSkip, &result)?;
+        result
     }
 
     async fn blocking_skip(
@@ -228,8 +269,10 @@ where
         stream: Resource<InputStream>,
         len: u64,
     ) -> StreamResult<u64> {
-        if let InputStream::Host(s) = self.table().get_mut(&stream)? {
-            s.ready().await;
+        if !self.ctx().trace.is_replaying() {
+            if let InputStream::Host(s) = self.table().get_mut(&stream)? {
+                s.ready().await;
+            }
         }
         self.skip(stream, len).await
     }
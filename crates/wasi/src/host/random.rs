@@ -1,4 +1,5 @@
 use crate::bindings::random::{insecure, insecure_seed, random};
+use crate::trace::EventKind;
 use crate::{WasiImpl, WasiView};
 use cap_rand::{distributions::Standard, Rng};
 
@@ -7,14 +8,19 @@ where
     T: WasiView,
 {
     fn get_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().random)
-            .sample_iter(Standard)
-            .take(len as usize)
-            .collect())
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::RandomBytes, || {
+            (&mut ctx.random)
+                .sample_iter(Standard)
+                .take(len as usize)
+                .collect()
+        })
     }
 
     fn get_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().random.sample(Standard))
+        let ctx = self.ctx();
+        ctx.trace
+            .value(EventKind::RandomU64, || ctx.random.sample(Standard))
     }
 }
 
@@ -23,14 +29,20 @@ where
     T: WasiView,
 {
     fn get_insecure_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().insecure_random)
-            .sample_iter(Standard)
-            .take(len as usize)
-            .collect())
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::InsecureRandomBytes, || {
+            (&mut ctx.insecure_random)
+                .sample_iter(Standard)
+                .take(len as usize)
+                .collect()
+        })
     }
 
     fn get_insecure_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().insecure_random.sample(Standard))
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::InsecureRandomU64, || {
+            ctx.insecure_random.sample(Standard)
+        })
     }
 }
 
@@ -39,7 +51,10 @@ where
     T: WasiView,
 {
     fn insecure_seed(&mut self) -> anyhow::Result<(u64, u64)> {
-        let seed: u128 = self.ctx().insecure_random_seed;
-        Ok((seed as u64, (seed >> 64) as u64))
+        let ctx = self.ctx();
+        ctx.trace.value(EventKind::InsecureSeed, || {
+            let seed: u128 = ctx.insecure_random_seed;
+            (seed as u64, (seed >> 64) as u64)
+        })
     }
 }
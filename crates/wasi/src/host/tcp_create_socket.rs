@@ -11,7 +11,10 @@ where
         &mut self,
         address_family: IpAddressFamily,
     ) -> SocketResult<Resource<TcpSocket>> {
-        let socket = TcpSocket::new(address_family.into())?;
+        let socket = match &self.ctx().socket_backend {
+            Some(backend) => TcpSocket::new_virtual(backend.clone(), address_family.into()),
+            None => TcpSocket::new(address_family.into())?,
+        };
         let socket = self.table().push(socket)?;
         Ok(socket)
     }
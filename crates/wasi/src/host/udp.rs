@@ -5,7 +5,9 @@ use crate::{
         sockets::network::{ErrorCode, IpAddressFamily, IpSocketAddress, Network},
         sockets::udp,
     },
-    udp::{IncomingDatagramStream, OutgoingDatagramStream, SendState, UdpState},
+    udp::{
+        IncomingDatagramStream, OutgoingDatagramStream, SendState, UdpHandle, UdpOptions, UdpState,
+    },
     Subscribe,
 };
 use crate::{Pollable, SocketError, SocketResult, WasiImpl, WasiView};
@@ -14,7 +16,6 @@ use async_trait::async_trait;
 use io_lifetimes::AsSocketlike;
 use rustix::io::Errno;
 use std::net::SocketAddr;
-use tokio::io::Interest;
 use wasmtime::component::Resource;
 
 /// Theoretical maximum byte size of a UDP datagram, the real limit is lower,
@@ -41,7 +42,7 @@ where
         match table.get(&this)?.udp_state {
             UdpState::Default => {}
             UdpState::BindStarted => return Err(ErrorCode::ConcurrencyConflict.into()),
-            UdpState::Bound | UdpState::Connected => return Err(ErrorCode::InvalidState.into()),
+            UdpState::Bound | UdpState::Connected(_) => return Err(ErrorCode::InvalidState.into()),
         }
 
         // Set the socket addr check on the socket so later functions have access to it through the socket handle
@@ -56,24 +57,34 @@ where
 
         util::validate_address_family(&local_address, &socket.family)?;
 
-        {
+        let endpoint = {
             check.check(local_address, SocketAddrUse::UdpBind).await?;
 
-            // Perform the OS bind call.
-            util::udp_bind(socket.udp_socket(), &local_address).map_err(|error| match error {
-                // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
-                // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
-                //
-                // The most common reasons for this error should have already
-                // been handled by our own validation slightly higher up in this
-                // function. This error mapping is here just in case there is
-                // an edge case we didn't catch.
-                Errno::AFNOSUPPORT => ErrorCode::InvalidArgument,
-                _ => ErrorCode::from(error),
-            })?;
-        }
+            match &socket.inner {
+                UdpHandle::Host(udp_socket) => {
+                    // Perform the OS bind call.
+                    util::udp_bind(&**udp_socket, &local_address).map_err(|error| match error {
+                        // From https://pubs.opengroup.org/onlinepubs/9699919799/functions/bind.html:
+                        // > [EAFNOSUPPORT] The specified address is not a valid address for the address family of the specified socket
+                        //
+                        // The most common reasons for this error should have already
+                        // been handled by our own validation slightly higher up in this
+                        // function. This error mapping is here just in case there is
+                        // an edge case we didn't catch.
+                        Errno::AFNOSUPPORT => ErrorCode::InvalidArgument,
+                        _ => ErrorCode::from(error),
+                    })?;
+                    None
+                }
+                UdpHandle::Unbound(backend) => Some(backend.udp_bind(local_address)?),
+                UdpHandle::Virtual(_) => return Err(ErrorCode::InvalidState.into()),
+            }
+        };
 
         let socket = table.get_mut(&this)?;
+        if let Some(endpoint) = endpoint {
+            socket.inner = UdpHandle::Virtual(endpoint);
+        }
         socket.udp_state = UdpState::BindStarted;
 
         Ok(())
@@ -114,7 +125,7 @@ where
         let remote_address = remote_address.map(SocketAddr::from);
 
         match socket.udp_state {
-            UdpState::Bound | UdpState::Connected => {}
+            UdpState::Bound | UdpState::Connected(_) => {}
             _ => return Err(ErrorCode::InvalidState.into()),
         }
 
@@ -125,8 +136,10 @@ where
         //   if there isn't a disconnect in between.
 
         // Step #1: Disconnect
-        if let UdpState::Connected = socket.udp_state {
-            util::udp_disconnect(socket.udp_socket())?;
+        if let UdpState::Connected(_) = socket.udp_state {
+            if let Some(udp_socket) = socket.udp_socket() {
+                util::udp_disconnect(udp_socket)?;
+            }
             socket.udp_state = UdpState::Bound;
         }
 
@@ -139,8 +152,10 @@ where
             util::validate_address_family(&connect_addr, &socket.family)?;
             check.check(connect_addr, SocketAddrUse::UdpConnect).await?;
 
-            rustix::net::connect(socket.udp_socket(), &connect_addr).map_err(
-                |error| match error {
+            // Sockets on a backend are connected by the datagram streams
+            // filtering on the remote address.
+            if let Some(udp_socket) = socket.udp_socket() {
+                rustix::net::connect(udp_socket, &connect_addr).map_err(|error| match error {
                     Errno::AFNOSUPPORT => ErrorCode::InvalidArgument, // See `bind` implementation.
                     Errno::INPROGRESS => {
                         tracing::debug!(
@@ -149,9 +164,9 @@ where
                         ErrorCode::Unknown
                     }
                     _ => ErrorCode::from(error),
-                },
-            )?;
-            socket.udp_state = UdpState::Connected;
+                })?;
+            }
+            socket.udp_state = UdpState::Connected(connect_addr);
         }
 
         let incoming_stream = IncomingDatagramStream {
@@ -182,10 +197,7 @@ where
             _ => {}
         }
 
-        let addr = socket
-            .udp_socket()
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .local_addr()?;
+        let addr = socket.inner.local_addr()?;
         Ok(addr.into())
     }
 
@@ -193,15 +205,17 @@ where
         let table = self.table();
         let socket = table.get(&this)?;
 
-        match socket.udp_state {
-            UdpState::Connected => {}
+        let connected_addr = match socket.udp_state {
+            UdpState::Connected(addr) => addr,
             _ => return Err(ErrorCode::InvalidState.into()),
-        }
+        };
 
-        let addr = socket
-            .udp_socket()
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .peer_addr()?;
+        let addr = match socket.udp_socket() {
+            Some(udp_socket) => udp_socket
+                .as_socketlike_view::<std::net::UdpSocket>()
+                .peer_addr()?,
+            None => connected_addr,
+        };
         Ok(addr.into())
     }
 
@@ -220,11 +234,17 @@ where
 
     fn unicast_hop_limit(&mut self, this: Resource<udp::UdpSocket>) -> SocketResult<u8> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
+        let family = socket.family;
 
-        let ttl = match socket.family {
-            SocketAddressFamily::Ipv4 => util::get_ip_ttl(socket.udp_socket())?,
-            SocketAddressFamily::Ipv6 => util::get_ipv6_unicast_hops(socket.udp_socket())?,
+        let ttl = match (socket.options(), family) {
+            (UdpOptions::Virtual(options), _) => options.hop_limit,
+            (UdpOptions::Host(udp_socket), SocketAddressFamily::Ipv4) => {
+                util::get_ip_ttl(udp_socket)?
+            }
+            (UdpOptions::Host(udp_socket), SocketAddressFamily::Ipv6) => {
+                util::get_ipv6_unicast_hops(udp_socket)?
+            }
         };
 
         Ok(ttl)
@@ -236,11 +256,17 @@ where
         value: u8,
     ) -> SocketResult<()> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
+        let family = socket.family;
 
-        match socket.family {
-            SocketAddressFamily::Ipv4 => util::set_ip_ttl(socket.udp_socket(), value)?,
-            SocketAddressFamily::Ipv6 => util::set_ipv6_unicast_hops(socket.udp_socket(), value)?,
+        match (socket.options(), family) {
+            (UdpOptions::Virtual(options), _) => options.set_hop_limit(value)?,
+            (UdpOptions::Host(udp_socket), SocketAddressFamily::Ipv4) => {
+                util::set_ip_ttl(udp_socket, value)?
+            }
+            (UdpOptions::Host(udp_socket), SocketAddressFamily::Ipv6) => {
+                util::set_ipv6_unicast_hops(udp_socket, value)?
+            }
         }
 
         Ok(())
@@ -248,9 +274,12 @@ where
 
     fn receive_buffer_size(&mut self, this: Resource<udp::UdpSocket>) -> SocketResult<u64> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
 
-        let value = util::get_socket_recv_buffer_size(socket.udp_socket())?;
+        let value = match socket.options() {
+            UdpOptions::Virtual(options) => options.receive_buffer_size,
+            UdpOptions::Host(udp_socket) => util::get_socket_recv_buffer_size(udp_socket)?,
+        };
         Ok(value as u64)
     }
 
@@ -260,18 +289,24 @@ where
         value: u64,
     ) -> SocketResult<()> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
         let value = value.try_into().unwrap_or(usize::MAX);
 
-        util::set_socket_recv_buffer_size(socket.udp_socket(), value)?;
+        match socket.options() {
+            UdpOptions::Virtual(options) => options.set_receive_buffer_size(value)?,
+            UdpOptions::Host(udp_socket) => util::set_socket_recv_buffer_size(udp_socket, value)?,
+        }
         Ok(())
     }
 
     fn send_buffer_size(&mut self, this: Resource<udp::UdpSocket>) -> SocketResult<u64> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
 
-        let value = util::get_socket_send_buffer_size(socket.udp_socket())?;
+        let value = match socket.options() {
+            UdpOptions::Virtual(options) => options.send_buffer_size,
+            UdpOptions::Host(udp_socket) => util::get_socket_send_buffer_size(udp_socket)?,
+        };
         Ok(value as u64)
     }
 
@@ -281,10 +316,13 @@ where
         value: u64,
     ) -> SocketResult<()> {
         let table = self.table();
-        let socket = table.get(&this)?;
+        let socket = table.get_mut(&this)?;
         let value = value.try_into().unwrap_or(usize::MAX);
 
-        util::set_socket_send_buffer_size(socket.udp_socket(), value)?;
+        match socket.options() {
+            UdpOptions::Virtual(options) => options.set_send_buffer_size(value)?,
+            UdpOptions::Host(udp_socket) => util::set_socket_send_buffer_size(udp_socket, value)?,
+        }
         Ok(())
     }
 
@@ -390,11 +428,7 @@ where
 #[async_trait]
 impl Subscribe for IncomingDatagramStream {
     async fn ready(&mut self) {
-        // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
-        self.inner
-            .ready(Interest::READABLE)
-            .await
-            .expect("failed to await UDP socket readiness");
+        self.inner.readable().await;
     }
 }
 
@@ -454,11 +488,9 @@ where
             util::validate_remote_address(&addr)?;
             util::validate_address_family(&addr, &stream.family)?;
 
-            if stream.remote_address == Some(addr) {
-                stream.inner.try_send(&datagram.data)?;
-            } else {
-                stream.inner.try_send_to(&datagram.data, addr)?;
-            }
+            stream
+                .inner
+                .try_send_to(&datagram.data, addr, stream.remote_address == Some(addr))?;
 
             Ok(())
         }
@@ -533,11 +565,7 @@ impl Subscribe for OutgoingDatagramStream {
         match self.send_state {
             SendState::Idle | SendState::Permitted(_) => {}
             SendState::Waiting => {
-                // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
-                self.inner
-                    .ready(Interest::WRITABLE)
-                    .await
-                    .expect("failed to await UDP socket readiness");
+                self.inner.writable().await;
                 self.send_state = SendState::Idle;
             }
         }
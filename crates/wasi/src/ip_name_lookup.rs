@@ -5,6 +5,7 @@ use crate::poll::{subscribe, Pollable, Subscribe};
 use crate::runtime::{spawn_blocking, AbortOnDropJoinHandle};
 use crate::{SocketError, WasiImpl, WasiView};
 use anyhow::Result;
+use std::future::Future;
 use std::mem;
 use std::net::{Ipv6Addr, ToSocketAddrs};
 use std::pin::Pin;
@@ -15,7 +16,7 @@ use wasmtime::component::Resource;
 use super::network::{from_ipv4_addr, from_ipv6_addr};
 
 pub enum ResolveAddressStream {
-    Waiting(AbortOnDropJoinHandle<Result<Vec<IpAddress>, SocketError>>),
+    Waiting(Pin<Box<dyn Future<Output = Result<Vec<IpAddress>, SocketError>> + Send>>),
     Done(Result<vec::IntoIter<IpAddress>, SocketError>),
 }
 
@@ -37,7 +38,27 @@ where
             return Err(ErrorCode::PermanentResolverFailure.into());
         }
 
-        let task = spawn_blocking(move || blocking_resolve(&host));
+        let task: Pin<Box<dyn Future<Output = _> + Send>> = match (host, &self.ctx().socket_backend)
+        {
+            (url::Host::Domain(domain), Some(backend)) => {
+                let backend = backend.clone();
+                Box::pin(async move {
+                    let addresses = backend
+                        .resolve_addresses(&domain)
+                        .await
+                        .map_err(|_| ErrorCode::NameUnresolvable)?;
+                    Ok(addresses
+                        .iter()
+                        .map(|ip| util::to_canonical(ip).into())
+                        .collect())
+                })
+            }
+            (host, _) => {
+                let task: AbortOnDropJoinHandle<_> =
+                    spawn_blocking(move || blocking_resolve(&host));
+                Box::pin(task)
+            }
+        };
         let resource = self.table().push(ResolveAddressStream::Waiting(task))?;
         Ok(resource)
     }
@@ -203,14 +203,22 @@ pub mod runtime;
 mod stdio;
 mod stream;
 mod tcp;
+mod trace;
 mod udp;
 mod write_stream;
 
 pub use self::clocks::{HostMonotonicClock, HostWallClock};
 pub use self::ctx::{WasiCtx, WasiCtxBuilder, WasiImpl, WasiView};
 pub use self::error::{I32Exit, TrappableError};
-pub use self::filesystem::{DirPerms, FileInputStream, FilePerms, FsError, FsResult};
-pub use self::network::{Network, SocketAddrUse, SocketError, SocketResult};
+pub use self::filesystem::{
+    DirPerms, FileInputStream, FilePerms, FsError, FsResult, MemoryDir, MemoryFile, OverlayDir,
+    WasiDir, WasiFile, WasiNode,
+};
+pub use self::network::{
+    LoopbackNetwork, LoopbackTcpListener, LoopbackTcpStream, LoopbackUdpSocket, Network,
+    SocketAddrUse, SocketBackend, SocketError, SocketResult, TcpBinding, TcpConnection,
+    TcpListener, TcpStreams, UdpEndpoint,
+};
 pub use self::poll::{subscribe, ClosureFuture, MakeFuture, Pollable, PollableFuture, Subscribe};
 pub use self::random::{thread_rng, Deterministic};
 pub use self::stdio::{
@@ -1,9 +1,18 @@
 use crate::bindings::sockets::network::{ErrorCode, Ipv4Address, Ipv6Address};
-use crate::TrappableError;
+use crate::{HostInputStream, HostOutputStream, TrappableError};
 use std::future::Future;
-use std::net::SocketAddr;
+use std::io;
+use std::net::{IpAddr, Shutdown, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+mod loopback;
+
+pub use self::loopback::{
+    LoopbackNetwork, LoopbackTcpListener, LoopbackTcpStream, LoopbackUdpSocket,
+};
 
 pub struct Network {
     pub socket_addr_check: SocketAddrCheck,
@@ -86,6 +95,197 @@ impl From<rustix::io::Errno> for SocketError {
     }
 }
 
+/// A network whose sockets are provided by an embedder-defined implementation
+/// rather than the host's network stack.
+///
+/// A socket backend is installed with [`WasiCtxBuilder::socket_backend`], after
+/// which every TCP and UDP socket created by the guest, as well as every name
+/// looked up through `wasi:sockets/ip-name-lookup`, goes through it. An
+/// in-process implementation which lets guests and host code talk to each
+/// other without touching the host network is provided with
+/// [`LoopbackNetwork`].
+///
+/// The socket address check configured with
+/// [`WasiCtxBuilder::socket_addr_check`] still applies, as do
+/// [`WasiCtxBuilder::allow_tcp`] and friends. Addresses are validated, for
+/// example against the family of the socket, before any method here is
+/// called.
+///
+/// [`WasiCtxBuilder::socket_backend`]: crate::WasiCtxBuilder::socket_backend
+/// [`WasiCtxBuilder::socket_addr_check`]: crate::WasiCtxBuilder::socket_addr_check
+/// [`WasiCtxBuilder::allow_tcp`]: crate::WasiCtxBuilder::allow_tcp
+#[async_trait::async_trait]
+pub trait SocketBackend: Send + Sync + 'static {
+    /// Binds a TCP socket to `addr`.
+    ///
+    /// A port of 0 requests an ephemeral port. The binding should fail with
+    /// [`io::ErrorKind::AddrInUse`] if `addr` is already bound.
+    fn tcp_bind(&self, addr: SocketAddr) -> io::Result<Box<dyn TcpBinding>>;
+
+    /// Binds a UDP socket to `addr`.
+    ///
+    /// A port of 0 requests an ephemeral port. The binding should fail with
+    /// [`io::ErrorKind::AddrInUse`] if `addr` is already bound.
+    fn udp_bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn UdpEndpoint>>;
+
+    /// Resolves the domain name `name` to a list of addresses.
+    ///
+    /// Names which are IP addresses are resolved without calling this method.
+    /// By default this fails to resolve any name.
+    async fn resolve_addresses(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let _ = name;
+        Err(io::ErrorKind::NotFound.into())
+    }
+}
+
+/// A TCP socket bound to a local address of a [`SocketBackend`], which can
+/// either start listening or connect to a remote address.
+pub trait TcpBinding: Send + Sync {
+    /// Returns the address this socket is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Starts listening for incoming connections, queueing at most `backlog`
+    /// connections which haven't been accepted yet.
+    fn listen(self: Box<Self>, backlog: u32) -> io::Result<Box<dyn TcpListener>>;
+
+    /// Connects to `remote`.
+    fn connect(
+        self: Box<Self>,
+        remote: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = io::Result<TcpStreams>> + Send>>;
+}
+
+/// A listening TCP socket of a [`SocketBackend`].
+pub trait TcpListener: Send + Sync {
+    /// Returns the address this socket is listening on.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Accepts the next incoming connection, arranging for the task in `cx`
+    /// to be woken once one arrives.
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TcpStreams>>;
+
+    /// Changes the number of connections which may be queued.
+    ///
+    /// By default this is unsupported.
+    fn set_backlog(&mut self, backlog: u32) -> io::Result<()> {
+        let _ = backlog;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// An established TCP connection of a [`SocketBackend`].
+pub trait TcpConnection: Send + Sync {
+    /// Returns the local address of this connection.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns the remote address of this connection.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Shuts down the receiving side, the sending side, or both sides of this
+    /// connection.
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+}
+
+/// A TCP connection along with the streams used to receive and send data.
+pub type TcpStreams = (
+    Box<dyn TcpConnection>,
+    Box<dyn HostInputStream>,
+    Box<dyn HostOutputStream>,
+);
+
+/// A bound UDP socket of a [`SocketBackend`].
+#[async_trait::async_trait]
+pub trait UdpEndpoint: Send + Sync {
+    /// Returns the address this socket is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Receives the next datagram into `buf`, returning its size and the
+    /// address it was sent from, or fails with
+    /// [`io::ErrorKind::WouldBlock`] if none has arrived yet.
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+
+    /// Sends `data` to `addr`, or fails with [`io::ErrorKind::WouldBlock`]
+    /// if the datagram can't be sent right now.
+    fn try_send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<()>;
+
+    /// Waits until a datagram can be received.
+    async fn readable(&self);
+
+    /// Waits until a datagram can be sent.
+    ///
+    /// By default sockets are always writable.
+    async fn writable(&self) {}
+}
+
+/// Socket options of sockets on a [`SocketBackend`], which are only
+/// remembered so they can be read back by the guest.
+#[derive(Clone, Debug)]
+pub(crate) struct VirtualSocketOptions {
+    pub keep_alive_enabled: bool,
+    pub keep_alive_idle_time: Duration,
+    pub keep_alive_interval: Duration,
+    pub keep_alive_count: u32,
+    pub hop_limit: u8,
+    pub receive_buffer_size: usize,
+    pub send_buffer_size: usize,
+}
+
+impl Default for VirtualSocketOptions {
+    fn default() -> Self {
+        // Linux' defaults.
+        Self {
+            keep_alive_enabled: false,
+            keep_alive_idle_time: Duration::from_secs(7200),
+            keep_alive_interval: Duration::from_secs(75),
+            keep_alive_count: 9,
+            hop_limit: 64,
+            receive_buffer_size: 64 * 1024,
+            send_buffer_size: 64 * 1024,
+        }
+    }
+}
+
+impl VirtualSocketOptions {
+    pub fn set_keep_alive_idle_time(&mut self, value: Duration) -> SocketResult<()> {
+        self.keep_alive_idle_time = nonzero(value, Duration::ZERO)?;
+        Ok(())
+    }
+
+    pub fn set_keep_alive_interval(&mut self, value: Duration) -> SocketResult<()> {
+        self.keep_alive_interval = nonzero(value, Duration::ZERO)?;
+        Ok(())
+    }
+
+    pub fn set_keep_alive_count(&mut self, value: u32) -> SocketResult<()> {
+        self.keep_alive_count = nonzero(value, 0)?;
+        Ok(())
+    }
+
+    pub fn set_hop_limit(&mut self, value: u8) -> SocketResult<()> {
+        self.hop_limit = nonzero(value, 0)?;
+        Ok(())
+    }
+
+    pub fn set_receive_buffer_size(&mut self, value: usize) -> SocketResult<()> {
+        self.receive_buffer_size = nonzero(value, 0)?;
+        Ok(())
+    }
+
+    pub fn set_send_buffer_size(&mut self, value: usize) -> SocketResult<()> {
+        self.send_buffer_size = nonzero(value, 0)?;
+        Ok(())
+    }
+}
+
+/// WIT: "If the provided value is 0, an `invalid-argument` error is returned."
+fn nonzero<T: PartialEq>(value: T, zero: T) -> SocketResult<T> {
+    if value == zero {
+        Err(ErrorCode::InvalidArgument.into())
+    } else {
+        Ok(value)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum SocketAddressFamily {
     Ipv4,
@@ -0,0 +1,1023 @@
+//! An in-process network implementing [`SocketBackend`].
+
+use super::{SocketBackend, TcpBinding, TcpConnection, TcpListener, TcpStreams, UdpEndpoint};
+use crate::{HostInputStream, HostOutputStream, StreamError, StreamResult, Subscribe};
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::ops::RangeInclusive;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Number of bytes which may be in flight in one direction of a TCP
+/// connection before writes have to wait for the peer to read.
+const TCP_WINDOW: usize = 256 * 1024;
+
+/// Number of datagrams which may be queued on a UDP socket before further
+/// datagrams are dropped.
+const UDP_QUEUE: usize = 1024;
+
+/// Connections queued on listeners created with [`LoopbackNetwork::listen`].
+const DEFAULT_BACKLOG: u32 = 128;
+
+/// The dynamic port range suggested by RFC 6335.
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// An in-process network which guests and host code can use to talk to each
+/// other without touching the host network.
+///
+/// A `LoopbackNetwork` is a handle to a network which may be shared: cloning
+/// it produces another handle to the same network. Installing the same
+/// network in the [`WasiCtx`] of several instances with
+/// [`WasiCtxBuilder::socket_backend`] lets them connect to each other, and
+/// host code can take part with [`LoopbackNetwork::listen`],
+/// [`LoopbackNetwork::connect`], and [`LoopbackNetwork::bind_udp`].
+///
+/// Every address is local to the network: sockets may bind to any unicast
+/// address, and connecting to an address reaches the socket bound to it or
+/// to the unspecified address of the same family. Nothing is forwarded to
+/// the host network. Domain names can be made resolvable with
+/// [`LoopbackNetwork::add_host`].
+///
+/// Latency and loss of UDP datagrams can be simulated with
+/// [`LoopbackNetwork::set_latency`] and
+/// [`LoopbackNetwork::set_drop_probability`]. Which datagrams are dropped is
+/// decided by a pseudo-random sequence which is the same on every run.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::{LoopbackNetwork, WasiCtxBuilder};
+///
+/// let network = LoopbackNetwork::new();
+/// network.add_host("example.com", ["10.0.0.1".parse().unwrap()]);
+///
+/// let mut wasi = WasiCtxBuilder::new();
+/// wasi.socket_backend(network.clone())
+///     .inherit_network()
+///     .allow_ip_name_lookup(true);
+/// ```
+///
+/// [`WasiCtx`]: crate::WasiCtx
+/// [`WasiCtxBuilder::socket_backend`]: crate::WasiCtxBuilder::socket_backend
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork(Net);
+
+type Net = Arc<Mutex<State>>;
+
+struct State {
+    latency: Duration,
+    drop_probability: f64,
+    /// State of the xorshift generator deciding which datagrams are dropped.
+    rng: u64,
+    names: HashMap<String, Vec<IpAddr>>,
+    /// Bound TCP addresses, along with the queue of incoming connections
+    /// once they're listening.
+    tcp: HashMap<SocketAddr, Option<Arc<Mutex<Backlog>>>>,
+    /// Bound UDP addresses, along with their queue of incoming datagrams.
+    udp: HashMap<SocketAddr, Arc<Datagrams>>,
+    next_port: u16,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            drop_probability: 0.0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            names: HashMap::new(),
+            tcp: HashMap::new(),
+            udp: HashMap::new(),
+            next_port: *EPHEMERAL_PORTS.start(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap()
+}
+
+fn unspecified(addr: SocketAddr) -> SocketAddr {
+    let ip = match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    SocketAddr::new(ip, addr.port())
+}
+
+impl State {
+    fn in_use(&self, protocol: Protocol, addr: SocketAddr) -> bool {
+        let conflicts = |bound: &SocketAddr| {
+            bound.port() == addr.port()
+                && bound.is_ipv4() == addr.is_ipv4()
+                && (bound.ip() == addr.ip()
+                    || bound.ip().is_unspecified()
+                    || addr.ip().is_unspecified())
+        };
+        match protocol {
+            Protocol::Tcp => self.tcp.keys().any(conflicts),
+            Protocol::Udp => self.udp.keys().any(conflicts),
+        }
+    }
+
+    /// Picks the address to bind to for `addr`, choosing an ephemeral port if
+    /// its port is 0.
+    fn pick(&mut self, protocol: Protocol, mut addr: SocketAddr) -> io::Result<SocketAddr> {
+        if addr.port() != 0 {
+            if self.in_use(protocol, addr) {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+            return Ok(addr);
+        }
+        for _ in EPHEMERAL_PORTS {
+            let port = self.next_port;
+            self.next_port = if port == *EPHEMERAL_PORTS.end() {
+                *EPHEMERAL_PORTS.start()
+            } else {
+                port + 1
+            };
+            addr.set_port(port);
+            if !self.in_use(protocol, addr) {
+                return Ok(addr);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "no more free local ports",
+        ))
+    }
+
+    /// Returns the queue of the socket listening on `addr`, if any.
+    fn listener(&self, addr: SocketAddr) -> Option<Arc<Mutex<Backlog>>> {
+        self.tcp
+            .get(&addr)
+            .or_else(|| self.tcp.get(&unspecified(addr)))
+            .cloned()
+            .flatten()
+    }
+
+    /// Returns the queue of the UDP socket bound to `addr`, if any.
+    fn datagrams(&self, addr: SocketAddr) -> Option<Arc<Datagrams>> {
+        self.udp
+            .get(&addr)
+            .or_else(|| self.udp.get(&unspecified(addr)))
+            .cloned()
+    }
+
+    fn drop_datagram(&mut self) -> bool {
+        if self.drop_probability <= 0.0 {
+            return false;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        ((self.rng >> 11) as f64 / (1u64 << 53) as f64) < self.drop_probability
+    }
+}
+
+/// An address bound on the network, which is released when dropped.
+struct Reservation {
+    net: Net,
+    protocol: Protocol,
+    addr: SocketAddr,
+}
+
+impl Reservation {
+    fn new(net: &Net, protocol: Protocol, addr: SocketAddr) -> io::Result<Self> {
+        let mut state = lock(net);
+        let addr = state.pick(protocol, addr)?;
+        match protocol {
+            Protocol::Tcp => {
+                state.tcp.insert(addr, None);
+            }
+            Protocol::Udp => {
+                state.udp.insert(addr, Arc::default());
+            }
+        }
+        Ok(Self {
+            net: net.clone(),
+            protocol,
+            addr,
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = lock(&self.net);
+        // Dropped after the lock is released as this may close connections
+        // which were never accepted.
+        let _backlog = match self.protocol {
+            Protocol::Tcp => state.tcp.remove(&self.addr),
+            Protocol::Udp => {
+                state.udp.remove(&self.addr);
+                None
+            }
+        };
+        drop(state);
+    }
+}
+
+impl LoopbackNetwork {
+    /// Creates a new network without any bound sockets or host names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays the delivery of all data sent over this network by `latency`.
+    ///
+    /// This applies to data sent after this call, and to establishing TCP
+    /// connections. By default there is no latency.
+    pub fn set_latency(&self, latency: Duration) {
+        lock(&self.0).latency = latency;
+    }
+
+    /// Drops each UDP datagram sent over this network with the given
+    /// `probability`.
+    ///
+    /// TCP connections are unaffected. By default no datagrams are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probability` is not between 0 and 1.
+    pub fn set_drop_probability(&self, probability: f64) {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "drop probability must be between 0 and 1"
+        );
+        lock(&self.0).drop_probability = probability;
+    }
+
+    /// Makes `name` resolve to `addrs` in `wasi:sockets/ip-name-lookup`,
+    /// replacing any addresses it previously resolved to.
+    pub fn add_host(&self, name: &str, addrs: impl IntoIterator<Item = IpAddr>) {
+        lock(&self.0)
+            .names
+            .insert(name.to_ascii_lowercase(), addrs.into_iter().collect());
+    }
+
+    /// Listens for TCP connections on `addr`.
+    ///
+    /// A port of 0 picks an ephemeral port, see
+    /// [`LoopbackTcpListener::local_addr`].
+    pub fn listen(&self, addr: SocketAddr) -> io::Result<LoopbackTcpListener> {
+        let binding = Binding(Reservation::new(&self.0, Protocol::Tcp, addr)?);
+        Ok(LoopbackTcpListener(binding.listen(DEFAULT_BACKLOG)))
+    }
+
+    /// Connects to the TCP socket listening on `addr`.
+    pub async fn connect(&self, addr: SocketAddr) -> io::Result<LoopbackTcpStream> {
+        let binding = Binding(Reservation::new(
+            &self.0,
+            Protocol::Tcp,
+            unspecified(SocketAddr::new(addr.ip(), 0)),
+        )?);
+        Ok(LoopbackTcpStream(binding.connect(addr).await?))
+    }
+
+    /// Binds a UDP socket to `addr`.
+    ///
+    /// A port of 0 picks an ephemeral port, see
+    /// [`LoopbackUdpSocket::local_addr`].
+    pub fn bind_udp(&self, addr: SocketAddr) -> io::Result<LoopbackUdpSocket> {
+        Ok(LoopbackUdpSocket(Arc::new(UdpSocket::new(&self.0, addr)?)))
+    }
+}
+
+#[async_trait::async_trait]
+impl SocketBackend for LoopbackNetwork {
+    fn tcp_bind(&self, addr: SocketAddr) -> io::Result<Box<dyn TcpBinding>> {
+        Ok(Box::new(Binding(Reservation::new(
+            &self.0,
+            Protocol::Tcp,
+            addr,
+        )?)))
+    }
+
+    fn udp_bind(&self, addr: SocketAddr) -> io::Result<Arc<dyn UdpEndpoint>> {
+        Ok(Arc::new(UdpSocket::new(&self.0, addr)?))
+    }
+
+    async fn resolve_addresses(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        lock(&self.0)
+            .names
+            .get(&name.to_ascii_lowercase())
+            .cloned()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+/// One direction of a TCP connection.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct PipeState {
+    /// Data which has been written, along with when it's delivered.
+    chunks: VecDeque<(Instant, Bytes)>,
+    buffered: usize,
+    reader_closed: bool,
+    writer_closed: bool,
+}
+
+impl Pipe {
+    fn close_reader(&self) {
+        let mut state = lock(&self.state);
+        state.reader_closed = true;
+        state.chunks.clear();
+        state.buffered = 0;
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    fn close_writer(&self) {
+        lock(&self.state).writer_closed = true;
+        self.changed.notify_waiters();
+    }
+
+    /// Returns how many bytes may be written without exceeding the window.
+    fn capacity(&self) -> io::Result<usize> {
+        let state = lock(&self.state);
+        if state.writer_closed {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "connection was shut down",
+            ));
+        }
+        if state.reader_closed {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+        Ok(TCP_WINDOW.saturating_sub(state.buffered))
+    }
+
+    fn write(&self, data: Bytes, latency: Duration) -> io::Result<()> {
+        self.capacity()?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut state = lock(&self.state);
+        let mut at = Instant::now() + latency;
+        if let Some((last, _)) = state.chunks.back() {
+            at = at.max(*last);
+        }
+        state.buffered += data.len();
+        state.chunks.push_back((at, data));
+        drop(state);
+        self.changed.notify_waiters();
+        Ok(())
+    }
+
+    /// Reads up to `size` bytes which have been delivered, returning `None`
+    /// once the writer has closed and all data has been read.
+    fn read(&self, size: usize) -> Option<Bytes> {
+        let mut state = lock(&self.state);
+        if state.reader_closed {
+            return None;
+        }
+        let now = Instant::now();
+        let mut data = BytesMut::new();
+        while data.len() < size {
+            let Some((at, chunk)) = state.chunks.front_mut() else {
+                break;
+            };
+            if *at > now {
+                break;
+            }
+            let n = chunk.len().min(size - data.len());
+            data.extend_from_slice(&chunk.split_to(n));
+            if chunk.is_empty() {
+                state.chunks.pop_front();
+            }
+        }
+        if data.is_empty() && state.chunks.is_empty() && state.writer_closed {
+            return None;
+        }
+        state.buffered -= data.len();
+        drop(state);
+        if !data.is_empty() {
+            self.changed.notify_waiters();
+        }
+        Some(data.freeze())
+    }
+
+    async fn readable(&self) {
+        loop {
+            let mut changed = pin!(self.changed.notified());
+            changed.as_mut().enable();
+            let deadline = {
+                let state = lock(&self.state);
+                match state.chunks.front() {
+                    _ if state.reader_closed => return,
+                    Some((at, _)) if *at <= Instant::now() => return,
+                    None if state.writer_closed => return,
+                    Some((at, _)) => Some(*at),
+                    None => None,
+                }
+            };
+            match deadline {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => changed.await,
+            }
+        }
+    }
+
+    async fn writable(&self) {
+        loop {
+            let mut changed = pin!(self.changed.notified());
+            changed.as_mut().enable();
+            {
+                let state = lock(&self.state);
+                if state.reader_closed || state.writer_closed || state.buffered < TCP_WINDOW {
+                    return;
+                }
+            }
+            changed.await;
+        }
+    }
+}
+
+/// One side of a TCP connection, shared by the connection and its streams.
+///
+/// Dropping it closes the connection.
+struct Endpoint {
+    net: Net,
+    inbound: Arc<Pipe>,
+    outbound: Arc<Pipe>,
+    /// The local address of the connecting side.
+    _reservation: Option<Reservation>,
+}
+
+impl Endpoint {
+    fn write(&self, data: Bytes) -> io::Result<()> {
+        let latency = lock(&self.net).latency;
+        self.outbound.write(data, latency)
+    }
+
+    fn shutdown(&self, how: Shutdown) {
+        if let Shutdown::Read | Shutdown::Both = how {
+            self.inbound.close_reader();
+        }
+        if let Shutdown::Write | Shutdown::Both = how {
+            self.outbound.close_writer();
+        }
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.shutdown(Shutdown::Both);
+    }
+}
+
+struct Connection {
+    endpoint: Arc<Endpoint>,
+    local: SocketAddr,
+    peer: SocketAddr,
+}
+
+impl Connection {
+    fn into_streams(self) -> TcpStreams {
+        let endpoint = self.endpoint.clone();
+        (
+            Box::new(self),
+            Box::new(ReadStream(endpoint.clone())),
+            Box::new(WriteStream(endpoint)),
+        )
+    }
+}
+
+impl TcpConnection for Connection {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.endpoint.shutdown(how);
+        Ok(())
+    }
+}
+
+struct ReadStream(Arc<Endpoint>);
+
+#[async_trait::async_trait]
+impl HostInputStream for ReadStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        self.0.inbound.read(size).ok_or(StreamError::Closed)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for ReadStream {
+    async fn ready(&mut self) {
+        self.0.inbound.readable().await;
+    }
+}
+
+struct WriteStream(Arc<Endpoint>);
+
+#[async_trait::async_trait]
+impl HostOutputStream for WriteStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.0
+            .write(bytes)
+            .map_err(|e| StreamError::LastOperationFailed(e.into()))
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        // Data is handed to the peer as soon as it's written.
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        self.0
+            .outbound
+            .capacity()
+            .map_err(|e| StreamError::LastOperationFailed(e.into()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for WriteStream {
+    async fn ready(&mut self) {
+        self.0.outbound.writable().await;
+    }
+}
+
+/// Connections which have been established but not accepted yet.
+struct Backlog {
+    queue: VecDeque<Connection>,
+    limit: u32,
+    waker: Option<Waker>,
+}
+
+struct Binding(Reservation);
+
+impl Binding {
+    fn listen(self, backlog: u32) -> Listener {
+        let backlog = Arc::new(Mutex::new(Backlog {
+            queue: VecDeque::new(),
+            limit: backlog,
+            waker: None,
+        }));
+        lock(&self.0.net)
+            .tcp
+            .insert(self.0.addr, Some(backlog.clone()));
+        Listener {
+            backlog,
+            reservation: self.0,
+        }
+    }
+
+    async fn connect(self, remote: SocketAddr) -> io::Result<Connection> {
+        let net = self.0.net.clone();
+        let latency = lock(&net).latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+
+        let backlog = lock(&net)
+            .listener(remote)
+            .ok_or(io::ErrorKind::ConnectionRefused)?;
+        let mut backlog = lock(&backlog);
+        if backlog.queue.len() >= backlog.limit as usize {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        }
+
+        let mut local = self.0.addr;
+        if local.ip().is_unspecified() {
+            local.set_ip(remote.ip());
+        }
+        let (to_server, to_client) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+        backlog.queue.push_back(Connection {
+            endpoint: Arc::new(Endpoint {
+                net: net.clone(),
+                inbound: to_server.clone(),
+                outbound: to_client.clone(),
+                _reservation: None,
+            }),
+            local: remote,
+            peer: local,
+        });
+        if let Some(waker) = backlog.waker.take() {
+            waker.wake();
+        }
+        drop(backlog);
+
+        Ok(Connection {
+            endpoint: Arc::new(Endpoint {
+                net,
+                inbound: to_client,
+                outbound: to_server,
+                _reservation: Some(self.0),
+            }),
+            local,
+            peer: remote,
+        })
+    }
+}
+
+impl TcpBinding for Binding {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.0.addr)
+    }
+
+    fn listen(self: Box<Self>, backlog: u32) -> io::Result<Box<dyn TcpListener>> {
+        Ok(Box::new(Binding::listen(*self, backlog)))
+    }
+
+    fn connect(
+        self: Box<Self>,
+        remote: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = io::Result<TcpStreams>> + Send>> {
+        Box::pin(async move { Ok(Binding::connect(*self, remote).await?.into_streams()) })
+    }
+}
+
+struct Listener {
+    backlog: Arc<Mutex<Backlog>>,
+    reservation: Reservation,
+}
+
+impl Listener {
+    fn local_addr(&self) -> SocketAddr {
+        self.reservation.addr
+    }
+
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<Connection> {
+        let mut backlog = lock(&self.backlog);
+        match backlog.queue.pop_front() {
+            Some(connection) => Poll::Ready(connection),
+            None => {
+                backlog.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl TcpListener for Listener {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(Listener::local_addr(self))
+    }
+
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TcpStreams>> {
+        Listener::poll_accept(self, cx).map(|connection| Ok(connection.into_streams()))
+    }
+
+    fn set_backlog(&mut self, backlog: u32) -> io::Result<()> {
+        lock(&self.backlog).limit = backlog;
+        Ok(())
+    }
+}
+
+/// A TCP socket listening on a [`LoopbackNetwork`], created with
+/// [`LoopbackNetwork::listen`].
+pub struct LoopbackTcpListener(Listener);
+
+impl LoopbackTcpListener {
+    /// Returns the address this socket is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.0.local_addr()
+    }
+
+    /// Waits for the next incoming connection.
+    pub async fn accept(&mut self) -> io::Result<LoopbackTcpStream> {
+        let connection = futures::future::poll_fn(|cx| self.0.poll_accept(cx)).await;
+        Ok(LoopbackTcpStream(connection))
+    }
+}
+
+/// A TCP connection on a [`LoopbackNetwork`], created with
+/// [`LoopbackNetwork::connect`] or [`LoopbackTcpListener::accept`].
+pub struct LoopbackTcpStream(Connection);
+
+impl LoopbackTcpStream {
+    /// Returns the local address of this connection.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.0.local
+    }
+
+    /// Returns the remote address of this connection.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.0.peer
+    }
+
+    /// Reads data into `buf`, returning how many bytes were read.
+    ///
+    /// Returns 0 once the peer has shut down its sending side and all data
+    /// has been read.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let inbound = &self.0.endpoint.inbound;
+        loop {
+            match inbound.read(buf.len()) {
+                None => return Ok(0),
+                Some(data) if data.is_empty() => inbound.readable().await,
+                Some(data) => {
+                    buf[..data.len()].copy_from_slice(&data);
+                    return Ok(data.len());
+                }
+            }
+        }
+    }
+
+    /// Reads until the peer has shut down its sending side.
+    pub async fn read_to_end(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            match self.read(&mut buf).await? {
+                0 => return Ok(data),
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    /// Writes all of `data`, waiting for the peer to read some if too much is
+    /// in flight.
+    pub async fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let endpoint = &self.0.endpoint;
+        while !data.is_empty() {
+            let n = endpoint.outbound.capacity()?.min(data.len());
+            if n == 0 {
+                endpoint.outbound.writable().await;
+                continue;
+            }
+            endpoint.write(Bytes::copy_from_slice(&data[..n]))?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Shuts down the receiving side, the sending side, or both sides of this
+    /// connection.
+    pub fn shutdown(&self, how: Shutdown) {
+        self.0.endpoint.shutdown(how);
+    }
+}
+
+/// Datagrams which have been sent to a UDP socket.
+#[derive(Default)]
+struct Datagrams {
+    queue: Mutex<VecDeque<(Instant, SocketAddr, Bytes)>>,
+    changed: Notify,
+}
+
+struct UdpSocket {
+    datagrams: Arc<Datagrams>,
+    reservation: Reservation,
+}
+
+impl UdpSocket {
+    fn new(net: &Net, addr: SocketAddr) -> io::Result<Self> {
+        let reservation = Reservation::new(net, Protocol::Udp, addr)?;
+        let datagrams = lock(net).udp[&reservation.addr].clone();
+        Ok(Self {
+            datagrams,
+            reservation,
+        })
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut queue = lock(&self.datagrams.queue);
+        match queue.front() {
+            Some((at, _, _)) if *at <= Instant::now() => {
+                let (_, from, data) = queue.pop_front().unwrap();
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok((n, from))
+            }
+            _ => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<()> {
+        let mut state = lock(&self.reservation.net);
+        if state.drop_datagram() {
+            return Ok(());
+        }
+        let latency = state.latency;
+        // Like any network, datagrams to addresses nobody listens on are lost.
+        let Some(target) = state.datagrams(addr) else {
+            return Ok(());
+        };
+        drop(state);
+
+        let mut from = self.reservation.addr;
+        if from.ip().is_unspecified() {
+            from.set_ip(addr.ip());
+        }
+        let mut queue = lock(&target.queue);
+        if queue.len() < UDP_QUEUE {
+            let mut at = Instant::now() + latency;
+            if let Some((last, _, _)) = queue.back() {
+                at = at.max(*last);
+            }
+            queue.push_back((at, from, Bytes::copy_from_slice(data)));
+        }
+        drop(queue);
+        target.changed.notify_waiters();
+        Ok(())
+    }
+
+    async fn readable(&self) {
+        loop {
+            let mut changed = pin!(self.datagrams.changed.notified());
+            changed.as_mut().enable();
+            let deadline = match lock(&self.datagrams.queue).front() {
+                Some((at, _, _)) if *at <= Instant::now() => return,
+                Some((at, _, _)) => Some(*at),
+                None => None,
+            };
+            match deadline {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => changed.await,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UdpEndpoint for UdpSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.reservation.addr)
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::try_recv_from(self, buf)
+    }
+
+    fn try_send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<()> {
+        self.send_to(data, addr)
+    }
+
+    async fn readable(&self) {
+        UdpSocket::readable(self).await
+    }
+}
+
+/// A UDP socket bound on a [`LoopbackNetwork`], created with
+/// [`LoopbackNetwork::bind_udp`].
+pub struct LoopbackUdpSocket(Arc<UdpSocket>);
+
+impl LoopbackUdpSocket {
+    /// Returns the address this socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.0.reservation.addr
+    }
+
+    /// Sends `data` to `addr`.
+    ///
+    /// Datagrams to addresses no socket is bound to are silently dropped.
+    pub fn send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<()> {
+        self.0.send_to(data, addr)
+    }
+
+    /// Waits for the next datagram and receives it into `buf`, returning its
+    /// size and the address it was sent from.
+    ///
+    /// Datagrams larger than `buf` are truncated.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            match self.0.try_recv_from(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.0.readable().await,
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn tcp_echo() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        let mut listener = net.listen(addr("0.0.0.0:80"))?;
+        let mut client = net.connect(addr("10.0.0.1:80")).await?;
+        let mut server = listener.accept().await?;
+
+        assert_eq!(client.peer_addr(), addr("10.0.0.1:80"));
+        assert_eq!(server.local_addr(), addr("10.0.0.1:80"));
+        assert_eq!(server.peer_addr(), client.local_addr());
+
+        client.write_all(b"ping").await?;
+        client.shutdown(Shutdown::Write);
+        assert_eq!(server.read_to_end().await?, b"ping");
+        server.write_all(b"pong").await?;
+        drop(server);
+        assert_eq!(client.read_to_end().await?, b"pong");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tcp_addresses() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        let err = net.connect(addr("127.0.0.1:80")).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        let listener = net.listen(addr("127.0.0.1:0"))?;
+        let port = listener.local_addr().port();
+        assert!(EPHEMERAL_PORTS.contains(&port));
+        let err = net.listen(addr(&format!("0.0.0.0:{port}"))).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        // Families don't share ports, and ports are released when dropped.
+        net.listen(addr(&format!("[::]:{port}")))?;
+        drop(listener);
+        net.listen(addr(&format!("0.0.0.0:{port}")))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tcp_window() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        let mut listener = net.listen(addr("127.0.0.1:80"))?;
+        let mut client = net.connect(addr("127.0.0.1:80")).await?;
+        let mut server = listener.accept().await?;
+
+        let data = vec![1; TCP_WINDOW * 3];
+        let reader = tokio::spawn(async move { server.read_to_end().await });
+        client.write_all(&data).await?;
+        drop(client);
+        assert_eq!(reader.await.unwrap()?, data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn latency() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        net.set_latency(Duration::from_millis(50));
+        let a = net.bind_udp(addr("127.0.0.1:1000"))?;
+        let b = net.bind_udp(addr("127.0.0.1:2000"))?;
+
+        let start = Instant::now();
+        a.send_to(b"hello", b.local_addr())?;
+        assert!(b.0.try_recv_from(&mut [0; 16]).is_err());
+        let mut buf = [0; 16];
+        assert_eq!(b.recv_from(&mut buf).await?, (5, a.local_addr()));
+        assert_eq!(&buf[..5], b"hello");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_drops() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        let a = net.bind_udp(addr("[::1]:0"))?;
+        let b = net.bind_udp(addr("[::]:0"))?;
+        let to = SocketAddr::new(addr("[::1]:0").ip(), b.local_addr().port());
+
+        net.set_drop_probability(0.5);
+        for _ in 0..100 {
+            a.send_to(b"x", to)?;
+        }
+        net.set_drop_probability(1.0);
+        a.send_to(b"x", to)?;
+
+        let mut received = 0;
+        while b.0.try_recv_from(&mut [0; 1]).is_ok() {
+            received += 1;
+        }
+        assert!(received > 20 && received < 80, "received {received}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn names() -> io::Result<()> {
+        let net = LoopbackNetwork::new();
+        net.add_host("Example.COM", [addr("10.0.0.1:0").ip()]);
+        assert_eq!(
+            net.resolve_addresses("example.com").await?,
+            [addr("10.0.0.1:0").ip()]
+        );
+        assert!(net.resolve_addresses("example.org").await.is_err());
+        Ok(())
+    }
+}
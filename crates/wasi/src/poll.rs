@@ -1,4 +1,4 @@
-use crate::{bindings::io::poll, WasiImpl, WasiView};
+use crate::{bindings::io::poll, trace::EventKind, WasiImpl, WasiView};
 use anyhow::{anyhow, Result};
 use std::any::Any;
 use std::collections::HashMap;
@@ -128,6 +128,17 @@ where
             return Err(anyhow!("empty poll list"));
         }
 
+        // When replaying, which pollables were ready is taken from the trace
+        // rather than waiting for them.
+        if self.ctx().trace.is_replaying() {
+            for p in pollables.iter() {
+                self.table().get(p)?;
+            }
+        }
+        if let Some(ready) = self.ctx().trace.replay_value(EventKind::Poll)? {
+            return Ok(ready);
+        }
+
         let table = self.table();
 
         let mut table_futures: HashMap<u32, (MakeFuture, Vec<ReadylistIndex>)> = HashMap::new();
@@ -174,7 +185,9 @@ where
             }
         }
 
-        Ok(PollList { futures }.await)
+        let ready = PollList { futures }.await;
+        self.ctx().trace.record_value(EventKind::Poll, &ready)?;
+        Ok(ready)
     }
 }
 
@@ -184,21 +197,33 @@ where
     T: WasiView,
 {
     async fn block(&mut self, pollable: Resource<Pollable>) -> Result<()> {
+        let replaying = self.ctx().trace.is_replaying();
         let table = self.table();
         let pollable = table.get(&pollable)?;
+        if replaying {
+            // Whatever was waited for is replayed from the trace.
+            return Ok(());
+        }
         let ready = (pollable.make_future)(table.get_any_mut(pollable.index)?);
         ready.await;
         Ok(())
     }
     async fn ready(&mut self, pollable: Resource<Pollable>) -> Result<bool> {
-        let table = self.table();
-        let pollable = table.get(&pollable)?;
-        let ready = (pollable.make_future)(table.get_any_mut(pollable.index)?);
-        futures::pin_mut!(ready);
-        Ok(matches!(
-            futures::future::poll_immediate(ready).await,
-            Some(())
-        ))
+        if let Some(ready) = self.ctx().trace.replay_value(EventKind::PollableReady)? {
+            self.table().get(&pollable)?;
+            return Ok(ready);
+        }
+        let ready = {
+            let table = self.table();
+            let pollable = table.get(&pollable)?;
+            let ready = (pollable.make_future)(table.get_any_mut(pollable.index)?);
+            futures::pin_mut!(ready);
+            matches!(futures::future::poll_immediate(ready).await, Some(()))
+        };
+        self.ctx()
+            .trace
+            .record_value(EventKind::PollableReady, &ready)?;
+        Ok(ready)
     }
     fn drop(&mut self, pollable: Resource<Pollable>) -> Result<()> {
         let pollable = self.table().delete(pollable)?;
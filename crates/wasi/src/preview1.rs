@@ -72,6 +72,8 @@ use crate::bindings::{
     filesystem::{preopens::Host as _, types as filesystem},
     io::streams,
 };
+use crate::filesystem::FileRef;
+use crate::trace::EventKind;
 use crate::{
     FsError, IsATTY, ResourceTable, StreamError, StreamResult, WasiCtx, WasiImpl, WasiView,
 };
@@ -606,6 +608,40 @@ impl WasiP1Ctx {
         Ok(fd)
     }
 
+    /// Records the `bytes_read` bytes which were read from a file into `iov`,
+    /// or when replaying replaces them with the recorded bytes, and returns
+    /// the number of bytes read.
+    fn trace_file_read(
+        &mut self,
+        memory: &mut GuestMemory<'_>,
+        iov: GuestPtr<[u8]>,
+        bytes_read: usize,
+    ) -> Result<usize, types::Error> {
+        if !self.ctx().trace.is_enabled() {
+            return Ok(bytes_read);
+        }
+        let read = iov.get_range(0..u32::try_from(bytes_read)?).unwrap();
+        let result = Ok::<_, ()>((memory.to_vec(read)?, bytes_read == 0));
+        // The read succeeded, so the substituted result did too.
+        let (data, _) = self
+            .ctx()
+            .trace
+            .substitute(EventKind::FileRead, result)
+            .map_err(types::Error::trap)?
+            .unwrap();
+        if self.ctx().trace.is_replaying() {
+            let read = iov
+                .get_range(0..u32::try_from(data.len())?)
+                .ok_or_else(|| {
+                    types::Error::trap(anyhow::anyhow!(
+                        "replay diverged: recorded file read is larger than the buffer"
+                    ))
+                })?;
+            memory.copy_from_slice(&data, read)?;
+        }
+        Ok(data.len())
+    }
+
     /// Shared implementation of `fd_write` and `fd_pwrite`.
     async fn fd_write_impl(
         &mut self,
@@ -634,8 +670,28 @@ impl WasiP1Ctx {
                 let pos = position.load(Ordering::Relaxed);
                 let append = *append;
                 drop(t);
-                let f = self.table().get(&fd)?.file()?;
                 let buf = first_non_empty_ciovec(memory, ciovs)?;
+                let f = match self.table().get(&fd)?.any_file()? {
+                    FileRef::Host(f) => f,
+                    FileRef::Virtual(f) => {
+                        let buf = memory.as_cow(buf)?;
+                        let nwritten = match (append, write) {
+                            (true, _) => f.append(&buf)?,
+                            (false, FdWrite::At(pos)) => f.write_at(&buf, pos)?,
+                            (false, FdWrite::AtCur) => f.write_at(&buf, pos)?,
+                        };
+                        if let FdWrite::AtCur = write {
+                            let pos = if append {
+                                f.file.stat()?.size
+                            } else {
+                                pos.checked_add(nwritten as u64)
+                                    .ok_or(types::Errno::Overflow)?
+                            };
+                            position.store(pos, Ordering::Relaxed);
+                        }
+                        return Ok(nwritten.try_into()?);
+                    }
+                };
 
                 let do_write = move |f: &cap_std::fs::File, buf: &[u8]| match (append, write) {
                     // Note that this is implementing Linux semantics of
@@ -1712,8 +1768,28 @@ impl wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiP1Ctx {
                 let position = position.clone();
                 drop(t);
                 let pos = position.load(Ordering::Relaxed);
-                let file = self.table().get(&fd)?.file()?;
                 let iov = first_non_empty_iovec(memory, iovs)?;
+                let file = match self.table().get(&fd)?.any_file()? {
+                    FileRef::Host(f) => f,
+                    FileRef::Virtual(f) => {
+                        let bytes_read = match memory.as_slice_mut(iov)? {
+                            Some(mut buf) => f.read_at(&mut buf, pos)?,
+                            None => {
+                                let mut buf = vec![0; iov.len() as usize];
+                                let bytes_read = f.read_at(&mut buf, pos)?;
+                                let iov = iov.get_range(0..u32::try_from(bytes_read)?).unwrap();
+                                memory.copy_from_slice(&buf[..bytes_read], iov)?;
+                                bytes_read
+                            }
+                        };
+                        let bytes_read = self.trace_file_read(memory, iov, bytes_read)?;
+                        let pos = pos
+                            .checked_add(bytes_read.try_into()?)
+                            .ok_or(types::Errno::Overflow)?;
+                        position.store(pos, Ordering::Relaxed);
+                        return Ok(bytes_read.try_into()?);
+                    }
+                };
                 let bytes_read = match (file.as_blocking_file(), memory.as_slice_mut(iov)?) {
                     // Try to read directly into wasm memory where possible
                     // when the current thread can block and additionally wasm
@@ -1741,6 +1817,7 @@ impl wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiP1Ctx {
                         buf.len()
                     }
                 };
+                let bytes_read = self.trace_file_read(memory, iov, bytes_read)?;
 
                 let pos = pos
                     .checked_add(bytes_read.try_into()?)
@@ -2286,17 +2363,18 @@ impl wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiP1Ctx {
                     .unwrap_or_else(types::Error::trap)
             })?;
         let mut t = self.transact()?;
-        let desc = match t.view.table().get(&fd)? {
-            crate::filesystem::Descriptor::Dir(_) => Descriptor::Directory {
+        let desc = if t.view.table().get(&fd)?.is_dir() {
+            Descriptor::Directory {
                 fd,
                 preopen_path: None,
-            },
-            crate::filesystem::Descriptor::File(_) => Descriptor::File(File {
+            }
+        } else {
+            Descriptor::File(File {
                 fd,
                 position: Default::default(),
                 append: fdflags.contains(types::Fdflags::APPEND),
                 blocking_mode: BlockingMode::from_fdflags(&fdflags),
-            }),
+            })
         };
         let fd = t.descriptors.push(desc)?;
         Ok(fd.into())
@@ -1,6 +1,8 @@
 use crate::bindings::sockets::tcp::ErrorCode;
 use crate::host::network;
-use crate::network::SocketAddressFamily;
+use crate::network::{
+    SocketAddressFamily, SocketBackend, TcpBinding, TcpConnection, TcpStreams, VirtualSocketOptions,
+};
 use crate::runtime::{with_ambient_tokio_runtime, AbortOnDropJoinHandle};
 use crate::{
     HostInputStream, HostOutputStream, InputStream, OutputStream, SocketResult, StreamError,
@@ -15,10 +17,10 @@ use rustix::io::Errno;
 use rustix::net::sockopt;
 use std::io;
 use std::mem;
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::Poll;
+use std::task::{Context, Poll};
 
 /// Value taken from rust std library.
 const DEFAULT_BACKLOG: u32 = 128;
@@ -29,32 +31,32 @@ const DEFAULT_BACKLOG: u32 = 128;
 /// activities of binding, listening, accepting, and connecting.
 enum TcpState {
     /// The initial state for a newly-created socket.
-    Default(tokio::net::TcpSocket),
+    Default(Socket),
 
     /// Binding started via `start_bind`.
-    BindStarted(tokio::net::TcpSocket),
+    BindStarted(Socket),
 
     /// Binding finished via `finish_bind`. The socket has an address but
     /// is not yet listening for connections.
-    Bound(tokio::net::TcpSocket),
+    Bound(Socket),
 
     /// Listening started via `listen_start`.
-    ListenStarted(tokio::net::TcpSocket),
+    ListenStarted(Socket),
 
     /// The socket is now listening and waiting for an incoming connection.
     Listening {
-        listener: tokio::net::TcpListener,
-        pending_accept: Option<io::Result<tokio::net::TcpStream>>,
+        listener: Listener,
+        pending_accept: Option<io::Result<Connection>>,
     },
 
     /// An outgoing connection is started via `start_connect`.
-    Connecting(Pin<Box<dyn Future<Output = io::Result<tokio::net::TcpStream>> + Send>>),
+    Connecting(Pin<Box<dyn Future<Output = io::Result<Connection>> + Send>>),
 
     /// An outgoing connection is ready to be established.
-    ConnectReady(io::Result<tokio::net::TcpStream>),
+    ConnectReady(io::Result<Connection>),
 
     /// An outgoing connection has been established.
-    Connected(Arc<tokio::net::TcpStream>),
+    Connected(Stream),
 
     Closed,
 }
@@ -78,6 +80,77 @@ impl std::fmt::Debug for TcpState {
     }
 }
 
+/// A socket which is neither listening nor connected.
+enum Socket {
+    /// A host socket, which may be bound.
+    Host(tokio::net::TcpSocket),
+
+    /// A socket on a [`SocketBackend`] which isn't bound yet.
+    Unbound(Arc<dyn SocketBackend>),
+
+    /// A socket bound on a [`SocketBackend`].
+    Bound(Box<dyn TcpBinding>),
+}
+
+/// A listening socket.
+enum Listener {
+    Host(tokio::net::TcpListener),
+    Virtual(Box<dyn crate::network::TcpListener>),
+}
+
+impl Listener {
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Connection>> {
+        match self {
+            Listener::Host(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| Connection::Host(stream)),
+            Listener::Virtual(listener) => listener.poll_accept(cx).map_ok(Connection::Virtual),
+        }
+    }
+}
+
+/// A connection which hasn't been handed to the guest yet.
+enum Connection {
+    Host(tokio::net::TcpStream),
+    Virtual(TcpStreams),
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host(stream) => f.debug_tuple("Host").field(stream).finish(),
+            Self::Virtual(_) => f.debug_tuple("Virtual").finish(),
+        }
+    }
+}
+
+impl Connection {
+    /// Splits this connection into the state of its socket and the streams
+    /// handed to the guest.
+    fn into_streams(self) -> (Stream, InputStream, OutputStream) {
+        match self {
+            Connection::Host(stream) => {
+                let stream = Arc::new(stream);
+                let input: InputStream =
+                    InputStream::Host(Box::new(TcpReadStream::new(stream.clone())));
+                let output: OutputStream = Box::new(TcpWriteStream::new(stream.clone()));
+                (Stream::Host(stream), input, output)
+            }
+            Connection::Virtual((connection, input, output)) => (
+                Stream::Virtual(connection),
+                InputStream::Host(input),
+                output,
+            ),
+        }
+    }
+}
+
+/// A connection which has been handed to the guest.
+enum Stream {
+    Host(Arc<tokio::net::TcpStream>),
+    Virtual(Box<dyn TcpConnection>),
+}
+
 /// A host TCP socket, plus associated bookkeeping.
 pub struct TcpSocket {
     /// The current state in the bind/listen/accept/connect progression.
@@ -88,6 +161,10 @@ pub struct TcpSocket {
 
     family: SocketAddressFamily,
 
+    /// The socket options of a socket on a [`SocketBackend`], or `None` for
+    /// host sockets.
+    virtual_options: Option<VirtualSocketOptions>,
+
     // The socket options below are not automatically inherited from the listener
     // on all platforms. So we keep track of which options have been explicitly
     // set and manually apply those values to newly accepted clients.
@@ -117,16 +194,29 @@ impl TcpSocket {
                 }
             };
 
-            Self::from_state(TcpState::Default(socket), family)
+            Self::from_state(TcpState::Default(Socket::Host(socket)), family)
         })
     }
 
+    /// Create a new socket in the given family on `backend`.
+    pub(crate) fn new_virtual(backend: Arc<dyn SocketBackend>, family: AddressFamily) -> Self {
+        let family = match family {
+            AddressFamily::Ipv4 => SocketAddressFamily::Ipv4,
+            AddressFamily::Ipv6 => SocketAddressFamily::Ipv6,
+        };
+        let mut socket = Self::from_state(TcpState::Default(Socket::Unbound(backend)), family)
+            .expect("creating a socket from a state is infallible");
+        socket.virtual_options = Some(VirtualSocketOptions::default());
+        socket
+    }
+
     /// Create a `TcpSocket` from an existing socket.
     fn from_state(state: TcpState, family: SocketAddressFamily) -> io::Result<Self> {
         Ok(Self {
             tcp_state: state,
             listen_backlog_size: DEFAULT_BACKLOG,
             family,
+            virtual_options: None,
             #[cfg(target_os = "macos")]
             receive_buffer_size: None,
             #[cfg(target_os = "macos")]
@@ -142,26 +232,57 @@ impl TcpSocket {
         use crate::bindings::sockets::network::ErrorCode;
 
         match &self.tcp_state {
-            TcpState::Default(socket) | TcpState::Bound(socket) => {
+            TcpState::Default(Socket::Host(socket)) | TcpState::Bound(Socket::Host(socket)) => {
                 Ok(socket.as_socketlike_view::<std::net::TcpStream>())
             }
-            TcpState::Connected(stream) => Ok(stream.as_socketlike_view::<std::net::TcpStream>()),
-            TcpState::Listening { listener, .. } => {
-                Ok(listener.as_socketlike_view::<std::net::TcpStream>())
+            TcpState::Connected(Stream::Host(stream)) => {
+                Ok(stream.as_socketlike_view::<std::net::TcpStream>())
             }
+            TcpState::Listening {
+                listener: Listener::Host(listener),
+                ..
+            } => Ok(listener.as_socketlike_view::<std::net::TcpStream>()),
 
-            TcpState::BindStarted(..)
+            TcpState::Default(..)
+            | TcpState::Bound(..)
+            | TcpState::Connected(..)
+            | TcpState::Listening { .. }
+            | TcpState::BindStarted(..)
             | TcpState::ListenStarted(..)
             | TcpState::Connecting(..)
             | TcpState::ConnectReady(..)
             | TcpState::Closed => Err(ErrorCode::InvalidState.into()),
         }
     }
+
+    /// Returns the socket options of a socket on a [`SocketBackend`], or
+    /// `None` for host sockets, which are accessed with `as_std_view`.
+    fn virtual_options(&self) -> SocketResult<Option<&VirtualSocketOptions>> {
+        self.check_options_state()?;
+        Ok(self.virtual_options.as_ref())
+    }
+
+    fn virtual_options_mut(&mut self) -> SocketResult<Option<&mut VirtualSocketOptions>> {
+        self.check_options_state()?;
+        Ok(self.virtual_options.as_mut())
+    }
+
+    /// Socket options can be accessed in the same states as `as_std_view`
+    /// succeeds in.
+    fn check_options_state(&self) -> SocketResult<()> {
+        match self.tcp_state {
+            TcpState::Default(..)
+            | TcpState::Bound(..)
+            | TcpState::Connected(..)
+            | TcpState::Listening { .. } => Ok(()),
+            _ => Err(ErrorCode::InvalidState.into()),
+        }
+    }
 }
 
 impl TcpSocket {
     pub fn start_bind(&mut self, local_address: SocketAddr) -> io::Result<()> {
-        let tokio_socket = match &self.tcp_state {
+        let socket = match &self.tcp_state {
             TcpState::Default(socket) => socket,
             TcpState::BindStarted(..) => return Err(Errno::ALREADY.into()),
             _ => return Err(Errno::ISCONN.into()),
@@ -170,6 +291,17 @@ impl TcpSocket {
         network::util::validate_unicast(&local_address)?;
         network::util::validate_address_family(&local_address, &self.family)?;
 
+        let tokio_socket = match socket {
+            Socket::Host(socket) => socket,
+            Socket::Unbound(backend) => {
+                let binding = backend.tcp_bind(local_address)?;
+                self.tcp_state = TcpState::BindStarted(Socket::Bound(binding));
+                return Ok(());
+            }
+            // Bound sockets are never in the default state.
+            Socket::Bound(_) => unreachable!(),
+        };
+
         {
             // Automatically bypass the TIME_WAIT state when the user is trying
             // to bind to a specific port:
@@ -242,15 +374,34 @@ impl TcpSocket {
         network::util::validate_remote_address(&remote_address)?;
         network::util::validate_address_family(&remote_address, &self.family)?;
 
-        let TcpState::Default(tokio_socket) =
-            std::mem::replace(&mut self.tcp_state, TcpState::Closed)
+        let TcpState::Default(socket) = std::mem::replace(&mut self.tcp_state, TcpState::Closed)
         else {
             unreachable!();
         };
 
-        let future = tokio_socket.connect(remote_address);
-
-        self.tcp_state = TcpState::Connecting(Box::pin(future));
+        self.tcp_state = match socket {
+            Socket::Host(tokio_socket) => {
+                let future = tokio_socket.connect(remote_address);
+                TcpState::Connecting(Box::pin(async move { Ok(Connection::Host(future.await?)) }))
+            }
+            Socket::Unbound(backend) => {
+                // Like the host, implicitly bind to an ephemeral port.
+                let local_address = match self.family {
+                    SocketAddressFamily::Ipv4 => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+                    SocketAddressFamily::Ipv6 => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+                };
+                match backend.tcp_bind(local_address) {
+                    Ok(binding) => {
+                        let future = binding.connect(remote_address);
+                        TcpState::Connecting(Box::pin(async move {
+                            Ok(Connection::Virtual(future.await?))
+                        }))
+                    }
+                    Err(err) => TcpState::ConnectReady(Err(err)),
+                }
+            }
+            Socket::Bound(_) => unreachable!(),
+        };
         Ok(())
     }
 
@@ -275,12 +426,9 @@ impl TcpSocket {
         };
 
         match result {
-            Ok(stream) => {
-                let stream = Arc::new(stream);
-                self.tcp_state = TcpState::Connected(stream.clone());
-                let input: InputStream =
-                    InputStream::Host(Box::new(TcpReadStream::new(stream.clone())));
-                let output: OutputStream = Box::new(TcpWriteStream::new(stream));
+            Ok(connection) => {
+                let (stream, input, output) = connection.into_streams();
+                self.tcp_state = TcpState::Connected(stream);
                 Ok((input, output))
             }
             Err(err) => {
@@ -308,15 +456,27 @@ impl TcpSocket {
     }
 
     pub fn finish_listen(&mut self) -> SocketResult<()> {
-        let tokio_socket = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
-            TcpState::ListenStarted(tokio_socket) => tokio_socket,
+        let socket = match std::mem::replace(&mut self.tcp_state, TcpState::Closed) {
+            TcpState::ListenStarted(socket) => socket,
             previous_state => {
                 self.tcp_state = previous_state;
                 return Err(ErrorCode::NotInProgress.into());
             }
         };
 
-        match with_ambient_tokio_runtime(|| tokio_socket.listen(self.listen_backlog_size)) {
+        let result = match socket {
+            Socket::Host(tokio_socket) => {
+                with_ambient_tokio_runtime(|| tokio_socket.listen(self.listen_backlog_size))
+                    .map(Listener::Host)
+            }
+            Socket::Bound(binding) => binding
+                .listen(self.listen_backlog_size)
+                .map(Listener::Virtual),
+            // Only bound sockets can start listening.
+            Socket::Unbound(_) => unreachable!(),
+        };
+
+        match result {
             Ok(listener) => {
                 self.tcp_state = TcpState::Listening {
                     listener,
@@ -359,9 +519,7 @@ impl TcpSocket {
             Some(result) => result,
             None => {
                 let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
-                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx))
-                    .map_ok(|(stream, _)| stream)
-                {
+                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx)) {
                     Poll::Ready(result) => result,
                     Poll::Pending => Err(Errno::WOULDBLOCK.into()),
                 }
@@ -403,7 +561,7 @@ impl TcpSocket {
         })?;
 
         #[cfg(target_os = "macos")]
-        {
+        if let Connection::Host(client) = &client {
             // Manually inherit socket options from listener. We only have to
             // do this on platforms that don't already do this automatically
             // and only if a specific value was explicitly set on the listener.
@@ -426,19 +584,25 @@ impl TcpSocket {
             }
         }
 
-        let client = Arc::new(client);
+        let (stream, input, output) = client.into_streams();
+        let mut tcp_socket = TcpSocket::from_state(TcpState::Connected(stream), self.family)?;
 
-        let input: InputStream = InputStream::Host(Box::new(TcpReadStream::new(client.clone())));
-        let output: OutputStream = Box::new(TcpWriteStream::new(client.clone()));
-        let tcp_socket = TcpSocket::from_state(TcpState::Connected(client), self.family)?;
+        // Sockets on a backend inherit the options of the listener.
+        tcp_socket.virtual_options = self.virtual_options.clone();
 
         Ok((tcp_socket, input, output))
     }
 
     pub fn local_address(&self) -> SocketResult<SocketAddr> {
-        let view = match self.tcp_state {
+        let view = match &self.tcp_state {
             TcpState::Default(..) => return Err(ErrorCode::InvalidState.into()),
             TcpState::BindStarted(..) => return Err(ErrorCode::ConcurrencyConflict.into()),
+            TcpState::Bound(Socket::Bound(binding)) => return Ok(binding.local_addr()?),
+            TcpState::Listening {
+                listener: Listener::Virtual(listener),
+                ..
+            } => return Ok(listener.local_addr()?),
+            TcpState::Connected(Stream::Virtual(connection)) => return Ok(connection.local_addr()?),
             _ => self.as_std_view()?,
         };
 
@@ -446,7 +610,8 @@ impl TcpSocket {
     }
 
     pub fn remote_address(&self) -> SocketResult<SocketAddr> {
-        let view = match self.tcp_state {
+        let view = match &self.tcp_state {
+            TcpState::Connected(Stream::Virtual(connection)) => return Ok(connection.peer_addr()?),
             TcpState::Connected(..) => self.as_std_view()?,
             TcpState::Connecting(..) | TcpState::ConnectReady(..) => {
                 return Err(ErrorCode::ConcurrencyConflict.into())
@@ -476,11 +641,22 @@ impl TcpSocket {
         // Silently clamp backlog size. This is OK for us to do, because operating systems do this too.
         let value = value.clamp(MIN_BACKLOG, MAX_BACKLOG);
 
-        match &self.tcp_state {
+        match &mut self.tcp_state {
             TcpState::Default(..) | TcpState::Bound(..) => {
                 // Socket not listening yet. Stash value for first invocation to `listen`.
             }
-            TcpState::Listening { listener, .. } => {
+            TcpState::Listening {
+                listener: Listener::Virtual(listener),
+                ..
+            } => {
+                listener
+                    .set_backlog(value)
+                    .map_err(|_| ErrorCode::NotSupported)?;
+            }
+            TcpState::Listening {
+                listener: Listener::Host(listener),
+                ..
+            } => {
                 // Try to update the backlog by calling `listen` again.
                 // Not all platforms support this. We'll only update our own value if the OS supports changing the backlog size after the fact.
 
@@ -495,21 +671,35 @@ impl TcpSocket {
     }
 
     pub fn keep_alive_enabled(&self) -> SocketResult<bool> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.keep_alive_enabled);
+        }
         let view = &*self.as_std_view()?;
         Ok(sockopt::get_socket_keepalive(view)?)
     }
 
-    pub fn set_keep_alive_enabled(&self, value: bool) -> SocketResult<()> {
+    pub fn set_keep_alive_enabled(&mut self, value: bool) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            options.keep_alive_enabled = value;
+            return Ok(());
+        }
         let view = &*self.as_std_view()?;
         Ok(sockopt::set_socket_keepalive(view, value)?)
     }
 
     pub fn keep_alive_idle_time(&self) -> SocketResult<std::time::Duration> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.keep_alive_idle_time);
+        }
         let view = &*self.as_std_view()?;
         Ok(sockopt::get_tcp_keepidle(view)?)
     }
 
     pub fn set_keep_alive_idle_time(&mut self, duration: std::time::Duration) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_keep_alive_idle_time(duration);
+        }
+
         {
             let view = &*self.as_std_view()?;
             network::util::set_tcp_keepidle(view, duration)?;
@@ -524,26 +714,41 @@ impl TcpSocket {
     }
 
     pub fn keep_alive_interval(&self) -> SocketResult<std::time::Duration> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.keep_alive_interval);
+        }
         let view = &*self.as_std_view()?;
         Ok(sockopt::get_tcp_keepintvl(view)?)
     }
 
-    pub fn set_keep_alive_interval(&self, duration: std::time::Duration) -> SocketResult<()> {
+    pub fn set_keep_alive_interval(&mut self, duration: std::time::Duration) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_keep_alive_interval(duration);
+        }
         let view = &*self.as_std_view()?;
         Ok(network::util::set_tcp_keepintvl(view, duration)?)
     }
 
     pub fn keep_alive_count(&self) -> SocketResult<u32> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.keep_alive_count);
+        }
         let view = &*self.as_std_view()?;
         Ok(sockopt::get_tcp_keepcnt(view)?)
     }
 
-    pub fn set_keep_alive_count(&self, value: u32) -> SocketResult<()> {
+    pub fn set_keep_alive_count(&mut self, value: u32) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_keep_alive_count(value);
+        }
         let view = &*self.as_std_view()?;
         Ok(network::util::set_tcp_keepcnt(view, value)?)
     }
 
     pub fn hop_limit(&self) -> SocketResult<u8> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.hop_limit);
+        }
         let view = &*self.as_std_view()?;
 
         let ttl = match self.family {
@@ -555,6 +760,10 @@ impl TcpSocket {
     }
 
     pub fn set_hop_limit(&mut self, value: u8) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_hop_limit(value);
+        }
+
         {
             let view = &*self.as_std_view()?;
 
@@ -573,12 +782,19 @@ impl TcpSocket {
     }
 
     pub fn receive_buffer_size(&self) -> SocketResult<usize> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.receive_buffer_size);
+        }
         let view = &*self.as_std_view()?;
 
         Ok(network::util::get_socket_recv_buffer_size(view)?)
     }
 
     pub fn set_receive_buffer_size(&mut self, value: usize) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_receive_buffer_size(value);
+        }
+
         {
             let view = &*self.as_std_view()?;
 
@@ -594,12 +810,19 @@ impl TcpSocket {
     }
 
     pub fn send_buffer_size(&self) -> SocketResult<usize> {
+        if let Some(options) = self.virtual_options()? {
+            return Ok(options.send_buffer_size);
+        }
         let view = &*self.as_std_view()?;
 
         Ok(network::util::get_socket_send_buffer_size(view)?)
     }
 
     pub fn set_send_buffer_size(&mut self, value: usize) -> SocketResult<()> {
+        if let Some(options) = self.virtual_options_mut()? {
+            return options.set_send_buffer_size(value);
+        }
+
         {
             let view = &*self.as_std_view()?;
 
@@ -616,7 +839,8 @@ impl TcpSocket {
 
     pub fn shutdown(&self, how: std::net::Shutdown) -> io::Result<()> {
         let stream = match &self.tcp_state {
-            TcpState::Connected(stream) => stream,
+            TcpState::Connected(Stream::Host(stream)) => stream,
+            TcpState::Connected(Stream::Virtual(connection)) => return connection.shutdown(how),
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::NotConnected,
@@ -654,10 +878,7 @@ impl Subscribe for TcpSocket {
             } => match pending_accept {
                 Some(_) => {}
                 None => {
-                    let result = futures::future::poll_fn(|cx| {
-                        listener.poll_accept(cx).map_ok(|(stream, _)| stream)
-                    })
-                    .await;
+                    let result = futures::future::poll_fn(|cx| listener.poll_accept(cx)).await;
                     *pending_accept = Some(result);
                 }
             },
@@ -0,0 +1,589 @@
+//! Recording and replaying the nondeterministic results of WASI calls.
+//!
+//! A [`WasiCtx`](crate::WasiCtx) configured with
+//! [`WasiCtxBuilder::record`](crate::WasiCtxBuilder::record) appends an event
+//! to a trace for each host call whose result isn't determined by the guest:
+//! reading clocks, generating random numbers, reading from input streams,
+//! polling, and reading file data and metadata. A context configured with
+//! [`WasiCtxBuilder::replay`](crate::WasiCtxBuilder::replay) returns the
+//! results from such a trace instead, so that running the same guest with the
+//! same arguments and environment reproduces the recorded execution.
+//!
+//! Clocks, random numbers, stream reads and polls are taken entirely from the
+//! trace when replaying; the host isn't consulted at all. Filesystem
+//! operations are still performed when replaying, so the preopened
+//! directories must have the same layout as when recording, but the data and
+//! metadata they return are replaced by those recorded. Other operations,
+//! such as connecting sockets, aren't recorded and are performed as usual. If
+//! an operation
+//! succeeds when replaying but failed when recording, or the other way
+//! around, or if the guest makes different calls than were recorded, the
+//! replay has diverged and the guest traps.
+//!
+//! A trace starts with a header followed by a sequence of events. Each event
+//! is a one-byte [`EventKind`], a little-endian `u32` length, and that many
+//! bytes of payload encoded by [`TraceValue`].
+
+use crate::bindings::clocks::wall_clock::Datetime;
+use crate::bindings::filesystem::types::{
+    DescriptorStat, DescriptorType, ErrorCode, MetadataHashValue,
+};
+use crate::StreamError;
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufReader, Read, Write};
+
+const MAGIC: &[u8; 8] = b"\0wasitrc";
+const VERSION: u32 = 1;
+
+/// The kind of host call that an event in a trace records the result of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    WallClockNow,
+    WallClockResolution,
+    MonotonicClockNow,
+    MonotonicClockResolution,
+    RandomBytes,
+    RandomU64,
+    InsecureRandomBytes,
+    InsecureRandomU64,
+    InsecureSeed,
+    StreamRead,
+    StreamSkip,
+    Poll,
+    PollableReady,
+    FileRead,
+    Stat,
+    MetadataHash,
+}
+
+impl EventKind {
+    const ALL: [EventKind; 16] = [
+        EventKind::WallClockNow,
+        EventKind::WallClockResolution,
+        EventKind::MonotonicClockNow,
+        EventKind::MonotonicClockResolution,
+        EventKind::RandomBytes,
+        EventKind::RandomU64,
+        EventKind::InsecureRandomBytes,
+        EventKind::InsecureRandomU64,
+        EventKind::InsecureSeed,
+        EventKind::StreamRead,
+        EventKind::StreamSkip,
+        EventKind::Poll,
+        EventKind::PollableReady,
+        EventKind::FileRead,
+        EventKind::Stat,
+        EventKind::MetadataHash,
+    ];
+
+    fn to_byte(self) -> u8 {
+        // Start at one so that a zeroed trace doesn't parse.
+        Self::ALL.iter().position(|k| *k == self).unwrap() as u8 + 1
+    }
+
+    fn from_byte(byte: u8) -> Result<EventKind> {
+        byte.checked_sub(1)
+            .and_then(|i| Self::ALL.get(usize::from(i)))
+            .copied()
+            .ok_or_else(|| anyhow!("invalid trace event kind {byte}"))
+    }
+}
+
+/// Whether, and how, the nondeterministic results of WASI calls are traced.
+#[derive(Default)]
+pub(crate) enum Trace {
+    #[default]
+    Off,
+    Record(Box<dyn Write + Send>),
+    Replay(BufReader<Box<dyn Read + Send>>),
+}
+
+impl Trace {
+    /// Start recording to `writer`, writing the trace header.
+    pub(crate) fn record(mut writer: Box<dyn Write + Send>) -> Result<Trace> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.flush()?;
+        Ok(Trace::Record(writer))
+    }
+
+    /// Start replaying from `reader`, checking the trace header.
+    pub(crate) fn replay(reader: Box<dyn Read + Send>) -> Result<Trace> {
+        let mut reader = BufReader::new(reader);
+        let mut header = [0; 12];
+        reader
+            .read_exact(&mut header)
+            .context("failed to read trace header")?;
+        if &header[..8] != MAGIC {
+            bail!("not a WASI trace");
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != VERSION {
+            bail!("unsupported WASI trace version {version}, expected {VERSION}");
+        }
+        Ok(Trace::Replay(reader))
+    }
+
+    /// Whether results are being recorded or replayed.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !matches!(self, Trace::Off)
+    }
+
+    /// Whether results are being taken from a trace instead of the host.
+    pub(crate) fn is_replaying(&self) -> bool {
+        matches!(self, Trace::Replay(_))
+    }
+
+    /// Record `value` as the result of a `kind` call, if recording.
+    pub(crate) fn record_value<V: TraceValue>(&mut self, kind: EventKind, value: &V) -> Result<()> {
+        let mut payload = Vec::new();
+        value.encode(&mut payload);
+        self.write_event(kind, payload)
+    }
+
+    fn write_event(&mut self, kind: EventKind, payload: Vec<u8>) -> Result<()> {
+        let writer = match self {
+            Trace::Record(writer) => writer,
+            Trace::Off | Trace::Replay(_) => return Ok(()),
+        };
+        let len = u32::try_from(payload.len()).context("trace event too large")?;
+        let mut event = Vec::with_capacity(payload.len() + 5);
+        event.push(kind.to_byte());
+        event.extend_from_slice(&len.to_le_bytes());
+        event.extend_from_slice(&payload);
+        // Flush every event so that the trace is complete even if the process
+        // exits without dropping the context, for example via `proc_exit`.
+        writer
+            .write_all(&event)
+            .and_then(|()| writer.flush())
+            .context("failed to write trace event")
+    }
+
+    /// Take the recorded result of the next call, which must be a `kind`
+    /// call, if replaying.
+    pub(crate) fn replay_value<V: TraceValue>(&mut self, kind: EventKind) -> Result<Option<V>> {
+        let reader = match self {
+            Trace::Replay(reader) => reader,
+            Trace::Off | Trace::Record(_) => return Ok(None),
+        };
+        let mut header = [0; 5];
+        reader
+            .read_exact(&mut header)
+            .with_context(|| format!("replay diverged: trace ended before {kind:?} event"))?;
+        let found = EventKind::from_byte(header[0])?;
+        if found != kind {
+            bail!("replay diverged: expected {kind:?} event, found {found:?} event in trace");
+        }
+        let len = u32::from_le_bytes(header[1..].try_into().unwrap());
+        // Don't trust the length to allocate the payload up front, a corrupt
+        // trace could claim a payload of up to 4GiB.
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(u64::from(len))
+            .read_to_end(&mut payload)
+            .context("failed to read trace event")?;
+        if payload.len() != len as usize {
+            bail!("replay diverged: trace ended in {kind:?} event");
+        }
+        let mut input = &payload[..];
+        let value = V::decode(&mut input)
+            .with_context(|| format!("failed to decode {kind:?} trace event"))?;
+        if !input.is_empty() {
+            bail!("trailing data in {kind:?} trace event");
+        }
+        Ok(Some(value))
+    }
+
+    /// Returns the recorded result of a `kind` call when replaying, and
+    /// otherwise calls `f` and records its result.
+    pub(crate) fn value<V: TraceValue>(
+        &mut self,
+        kind: EventKind,
+        f: impl FnOnce() -> V,
+    ) -> Result<V> {
+        if let Some(value) = self.replay_value(kind)? {
+            return Ok(value);
+        }
+        let value = f();
+        self.record_value(kind, &value)?;
+        Ok(value)
+    }
+
+    /// Records whether an operation performed on the host succeeded, and its
+    /// result if so, or when replaying substitutes the recorded result.
+    ///
+    /// This is used for operations which are performed when replaying too,
+    /// and only diverges if the operation succeeds in one case but not the
+    /// other.
+    pub(crate) fn substitute<V: TraceValue, E>(
+        &mut self,
+        kind: EventKind,
+        result: Result<V, E>,
+    ) -> Result<Result<V, E>> {
+        match self.replay_value::<Option<V>>(kind)? {
+            Some(recorded) => match (result, recorded) {
+                (Ok(_), Some(value)) => Ok(Ok(value)),
+                (Err(e), None) => Ok(Err(e)),
+                (Ok(_), None) => bail!("replay diverged: {kind:?} succeeded but failed in trace"),
+                (Err(_), Some(_)) => {
+                    bail!("replay diverged: {kind:?} failed but succeeded in trace")
+                }
+            },
+            None => {
+                // Encoded the same as an `Option<V>`, without cloning the
+                // value.
+                let mut payload = Vec::new();
+                match &result {
+                    Ok(value) => {
+                        1u8.encode(&mut payload);
+                        value.encode(&mut payload);
+                    }
+                    Err(_) => 0u8.encode(&mut payload),
+                }
+                self.write_event(kind, payload)?;
+                Ok(result)
+            }
+        }
+    }
+}
+
+/// A value which is encoded in the payload of trace events.
+pub(crate) trait TraceValue: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Result<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        bail!("unexpected end of trace event");
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! int_trace_values {
+    ($($ty:ty)*) => {$(
+        impl TraceValue for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self> {
+                let bytes = take(input, std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+int_trace_values!(u8 u32 u64);
+
+impl TraceValue for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u8::from(*self).encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            n => bail!("invalid boolean {n}"),
+        }
+    }
+}
+
+impl TraceValue for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        out.extend_from_slice(self);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let len = usize::try_from(u64::decode(input)?)?;
+        Ok(take(input, len)?.to_vec())
+    }
+}
+
+impl TraceValue for Vec<u32> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        for n in self {
+            n.encode(out);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let len = u64::decode(input)?;
+        (0..len).map(|_| u32::decode(input)).collect()
+    }
+}
+
+impl<A: TraceValue, B: TraceValue> TraceValue for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl<T: TraceValue> TraceValue for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => 0u8.encode(out),
+            Some(value) => {
+                1u8.encode(out);
+                value.encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            n => bail!("invalid option tag {n}"),
+        }
+    }
+}
+
+/// Stream results are recorded along with the error code and message of any
+/// error, which are replayed as an error with that message that has that
+/// code.
+impl<T: TraceValue> TraceValue for Result<T, StreamError> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                0u8.encode(out);
+                value.encode(out);
+            }
+            Err(StreamError::Closed) => 1u8.encode(out),
+            Err(StreamError::LastOperationFailed(e)) => {
+                2u8.encode(out);
+                encode_error(e, out);
+            }
+            Err(StreamError::Trap(e)) => {
+                3u8.encode(out);
+                encode_error(e, out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Err(match u8::decode(input)? {
+            0 => return Ok(Ok(T::decode(input)?)),
+            1 => StreamError::Closed,
+            2 => StreamError::LastOperationFailed(decode_error(input)?),
+            3 => StreamError::Trap(decode_error(input)?),
+            n => bail!("invalid stream result tag {n}"),
+        }))
+    }
+}
+
+/// Encodes the error code that `filesystem-error-code` returns for `error`, if
+/// any, and the message of `error`.
+fn encode_error(error: &anyhow::Error, out: &mut Vec<u8>) {
+    let code = match error.downcast_ref::<std::io::Error>() {
+        Some(e) => Some(ErrorCode::from(e)),
+        None => error.downcast_ref::<ErrorCode>().copied(),
+    };
+    code.encode(out);
+    error.to_string().into_bytes().encode(out);
+}
+
+fn decode_error(input: &mut &[u8]) -> Result<anyhow::Error> {
+    let code = Option::<ErrorCode>::decode(input)?;
+    let message = String::from_utf8(Vec::decode(input)?)?;
+    Ok(match code {
+        Some(code) => anyhow::Error::from(code).context(message),
+        None => anyhow!(message),
+    })
+}
+
+impl TraceValue for ErrorCode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag = ERROR_CODES.iter().position(|c| c == self).unwrap() as u8;
+        tag.encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let tag = u8::decode(input)?;
+        ERROR_CODES
+            .get(usize::from(tag))
+            .copied()
+            .ok_or_else(|| anyhow!("invalid error code {tag}"))
+    }
+}
+
+/// The filesystem error codes, in the order of their tags in a trace.
+const ERROR_CODES: [ErrorCode; 37] = [
+    ErrorCode::Access,
+    ErrorCode::WouldBlock,
+    ErrorCode::Already,
+    ErrorCode::BadDescriptor,
+    ErrorCode::Busy,
+    ErrorCode::Deadlock,
+    ErrorCode::Quota,
+    ErrorCode::Exist,
+    ErrorCode::FileTooLarge,
+    ErrorCode::IllegalByteSequence,
+    ErrorCode::InProgress,
+    ErrorCode::Interrupted,
+    ErrorCode::Invalid,
+    ErrorCode::Io,
+    ErrorCode::IsDirectory,
+    ErrorCode::Loop,
+    ErrorCode::TooManyLinks,
+    ErrorCode::MessageSize,
+    ErrorCode::NameTooLong,
+    ErrorCode::NoDevice,
+    ErrorCode::NoEntry,
+    ErrorCode::NoLock,
+    ErrorCode::InsufficientMemory,
+    ErrorCode::InsufficientSpace,
+    ErrorCode::NotDirectory,
+    ErrorCode::NotEmpty,
+    ErrorCode::NotRecoverable,
+    ErrorCode::Unsupported,
+    ErrorCode::NoTty,
+    ErrorCode::NoSuchDevice,
+    ErrorCode::Overflow,
+    ErrorCode::NotPermitted,
+    ErrorCode::Pipe,
+    ErrorCode::ReadOnly,
+    ErrorCode::InvalidSeek,
+    ErrorCode::TextFileBusy,
+    ErrorCode::CrossDevice,
+];
+
+impl TraceValue for Datetime {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.seconds.encode(out);
+        self.nanoseconds.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Datetime {
+            seconds: u64::decode(input)?,
+            nanoseconds: u32::decode(input)?,
+        })
+    }
+}
+
+impl TraceValue for DescriptorType {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            DescriptorType::Unknown => 0,
+            DescriptorType::BlockDevice => 1,
+            DescriptorType::CharacterDevice => 2,
+            DescriptorType::Directory => 3,
+            DescriptorType::Fifo => 4,
+            DescriptorType::SymbolicLink => 5,
+            DescriptorType::RegularFile => 6,
+            DescriptorType::Socket => 7,
+        };
+        tag.encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(match u8::decode(input)? {
+            0 => DescriptorType::Unknown,
+            1 => DescriptorType::BlockDevice,
+            2 => DescriptorType::CharacterDevice,
+            3 => DescriptorType::Directory,
+            4 => DescriptorType::Fifo,
+            5 => DescriptorType::SymbolicLink,
+            6 => DescriptorType::RegularFile,
+            7 => DescriptorType::Socket,
+            n => bail!("invalid descriptor type {n}"),
+        })
+    }
+}
+
+impl TraceValue for DescriptorStat {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.type_.encode(out);
+        self.link_count.encode(out);
+        self.size.encode(out);
+        self.data_access_timestamp.encode(out);
+        self.data_modification_timestamp.encode(out);
+        self.status_change_timestamp.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(DescriptorStat {
+            type_: TraceValue::decode(input)?,
+            link_count: TraceValue::decode(input)?,
+            size: TraceValue::decode(input)?,
+            data_access_timestamp: TraceValue::decode(input)?,
+            data_modification_timestamp: TraceValue::decode(input)?,
+            status_change_timestamp: TraceValue::decode(input)?,
+        })
+    }
+}
+
+impl TraceValue for MetadataHashValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.lower.encode(out);
+        self.upper.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(MetadataHashValue {
+            lower: u64::decode(input)?,
+            upper: u64::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(events: &[u8]) -> Trace {
+        let mut trace = MAGIC.to_vec();
+        trace.extend_from_slice(&VERSION.to_le_bytes());
+        trace.extend_from_slice(events);
+        Trace::replay(Box::new(std::io::Cursor::new(trace))).unwrap()
+    }
+
+    #[test]
+    fn truncated_event_is_rejected() {
+        // An event claiming a huge payload fails once the trace ends, rather
+        // than allocating the payload up front.
+        let mut event = vec![EventKind::RandomU64.to_byte()];
+        event.extend_from_slice(&u32::MAX.to_le_bytes());
+        event.extend_from_slice(&[0; 4]);
+        let err = replay(&event)
+            .replay_value::<u64>(EventKind::RandomU64)
+            .unwrap_err();
+        assert!(err.to_string().contains("trace ended"), "{err:?}");
+    }
+
+    #[test]
+    fn stream_errors_round_trip() {
+        let results: [Result<Vec<u8>, StreamError>; 4] = [
+            Ok(b"data".to_vec()),
+            Err(StreamError::Closed),
+            Err(StreamError::LastOperationFailed(
+                std::io::Error::from(std::io::ErrorKind::NotFound).into(),
+            )),
+            Err(StreamError::Trap(anyhow!("bad stream"))),
+        ];
+        for result in results {
+            let mut payload = Vec::new();
+            result.encode(&mut payload);
+            let mut input = &payload[..];
+            let decoded = Result::<Vec<u8>, StreamError>::decode(&mut input).unwrap();
+            assert!(input.is_empty());
+            match (result, decoded) {
+                (Ok(a), Ok(b)) => assert_eq!(a, b),
+                (Err(StreamError::Closed), Err(StreamError::Closed)) => {}
+                (
+                    Err(StreamError::LastOperationFailed(a)),
+                    Err(StreamError::LastOperationFailed(b)),
+                ) => {
+                    assert_eq!(a.to_string(), b.to_string());
+                    assert_eq!(b.downcast_ref::<ErrorCode>(), Some(&ErrorCode::NoEntry));
+                }
+                (Err(StreamError::Trap(a)), Err(StreamError::Trap(b))) => {
+                    assert_eq!(a.to_string(), b.to_string());
+                    assert!(b.downcast_ref::<ErrorCode>().is_none());
+                }
+                (a, b) => panic!("{a:?} decoded as {b:?}"),
+            }
+        }
+    }
+}
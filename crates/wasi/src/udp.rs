@@ -7,8 +7,11 @@ use io_lifetimes::raw::{FromRawSocketlike, IntoRawSocketlike};
 use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::io::Interest;
 
-use super::network::{SocketAddrCheck, SocketAddressFamily};
+use super::network::{
+    SocketAddrCheck, SocketAddressFamily, SocketBackend, UdpEndpoint, VirtualSocketOptions,
+};
 
 /// The state of a UDP socket.
 ///
@@ -26,7 +29,7 @@ pub(crate) enum UdpState {
     Bound,
 
     /// The socket is "connected" to a peer address.
-    Connected,
+    Connected(SocketAddr),
 }
 
 /// A host UDP socket, plus associated bookkeeping.
@@ -36,7 +39,7 @@ pub(crate) enum UdpState {
 pub struct UdpSocket {
     /// The part of a `UdpSocket` which is reference-counted so that we
     /// can pass it to async tasks.
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: UdpHandle,
 
     /// The current state in the bind/connect progression.
     pub(crate) udp_state: UdpState,
@@ -46,6 +49,99 @@ pub struct UdpSocket {
 
     /// The check of allowed addresses
     pub(crate) socket_addr_check: Option<SocketAddrCheck>,
+
+    /// The socket options of a socket on a [`SocketBackend`], or `None` for
+    /// host sockets.
+    pub(crate) virtual_options: Option<VirtualSocketOptions>,
+}
+
+/// Where the socket options of a [`UdpSocket`] are kept.
+pub(crate) enum UdpOptions<'a> {
+    Host(&'a tokio::net::UdpSocket),
+    Virtual(&'a mut VirtualSocketOptions),
+}
+
+/// The socket a [`UdpSocket`] and its datagram streams send and receive
+/// datagrams on.
+#[derive(Clone)]
+pub(crate) enum UdpHandle {
+    Host(Arc<tokio::net::UdpSocket>),
+
+    /// A socket on a [`SocketBackend`] which isn't bound yet.
+    Unbound(Arc<dyn SocketBackend>),
+
+    /// A socket bound on a [`SocketBackend`].
+    Virtual(Arc<dyn UdpEndpoint>),
+}
+
+impl UdpHandle {
+    fn not_bound() -> io::Error {
+        io::Error::new(io::ErrorKind::NotConnected, "socket is not bound")
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        use io_lifetimes::AsSocketlike;
+
+        match self {
+            UdpHandle::Host(socket) => socket
+                .as_socketlike_view::<std::net::UdpSocket>()
+                .local_addr(),
+            UdpHandle::Virtual(endpoint) => endpoint.local_addr(),
+            UdpHandle::Unbound(_) => Err(Self::not_bound()),
+        }
+    }
+
+    pub(crate) fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match self {
+            UdpHandle::Host(socket) => socket.try_recv_from(buf),
+            UdpHandle::Virtual(endpoint) => endpoint.try_recv_from(buf),
+            UdpHandle::Unbound(_) => Err(Self::not_bound()),
+        }
+    }
+
+    /// Sends `data` to `addr`, which is the address the socket is connected
+    /// to if `connected` is set.
+    pub(crate) fn try_send_to(
+        &self,
+        data: &[u8],
+        addr: SocketAddr,
+        connected: bool,
+    ) -> io::Result<()> {
+        match self {
+            UdpHandle::Host(socket) if connected => socket.try_send(data).map(drop),
+            UdpHandle::Host(socket) => socket.try_send_to(data, addr).map(drop),
+            UdpHandle::Virtual(endpoint) => endpoint.try_send_to(data, addr),
+            UdpHandle::Unbound(_) => Err(Self::not_bound()),
+        }
+    }
+
+    pub(crate) async fn readable(&self) {
+        match self {
+            // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
+            UdpHandle::Host(socket) => {
+                socket
+                    .ready(Interest::READABLE)
+                    .await
+                    .expect("failed to await UDP socket readiness");
+            }
+            UdpHandle::Virtual(endpoint) => endpoint.readable().await,
+            UdpHandle::Unbound(_) => futures::future::pending().await,
+        }
+    }
+
+    pub(crate) async fn writable(&self) {
+        match self {
+            // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
+            UdpHandle::Host(socket) => {
+                socket
+                    .ready(Interest::WRITABLE)
+                    .await
+                    .expect("failed to await UDP socket readiness");
+            }
+            UdpHandle::Virtual(endpoint) => endpoint.writable().await,
+            UdpHandle::Unbound(_) => {}
+        }
+    }
 }
 
 #[async_trait]
@@ -73,33 +169,61 @@ impl UdpSocket {
         let socket = Self::setup_tokio_udp_socket(fd)?;
 
         Ok(UdpSocket {
-            inner: Arc::new(socket),
+            inner: UdpHandle::Host(Arc::new(socket)),
             udp_state: UdpState::Default,
             family: socket_address_family,
             socket_addr_check: None,
+            virtual_options: None,
         })
     }
 
+    /// Create a new socket in the given family on `backend`.
+    pub(crate) fn new_virtual(backend: Arc<dyn SocketBackend>, family: AddressFamily) -> Self {
+        UdpSocket {
+            inner: UdpHandle::Unbound(backend),
+            udp_state: UdpState::Default,
+            family: match family {
+                AddressFamily::Ipv4 => SocketAddressFamily::Ipv4,
+                AddressFamily::Ipv6 => SocketAddressFamily::Ipv6,
+            },
+            socket_addr_check: None,
+            virtual_options: Some(VirtualSocketOptions::default()),
+        }
+    }
+
     fn setup_tokio_udp_socket(fd: rustix::fd::OwnedFd) -> io::Result<tokio::net::UdpSocket> {
         let std_socket =
             unsafe { std::net::UdpSocket::from_raw_socketlike(fd.into_raw_socketlike()) };
         with_ambient_tokio_runtime(|| tokio::net::UdpSocket::try_from(std_socket))
     }
 
-    pub fn udp_socket(&self) -> &tokio::net::UdpSocket {
-        &self.inner
+    pub(crate) fn options(&mut self) -> UdpOptions<'_> {
+        match (&mut self.virtual_options, &self.inner) {
+            (Some(options), _) => UdpOptions::Virtual(options),
+            (None, UdpHandle::Host(socket)) => UdpOptions::Host(socket),
+            (None, _) => unreachable!("sockets on a backend have virtual options"),
+        }
+    }
+
+    /// Returns the host socket, or `None` if this socket was created on a
+    /// [`SocketBackend`].
+    pub fn udp_socket(&self) -> Option<&tokio::net::UdpSocket> {
+        match &self.inner {
+            UdpHandle::Host(socket) => Some(socket),
+            UdpHandle::Unbound(_) | UdpHandle::Virtual(_) => None,
+        }
     }
 }
 
 pub struct IncomingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: UdpHandle,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
 }
 
 pub struct OutgoingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: UdpHandle,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
@@ -130,6 +130,7 @@ pub struct Config {
     pub(crate) force_memory_init_memfd: bool,
     pub(crate) wmemcheck: bool,
     pub(crate) coredump_on_trap: bool,
+    pub(crate) collector: Collector,
     pub(crate) macos_use_mach_ports: bool,
     pub(crate) detect_host_feature: Option<fn(&str) -> Option<bool>>,
 }
@@ -248,6 +249,7 @@ impl Config {
             force_memory_init_memfd: false,
             wmemcheck: false,
             coredump_on_trap: false,
+            collector: Collector::Auto,
             macos_use_mach_ports: !cfg!(miri),
             #[cfg(feature = "std")]
             detect_host_feature: Some(detect_host_feature),
@@ -836,6 +838,21 @@ impl Config {
         self
     }
 
+    /// Configures which garbage collector will be used for GC-managed
+    /// references, such as `externref` and `structref`.
+    ///
+    /// Different collectors make different trade-offs and require different
+    /// GC barriers in compiled code, so modules must be compiled for the same
+    /// collector that they are run with. For more documentation consult the
+    /// [`Collector`] enumeration and its documentation.
+    ///
+    /// The default value for this is `Collector::Auto`.
+    #[cfg(feature = "gc")]
+    pub fn collector(&mut self, collector: Collector) -> &mut Self {
+        self.collector = collector;
+        self
+    }
+
     /// Configures whether the WebAssembly SIMD proposal will be
     /// enabled for compilation.
     ///
@@ -1673,6 +1690,13 @@ impl Config {
     /// Configures whether or not a coredump should be generated and attached to
     /// the anyhow::Error when a trap is raised.
     ///
+    /// When enabled, and when compiling with Cranelift, compiled code will
+    /// additionally record the values of Wasm locals and of the operand stack
+    /// before each instruction which may trap or call another function. These
+    /// values are then included in each frame of the coredump. Note that this
+    /// makes compiled code larger and slower, and that only `i32`, `i64`,
+    /// `f32`, and `f64` values are recovered.
+    ///
     /// This option is disabled by default.
     #[cfg(feature = "coredump")]
     pub fn coredump_on_trap(&mut self, enable: bool) -> &mut Self {
@@ -1821,6 +1845,19 @@ impl Config {
             tail_callable
         }
 
+        // Core dumps can only contain locals and operand stack values if
+        // compiled code records them.
+        #[cfg(feature = "coredump")]
+        {
+            tunables.generate_frame_state = self.coredump_on_trap;
+        }
+
+        // Only compiled code that uses GC references needs barriers for a
+        // particular collector.
+        if cfg!(feature = "gc") && self.features.contains(WasmFeatures::REFERENCE_TYPES) {
+            tunables.collector = Some(self.collector.not_auto());
+        }
+
         // If we're going to compile with winch, we must use the winch calling convention.
         #[cfg(any(feature = "cranelift", feature = "winch"))]
         {
@@ -1880,7 +1917,13 @@ impl Config {
     }
 
     #[cfg(feature = "runtime")]
-    pub(crate) fn build_gc_runtime(&self) -> Result<Arc<dyn GcRuntime>> {
+    pub(crate) fn build_gc_runtime(&self, tunables: &Tunables) -> Result<Arc<dyn GcRuntime>> {
+        #[cfg(feature = "gc")]
+        if let Some(wasmtime_environ::Collector::Copying) = tunables.collector {
+            return Ok(Arc::new(crate::runtime::vm::CopyingCollector) as Arc<dyn GcRuntime>);
+        }
+
+        let _ = tunables;
         Ok(Arc::new(crate::runtime::vm::default_gc_runtime()) as Arc<dyn GcRuntime>)
     }
 
@@ -2143,6 +2186,7 @@ impl fmt::Debug for Config {
         if let Some(enable) = self.tunables.guard_before_linear_memory {
             f.field("guard_before_linear_memory", &enable);
         }
+        f.field("collector", &self.collector);
         f.finish()
     }
 }
@@ -2190,6 +2234,52 @@ impl Strategy {
     }
 }
 
+/// Possible garbage collector implementations for Wasm.
+///
+/// This is used as an argument to the [`Config::collector`] method.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub enum Collector {
+    /// An indicator that the garbage collector should be automatically
+    /// selected.
+    ///
+    /// This is generally what you want for most projects and indicates that
+    /// the `wasmtime` crate itself should make the decision about what the
+    /// best collector for a wasm module is.
+    ///
+    /// Currently this always defaults to the deferred reference-counting
+    /// collector, but the default value may change over time.
+    Auto,
+
+    /// The deferred reference-counting collector.
+    ///
+    /// A reference-counting collector, generally trading improved latency and
+    /// prompt reclamation of garbage for worse throughput. Garbage cycles are
+    /// reclaimed by a backup mark-sweep collection.
+    DeferredReferenceCounting,
+
+    /// The semi-space copying collector.
+    ///
+    /// A tracing collector that copies all live objects from one half of the
+    /// GC heap to the other during each collection. Compiled code needs no GC
+    /// barriers and allocation is a simple pointer bump, generally trading
+    /// improved throughput for longer pauses and only being able to use half
+    /// of the GC heap at any given time.
+    Copying,
+}
+
+impl Collector {
+    fn not_auto(&self) -> wasmtime_environ::Collector {
+        match self {
+            Collector::Auto => wasmtime_environ::Collector::DeferredReferenceCounting,
+            Collector::DeferredReferenceCounting => {
+                wasmtime_environ::Collector::DeferredReferenceCounting
+            }
+            Collector::Copying => wasmtime_environ::Collector::Copying,
+        }
+    }
+}
+
 /// Possible optimization levels for the Cranelift codegen backend.
 #[non_exhaustive]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
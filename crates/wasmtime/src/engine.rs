@@ -116,7 +116,7 @@ impl Engine {
                 #[cfg(feature = "runtime")]
                 allocator: config.build_allocator(&tunables)?,
                 #[cfg(feature = "runtime")]
-                gc_runtime: config.build_gc_runtime()?,
+                gc_runtime: config.build_gc_runtime(&tunables)?,
                 #[cfg(feature = "runtime")]
                 profiler: config.build_profiler()?,
                 #[cfg(feature = "runtime")]
@@ -30,7 +30,7 @@ use object::write::{Object, StandardSegment};
 use object::{read::elf::ElfFile64, FileFlags, Object as _, ObjectSection, SectionKind};
 use serde_derive::{Deserialize, Serialize};
 use wasmtime_environ::obj;
-use wasmtime_environ::{FlagValue, ObjectKind, Tunables};
+use wasmtime_environ::{Collector, FlagValue, ObjectKind, Tunables};
 
 const VERSION: u8 = 0;
 
@@ -368,6 +368,7 @@ impl Metadata<'_> {
             relaxed_simd_deterministic,
             tail_callable,
             winch_callable,
+            collector,
 
             // This doesn't affect compilation, it's just a runtime setting.
             dynamic_memory_growth_reserve: _,
@@ -378,6 +379,10 @@ impl Metadata<'_> {
             // whether it's present or not)
             generate_address_map: _,
 
+            // Similar to the address map, this only affects whether frames
+            // can be inspected in core dumps and modules work either way.
+            generate_frame_state: _,
+
             // Just a debugging aid, doesn't affect functionality at all.
             debug_adapter_modules: _,
         } = self.tunables;
@@ -435,10 +440,27 @@ impl Metadata<'_> {
             other.winch_callable,
             "Winch calling convention",
         )?;
+        Self::check_collector(collector, other.collector)?;
 
         Ok(())
     }
 
+    fn check_collector(found: Option<Collector>, expected: Option<Collector>) -> Result<()> {
+        if found == expected {
+            return Ok(());
+        }
+
+        let describe = |collector: Option<Collector>| match collector {
+            Some(collector) => format!("the {collector} collector"),
+            None => "no collector".to_string(),
+        };
+        bail!(
+            "Module was compiled for {} but the host is configured with {}",
+            describe(found),
+            describe(expected),
+        );
+    }
+
     fn check_cfg_bool(
         cfg: bool,
         cfg_str: &str,
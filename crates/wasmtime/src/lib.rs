@@ -233,6 +233,14 @@
 //!   a core dump when a trap happens. This can be configured via
 //!   [`Config::coredump_on_trap`].
 //!
+//! * `snapshot` - Not enabled by default. This feature adds
+//!   [`InstanceSnapshot`], which captures the state of an instance after
+//!   running initialization code and produces a pre-initialized module from it.
+//!
+//! * `checkpoint` - Not enabled by default. This feature adds
+//!   [`Store::checkpoint`] and [`Store::restore`] to save the state of a store
+//!   to bytes and restore it later, possibly in another process.
+//!
 //! * `addr2line` - Enabled by default, this feature configures whether traps
 //!   will attempt to parse DWARF debug information and convert WebAssembly
 //!   addresses to source filenames and line numbers.
@@ -51,6 +51,8 @@ pub use module::{Module, ModuleExport};
 pub use resources::*;
 #[cfg(all(feature = "async", feature = "call-hook"))]
 pub use store::CallHookHandler;
+#[cfg(feature = "checkpoint")]
+pub use store::Checkpointable;
 pub use store::{
     AsContext, AsContextMut, CallHook, Store, StoreContext, StoreContextMut, UpdateDeadline,
 };
@@ -79,6 +81,13 @@ mod coredump;
 #[cfg(feature = "coredump")]
 pub use coredump::*;
 
+#[cfg(any(feature = "snapshot", feature = "checkpoint"))]
+pub(crate) mod instance_state;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+#[cfg(feature = "snapshot")]
+pub use snapshot::InstanceSnapshot;
+
 fn _assertions_runtime() {
     use crate::_assert_send_and_sync;
 
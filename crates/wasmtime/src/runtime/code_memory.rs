@@ -30,6 +30,7 @@ pub struct CodeMemory {
     trap_data: Range<usize>,
     wasm_data: Range<usize>,
     address_map_data: Range<usize>,
+    frame_state_data: Range<usize>,
     func_name_data: Range<usize>,
     info_data: Range<usize>,
     dwarf: Range<usize>,
@@ -68,6 +69,7 @@ impl CodeMemory {
         let mut trap_data = 0..0;
         let mut wasm_data = 0..0;
         let mut address_map_data = 0..0;
+        let mut frame_state_data = 0..0;
         let mut func_name_data = 0..0;
         let mut info_data = 0..0;
         let mut dwarf = 0..0;
@@ -121,6 +123,7 @@ impl CodeMemory {
                 UnwindRegistration::SECTION_NAME => unwind = range,
                 obj::ELF_WASM_DATA => wasm_data = range,
                 obj::ELF_WASMTIME_ADDRMAP => address_map_data = range,
+                obj::ELF_WASMTIME_FRAME_STATE => frame_state_data = range,
                 obj::ELF_WASMTIME_TRAPS => trap_data = range,
                 obj::ELF_NAME_DATA => func_name_data = range,
                 obj::ELF_WASMTIME_INFO => info_data = range,
@@ -139,6 +142,7 @@ impl CodeMemory {
             unwind,
             trap_data,
             address_map_data,
+            frame_state_data,
             func_name_data,
             dwarf,
             info_data,
@@ -189,6 +193,37 @@ impl CodeMemory {
         &self.mmap[self.address_map_data.clone()]
     }
 
+    /// Returns the encoded frame state section used to pass to
+    /// `wasmtime_environ::lookup_frame_state`, or an empty slice if it wasn't
+    /// found.
+    #[inline]
+    pub fn frame_state_data(&self) -> &[u8] {
+        &self.mmap[self.frame_state_data.clone()]
+    }
+
+    /// Returns a checksum of this image which is the same wherever it's
+    /// loaded, as the libcall addresses patched into the text section are
+    /// skipped.
+    #[cfg(feature = "checkpoint")]
+    pub(crate) fn checksum(&self) -> u64 {
+        use std::hash::Hasher;
+
+        let mut offsets = self
+            .relocations
+            .iter()
+            .map(|(offset, _)| self.text.start + offset)
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        let mut start = 0;
+        for offset in offsets {
+            hasher.write(&self.mmap[start..offset]);
+            start = offset + core::mem::size_of::<usize>();
+        }
+        hasher.write(&self.mmap[start..]);
+        hasher.finish()
+    }
+
     /// Returns the contents of the `ELF_WASMTIME_INFO` section, or an empty
     /// slice if it wasn't found.
     #[inline]
@@ -31,6 +31,7 @@ use wasmtime_environ::{EntityIndex, EntityType, Global, PrimaryMap, WasmValType}
 /// [`wasmtime::Instance`](crate::Instance) except that it represents an
 /// instantiated component instead of an instantiated module.
 #[derive(Copy, Clone)]
+#[repr(transparent)] // NB: relied on in the C API
 pub struct Instance(pub(crate) Stored<Option<Box<InstanceData>>>);
 
 pub(crate) struct InstanceData {
@@ -316,6 +316,154 @@ impl ResourceTable {
             child.entry.as_ref()
         }))
     }
+
+    /// Serializes this table to bytes, using `save` to serialize each resource.
+    ///
+    /// The indices of resources and their parent and child relationships are
+    /// preserved by [`ResourceTable::restore`], so handles held by guests
+    /// remain valid. Resources which wrap host objects, such as open files or
+    /// sockets, generally can't be saved, in which case `save` should return
+    /// an error.
+    #[cfg(feature = "checkpoint")]
+    pub fn checkpoint(
+        &self,
+        mut save: impl FnMut(&(dyn Any + Send)) -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            entries.push(match entry {
+                Entry::Free { next } => SavedEntry::Free {
+                    next: next.map(|next| next as u64),
+                },
+                Entry::Occupied { entry } => SavedEntry::Occupied {
+                    data: save(entry.entry.as_ref())?,
+                    parent: entry.parent,
+                    children: entry.children.iter().copied().collect(),
+                },
+            });
+        }
+        let saved = SavedTable {
+            entries,
+            free_head: self.free_head.map(|head| head as u64),
+        };
+        postcard::to_allocvec(&saved).err2anyhow()
+    }
+
+    /// Restores a table from bytes returned by [`ResourceTable::checkpoint`],
+    /// using `load` to deserialize each resource.
+    #[cfg(feature = "checkpoint")]
+    pub fn restore(
+        bytes: &[u8],
+        mut load: impl FnMut(&[u8]) -> Result<Box<dyn Any + Send>>,
+    ) -> Result<ResourceTable> {
+        let saved: SavedTable = postcard::from_bytes(bytes).err2anyhow()?;
+        let len = saved.entries.len() as u64;
+        let index = |ix: u64| -> Result<usize> {
+            if ix >= len {
+                bail!("resource table checkpoint refers to a missing entry");
+            }
+            Ok(ix as usize)
+        };
+
+        let mut entries = Vec::with_capacity(saved.entries.len());
+        for entry in saved.entries {
+            entries.push(match entry {
+                SavedEntry::Free { next } => Entry::Free {
+                    next: next.map(index).transpose()?,
+                },
+                SavedEntry::Occupied {
+                    data,
+                    parent,
+                    children,
+                } => {
+                    for ix in parent.iter().chain(&children) {
+                        index(u64::from(*ix))?;
+                    }
+                    let mut entry = TableEntry::new(load(&data)?, parent);
+                    entry.children = children.into_iter().collect();
+                    Entry::Occupied { entry }
+                }
+            });
+        }
+        let table = ResourceTable {
+            entries,
+            free_head: saved.free_head.map(index).transpose()?,
+        };
+        table.validate()?;
+        Ok(table)
+    }
+
+    /// Check that the free list and the parent and child relationships of a
+    /// restored table are consistent, so that a corrupted checkpoint is
+    /// rejected rather than causing a panic when the table is used later.
+    #[cfg(feature = "checkpoint")]
+    fn validate(&self) -> Result<()> {
+        // The free list must visit every free entry exactly once, and nothing
+        // else.
+        let free = self
+            .entries
+            .iter()
+            .filter(|e| matches!(e, Entry::Free { .. }))
+            .count();
+        let mut visited = 0;
+        let mut next = self.free_head;
+        while let Some(ix) = next {
+            if visited == free {
+                bail!("resource table checkpoint has a cycle in its free list");
+            }
+            next = match &self.entries[ix] {
+                Entry::Free { next } => *next,
+                Entry::Occupied { .. } => {
+                    bail!("resource table checkpoint has an occupied entry in its free list")
+                }
+            };
+            visited += 1;
+        }
+        if visited != free {
+            bail!("resource table checkpoint has free entries missing from its free list");
+        }
+
+        // Parents and children must be occupied and refer to each other.
+        for (ix, entry) in self.entries.iter().enumerate() {
+            let Some(entry) = entry.occupied() else {
+                continue;
+            };
+            let ix = ix as u32;
+            if let Some(parent) = entry.parent {
+                match self.entries[parent as usize].occupied() {
+                    Some(p) if parent != ix && p.children.contains(&ix) => {}
+                    _ => bail!("resource table checkpoint has an invalid parent for entry {ix}"),
+                }
+            }
+            for child in &entry.children {
+                match self.entries[*child as usize].occupied() {
+                    Some(c) if c.parent == Some(ix) => {}
+                    _ => bail!("resource table checkpoint has an invalid child for entry {ix}"),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "checkpoint")]
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct SavedTable {
+    entries: Vec<SavedEntry>,
+    free_head: Option<u64>,
+}
+
+#[cfg(feature = "checkpoint")]
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+enum SavedEntry {
+    Free {
+        next: Option<u64>,
+    },
+    Occupied {
+        data: Vec<u8>,
+        parent: Option<u32>,
+        children: Vec<u32>,
+    },
 }
 
 impl Default for ResourceTable {
@@ -353,3 +501,92 @@ pub fn test_free_list() {
     let x = table.push(()).unwrap();
     assert_eq!(x.rep(), 2);
 }
+
+#[test]
+#[cfg(feature = "checkpoint")]
+pub fn test_checkpoint() {
+    let mut table = ResourceTable::new();
+
+    let parent = table.push(1u32).unwrap();
+    let free = table.push(2u32).unwrap();
+    let child = table.push_child(3u32, &parent).unwrap();
+    table.delete(free).unwrap();
+
+    let bytes = table
+        .checkpoint(|entry| Ok(entry.downcast_ref::<u32>().unwrap().to_le_bytes().to_vec()))
+        .unwrap();
+    let mut table = ResourceTable::restore(&bytes, |bytes| {
+        Ok(Box::new(u32::from_le_bytes(bytes.try_into().unwrap())))
+    })
+    .unwrap();
+
+    assert_eq!(*table.get(&parent).unwrap(), 1);
+    assert_eq!(*table.get(&child).unwrap(), 3);
+    assert_eq!(table.iter_children(&parent).unwrap().count(), 1);
+    assert!(table.delete(parent).is_err());
+
+    // The freed index is reused first, as it would have been before.
+    let x = table.push(4u32).unwrap();
+    assert_eq!(x.rep(), 1);
+}
+
+#[test]
+#[cfg(feature = "checkpoint")]
+pub fn test_restore_rejects_corrupt_checkpoint() {
+    let restore = |entries: Vec<SavedEntry>, free_head: Option<u64>| {
+        let bytes = postcard::to_allocvec(&SavedTable { entries, free_head }).unwrap();
+        ResourceTable::restore(&bytes, |_| Ok(Box::new(())))
+    };
+    let occupied = |parent: Option<u32>, children: Vec<u32>| SavedEntry::Occupied {
+        data: Vec::new(),
+        parent,
+        children,
+    };
+
+    // A consistent table with a parent, a child, and a free entry.
+    assert!(restore(
+        vec![
+            occupied(None, vec![2]),
+            SavedEntry::Free { next: None },
+            occupied(Some(0), vec![]),
+        ],
+        Some(1),
+    )
+    .is_ok());
+
+    // The free list refers to an occupied entry.
+    assert!(restore(vec![occupied(None, vec![])], Some(0)).is_err());
+    assert!(restore(
+        vec![SavedEntry::Free { next: Some(1) }, occupied(None, vec![])],
+        Some(0),
+    )
+    .is_err());
+    // The free list has a cycle.
+    assert!(restore(
+        vec![
+            SavedEntry::Free { next: Some(1) },
+            SavedEntry::Free { next: Some(0) },
+        ],
+        Some(0),
+    )
+    .is_err());
+    // A free entry isn't on the free list.
+    assert!(restore(vec![SavedEntry::Free { next: None }], None).is_err());
+    // A parent or child is free, or doesn't refer back.
+    assert!(restore(
+        vec![SavedEntry::Free { next: None }, occupied(Some(0), vec![])],
+        Some(0),
+    )
+    .is_err());
+    assert!(restore(
+        vec![occupied(None, vec![1]), SavedEntry::Free { next: None }],
+        Some(1),
+    )
+    .is_err());
+    assert!(restore(
+        vec![occupied(None, vec![]), occupied(Some(0), vec![])],
+        None
+    )
+    .is_err());
+    assert!(restore(vec![occupied(Some(0), vec![0])], None).is_err());
+}
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use crate::runtime::vm::CoreDumpFrame;
 use crate::{
     store::StoreOpaque, AsContextMut, FrameInfo, Global, HeapType, Instance, Memory, Module,
     StoreContextMut, Val, ValType, WasmBacktrace,
@@ -15,9 +16,11 @@ use std::{collections::HashMap, fmt};
 /// error returned this will get printed along with the rest of the error when
 /// the error is logged.
 ///
-/// Note that some state, such as Wasm locals or values on the operand stack,
-/// may be optimized away by the compiler or otherwise not recovered in the
-/// coredump.
+/// The values of Wasm locals and of the operand stack are recorded for each
+/// frame when compiling with Cranelift. Only numeric values are recovered and
+/// frames that are not at a call or at an instruction which may trap, for
+/// example frames interrupted in a loop header, have none of their values
+/// recovered.
 ///
 /// Capturing of wasm coredumps can be configured through the
 /// [`Config::coredump_on_trap`][crate::Config::coredump_on_trap] method.
@@ -34,10 +37,21 @@ pub struct WasmCoreDump {
     memories: Vec<Memory>,
     globals: Vec<Global>,
     backtrace: WasmBacktrace,
+
+    /// For each frame of `backtrace`, the index within `instances` of the
+    /// instance it belongs to, if known.
+    frame_instances: Vec<Option<usize>>,
+    locals: Vec<Vec<Option<Val>>>,
+    operand_stacks: Vec<Vec<Option<Val>>>,
 }
 
 impl WasmCoreDump {
-    pub(crate) fn new(store: &mut StoreOpaque, backtrace: WasmBacktrace) -> WasmCoreDump {
+    pub(crate) fn new(
+        store: &mut StoreOpaque,
+        backtrace: WasmBacktrace,
+        frames: Vec<CoreDumpFrame>,
+    ) -> WasmCoreDump {
+        debug_assert_eq!(frames.len(), backtrace.frames().len());
         let modules: Vec<_> = store.modules().all_modules().cloned().collect();
         let instances: Vec<Instance> = store.all_instances().collect();
         let store_memories: Vec<Memory> = store.all_memories().collect();
@@ -45,6 +59,35 @@ impl WasmCoreDump {
         let mut store_globals: Vec<Global> = vec![];
         store.for_each_global(|_store, global| store_globals.push(global));
 
+        let vmctxs = instances
+            .iter()
+            .map(|i| store.instance(i.id(store)).vmctx())
+            .collect::<Vec<_>>();
+        let mut frame_instances = Vec::with_capacity(frames.len());
+        let mut locals = Vec::with_capacity(frames.len());
+        let mut operand_stacks = Vec::with_capacity(frames.len());
+        for frame in frames {
+            frame_instances.push(
+                frame
+                    .vmctx
+                    .and_then(|vmctx| vmctxs.iter().position(|v| *v == vmctx)),
+            );
+            locals.push(
+                frame
+                    .locals
+                    .into_iter()
+                    .map(coredump_value_to_val)
+                    .collect(),
+            );
+            operand_stacks.push(
+                frame
+                    .operand_stack
+                    .into_iter()
+                    .map(coredump_value_to_val)
+                    .collect(),
+            );
+        }
+
         WasmCoreDump {
             name: String::from("store_name"),
             modules,
@@ -52,6 +95,9 @@ impl WasmCoreDump {
             memories: store_memories,
             globals: store_globals,
             backtrace,
+            frame_instances,
+            locals,
+            operand_stacks,
         }
     }
 
@@ -63,6 +109,34 @@ impl WasmCoreDump {
         self.backtrace.frames()
     }
 
+    /// The values of the Wasm locals of the `frame`th entry of
+    /// [`WasmCoreDump::frames`], including function parameters.
+    ///
+    /// This is empty if the frame's locals could not be recovered, and
+    /// individual values are `None` if they were not recorded, for example
+    /// because they are references.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` is out of bounds.
+    pub fn locals(&self, frame: usize) -> &[Option<Val>] {
+        &self.locals[frame]
+    }
+
+    /// The values on the Wasm operand stack of the `frame`th entry of
+    /// [`WasmCoreDump::frames`], from the bottom of the stack to the top.
+    ///
+    /// This is empty if the frame's operand stack could not be recovered, and
+    /// individual values are `None` if they were not recorded, for example
+    /// because they are references.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` is out of bounds.
+    pub fn operand_stack(&self, frame: usize) -> &[Option<Val>] {
+        &self.operand_stacks[frame]
+    }
+
     /// All modules instantiated inside the store when the core dump was
     /// created.
     pub fn modules(&self) -> &[Module] {
@@ -237,12 +311,12 @@ impl WasmCoreDump {
             core_dump.section(&modules);
         }
 
-        // TODO: We can't currently recover instances from stack frames. We can
-        // recover module via the frame's PC, but if there are multiple
-        // instances of the same module, we don't know which instance the frame
-        // is associated with. Therefore, we do a best effort job: remember the
-        // last instance of each module and always choose that one. We record
-        // that information here.
+        // Frames usually know which instance they belong to, but if a frame's
+        // state couldn't be recovered then we can only recover its module via
+        // the frame's PC. If there are multiple instances of that module we
+        // don't know which instance the frame is associated with, so we do a
+        // best effort job: remember the last instance of each module and
+        // always choose that one. We record that information here.
         let mut module_to_instance = HashMap::new();
 
         {
@@ -275,11 +349,15 @@ impl WasmCoreDump {
         {
             let thread_name = "main";
             let mut stack = wasm_encoder::CoreDumpStackSection::new(thread_name);
-            for frame in self.frames() {
-                // This isn't necessarily the right instance if there are
-                // multiple instances of the same module. See comment above
-                // `module_to_instance` for details.
-                let instance = module_to_instance[&frame.module().id()];
+            for (i, frame) in self.frames().iter().enumerate() {
+                // This isn't necessarily the right instance if the frame's
+                // instance is unknown and there are multiple instances of the
+                // same module. See comment above `module_to_instance` for
+                // details.
+                let instance = match self.frame_instances[i] {
+                    Some(index) => u32::try_from(index).unwrap(),
+                    None => module_to_instance[&frame.module().id()],
+                };
 
                 let func = frame.func_index();
 
@@ -288,10 +366,8 @@ impl WasmCoreDump {
                     .and_then(|o| u32::try_from(o).ok())
                     .unwrap_or(0);
 
-                // We can't currently recover locals and the operand stack. We
-                // should eventually be able to do that with Winch though.
-                let locals = [];
-                let operand_stack = [];
+                let locals = self.locals[i].iter().map(val_to_coredump_value);
+                let operand_stack = self.operand_stacks[i].iter().map(val_to_coredump_value);
 
                 stack.frame(instance, func, offset, locals, operand_stack);
             }
@@ -302,6 +378,26 @@ impl WasmCoreDump {
     }
 }
 
+fn coredump_value_to_val(value: wasm_encoder::CoreDumpValue) -> Option<Val> {
+    match value {
+        wasm_encoder::CoreDumpValue::Missing => None,
+        wasm_encoder::CoreDumpValue::I32(x) => Some(Val::I32(x)),
+        wasm_encoder::CoreDumpValue::I64(x) => Some(Val::I64(x)),
+        wasm_encoder::CoreDumpValue::F32(x) => Some(Val::F32(x.to_bits())),
+        wasm_encoder::CoreDumpValue::F64(x) => Some(Val::F64(x.to_bits())),
+    }
+}
+
+fn val_to_coredump_value(value: &Option<Val>) -> wasm_encoder::CoreDumpValue {
+    match value {
+        Some(Val::I32(x)) => wasm_encoder::CoreDumpValue::I32(*x),
+        Some(Val::I64(x)) => wasm_encoder::CoreDumpValue::I64(*x),
+        Some(Val::F32(x)) => wasm_encoder::CoreDumpValue::F32(f32::from_bits(*x)),
+        Some(Val::F64(x)) => wasm_encoder::CoreDumpValue::F64(f64::from_bits(*x)),
+        _ => wasm_encoder::CoreDumpValue::Missing,
+    }
+}
+
 impl fmt::Display for WasmCoreDump {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "wasm coredump generated while executing {}:", self.name)?;
@@ -5,10 +5,14 @@ mod anyref;
 mod externref;
 mod i31;
 mod rooting;
+mod snapshot;
+mod stats;
 mod structref;
 
 pub use anyref::*;
 pub use externref::*;
 pub use i31::*;
 pub use rooting::*;
+pub use snapshot::*;
+pub use stats::*;
 pub use structref::*;
@@ -0,0 +1,320 @@
+//! Snapshots of the objects in a store's GC heap.
+
+use crate::prelude::*;
+use crate::runtime::vm::{GcHeapObjectInfo, GcLayout};
+use crate::store::StoreOpaque;
+use core::fmt::Write;
+use hashbrown::{HashMap, HashSet};
+use wasmtime_environ::VMGcKind;
+
+/// A snapshot of every object in a store's GC heap, and the references between
+/// them.
+///
+/// Obtained via [`Store::gc_heap_snapshot`][crate::Store::gc_heap_snapshot].
+///
+/// The snapshot is a graph whose nodes are GC objects and whose edges are the
+/// references held in those objects' fields. Additionally, the snapshot
+/// records which objects are GC roots: those that are directly referenced by
+/// the host, by globals and tables, or by Wasm frames on the stack.
+///
+/// The snapshot can be exported in the `.heapsnapshot` format used by V8 via
+/// [`GcHeapSnapshot::to_heapsnapshot_json`], and then loaded into tools such
+/// as the Memory panel of Chrome's DevTools to explore what is keeping objects
+/// alive.
+///
+/// # Example
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn _foo() -> Result<()> {
+/// let mut store = Store::<()>::default();
+///
+/// let mut scope = RootScope::new(&mut store);
+/// ExternRef::new(&mut scope, "hello")?;
+///
+/// let snapshot = scope.as_context_mut().gc_heap_snapshot();
+/// assert_eq!(snapshot.objects().len(), 1);
+/// assert_eq!(snapshot.roots().len(), 1);
+///
+/// let json = snapshot.to_heapsnapshot_json();
+/// assert!(json.contains("externref"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GcHeapSnapshot {
+    objects: Vec<GcHeapSnapshotObject>,
+    roots: Vec<u32>,
+}
+
+/// A single object within a [`GcHeapSnapshot`].
+#[derive(Clone, Debug)]
+pub struct GcHeapSnapshotObject {
+    id: u32,
+    kind: VMGcKind,
+    name: String,
+    size: u32,
+    references: Vec<(u32, u32)>,
+}
+
+impl GcHeapSnapshotObject {
+    /// This object's identifier, which is unique within its snapshot.
+    ///
+    /// Identifiers are not stable across snapshots: a collection may move an
+    /// object, or reuse the identifier of an object that was reclaimed for a
+    /// new object.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// A human-readable description of this object's type, e.g. `externref`
+    /// or the definition of a struct type.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Is this object an `externref`?
+    pub fn is_externref(&self) -> bool {
+        self.kind.matches(VMGcKind::ExternRef)
+    }
+
+    /// Is this object a struct?
+    pub fn is_struct(&self) -> bool {
+        self.kind.matches(VMGcKind::StructRef)
+    }
+
+    /// Is this object an array?
+    pub fn is_array(&self) -> bool {
+        self.kind.matches(VMGcKind::ArrayRef)
+    }
+
+    /// The size of this object in the GC heap, in bytes, including any
+    /// collector-specific header.
+    ///
+    /// This does not include the size of an `externref`'s host data, which
+    /// lives outside of the GC heap.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The objects that this object references.
+    ///
+    /// Yields pairs of the index of the field holding the reference and the
+    /// referenced object's identifier.
+    pub fn references(&self) -> impl ExactSizeIterator<Item = (u32, u32)> + '_ {
+        self.references.iter().copied()
+    }
+}
+
+impl GcHeapSnapshot {
+    pub(crate) fn new(store: &mut StoreOpaque) -> Self {
+        // If the GC heap hasn't been allocated yet, then it has no objects.
+        if store.gc_store().is_err() {
+            return GcHeapSnapshot::default();
+        }
+
+        let roots = store.gc_root_refs();
+        let mut roots = roots
+            .iter()
+            .filter(|r| !r.is_i31())
+            .map(|r| r.as_heap_index().unwrap().get())
+            .collect::<Vec<_>>();
+        roots.sort_unstable();
+        roots.dedup();
+
+        let engine = store.engine().clone();
+        let mut objects = Vec::new();
+        store
+            .unwrap_gc_store()
+            .gc_heap
+            .for_each_object(&mut |info| objects.push(snapshot_object(&engine, info)));
+
+        // Every root and reference should point to an object in the heap, but
+        // don't produce a dangling graph if that doesn't hold.
+        let ids = objects.iter().map(|obj| obj.id).collect::<HashSet<_>>();
+        roots.retain(|id| ids.contains(id));
+        for obj in &mut objects {
+            obj.references.retain(|(_, id)| ids.contains(id));
+        }
+
+        GcHeapSnapshot { objects, roots }
+    }
+
+    /// The objects in the GC heap.
+    pub fn objects(&self) -> &[GcHeapSnapshotObject] {
+        &self.objects
+    }
+
+    /// The identifiers of the objects that are GC roots.
+    pub fn roots(&self) -> &[u32] {
+        &self.roots
+    }
+
+    /// Serialize this snapshot as JSON in the `.heapsnapshot` format used by
+    /// V8.
+    ///
+    /// The resulting file can be loaded into heap-snapshot tooling, such as the
+    /// Memory panel of Chrome's DevTools. All GC roots are referenced by a
+    /// single synthetic root node, and struct fields are represented as
+    /// element edges indexed by field index.
+    pub fn to_heapsnapshot_json(&self) -> String {
+        // Node and edge types, as indices into the `node_types` and
+        // `edge_types` lists in the metadata below.
+        const NODE_TYPE_OBJECT: u32 = 3;
+        const NODE_TYPE_NATIVE: u32 = 8;
+        const NODE_TYPE_SYNTHETIC: u32 = 9;
+        const EDGE_TYPE_ELEMENT: u32 = 1;
+        const NODE_FIELD_COUNT: usize = 6;
+
+        let mut strings = vec!["(GC roots)".to_string()];
+        let mut string_ids = HashMap::new();
+        let mut intern = |s: &str| -> usize {
+            *string_ids.entry(s.to_string()).or_insert_with(|| {
+                strings.push(s.to_string());
+                strings.len() - 1
+            })
+        };
+
+        // The position of each object within the `nodes` array. The synthetic
+        // root is the first node, so object nodes begin at index one.
+        let node_index: HashMap<u32, usize> = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| (obj.id, (i + 1) * NODE_FIELD_COUNT))
+            .collect();
+
+        let mut nodes = String::new();
+        let mut edges = String::new();
+        let mut edge_count = 0;
+
+        let mut push_edge = |edges: &mut String, index: u32, to: u32| {
+            let sep = if edge_count == 0 { "" } else { "," };
+            write!(
+                edges,
+                "{sep}{EDGE_TYPE_ELEMENT},{index},{}",
+                node_index[&to]
+            )
+            .unwrap();
+            edge_count += 1;
+        };
+
+        // The synthetic root node, with an edge to every GC root. Its ID is
+        // odd, so it can never collide with an object's ID since objects are
+        // always aligned.
+        write!(nodes, "{NODE_TYPE_SYNTHETIC},0,1,0,{},0", self.roots.len()).unwrap();
+        for (i, root) in self.roots.iter().enumerate() {
+            push_edge(&mut edges, u32::try_from(i).unwrap(), *root);
+        }
+
+        for obj in &self.objects {
+            let ty = if obj.is_externref() {
+                NODE_TYPE_NATIVE
+            } else {
+                NODE_TYPE_OBJECT
+            };
+            let name = intern(&obj.name);
+            write!(
+                nodes,
+                ",{ty},{name},{},{},{},0",
+                obj.id,
+                obj.size,
+                obj.references.len()
+            )
+            .unwrap();
+            for (field, to) in &obj.references {
+                push_edge(&mut edges, *field, *to);
+            }
+        }
+
+        let mut json = String::new();
+        json.push_str(concat!(
+            r#"{"snapshot":{"meta":{"#,
+            r#""node_fields":["type","name","id","self_size","edge_count","trace_node_id"],"#,
+            r#""node_types":[["hidden","array","string","object","code","closure","regexp","#,
+            r#""number","native","synthetic","concatenated string","sliced string","symbol","#,
+            r#""bigint"],"string","number","number","number","number"],"#,
+            r#""edge_fields":["type","name_or_index","to_node"],"#,
+            r#""edge_types":[["context","element","property","internal","hidden","shortcut","#,
+            r#""weak"],"string_or_number","node"],"#,
+            r#""trace_function_info_fields":["function_id","name","script_name","script_id","#,
+            r#""line","column"],"#,
+            r#""trace_node_fields":["id","function_info_index","count","size","children"],"#,
+            r#""sample_fields":["timestamp_us","last_assigned_id"],"#,
+            r#""location_fields":["object_index","script_id","line","column"]},"#,
+        ));
+        write!(
+            json,
+            r#""node_count":{},"edge_count":{edge_count},"trace_function_count":0}},"#,
+            self.objects.len() + 1
+        )
+        .unwrap();
+        write!(json, r#""nodes":[{nodes}],"edges":[{edges}],"#).unwrap();
+        json.push_str(r#""trace_function_infos":[],"trace_tree":[],"samples":[],"locations":[],"#);
+        json.push_str(r#""strings":["#);
+        for (i, s) in strings.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, s);
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Convert the information that the GC heap reports about an object into a
+/// snapshot object.
+fn snapshot_object(engine: &crate::Engine, info: GcHeapObjectInfo<'_>) -> GcHeapSnapshotObject {
+    let kind = info.header.kind();
+    let ty = info.header.ty();
+
+    let name = match ty.and_then(|ty| engine.signatures().borrow(ty)) {
+        Some(ty) => ty.to_string(),
+        None if kind.matches(VMGcKind::ExternRef) => "externref".to_string(),
+        None if kind.matches(VMGcKind::StructRef) => "struct".to_string(),
+        None if kind.matches(VMGcKind::ArrayRef) => "array".to_string(),
+        None => "anyref".to_string(),
+    };
+
+    // Map field offsets back to field indices, if we know this object's
+    // layout.
+    let field_offsets = match ty.and_then(|ty| engine.signatures().layout(ty)) {
+        Some(GcLayout::Struct(layout)) => layout.fields,
+        _ => vec![],
+    };
+    let references = info
+        .edges
+        .iter()
+        .map(|(offset, gc_ref)| {
+            let field = field_offsets
+                .iter()
+                .position(|o| o == offset)
+                .map_or(*offset, |i| u32::try_from(i).unwrap());
+            (field, gc_ref.as_heap_index().unwrap().get())
+        })
+        .collect();
+
+    GcHeapSnapshotObject {
+        id: info.gc_ref.as_heap_index().unwrap().get(),
+        kind,
+        name,
+        size: info.size,
+        references,
+    }
+}
+
+/// Write `s` into `json` as a quoted and escaped JSON string.
+fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if u32::from(c) < 0x20 => write!(json, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
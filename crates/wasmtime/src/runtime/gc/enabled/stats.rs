@@ -0,0 +1,95 @@
+//! Statistics about a store's GC heap and its collections.
+
+use crate::store::StoreOpaque;
+use core::time::Duration;
+
+/// Statistics about a store's GC heap and the garbage collections that have
+/// been performed within it.
+///
+/// Obtained via [`Store::gc_stats`][crate::Store::gc_stats].
+///
+/// # Example
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn _foo() -> Result<()> {
+/// let mut store = Store::<()>::default();
+///
+/// {
+///     let mut scope = RootScope::new(&mut store);
+///     ExternRef::new(&mut scope, "hello")?;
+/// }
+///
+/// store.gc();
+///
+/// let stats = store.gc_stats();
+/// assert_eq!(stats.collections, 1);
+/// assert_eq!(stats.live_objects, 0);
+/// assert_eq!(stats.bytes_allocated, stats.bytes_freed);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct GcStats {
+    /// The number of objects currently allocated in the GC heap.
+    ///
+    /// Depending on the collector, this may include unreachable objects that
+    /// have not been reclaimed yet because there hasn't been a collection
+    /// since they became unreachable.
+    pub live_objects: u64,
+
+    /// The number of bytes currently allocated in the GC heap.
+    ///
+    /// Like `live_objects`, this may include unreachable objects that have not
+    /// been reclaimed yet.
+    pub live_bytes: u64,
+
+    /// The total number of bytes allocated in the GC heap over the lifetime of
+    /// this store.
+    pub bytes_allocated: u64,
+
+    /// The total number of bytes freed in the GC heap over the lifetime of this
+    /// store.
+    pub bytes_freed: u64,
+
+    /// The number of bytes that the GC heap can allocate objects within.
+    pub heap_capacity: u64,
+
+    /// The number of garbage collections that have been performed.
+    pub collections: u64,
+
+    /// The total time spent performing garbage collections.
+    ///
+    /// This is always zero when the `std` Cargo feature is disabled.
+    pub total_pause: Duration,
+
+    /// The time spent performing the longest garbage collection.
+    ///
+    /// This is always zero when the `std` Cargo feature is disabled.
+    pub max_pause: Duration,
+}
+
+impl GcStats {
+    pub(crate) fn new(store: &StoreOpaque) -> Self {
+        // If the GC heap hasn't been allocated yet, then nothing has ever been
+        // allocated in it nor collected.
+        let gc_store = match store.gc_store() {
+            Ok(gc_store) => gc_store,
+            Err(_) => return GcStats::default(),
+        };
+
+        let heap_stats = gc_store.gc_heap.stats();
+        let collection_stats = gc_store.collection_stats;
+        GcStats {
+            live_objects: heap_stats.live_objects,
+            live_bytes: heap_stats.live_bytes,
+            bytes_allocated: heap_stats.bytes_allocated,
+            bytes_freed: heap_stats.bytes_freed,
+            heap_capacity: heap_stats.capacity,
+            collections: collection_stats.collections,
+            total_pause: collection_stats.total_pause,
+            max_pause: collection_stats.max_pause,
+        }
+    }
+}
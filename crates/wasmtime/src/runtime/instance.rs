@@ -595,7 +595,7 @@ impl Instance {
         self.get_export(store, name)?.into_global()
     }
 
-    #[cfg(feature = "component-model")]
+    #[cfg(any(feature = "component-model", feature = "snapshot"))]
     pub(crate) fn id(&self, store: &StoreOpaque) -> InstanceId {
         store[self.0].id
     }
@@ -639,6 +639,27 @@ impl Instance {
             .into_iter()
             .map(|(i, m)| (i, unsafe { Memory::from_wasmtime_memory(m, store) }))
     }
+
+    /// Get all tables within this instance.
+    ///
+    /// Returns both import and defined tables.
+    ///
+    /// Returns both exported and non-exported tables.
+    ///
+    /// Gives access to the full tables space.
+    #[cfg(feature = "snapshot")]
+    pub(crate) fn all_tables<'a>(
+        &'a self,
+        store: &'a mut StoreOpaque,
+    ) -> impl ExactSizeIterator<Item = (TableIndex, Table)> + 'a {
+        let data = &store[self.0];
+        let instance = store.instance_mut(data.id);
+        instance
+            .all_tables()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|(i, t)| (i, unsafe { Table::from_wasmtime_table(t, store) }))
+    }
 }
 
 pub(crate) struct OwnedImports {
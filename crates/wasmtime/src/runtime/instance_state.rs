@@ -0,0 +1,43 @@
+//! Helpers shared by [`InstanceSnapshot`](crate::InstanceSnapshot) and
+//! [`Store::checkpoint`](crate::Store::checkpoint) for capturing the state of
+//! instances.
+
+use crate::store::{InstanceId, StoreOpaque};
+use std::collections::HashMap;
+use wasmtime_environ::FuncIndex;
+
+/// The size of the chunks that memories are scanned in for non-zero data.
+pub(crate) const CHUNK_SIZE: usize = 4096;
+
+/// Iterates over the chunks of `data` which contain any non-zero bytes, along
+/// with their offsets.
+pub(crate) fn nonzero_chunks(data: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    data.chunks(CHUNK_SIZE)
+        .enumerate()
+        .filter(|(_, chunk)| chunk.iter().any(|byte| *byte != 0))
+        .map(|(i, chunk)| (i * CHUNK_SIZE, chunk))
+}
+
+/// Maps each function reference that the instance `id` can hand out back to
+/// its function index.
+///
+/// Only functions that may escape into tables and globals have a function
+/// reference, so references to functions of other instances or of the host
+/// aren't in the map.
+pub(crate) fn func_ref_indices(
+    store: &mut StoreOpaque,
+    id: InstanceId,
+) -> HashMap<usize, FuncIndex> {
+    let instance = store.instance_mut(id);
+    let module = instance.module().clone();
+    let mut indices = HashMap::new();
+    for (index, func) in module.functions.iter() {
+        if !func.is_escaping() {
+            continue;
+        }
+        if let Some(func_ref) = instance.instance_mut().get_func_ref(index) {
+            indices.insert(func_ref as usize, index);
+        }
+    }
+    indices
+}
@@ -0,0 +1,657 @@
+use crate::instance_state::{func_ref_indices, nonzero_chunks};
+use crate::prelude::*;
+use crate::{AsContextMut, Instance, Mutability, Ref, StoreContextMut, Val};
+use core::ops::Range;
+use wasm_encoder::reencode::{Reencode, RoundtripReencoder};
+use wasmparser::{DataKind, ElementKind, Encoding, Parser, Payload, TableInit, TypeRef};
+
+/// Runs of zeroes shorter than this are kept inside a data segment rather than
+/// splitting it, since starting a new segment costs about as many bytes.
+const MIN_GAP: usize = 8;
+
+/// The maximum number of data segments emitted for a single memory, well below
+/// the limit that validators enforce for a whole module.
+const MAX_DATA_SEGMENTS: usize = 10_000;
+
+/// A snapshot of the state of an [`Instance`], used to pre-initialize the
+/// module it was instantiated from.
+///
+/// A snapshot records the contents of the memories, mutable globals and
+/// tables that the instance defines itself. It's typically taken after calling
+/// an initialization function of the instance, for example a WASI reactor's
+/// `_initialize`. [`InstanceSnapshot::rewrite`] then produces a copy of the
+/// original module whose data segments, global initializers, element segments
+/// and memory and table sizes reproduce this state, so instantiating the new
+/// module starts where the initialization function left off without running
+/// it again.
+///
+/// The rewritten module's data segments are regular active segments, so
+/// Wasmtime builds copy-on-write memory images from them just like for any
+/// other module and instantiating a pre-initialized module stays cheap no
+/// matter how much memory initialization touched.
+///
+/// Only state that can be expressed in a module is supported: globals and
+/// tables may only contain null references or functions of the snapshotted
+/// instance itself, and shared memories can't be snapshotted. The state of
+/// imported memories, globals and tables isn't recorded, and the module's
+/// start function is removed since it already ran.
+///
+/// ```
+/// # use wasmtime::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let wat = r#"
+///     (module
+///         (memory (export "memory") 1)
+///         (global $ready (mut i32) (i32.const 0))
+///         (func (export "_initialize")
+///             (i32.store (i32.const 16) (i32.const 42))
+///             (global.set $ready (i32.const 1)))
+///         (func (export "ready") (result i32) global.get $ready))
+/// "#;
+/// let engine = Engine::default();
+/// let module = Module::new(&engine, wat)?;
+/// let mut store = Store::new(&engine, ());
+/// let instance = Instance::new(&mut store, &module, &[])?;
+/// instance
+///     .get_typed_func::<(), ()>(&mut store, "_initialize")?
+///     .call(&mut store, ())?;
+///
+/// let mut snapshot = InstanceSnapshot::new(&mut store, &instance)?;
+/// snapshot.strip_export("_initialize");
+/// let module = snapshot.to_module(&engine, wat)?;
+///
+/// let mut store = Store::new(&engine, ());
+/// let instance = Instance::new(&mut store, &module, &[])?;
+/// let ready = instance.get_typed_func::<(), i32>(&mut store, "ready")?;
+/// assert_eq!(ready.call(&mut store, ())?, 1);
+/// let memory = instance.get_memory(&mut store, "memory").unwrap();
+/// assert_eq!(memory.data(&store)[16], 42);
+/// assert!(instance.get_func(&mut store, "_initialize").is_none());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct InstanceSnapshot {
+    memories: Vec<MemorySnapshot>,
+    /// The value of each defined global, or `None` for immutable globals which
+    /// keep their original initializer.
+    globals: Vec<Option<SnapshotValue>>,
+    tables: Vec<TableSnapshot>,
+    stripped_exports: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct MemorySnapshot {
+    pages: u64,
+    memory64: bool,
+    segments: Vec<(u64, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone)]
+struct TableSnapshot {
+    /// The function index held by each element of the table, or `None` for
+    /// null elements.
+    elements: Vec<Option<u32>>,
+}
+
+#[derive(Debug, Clone)]
+enum SnapshotValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+    Null,
+    Func(u32),
+}
+
+/// What the rewrite needs to know about the original module, gathered in a
+/// first pass over it.
+#[derive(Default)]
+struct ModuleInfo {
+    imported_memories: u32,
+    imported_tables: u32,
+    defined_memories: u32,
+    /// The element type, whether it has an initializer, and whether it's a
+    /// 64-bit table for each defined table.
+    defined_tables: Vec<(wasm_encoder::RefType, bool, bool)>,
+    defined_globals: u32,
+    data_segments: Option<u32>,
+    has_elements: bool,
+}
+
+impl InstanceSnapshot {
+    /// Captures the current state of `instance`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the instance defines a shared memory, or if one of
+    /// its globals or tables holds a reference that can't be written down in a
+    /// module: a non-null `externref` or `anyref`, or a function that isn't
+    /// one of the instance's own functions or imports.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instance` doesn't belong to `store`.
+    pub fn new(mut store: impl AsContextMut, instance: &Instance) -> Result<InstanceSnapshot> {
+        let store = store.as_context_mut();
+        Self::_new(store, instance)
+    }
+
+    fn _new<T>(mut store: StoreContextMut<'_, T>, instance: &Instance) -> Result<InstanceSnapshot> {
+        let module = instance.module(&store).clone();
+        let env_module = module.env_module().clone();
+
+        // Map each function reference the instance can hand out back to its
+        // function index. Only functions that may escape into tables and
+        // globals have one.
+        let id = instance.id(store.0);
+        let funcs = func_ref_indices(store.0, id);
+        let func_index =
+            |store: &mut StoreContextMut<'_, T>, func: &crate::Func| {
+                let func_ref = func.vm_func_ref(store.0).as_ptr() as usize;
+                funcs.get(&func_ref).map(|index| index.as_u32()).ok_or_else(|| {
+                anyhow!("cannot snapshot a reference to a function of another instance or the host")
+            })
+            };
+
+        let mut memories = Vec::new();
+        for (index, memory) in instance.all_memories(store.0).collect::<Vec<_>>() {
+            if env_module.is_imported_memory(index) {
+                continue;
+            }
+            let ty = memory.ty(&store);
+            if ty.is_shared() {
+                bail!("cannot snapshot shared memories");
+            }
+            let data = memory.data(&store);
+            memories.push(MemorySnapshot {
+                pages: memory.size(&store),
+                memory64: ty.is_64(),
+                segments: data_ranges(data)
+                    .into_iter()
+                    .map(|range| (range.start as u64, data[range].to_vec()))
+                    .collect(),
+            });
+        }
+
+        let mut globals = Vec::new();
+        for (index, global) in instance.all_globals(store.0).collect::<Vec<_>>() {
+            if env_module.is_imported_global(index) {
+                continue;
+            }
+            if global.ty(&store).mutability() == Mutability::Const {
+                globals.push(None);
+                continue;
+            }
+            let value = match global.get(&mut store) {
+                Val::I32(x) => SnapshotValue::I32(x),
+                Val::I64(x) => SnapshotValue::I64(x),
+                Val::F32(x) => SnapshotValue::F32(x),
+                Val::F64(x) => SnapshotValue::F64(x),
+                Val::V128(x) => SnapshotValue::V128(x.as_u128()),
+                Val::FuncRef(None) | Val::ExternRef(None) | Val::AnyRef(None) => {
+                    SnapshotValue::Null
+                }
+                Val::FuncRef(Some(func)) => SnapshotValue::Func(
+                    func_index(&mut store, &func)
+                        .with_context(|| format!("failed to snapshot global {}", index.as_u32()))?,
+                ),
+                Val::ExternRef(Some(_)) | Val::AnyRef(Some(_)) => {
+                    bail!(
+                        "cannot snapshot global {} holding a non-null reference",
+                        index.as_u32()
+                    )
+                }
+            };
+            globals.push(Some(value));
+        }
+
+        let mut tables = Vec::new();
+        for (index, table) in instance.all_tables(store.0).collect::<Vec<_>>() {
+            if env_module.is_imported_table(index) {
+                continue;
+            }
+            let i = index.as_u32();
+            let size = table.size(&store);
+            let mut elements = Vec::with_capacity(size as usize);
+            for element in 0..size {
+                let element = match table.get(&mut store, element) {
+                    Some(Ref::Func(Some(func))) => Some(
+                        func_index(&mut store, &func)
+                            .with_context(|| format!("failed to snapshot table {i}"))?,
+                    ),
+                    Some(Ref::Func(None) | Ref::Extern(None) | Ref::Any(None)) | None => None,
+                    Some(Ref::Extern(Some(_)) | Ref::Any(Some(_))) => {
+                        bail!("cannot snapshot table {i} holding a non-null reference")
+                    }
+                };
+                elements.push(element);
+            }
+            tables.push(TableSnapshot { elements });
+        }
+
+        Ok(InstanceSnapshot {
+            memories,
+            globals,
+            tables,
+            stripped_exports: Vec::new(),
+        })
+    }
+
+    /// Removes the export `name` from modules produced from this snapshot.
+    ///
+    /// This is typically used to remove the initialization function, since
+    /// calling it again on a pre-initialized instance would initialize it
+    /// twice.
+    pub fn strip_export(&mut self, name: &str) -> &mut Self {
+        self.stripped_exports.push(name.to_string());
+        self
+    }
+
+    /// Rewrites `wasm`, the module the snapshotted instance was instantiated
+    /// from, so that it starts out in the snapshotted state.
+    ///
+    /// Active data and element segments for the instance's own memories and
+    /// tables are replaced by segments holding the snapshotted contents. The
+    /// replaced data segments are kept as empty passive segments and the
+    /// replaced element segments as declarative segments, so that the indices
+    /// used by `memory.init`, `table.init`, `data.drop` and `elem.drop`
+    /// instructions don't change.
+    ///
+    /// If the `wat` feature is enabled then `wasm` may also be in the text
+    /// format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `wasm` can't be parsed, is a component, or doesn't
+    /// define the same number of memories, tables and globals as the
+    /// snapshotted instance.
+    pub fn rewrite(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        #[cfg(feature = "wat")]
+        let wasm = &wat::parse_bytes(wasm)?[..];
+
+        let info = self.module_info(wasm)?;
+        let mut module = wasm_encoder::Module::new();
+        let mut elements_written = info.has_elements;
+        let mut data_written = info.data_segments.is_some();
+
+        for payload in Parser::new(0).parse_all(wasm) {
+            let payload = payload?;
+
+            // Sections that are only added by the snapshot need to be inserted
+            // before the first section that must follow them.
+            if !elements_written
+                && matches!(
+                    payload,
+                    Payload::DataCountSection { .. }
+                        | Payload::CodeSectionStart { .. }
+                        | Payload::DataSection(_)
+                        | Payload::End(_)
+                )
+            {
+                let mut elements = wasm_encoder::ElementSection::new();
+                self.table_segments(&info, &mut elements);
+                if !elements.is_empty() {
+                    module.section(&elements);
+                }
+                elements_written = true;
+            }
+            if !data_written && matches!(payload, Payload::End(_)) {
+                let mut data = wasm_encoder::DataSection::new();
+                self.memory_segments(&info, &mut data);
+                if !data.is_empty() {
+                    module.section(&data);
+                }
+                data_written = true;
+            }
+
+            match payload {
+                Payload::Version { .. } => {}
+
+                Payload::TableSection(reader) => {
+                    let mut tables = wasm_encoder::TableSection::new();
+                    for (table, snapshot) in reader.into_iter().zip(&self.tables) {
+                        let table = table?;
+                        let mut ty = RoundtripReencoder.table_type(table.ty)?;
+                        ty.minimum = snapshot.elements.len() as u64;
+                        match table.init {
+                            TableInit::RefNull => tables.table(ty),
+                            TableInit::Expr(init) => {
+                                tables.table_with_init(ty, &RoundtripReencoder.const_expr(init)?)
+                            }
+                        };
+                    }
+                    module.section(&tables);
+                }
+
+                Payload::MemorySection(reader) => {
+                    let mut memories = wasm_encoder::MemorySection::new();
+                    for (memory, snapshot) in reader.into_iter().zip(&self.memories) {
+                        let mut ty = RoundtripReencoder.memory_type(memory?);
+                        ty.minimum = snapshot.pages;
+                        memories.memory(ty);
+                    }
+                    module.section(&memories);
+                }
+
+                Payload::GlobalSection(reader) => {
+                    let mut globals = wasm_encoder::GlobalSection::new();
+                    for (global, snapshot) in reader.into_iter().zip(&self.globals) {
+                        let global = global?;
+                        let ty = RoundtripReencoder.global_type(global.ty)?;
+                        let init = match snapshot {
+                            None => RoundtripReencoder.const_expr(global.init_expr)?,
+                            Some(SnapshotValue::I32(x)) => wasm_encoder::ConstExpr::i32_const(*x),
+                            Some(SnapshotValue::I64(x)) => wasm_encoder::ConstExpr::i64_const(*x),
+                            Some(SnapshotValue::F32(x)) => {
+                                wasm_encoder::ConstExpr::f32_const(f32::from_bits(*x))
+                            }
+                            Some(SnapshotValue::F64(x)) => {
+                                wasm_encoder::ConstExpr::f64_const(f64::from_bits(*x))
+                            }
+                            Some(SnapshotValue::V128(x)) => {
+                                wasm_encoder::ConstExpr::v128_const(*x as i128)
+                            }
+                            Some(SnapshotValue::Func(index)) => {
+                                wasm_encoder::ConstExpr::ref_func(*index)
+                            }
+                            Some(SnapshotValue::Null) => match ty.val_type {
+                                wasm_encoder::ValType::Ref(ty) => {
+                                    wasm_encoder::ConstExpr::ref_null(ty.heap_type)
+                                }
+                                _ => bail!("null value for a global of a non-reference type"),
+                            },
+                        };
+                        globals.global(ty, &init);
+                    }
+                    module.section(&globals);
+                }
+
+                Payload::ExportSection(reader) => {
+                    let mut exports = wasm_encoder::ExportSection::new();
+                    for export in reader {
+                        let export = export?;
+                        if !self.stripped_exports.iter().any(|name| name == export.name) {
+                            RoundtripReencoder.parse_export(&mut exports, export);
+                        }
+                    }
+                    module.section(&exports);
+                }
+
+                // The start function already ran in the snapshotted instance.
+                Payload::StartSection { .. } => {}
+
+                Payload::ElementSection(reader) => {
+                    let mut elements = wasm_encoder::ElementSection::new();
+                    for element in reader {
+                        let element = element?;
+                        match element.kind {
+                            ElementKind::Active { table_index, .. }
+                                if table_index.unwrap_or(0) >= info.imported_tables =>
+                            {
+                                // Kept as a declarative segment, which is dropped
+                                // at instantiation just like a used active one,
+                                // so that its functions stay declared for
+                                // `ref.func`.
+                                let element = wasmparser::Element {
+                                    kind: ElementKind::Declared,
+                                    ..element
+                                };
+                                RoundtripReencoder.parse_element(&mut elements, element)?;
+                            }
+                            _ => RoundtripReencoder.parse_element(&mut elements, element)?,
+                        }
+                    }
+                    self.table_segments(&info, &mut elements);
+                    module.section(&elements);
+                }
+
+                Payload::DataCountSection { count, .. } => {
+                    let added = self.memory_segment_count();
+                    module.section(&wasm_encoder::DataCountSection {
+                        count: count + added,
+                    });
+                }
+
+                Payload::CodeSectionStart { range, .. } => {
+                    module.section(&wasm_encoder::RawSection {
+                        id: wasm_encoder::SectionId::Code as u8,
+                        data: &wasm[range],
+                    });
+                    if !data_written {
+                        let mut data = wasm_encoder::DataSection::new();
+                        self.memory_segments(&info, &mut data);
+                        if !data.is_empty() {
+                            module.section(&data);
+                        }
+                        data_written = true;
+                    }
+                }
+                Payload::CodeSectionEntry(_) => {}
+
+                Payload::DataSection(reader) => {
+                    let mut data = wasm_encoder::DataSection::new();
+                    for datum in reader {
+                        let datum = datum?;
+                        match datum.kind {
+                            DataKind::Active { memory_index, .. }
+                                if memory_index >= info.imported_memories =>
+                            {
+                                data.passive([]);
+                            }
+                            _ => RoundtripReencoder.parse_data(&mut data, datum)?,
+                        }
+                    }
+                    self.memory_segments(&info, &mut data);
+                    module.section(&data);
+                }
+
+                Payload::End(_) => {}
+
+                other => match other.as_section() {
+                    Some((id, range)) => {
+                        module.section(&wasm_encoder::RawSection {
+                            id,
+                            data: &wasm[range],
+                        });
+                    }
+                    None => bail!("unexpected payload in a core wasm module"),
+                },
+            }
+        }
+
+        Ok(module.finish())
+    }
+
+    /// Rewrites `wasm` like [`InstanceSnapshot::rewrite`] and compiles the
+    /// result.
+    ///
+    /// The returned module may be [serialized](crate::Module::serialize) to
+    /// avoid compiling it again for every process that wants to use it.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub fn to_module(
+        &self,
+        engine: &crate::Engine,
+        wasm: impl AsRef<[u8]>,
+    ) -> Result<crate::Module> {
+        let wasm = self.rewrite(wasm.as_ref())?;
+        crate::Module::new(engine, &wasm)
+    }
+
+    fn module_info(&self, wasm: &[u8]) -> Result<ModuleInfo> {
+        let mut info = ModuleInfo::default();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::Version {
+                    encoding: Encoding::Component,
+                    ..
+                } => bail!("cannot rewrite a component with an instance snapshot"),
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        match import?.ty {
+                            TypeRef::Memory(_) => info.imported_memories += 1,
+                            TypeRef::Table(_) => info.imported_tables += 1,
+                            TypeRef::Func(_) | TypeRef::Global(_) | TypeRef::Tag(_) => {}
+                        }
+                    }
+                }
+                Payload::MemorySection(reader) => info.defined_memories = reader.count(),
+                Payload::TableSection(reader) => {
+                    for table in reader {
+                        let table = table?;
+                        info.defined_tables.push((
+                            RoundtripReencoder.ref_type(table.ty.element_type)?,
+                            matches!(table.init, TableInit::Expr(_)),
+                            table.ty.table64,
+                        ));
+                    }
+                }
+                Payload::GlobalSection(reader) => info.defined_globals = reader.count(),
+                Payload::ElementSection(_) => info.has_elements = true,
+                Payload::DataSection(reader) => info.data_segments = Some(reader.count()),
+                _ => {}
+            }
+        }
+
+        if info.defined_memories as usize != self.memories.len()
+            || info.defined_tables.len() != self.tables.len()
+            || info.defined_globals as usize != self.globals.len()
+        {
+            bail!("wasm module doesn't match the snapshotted instance");
+        }
+        Ok(info)
+    }
+
+    fn memory_segment_count(&self) -> u32 {
+        self.memories
+            .iter()
+            .map(|memory| memory.segments.len() as u32)
+            .sum()
+    }
+
+    fn memory_segments(&self, info: &ModuleInfo, data: &mut wasm_encoder::DataSection) {
+        for (i, memory) in self.memories.iter().enumerate() {
+            let memory_index = info.imported_memories + i as u32;
+            for (offset, bytes) in memory.segments.iter() {
+                let offset = if memory.memory64 {
+                    wasm_encoder::ConstExpr::i64_const(*offset as i64)
+                } else {
+                    wasm_encoder::ConstExpr::i32_const(*offset as i32)
+                };
+                data.active(memory_index, &offset, bytes.iter().copied());
+            }
+        }
+    }
+
+    fn table_segments(&self, info: &ModuleInfo, elements: &mut wasm_encoder::ElementSection) {
+        for (i, (table, (ty, has_init, table64))) in
+            self.tables.iter().zip(&info.defined_tables).enumerate()
+        {
+            let table_index = info.imported_tables + i as u32;
+            let offset = |offset: usize| {
+                if *table64 {
+                    wasm_encoder::ConstExpr::i64_const(offset as i64)
+                } else {
+                    wasm_encoder::ConstExpr::i32_const(offset as i32)
+                }
+            };
+
+            // Tables with an initializer don't start out null, so every
+            // element is written down explicitly.
+            if *has_init {
+                if table.elements.is_empty() {
+                    continue;
+                }
+                let exprs = table
+                    .elements
+                    .iter()
+                    .map(|element| match element {
+                        Some(func) => wasm_encoder::ConstExpr::ref_func(*func),
+                        None => wasm_encoder::ConstExpr::ref_null(ty.heap_type),
+                    })
+                    .collect::<Vec<_>>();
+                elements.active(
+                    Some(table_index),
+                    &offset(0),
+                    wasm_encoder::Elements::Expressions(*ty, &exprs),
+                );
+                continue;
+            }
+
+            let mut start = 0;
+            while start < table.elements.len() {
+                if table.elements[start].is_none() {
+                    start += 1;
+                    continue;
+                }
+                let end = table.elements[start..]
+                    .iter()
+                    .position(|element| element.is_none())
+                    .map_or(table.elements.len(), |len| start + len);
+                let funcs = table.elements[start..end]
+                    .iter()
+                    .map(|element| element.unwrap())
+                    .collect::<Vec<_>>();
+                if *ty == wasm_encoder::RefType::FUNCREF {
+                    elements.active(
+                        (table_index != 0).then_some(table_index),
+                        &offset(start),
+                        wasm_encoder::Elements::Functions(&funcs),
+                    );
+                } else {
+                    let exprs = funcs
+                        .iter()
+                        .map(|func| wasm_encoder::ConstExpr::ref_func(*func))
+                        .collect::<Vec<_>>();
+                    elements.active(
+                        Some(table_index),
+                        &offset(start),
+                        wasm_encoder::Elements::Expressions(*ty, &exprs),
+                    );
+                }
+                start = end;
+            }
+        }
+    }
+}
+
+/// Splits the non-zero contents of `data` into ranges to emit as data
+/// segments.
+///
+/// Memories are scanned in chunks with runs of zeroes trimmed from either end
+/// of each chunk, and neighboring ranges are merged whenever the zeroes between
+/// them are cheaper to include than another segment. If that still leaves too
+/// many segments, the ranges separated by the smallest runs of zeroes are
+/// merged until it doesn't.
+fn data_ranges(data: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (offset, chunk) in nonzero_chunks(data) {
+        let start = chunk.iter().position(|byte| *byte != 0).unwrap();
+        let end = chunk.iter().rposition(|byte| *byte != 0).unwrap() + 1;
+        let range = offset + start..offset + end;
+        match ranges.last_mut() {
+            Some(last) if range.start - last.end < MIN_GAP => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+
+    if ranges.len() > MAX_DATA_SEGMENTS {
+        let mut gaps = ranges
+            .windows(2)
+            .map(|pair| pair[1].start - pair[0].end)
+            .collect::<Vec<_>>();
+        gaps.sort_unstable();
+        let threshold = gaps[ranges.len() - MAX_DATA_SEGMENTS - 1];
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(MAX_DATA_SEGMENTS);
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start - last.end <= threshold => last.end = range.end,
+                _ => merged.push(range),
+            }
+        }
+        ranges = merged;
+    }
+
+    ranges
+}
@@ -111,6 +111,10 @@ mod data;
 pub use self::data::*;
 mod func_refs;
 use func_refs::FuncRefs;
+#[cfg(feature = "checkpoint")]
+mod checkpoint;
+#[cfg(feature = "checkpoint")]
+pub use self::checkpoint::Checkpointable;
 
 /// A [`Store`] is a collection of WebAssembly instances and host-defined state.
 ///
@@ -842,6 +846,31 @@ impl<T> Store<T> {
         self.inner.gc_async().await;
     }
 
+    /// Get statistics about this store's GC heap and the garbage collections
+    /// that have been performed within it.
+    ///
+    /// See [`GcStats`][crate::GcStats] for details.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        crate::GcStats::new(&self.inner)
+    }
+
+    /// Take a snapshot of every object in this store's GC heap and the
+    /// references between them.
+    ///
+    /// This does not perform a garbage collection, so the snapshot may include
+    /// unreachable objects that have not been reclaimed yet. Call
+    /// [`Store::gc`] first to exclude them. See
+    /// [`GcHeapSnapshot`][crate::GcHeapSnapshot] for details.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_snapshot(&mut self) -> crate::GcHeapSnapshot {
+        crate::GcHeapSnapshot::new(&mut self.inner)
+    }
+
     /// Returns the amount fuel in this [`Store`]. When fuel is enabled, it must
     /// be configured via [`Store::set_fuel`].
     ///
@@ -1054,6 +1083,16 @@ impl<'a, T> StoreContext<'a, T> {
     pub fn get_fuel(&self) -> Result<u64> {
         self.0.get_fuel()
     }
+
+    /// Get statistics about this store's GC heap.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        crate::GcStats::new(&self.0)
+    }
 }
 
 impl<'a, T> StoreContextMut<'a, T> {
@@ -1099,6 +1138,26 @@ impl<'a, T> StoreContextMut<'a, T> {
         self.0.gc_async().await;
     }
 
+    /// Get statistics about this store's GC heap.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        crate::GcStats::new(&self.0)
+    }
+
+    /// Take a snapshot of this store's GC heap.
+    ///
+    /// Same as [`Store::gc_heap_snapshot`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_snapshot(&mut self) -> crate::GcHeapSnapshot {
+        crate::GcHeapSnapshot::new(&mut self.0)
+    }
+
     /// Returns remaining fuel in this store.
     ///
     /// For more information see [`Store::get_fuel`]
@@ -1610,6 +1669,19 @@ impl StoreOpaque {
         self.gc_roots_list = roots;
     }
 
+    /// Get every GC root, without doing a collection.
+    #[cfg(feature = "gc")]
+    pub(crate) fn gc_root_refs(&mut self) -> Vec<VMGcRef> {
+        let mut roots = core::mem::take(&mut self.gc_roots_list);
+
+        self.trace_roots(&mut roots);
+        let gc_refs = unsafe { roots.iter() }.map(|root| root.get()).collect();
+
+        roots.clear();
+        self.gc_roots_list = roots;
+        gc_refs
+    }
+
     #[inline]
     #[cfg(not(feature = "gc"))]
     pub fn gc(&mut self) {
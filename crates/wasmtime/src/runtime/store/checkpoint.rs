@@ -0,0 +1,478 @@
+//! Checkpointing the state of a store to bytes and restoring it.
+
+use super::{StoreInstanceKind, StoreOpaque};
+use crate::instance_state::{func_ref_indices, nonzero_chunks, CHUNK_SIZE};
+use crate::prelude::*;
+use crate::store::InstanceId;
+use crate::{
+    AsContextMut, Func, Global, GlobalType, HeapType, Memory, Module, Ref, Store, StoreContextMut,
+    Table, Val, ValType,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasmtime_environ::FuncIndex;
+
+/// Identifies checkpoints produced by [`Store::checkpoint`].
+const MAGIC: &[u8] = b"\0wasmtime-checkpoint\x01";
+
+/// Host state of a [`Store`] which can be saved in a checkpoint.
+///
+/// This is implemented by the `T` of a [`Store<T>`] to opt into
+/// [`Store::checkpoint`] and [`Store::restore`]. Host state often holds things
+/// that can't be moved to another process, such as open files or sockets, so
+/// implementations decide what to save and return an error if the current
+/// state can't be saved at all. Resources of the component model can be saved
+/// with `ResourceTable::checkpoint`.
+pub trait Checkpointable {
+    /// Serializes this state into bytes for a checkpoint.
+    fn save(&mut self) -> Result<Vec<u8>>;
+
+    /// Restores this state from bytes previously returned by
+    /// [`Checkpointable::save`], possibly in another process.
+    fn restore(&mut self, bytes: &[u8]) -> Result<()>;
+}
+
+impl Checkpointable for () {
+    fn save(&mut self) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn restore(&mut self, _bytes: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    /// The checksum of the module of each instance, see [`module_checksum`].
+    modules: Vec<u64>,
+    memories: Vec<MemoryState>,
+    tables: Vec<Vec<Value>>,
+    globals: Vec<GlobalState>,
+    fuel: Option<u64>,
+    /// The number of epoch ticks left until the deadline.
+    epoch_deadline: Option<u64>,
+    host: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MemoryState {
+    pages: u64,
+    /// The non-zero parts of the memory and their offsets.
+    chunks: Vec<(u64, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GlobalState {
+    ty: GlobalKind,
+    /// The value of the global, or `None` if it's immutable.
+    value: Option<Value>,
+}
+
+/// The type of a global, which must match when it's restored.
+///
+/// Reference types are only recorded by their type hierarchy, as concrete
+/// types aren't meaningful outside of the engine they were registered with.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct GlobalKind {
+    mutable: bool,
+    content: ValueKind,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+enum ValueKind {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    FuncRef,
+    ExternRef,
+    AnyRef,
+}
+
+impl GlobalKind {
+    fn new(ty: &GlobalType) -> GlobalKind {
+        let content = match ty.content() {
+            ValType::I32 => ValueKind::I32,
+            ValType::I64 => ValueKind::I64,
+            ValType::F32 => ValueKind::F32,
+            ValType::F64 => ValueKind::F64,
+            ValType::V128 => ValueKind::V128,
+            ValType::Ref(r) => match r.heap_type().top() {
+                HeapType::Func => ValueKind::FuncRef,
+                HeapType::Extern => ValueKind::ExternRef,
+                _ => ValueKind::AnyRef,
+            },
+        };
+        GlobalKind {
+            mutable: ty.mutability().is_var(),
+            content,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Value {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+    Null,
+    /// A function of a wasm instance, as the index of the instance in the
+    /// store and the index of the function in the instance's module.
+    Func(u32, u32),
+}
+
+impl<T: Checkpointable> Store<T> {
+    /// Saves the state of this store to bytes, to be restored later with
+    /// [`Store::restore`].
+    ///
+    /// A checkpoint contains the contents of all memories, tables and globals
+    /// in the store, the remaining fuel and epoch deadline, and the host state
+    /// saved by [`Checkpointable::save`]. Together with the same modules this
+    /// is enough to evict an idle store, for example to disk, and to later
+    /// resume it in another process.
+    ///
+    /// Since this takes the store itself, WebAssembly can't be running in the
+    /// store while it's checkpointed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store contains a shared memory, or a global or
+    /// table holding a non-null `externref` or `anyref`, or a function that
+    /// was defined by the host rather than by a WebAssembly module. Errors
+    /// from [`Checkpointable::save`] are also returned.
+    pub fn checkpoint(&mut self) -> Result<Vec<u8>> {
+        let instances = wasm_instances(&self.inner);
+        let func_indices = func_indices(&mut self.inner, &instances);
+        let memories = self.inner.all_memories().collect::<Vec<_>>();
+        let mut tables = Vec::new();
+        self.inner.for_each_table(|_, table| tables.push(table));
+        let mut globals = Vec::new();
+        self.inner.for_each_global(|_, global| globals.push(global));
+
+        let fuel = if self.engine().tunables().consume_fuel {
+            Some(self.get_fuel()?)
+        } else {
+            None
+        };
+        let epoch_deadline = if self.engine().tunables().epoch_interruption {
+            let current = self.engine().current_epoch();
+            Some(self.inner.get_epoch_deadline().saturating_sub(current))
+        } else {
+            None
+        };
+
+        let mut store = self.as_context_mut();
+        let func_value = |store: &mut StoreContextMut<'_, T>, func: &Func| {
+            let func_ref = func.vm_func_ref(store.0).as_ptr() as usize;
+            match func_indices.get(&func_ref) {
+                Some((instance, func)) => Ok(Value::Func(*instance, *func)),
+                None => bail!("cannot checkpoint a reference to a host function"),
+            }
+        };
+
+        let mut memory_states = Vec::with_capacity(memories.len());
+        for memory in memories {
+            if memory.ty(&store).is_shared() {
+                bail!("cannot checkpoint shared memories");
+            }
+            let mut chunks: Vec<(u64, Vec<u8>)> = Vec::new();
+            for (offset, chunk) in nonzero_chunks(memory.data(&store)) {
+                let offset = offset as u64;
+                match chunks.last_mut() {
+                    Some((start, data)) if *start + data.len() as u64 == offset => {
+                        data.extend_from_slice(chunk)
+                    }
+                    _ => chunks.push((offset, chunk.to_vec())),
+                }
+            }
+            memory_states.push(MemoryState {
+                pages: memory.size(&store),
+                chunks,
+            });
+        }
+
+        let mut table_states = Vec::with_capacity(tables.len());
+        for table in tables {
+            let size = table.size(&store);
+            let mut elements = Vec::with_capacity(size as usize);
+            for i in 0..size {
+                let element = match table.get(&mut store, i) {
+                    Some(Ref::Func(Some(func))) => func_value(&mut store, &func)?,
+                    Some(Ref::Func(None) | Ref::Extern(None) | Ref::Any(None)) | None => {
+                        Value::Null
+                    }
+                    Some(Ref::Extern(Some(_)) | Ref::Any(Some(_))) => {
+                        bail!("cannot checkpoint a table holding a non-null reference")
+                    }
+                };
+                elements.push(element);
+            }
+            table_states.push(elements);
+        }
+
+        let mut global_states = Vec::with_capacity(globals.len());
+        for global in globals {
+            let ty = GlobalKind::new(&global.ty(&store));
+            if !ty.mutable {
+                global_states.push(GlobalState { ty, value: None });
+                continue;
+            }
+            let value = match global.get(&mut store) {
+                Val::I32(x) => Value::I32(x),
+                Val::I64(x) => Value::I64(x),
+                Val::F32(x) => Value::F32(x),
+                Val::F64(x) => Value::F64(x),
+                Val::V128(x) => Value::V128(x.as_u128()),
+                Val::FuncRef(Some(func)) => func_value(&mut store, &func)?,
+                Val::FuncRef(None) | Val::ExternRef(None) | Val::AnyRef(None) => Value::Null,
+                Val::ExternRef(Some(_)) | Val::AnyRef(Some(_)) => {
+                    bail!("cannot checkpoint a global holding a non-null reference")
+                }
+            };
+            global_states.push(GlobalState {
+                ty,
+                value: Some(value),
+            });
+        }
+
+        let checkpoint = Checkpoint {
+            modules: module_checksums(&self.inner, &instances),
+            memories: memory_states,
+            tables: table_states,
+            globals: global_states,
+            fuel,
+            epoch_deadline,
+            host: self.data_mut().save()?,
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(postcard::to_allocvec(&checkpoint).err2anyhow()?);
+        Ok(bytes)
+    }
+
+    /// Restores the state saved by [`Store::checkpoint`] into this store.
+    ///
+    /// The store must have been set up like the checkpointed one: the same
+    /// modules instantiated in the same order, and the same host memories,
+    /// tables and globals created in the same order. This is usually done by
+    /// instantiating with the same [`Linker`](crate::Linker) setup in a fresh
+    /// store. The contents of memories, tables and globals are then replaced
+    /// by the checkpointed ones, memories and tables are grown to their
+    /// checkpointed sizes, and the fuel, epoch deadline and host state are
+    /// restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `checkpoint` isn't a checkpoint, if it doesn't
+    /// match the layout of this store, if this store's instances are of
+    /// different modules or its globals have different types, or if a memory
+    /// or table is already larger than it was in the checkpoint. If an error
+    /// is returned the store may be partially restored and shouldn't be used
+    /// any further.
+    pub fn restore(&mut self, checkpoint: &[u8]) -> Result<()> {
+        let checkpoint: Checkpoint = match checkpoint.strip_prefix(MAGIC) {
+            Some(bytes) => postcard::from_bytes(bytes).err2anyhow()?,
+            None => bail!("not a store checkpoint"),
+        };
+
+        let instances = wasm_instances(&self.inner);
+        let memories = self.inner.all_memories().collect::<Vec<_>>();
+        let mut tables = Vec::new();
+        self.inner.for_each_table(|_, table| tables.push(table));
+        let mut globals = Vec::new();
+        self.inner.for_each_global(|_, global| globals.push(global));
+        if checkpoint.modules != module_checksums(&self.inner, &instances)
+            || checkpoint.memories.len() != memories.len()
+            || checkpoint.tables.len() != tables.len()
+            || checkpoint.globals.len() != globals.len()
+        {
+            bail!("checkpoint doesn't match the instances of this store");
+        }
+        for (i, (global, state)) in globals.iter().zip(&checkpoint.globals).enumerate() {
+            if GlobalKind::new(&global.ty(&*self)) != state.ty {
+                bail!("type of global {i} doesn't match the checkpoint");
+            }
+        }
+
+        let mut store = self.as_context_mut();
+        for (memory, state) in memories.iter().zip(checkpoint.memories) {
+            restore_memory(&mut store, memory, state)?;
+        }
+        for (table, state) in tables.iter().zip(checkpoint.tables) {
+            restore_table(&mut store, &instances, table, state)?;
+        }
+        for (global, state) in globals.iter().zip(checkpoint.globals) {
+            if let Some(value) = state.value {
+                restore_global(&mut store, &instances, global, value)?;
+            }
+        }
+
+        if let Some(fuel) = checkpoint.fuel {
+            self.set_fuel(fuel)?;
+        }
+        if let Some(ticks) = checkpoint.epoch_deadline {
+            self.set_epoch_deadline(ticks);
+        }
+        self.data_mut().restore(&checkpoint.host)
+    }
+}
+
+/// Returns the ids of the store's instances of wasm modules, as opposed to
+/// the dummy instances backing host-defined items.
+fn wasm_instances(store: &StoreOpaque) -> Vec<InstanceId> {
+    store
+        .instances
+        .iter()
+        .enumerate()
+        .filter(|(_, instance)| matches!(instance.kind, StoreInstanceKind::Real { .. }))
+        .map(|(i, _)| InstanceId::from_index(i))
+        .collect()
+}
+
+/// Returns the checksum of the module of each of `instances`.
+fn module_checksums(store: &StoreOpaque, instances: &[InstanceId]) -> Vec<u64> {
+    instances
+        .iter()
+        .map(|id| module_checksum(store.module_for_instance(*id).unwrap()))
+        .collect()
+}
+
+/// Identifies a compiled module by the checksum of its compiled image.
+///
+/// This is the same for the same module compiled by the same version of
+/// Wasmtime with the same settings, including in another process.
+fn module_checksum(module: &Module) -> u64 {
+    module.compiled_module().code_memory().checksum()
+}
+
+/// Maps each function reference that instances can hand out to the indices
+/// of its instance and function.
+fn func_indices(store: &mut StoreOpaque, instances: &[InstanceId]) -> HashMap<usize, (u32, u32)> {
+    let mut func_indices = HashMap::new();
+    for (i, id) in instances.iter().enumerate() {
+        for (func_ref, index) in func_ref_indices(store, *id) {
+            func_indices.insert(func_ref, (i as u32, index.as_u32()));
+        }
+    }
+    func_indices
+}
+
+fn func<T>(
+    store: &mut StoreContextMut<'_, T>,
+    instances: &[InstanceId],
+    instance: u32,
+    func: u32,
+) -> Result<Func> {
+    let id = instances
+        .get(instance as usize)
+        .ok_or_else(|| anyhow!("checkpoint refers to a missing instance"))?;
+    let instance = store.0.instance_mut(*id);
+    let index = FuncIndex::from_u32(func);
+    if !instance
+        .module()
+        .functions
+        .get(index)
+        .is_some_and(|func| func.is_escaping())
+    {
+        bail!("checkpoint refers to a missing function");
+    }
+    let func_ref = instance.instance_mut().get_func_ref(index).unwrap();
+    Ok(unsafe { Func::from_vm_func_ref(store.0, func_ref).unwrap() })
+}
+
+fn restore_memory<T>(
+    store: &mut StoreContextMut<'_, T>,
+    memory: &Memory,
+    state: MemoryState,
+) -> Result<()> {
+    let size = memory.size(&*store);
+    if state.pages < size {
+        bail!("memory is larger than in the checkpoint");
+    }
+    memory.grow(&mut *store, state.pages - size)?;
+
+    // Everything between the checkpointed chunks was zero when the checkpoint
+    // was taken.
+    let data = memory.data_mut(&mut *store);
+    let mut end = 0;
+    for (offset, chunk) in state.chunks {
+        let offset = usize::try_from(offset)
+            .ok()
+            .filter(|offset| *offset >= end)
+            .ok_or_else(|| anyhow!("checkpointed memory contents overlap"))?;
+        let dst = data
+            .get_mut(offset..)
+            .and_then(|data| data.get_mut(..chunk.len()))
+            .ok_or_else(|| anyhow!("checkpointed memory contents out of bounds"))?;
+        dst.copy_from_slice(&chunk);
+        clear(&mut data[end..offset]);
+        end = offset + chunk.len();
+    }
+    clear(&mut data[end..]);
+    Ok(())
+}
+
+/// Zeroes `data`, skipping chunks that are already zero so that the untouched
+/// pages of a memory, such as those of a freshly instantiated store, aren't
+/// committed by writing to them.
+fn clear(data: &mut [u8]) {
+    for chunk in data.chunks_mut(CHUNK_SIZE) {
+        if chunk.iter().any(|byte| *byte != 0) {
+            chunk.fill(0);
+        }
+    }
+}
+
+fn restore_table<T>(
+    store: &mut StoreContextMut<'_, T>,
+    instances: &[InstanceId],
+    table: &Table,
+    state: Vec<Value>,
+) -> Result<()> {
+    let heap_type = table.ty(&*store).element().heap_type().clone();
+    let size = table.size(&*store);
+    let new_size =
+        u32::try_from(state.len()).map_err(|_| anyhow!("checkpointed table is too large"))?;
+    if new_size < size {
+        bail!("table is larger than in the checkpoint");
+    }
+    table.grow(&mut *store, new_size - size, Ref::null(&heap_type))?;
+
+    for (i, value) in state.into_iter().enumerate() {
+        let element = match value {
+            Value::Null => Ref::null(&heap_type),
+            Value::Func(instance, index) => {
+                Ref::Func(Some(func(store, instances, instance, index)?))
+            }
+            _ => bail!("checkpointed table element isn't a reference"),
+        };
+        table.set(&mut *store, i as u32, element)?;
+    }
+    Ok(())
+}
+
+fn restore_global<T>(
+    store: &mut StoreContextMut<'_, T>,
+    instances: &[InstanceId],
+    global: &Global,
+    state: Value,
+) -> Result<()> {
+    let value = match state {
+        Value::I32(x) => Val::I32(x),
+        Value::I64(x) => Val::I64(x),
+        Value::F32(x) => Val::F32(x),
+        Value::F64(x) => Val::F64(x),
+        Value::V128(x) => Val::V128(x.into()),
+        Value::Null => match global.ty(&*store).content() {
+            ValType::Ref(ty) => Ref::null(ty.heap_type()).into(),
+            _ => bail!("checkpointed global value isn't a number"),
+        },
+        Value::Func(instance, index) => {
+            Val::FuncRef(Some(func(store, instances, instance, index)?))
+        }
+    };
+    global.set(&mut *store, value)
+}
@@ -139,8 +139,13 @@ pub(crate) fn from_runtime_box(
     let _ = &coredumpstack;
     #[cfg(feature = "coredump")]
     if let Some(coredump) = coredumpstack {
-        let bt = WasmBacktrace::from_captured(store, coredump.bt, pc);
-        let cd = WasmCoreDump::new(store, bt);
+        let (bt, kept) = WasmBacktrace::from_captured_frames(store, coredump.bt, pc);
+        let mut frames = coredump.frames;
+        let frames = kept
+            .into_iter()
+            .map(|i| core::mem::take(&mut frames[i]))
+            .collect();
+        let cd = WasmCoreDump::new(store, bt, frames);
         error = error.context(cd);
     }
 
@@ -286,12 +291,23 @@ impl WasmBacktrace {
         runtime_trace: crate::runtime::vm::Backtrace,
         trap_pc: Option<usize>,
     ) -> Self {
+        Self::from_captured_frames(store, runtime_trace, trap_pc).0
+    }
+
+    /// Same as `from_captured`, but additionally returns the index within
+    /// `runtime_trace` of each frame in the returned backtrace.
+    fn from_captured_frames(
+        store: &StoreOpaque,
+        runtime_trace: crate::runtime::vm::Backtrace,
+        trap_pc: Option<usize>,
+    ) -> (Self, Vec<usize>) {
+        let mut kept = Vec::with_capacity(runtime_trace.frames().len());
         let mut wasm_trace = Vec::<FrameInfo>::with_capacity(runtime_trace.frames().len());
         let mut hint_wasm_backtrace_details_env = false;
         let wasm_backtrace_details_env_used =
             store.engine().config().wasm_backtrace_details_env_used;
 
-        for frame in runtime_trace.frames() {
+        for (index, frame) in runtime_trace.frames().enumerate() {
             debug_assert!(frame.pc() != 0);
 
             // Note that we need to be careful about the pc we pass in
@@ -336,6 +352,7 @@ impl WasmBacktrace {
             // this store's module registry.
             if let Some((info, module)) = store.modules().lookup_frame_info(pc_to_lookup) {
                 wasm_trace.push(info);
+                kept.push(index);
 
                 // If this frame has unparsed debug information and the
                 // store's configuration indicates that we were
@@ -350,11 +367,12 @@ impl WasmBacktrace {
             }
         }
 
-        Self {
+        let trace = Self {
             wasm_trace,
             runtime_trace,
             hint_wasm_backtrace_details_env,
-        }
+        };
+        (trace, kept)
     }
 
     /// Returns a list of function frames in WebAssembly this backtrace
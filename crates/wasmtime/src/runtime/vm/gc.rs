@@ -21,6 +21,7 @@ pub use i31::*;
 use crate::prelude::*;
 use crate::runtime::vm::GcHeapAllocationIndex;
 use core::ptr;
+use core::time::Duration;
 use core::{any::Any, num::NonZeroUsize};
 use wasmtime_environ::{StackMap, VMGcKind, VMSharedTypeIndex};
 
@@ -54,6 +55,59 @@ pub struct GcStore {
 
     /// The `externref` host data table for this GC heap.
     pub host_data_table: ExternRefHostDataTable,
+
+    /// Statistics about the collections that have been performed in this GC
+    /// heap.
+    pub collection_stats: GcCollectionStats,
+}
+
+/// Statistics about the collections that have been performed in a GC heap.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcCollectionStats {
+    /// The number of collections that have been performed.
+    pub collections: u64,
+
+    /// The total time spent in collections.
+    ///
+    /// Always zero when the `std` cargo feature is disabled, since we have no
+    /// clock to measure time with.
+    pub total_pause: Duration,
+
+    /// The time spent in the longest collection.
+    ///
+    /// Always zero when the `std` cargo feature is disabled, since we have no
+    /// clock to measure time with.
+    pub max_pause: Duration,
+}
+
+impl GcCollectionStats {
+    fn record(&mut self, pause: Duration) {
+        self.collections += 1;
+        self.total_pause += pause;
+        self.max_pause = core::cmp::max(self.max_pause, pause);
+    }
+}
+
+/// Measures how long a collection pauses execution for.
+struct PauseTimer {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl PauseTimer {
+    fn start() -> Self {
+        PauseTimer {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.start.elapsed();
+        #[cfg(not(feature = "std"))]
+        return Duration::ZERO;
+    }
 }
 
 impl GcStore {
@@ -64,20 +118,26 @@ impl GcStore {
             allocation_index,
             gc_heap,
             host_data_table,
+            collection_stats: GcCollectionStats::default(),
         }
     }
 
     /// Perform garbage collection within this heap.
     pub fn gc(&mut self, roots: GcRootsIter<'_>) {
+        let timer = PauseTimer::start();
         let mut collection = self.gc_heap.gc(roots, &mut self.host_data_table);
         collection.collect();
+        drop(collection);
+        self.collection_stats.record(timer.elapsed());
     }
 
     /// Asynchronously perform garbage collection within this heap.
     #[cfg(feature = "async")]
     pub async fn gc_async(&mut self, roots: GcRootsIter<'_>) {
+        let timer = PauseTimer::start();
         let collection = self.gc_heap.gc(roots, &mut self.host_data_table);
         collect_async(collection).await;
+        self.collection_stats.record(timer.elapsed());
     }
 
     /// Get the kind of the given GC reference.
@@ -288,6 +348,10 @@ pub fn disabled_gc_heap() -> Box<dyn GcHeap> {
                 }
             }
         }
+        fn stats(&self) -> GcHeapStats {
+            GcHeapStats::default()
+        }
+        fn for_each_object(&self, _f: &mut dyn FnMut(GcHeapObjectInfo<'_>)) {}
         unsafe fn vmctx_gc_heap_base(&self) -> *mut u8 {
             ptr::null_mut()
         }
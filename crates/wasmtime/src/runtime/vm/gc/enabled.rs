@@ -1,15 +1,19 @@
 //! Implementation of garbage collection and GC types in Wasmtime.
 
+mod copying;
 mod drc;
 mod externref;
 mod free_list;
 mod structref;
 
+pub use copying::*;
 pub use drc::*;
 pub use externref::*;
 pub use structref::*;
 
-use crate::runtime::vm::GcRuntime;
+use crate::prelude::*;
+use crate::runtime::vm::{GcArrayLayout, GcRuntime, GcStructLayout};
+use wasmtime_environ::{WasmArrayType, WasmStorageType, WasmStructType, WasmValType};
 
 /// Get the default GC runtime.
 pub fn default_gc_runtime() -> impl GcRuntime {
@@ -18,3 +22,92 @@ pub fn default_gc_runtime() -> impl GcRuntime {
 
 /// The default GC heap capacity: 512KiB.
 const DEFAULT_GC_HEAP_CAPACITY: usize = 1 << 19;
+
+/// Align `offset` up to `bytes`, updating `max_align` if `align` is the
+/// new maximum alignment, and returning the aligned offset.
+fn align_up(offset: &mut u32, max_align: &mut u32, align: u32) -> u32 {
+    debug_assert!(max_align.is_power_of_two());
+    debug_assert!(align.is_power_of_two());
+    *offset = offset.checked_add(align - 1).unwrap() & !(align - 1);
+    *max_align = core::cmp::max(*max_align, align);
+    *offset
+}
+
+/// Define a new field of size and alignment `bytes`, updating the object's
+/// total `size` and `align` as necessary. The offset of the new field is
+/// returned.
+fn field(size: &mut u32, align: &mut u32, bytes: u32) -> u32 {
+    let offset = align_up(size, align, bytes);
+    *size += bytes;
+    offset
+}
+
+fn size_of_wasm_ty(ty: &WasmStorageType) -> u32 {
+    match ty {
+        WasmStorageType::I8 => 1,
+        WasmStorageType::I16 => 2,
+        WasmStorageType::Val(ty) => match ty {
+            WasmValType::I32 | WasmValType::F32 | WasmValType::Ref(_) => 4,
+            WasmValType::I64 | WasmValType::F64 => 8,
+            WasmValType::V128 => 16,
+        },
+    }
+}
+
+/// Compute the layout of the given array type for a collector whose objects
+/// all begin with a header of the given size and alignment.
+fn array_layout(header_size: u32, header_align: u32, ty: &WasmArrayType) -> GcArrayLayout {
+    let mut size = header_size;
+    let mut align = header_align;
+    let length_field_offset = field(&mut size, &mut align, 4);
+    let elems_offset = align_up(&mut size, &mut align, size_of_wasm_ty(&ty.0.element_type));
+    GcArrayLayout {
+        size,
+        align,
+        length_field_offset,
+        elems_offset,
+    }
+}
+
+/// Compute the layout of the given struct type for a collector whose objects
+/// all begin with a header of the given size and alignment.
+fn struct_layout(header_size: u32, header_align: u32, ty: &WasmStructType) -> GcStructLayout {
+    // Process each field, aligning it to its natural alignment.
+    //
+    // We don't try and do any fancy field reordering to minimize padding
+    // (yet?) because (a) the toolchain probably already did that and (b)
+    // we're just doing the simple thing first. We can come back and improve
+    // things here if we find that (a) isn't actually holding true in
+    // practice.
+    let mut size = header_size;
+    let mut align = header_align;
+    let fields: Vec<u32> = ty
+        .fields
+        .iter()
+        .map(|f| {
+            let field_size = size_of_wasm_ty(&f.element_type);
+            field(&mut size, &mut align, field_size)
+        })
+        .collect();
+    let gc_ref_fields = ty
+        .fields
+        .iter()
+        .zip(&fields)
+        .filter(|(f, _)| match &f.element_type {
+            WasmStorageType::Val(ty) => ty.is_vmgcref_type(),
+            WasmStorageType::I8 | WasmStorageType::I16 => false,
+        })
+        .map(|(_, offset)| *offset)
+        .collect();
+
+    // Ensure that the final size is a multiple of the alignment, for
+    // simplicity.
+    align_up(&mut size, &mut 16, align);
+
+    GcStructLayout {
+        size,
+        align,
+        fields,
+        gc_ref_fields,
+    }
+}
@@ -0,0 +1,778 @@
+//! The semi-space copying collector.
+//!
+//! The GC heap is split into two equally-sized halves, or *semi-spaces*. At
+//! any given time only one of them is active, and new objects are
+//! bump-allocated in that active semi-space. When it fills up, we collect
+//! garbage by copying every live object into the other semi-space and then
+//! flipping which semi-space is active. Garbage is never visited at all; it is
+//! simply left behind in the old semi-space, which is reused as the target of
+//! the next collection.
+//!
+//! Copying uses Cheney's algorithm: first each GC root is copied and updated
+//! to point to its object's new location, then the copied objects are scanned
+//! in order and each of their GC reference fields are copied and updated in
+//! turn, until the scan finger catches up with the allocation finger. When an
+//! object is copied, its old location is overwritten with a forwarding
+//! reference to its new location, so that objects referenced multiple times
+//! are only copied once and all references to them are updated to the same
+//! new location.
+//!
+//! Because objects move, every GC reference must be found and updated during
+//! collection. Wasm frames' GC references are found precisely via stack maps,
+//! and host code only ever holds GC references through the GC roots in the
+//! store (`Rooted<T>`, `ManuallyRooted<T>`, globals, and tables), so nothing
+//! needs to be tracked between collections and compiled Wasm code doesn't need
+//! any GC barriers.
+//!
+//! Since dead `externref`s are never visited, we keep a list of every
+//! allocated `externref` so that the host data of the ones that didn't survive
+//! a collection can be deallocated.
+//!
+//! For more general information on copying collection, see *A Nonrecursive
+//! List Compacting Algorithm* by Cheney:
+//! <https://dl.acm.org/doi/10.1145/362790.362798>
+
+use super::free_list::ALIGN_USIZE;
+use super::{VMStructDataMut, VMStructRef};
+use crate::prelude::*;
+use crate::runtime::vm::{
+    ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection, GcArrayLayout, GcHeap,
+    GcHeapObject, GcHeapObjectInfo, GcHeapStats, GcProgress, GcRootsIter, GcRuntime,
+    GcStructLayout, Mmap, TypedGcRef, VMExternRef, VMGcHeader, VMGcRef,
+};
+use core::{alloc::Layout, any::Any, cell::UnsafeCell, mem, num::NonZeroU32, num::NonZeroUsize};
+use hashbrown::HashMap;
+use wasmtime_environ::{VMGcKind, VMSharedTypeIndex};
+
+/// The semi-space copying collector.
+///
+/// This is a moving collector: objects are relocated during every collection,
+/// and every GC root is updated to point to its object's new location.
+pub struct CopyingCollector;
+
+unsafe impl GcRuntime for CopyingCollector {
+    fn new_gc_heap(&self) -> Result<Box<dyn GcHeap>> {
+        let heap = CopyingHeap::new()?;
+        Ok(Box::new(heap) as _)
+    }
+
+    fn array_layout(&self, ty: &wasmtime_environ::WasmArrayType) -> GcArrayLayout {
+        super::array_layout(
+            VMCopyingHeader::HEADER_SIZE,
+            VMCopyingHeader::HEADER_ALIGN,
+            ty,
+        )
+    }
+
+    fn struct_layout(&self, ty: &wasmtime_environ::WasmStructType) -> GcStructLayout {
+        super::struct_layout(
+            VMCopyingHeader::HEADER_SIZE,
+            VMCopyingHeader::HEADER_ALIGN,
+            ty,
+        )
+    }
+}
+
+/// A semi-space copying heap.
+struct CopyingHeap {
+    no_gc_count: u64,
+    heap: Mmap,
+
+    /// The size of each semi-space.
+    semi_space_size: usize,
+
+    /// Which semi-space, `0` or `1`, objects are currently allocated in.
+    active: usize,
+
+    /// The bump allocation finger. Outside of collections this is within the
+    /// active semi-space, and during collections it is within the semi-space
+    /// that objects are being copied into.
+    next: usize,
+
+    /// The scan finger, only used during collections.
+    scan: usize,
+
+    /// The offsets of the GC reference fields of each struct type that has
+    /// been allocated in this heap, used to trace through objects.
+    trace_infos: HashMap<VMSharedTypeIndex, Box<[u32]>>,
+
+    /// Every `externref` allocated in the active semi-space, so that we can
+    /// deallocate the host data of those that die.
+    externrefs: Vec<VMGcRef>,
+
+    /// The number of objects in the active semi-space.
+    live_objects: u64,
+
+    /// The number of bytes in use in the active semi-space.
+    live_bytes: u64,
+
+    /// The total number of bytes ever allocated in this heap.
+    bytes_allocated: u64,
+
+    /// The total number of bytes ever freed in this heap.
+    bytes_freed: u64,
+}
+
+impl CopyingHeap {
+    /// Construct a new, default copying heap.
+    fn new() -> Result<Self> {
+        Self::with_capacity(super::DEFAULT_GC_HEAP_CAPACITY)
+    }
+
+    /// Create a new copying heap with the given capacity.
+    ///
+    /// Each semi-space gets half of the capacity.
+    fn with_capacity(capacity: usize) -> Result<Self> {
+        let heap = Mmap::with_at_least(capacity)?;
+
+        // Index zero is never a valid `VMGcRef`, so skip over the first
+        // `ALIGN_USIZE` bytes, and limit ourselves to indices that fit in a
+        // `u32`.
+        let len = core::cmp::min(heap.len(), usize::try_from(u32::MAX).unwrap());
+        let semi_space_size = (len.saturating_sub(ALIGN_USIZE) / 2) & !(ALIGN_USIZE - 1);
+
+        let mut heap = Self {
+            no_gc_count: 0,
+            heap,
+            semi_space_size,
+            active: 0,
+            next: 0,
+            scan: 0,
+            trace_infos: HashMap::new(),
+            externrefs: Vec::new(),
+            live_objects: 0,
+            live_bytes: 0,
+            bytes_allocated: 0,
+            bytes_freed: 0,
+        };
+        heap.next = heap.semi_space_start(0);
+        Ok(heap)
+    }
+
+    /// The heap index at which the given semi-space begins.
+    fn semi_space_start(&self, space: usize) -> usize {
+        debug_assert!(space < 2);
+        ALIGN_USIZE + space * self.semi_space_size
+    }
+
+    /// The heap index at which the given semi-space ends.
+    fn semi_space_end(&self, space: usize) -> usize {
+        self.semi_space_start(space) + self.semi_space_size
+    }
+
+    fn heap_slice(&self) -> &[UnsafeCell<u8>] {
+        let ptr = self.heap.as_ptr().cast::<UnsafeCell<u8>>();
+        let len = self.heap.len();
+        unsafe { core::slice::from_raw_parts(ptr, len) }
+    }
+
+    fn heap_slice_mut(&mut self) -> &mut [u8] {
+        let ptr = self.heap.as_mut_ptr();
+        let len = self.heap.len();
+        unsafe { core::slice::from_raw_parts_mut(ptr, len) }
+    }
+
+    /// Allocate a blank GC object.
+    ///
+    /// The given layout must include the `VMCopyingHeader`.
+    ///
+    /// The resulting GC reference has its header initialized, but everything
+    /// else uninitialized.
+    fn alloc(&mut self, mut header: VMGcHeader, layout: Layout) -> Result<Option<VMGcRef>> {
+        ensure!(
+            layout.align() <= ALIGN_USIZE,
+            "requested allocation's alignment of {} is greater than max supported alignment of {ALIGN_USIZE}",
+            layout.align(),
+        );
+        let size = layout
+            .size()
+            .checked_next_multiple_of(ALIGN_USIZE)
+            .ok_or_else(|| {
+                anyhow!(
+                    "requested allocation's size of {} is too large",
+                    layout.size()
+                )
+            })?;
+        ensure!(
+            size <= self.semi_space_size,
+            "requested allocation's size of {} is greater than the max supported size of {}",
+            layout.size(),
+            self.semi_space_size,
+        );
+
+        if self.semi_space_end(self.active) - self.next < size {
+            return Ok(None);
+        }
+        let index = self.next;
+        self.next += size;
+        self.live_objects += 1;
+        self.live_bytes += u64::try_from(size).unwrap();
+        self.bytes_allocated += u64::try_from(size).unwrap();
+
+        let gc_ref = heap_index_to_gc_ref(index);
+        debug_assert_eq!(header.reserved_u26(), 0);
+        header.set_reserved_u26(u32::try_from(size).unwrap());
+        *self.index_mut(copying_ref(&gc_ref)) = VMCopyingHeader {
+            header,
+            forwarding_ref: None,
+        };
+        Ok(Some(gc_ref))
+    }
+
+    /// Index into this heap and get a shared reference to the `T` that `gc_ref`
+    /// points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics on out of bounds or if the `gc_ref` is an `i31ref`.
+    fn index<T>(&self, gc_ref: &TypedGcRef<T>) -> &T
+    where
+        T: GcHeapObject,
+    {
+        assert!(!mem::needs_drop::<T>());
+        let gc_ref = gc_ref.as_untyped();
+        let start = gc_ref.as_heap_index().unwrap().get();
+        let start = usize::try_from(start).unwrap();
+        let len = mem::size_of::<T>();
+        let slice = &self.heap_slice()[start..][..len];
+        unsafe { &*(slice.as_ptr().cast::<T>()) }
+    }
+
+    /// Index into this heap and get an exclusive reference to the `T` that
+    /// `gc_ref` points to.
+    ///
+    /// # Panics
+    ///
+    /// Panics on out of bounds or if the `gc_ref` is an `i31ref`.
+    fn index_mut<T>(&mut self, gc_ref: &TypedGcRef<T>) -> &mut T
+    where
+        T: GcHeapObject,
+    {
+        assert!(!mem::needs_drop::<T>());
+        let gc_ref = gc_ref.as_untyped();
+        let start = gc_ref.as_heap_index().unwrap().get();
+        let start = usize::try_from(start).unwrap();
+        let len = mem::size_of::<T>();
+        let slice = &mut self.heap_slice_mut()[start..][..len];
+        unsafe { &mut *(slice.as_mut_ptr().cast::<T>()) }
+    }
+
+    /// Begin a collection by directing allocation into the inactive
+    /// semi-space, which objects will be copied into.
+    fn begin_collection(&mut self) {
+        let to_space = 1 - self.active;
+        self.next = self.semi_space_start(to_space);
+        self.scan = self.next;
+        self.live_objects = 0;
+    }
+
+    /// Copy the given object into the semi-space that we are copying into,
+    /// unless it was already copied, and return its new location.
+    fn forward(&mut self, gc_ref: &VMGcRef) -> VMGcRef {
+        debug_assert!(!gc_ref.is_i31());
+        let header = self.index(copying_ref(gc_ref));
+        if let Some(forwarded) = &header.forwarding_ref {
+            return forwarded.unchecked_copy();
+        }
+
+        let size = header.object_size();
+        let from = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+        let to = self.next;
+        assert!(
+            self.semi_space_end(1 - self.active) - to >= size,
+            "live objects cannot exceed the size of a semi-space"
+        );
+        self.next += size;
+        self.heap_slice_mut().copy_within(from..from + size, to);
+
+        let new_ref = heap_index_to_gc_ref(to);
+        log::trace!("copied {gc_ref:#p} to {new_ref:#p}");
+        self.index_mut(copying_ref(gc_ref)).forwarding_ref = Some(new_ref.unchecked_copy());
+        new_ref
+    }
+
+    /// Copy every object that is directly referenced by a GC root, and update
+    /// the roots to point to the objects' new locations.
+    fn copy_roots(&mut self, roots: &mut GcRootsIter<'_>) {
+        for mut root in roots {
+            let gc_ref = root.get();
+            if gc_ref.is_i31() {
+                continue;
+            }
+            let new_ref = self.forward(&gc_ref);
+            root.set(new_ref);
+        }
+    }
+
+    /// Scan every copied object, copying the objects that they reference in
+    /// turn, until everything transitively reachable from the roots has been
+    /// copied.
+    fn scan(&mut self) {
+        let trace_infos = mem::take(&mut self.trace_infos);
+
+        while self.scan < self.next {
+            let gc_ref = heap_index_to_gc_ref(self.scan);
+            let header = self.index(copying_ref(&gc_ref));
+            let size = header.object_size();
+            let kind = header.header.kind();
+            let ty = header.header.ty();
+            self.scan += size;
+            self.live_objects += 1;
+
+            if !kind.matches(VMGcKind::StructRef) {
+                continue;
+            }
+            let fields = match ty.and_then(|ty| trace_infos.get(&ty)) {
+                Some(fields) => fields,
+                None => continue,
+            };
+
+            let start = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+            for offset in fields.iter() {
+                let offset = start + usize::try_from(*offset).unwrap();
+                let bytes = &mut self.heap_slice_mut()[offset..][..mem::size_of::<u32>()];
+                let raw = u32::from_le_bytes(bytes.try_into().unwrap());
+                let field = match VMGcRef::from_raw_u32(raw) {
+                    Some(field) if !field.is_i31() => field,
+                    _ => continue,
+                };
+                let new_field = self.forward(&field);
+                let bytes = &mut self.heap_slice_mut()[offset..][..mem::size_of::<u32>()];
+                bytes.copy_from_slice(&new_field.as_raw_u32().to_le_bytes());
+            }
+        }
+
+        self.trace_infos = trace_infos;
+    }
+
+    /// Update our list of `externref`s to their new locations, and deallocate
+    /// the host data of every `externref` that wasn't copied.
+    fn sweep_externrefs(&mut self, host_data_table: &mut ExternRefHostDataTable) {
+        let mut externrefs = mem::take(&mut self.externrefs);
+        externrefs.retain_mut(|gc_ref| {
+            let header = self.index(copying_ref(gc_ref));
+            match &header.forwarding_ref {
+                Some(forwarded) => {
+                    *gc_ref = forwarded.unchecked_copy();
+                    true
+                }
+                None => {
+                    let host_data_id = self.index(externref_to_copying(gc_ref)).host_data;
+                    log::trace!("deallocating host data for dead externref {gc_ref:#p}");
+                    host_data_table.dealloc(host_data_id);
+                    false
+                }
+            }
+        });
+        self.externrefs = externrefs;
+    }
+
+    /// Finish a collection by making the semi-space that we copied objects
+    /// into the active semi-space.
+    fn flip(&mut self) {
+        let from_space = self.active;
+        self.active = 1 - from_space;
+        self.scan = 0;
+
+        let live_bytes = u64::try_from(self.next - self.semi_space_start(self.active)).unwrap();
+        self.bytes_freed += self.live_bytes - live_bytes;
+        self.live_bytes = live_bytes;
+
+        // Poison the old semi-space in debug builds, so that any lingering
+        // references to it are more likely to be caught.
+        if cfg!(debug_assertions) {
+            let start = self.semi_space_start(from_space);
+            let end = self.semi_space_end(from_space);
+            self.heap_slice_mut()[start..end].fill(POISON);
+        }
+
+        log::trace!(
+            "{} of {} bytes in use after collection",
+            self.live_bytes,
+            self.semi_space_size,
+        );
+    }
+}
+
+/// The byte pattern written over a semi-space after its objects have been
+/// copied out of it, in debug builds.
+const POISON: u8 = 0xdb;
+
+/// Convert the given heap index into a GC reference.
+fn heap_index_to_gc_ref(index: usize) -> VMGcRef {
+    let index = NonZeroU32::new(u32::try_from(index).unwrap()).unwrap();
+    VMGcRef::from_heap_index(index).unwrap()
+}
+
+/// Convert the given GC reference as a typed GC reference pointing to a
+/// `VMCopyingHeader`.
+fn copying_ref(gc_ref: &VMGcRef) -> &TypedGcRef<VMCopyingHeader> {
+    debug_assert!(!gc_ref.is_i31());
+    gc_ref.as_typed_unchecked()
+}
+
+/// Convert a generic GC reference to an `externref` to a typed reference to
+/// our concrete `externref` type.
+fn externref_to_copying(gc_ref: &VMGcRef) -> &TypedGcRef<VMCopyingExternRef> {
+    debug_assert!(!gc_ref.is_i31());
+    gc_ref.as_typed_unchecked()
+}
+
+/// The common header for all objects in the copying collector.
+///
+/// This adds a forwarding reference on top of the collector-agnostic
+/// `VMGcHeader`.
+#[repr(C)]
+struct VMCopyingHeader {
+    header: VMGcHeader,
+
+    /// The object's new location, once it has been copied during a
+    /// collection. Always `None` outside of collections.
+    forwarding_ref: Option<VMGcRef>,
+}
+
+unsafe impl GcHeapObject for VMCopyingHeader {
+    #[inline]
+    fn is(_header: &VMGcHeader) -> bool {
+        // All copying objects have a copying header.
+        true
+    }
+}
+
+const _: () = {
+    assert!((VMCopyingHeader::HEADER_SIZE as usize) == core::mem::size_of::<VMCopyingHeader>());
+    assert!((VMCopyingHeader::HEADER_ALIGN as usize) == core::mem::align_of::<VMCopyingHeader>());
+};
+
+impl VMCopyingHeader {
+    /// The size of `VMCopyingHeader` on *all* architectures.
+    ///
+    /// This includes four bytes of padding after the forwarding reference.
+    const HEADER_SIZE: u32 = VMGcHeader::HEADER_SIZE + 8;
+
+    /// The alignment of `VMCopyingHeader` on *all* architectures.
+    const HEADER_ALIGN: u32 = 8;
+
+    /// The size of this header's object.
+    ///
+    /// This is stored in the inner `VMGcHeader`'s reserved bits.
+    fn object_size(&self) -> usize {
+        let size = self.header.reserved_u26();
+        usize::try_from(size).unwrap()
+    }
+}
+
+#[repr(C)]
+struct VMCopyingExternRef {
+    header: VMCopyingHeader,
+    host_data: ExternRefHostDataId,
+}
+
+unsafe impl GcHeapObject for VMCopyingExternRef {
+    #[inline]
+    fn is(header: &VMGcHeader) -> bool {
+        header.kind() == VMGcKind::ExternRef
+    }
+}
+
+unsafe impl GcHeap for CopyingHeap {
+    fn as_any(&self) -> &dyn Any {
+        self as _
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self as _
+    }
+
+    fn enter_no_gc_scope(&mut self) {
+        self.no_gc_count += 1;
+    }
+
+    fn exit_no_gc_scope(&mut self) {
+        self.no_gc_count -= 1;
+    }
+
+    fn header(&self, gc_ref: &VMGcRef) -> &VMGcHeader {
+        self.index(gc_ref.as_typed_unchecked())
+    }
+
+    fn clone_gc_ref(&mut self, gc_ref: &VMGcRef) -> VMGcRef {
+        gc_ref.unchecked_copy()
+    }
+
+    fn write_gc_ref(
+        &mut self,
+        _host_data_table: &mut ExternRefHostDataTable,
+        destination: &mut Option<VMGcRef>,
+        source: Option<&VMGcRef>,
+    ) {
+        *destination = source.map(|s| s.unchecked_copy());
+    }
+
+    fn expose_gc_ref_to_wasm(&mut self, _gc_ref: VMGcRef) {
+        // Nothing to do: Wasm frames' GC references are found via stack maps
+        // during collection.
+    }
+
+    fn need_gc_before_entering_wasm(&self, _num_gc_refs: NonZeroUsize) -> bool {
+        false
+    }
+
+    fn alloc_externref(&mut self, host_data: ExternRefHostDataId) -> Result<Option<VMExternRef>> {
+        let gc_ref =
+            match self.alloc(VMGcHeader::externref(), Layout::new::<VMCopyingExternRef>())? {
+                None => return Ok(None),
+                Some(gc_ref) => gc_ref,
+            };
+        self.index_mut::<VMCopyingExternRef>(gc_ref.as_typed_unchecked())
+            .host_data = host_data;
+        self.externrefs.push(gc_ref.unchecked_copy());
+        Ok(Some(gc_ref.into_externref_unchecked()))
+    }
+
+    fn externref_host_data(&self, externref: &VMExternRef) -> ExternRefHostDataId {
+        let typed_ref = externref_to_copying(externref.as_gc_ref());
+        self.index(typed_ref).host_data
+    }
+
+    fn alloc_uninit_struct(
+        &mut self,
+        ty: VMSharedTypeIndex,
+        layout: &GcStructLayout,
+    ) -> Result<Option<VMStructRef>> {
+        if !self.trace_infos.contains_key(&ty) {
+            self.trace_infos
+                .insert(ty, layout.gc_ref_fields.clone().into_boxed_slice());
+        }
+        let layout = Layout::from_size_align(
+            usize::try_from(layout.size).unwrap(),
+            usize::try_from(layout.align).unwrap(),
+        )
+        .unwrap();
+        let gc_ref = match self.alloc(
+            VMGcHeader::from_kind_and_index(VMGcKind::StructRef, ty),
+            layout,
+        )? {
+            None => return Ok(None),
+            Some(gc_ref) => gc_ref,
+        };
+        Ok(Some(gc_ref.into_structref_unchecked()))
+    }
+
+    fn dealloc_uninit_struct(&mut self, structref: VMStructRef) {
+        // We can only reclaim the struct's space eagerly if it was the most
+        // recent allocation, otherwise it is garbage that is left behind at
+        // the next collection, just like every other dead object.
+        let gc_ref: VMGcRef = structref.into();
+        let size = self.index(copying_ref(&gc_ref)).object_size();
+        let index = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+        if index + size == self.next {
+            self.next = index;
+            self.live_objects -= 1;
+            self.live_bytes -= u64::try_from(size).unwrap();
+            self.bytes_freed += u64::try_from(size).unwrap();
+        }
+    }
+
+    fn struct_data(&mut self, structref: &VMStructRef, size: u32) -> VMStructDataMut<'_> {
+        let start = structref.as_gc_ref().as_heap_index().unwrap().get();
+        let start = usize::try_from(start).unwrap();
+        let size = usize::try_from(size).unwrap();
+        let end = start + size;
+        let data = &mut self.heap_slice_mut()[start..end];
+        VMStructDataMut::new(data)
+    }
+
+    fn gc<'a>(
+        &'a mut self,
+        roots: GcRootsIter<'a>,
+        host_data_table: &'a mut ExternRefHostDataTable,
+    ) -> Box<dyn GarbageCollection<'a> + 'a> {
+        assert_eq!(self.no_gc_count, 0, "Cannot GC inside a no-GC scope!");
+        Box::new(CopyingCollection {
+            roots,
+            host_data_table,
+            heap: self,
+            phase: CopyingCollectionPhase::CopyRoots,
+        })
+    }
+
+    fn stats(&self) -> GcHeapStats {
+        GcHeapStats {
+            live_objects: self.live_objects,
+            live_bytes: self.live_bytes,
+            bytes_allocated: self.bytes_allocated,
+            bytes_freed: self.bytes_freed,
+            capacity: u64::try_from(self.semi_space_size).unwrap(),
+        }
+    }
+
+    fn for_each_object(&self, f: &mut dyn FnMut(GcHeapObjectInfo<'_>)) {
+        let mut edges = Vec::new();
+        let mut index = self.semi_space_start(self.active);
+        while index < self.next {
+            let gc_ref = heap_index_to_gc_ref(index);
+            let header = self.index(copying_ref(&gc_ref));
+            let size = header.object_size();
+            index += size;
+
+            edges.clear();
+            if header.header.kind().matches(VMGcKind::StructRef) {
+                let fields = header.header.ty().and_then(|ty| self.trace_infos.get(&ty));
+                let start = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+                let heap = self.heap_slice();
+                for field_offset in fields.iter().flat_map(|fields| fields.iter()) {
+                    let offset = start + usize::try_from(*field_offset).unwrap();
+                    let bytes = &heap[offset..][..mem::size_of::<u32>()];
+                    let raw =
+                        u32::from_le_bytes(core::array::from_fn(|i| unsafe { *bytes[i].get() }));
+                    match VMGcRef::from_raw_u32(raw) {
+                        Some(field) if !field.is_i31() => edges.push((*field_offset, field)),
+                        _ => {}
+                    }
+                }
+            }
+
+            f(GcHeapObjectInfo {
+                gc_ref: &gc_ref,
+                header: &header.header,
+                size: u32::try_from(size).unwrap(),
+                edges: &edges,
+            });
+        }
+    }
+
+    unsafe fn vmctx_gc_heap_base(&self) -> *mut u8 {
+        self.heap.as_ptr().cast_mut()
+    }
+
+    unsafe fn vmctx_gc_heap_bound(&self) -> usize {
+        self.heap.len()
+    }
+
+    unsafe fn vmctx_gc_heap_data(&self) -> *mut u8 {
+        // Compiled code doesn't need any collector-specific data.
+        core::ptr::null_mut()
+    }
+
+    #[cfg(feature = "pooling-allocator")]
+    fn reset(&mut self) {
+        let CopyingHeap {
+            no_gc_count,
+            heap: _,
+            semi_space_size: _,
+            active,
+            next: _,
+            scan,
+            trace_infos,
+            externrefs,
+            live_objects,
+            live_bytes,
+            bytes_allocated,
+            bytes_freed,
+        } = self;
+
+        *no_gc_count = 0;
+        *live_objects = 0;
+        *live_bytes = 0;
+        *bytes_allocated = 0;
+        *bytes_freed = 0;
+        *active = 0;
+        *scan = 0;
+        trace_infos.clear();
+        externrefs.clear();
+        self.next = self.semi_space_start(0);
+    }
+}
+
+struct CopyingCollection<'a> {
+    roots: GcRootsIter<'a>,
+    host_data_table: &'a mut ExternRefHostDataTable,
+    heap: &'a mut CopyingHeap,
+    phase: CopyingCollectionPhase,
+}
+
+enum CopyingCollectionPhase {
+    CopyRoots,
+    Scan,
+    SweepExternRefs,
+    Done,
+}
+
+impl<'a> GarbageCollection<'a> for CopyingCollection<'a> {
+    fn collect_increment(&mut self) -> GcProgress {
+        match self.phase {
+            CopyingCollectionPhase::CopyRoots => {
+                log::trace!("Begin copying roots");
+                self.heap.begin_collection();
+                self.heap.copy_roots(&mut self.roots);
+                log::trace!("End copying roots");
+                self.phase = CopyingCollectionPhase::Scan;
+                GcProgress::Continue
+            }
+            CopyingCollectionPhase::Scan => {
+                log::trace!("Begin copying scan");
+                self.heap.scan();
+                log::trace!("End copying scan");
+                self.phase = CopyingCollectionPhase::SweepExternRefs;
+                GcProgress::Continue
+            }
+            CopyingCollectionPhase::SweepExternRefs => {
+                log::trace!("Begin copying externref sweep");
+                self.heap.sweep_externrefs(self.host_data_table);
+                self.heap.flip();
+                log::trace!("End copying externref sweep");
+                self.phase = CopyingCollectionPhase::Done;
+                GcProgress::Complete
+            }
+            CopyingCollectionPhase::Done => GcProgress::Complete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_are_copied_and_forwarded() {
+        let mut heap = CopyingHeap::with_capacity(1024).unwrap();
+        let mut table = ExternRefHostDataTable::default();
+
+        let live_id = table.alloc(Box::new(1_u32));
+        let dead_id = table.alloc(Box::new(2_u32));
+        let live = heap.alloc_externref(live_id).unwrap().unwrap();
+        let dead = heap.alloc_externref(dead_id).unwrap().unwrap();
+        let live = VMGcRef::from(live);
+        drop(dead);
+
+        heap.begin_collection();
+        let new_live = heap.forward(&live);
+        assert_ne!(new_live, live);
+        assert_eq!(heap.forward(&live), new_live);
+        heap.scan();
+        heap.sweep_externrefs(&mut table);
+        heap.flip();
+
+        let new_live = new_live.into_externref_unchecked();
+        assert_eq!(heap.externref_host_data(&new_live), live_id);
+        assert_eq!(heap.externrefs.len(), 1);
+        assert!(table.get(live_id).is::<u32>());
+    }
+
+    #[test]
+    fn alloc_returns_none_when_semi_space_is_full() {
+        let mut heap = CopyingHeap::with_capacity(1024).unwrap();
+        let mut table = ExternRefHostDataTable::default();
+        let mut allocated = 0;
+        loop {
+            let id = table.alloc(Box::new(()));
+            match heap.alloc_externref(id).unwrap() {
+                Some(_) => allocated += 1,
+                None => break,
+            }
+        }
+        assert!(allocated > 0);
+        assert!(heap.next <= heap.semi_space_end(heap.active));
+    }
+}
@@ -1,8 +1,5 @@
 //! The deferred reference-counting (DRC) collector.
 //!
-//! Warning: this ref-counting collector does not have a tracing cycle
-//! collector, and therefore cannot collect cycles between GC objects!
-//!
 //! For host VM code, we use plain reference counting, where cloning increments
 //! the reference count, and dropping decrements it. We can avoid many of the
 //! on-stack increment/decrement operations that typically plague the
@@ -37,17 +34,24 @@
 //! "borrowed" from the `VMGcRefActivationsTable` and the reference count from
 //! the table will be dropped at the next GC).
 //!
+//! Reference counting alone can never reclaim cycles between GC objects, so
+//! every collection also runs a backup mark-sweep cycle collector after the
+//! deferred reference counts have been reconciled. Starting from the same GC
+//! roots, it marks every object that is transitively reachable through struct
+//! fields, and then deallocates every allocated object that was not marked,
+//! since those objects can only be kept alive by garbage cycles.
+//!
 //! For more general information on deferred reference counting, see *An
 //! Examination of Deferred Reference Counting and Cycle Detection* by Quinane:
 //! <https://openresearch-repository.anu.edu.au/bitstream/1885/42030/2/hon-thesis.pdf>
 
-use super::free_list::FreeList;
+use super::free_list::{FreeList, ALIGN_USIZE};
 use super::{VMStructDataMut, VMStructRef};
 use crate::prelude::*;
 use crate::runtime::vm::{
     ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection, GcArrayLayout, GcHeap,
-    GcHeapObject, GcProgress, GcRootsIter, GcRuntime, GcStructLayout, Mmap, TypedGcRef,
-    VMExternRef, VMGcHeader, VMGcRef,
+    GcHeapObject, GcHeapObjectInfo, GcHeapStats, GcProgress, GcRootsIter, GcRuntime,
+    GcStructLayout, Mmap, TypedGcRef, VMExternRef, VMGcHeader, VMGcRef,
 };
 use core::ops::{Deref, DerefMut};
 use core::{
@@ -55,52 +59,23 @@ use core::{
     any::Any,
     cell::UnsafeCell,
     mem,
-    num::NonZeroUsize,
+    num::{NonZeroU32, NonZeroUsize},
     ptr::{self, NonNull},
 };
-use hashbrown::HashSet;
-use wasmtime_environ::{VMGcKind, VMSharedTypeIndex, WasmStorageType, WasmValType};
+use cranelift_bitset::CompoundBitSet;
+use hashbrown::{HashMap, HashSet};
+use wasmtime_environ::{VMGcKind, VMSharedTypeIndex};
 
 /// The deferred reference-counting (DRC) collector.
 ///
-/// This reference-counting collector does not have a cycle collector, and so it
-/// will not be able to reclaim garbage cycles.
+/// Garbage cycles, which reference counting alone cannot reclaim, are collected
+/// by a backup mark-sweep cycle collector that runs as part of every
+/// collection.
 ///
 /// This is not a moving collector; it doesn't have a nursery or do any
 /// compaction.
 pub struct DrcCollector;
 
-/// Align `offset` up to `bytes`, updating `max_align` if `align` is the
-/// new maximum alignment, and returning the aligned offset.
-fn align_up(offset: &mut u32, max_align: &mut u32, align: u32) -> u32 {
-    debug_assert!(max_align.is_power_of_two());
-    debug_assert!(align.is_power_of_two());
-    *offset = offset.checked_add(align - 1).unwrap() & !(align - 1);
-    *max_align = core::cmp::max(*max_align, align);
-    *offset
-}
-
-/// Define a new field of size and alignment `bytes`, updating the object's
-/// total `size` and `align` as necessary. The offset of the new field is
-/// returned.
-fn field(size: &mut u32, align: &mut u32, bytes: u32) -> u32 {
-    let offset = align_up(size, align, bytes);
-    *size += bytes;
-    offset
-}
-
-fn size_of_wasm_ty(ty: &WasmStorageType) -> u32 {
-    match ty {
-        WasmStorageType::I8 => 1,
-        WasmStorageType::I16 => 2,
-        WasmStorageType::Val(ty) => match ty {
-            WasmValType::I32 | WasmValType::F32 | WasmValType::Ref(_) => 4,
-            WasmValType::I64 | WasmValType::F64 => 8,
-            WasmValType::V128 => 16,
-        },
-    }
-}
-
 unsafe impl GcRuntime for DrcCollector {
     fn new_gc_heap(&self) -> Result<Box<dyn GcHeap>> {
         let heap = DrcHeap::new()?;
@@ -108,46 +83,11 @@ unsafe impl GcRuntime for DrcCollector {
     }
 
     fn array_layout(&self, ty: &wasmtime_environ::WasmArrayType) -> GcArrayLayout {
-        let mut size = VMDrcHeader::HEADER_SIZE;
-        let mut align = VMDrcHeader::HEADER_ALIGN;
-        let length_field_offset = field(&mut size, &mut align, 4);
-        let elems_offset = align_up(&mut size, &mut align, size_of_wasm_ty(&ty.0.element_type));
-        GcArrayLayout {
-            size,
-            align,
-            length_field_offset,
-            elems_offset,
-        }
+        super::array_layout(VMDrcHeader::HEADER_SIZE, VMDrcHeader::HEADER_ALIGN, ty)
     }
 
     fn struct_layout(&self, ty: &wasmtime_environ::WasmStructType) -> GcStructLayout {
-        // Process each field, aligning it to its natural alignment.
-        //
-        // We don't try and do any fancy field reordering to minimize padding
-        // (yet?) because (a) the toolchain probably already did that and (b)
-        // we're just doing the simple thing first. We can come back and improve
-        // things here if we find that (a) isn't actually holding true in
-        // practice.
-        let mut size = VMDrcHeader::HEADER_SIZE;
-        let mut align = VMDrcHeader::HEADER_ALIGN;
-        let fields = ty
-            .fields
-            .iter()
-            .map(|f| {
-                let field_size = size_of_wasm_ty(&f.element_type);
-                field(&mut size, &mut align, field_size)
-            })
-            .collect();
-
-        // Ensure that the final size is a multiple of the alignment, for
-        // simplicity.
-        align_up(&mut size, &mut 16, align);
-
-        GcStructLayout {
-            size,
-            align,
-            fields,
-        }
+        super::struct_layout(VMDrcHeader::HEADER_SIZE, VMDrcHeader::HEADER_ALIGN, ty)
     }
 }
 
@@ -159,6 +99,32 @@ struct DrcHeap {
     activations_table: Box<VMGcRefActivationsTable>,
     heap: Mmap,
     free_list: FreeList,
+
+    /// The offsets of the GC reference fields of each struct type that has
+    /// been allocated in this heap, used to trace through objects.
+    trace_infos: HashMap<VMSharedTypeIndex, Box<[u32]>>,
+
+    /// The set of allocated objects, indexed by `object_index`.
+    objects: CompoundBitSet,
+
+    /// The set of objects found to be reachable during cycle collection,
+    /// indexed by `object_index`. Empty outside of collections.
+    marked: CompoundBitSet,
+
+    /// The GC roots found while tracing, and then the work list while marking.
+    /// Empty outside of collections.
+    mark_stack: Vec<VMGcRef>,
+
+    /// Work list of objects whose ref counts need decrementing. Only used
+    /// inside `dec_ref_and_maybe_dealloc` and `sweep_cycles`; it's part of this
+    /// struct so we can reuse its allocation.
+    dec_ref_stack: Vec<VMGcRef>,
+
+    /// The total number of bytes ever allocated in this heap.
+    bytes_allocated: u64,
+
+    /// The total number of bytes ever freed in this heap.
+    bytes_freed: u64,
 }
 
 impl DrcHeap {
@@ -171,11 +137,19 @@ impl DrcHeap {
     fn with_capacity(capacity: usize) -> Result<Self> {
         let heap = Mmap::with_at_least(capacity)?;
         let free_list = FreeList::new(heap.len());
+        let num_objects = heap.len() / ALIGN_USIZE;
         Ok(Self {
             no_gc_count: 0,
             activations_table: Box::new(VMGcRefActivationsTable::default()),
             heap,
             free_list,
+            trace_infos: HashMap::new(),
+            objects: CompoundBitSet::with_capacity(num_objects),
+            marked: CompoundBitSet::with_capacity(num_objects),
+            mark_stack: Vec::new(),
+            dec_ref_stack: Vec::new(),
+            bytes_allocated: 0,
+            bytes_freed: 0,
         })
     }
 
@@ -211,6 +185,8 @@ impl DrcHeap {
             ref_count: UnsafeCell::new(1),
         };
         log::trace!("increment {gc_ref:#p} ref count -> 1");
+        self.objects.insert(object_index(&gc_ref));
+        self.bytes_allocated += u64::try_from(layout.size()).unwrap();
         Ok(Some(gc_ref))
     }
 
@@ -218,6 +194,9 @@ impl DrcHeap {
         let drc_ref = drc_ref(&gc_ref);
         let size = self.index(drc_ref).object_size();
         let layout = FreeList::layout(size);
+        let was_allocated = self.objects.remove(object_index(&gc_ref));
+        debug_assert!(was_allocated, "{gc_ref:#p} is not allocated");
+        self.bytes_freed += u64::try_from(size).unwrap();
         self.free_list
             .dealloc(gc_ref.as_heap_index().unwrap(), layout);
     }
@@ -311,13 +290,22 @@ impl DrcHeap {
     /// Decrement the ref count for the associated object.
     ///
     /// If the ref count reached zero, then deallocate the object and remove its
-    /// associated entry from the `host_data_table` if necessary.
+    /// associated entry from the `host_data_table` if necessary, and then
+    /// decrement the ref counts of the objects it references in turn.
     fn dec_ref_and_maybe_dealloc(
         &mut self,
         host_data_table: &mut ExternRefHostDataTable,
         gc_ref: &VMGcRef,
     ) {
-        if self.dec_ref(gc_ref) {
+        let mut stack = mem::take(&mut self.dec_ref_stack);
+        debug_assert!(stack.is_empty());
+        stack.push(gc_ref.unchecked_copy());
+
+        while let Some(gc_ref) = stack.pop() {
+            if !self.dec_ref(&gc_ref) {
+                continue;
+            }
+
             // If this was an `externref`, remove its associated entry from
             // the host data table.
             if let Some(externref) = gc_ref.as_typed::<VMDrcExternRef>(self) {
@@ -325,11 +313,47 @@ impl DrcHeap {
                 host_data_table.dealloc(host_data_id);
             }
 
-            // TODO: `dec_ref_and_maybe_dealloc` each `VMGcRef` inside this
-            // object.
+            // The references inside this object are going away, so decrement
+            // their ref counts too.
+            self.trace_gc_ref(&gc_ref, &mut stack);
 
             // Deallocate this GC object.
-            self.dealloc(gc_ref.unchecked_copy());
+            self.dealloc(gc_ref);
+        }
+
+        self.dec_ref_stack = stack;
+    }
+
+    /// Push each non-`i31` GC reference held in the fields of the given
+    /// object onto `stack`.
+    fn trace_gc_ref(&self, gc_ref: &VMGcRef, stack: &mut Vec<VMGcRef>) {
+        self.for_each_gc_ref_field(gc_ref, |_offset, field| stack.push(field));
+    }
+
+    /// Call `f` with the offset and value of each non-`i31` GC reference held
+    /// in the fields of the given object.
+    fn for_each_gc_ref_field(&self, gc_ref: &VMGcRef, mut f: impl FnMut(u32, VMGcRef)) {
+        debug_assert!(!gc_ref.is_i31());
+        let header = &self.index(drc_ref(gc_ref)).header;
+        if !header.kind().matches(VMGcKind::StructRef) {
+            return;
+        }
+        let fields = match header.ty().and_then(|ty| self.trace_infos.get(&ty)) {
+            Some(fields) => fields,
+            None => return,
+        };
+
+        let start = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+        let heap = self.heap_slice();
+        for field_offset in fields.iter() {
+            let offset = start + usize::try_from(*field_offset).unwrap();
+            let bytes = &heap[offset..][..mem::size_of::<u32>()];
+            let raw = u32::from_le_bytes(core::array::from_fn(|i| unsafe { *bytes[i].get() }));
+            if let Some(field) = VMGcRef::from_raw_u32(raw) {
+                if !field.is_i31() {
+                    f(*field_offset, field);
+                }
+            }
         }
     }
 
@@ -355,6 +379,13 @@ impl DrcHeap {
         }
 
         for root in roots {
+            // Every root, on the Wasm stack or not, is a starting point for
+            // marking during cycle collection.
+            let gc_ref = root.get();
+            if !gc_ref.is_i31() {
+                self.mark_stack.push(gc_ref.unchecked_copy());
+            }
+
             if !root.is_on_wasm_stack() {
                 // We only trace on-Wasm-stack GC roots. These are the
                 // GC references that we do deferred ref counting for
@@ -364,7 +395,6 @@ impl DrcHeap {
                 continue;
             }
 
-            let gc_ref = root.get();
             debug_assert!(
                 gc_ref.is_i31() || activations_table_set.contains(&gc_ref),
                 "every on-stack gc_ref inside a Wasm frame should \
@@ -517,6 +547,76 @@ impl DrcHeap {
             );
         }
     }
+
+    /// Mark every object that is transitively reachable from the GC roots
+    /// found while tracing.
+    fn mark(&mut self) {
+        debug_assert!(self.marked.is_empty());
+        let mut stack = mem::take(&mut self.mark_stack);
+        while let Some(gc_ref) = stack.pop() {
+            if self.marked.insert(object_index(&gc_ref)) {
+                self.trace_gc_ref(&gc_ref, &mut stack);
+            }
+        }
+        self.mark_stack = stack;
+    }
+
+    /// Deallocate every object that was not marked, since those objects are
+    /// unreachable from the GC roots and are only being kept alive by garbage
+    /// cycles.
+    fn sweep_cycles(&mut self, host_data_table: &mut ExternRefHostDataTable) {
+        let mut garbage = mem::take(&mut self.mark_stack);
+        debug_assert!(garbage.is_empty());
+        garbage.extend(
+            self.objects
+                .iter()
+                .filter(|i| !self.marked.contains(*i))
+                .map(|i| {
+                    let index = u32::try_from(i * ALIGN_USIZE).unwrap();
+                    VMGcRef::from_heap_index(NonZeroU32::new(index).unwrap()).unwrap()
+                }),
+        );
+        log::trace!("Found {} objects in garbage cycles", garbage.len());
+
+        // Garbage objects may still reference live objects, and those
+        // references contribute to the live objects' ref counts, so remove
+        // their contributions. Live objects are still referenced by something
+        // reachable from the roots, so this will never free them.
+        let mut edges = mem::take(&mut self.dec_ref_stack);
+        for gc_ref in garbage.iter() {
+            self.trace_gc_ref(gc_ref, &mut edges);
+        }
+        for gc_ref in edges.drain(..) {
+            if self.marked.contains(object_index(&gc_ref)) {
+                let freed = self.dec_ref(&gc_ref);
+                debug_assert!(
+                    !freed,
+                    "{gc_ref:#p} is reachable from the GC roots; should have \
+                     nonzero ref count",
+                );
+            }
+        }
+        self.dec_ref_stack = edges;
+
+        for gc_ref in garbage.drain(..) {
+            if let Some(externref) = gc_ref.as_typed::<VMDrcExternRef>(self) {
+                let host_data_id = self.index(externref).host_data;
+                host_data_table.dealloc(host_data_id);
+            }
+            log::trace!("Deallocating {gc_ref:#p} from a garbage cycle");
+            self.dealloc(gc_ref);
+        }
+
+        self.mark_stack = garbage;
+        self.marked.clear();
+    }
+}
+
+/// Get the index of the given object within `DrcHeap::objects` and
+/// `DrcHeap::marked`.
+fn object_index(gc_ref: &VMGcRef) -> usize {
+    let index = gc_ref.as_heap_index().unwrap().get();
+    usize::try_from(index).unwrap() / ALIGN_USIZE
 }
 
 /// Convert the given GC reference as a typed GC reference pointing to a
@@ -669,6 +769,10 @@ unsafe impl GcHeap for DrcHeap {
         ty: VMSharedTypeIndex,
         layout: &GcStructLayout,
     ) -> Result<Option<VMStructRef>> {
+        if !self.trace_infos.contains_key(&ty) {
+            self.trace_infos
+                .insert(ty, layout.gc_ref_fields.clone().into_boxed_slice());
+        }
         let layout = Layout::from_size_align(
             usize::try_from(layout.size).unwrap(),
             usize::try_from(layout.align).unwrap(),
@@ -711,6 +815,35 @@ unsafe impl GcHeap for DrcHeap {
         })
     }
 
+    fn stats(&self) -> GcHeapStats {
+        GcHeapStats {
+            live_objects: u64::try_from(self.objects.len()).unwrap(),
+            live_bytes: self.bytes_allocated - self.bytes_freed,
+            bytes_allocated: self.bytes_allocated,
+            bytes_freed: self.bytes_freed,
+            capacity: u64::try_from(self.heap.len()).unwrap(),
+        }
+    }
+
+    fn for_each_object(&self, f: &mut dyn FnMut(GcHeapObjectInfo<'_>)) {
+        let mut edges = Vec::new();
+        for i in self.objects.iter() {
+            let index = u32::try_from(i * ALIGN_USIZE).unwrap();
+            let gc_ref = VMGcRef::from_heap_index(NonZeroU32::new(index).unwrap()).unwrap();
+            let header = self.index(drc_ref(&gc_ref));
+
+            edges.clear();
+            self.for_each_gc_ref_field(&gc_ref, |offset, field| edges.push((offset, field)));
+
+            f(GcHeapObjectInfo {
+                gc_ref: &gc_ref,
+                header: &header.header,
+                size: u32::try_from(header.object_size()).unwrap(),
+                edges: &edges,
+            });
+        }
+    }
+
     unsafe fn vmctx_gc_heap_base(&self) -> *mut u8 {
         self.heap.as_ptr().cast_mut()
     }
@@ -731,11 +864,25 @@ unsafe impl GcHeap for DrcHeap {
             activations_table,
             free_list,
             heap: _,
+            trace_infos,
+            objects,
+            marked,
+            mark_stack,
+            dec_ref_stack,
+            bytes_allocated,
+            bytes_freed,
         } = self;
 
         *no_gc_count = 0;
         free_list.reset();
         activations_table.reset();
+        trace_infos.clear();
+        objects.clear();
+        marked.clear();
+        mark_stack.clear();
+        dec_ref_stack.clear();
+        *bytes_allocated = 0;
+        *bytes_freed = 0;
     }
 }
 
@@ -749,6 +896,8 @@ struct DrcCollection<'a> {
 enum DrcCollectionPhase {
     Trace,
     Sweep,
+    Mark,
+    SweepCycles,
     Done,
 }
 
@@ -766,6 +915,20 @@ impl<'a> GarbageCollection<'a> for DrcCollection<'a> {
                 log::trace!("Begin DRC sweep");
                 self.heap.sweep(self.host_data_table);
                 log::trace!("End DRC sweep");
+                self.phase = DrcCollectionPhase::Mark;
+                GcProgress::Continue
+            }
+            DrcCollectionPhase::Mark => {
+                log::trace!("Begin DRC cycle collection mark");
+                self.heap.mark();
+                log::trace!("End DRC cycle collection mark");
+                self.phase = DrcCollectionPhase::SweepCycles;
+                GcProgress::Continue
+            }
+            DrcCollectionPhase::SweepCycles => {
+                log::trace!("Begin DRC cycle collection sweep");
+                self.heap.sweep_cycles(self.host_data_table);
+                log::trace!("End DRC cycle collection sweep");
                 self.phase = DrcCollectionPhase::Done;
                 GcProgress::Complete
             }
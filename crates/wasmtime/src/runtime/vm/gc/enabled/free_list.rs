@@ -14,7 +14,7 @@ pub(crate) struct FreeList {
 
 /// Our minimum and maximum supported alignment. Every allocation is aligned to
 /// this.
-const ALIGN_USIZE: usize = 8;
+pub(super) const ALIGN_USIZE: usize = 8;
 const ALIGN_U32: u32 = ALIGN_USIZE as u32;
 
 /// Our minimum allocation size.
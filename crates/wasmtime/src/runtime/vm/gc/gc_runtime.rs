@@ -283,6 +283,21 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
         host_data_table: &'a mut ExternRefHostDataTable,
     ) -> Box<dyn GarbageCollection<'a> + 'a>;
 
+    ////////////////////////////////////////////////////////////////////////////
+    // Introspection Methods
+
+    /// Get statistics about the objects currently allocated in this heap and
+    /// the allocations it has made over its lifetime.
+    fn stats(&self) -> GcHeapStats;
+
+    /// Call `f` with information about every object that is currently
+    /// allocated in this heap, including objects that are garbage but have not
+    /// been reclaimed yet.
+    ///
+    /// This method should not allocate or deallocate GC objects, nor run any
+    /// GC barriers.
+    fn for_each_object(&self, f: &mut dyn FnMut(GcHeapObjectInfo<'_>));
+
     ////////////////////////////////////////////////////////////////////////////
     // JIT-Code Interaction Methods
 
@@ -428,6 +443,10 @@ pub struct GcStructLayout {
     /// The fields of this struct. The `i`th entry is the `i`th struct field's
     /// offset in the struct.
     pub fields: Vec<u32>,
+
+    /// The offsets of the fields that hold GC references, which collectors
+    /// need to trace through.
+    pub gc_ref_fields: Vec<u32>,
 }
 
 /// A list of GC roots.
@@ -578,6 +597,42 @@ impl GcRoot<'_> {
     }
 }
 
+/// Statistics about a GC heap's objects and allocations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcHeapStats {
+    /// The number of objects currently allocated in the heap.
+    pub live_objects: u64,
+
+    /// The number of bytes currently allocated in the heap.
+    pub live_bytes: u64,
+
+    /// The total number of bytes ever allocated in this heap.
+    pub bytes_allocated: u64,
+
+    /// The total number of bytes ever freed in this heap.
+    pub bytes_freed: u64,
+
+    /// The number of bytes that this heap can allocate objects within.
+    pub capacity: u64,
+}
+
+/// Information about a single object in a GC heap, as reported by
+/// `GcHeap::for_each_object`.
+pub struct GcHeapObjectInfo<'a> {
+    /// A reference to the object.
+    pub gc_ref: &'a VMGcRef,
+
+    /// The object's header.
+    pub header: &'a VMGcHeader,
+
+    /// The size of the object, in bytes, including its header.
+    pub size: u32,
+
+    /// The non-`i31ref` GC references held in the object's fields, along with
+    /// the offset of each field within the object.
+    pub edges: &'a [(u32, VMGcRef)],
+}
+
 /// A garbage collection process.
 ///
 /// Implementations define the `collect_increment` method, and then consumers
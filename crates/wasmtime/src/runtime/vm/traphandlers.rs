@@ -21,6 +21,8 @@ use core::ptr;
 
 pub use self::backtrace::Backtrace;
 pub use self::coredump::CoreDumpStack;
+#[cfg(feature = "coredump")]
+pub use self::coredump::CoreDumpFrame;
 pub use self::tls::{tls_eager_initialize, AsyncWasmCallState, PreviousAsyncWasmCallState};
 
 pub use traphandlers::SignalHandler;
@@ -1,7 +1,12 @@
 use super::CallThreadState;
 use crate::prelude::*;
-use crate::runtime::vm::{Backtrace, VMRuntimeLimits};
+use crate::runtime::module::lookup_code;
+use crate::runtime::vm::{Backtrace, VMContext, VMRuntimeLimits};
 use wasm_encoder::CoreDumpValue;
+use wasmtime_environ::{
+    frame_state_value_offset, lookup_file_pos, lookup_frame_state, FrameValueType,
+    FRAME_STATE_VMCTX_OFFSET,
+};
 
 /// A WebAssembly Coredump
 #[derive(Debug)]
@@ -9,17 +14,24 @@ pub struct CoreDumpStack {
     /// The backtrace containing the stack frames for the CoreDump
     pub bt: Backtrace,
 
-    /// The locals for each frame in the backtrace.
-    ///
-    /// This is not currently implemented.
-    #[allow(dead_code)]
-    pub locals: Vec<Vec<CoreDumpValue>>,
-
-    /// The operands for each stack frame
-    ///
-    /// This is not currently implemented.
-    #[allow(dead_code)]
-    pub operand_stack: Vec<Vec<CoreDumpValue>>,
+    /// The state recovered for each frame in the backtrace.
+    pub frames: Vec<CoreDumpFrame>,
+}
+
+/// The state of a single Wasm stack frame recovered for a coredump.
+///
+/// Everything here is empty if the frame's state could not be recovered.
+#[derive(Debug, Default)]
+pub struct CoreDumpFrame {
+    /// The `VMContext` of the instance that this frame belongs to.
+    pub vmctx: Option<*mut VMContext>,
+
+    /// The values of this frame's locals.
+    pub locals: Vec<CoreDumpValue>,
+
+    /// The values on this frame's operand stack, from the bottom of the stack
+    /// to the top.
+    pub operand_stack: Vec<CoreDumpValue>,
 }
 
 impl CallThreadState {
@@ -33,10 +45,68 @@ impl CallThreadState {
         }
         let bt = unsafe { Backtrace::new_with_trap_state(limits, self, trap_pc_and_fp) };
 
-        Some(CoreDumpStack {
-            bt,
-            locals: vec![],
-            operand_stack: vec![],
-        })
+        let trap_pc = trap_pc_and_fp.map(|(pc, _)| pc);
+        let frames = bt
+            .frames()
+            .map(|frame| {
+                // Frames other than the one that trapped are suspended at a
+                // call, so look up the call instruction itself rather than the
+                // return address, as `WasmBacktrace` does.
+                let pc = if Some(frame.pc()) == trap_pc {
+                    frame.pc()
+                } else {
+                    frame.pc() - 1
+                };
+                unsafe { read_frame_state(pc, frame.fp()) }.unwrap_or_default()
+            })
+            .collect();
+
+        Some(CoreDumpStack { bt, frames })
     }
 }
+
+/// Reads the `VMContext`, locals, and operand stack recorded in the frame
+/// state slot of the frame at `fp` which is currently executing `pc`.
+///
+/// Returns `None` if the compiled code didn't record frame state at `pc`.
+///
+/// # Safety
+///
+/// The `fp` must be the frame pointer of a live Wasm frame executing `pc`.
+unsafe fn read_frame_state(pc: usize, fp: usize) -> Option<CoreDumpFrame> {
+    let (code, text_offset) = lookup_code(pc)?;
+    let wasm_offset = lookup_file_pos(code.address_map_data(), text_offset)?.file_offset()?;
+    let state = lookup_frame_state(code.frame_state_data(), text_offset, wasm_offset)?;
+    let slot = fp.checked_sub(usize::try_from(state.slot_offset()).unwrap())?;
+
+    let read = |index: usize, ty: FrameValueType| {
+        let index = u32::try_from(index).unwrap();
+        let addr = slot + usize::try_from(frame_state_value_offset(index)).unwrap();
+        match ty {
+            FrameValueType::Missing => CoreDumpValue::Missing,
+            FrameValueType::I32 => CoreDumpValue::I32((addr as *const i32).read_unaligned()),
+            FrameValueType::I64 => CoreDumpValue::I64((addr as *const i64).read_unaligned()),
+            FrameValueType::F32 => CoreDumpValue::F32((addr as *const f32).read_unaligned()),
+            FrameValueType::F64 => CoreDumpValue::F64((addr as *const f64).read_unaligned()),
+        }
+    };
+
+    let vmctx_addr = slot + usize::try_from(FRAME_STATE_VMCTX_OFFSET).unwrap();
+    let vmctx = (vmctx_addr as *const *mut VMContext).read_unaligned();
+    let locals = state
+        .locals()
+        .enumerate()
+        .map(|(i, ty)| read(i, ty))
+        .collect::<Vec<_>>();
+    let num_locals = locals.len();
+    let operand_stack = state
+        .stack()
+        .enumerate()
+        .map(|(i, ty)| read(num_locals + i, ty))
+        .collect();
+    Some(CoreDumpFrame {
+        vmctx: Some(vmctx),
+        locals,
+        operand_stack,
+    })
+}
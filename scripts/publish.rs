@@ -37,6 +37,8 @@ const CRATES_TO_PUBLISH: &[&str] = &[
     "cranelift-object",
     "cranelift-interpreter",
     "wasmtime-jit-icache-coherence",
+    "wasmtime-versioned-export-macros",
+    "wasmtime-jit-debug",
     "cranelift-jit",
     "cranelift",
     // wiggle
@@ -46,12 +48,10 @@ const CRATES_TO_PUBLISH: &[&str] = &[
     "winch",
     // wasmtime
     "wasmtime-asm-macros",
-    "wasmtime-versioned-export-macros",
     "wasmtime-slab",
     "wasmtime-component-util",
     "wasmtime-wit-bindgen",
     "wasmtime-component-macro",
-    "wasmtime-jit-debug",
     "wasmtime-fiber",
     "wasmtime-environ",
     "wasmtime-wmemcheck",
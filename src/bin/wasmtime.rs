@@ -65,6 +65,10 @@ enum Subcommand {
     #[cfg(feature = "compile")]
     Compile(wasmtime_cli::commands::CompileCommand),
 
+    /// Inspects a core dump written by a trapping WebAssembly program.
+    #[cfg(feature = "coredump")]
+    Coredump(wasmtime_cli::commands::CoredumpCommand),
+
     /// Explore the compilation of a WebAssembly module to native code.
     #[cfg(feature = "explore")]
     Explore(wasmtime_cli::commands::ExploreCommand),
@@ -73,6 +77,11 @@ enum Subcommand {
     #[cfg(feature = "serve")]
     Serve(wasmtime_cli::commands::ServeCommand),
 
+    /// Pre-initializes a WebAssembly module by snapshotting it after running
+    /// its initialization function.
+    #[cfg(feature = "snapshot")]
+    Snapshot(wasmtime_cli::commands::SnapshotCommand),
+
     /// Displays available Cranelift settings for a target.
     #[cfg(feature = "cranelift")]
     Settings(wasmtime_cli::commands::SettingsCommand),
@@ -100,12 +109,18 @@ impl Wasmtime {
             #[cfg(feature = "compile")]
             Subcommand::Compile(c) => c.execute(),
 
+            #[cfg(feature = "coredump")]
+            Subcommand::Coredump(c) => c.execute(),
+
             #[cfg(feature = "explore")]
             Subcommand::Explore(c) => c.execute(),
 
             #[cfg(feature = "serve")]
             Subcommand::Serve(c) => c.execute(),
 
+            #[cfg(feature = "snapshot")]
+            Subcommand::Snapshot(c) => c.execute(),
+
             #[cfg(feature = "cranelift")]
             Subcommand::Settings(c) => c.execute(),
 
@@ -15,11 +15,21 @@ mod explore;
 #[cfg(feature = "explore")]
 pub use self::explore::*;
 
+#[cfg(feature = "snapshot")]
+mod snapshot;
+#[cfg(feature = "snapshot")]
+pub use self::snapshot::*;
+
 #[cfg(feature = "wast")]
 mod wast;
 #[cfg(feature = "wast")]
 pub use self::wast::*;
 
+#[cfg(feature = "coredump")]
+mod coredump;
+#[cfg(feature = "coredump")]
+pub use self::coredump::*;
+
 #[cfg(feature = "cache")]
 mod config;
 #[cfg(feature = "cache")]
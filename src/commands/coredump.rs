@@ -0,0 +1,842 @@
+//! The module that implements the `wasmtime coredump` command.
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use wasmparser::{
+    CoreDumpValue, DataKind, KnownCustom, Name, Operator, Parser as WasmParser, Payload, TypeRef,
+};
+use wasmtime_environ::{demangle_function_name, demangle_function_name_or_index};
+
+/// Inspects a Wasm core dump, such as one written by `wasmtime run -D
+/// coredump=...`.
+#[derive(Parser, PartialEq)]
+pub struct CoredumpCommand {
+    /// The original Wasm module(s) that were running when the core dump was
+    /// taken, used to symbolize the backtrace.
+    ///
+    /// Modules are paired with the modules in the core dump by their name in
+    /// the `name` section or by their file name. If no modules are given then
+    /// the executable named in the core dump is used, if it exists.
+    #[arg(long = "module", short = 'm', value_name = "MODULE")]
+    modules: Vec<PathBuf>,
+
+    /// The maximum number of bytes of each memory to print.
+    #[arg(long, value_name = "BYTES", default_value_t = 256)]
+    memory_bytes: usize,
+
+    /// The format to print the core dump in.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// The path of the core dump to inspect.
+    #[arg(required = true, value_name = "COREDUMP")]
+    coredump: PathBuf,
+}
+
+/// Output formats supported by `wasmtime coredump`.
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// Human-readable text.
+    Text,
+    /// A JSON object, suitable for consumption by other tools.
+    Json,
+}
+
+impl CoredumpCommand {
+    /// Executes the command.
+    pub fn execute(self) -> Result<()> {
+        let bytes = std::fs::read(&self.coredump)
+            .with_context(|| format!("failed to read core dump: {}", self.coredump.display()))?;
+        let coredump = CoreDump::parse(&bytes)
+            .with_context(|| format!("failed to parse core dump: {}", self.coredump.display()))?;
+
+        let paths = if self.modules.is_empty() {
+            self.default_module_path(&coredump.name)
+                .into_iter()
+                .collect()
+        } else {
+            self.modules.clone()
+        };
+        let mut modules = Vec::new();
+        for path in paths {
+            modules.push(ModuleInfo::parse(&path)?);
+        }
+        let pairing = pair_modules(&coredump.modules, &modules, !self.modules.is_empty())?;
+
+        let report = Report::new(&coredump, &modules, &pairing, self.memory_bytes)?;
+        match self.format {
+            Format::Text => print!("{report}"),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the executable named in the core dump, either
+    /// relative to the current directory or to the core dump itself, if it
+    /// exists.
+    fn default_module_path(&self, name: &str) -> Option<PathBuf> {
+        let name = Path::new(name);
+        if name.is_file() {
+            return Some(name.to_path_buf());
+        }
+        let relative = self.coredump.parent()?.join(name.file_name()?);
+        relative.is_file().then_some(relative)
+    }
+}
+
+/// The contents of a core dump, as read from its sections.
+struct CoreDump<'a> {
+    name: String,
+    modules: Vec<String>,
+    instances: Vec<wasmparser::CoreDumpInstance>,
+    threads: Vec<wasmparser::CoreDumpStackSection<'a>>,
+    globals: Vec<(wasmparser::GlobalType, Value)>,
+    memories: Vec<wasmparser::MemoryType>,
+    data: Vec<(u32, u64, &'a [u8])>,
+}
+
+impl<'a> CoreDump<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<CoreDump<'a>> {
+        let mut name = None;
+        let mut ret = CoreDump {
+            name: String::new(),
+            modules: Vec::new(),
+            instances: Vec::new(),
+            threads: Vec::new(),
+            globals: Vec::new(),
+            memories: Vec::new(),
+            data: Vec::new(),
+        };
+        for payload in WasmParser::new(0).parse_all(bytes) {
+            match payload? {
+                Payload::CustomSection(s) => match s.as_known() {
+                    KnownCustom::CoreDump(s) => name = Some(s.name.to_string()),
+                    KnownCustom::CoreDumpModules(s) => {
+                        ret.modules = s.modules.iter().map(|m| m.to_string()).collect();
+                    }
+                    KnownCustom::CoreDumpInstances(s) => ret.instances = s.instances,
+                    KnownCustom::CoreDumpStack(s) => ret.threads.push(s),
+                    _ => {}
+                },
+                Payload::GlobalSection(s) => {
+                    for global in s {
+                        let global = global?;
+                        let value = const_expr_value(&global.init_expr)?;
+                        ret.globals.push((global.ty, value));
+                    }
+                }
+                Payload::MemorySection(s) => {
+                    for memory in s {
+                        ret.memories.push(memory?);
+                    }
+                }
+                Payload::DataSection(s) => {
+                    for data in s {
+                        let data = data?;
+                        let DataKind::Active {
+                            memory_index,
+                            offset_expr,
+                        } = data.kind
+                        else {
+                            continue;
+                        };
+                        let offset = match const_expr_value(&offset_expr)? {
+                            Value::I32(x) => u64::from(x as u32),
+                            Value::I64(x) => x as u64,
+                            _ => bail!("invalid data segment offset"),
+                        };
+                        ret.data.push((memory_index, offset, data.data));
+                    }
+                }
+                _ => {}
+            }
+        }
+        ret.name = match name {
+            Some(name) => name,
+            None => bail!("missing `core` custom section, this is not a core dump"),
+        };
+        Ok(ret)
+    }
+}
+
+/// A value in a core dump.
+#[derive(Copy, Clone)]
+enum Value {
+    Missing,
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    V128(u128),
+    Null,
+}
+
+impl From<&CoreDumpValue> for Value {
+    fn from(value: &CoreDumpValue) -> Value {
+        match *value {
+            CoreDumpValue::Missing => Value::Missing,
+            CoreDumpValue::I32(x) => Value::I32(x),
+            CoreDumpValue::I64(x) => Value::I64(x),
+            CoreDumpValue::F32(x) => Value::F32(x),
+            CoreDumpValue::F64(x) => Value::F64(x),
+        }
+    }
+}
+
+impl Value {
+    fn ty(&self) -> &'static str {
+        match self {
+            Value::Missing => "missing",
+            Value::I32(_) => "i32",
+            Value::I64(_) => "i64",
+            Value::F32(_) => "f32",
+            Value::F64(_) => "f64",
+            Value::V128(_) => "v128",
+            Value::Null => "ref",
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let float = |x: f64| match serde_json::Number::from_f64(x) {
+            Some(n) => serde_json::Value::Number(n),
+            None => serde_json::Value::String(x.to_string()),
+        };
+        match *self {
+            Value::Missing | Value::Null => serde_json::Value::Null,
+            Value::I32(x) => x.into(),
+            Value::I64(x) => x.into(),
+            Value::F32(x) => float(x.into()),
+            Value::F64(x) => float(x),
+            Value::V128(x) => format!("{x:#034x}").into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Missing => write!(f, "<missing>"),
+            Value::I32(x) => write!(f, "i32 {x}"),
+            Value::I64(x) => write!(f, "i64 {x}"),
+            Value::F32(x) => write!(f, "f32 {x}"),
+            Value::F64(x) => write!(f, "f64 {x}"),
+            Value::V128(x) => write!(f, "v128 {x:#034x}"),
+            Value::Null => write!(f, "ref.null"),
+        }
+    }
+}
+
+fn const_expr_value(expr: &wasmparser::ConstExpr<'_>) -> Result<Value> {
+    let mut ops = expr.get_operators_reader();
+    let value = match ops.read()? {
+        Operator::I32Const { value } => Value::I32(value),
+        Operator::I64Const { value } => Value::I64(value),
+        Operator::F32Const { value } => Value::F32(f32::from_bits(value.bits())),
+        Operator::F64Const { value } => Value::F64(f64::from_bits(value.bits())),
+        Operator::V128Const { value } => Value::V128(u128::from_le_bytes(*value.bytes())),
+        Operator::RefNull { .. } => Value::Null,
+        op => bail!("unsupported constant expression in core dump: {op:?}"),
+    };
+    Ok(value)
+}
+
+/// Information about an original Wasm module used to symbolize a core dump.
+struct ModuleInfo {
+    path: PathBuf,
+    name: Option<String>,
+    num_imported_funcs: u32,
+    code_section_offset: u64,
+    /// The offset within the module of each defined function's body.
+    bodies: Vec<u64>,
+    func_names: HashMap<u32, String>,
+    local_names: HashMap<(u32, u32), String>,
+    global_names: HashMap<u32, String>,
+    dwarf: HashMap<String, Vec<u8>>,
+}
+
+impl ModuleInfo {
+    fn parse(path: &Path) -> Result<ModuleInfo> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read Wasm module: {}", path.display()))?;
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(&bytes).map_err(|mut e| {
+            e.set_path(path);
+            e
+        })?;
+
+        let mut ret = ModuleInfo {
+            path: path.to_path_buf(),
+            name: None,
+            num_imported_funcs: 0,
+            code_section_offset: 0,
+            bodies: Vec::new(),
+            func_names: HashMap::new(),
+            local_names: HashMap::new(),
+            global_names: HashMap::new(),
+            dwarf: HashMap::new(),
+        };
+        let context = || format!("failed to parse Wasm module: {}", path.display());
+        for payload in WasmParser::new(0).parse_all(&bytes) {
+            match payload.with_context(context)? {
+                Payload::ImportSection(s) => {
+                    for import in s {
+                        if let TypeRef::Func(_) = import.with_context(context)?.ty {
+                            ret.num_imported_funcs += 1;
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => {
+                    ret.code_section_offset = range.start as u64;
+                }
+                Payload::CodeSectionEntry(body) => {
+                    ret.bodies.push(body.range().start as u64);
+                }
+                Payload::CustomSection(s) => match s.as_known() {
+                    KnownCustom::Name(names) => {
+                        // Names are only used to make output nicer so ignore
+                        // a malformed name section.
+                        let _ = ret.read_names(names);
+                    }
+                    _ if s.name().starts_with(".debug_") => {
+                        ret.dwarf.insert(s.name().to_string(), s.data().to_vec());
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+
+    fn read_names(&mut self, names: wasmparser::NameSectionReader<'_>) -> Result<()> {
+        for name in names {
+            match name? {
+                Name::Module { name, .. } => self.name = Some(name.to_string()),
+                Name::Function(names) => {
+                    for naming in names {
+                        let naming = naming?;
+                        self.func_names
+                            .insert(naming.index, naming.name.to_string());
+                    }
+                }
+                Name::Local(names) => {
+                    for func in names {
+                        let func = func?;
+                        for naming in func.names {
+                            let naming = naming?;
+                            self.local_names
+                                .insert((func.index, naming.index), naming.name.to_string());
+                        }
+                    }
+                }
+                Name::Global(names) => {
+                    for naming in names {
+                        let naming = naming?;
+                        self.global_names
+                            .insert(naming.index, naming.name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+            || self.path.file_name().and_then(|s| s.to_str()) == Some(name)
+            || self.path.file_stem().and_then(|s| s.to_str()) == Some(name)
+    }
+
+    /// Returns the offset within this module of the instruction at
+    /// `code_offset` within the function `func_index`.
+    fn module_offset(&self, func_index: u32, code_offset: u32) -> Option<u64> {
+        let defined = func_index.checked_sub(self.num_imported_funcs)?;
+        let body = self.bodies.get(usize::try_from(defined).ok()?)?;
+        Some(body + u64::from(code_offset))
+    }
+
+    fn addr2line(&self) -> Result<Option<Addr2LineContext<'_>>> {
+        if self.dwarf.is_empty() {
+            return Ok(None);
+        }
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_> {
+            let data = self.dwarf.get(id.name()).map(|d| &d[..]).unwrap_or(&[]);
+            Ok(gimli::EndianSlice::new(data, gimli::LittleEndian))
+        })?;
+        let cx = addr2line::Context::from_dwarf(dwarf).with_context(|| {
+            format!(
+                "failed to read DWARF of Wasm module: {}",
+                self.path.display()
+            )
+        })?;
+        Ok(Some(cx))
+    }
+}
+
+type Addr2LineContext<'a> = addr2line::Context<gimli::EndianSlice<'a, gimli::LittleEndian>>;
+
+/// Pairs the core dump's modules with the provided original modules,
+/// returning the index within `modules` for each of the core dump's modules.
+fn pair_modules(
+    names: &[String],
+    modules: &[ModuleInfo],
+    explicit: bool,
+) -> Result<Vec<Option<usize>>> {
+    let mut pairing = names
+        .iter()
+        .map(|name| modules.iter().position(|m| m.matches(name)))
+        .collect::<Vec<_>>();
+
+    // Core dumps of a single anonymous module are common, so if there's only
+    // one module on each side then pair them up regardless of names.
+    if names.len() == 1 && modules.len() == 1 {
+        pairing[0] = Some(0);
+    }
+
+    if explicit {
+        for (i, module) in modules.iter().enumerate() {
+            if !pairing.contains(&Some(i)) {
+                bail!(
+                    "Wasm module `{}` does not match any module in the core dump",
+                    module.path.display()
+                );
+            }
+        }
+    }
+    Ok(pairing)
+}
+
+#[derive(Serialize)]
+struct Report {
+    name: String,
+    modules: Vec<ModuleReport>,
+    instances: Vec<InstanceReport>,
+    threads: Vec<ThreadReport>,
+    globals: Vec<GlobalReport>,
+    memories: Vec<MemoryReport>,
+}
+
+#[derive(Serialize)]
+struct ModuleReport {
+    name: String,
+    path: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct InstanceReport {
+    module: u32,
+    memories: Vec<u32>,
+    globals: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct ThreadReport {
+    name: String,
+    frames: Vec<FrameReport>,
+}
+
+#[derive(Serialize)]
+struct FrameReport {
+    instance: u32,
+    module: Option<u32>,
+    func_index: u32,
+    func_name: Option<String>,
+    code_offset: u32,
+    module_offset: Option<u64>,
+    symbols: Vec<SymbolReport>,
+    locals: Vec<ValueReport>,
+    operand_stack: Vec<ValueReport>,
+}
+
+#[derive(Serialize)]
+struct SymbolReport {
+    name: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ValueReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    value: serde_json::Value,
+    #[serde(skip)]
+    display: String,
+}
+
+impl ValueReport {
+    fn new(value: Value, name: Option<String>) -> ValueReport {
+        ValueReport {
+            name,
+            ty: value.ty(),
+            value: value.to_json(),
+            display: value.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GlobalReport {
+    mutable: bool,
+    #[serde(flatten)]
+    value: ValueReport,
+    names: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MemoryReport {
+    pages: u64,
+    max_pages: Option<u64>,
+    memory64: bool,
+    shared: bool,
+    excerpts: Vec<MemoryExcerpt>,
+}
+
+#[derive(Serialize)]
+struct MemoryExcerpt {
+    offset: u64,
+    #[serde(serialize_with = "serialize_hex")]
+    data: Vec<u8>,
+}
+
+fn serialize_hex<S: serde::Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut hex = String::with_capacity(data.len() * 2);
+    for byte in data {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    serializer.serialize_str(&hex)
+}
+
+impl Report {
+    fn new(
+        coredump: &CoreDump<'_>,
+        modules: &[ModuleInfo],
+        pairing: &[Option<usize>],
+        memory_bytes: usize,
+    ) -> Result<Report> {
+        let info = |module: u32| {
+            let index = (*pairing.get(usize::try_from(module).ok()?)?)?;
+            Some(&modules[index])
+        };
+        let contexts = modules
+            .iter()
+            .map(|m| m.addr2line())
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut threads = Vec::new();
+        for thread in coredump.threads.iter() {
+            let mut frames = Vec::new();
+            for frame in thread.frames.iter() {
+                let module = coredump
+                    .instances
+                    .get(usize::try_from(frame.instanceidx)?)
+                    .map(|i| i.module_index);
+                let module_info = module.and_then(info);
+                let func_name = module_info
+                    .and_then(|m| m.func_names.get(&frame.funcidx))
+                    .cloned();
+                let module_offset =
+                    module_info.and_then(|m| m.module_offset(frame.funcidx, frame.codeoffset));
+
+                let mut symbols = Vec::new();
+                let cx = module_info.and_then(|m| {
+                    let index = modules.iter().position(|i| std::ptr::eq(i, m))?;
+                    contexts[index].as_ref().map(|cx| (m, cx))
+                });
+                if let (Some((m, cx)), Some(offset)) = (cx, module_offset) {
+                    let to_lookup = offset - m.code_section_offset;
+                    if let Ok(mut frames) = cx.find_frames(to_lookup).skip_all_loads() {
+                        while let Ok(Some(frame)) = frames.next() {
+                            symbols.push(SymbolReport {
+                                name: frame
+                                    .function
+                                    .as_ref()
+                                    .and_then(|l| l.raw_name().ok())
+                                    .map(|s| s.to_string()),
+                                file: frame
+                                    .location
+                                    .as_ref()
+                                    .and_then(|l| l.file)
+                                    .map(|s| s.to_string()),
+                                line: frame.location.as_ref().and_then(|l| l.line),
+                                column: frame.location.as_ref().and_then(|l| l.column),
+                            });
+                        }
+                    }
+                }
+
+                let local_name = |i: usize| {
+                    let i = u32::try_from(i).ok()?;
+                    module_info?.local_names.get(&(frame.funcidx, i)).cloned()
+                };
+                frames.push(FrameReport {
+                    instance: frame.instanceidx,
+                    module,
+                    func_index: frame.funcidx,
+                    func_name,
+                    code_offset: frame.codeoffset,
+                    module_offset,
+                    symbols,
+                    locals: frame
+                        .locals
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| ValueReport::new(v.into(), local_name(i)))
+                        .collect(),
+                    operand_stack: frame
+                        .stack
+                        .iter()
+                        .map(|v| ValueReport::new(v.into(), None))
+                        .collect(),
+                });
+            }
+            threads.push(ThreadReport {
+                name: thread.name.to_string(),
+                frames,
+            });
+        }
+
+        let mut global_names = HashMap::<u32, Vec<String>>::new();
+        for instance in coredump.instances.iter() {
+            let Some(module_info) = info(instance.module_index) else {
+                continue;
+            };
+            for (i, global) in instance.globals.iter().enumerate() {
+                let Some(name) = u32::try_from(i)
+                    .ok()
+                    .and_then(|i| module_info.global_names.get(&i))
+                else {
+                    continue;
+                };
+                let names = global_names.entry(*global).or_default();
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        let globals = coredump
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(i, (ty, value))| GlobalReport {
+                mutable: ty.mutable,
+                value: ValueReport::new(*value, None),
+                names: u32::try_from(i)
+                    .ok()
+                    .and_then(|i| global_names.remove(&i))
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let memories = coredump
+            .memories
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let mut remaining = memory_bytes;
+                let mut segments = coredump
+                    .data
+                    .iter()
+                    .filter(|(memory, _, _)| usize::try_from(*memory).ok() == Some(i))
+                    .collect::<Vec<_>>();
+                segments.sort_by_key(|(_, offset, _)| *offset);
+                let mut excerpts = Vec::new();
+                for (_, offset, data) in segments {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let len = data.len().min(remaining);
+                    remaining -= len;
+                    excerpts.push(MemoryExcerpt {
+                        offset: *offset,
+                        data: data[..len].to_vec(),
+                    });
+                }
+                MemoryReport {
+                    pages: ty.initial,
+                    max_pages: ty.maximum,
+                    memory64: ty.memory64,
+                    shared: ty.shared,
+                    excerpts,
+                }
+            })
+            .collect();
+
+        Ok(Report {
+            name: coredump.name.clone(),
+            modules: coredump
+                .modules
+                .iter()
+                .zip(pairing)
+                .map(|(name, index)| ModuleReport {
+                    name: name.clone(),
+                    path: index.map(|i| modules[i].path.clone()),
+                })
+                .collect(),
+            instances: coredump
+                .instances
+                .iter()
+                .map(|i| InstanceReport {
+                    module: i.module_index,
+                    memories: i.memories.clone(),
+                    globals: i.globals.clone(),
+                })
+                .collect(),
+            threads,
+            globals,
+            memories,
+        })
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "core dump of `{}`", self.name)?;
+
+        writeln!(f, "modules:")?;
+        for (i, module) in self.modules.iter().enumerate() {
+            write!(f, "  {i:>3}: {}", module.name)?;
+            match &module.path {
+                Some(path) => writeln!(f, " ({})", path.display())?,
+                None => writeln!(f, " (original module not found)")?,
+            }
+        }
+
+        writeln!(f, "instances:")?;
+        for (i, instance) in self.instances.iter().enumerate() {
+            writeln!(f, "  {i:>3}: module {}", instance.module)?;
+        }
+
+        for thread in self.threads.iter() {
+            writeln!(f, "thread `{}` backtrace:", thread.name)?;
+            for (i, frame) in thread.frames.iter().enumerate() {
+                self.write_frame(f, i, frame)?;
+            }
+        }
+
+        writeln!(f, "globals:")?;
+        for (i, global) in self.globals.iter().enumerate() {
+            write!(f, "  {i:>3}: ")?;
+            if global.mutable {
+                write!(f, "mut ")?;
+            }
+            write!(f, "{}", global.value.display)?;
+            if !global.names.is_empty() {
+                write!(f, " (${})", global.names.join(", $"))?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "memories:")?;
+        for (i, memory) in self.memories.iter().enumerate() {
+            write!(f, "  {i:>3}: {} pages", memory.pages)?;
+            if let Some(max) = memory.max_pages {
+                write!(f, " (maximum {max})")?;
+            }
+            writeln!(f)?;
+            for excerpt in memory.excerpts.iter() {
+                for (j, chunk) in excerpt.data.chunks(16).enumerate() {
+                    let address = excerpt.offset + 16 * j as u64;
+                    write!(f, "       {address:#010x}: ")?;
+                    for byte in chunk {
+                        write!(f, "{byte:02x} ")?;
+                    }
+                    for _ in chunk.len()..16 {
+                        write!(f, "   ")?;
+                    }
+                    write!(f, "|")?;
+                    for byte in chunk {
+                        let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                            char::from(*byte)
+                        } else {
+                            '.'
+                        };
+                        write!(f, "{c}")?;
+                    }
+                    writeln!(f, "|")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Report {
+    fn write_frame(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        i: usize,
+        frame: &FrameReport,
+    ) -> std::fmt::Result {
+        let module = frame
+            .module
+            .and_then(|m| self.modules.get(usize::try_from(m).ok()?))
+            .map(|m| m.name.as_str())
+            .unwrap_or("<unknown>");
+        write!(f, "  {i:>3}: ")?;
+        if let Some(offset) = frame.module_offset {
+            write!(f, "{offset:#6x} - ")?;
+        }
+        let write_raw_func_name = |f: &mut std::fmt::Formatter<'_>| {
+            demangle_function_name_or_index(
+                f,
+                frame.func_name.as_deref(),
+                frame.func_index as usize,
+            )
+        };
+        if frame.symbols.is_empty() {
+            write!(f, "{module}!")?;
+            write_raw_func_name(f)?;
+        } else {
+            for (i, symbol) in frame.symbols.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "              - ")?;
+                }
+                match &symbol.name {
+                    Some(name) => demangle_function_name(f, name)?,
+                    None if i == 0 => write_raw_func_name(f)?,
+                    None => write!(f, "<inlined function>")?,
+                }
+                if let Some(file) = &symbol.file {
+                    writeln!(f)?;
+                    write!(f, "                    at {file}")?;
+                    if let Some(line) = symbol.line {
+                        write!(f, ":{line}")?;
+                        if let Some(col) = symbol.column {
+                            write!(f, ":{col}")?;
+                        }
+                    }
+                }
+                if i + 1 < frame.symbols.len() {
+                    writeln!(f)?;
+                }
+            }
+        }
+        writeln!(f)?;
+
+        if !frame.locals.is_empty() {
+            writeln!(f, "         locals:")?;
+            for (i, local) in frame.locals.iter().enumerate() {
+                match &local.name {
+                    Some(name) => writeln!(f, "           ${name}: {}", local.display)?,
+                    None => writeln!(f, "           {i}: {}", local.display)?,
+                }
+            }
+        }
+        if !frame.operand_stack.is_empty() {
+            writeln!(f, "         operand stack:")?;
+            for value in frame.operand_stack.iter() {
+                writeln!(f, "           {}", value.display)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -10,6 +10,8 @@ use crate::common::{Profile, RunCommon, RunTarget};
 use anyhow::{anyhow, bail, Context as _, Error, Result};
 use clap::Parser;
 use std::ffi::OsString;
+use std::fs::File;
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -44,6 +46,14 @@ pub struct RunCommand {
     pub run: RunCommon,
 
     /// The name of the function to run
+    ///
+    /// For core wasm modules this is the name of an exported function whose
+    /// arguments are taken from the arguments after the module.
+    ///
+    /// For components this is a function call such as `add(1, 2)` or
+    /// `iface#func("a", [1, 2])` where `iface#` names an exported instance
+    /// and the arguments are written in the WebAssembly Value Encoding
+    /// (WAVE) syntax. Results are printed in the same syntax.
     #[arg(long, value_name = "FUNCTION")]
     pub invoke: Option<String>,
 
@@ -65,6 +75,20 @@ pub struct RunCommand {
     #[arg(long)]
     pub argv0: Option<String>,
 
+    /// Record the results of WASI calls which aren't determined by the guest,
+    /// such as reading clocks, random numbers, and stdin, to the given trace
+    /// file.
+    ///
+    /// Running the same module with `--replay` and the trace reproduces the
+    /// recorded execution.
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay the results of WASI calls from a trace file written with
+    /// `--record`, instead of taking them from the host.
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+
     /// The WebAssembly module to run and arguments to pass to it.
     ///
     /// Arguments passed to the wasm module will be configured as WASI CLI
@@ -436,29 +460,33 @@ impl RunCommand {
             }
             #[cfg(feature = "component-model")]
             CliLinker::Component(linker) => {
-                if self.invoke.is_some() {
-                    bail!("using `--invoke` with components is not supported");
-                }
-
                 let component = module.unwrap_component();
 
-                let command = wasmtime_wasi::bindings::sync::Command::instantiate(
-                    &mut *store,
-                    component,
-                    linker,
-                )?;
-                let result = command
-                    .wasi_cli_run()
-                    .call_run(&mut *store)
-                    .context("failed to invoke `run` function")
-                    .map_err(|e| self.handle_core_dump(&mut *store, e));
-
-                // Translate the `Result<(),()>` produced by wasm into a feigned
-                // explicit exit here with status 1 if `Err(())` is returned.
-                result.and_then(|wasm_result| match wasm_result {
-                    Ok(()) => Ok(()),
-                    Err(()) => Err(wasmtime_wasi::I32Exit(1).into()),
-                })
+                // If a specific function was requested then look it up and
+                // call it with the arguments provided, otherwise run the
+                // component as a `wasi:cli/command`.
+                if let Some(invoke) = &self.invoke {
+                    self.invoke_component(store, linker, component, invoke)
+                } else {
+                    let command = wasmtime_wasi::bindings::sync::Command::instantiate(
+                        &mut *store,
+                        component,
+                        linker,
+                    )?;
+                    let result = command
+                        .wasi_cli_run()
+                        .call_run(&mut *store)
+                        .context("failed to invoke `run` function")
+                        .map_err(|e| self.handle_core_dump(&mut *store, e));
+
+                    // Translate the `Result<(),()>` produced by wasm into a
+                    // feigned explicit exit here with status 1 if `Err(())` is
+                    // returned.
+                    result.and_then(|wasm_result| match wasm_result {
+                        Ok(()) => Ok(()),
+                        Err(()) => Err(wasmtime_wasi::I32Exit(1).into()),
+                    })
+                }
             }
         };
         finish_epoch_handler(store);
@@ -545,6 +573,73 @@ impl RunCommand {
         Ok(())
     }
 
+    #[cfg(feature = "component-model")]
+    fn invoke_component(
+        &self,
+        store: &mut Store<Host>,
+        linker: &mut wasmtime::component::Linker<Host>,
+        component: &wasmtime::component::Component,
+        invoke: &str,
+    ) -> Result<()> {
+        use wasmtime::component::{types::ComponentItem, Val};
+
+        if self.module_and_args.len() > 1 {
+            bail!(
+                "arguments to component functions must be passed within \
+                 `--invoke`, for example `--invoke 'foo(1, \"a\")'`"
+            );
+        }
+
+        // Functions are named either as `func` for a function exported
+        // directly from the component or as `iface#func` for a function
+        // exported from one of the component's exported instances.
+        let (name, args) = crate::wave::parse_call(invoke)?;
+        let (instance, func_name) = match name.rsplit_once('#') {
+            Some((instance, func_name)) => {
+                let (ty, index) = component
+                    .export_index(None, instance)
+                    .ok_or_else(|| anyhow!("no export named `{instance}` found"))?;
+                if !matches!(ty, ComponentItem::ComponentInstance(_)) {
+                    bail!("export `{instance}` is not an instance");
+                }
+                (Some(index), func_name)
+            }
+            None => (None, name),
+        };
+        let (ty, index) = component
+            .export_index(instance.as_ref(), func_name)
+            .ok_or_else(|| anyhow!("no func export named `{name}` found"))?;
+        if !matches!(ty, ComponentItem::ComponentFunc(_)) {
+            bail!("export `{name}` is not a function");
+        }
+
+        let instance = linker.instantiate(&mut *store, component).context(format!(
+            "failed to instantiate {:?}",
+            self.module_and_args[0]
+        ))?;
+        let func = instance
+            .get_func(&mut *store, &index)
+            .ok_or_else(|| anyhow!("no func export named `{name}` found"))?;
+
+        let params = func.params(&*store);
+        let values = crate::wave::parse_args(args, &params)
+            .with_context(|| format!("failed to parse arguments to `{name}`"))?;
+        let mut results = vec![Val::Bool(false); func.results(&*store).len()];
+        let invoke_res = func
+            .call(&mut *store, &values, &mut results)
+            .with_context(|| format!("failed to invoke `{name}`"));
+        if let Err(err) = invoke_res {
+            return Err(self.handle_core_dump(&mut *store, err));
+        }
+        func.post_return(&mut *store)?;
+
+        for result in results.iter() {
+            println!("{}", crate::wave::to_string(result));
+        }
+
+        Ok(())
+    }
+
     #[cfg(feature = "coredump")]
     fn handle_core_dump(&self, store: &mut Store<Host>, err: Error) -> Error {
         let coredump_path = match &self.run.common.debug.coredump {
@@ -748,7 +843,17 @@ impl RunCommand {
                     }
                 }
 
-                store.data_mut().wasi_http = Some(Arc::new(WasiHttpCtx::new()));
+                let mut ctx = WasiHttpCtx::new();
+                if let Some(tls_config) = self.run.wasi_http_tls_config()? {
+                    ctx.set_tls_config(tls_config);
+                }
+                if let Some(policy) = self.run.wasi_http_outgoing_policy()? {
+                    ctx.set_outgoing_policy(policy);
+                }
+                if let Some(pool) = self.run.wasi_http_connection_pool(false) {
+                    ctx.set_connection_pool(pool);
+                }
+                store.data_mut().wasi_http = Some(Arc::new(ctx));
             }
         }
 
@@ -756,6 +861,13 @@ impl RunCommand {
     }
 
     fn set_preview1_ctx(&self, store: &mut Store<Host>) -> Result<()> {
+        if self.record.is_some() || self.replay.is_some() {
+            bail!(
+                "recording and replaying WASI calls is not supported with the \
+                 legacy WASI implementation, use `-Spreview2` instead"
+            );
+        }
+
         let mut builder = WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?)?;
 
@@ -792,10 +904,16 @@ impl RunCommand {
             num_fd += 1;
         }
 
-        for (host, guest) in self.run.dirs.iter() {
-            let dir = Dir::open_ambient_dir(host, ambient_authority())
-                .with_context(|| format!("failed to open directory '{}'", host))?;
-            builder.preopened_dir(dir, guest)?;
+        for dir in self.run.dirs.iter() {
+            if dir.overlay {
+                bail!(
+                    "overlay directories are not supported with the legacy WASI \
+                     implementation, use `-Spreview2` instead"
+                );
+            }
+            let host = Dir::open_ambient_dir(&dir.host, ambient_authority())
+                .with_context(|| format!("failed to open directory '{}'", dir.host))?;
+            builder.preopened_dir(host, &dir.guest)?;
         }
 
         store.data_mut().preview1_ctx = Some(builder.build());
@@ -806,6 +924,18 @@ impl RunCommand {
         let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?);
         self.run.configure_wasip2(&mut builder)?;
+        if let Some(path) = &self.record {
+            let trace = File::create(path)
+                .with_context(|| format!("failed to create trace file '{}'", path.display()))?;
+            builder.record(BufWriter::new(trace))?;
+        }
+        if let Some(path) = &self.replay {
+            let trace = File::open(path)
+                .with_context(|| format!("failed to open trace file '{}'", path.display()))?;
+            builder
+                .replay(trace)
+                .with_context(|| format!("failed to read trace file '{}'", path.display()))?;
+        }
         let ctx = builder.build_p1();
         store.data_mut().preview2_ctx = Some(Arc::new(Mutex::new(ctx)));
         Ok(())
@@ -1,20 +1,28 @@
 use crate::common::{Profile, RunCommon, RunTarget};
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Parser;
+use http_body_util::BodyExt;
+use serde_derive::Deserialize;
 use std::net::SocketAddr;
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
+    pin::Pin,
     sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
+    task::Poll,
 };
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use wasmtime::component::Linker;
-use wasmtime::{Config, Engine, Memory, MemoryType, Store, StoreLimits};
+use wasmtime::{Config, Engine, Memory, MemoryType, ResourceLimiter, Store, StoreLimits, Trap};
 use wasmtime_wasi::{StreamError, StreamResult, WasiCtx, WasiCtxBuilder, WasiView};
 use wasmtime_wasi_http::bindings::http::types::Scheme;
 use wasmtime_wasi_http::bindings::ProxyPre;
-use wasmtime_wasi_http::io::TokioIo;
+use wasmtime_wasi_http::io::{TokioExecutor, TokioIo};
+use wasmtime_wasi_http::policy::OutgoingRequestPolicy;
+use wasmtime_wasi_http::pool::ConnectionPool;
+use wasmtime_wasi_http::tls::{TlsAcceptor, TlsConfig};
 use wasmtime_wasi_http::{body::HyperOutgoingBody, WasiHttpCtx, WasiHttpView};
 
 #[cfg(feature = "wasi-nn")]
@@ -27,7 +35,7 @@ struct Host {
     ctx: WasiCtx,
     http: WasiHttpCtx,
 
-    limits: StoreLimits,
+    limits: RequestLimiter,
 
     #[cfg(feature = "wasi-nn")]
     nn: Option<WasiNnCtx>,
@@ -71,11 +79,72 @@ pub struct ServeCommand {
     #[arg(long = "addr", value_name = "SOCKADDR", default_value_t = DEFAULT_ADDR )]
     addr: SocketAddr,
 
+    /// A TOML file describing the resource limits applied to each request.
+    ///
+    /// The file may contain the keys `max-memory-size`, `max-table-elements`,
+    /// `max-instances`, `fuel`, `max-concurrent-instances`, and
+    /// `max-queued-requests`, which behave the same as the equivalent `-W`
+    /// options and flags of this command. Limits given on the command line
+    /// take precedence over those in this file.
+    #[arg(long = "limits", value_name = "FILE")]
+    limits: Option<PathBuf>,
+
+    /// The maximum number of instances that may be handling requests at once.
+    ///
+    /// Requests that arrive while this many instances are running wait until
+    /// one of the instances finishes. By default the number of instances is
+    /// only limited by the instance allocator.
+    #[arg(long = "max-concurrent-instances", value_name = "N")]
+    max_concurrent_instances: Option<usize>,
+
+    /// The maximum number of requests that may be waiting for an instance when
+    /// `--max-concurrent-instances` is reached.
+    ///
+    /// Requests that arrive while the queue is full are rejected with a
+    /// `503 Service Unavailable` response. By default the queue is unbounded.
+    #[arg(long = "max-queued-requests", value_name = "N")]
+    max_queued_requests: Option<usize>,
+
+    /// A PEM file containing the certificate chain to serve HTTPS with.
+    ///
+    /// When given, along with `--tls-key`, connections are served over TLS
+    /// and requests are passed to the component with the `https` scheme. On
+    /// Unix the certificate and key are read again from their files when the
+    /// server receives `SIGHUP`, so that they can be renewed without a
+    /// restart.
+    #[arg(long = "tls-cert", value_name = "FILE", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// A PEM file containing the private key of `--tls-cert`.
+    #[arg(long = "tls-key", value_name = "FILE", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
     /// The WebAssembly component to run.
     #[arg(value_name = "WASM", required = true)]
     component: PathBuf,
 }
 
+/// The contents of the file passed to `wasmtime serve --limits`.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct LimitsFile {
+    max_memory_size: Option<usize>,
+    max_table_elements: Option<u32>,
+    max_instances: Option<usize>,
+    fuel: Option<u64>,
+    max_concurrent_instances: Option<usize>,
+    max_queued_requests: Option<usize>,
+}
+
+impl LimitsFile {
+    fn load(path: &Path) -> Result<LimitsFile> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read limits file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("failed to parse limits file {}", path.display()))
+    }
+}
+
 impl ServeCommand {
     /// Start a server to run the given wasi-http proxy component
     pub fn execute(mut self) -> Result<()> {
@@ -107,6 +176,11 @@ impl ServeCommand {
             bail!("components are required for the serve command, and must not be disabled");
         }
 
+        self.apply_limits_file()?;
+        if self.max_concurrent_instances == Some(0) {
+            bail!("`max-concurrent-instances` must be at least 1");
+        }
+
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_time()
             .enable_io()
@@ -127,7 +201,57 @@ impl ServeCommand {
         Ok(())
     }
 
-    fn new_store(&self, engine: &Engine, req_id: u64) -> Result<Store<Host>> {
+    /// Read the certificate chain and private key given with `--tls-cert` and
+    /// `--tls-key`.
+    fn read_tls_files(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let (cert, key) = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Ok(None),
+        };
+        let cert = std::fs::read(cert)
+            .with_context(|| format!("failed to read TLS certificate {}", cert.display()))?;
+        let key = std::fs::read(key)
+            .with_context(|| format!("failed to read TLS key {}", key.display()))?;
+        Ok(Some((cert, key)))
+    }
+
+    /// Create the acceptor that terminates TLS for incoming connections, if
+    /// `--tls-cert` and `--tls-key` were given.
+    fn tls_acceptor(&self) -> Result<Option<TlsAcceptor>> {
+        match self.read_tls_files()? {
+            Some((cert, key)) => Ok(Some(
+                TlsAcceptor::new(&cert, &key).context("failed to load TLS certificate")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Fill in any per-request limits that weren't given on the command line
+    /// from the `--limits` file, if any.
+    fn apply_limits_file(&mut self) -> Result<()> {
+        let file = match &self.limits {
+            Some(path) => LimitsFile::load(path)?,
+            None => return Ok(()),
+        };
+
+        let wasm = &mut self.run.common.wasm;
+        wasm.max_memory_size = wasm.max_memory_size.or(file.max_memory_size);
+        wasm.max_table_elements = wasm.max_table_elements.or(file.max_table_elements);
+        wasm.max_instances = wasm.max_instances.or(file.max_instances);
+        wasm.fuel = wasm.fuel.or(file.fuel);
+        self.max_concurrent_instances = self
+            .max_concurrent_instances
+            .or(file.max_concurrent_instances);
+        self.max_queued_requests = self.max_queued_requests.or(file.max_queued_requests);
+        Ok(())
+    }
+
+    fn new_store(
+        &self,
+        engine: &Engine,
+        req_id: u64,
+        limit_exceeded: Arc<AtomicBool>,
+    ) -> Result<Store<Host>> {
         let mut builder = WasiCtxBuilder::new();
         self.run.configure_wasip2(&mut builder)?;
 
@@ -148,7 +272,10 @@ impl ServeCommand {
             ctx: builder.build(),
             http: WasiHttpCtx::new(),
 
-            limits: StoreLimits::default(),
+            limits: RequestLimiter {
+                limits: self.run.store_limits(),
+                exceeded: limit_exceeded,
+            },
 
             #[cfg(feature = "wasi-nn")]
             nn: None,
@@ -193,7 +320,6 @@ impl ServeCommand {
             store.set_epoch_deadline(u64::from(EPOCH_PRECISION) + 1);
         }
 
-        store.data_mut().limits = self.run.store_limits();
         store.limiter(|t| &mut t.limits);
 
         // If fuel has been configured, we want to add the configured
@@ -275,8 +401,6 @@ impl ServeCommand {
     }
 
     async fn serve(mut self) -> Result<()> {
-        use hyper::server::conn::http1;
-
         let mut config = self
             .run
             .common
@@ -311,6 +435,10 @@ impl ServeCommand {
 
         let instance = linker.instantiate_pre(&component)?;
         let instance = ProxyPre::new(instance)?;
+        let tls_config = self.run.wasi_http_tls_config()?;
+        let tls_acceptor = self.tls_acceptor()?;
+        let outgoing_policy = self.run.wasi_http_outgoing_policy()?;
+        let connection_pool = self.run.wasi_http_connection_pool(true);
 
         let socket = match &self.addr {
             SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
@@ -328,7 +456,10 @@ impl ServeCommand {
         socket.bind(self.addr)?;
         let listener = socket.listen(100)?;
 
-        eprintln!("Serving HTTP on http://{}/", listener.local_addr()?);
+        match &tls_acceptor {
+            Some(_) => eprintln!("Serving HTTPS on https://{}/", listener.local_addr()?),
+            None => eprintln!("Serving HTTP on http://{}/", listener.local_addr()?),
+        }
 
         let _epoch_thread = if let Some(timeout) = self.run.common.wasm.timeout {
             Some(EpochThread::spawn(
@@ -341,21 +472,52 @@ impl ServeCommand {
 
         log::info!("Listening on {}", self.addr);
 
-        let handler = ProxyHandler::new(self, engine, instance);
+        let handler = ProxyHandler::new(
+            self,
+            engine,
+            instance,
+            tls_config,
+            outgoing_policy,
+            connection_pool,
+        );
+
+        #[cfg(unix)]
+        if let Some(acceptor) = &tls_acceptor {
+            handler.reload_tls_on_sighup(acceptor.clone())?;
+        }
 
         loop {
             let (stream, _) = listener.accept().await?;
-            let stream = TokioIo::new(stream);
             let h = handler.clone();
-            tokio::task::spawn(async {
-                if let Err(e) = http1::Builder::new()
-                    .keep_alive(true)
-                    .serve_connection(
-                        stream,
-                        hyper::service::service_fn(move |req| handle_request(h.clone(), req)),
-                    )
-                    .await
-                {
+            let tls_acceptor = tls_acceptor.clone();
+            tokio::task::spawn(async move {
+                let result = match tls_acceptor {
+                    Some(acceptor) => {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                        {
+                            Ok(Ok(stream)) => {
+                                let http2 = stream.is_http2();
+                                serve_connection(h, stream, http2).await
+                            }
+                            Ok(Err(e)) => {
+                                log::warn!("TLS handshake failed: {e:?}");
+                                return;
+                            }
+                            Err(_) => {
+                                log::warn!("timed out waiting for the TLS handshake");
+                                return;
+                            }
+                        }
+                    }
+                    None => match read_http2_prior_knowledge(stream).await {
+                        Ok((stream, http2)) => serve_connection(h, stream, http2).await,
+                        Err(e) => {
+                            log::warn!("failed to read connection preface: {e:?}");
+                            return;
+                        }
+                    },
+                };
+                if let Err(e) = result {
                     eprintln!("error: {e:?}");
                 }
             });
@@ -363,6 +525,140 @@ impl ServeCommand {
     }
 }
 
+/// Serve the requests of a newly-accepted connection, with HTTP/2 if `http2` is
+/// set and HTTP/1.1 otherwise.
+async fn serve_connection<IO>(handler: ProxyHandler, io: IO, http2: bool) -> hyper::Result<()>
+where
+    IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use hyper::server::conn::{http1, http2};
+
+    let service = hyper::service::service_fn(move |req| handle_request(handler.clone(), req));
+    if http2 {
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(TokioIo::new(io), service)
+            .await
+    } else {
+        http1::Builder::new()
+            .keep_alive(true)
+            .serve_connection(TokioIo::new(io), service)
+            .await
+    }
+}
+
+/// How long a client may take to complete the TLS handshake or to send the
+/// start of a connection before it's
+/// closed.
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Determine whether a client has started an HTTP/2 connection without first
+/// negotiating it, as gRPC clients do over cleartext, by sending the HTTP/2
+/// connection preface.
+///
+/// This reads as much of the preface as the client sends, giving up after
+/// [`HANDSHAKE_TIMEOUT`]. The bytes that were read are replayed by the
+/// returned stream, so that they're still seen by whichever protocol ends up
+/// serving the connection.
+async fn read_http2_prior_knowledge(
+    mut stream: tokio::net::TcpStream,
+) -> std::io::Result<(Rewind<tokio::net::TcpStream>, bool)> {
+    use tokio::io::AsyncReadExt;
+
+    const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+    let mut buf = Vec::with_capacity(PREFACE.len());
+    let read = async {
+        while buf.len() < PREFACE.len() {
+            let mut chunk = [0; PREFACE.len()];
+            let n = stream.read(&mut chunk[..PREFACE.len() - buf.len()]).await?;
+            buf.extend_from_slice(&chunk[..n]);
+            if n == 0 || !PREFACE.starts_with(&buf) {
+                return Ok(false);
+            }
+        }
+        Ok::<_, std::io::Error>(true)
+    };
+    let http2 = tokio::time::timeout(HANDSHAKE_TIMEOUT, read)
+        .await
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "timed out reading connection preface",
+            )
+        })??;
+    Ok((Rewind::new(buf, stream), http2))
+}
+
+/// A stream which replays bytes that were already read from `inner` before
+/// reading from it again.
+struct Rewind<IO> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: IO,
+}
+
+impl<IO> Rewind<IO> {
+    fn new(prefix: Vec<u8>, inner: IO) -> Self {
+        Rewind {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+}
+
+impl<IO: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for Rewind<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        if this.pos < this.prefix.len() {
+            let n = buf.remaining().min(this.prefix.len() - this.pos);
+            buf.put_slice(&this.prefix[this.pos..][..n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<IO: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for Rewind<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
 /// This is the number of epochs that we will observe before expiring a request handler. As
 /// instances may be started at any point within an epoch, and epochs are counted globally per
 /// engine, we expire after `EPOCH_PRECISION + 1` epochs have been observed. This gives a maximum
@@ -401,31 +697,179 @@ impl Drop for EpochThread {
     }
 }
 
+/// A `ResourceLimiter` which enforces a request's `StoreLimits` and records
+/// whether any of them were exceeded.
+struct RequestLimiter {
+    limits: StoreLimits,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl RequestLimiter {
+    fn record<T>(&self, result: Result<T>, allowed: impl FnOnce(&T) -> bool) -> Result<T> {
+        if !result.as_ref().map_or(false, allowed) {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl ResourceLimiter for RequestLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        let result = self.limits.memory_growing(current, desired, maximum);
+        self.record(result, |allow| *allow)
+    }
+
+    fn memory_grow_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        let result = self.limits.memory_grow_failed(error);
+        self.record(result, |_| true)
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> Result<bool> {
+        let result = self.limits.table_growing(current, desired, maximum);
+        self.record(result, |allow| *allow)
+    }
+
+    fn table_grow_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        let result = self.limits.table_grow_failed(error);
+        self.record(result, |_| true)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
+
 struct ProxyHandlerInner {
     cmd: ServeCommand,
     engine: Engine,
     instance_pre: ProxyPre<Host>,
     next_id: AtomicU64,
+    /// Permits for running an instance, one of which is held by each request
+    /// for as long as its instance is alive.
+    instance_permits: Arc<Semaphore>,
+    /// The number of requests waiting for an instance permit.
+    queued_requests: AtomicUsize,
+    /// Connections for outgoing requests, if pooling them is enabled, shared
+    /// between all requests so that they can be reused across instances.
+    connection_pool: Option<ConnectionPool>,
+    /// The TLS configuration for outgoing requests, if any.
+    tls_config: Option<TlsConfig>,
+    /// The policy for outgoing requests, if any, shared between all requests
+    /// so that its rate limit applies to them in total.
+    outgoing_policy: Option<OutgoingRequestPolicy>,
 }
 
 impl ProxyHandlerInner {
     fn next_req_id(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::Relaxed)
     }
+
+    /// Wait for permission to create an instance for a request.
+    ///
+    /// Returns `None` if the request should be rejected because too many
+    /// requests are already waiting.
+    async fn acquire_instance_permit(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.instance_permits.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+
+        let max_queued = self.cmd.max_queued_requests.unwrap_or(usize::MAX);
+        let queued = QueuedRequest::new(&self.queued_requests);
+        if queued.position >= max_queued {
+            return None;
+        }
+
+        // The semaphore is never closed, so this can't fail.
+        self.instance_permits.clone().acquire_owned().await.ok()
+    }
+}
+
+/// A request waiting for an instance permit, which leaves the queue when
+/// dropped, including when the connection is closed while waiting.
+struct QueuedRequest<'a> {
+    queued_requests: &'a AtomicUsize,
+    position: usize,
+}
+
+impl<'a> QueuedRequest<'a> {
+    fn new(queued_requests: &'a AtomicUsize) -> Self {
+        let position = queued_requests.fetch_add(1, Ordering::Relaxed);
+        QueuedRequest {
+            queued_requests,
+            position,
+        }
+    }
+}
+
+impl Drop for QueuedRequest<'_> {
+    fn drop(&mut self) {
+        self.queued_requests.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 #[derive(Clone)]
 struct ProxyHandler(Arc<ProxyHandlerInner>);
 
 impl ProxyHandler {
-    fn new(cmd: ServeCommand, engine: Engine, instance_pre: ProxyPre<Host>) -> Self {
+    fn new(
+        cmd: ServeCommand,
+        engine: Engine,
+        instance_pre: ProxyPre<Host>,
+        tls_config: Option<TlsConfig>,
+        outgoing_policy: Option<OutgoingRequestPolicy>,
+        connection_pool: Option<ConnectionPool>,
+    ) -> Self {
+        let permits = cmd
+            .max_concurrent_instances
+            .unwrap_or(Semaphore::MAX_PERMITS);
         Self(Arc::new(ProxyHandlerInner {
             cmd,
             engine,
             instance_pre,
             next_id: AtomicU64::from(0),
+            instance_permits: Arc::new(Semaphore::new(permits)),
+            queued_requests: AtomicUsize::new(0),
+            connection_pool,
+            tls_config,
+            outgoing_policy,
         }))
     }
+
+    /// Read the TLS certificate and key again whenever the server receives
+    /// `SIGHUP`, keeping the current ones if they can't be loaded.
+    #[cfg(unix)]
+    fn reload_tls_on_sighup(&self, acceptor: TlsAcceptor) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = signal(SignalKind::hangup())?;
+        let inner = self.0.clone();
+        tokio::task::spawn(async move {
+            while hangups.recv().await.is_some() {
+                let result = inner.cmd.read_tls_files().and_then(|files| {
+                    let (cert, key) = files.expect("TLS is enabled");
+                    acceptor.set_certificate_pem(&cert, &key)
+                });
+                match result {
+                    Ok(()) => eprintln!("Reloaded TLS certificate"),
+                    Err(e) => eprintln!("error: failed to reload TLS certificate: {e:?}"),
+                }
+            }
+        });
+        Ok(())
+    }
 }
 
 type Request = hyper::Request<hyper::body::Incoming>;
@@ -444,13 +888,53 @@ async fn handle_request(
         req.uri()
     );
 
-    let mut store = inner.cmd.new_store(&inner.engine, req_id)?;
+    let permit = match inner.acquire_instance_permit().await {
+        Some(permit) => permit,
+        None => {
+            log::warn!("[{req_id}] :: rejected: too many queued requests");
+            return Ok(service_unavailable());
+        }
+    };
+
+    let limit_exceeded = Arc::new(AtomicBool::new(false));
+    let mut store = inner
+        .cmd
+        .new_store(&inner.engine, req_id, limit_exceeded.clone())?;
+    if let Some(pool) = &inner.connection_pool {
+        store.data_mut().http.set_connection_pool(pool.clone());
+    }
+    if let Some(tls_config) = &inner.tls_config {
+        store.data_mut().http.set_tls_config(tls_config.clone());
+    }
+    if let Some(policy) = &inner.outgoing_policy {
+        store.data_mut().http.set_outgoing_policy(policy.clone());
+    }
 
-    let req = store.data_mut().new_incoming_request(Scheme::Http, req)?;
+    let scheme = if inner.cmd.tls_cert.is_some() {
+        Scheme::Https
+    } else {
+        Scheme::Http
+    };
+    let req = store.data_mut().new_incoming_request(scheme, req)?;
     let out = store.data_mut().new_response_outparam(sender)?;
-    let proxy = inner.instance_pre.instantiate_async(&mut store).await?;
+
+    // If instantiation exhausted a limit, either one of this request's limits
+    // or one of the pooling allocator's, let the client know that they may try
+    // again later. Any other failure is an error in the component.
+    let proxy = match inner.instance_pre.instantiate_async(&mut store).await {
+        Ok(proxy) => proxy,
+        Err(e) if limit_exceeded.load(Ordering::Relaxed) || is_pool_exhausted(&e) => {
+            log::warn!("[{req_id}] :: instantiation exceeded a limit: {e:?}");
+            return Ok(service_unavailable());
+        }
+        Err(e) => return Err(e),
+    };
 
     let task = tokio::task::spawn(async move {
+        // Hold on to the permit until the instance is done, which may be after
+        // the response has been sent if the guest is streaming the body.
+        let _permit = permit;
+
         if let Err(e) = proxy
             .wasi_http_incoming_handler()
             .call_handle(store, req, out)
@@ -478,11 +962,42 @@ async fn handle_request(
                 Ok(r) => r.expect_err("if the receiver has an error, the task must have failed"),
                 Err(e) => e.into(),
             };
+            if limit_exceeded.load(Ordering::Relaxed)
+                || e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel)
+            {
+                log::warn!("[{req_id}] :: exceeded its limits: {e:?}");
+                return Ok(service_unavailable());
+            }
             bail!("guest never invoked `response-outparam::set` method: {e:?}")
         }
     }
 }
 
+/// Whether `error` is due to the pooling allocator having no more slots for
+/// the instance.
+fn is_pool_exhausted(error: &anyhow::Error) -> bool {
+    #[cfg(feature = "pooling-allocator")]
+    return error
+        .downcast_ref::<wasmtime::PoolConcurrencyLimitError>()
+        .is_some();
+    #[cfg(not(feature = "pooling-allocator"))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// The response sent when a request can't be handled because of a resource
+/// limit.
+fn service_unavailable() -> hyper::Response<HyperOutgoingBody> {
+    let body = http_body_util::Empty::new()
+        .map_err(|never| match never {})
+        .boxed();
+    let mut response = hyper::Response::new(body);
+    *response.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+    response
+}
+
 #[derive(Clone)]
 enum Output {
     Stdout,
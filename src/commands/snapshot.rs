@@ -0,0 +1,88 @@
+//! The module that implements the `wasmtime snapshot` command.
+
+use crate::common::RunCommon;
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use std::path::PathBuf;
+use wasmtime::{Engine, InstanceSnapshot, Linker, Module, Store};
+use wasmtime_wasi::preview1::WasiP1Ctx;
+
+/// Pre-initializes a WebAssembly module by running its initialization function
+/// and snapshotting the resulting state into a new module.
+#[derive(Parser, PartialEq)]
+pub struct SnapshotCommand {
+    #[command(flatten)]
+    run: RunCommon,
+
+    /// The name of the exported function that initializes the module
+    #[arg(long, value_name = "FUNCTION", default_value = "_initialize")]
+    init_func: String,
+
+    /// Keep exporting the initialization function from the pre-initialized
+    /// module
+    #[arg(long)]
+    keep_init_func: bool,
+
+    /// The path of the pre-initialized module (derived from the MODULE name if
+    /// none provided)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// The path of the WebAssembly module to pre-initialize
+    #[arg(required = true, value_name = "MODULE")]
+    module: PathBuf,
+}
+
+impl SnapshotCommand {
+    /// Executes the command.
+    pub fn execute(mut self) -> Result<()> {
+        self.run.common.init_logging()?;
+
+        let config = self.run.common.config(None, None)?;
+        let engine = Engine::new(&config)?;
+
+        let bytes = std::fs::read(&self.module)
+            .with_context(|| format!("failed to read Wasm module: {}", self.module.display()))?;
+        let module = Module::new(&engine, &bytes)
+            .with_context(|| format!("failed to compile {}", self.module.display()))?;
+
+        // The initialization function may use WASI, for example to read
+        // configuration files from preopened directories.
+        let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
+        builder
+            .inherit_stdio()
+            .args(&[self.module.display().to_string()]);
+        self.run.configure_wasip2(&mut builder)?;
+        let mut store = Store::new(&engine, builder.build_p1());
+        let mut linker = Linker::<WasiP1Ctx>::new(&engine);
+        wasmtime_wasi::preview1::add_to_linker_sync(&mut linker, |ctx| ctx)?;
+        if self.run.common.wasm.unknown_imports_trap == Some(true) {
+            linker.define_unknown_imports_as_traps(&module)?;
+        }
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .with_context(|| format!("failed to instantiate {}", self.module.display()))?;
+        let init = instance
+            .get_func(&mut store, &self.init_func)
+            .ok_or_else(|| anyhow!("no func export named `{}` found", self.init_func))?;
+        init.typed::<(), ()>(&store)?
+            .call(&mut store, ())
+            .with_context(|| format!("failed to invoke `{}`", self.init_func))?;
+
+        let mut snapshot = InstanceSnapshot::new(&mut store, &instance)?;
+        if !self.keep_init_func {
+            snapshot.strip_export(&self.init_func);
+        }
+        let wasm = snapshot.rewrite(&bytes)?;
+
+        let output = self
+            .output
+            .take()
+            .unwrap_or_else(|| self.module.with_extension("snapshot.wasm"));
+        std::fs::write(&output, wasm)
+            .with_context(|| format!("failed to write file: {}", output.display()))?;
+
+        Ok(())
+    }
+}
@@ -79,8 +79,17 @@ pub struct RunCommon {
     /// host is made available within the guest. If specified as `HOST::GUEST`
     /// then the `HOST` directory is opened and made available as the name
     /// `GUEST` in the guest.
-    #[arg(long = "dir", value_name = "HOST_DIR[::GUEST_DIR]", value_parser = parse_dirs)]
-    pub dirs: Vec<(String, String)>,
+    ///
+    /// If specified as `HOST::GUEST:overlay` then the guest sees a
+    /// copy-on-write view of `HOST`: writes, creations, deletions, and renames
+    /// are kept in memory and discarded when the guest exits, leaving `HOST`
+    /// unmodified.
+    #[arg(
+        long = "dir",
+        value_name = "HOST_DIR[::GUEST_DIR[:overlay]]",
+        value_parser = parse_dirs
+    )]
+    pub dirs: Vec<PreopenDir>,
 
     /// Pass an environment variable to the program.
     ///
@@ -101,14 +110,31 @@ fn parse_env_var(s: &str) -> Result<(String, Option<String>)> {
     ))
 }
 
-fn parse_dirs(s: &str) -> Result<(String, String)> {
+/// A directory made available to the guest with `--dir`.
+#[derive(Clone, PartialEq)]
+pub struct PreopenDir {
+    pub host: String,
+    pub guest: String,
+    /// Whether the guest's modifications are kept in an in-memory overlay
+    /// instead of being applied to `host`.
+    pub overlay: bool,
+}
+
+fn parse_dirs(s: &str) -> Result<PreopenDir> {
     let mut parts = s.split("::");
     let host = parts.next().unwrap();
-    let guest = match parts.next() {
-        Some(guest) => guest,
-        None => host,
+    let (guest, overlay) = match parts.next() {
+        Some(guest) => match guest.strip_suffix(":overlay") {
+            Some(guest) => (guest, true),
+            None => (guest, false),
+        },
+        None => (host, false),
     };
-    Ok((host.into(), guest.into()))
+    Ok(PreopenDir {
+        host: host.into(),
+        guest: guest.into(),
+        overlay,
+    })
 }
 
 impl RunCommon {
@@ -262,6 +288,115 @@ impl RunCommon {
         })
     }
 
+    /// The TLS configuration for outgoing wasi-http requests, if any of the
+    /// `-S http-*` TLS options were given.
+    #[cfg(feature = "wasi-http")]
+    pub fn wasi_http_tls_config(&self) -> Result<Option<wasmtime_wasi_http::tls::TlsConfig>> {
+        let wasi = &self.common.wasi;
+        if wasi.http_ca_bundle.is_none()
+            && wasi.http_default_roots.is_none()
+            && wasi.http_client_cert.is_none()
+            && wasi.http_client_key.is_none()
+        {
+            return Ok(None);
+        }
+
+        let mut config = wasmtime_wasi_http::tls::TlsConfig::new();
+        config.webpki_roots(wasi.http_default_roots.unwrap_or(true));
+        if let Some(path) = &wasi.http_ca_bundle {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed to read CA bundle `{path}`"))?;
+            config
+                .add_root_certificates_pem(&pem)
+                .with_context(|| format!("failed to load CA bundle `{path}`"))?;
+        }
+        match (&wasi.http_client_cert, &wasi.http_client_key) {
+            (Some(cert), Some(key)) => {
+                let cert_pem = std::fs::read(cert)
+                    .with_context(|| format!("failed to read client certificate `{cert}`"))?;
+                let key_pem = std::fs::read(key)
+                    .with_context(|| format!("failed to read client key `{key}`"))?;
+                config
+                    .client_auth_pem(&cert_pem, &key_pem)
+                    .with_context(|| format!("failed to load client certificate `{cert}`"))?;
+            }
+            (None, None) => {}
+            _ => bail!("`-S http-client-cert` and `-S http-client-key` must be used together"),
+        }
+        Ok(Some(config))
+    }
+
+    /// The policy for outgoing wasi-http requests, if any of the `-S http-*`
+    /// policy options were given.
+    #[cfg(feature = "wasi-http")]
+    pub fn wasi_http_outgoing_policy(
+        &self,
+    ) -> Result<Option<wasmtime_wasi_http::policy::OutgoingRequestPolicy>> {
+        let wasi = &self.common.wasi;
+        if wasi.http_allow_authority.is_empty()
+            && wasi.http_deny_authority.is_empty()
+            && wasi.http_allow_port.is_empty()
+            && wasi.http_allow_scheme.is_empty()
+            && wasi.http_allow_method.is_empty()
+            && wasi.http_max_body_size.is_none()
+            && wasi.http_max_requests_per_second.is_none()
+        {
+            return Ok(None);
+        }
+
+        let mut policy = wasmtime_wasi_http::policy::OutgoingRequestPolicy::new();
+        for pattern in &wasi.http_allow_authority {
+            policy.allow_authority(pattern)?;
+        }
+        for pattern in &wasi.http_deny_authority {
+            policy.deny_authority(pattern)?;
+        }
+        for port in &wasi.http_allow_port {
+            policy.allow_port(*port);
+        }
+        for scheme in &wasi.http_allow_scheme {
+            policy.allow_scheme(scheme)?;
+        }
+        for method in &wasi.http_allow_method {
+            let method = hyper::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .with_context(|| format!("invalid method for `-S http-allow-method`: {method}"))?;
+            policy.allow_method(method);
+        }
+        policy
+            .max_body_size(wasi.http_max_body_size)
+            .max_requests_per_second(wasi.http_max_requests_per_second);
+        Ok(Some(policy))
+    }
+
+    /// The pool for the connections of outgoing wasi-http requests, if it's
+    /// enabled with `-S http-connection-pool` or by default.
+    ///
+    /// Setting any of the `-S http-pool-*` options enables the pool too,
+    /// unless it's explicitly disabled.
+    #[cfg(feature = "wasi-http")]
+    pub fn wasi_http_connection_pool(
+        &self,
+        default: bool,
+    ) -> Option<wasmtime_wasi_http::pool::ConnectionPool> {
+        let wasi = &self.common.wasi;
+        let configured = wasi.http_pool_idle_timeout.is_some()
+            || wasi.http_pool_max_idle_per_host.is_some()
+            || wasi.http_pool_max_connections_per_host.is_some();
+        if !wasi.http_connection_pool.unwrap_or(default || configured) {
+            return None;
+        }
+
+        let mut config = wasmtime_wasi_http::pool::ConnectionPoolConfig::new();
+        if let Some(timeout) = wasi.http_pool_idle_timeout {
+            config.idle_timeout(timeout);
+        }
+        if let Some(max) = wasi.http_pool_max_idle_per_host {
+            config.max_idle_per_host(max);
+        }
+        config.max_connections_per_host(wasi.http_pool_max_connections_per_host);
+        Some(wasmtime_wasi_http::pool::ConnectionPool::new(config))
+    }
+
     pub fn configure_wasip2(&self, builder: &mut WasiCtxBuilder) -> Result<()> {
         // It's ok to block the current thread since we're the only thread in
         // the program as the CLI. This helps improve the performance of some
@@ -290,13 +425,16 @@ impl RunCommon {
             builder.env(key, &value);
         }
 
-        for (host, guest) in self.dirs.iter() {
-            builder.preopened_dir(
-                host,
-                guest,
-                wasmtime_wasi::DirPerms::all(),
-                wasmtime_wasi::FilePerms::all(),
-            )?;
+        for dir in self.dirs.iter() {
+            let dir_perms = wasmtime_wasi::DirPerms::all();
+            let file_perms = wasmtime_wasi::FilePerms::all();
+            if dir.overlay {
+                builder
+                    .preopened_overlay_dir(&dir.host, &dir.guest, dir_perms, file_perms)
+                    .with_context(|| format!("failed to open directory '{}'", dir.host))?;
+            } else {
+                builder.preopened_dir(&dir.host, &dir.guest, dir_perms, file_perms)?;
+            }
         }
 
         if self.common.wasi.listenfd == Some(true) {
@@ -8,3 +8,6 @@ pub mod commands;
 
 #[cfg(feature = "run")]
 pub(crate) mod common;
+
+#[cfg(all(feature = "run", feature = "component-model"))]
+pub(crate) mod wave;
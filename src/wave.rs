@@ -0,0 +1,883 @@
+//! A small implementation of the WebAssembly Value Encoding (WAVE) text
+//! format for component model values.
+//!
+//! This is used by `wasmtime run --invoke` to parse the arguments of a
+//! component function call, such as `add(1, 2)` or `greet("world")`, into
+//! [`Val`]s according to the function's signature, and to print the results
+//! of the call in the same syntax.
+//!
+//! The syntax supported here is:
+//!
+//! * `bool` - `true` or `false`
+//! * integers - decimal literals like `42` or `-7`
+//! * floats - decimal literals like `1.5`, `-2e10`, plus `nan`, `inf` and
+//!   `-inf`
+//! * `char` - quoted characters like `'x'` or `'\u{1f600}'`
+//! * `string` - quoted strings like `"hello\n"`
+//! * `list<T>` - `[a, b, c]`
+//! * `tuple<...>` - `(a, b, c)`
+//! * `record` - `{name: "x", count: 2}`, where fields of type `option<T>`
+//!   may be omitted to mean `none`
+//! * `variant` - `case` or `case(payload)`
+//! * `enum` - `case`
+//! * `option<T>` - `some(x)` or `none`
+//! * `result<T, E>` - `ok`, `ok(x)`, `err` or `err(x)`
+//! * `flags` - `{a, b}`
+//!
+//! Labels which collide with a keyword (`true`, `false`, `some`, `none`,
+//! `ok`, `err`, `inf`, `nan`) may be prefixed with `%`, and trailing commas
+//! are accepted in all comma-separated lists.
+
+use anyhow::{anyhow, bail, Result};
+use std::fmt::Write;
+use wasmtime::component::{types, Type, Val};
+
+const KEYWORDS: &[&str] = &["true", "false", "some", "none", "ok", "err", "inf", "nan"];
+
+/// Parses the textual function call `s`, for example `iface#func(1, "a")`,
+/// into the name of the function and the unparsed text of its arguments.
+///
+/// The returned arguments are still enclosed in their parentheses and are
+/// intended to be passed to [`parse_args`].
+pub fn parse_call(s: &str) -> Result<(&str, &str)> {
+    let s = s.trim();
+    let Some(open) = s.find('(') else {
+        bail!(
+            "expected a function call with parenthesized arguments \
+             like `{s}()` when invoking a component function"
+        );
+    };
+    let name = s[..open].trim_end();
+    if name.is_empty() {
+        bail!("missing function name in `{s}`");
+    }
+    if !s.ends_with(')') {
+        bail!("expected `)` at the end of the function call `{s}`");
+    }
+    Ok((name, &s[open..]))
+}
+
+/// Parses a parenthesized, comma-separated list of arguments, for example
+/// `(1, "a")`, where each argument is typed by the corresponding entry of
+/// `params`.
+pub fn parse_args(s: &str, params: &[Type]) -> Result<Vec<Val>> {
+    let mut parser = Parser::new(s);
+    parser.expect('(')?;
+    let mut vals = Vec::with_capacity(params.len());
+    for (i, ty) in params.iter().enumerate() {
+        if i > 0 {
+            parser.expect(',')?;
+        }
+        if parser.eat(')') {
+            bail!(
+                "not enough arguments: expected {}, found {}",
+                params.len(),
+                vals.len()
+            );
+        }
+        vals.push(parser.value(ty)?);
+    }
+    parser.eat(',');
+    if !parser.eat(')') {
+        bail!(
+            "too many arguments: expected {} at offset {}",
+            params.len(),
+            parser.pos
+        );
+    }
+    parser.finish()?;
+    Ok(vals)
+}
+
+/// Renders `val` in the WAVE syntax.
+pub fn to_string(val: &Val) -> String {
+    let mut dst = String::new();
+    print(&mut dst, val);
+    dst
+}
+
+fn print(dst: &mut String, val: &Val) {
+    match val {
+        Val::Bool(b) => write!(dst, "{b}").unwrap(),
+        Val::S8(v) => write!(dst, "{v}").unwrap(),
+        Val::U8(v) => write!(dst, "{v}").unwrap(),
+        Val::S16(v) => write!(dst, "{v}").unwrap(),
+        Val::U16(v) => write!(dst, "{v}").unwrap(),
+        Val::S32(v) => write!(dst, "{v}").unwrap(),
+        Val::U32(v) => write!(dst, "{v}").unwrap(),
+        Val::S64(v) => write!(dst, "{v}").unwrap(),
+        Val::U64(v) => write!(dst, "{v}").unwrap(),
+        Val::Float32(v) => print_float(dst, *v),
+        Val::Float64(v) => print_float(dst, *v),
+        Val::Char(c) => {
+            dst.push('\'');
+            print_escaped(dst, *c, '\'');
+            dst.push('\'');
+        }
+        Val::String(s) => {
+            dst.push('"');
+            for c in s.chars() {
+                print_escaped(dst, c, '"');
+            }
+            dst.push('"');
+        }
+        Val::List(vals) => {
+            dst.push('[');
+            print_list(dst, vals);
+            dst.push(']');
+        }
+        Val::Tuple(vals) => {
+            dst.push('(');
+            print_list(dst, vals);
+            dst.push(')');
+        }
+        Val::Record(fields) => {
+            dst.push('{');
+            let mut first = true;
+            for (name, val) in fields {
+                // Fields which are `none` are omitted entirely, like WAVE
+                // does for optional record fields.
+                if let Val::Option(None) = val {
+                    continue;
+                }
+                if !first {
+                    dst.push_str(", ");
+                }
+                first = false;
+                print_label(dst, name);
+                dst.push_str(": ");
+                print(dst, val);
+            }
+            // An empty record is written as `{:}` to distinguish it from
+            // empty flags.
+            if first {
+                dst.push(':');
+            }
+            dst.push('}');
+        }
+        Val::Variant(name, payload) => {
+            print_label(dst, name);
+            print_payload(dst, payload);
+        }
+        Val::Enum(name) => print_label(dst, name),
+        Val::Option(None) => dst.push_str("none"),
+        Val::Option(Some(val)) => {
+            dst.push_str("some(");
+            print(dst, val);
+            dst.push(')');
+        }
+        Val::Result(Ok(payload)) => {
+            dst.push_str("ok");
+            print_payload(dst, payload);
+        }
+        Val::Result(Err(payload)) => {
+            dst.push_str("err");
+            print_payload(dst, payload);
+        }
+        Val::Flags(names) => {
+            dst.push('{');
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    dst.push_str(", ");
+                }
+                print_label(dst, name);
+            }
+            dst.push('}');
+        }
+        Val::Resource(_) => dst.push_str("<resource>"),
+    }
+}
+
+fn print_list(dst: &mut String, vals: &[Val]) {
+    for (i, val) in vals.iter().enumerate() {
+        if i > 0 {
+            dst.push_str(", ");
+        }
+        print(dst, val);
+    }
+}
+
+fn print_payload(dst: &mut String, payload: &Option<Box<Val>>) {
+    if let Some(val) = payload {
+        dst.push('(');
+        print(dst, val);
+        dst.push(')');
+    }
+}
+
+fn print_label(dst: &mut String, name: &str) {
+    if KEYWORDS.contains(&name) {
+        dst.push('%');
+    }
+    dst.push_str(name);
+}
+
+fn print_float(dst: &mut String, f: impl Into<f64> + std::fmt::Display + Copy) {
+    let f64 = f.into();
+    if f64.is_nan() {
+        dst.push_str("nan");
+    } else if f64.is_infinite() {
+        dst.push_str(if f64 > 0.0 { "inf" } else { "-inf" });
+    } else {
+        write!(dst, "{f}").unwrap();
+    }
+}
+
+fn print_escaped(dst: &mut String, c: char, quote: char) {
+    match c {
+        '\\' => dst.push_str("\\\\"),
+        '\n' => dst.push_str("\\n"),
+        '\r' => dst.push_str("\\r"),
+        '\t' => dst.push_str("\\t"),
+        c if c == quote => {
+            dst.push('\\');
+            dst.push(c);
+        }
+        c if c.is_control() => write!(dst, "\\u{{{:x}}}", c as u32).unwrap(),
+        c => dst.push(c),
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Parser<'a> {
+        Parser { src, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(found) => bail!("expected `{c}`, found `{found}` at offset {}", self.pos),
+            None => bail!("expected `{c}`, found end of input"),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => bail!("unexpected trailing `{c}` at offset {}", self.pos),
+        }
+    }
+
+    /// Parses a bare token such as a number, keyword or label.
+    fn token(&mut self) -> Result<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.' | '_')))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            match rest.chars().next() {
+                Some(c) => bail!("unexpected `{c}` at offset {}", self.pos),
+                None => bail!("unexpected end of input"),
+            }
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Parses a label, which is a kebab-case identifier optionally prefixed
+    /// with `%`.
+    ///
+    /// Returns the label without its prefix along with whether the prefix was
+    /// present.
+    fn label(&mut self) -> Result<(&'a str, bool)> {
+        let escaped = self.eat('%');
+        let start = self.pos;
+        let label = self.token()?;
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            bail!("invalid label `{label}` at offset {start}");
+        }
+        Ok((label, escaped))
+    }
+
+    fn value(&mut self, ty: &Type) -> Result<Val> {
+        Ok(match ty {
+            Type::Bool => match self.token()? {
+                "true" => Val::Bool(true),
+                "false" => Val::Bool(false),
+                other => bail!("expected `true` or `false`, found `{other}`"),
+            },
+            Type::S8 => Val::S8(self.int()?),
+            Type::U8 => Val::U8(self.int()?),
+            Type::S16 => Val::S16(self.int()?),
+            Type::U16 => Val::U16(self.int()?),
+            Type::S32 => Val::S32(self.int()?),
+            Type::U32 => Val::U32(self.int()?),
+            Type::S64 => Val::S64(self.int()?),
+            Type::U64 => Val::U64(self.int()?),
+            Type::Float32 => Val::Float32(self.float()?),
+            Type::Float64 => Val::Float64(self.float()?),
+            Type::Char => {
+                let s = self.quoted('\'')?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Val::Char(c),
+                    _ => bail!("expected a single character, found `{s}`"),
+                }
+            }
+            Type::String => Val::String(self.quoted('"')?),
+            Type::List(list) => {
+                let ty = list.ty();
+                let mut vals = Vec::new();
+                self.expect('[')?;
+                self.comma_separated(']', |p| {
+                    vals.push(p.value(&ty)?);
+                    Ok(())
+                })?;
+                Val::List(vals)
+            }
+            Type::Tuple(tuple) => {
+                let mut vals = Vec::new();
+                self.expect('(')?;
+                for (i, ty) in tuple.types().enumerate() {
+                    if i > 0 {
+                        self.expect(',')?;
+                    }
+                    vals.push(self.value(&ty)?);
+                }
+                self.eat(',');
+                self.expect(')')?;
+                Val::Tuple(vals)
+            }
+            Type::Record(record) => self.record(record)?,
+            Type::Variant(variant) => {
+                let start = self.pos;
+                let (name, _) = self.label()?;
+                let case = variant
+                    .cases()
+                    .find(|case| case.name == name)
+                    .ok_or_else(|| anyhow!("unknown variant case `{name}` at offset {start}"))?;
+                let payload = self.payload(case.ty.as_ref())?;
+                Val::Variant(name.to_string(), payload)
+            }
+            Type::Enum(enum_) => {
+                let start = self.pos;
+                let (name, _) = self.label()?;
+                if !enum_.names().any(|n| n == name) {
+                    bail!("unknown enum case `{name}` at offset {start}");
+                }
+                Val::Enum(name.to_string())
+            }
+            Type::Option(option) => match self.label()? {
+                ("none", false) => Val::Option(None),
+                ("some", false) => {
+                    self.expect('(')?;
+                    let val = self.value(&option.ty())?;
+                    self.expect(')')?;
+                    Val::Option(Some(Box::new(val)))
+                }
+                (other, _) => bail!("expected `some` or `none`, found `{other}`"),
+            },
+            Type::Result(result) => match self.label()? {
+                ("ok", false) => Val::Result(Ok(self.payload(result.ok().as_ref())?)),
+                ("err", false) => Val::Result(Err(self.payload(result.err().as_ref())?)),
+                (other, _) => bail!("expected `ok` or `err`, found `{other}`"),
+            },
+            Type::Flags(flags) => {
+                let mut names = Vec::new();
+                self.expect('{')?;
+                self.comma_separated('}', |p| {
+                    let start = p.pos;
+                    let (name, _) = p.label()?;
+                    if !flags.names().any(|n| n == name) {
+                        bail!("unknown flag `{name}` at offset {start}");
+                    }
+                    names.push(name.to_string());
+                    Ok(())
+                })?;
+                Val::Flags(names)
+            }
+            Type::Own(_) | Type::Borrow(_) => {
+                bail!("resources cannot be passed as arguments on the command line")
+            }
+        })
+    }
+
+    /// Parses the remainder of a list of items separated by commas and
+    /// terminated by `end`, allowing a trailing comma.
+    fn comma_separated(
+        &mut self,
+        end: char,
+        mut item: impl FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            if self.eat(end) {
+                return Ok(());
+            }
+            item(self)?;
+            if !self.eat(',') {
+                return self.expect(end);
+            }
+        }
+    }
+
+    fn record(&mut self, record: &types::Record) -> Result<Val> {
+        let mut vals: Vec<(String, Option<Val>)> = record
+            .fields()
+            .map(|field| (field.name.to_string(), None))
+            .collect();
+        self.expect('{')?;
+        // `{:}` is the syntax for an empty record.
+        if self.eat(':') {
+            self.expect('}')?;
+        } else {
+            self.comma_separated('}', |p| {
+                let start = p.pos;
+                let (name, _) = p.label()?;
+                let (i, field) = record
+                    .fields()
+                    .enumerate()
+                    .find(|(_, field)| field.name == name)
+                    .ok_or_else(|| anyhow!("unknown field `{name}` at offset {start}"))?;
+                if vals[i].1.is_some() {
+                    bail!("duplicate field `{name}` at offset {start}");
+                }
+                p.expect(':')?;
+                vals[i].1 = Some(p.value(&field.ty)?);
+                Ok(())
+            })?;
+        }
+        let mut fields = Vec::with_capacity(vals.len());
+        for ((name, val), field) in vals.into_iter().zip(record.fields()) {
+            let val = match (val, field.ty) {
+                (Some(val), _) => val,
+                (None, Type::Option(_)) => Val::Option(None),
+                (None, _) => bail!("missing field `{name}`"),
+            };
+            fields.push((name, val));
+        }
+        Ok(Val::Record(fields))
+    }
+
+    fn payload(&mut self, ty: Option<&Type>) -> Result<Option<Box<Val>>> {
+        match ty {
+            Some(ty) => {
+                self.expect('(')?;
+                let val = self.value(ty)?;
+                self.expect(')')?;
+                Ok(Some(Box::new(val)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn int<T: std::str::FromStr>(&mut self) -> Result<T>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let start = self.pos;
+        let token = self.token()?;
+        match token.parse() {
+            Ok(v) => Ok(v),
+            Err(e) => bail!("invalid integer `{token}` at offset {start}: {e}"),
+        }
+    }
+
+    fn float<T: std::str::FromStr + From<f32>>(&mut self) -> Result<T>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let start = self.pos;
+        let token = self.token()?;
+        match token {
+            "nan" => Ok(T::from(f32::NAN)),
+            "inf" => Ok(T::from(f32::INFINITY)),
+            "-inf" => Ok(T::from(f32::NEG_INFINITY)),
+            // Reject spellings which Rust accepts but WAVE does not.
+            _ if token.contains(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E') => {
+                bail!("invalid float `{token}` at offset {start}")
+            }
+            _ => match token.parse() {
+                Ok(v) => Ok(v),
+                Err(e) => bail!("invalid float `{token}` at offset {start}: {e}"),
+            },
+        }
+    }
+
+    /// Parses a string or character literal delimited by `quote`, returning
+    /// its contents with escapes resolved.
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        let start = self.pos;
+        self.expect(quote)?;
+        let mut ret = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            let Some((i, c)) = chars.next() else {
+                bail!("unterminated literal starting at offset {start}");
+            };
+            match c {
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(ret);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('\\') => '\\',
+                        Some('\'') => '\'',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut hex = String::new();
+                            if chars.next().map(|(_, c)| c) != Some('{') {
+                                bail!("expected `{{` after `\\u` in literal at offset {start}");
+                            }
+                            loop {
+                                match chars.next().map(|(_, c)| c) {
+                                    Some('}') => break,
+                                    Some(c) => hex.push(c),
+                                    None => {
+                                        bail!("unterminated literal starting at offset {start}")
+                                    }
+                                }
+                            }
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| anyhow!("invalid unicode escape `\\u{{{hex}}}`"))?
+                        }
+                        Some(c) => bail!("invalid escape `\\{c}` in literal at offset {start}"),
+                        None => bail!("unterminated literal starting at offset {start}"),
+                    };
+                    ret.push(escaped);
+                }
+                c => ret.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::types::ComponentItem;
+    use wasmtime::component::Component;
+    use wasmtime::Engine;
+
+    /// Returns the parameter types of a function `f` imported by a component
+    /// made of `wat`, which may also import the named types the function
+    /// uses.
+    fn params(wat: &str) -> Vec<Type> {
+        let engine = Engine::default();
+        let component = Component::new(&engine, format!("(component {wat})")).unwrap();
+        match component.component_type().get_import(&engine, "f") {
+            Some(ComponentItem::ComponentFunc(f)) => f.params().collect(),
+            _ => panic!("component doesn't import a function `f`"),
+        }
+    }
+
+    /// Parses `args`, checks that printing and parsing the result again
+    /// yields the same values, and returns them.
+    fn roundtrip(params: &[Type], args: &str) -> Vec<Val> {
+        let vals = parse_args(args, params).unwrap();
+        let printed = format!(
+            "({})",
+            vals.iter().map(to_string).collect::<Vec<_>>().join(", ")
+        );
+        let reparsed = parse_args(&printed, params).unwrap();
+        assert_eq!(vals, reparsed, "`{printed}` didn't round trip");
+        vals
+    }
+
+    #[test]
+    fn parse_call_syntax() {
+        assert_eq!(parse_call(" f() ").unwrap(), ("f", "()"));
+        assert_eq!(
+            parse_call("ns:pkg/iface#get-x(1, \"a\")").unwrap(),
+            ("ns:pkg/iface#get-x", "(1, \"a\")")
+        );
+        assert!(parse_call("f").is_err());
+        assert!(parse_call("(1)").is_err());
+        assert!(parse_call("f(1").is_err());
+    }
+
+    #[test]
+    fn primitives() {
+        let params = params(
+            r#"(import "f" (func
+                (param "a" bool) (param "b" s8) (param "c" u64)
+                (param "d" float32) (param "e" float64) (param "g" float64)))"#,
+        );
+        let vals = roundtrip(
+            &params,
+            "(true, -128, 18446744073709551615, 1.5, -2e10, inf,)",
+        );
+        assert_eq!(
+            vals,
+            [
+                Val::Bool(true),
+                Val::S8(-128),
+                Val::U64(u64::MAX),
+                Val::Float32(1.5),
+                Val::Float64(-2e10),
+                Val::Float64(f64::INFINITY),
+            ]
+        );
+
+        let vals = parse_args("(false, 0, 0, nan, -inf, 0.25)", &params).unwrap();
+        assert_eq!(to_string(&vals[3]), "nan");
+        assert_eq!(to_string(&vals[4]), "-inf");
+        assert_eq!(to_string(&vals[5]), "0.25");
+    }
+
+    #[test]
+    fn strings_and_chars() {
+        let params = params(
+            r#"(import "f" (func
+                (param "a" string) (param "b" char) (param "c" char)
+                (param "d" char) (param "e" string)))"#,
+        );
+        let vals = roundtrip(
+            &params,
+            r#"("a\"b\\c\n\t\r'\u{1f600}\u{7}", 'x', '\'', '\u{41}', "")"#,
+        );
+        assert_eq!(
+            vals,
+            [
+                Val::String("a\"b\\c\n\t\r'\u{1f600}\u{7}".to_string()),
+                Val::Char('x'),
+                Val::Char('\''),
+                Val::Char('A'),
+                Val::String(String::new()),
+            ]
+        );
+        assert_eq!(to_string(&vals[0]), r#""a\"b\\c\n\t\r'😀\u{7}""#);
+        assert_eq!(to_string(&vals[2]), r#"'\''"#);
+
+        // Non-ASCII text is kept as is, and `"` needn't be escaped in a char.
+        let vals = roundtrip(&params, r#"("ünïcödé", '"', 'é', '😀', " ")"#);
+        assert_eq!(vals[1], Val::Char('"'));
+        assert_eq!(vals[3], Val::Char('😀'));
+    }
+
+    #[test]
+    fn flags() {
+        let params = params(
+            r#"(type $perms' (flags "read" "write" "ok"))
+               (import "perms" (type $perms (eq $perms')))
+               (import "f" (func (param "a" $perms) (param "b" $perms)))"#,
+        );
+        let vals = roundtrip(&params, "({read, %ok,}, {})");
+        assert_eq!(
+            vals,
+            [
+                Val::Flags(vec!["read".to_string(), "ok".to_string()]),
+                Val::Flags(vec![]),
+            ]
+        );
+        assert_eq!(to_string(&vals[0]), "{read, %ok}");
+        assert_eq!(to_string(&vals[1]), "{}");
+
+        assert!(parse_args("({exec}, {})", &params).is_err());
+        assert!(parse_args("({read write}, {})", &params).is_err());
+    }
+
+    #[test]
+    fn option_and_result_shorthand() {
+        let params = params(
+            r#"(type $r' (record (field "a" u32) (field "b" (option string))))
+               (import "r" (type $r (eq $r')))
+               (import "f" (func
+                (param "a" (option u32)) (param "b" (option u32))
+                (param "c" (result u32 (error string))) (param "d" (result u32 (error string)))
+                (param "e" (result)) (param "g" (result))
+                (param "h" $r) (param "i" $r)))"#,
+        );
+        let vals = roundtrip(
+            &params,
+            r#"(some(1), none, ok(2), err("no"), ok, err, {a: 3}, {b: some("x"), a: 4})"#,
+        );
+        assert_eq!(
+            vals,
+            [
+                Val::Option(Some(Box::new(Val::U32(1)))),
+                Val::Option(None),
+                Val::Result(Ok(Some(Box::new(Val::U32(2))))),
+                Val::Result(Err(Some(Box::new(Val::String("no".to_string()))))),
+                Val::Result(Ok(None)),
+                Val::Result(Err(None)),
+                Val::Record(vec![
+                    ("a".to_string(), Val::U32(3)),
+                    ("b".to_string(), Val::Option(None)),
+                ]),
+                Val::Record(vec![
+                    ("a".to_string(), Val::U32(4)),
+                    (
+                        "b".to_string(),
+                        Val::Option(Some(Box::new(Val::String("x".to_string())))),
+                    ),
+                ]),
+            ]
+        );
+        assert_eq!(to_string(&vals[4]), "ok");
+        assert_eq!(to_string(&vals[6]), "{a: 3}");
+        assert_eq!(to_string(&vals[7]), r#"{a: 4, b: some("x")}"#);
+    }
+
+    #[test]
+    fn nested_records_and_variants() {
+        let params = params(
+            r#"(type $point' (record (field "x" s32) (field "y" s32)))
+               (import "point" (type $point (eq $point')))
+               (type $shape' (variant
+                 (case "circle" (tuple $point float64))
+                 (case "polygon" (list $point))
+                 (case "none")
+                 (case "empty")))
+               (import "shape" (type $shape (eq $shape')))
+               (type $color' (enum "red" "none"))
+               (import "color" (type $color (eq $color')))
+               (type $layer' (record
+                 (field "name" string)
+                 (field "shapes" (list $shape))
+                 (field "color" $color)))
+               (import "layer" (type $layer (eq $layer')))
+               (import "f" (func (param "a" (list $layer))))"#,
+        );
+        let vals = roundtrip(
+            &params,
+            "([
+                {
+                    name: \"a\",
+                    shapes: [circle(({x: 1, y: -1}, 2.5)), polygon([{y: 0, x: 0}]), %none],
+                    color: %none,
+                },
+                {name: \"b\", shapes: [], color: red},
+            ])",
+        );
+        let point = |x, y| {
+            Val::Record(vec![
+                ("x".to_string(), Val::S32(x)),
+                ("y".to_string(), Val::S32(y)),
+            ])
+        };
+        let layer = |name: &str, shapes, color: &str| {
+            Val::Record(vec![
+                ("name".to_string(), Val::String(name.to_string())),
+                ("shapes".to_string(), Val::List(shapes)),
+                ("color".to_string(), Val::Enum(color.to_string())),
+            ])
+        };
+        assert_eq!(
+            vals,
+            [Val::List(vec![
+                layer(
+                    "a",
+                    vec![
+                        Val::Variant(
+                            "circle".to_string(),
+                            Some(Box::new(Val::Tuple(vec![point(1, -1), Val::Float64(2.5)]))),
+                        ),
+                        Val::Variant(
+                            "polygon".to_string(),
+                            Some(Box::new(Val::List(vec![point(0, 0)]))),
+                        ),
+                        Val::Variant("none".to_string(), None),
+                    ],
+                    "none",
+                ),
+                layer("b", vec![], "red"),
+            ])]
+        );
+        assert_eq!(
+            to_string(&vals[0]),
+            "[{name: \"a\", shapes: [circle(({x: 1, y: -1}, 2.5)), polygon([{x: 0, y: 0}]), \
+             %none], color: %none}, {name: \"b\", shapes: [], color: red}]"
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        let params = params(
+            r#"(type $r' (record (field "a" u8) (field "b" (option u8))))
+               (import "r" (type $r (eq $r')))
+               (type $v' (variant (case "x" u8) (case "y")))
+               (import "v" (type $v (eq $v')))
+               (import "f" (func
+                (param "s" string) (param "c" char) (param "n" u8) (param "g" float32)
+                (param "r" $r) (param "v" $v) (param "o" (option u8))
+                (param "l" (list u8))))"#,
+        );
+        let valid = r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#;
+        parse_args(valid, &params).unwrap();
+
+        for args in [
+            "",
+            "(",
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1))"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1], 2)"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1]) x"#,
+            r#"("s" 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            // Strings and chars
+            r#"("s, 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\q", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\u41", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\u{110000}", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\u{d800}", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\u{zz}", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("\u{41", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s\", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", 'cd', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", '', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"(s, 'c', 1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            // Numbers
+            r#"("s", 'c', 256, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", 'c', -1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 0x1, 1.0, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, infinity, {a: 1}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0x, {a: 1}, x(1), some(1), [1])"#,
+            // Records
+            r#"("s", 'c', 1, 1.0, {}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {b: some(1)}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1, a: 2}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1, c: 2}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a 1}, x(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1, b: 2}, x(1), some(1), [1])"#,
+            // Variants and options
+            r#"("s", 'c', 1, 1.0, {a: 1}, z(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x, some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, y(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x_y(1), some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some, [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), %some(1), [1])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), 1, [1])"#,
+            // Lists
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1)"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [1 2])"#,
+            r#"("s", 'c', 1, 1.0, {a: 1}, x(1), some(1), [,])"#,
+        ] {
+            assert!(parse_args(args, &params).is_err(), "`{args}` was accepted");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (type $ret (func (result i32)))
+        (memory (export "memory") 1)
+        (table (export "table") 1 funcref)
+        (global $counter (export "counter") (mut i32) (i32.const 0))
+        (global $callback (mut (ref null $ret)) (ref.null $ret))
+        (func $one (result i32) i32.const 1)
+        (func $two (result i32) i32.const 2)
+        (elem declare func $one $two)
+        (func (export "step")
+            (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+            (drop (memory.grow (i32.const 1)))
+            (i32.store (i32.const 0x10000) (global.get $counter))
+            (drop (table.grow (ref.func $two) (i32.const 1)))
+            (global.set $callback (ref.func $one)))
+        (func (export "call") (result i32)
+            (call_ref $ret (ref.as_non_null (global.get $callback))))
+    )
+"#;
+
+fn instantiate(engine: &Engine, store: &mut Store<Host>) -> Result<Instance> {
+    let module = Module::new(engine, WAT)?;
+    Instance::new(store, &module, &[])
+}
+
+#[derive(Default)]
+struct Host {
+    requests: u32,
+}
+
+impl Checkpointable for Host {
+    fn save(&mut self) -> Result<Vec<u8>> {
+        Ok(self.requests.to_le_bytes().to_vec())
+    }
+
+    fn restore(&mut self, bytes: &[u8]) -> Result<()> {
+        self.requests = u32::from_le_bytes(bytes.try_into()?);
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn checkpoint_and_restore() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    let engine = Engine::new(&config)?;
+
+    let mut store = Store::new(&engine, Host { requests: 7 });
+    let instance = instantiate(&engine, &mut store)?;
+    let step = instance.get_typed_func::<(), ()>(&mut store, "step")?;
+    step.call(&mut store, ())?;
+    step.call(&mut store, ())?;
+    let checkpoint = store.checkpoint()?;
+
+    let mut store = Store::new(&engine, Host::default());
+    let instance = instantiate(&engine, &mut store)?;
+    store.restore(&checkpoint)?;
+    assert_eq!(store.data().requests, 7);
+
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 3);
+    assert_eq!(&memory.data(&store)[0x10000..0x10004], &2u32.to_le_bytes());
+
+    let counter = instance.get_global(&mut store, "counter").unwrap();
+    assert_eq!(counter.get(&mut store).unwrap_i32(), 2);
+
+    let table = instance.get_table(&mut store, "table").unwrap();
+    assert_eq!(table.size(&store), 3);
+    assert!(table.get(&mut store, 0).unwrap().unwrap_func().is_none());
+    let two = table
+        .get(&mut store, 2)
+        .unwrap()
+        .unwrap_func()
+        .unwrap()
+        .clone();
+    assert_eq!(two.typed::<(), i32>(&store)?.call(&mut store, ())?, 2);
+
+    let call = instance.get_typed_func::<(), i32>(&mut store, "call")?;
+    assert_eq!(call.call(&mut store, ())?, 1);
+
+    // Execution continues from the restored state.
+    let step = instance.get_typed_func::<(), ()>(&mut store, "step")?;
+    step.call(&mut store, ())?;
+    assert_eq!(counter.get(&mut store).unwrap_i32(), 3);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn restore_overwrites_memory() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    let engine = Engine::new(&config)?;
+
+    let mut store = Store::new(&engine, Host::default());
+    instantiate(&engine, &mut store)?;
+    let checkpoint = store.checkpoint()?;
+
+    let mut store = Store::new(&engine, Host::default());
+    let instance = instantiate(&engine, &mut store)?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    memory.data_mut(&mut store)[100] = 1;
+    store.restore(&checkpoint)?;
+    assert!(memory.data(&store).iter().all(|b| *b == 0));
+
+    // Memories can't shrink, so restoring into a grown store fails.
+    memory.grow(&mut store, 1)?;
+    assert!(store.restore(&checkpoint).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn checkpoint_fuel_and_epoch_deadline() -> Result<()> {
+    let mut config = Config::new();
+    config.consume_fuel(true).epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+
+    let mut store = Store::new(&engine, ());
+    store.set_fuel(1234)?;
+    store.set_epoch_deadline(5);
+    let checkpoint = store.checkpoint()?;
+
+    engine.increment_epoch();
+    let mut store = Store::new(&engine, ());
+    store.restore(&checkpoint)?;
+    assert_eq!(store.get_fuel()?, 1234);
+
+    // The restored deadline is relative to the current epoch.
+    let module = Module::new(&engine, r#"(module (func (export "f")))"#)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let f = instance.get_typed_func::<(), ()>(&mut store, "f")?;
+    for _ in 0..4 {
+        engine.increment_epoch();
+    }
+    f.call(&mut store, ())?;
+    engine.increment_epoch();
+    let err = f.call(&mut store, ()).unwrap_err();
+    assert_eq!(err.downcast::<Trap>()?, Trap::Interrupt);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn checkpoint_errors() -> Result<()> {
+    let engine = Engine::default();
+
+    let mut store = Store::new(&engine, ());
+    let func = Func::wrap(&mut store, || {});
+    let ty = TableType::new(RefType::FUNCREF, 1, None);
+    Table::new(&mut store, ty, func.into())?;
+    assert!(store.checkpoint().is_err());
+
+    let mut store = Store::new(&engine, ());
+    assert!(store.restore(b"garbage").is_err());
+
+    let checkpoint = store.checkpoint()?;
+    Memory::new(&mut store, MemoryType::new(1, None))?;
+    assert!(store.restore(&checkpoint).is_err());
+
+    // Globals must have the same types.
+    let mut store = Store::new(&engine, ());
+    let ty = GlobalType::new(ValType::I32, Mutability::Var);
+    Global::new(&mut store, ty, Val::I32(1))?;
+    let checkpoint = store.checkpoint()?;
+    let mut store = Store::new(&engine, ());
+    let ty = GlobalType::new(ValType::F32, Mutability::Var);
+    Global::new(&mut store, ty, Val::F32(0))?;
+    let err = store.restore(&checkpoint).unwrap_err();
+    assert!(err.to_string().contains("type of global 0"), "{err:?}");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn restore_into_different_module() -> Result<()> {
+    let engine = Engine::default();
+    let module = |value: i32| {
+        Module::new(
+            &engine,
+            format!(
+                r#"
+                    (module
+                        (memory 1)
+                        (global (mut i32) (i32.const 0))
+                        (func (export "f") (result i32) i32.const {value}))
+                "#
+            ),
+        )
+    };
+
+    let mut store = Store::new(&engine, ());
+    Instance::new(&mut store, &module(1)?, &[])?;
+    let checkpoint = store.checkpoint()?;
+
+    // The same module compiled again can be restored into.
+    let mut store = Store::new(&engine, ());
+    Instance::new(&mut store, &module(1)?, &[])?;
+    store.restore(&checkpoint)?;
+
+    // A module with the same memories, tables and globals can't be.
+    let mut store = Store::new(&engine, ());
+    Instance::new(&mut store, &module(2)?, &[])?;
+    let err = store.restore(&checkpoint).unwrap_err();
+    assert!(
+        err.to_string().contains("doesn't match the instances"),
+        "{err:?}"
+    );
+    Ok(())
+}
@@ -126,6 +126,58 @@ fn run_coredump_smoketest() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn inspect_coredump() -> Result<()> {
+    let wat = "tests/all/cli_tests/coredump_smoketest.wat";
+    let coredump_file = NamedTempFile::new()?;
+    let coredump_arg = format!("-Dcoredump={}", coredump_file.path().display());
+    run_wasmtime(&["run", "--invoke", "a", "-Ccache=n", &coredump_arg, wat]).unwrap_err();
+    let coredump = coredump_file.path().to_str().unwrap();
+
+    let stdout = run_wasmtime(&["coredump", "--module", wat, coredump])?;
+    assert!(
+        stdout.contains("thread `main` backtrace:"),
+        "bad output: {stdout}"
+    );
+    assert!(
+        stdout.contains("!<wasm function 0>"),
+        "bad output: {stdout}"
+    );
+
+    let stdout = run_wasmtime(&["coredump", "--module", wat, "--format", "json", coredump])?;
+    let json: serde_json::Value = serde_json::from_str(&stdout)?;
+    let frames = json["threads"][0]["frames"].as_array().unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0]["func_name"], "c");
+    assert_eq!(frames[1]["func_name"], "b");
+    assert_eq!(frames[2]["func_index"], 0);
+    assert_eq!(json["modules"][0]["path"], wat);
+    Ok(())
+}
+
+#[test]
+fn snapshot_init_func() -> Result<()> {
+    let wat = "tests/all/cli_tests/snapshot.wat";
+    let snapshot = NamedTempFile::new()?;
+    let snapshot = snapshot.path().to_str().unwrap();
+    run_wasmtime(&[
+        "snapshot",
+        "-Ccache=n",
+        "--init-func",
+        "_initialize",
+        "-o",
+        snapshot,
+        wat,
+    ])?;
+
+    let stdout = run_wasmtime(&["run", "-Ccache=n", "--invoke", "get", snapshot])?;
+    assert_eq!(stdout, "42\n");
+
+    // The initialization function isn't exported from the snapshot anymore.
+    assert!(run_wasmtime(&["run", "-Ccache=n", "--invoke", "_initialize", snapshot]).is_err());
+    Ok(())
+}
+
 // Running simple wat
 #[test]
 fn run_wasmtime_simple_wat() -> Result<()> {
@@ -874,6 +926,37 @@ fn run_precompiled_component() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(not(feature = "component-model"), ignore)]
+fn invoke_component_functions() -> Result<()> {
+    let path = "tests/all/cli_tests/component-invoke.wat";
+    let invoke = |call: &str| run_wasmtime(&["run", "-Ccache=n", "--invoke", call, path]);
+
+    assert_eq!(invoke("add(1, 2)")?, "3\n");
+    assert_eq!(invoke(" add( 40 , 2, ) ")?, "42\n");
+    assert_eq!(
+        invoke(r#"local:demo/api#echo("hello\n\"world\"")"#)?,
+        "\"hello\\n\\\"world\\\"\"\n"
+    );
+    assert_eq!(invoke("local:demo/api#succ(some(41))")?, "some(42)\n");
+    assert_eq!(invoke("local:demo/api#succ(none)")?, "none\n");
+
+    // Malformed invocations are reported as errors.
+    assert!(invoke("add").is_err());
+    assert!(invoke("add(1)").is_err());
+    assert!(invoke("add(1, 2, 3)").is_err());
+    assert!(invoke("add(1, -2)").is_err());
+    assert!(invoke("add(1, \"2\")").is_err());
+    assert!(invoke("missing()").is_err());
+    assert!(invoke("local:demo/api#missing()").is_err());
+    assert!(invoke("local:demo/api#succ(41)").is_err());
+
+    // Arguments must be passed within `--invoke` itself.
+    assert!(run_wasmtime(&["run", "-Ccache=n", "--invoke", "add(1, 2)", path, "3"]).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn memory_growth_failure() -> Result<()> {
     let output = get_wasmtime_command()?
@@ -1248,6 +1331,28 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn cli_file_append_overlay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        std::fs::File::create(dir.path().join("bar.txt"))?
+            .write_all(b"'Twas brillig, and the slithy toves.\n")?;
+
+        run_wasmtime(&[
+            "run",
+            "-Wcomponent-model",
+            &format!("--dir={}::/:overlay", dir.path().to_str().unwrap()),
+            CLI_FILE_APPEND_COMPONENT,
+        ])?;
+
+        let contents = std::fs::read(dir.path().join("bar.txt"))?;
+        assert_eq!(
+            std::str::from_utf8(&contents).unwrap(),
+            "'Twas brillig, and the slithy toves.\n"
+        );
+        Ok(())
+    }
+
     #[test]
     fn cli_file_dir_sync() -> Result<()> {
         let dir = tempfile::tempdir()?;
@@ -1650,7 +1755,9 @@ mod test_programs {
               // serve`.
     async fn cli_serve_respect_pooling_options() -> Result<()> {
         let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
-            cmd.arg("-Opooling-total-memories=0").arg("-Scli");
+            cmd.arg("-Opooling-total-memories=0")
+                .arg("-Scli")
+                .env("WASMTIME_LOG", "wasmtime_cli=warn");
         })?;
 
         let result = server
@@ -1661,8 +1768,8 @@ mod test_programs {
                     .body(String::new())
                     .context("failed to make request")?,
             )
-            .await;
-        assert!(result.is_err());
+            .await?;
+        assert_eq!(result.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
         let (_, stderr) = server.finish()?;
         assert!(
             stderr.contains("maximum concurrent memory limit of 0 reached"),
@@ -1671,6 +1778,342 @@ mod test_programs {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn cli_serve_http2_prior_knowledge() -> Result<()> {
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("--env=FOO=bar").arg("-Scli");
+        })?;
+
+        let tcp = TcpStream::connect(&server.addr)
+            .await
+            .context("failed to connect")?;
+        let (mut send, conn) = hyper::client::conn::http2::handshake(
+            wasmtime_wasi_http::io::TokioExecutor,
+            wasmtime_wasi_http::io::TokioIo::new(tcp),
+        )
+        .await
+        .context("failed http2 handshake")?;
+        let conn_task = tokio::task::spawn(conn);
+
+        // Send a couple of requests over the same connection.
+        for _ in 0..2 {
+            let response = send
+                .send_request(
+                    hyper::Request::builder()
+                        .uri(format!("http://{}/", server.addr))
+                        .header("env", "FOO")
+                        .body(String::new())
+                        .context("failed to make request")?,
+                )
+                .await
+                .context("error sending request")?;
+            assert!(response.status().is_success());
+            assert_eq!(response.version(), hyper::Version::HTTP_2);
+            assert_eq!(
+                response.headers().get("env"),
+                Some(&HeaderValue::from_static("bar"))
+            );
+        }
+
+        drop(send);
+        conn_task.await??;
+        server.finish()?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "s390x")))]
+    #[tokio::test]
+    async fn cli_serve_tls() -> Result<()> {
+        use std::time::Duration;
+        use wasmtime_wasi_http::tls::TlsConfig;
+        use wasmtime_wasi_http::types::{default_send_request_handler, OutgoingRequestConfig};
+
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("--env=FOO=bar")
+                .arg("-Scli")
+                .arg("--tls-cert=tests/all/cli_tests/serve-tls-cert.pem")
+                .arg("--tls-key=tests/all/cli_tests/serve-tls-key.pem");
+        })?;
+
+        let mut tls_config = TlsConfig::new();
+        tls_config
+            .webpki_roots(false)
+            .add_root_certificates_pem(include_bytes!("cli_tests/serve-tls-ca.pem"))?;
+
+        let request = hyper::Request::builder()
+            .uri(format!("https://localhost:{}/", server.addr.port()))
+            .header("env", "FOO")
+            .body(
+                http_body_util::Empty::new()
+                    .map_err(|_| unreachable!())
+                    .boxed(),
+            )?;
+        let timeout = Duration::from_secs(10);
+        let mut config = OutgoingRequestConfig::new(true, timeout, timeout, timeout);
+        config.tls_config(Some(tls_config));
+        let response = default_send_request_handler(request, config)
+            .await
+            .map_err(|e| anyhow::anyhow!("request failed: {e:?}"))?;
+        assert!(response.resp.status().is_success());
+        assert_eq!(response.resp.version(), hyper::Version::HTTP_2);
+        assert_eq!(
+            response.resp.headers().get("env"),
+            Some(&HeaderValue::from_static("bar"))
+        );
+        drop(response);
+
+        server.finish()?;
+        Ok(())
+    }
+
+    #[cfg(all(unix, not(any(target_arch = "riscv64", target_arch = "s390x"))))]
+    #[tokio::test]
+    async fn cli_serve_tls_reload() -> Result<()> {
+        use rustix::process::{kill_process, Pid, Signal};
+        use std::time::{Duration, Instant};
+        use wasmtime_wasi_http::tls::TlsConfig;
+        use wasmtime_wasi_http::types::{default_send_request_handler, OutgoingRequestConfig};
+
+        // Sends a request which only succeeds if the server's certificate is
+        // issued by `ca`.
+        async fn request(server: &WasmtimeServe, ca: &[u8]) -> Result<()> {
+            let mut tls_config = TlsConfig::new();
+            tls_config
+                .webpki_roots(false)
+                .add_root_certificates_pem(ca)?;
+            let request = hyper::Request::builder()
+                .uri(format!("https://localhost:{}/", server.addr.port()))
+                .body(
+                    http_body_util::Empty::new()
+                        .map_err(|_| unreachable!())
+                        .boxed(),
+                )?;
+            let timeout = Duration::from_secs(10);
+            let mut config = OutgoingRequestConfig::new(true, timeout, timeout, timeout);
+            config.tls_config(Some(tls_config));
+            let response = default_send_request_handler(request, config)
+                .await
+                .map_err(|e| anyhow::anyhow!("request failed: {e:?}"))?;
+            assert!(response.resp.status().is_success());
+            Ok(())
+        }
+
+        let ca = include_bytes!("cli_tests/serve-tls-ca.pem");
+        let renewed_ca = include_bytes!("cli_tests/serve-tls-renewed-ca.pem");
+
+        let dir = tempfile::tempdir()?;
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+        std::fs::copy("tests/all/cli_tests/serve-tls-cert.pem", &cert)?;
+        std::fs::copy("tests/all/cli_tests/serve-tls-key.pem", &key)?;
+
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli")
+                .arg(format!("--tls-cert={}", cert.display()))
+                .arg(format!("--tls-key={}", key.display()));
+        })?;
+        request(&server, ca).await?;
+        assert!(request(&server, renewed_ca).await.is_err());
+
+        // Replace the certificate and tell the server to reload it, which
+        // happens in the background.
+        std::fs::copy("tests/all/cli_tests/serve-tls-renewed-cert.pem", &cert)?;
+        std::fs::copy("tests/all/cli_tests/serve-tls-renewed-key.pem", &key)?;
+        kill_process(Pid::from_child(server.child.as_ref().unwrap()), Signal::Hup)?;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while let Err(e) = request(&server, renewed_ca).await {
+            if Instant::now() > deadline {
+                return Err(e.context("certificate wasn't reloaded"));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(request(&server, ca).await.is_err());
+
+        let (_, stderr) = server.finish()?;
+        assert!(
+            stderr.contains("Reloaded TLS certificate"),
+            "bad stderr: {stderr}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_full_queue() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = WasmtimeServe::new(API_PROXY_STREAMING_COMPONENT, |cmd| {
+            cmd.arg("--max-concurrent-instances=1")
+                .arg("--max-queued-requests=0");
+        })?;
+
+        // Start echoing a request whose body never finishes, so that its
+        // instance keeps running and holds the only permit for an instance.
+        let mut busy = TcpStream::connect(&server.addr)
+            .await
+            .context("failed to connect")?;
+        busy.write_all(
+            b"POST /echo HTTP/1.1\r\nhost: localhost\r\ncontent-length: 100\r\n\r\nhello",
+        )
+        .await?;
+        let mut response = Vec::new();
+        while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+            let mut buf = [0; 1024];
+            let n = busy.read(&mut buf).await?;
+            if n == 0 {
+                bail!(
+                    "connection closed early: {}",
+                    String::from_utf8_lossy(&response)
+                );
+            }
+            response.extend_from_slice(&buf[..n]);
+        }
+        assert!(
+            response.starts_with(b"HTTP/1.1 200"),
+            "bad response: {}",
+            String::from_utf8_lossy(&response)
+        );
+
+        // No other request may wait for the instance, so they're rejected.
+        let result = server
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert_eq!(result.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(busy);
+        server.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_out_of_fuel() -> Result<()> {
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Wfuel=1").arg("-Scli");
+        })?;
+
+        let result = server
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .header("env", "FOO")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert_eq!(result.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(result.body().is_empty());
+        server.finish()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cli_serve_limits_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let limits = dir.path().join("limits.toml");
+        std::fs::write(
+            &limits,
+            "fuel = 1\nmax-concurrent-instances = 4\nmax-queued-requests = 16\n",
+        )?;
+
+        // Limits in the file are applied to each request...
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli").arg("--limits").arg(&limits);
+        })?;
+        let result = server
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .header("env", "FOO")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert_eq!(result.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        server.finish()?;
+
+        // ...unless they're overridden on the command line.
+        let server = WasmtimeServe::new(CLI_SERVE_ECHO_ENV_COMPONENT, |cmd| {
+            cmd.arg("-Scli")
+                .arg("-Wfuel=100000000")
+                .arg("--env=FOO=bar")
+                .arg("--limits")
+                .arg(&limits);
+        })?;
+        let result = server
+            .send_request(
+                hyper::Request::builder()
+                    .uri("http://localhost/")
+                    .header("env", "FOO")
+                    .body(String::new())
+                    .context("failed to make request")?,
+            )
+            .await?;
+        assert!(result.status().is_success());
+        assert_eq!(
+            result.headers().get("env"),
+            Some(&HeaderValue::from_static("bar"))
+        );
+        server.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn cli_serve_bad_limits_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let limits = dir.path().join("limits.toml");
+        std::fs::write(&limits, "max-memory = 1\n")?;
+
+        let output = get_wasmtime_command()?
+            .arg("serve")
+            .arg("--limits")
+            .arg(&limits)
+            .arg(CLI_SERVE_ECHO_ENV_COMPONENT)
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("failed to parse limits file"),
+            "bad stderr: {stderr}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cli_serve_bad_tls_config() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let cert = dir.path().join("client.pem");
+        std::fs::write(&cert, "not a certificate\n")?;
+
+        let output = get_wasmtime_command()?
+            .arg("serve")
+            .arg(format!("-Shttp-ca-bundle={}", cert.display()))
+            .arg(CLI_SERVE_ECHO_ENV_COMPONENT)
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("no certificates found in PEM bundle"),
+            "bad stderr: {stderr}"
+        );
+
+        let output = get_wasmtime_command()?
+            .arg("serve")
+            .arg(format!("-Shttp-client-cert={}", cert.display()))
+            .arg(CLI_SERVE_ECHO_ENV_COMPONENT)
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("must be used together"),
+            "bad stderr: {stderr}"
+        );
+        Ok(())
+    }
+
     #[test]
     fn cli_large_env() -> Result<()> {
         for wasm in [CLI_LARGE_ENV, CLI_LARGE_ENV_COMPONENT] {
@@ -1894,3 +2337,31 @@ fn settings_command() -> Result<()> {
     assert!(output.contains("Cranelift settings for target"));
     Ok(())
 }
+
+#[test]
+fn record_and_replay_wasi() -> Result<()> {
+    let dir = TempDir::new()?;
+    let trace = dir.path().join("trace");
+    let trace = trace.to_str().unwrap();
+    let recorded_stdin = dir.path().join("recorded-stdin");
+    std::fs::write(&recorded_stdin, "recorded")?;
+    let replayed_stdin = dir.path().join("replayed-stdin");
+    std::fs::write(&replayed_stdin, "ignored")?;
+
+    let wasm = "tests/all/cli_tests/record-replay.wat";
+    let recorded =
+        run_wasmtime_for_output(&["run", "--record", trace, wasm], Some(&recorded_stdin))?;
+    assert!(recorded.status.success());
+    assert_eq!(&recorded.stdout[16..24], b"recorded");
+
+    // The clock, random bytes, and stdin are all taken from the trace.
+    let replayed =
+        run_wasmtime_for_output(&["run", "--replay", trace, wasm], Some(&replayed_stdin))?;
+    assert!(replayed.status.success());
+    assert_eq!(replayed.stdout, recorded.stdout);
+
+    let output = run_wasmtime_for_output(&["run", "--replay", wasm, wasm], None)?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a WASI trace"));
+    Ok(())
+}
@@ -0,0 +1,66 @@
+(component
+  (core module $m
+    (memory (export "memory") 1)
+    (global $bump (mut i32) (i32.const 1024))
+
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ret i32)
+      global.get $bump
+      local.set $ret
+      global.get $bump
+      local.get 3
+      i32.add
+      global.set $bump
+      local.get $ret)
+
+    (func (export "add") (param i32 i32) (result i32)
+      local.get 0
+      local.get 1
+      i32.add)
+
+    ;; Returns the string that was passed in by writing its pointer and length
+    ;; to a return area at address 8.
+    (func (export "echo") (param i32 i32) (result i32)
+      i32.const 8
+      local.get 0
+      i32.store
+      i32.const 12
+      local.get 1
+      i32.store
+      i32.const 8)
+
+    ;; Returns `some(x + 1)` for `some(x)` and `none` otherwise.
+    (func (export "succ") (param i32 i32) (result i32)
+      local.get 0
+      if
+        i32.const 16
+        i32.const 1
+        i32.store8
+        i32.const 20
+        local.get 1
+        i32.const 1
+        i32.add
+        i32.store
+      else
+        i32.const 16
+        i32.const 0
+        i32.store8
+      end
+      i32.const 16)
+  )
+  (core instance $i (instantiate $m))
+
+  (func (export "add") (param "a" u32) (param "b" u32) (result u32)
+    (canon lift (core func $i "add")))
+
+  (func $echo (param "s" string) (result string)
+    (canon lift (core func $i "echo")
+      (memory $i "memory")
+      (realloc (func $i "realloc"))))
+  (func $succ (param "x" (option u32)) (result (option u32))
+    (canon lift (core func $i "succ") (memory $i "memory")))
+
+  (instance (export "local:demo/api")
+    (export "echo" (func $echo))
+    (export "succ" (func $succ)))
+)
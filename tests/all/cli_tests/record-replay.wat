@@ -0,0 +1,25 @@
+;; Prints the wall clock time, eight random bytes, and up to 32 bytes read from
+;; stdin.
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+    (drop (call $random_get (i32.const 8) (i32.const 8)))
+
+    (i32.store (i32.const 100) (i32.const 16))
+    (i32.store (i32.const 104) (i32.const 32))
+    (drop (call $fd_read (i32.const 0) (i32.const 100) (i32.const 1) (i32.const 108)))
+
+    (i32.store (i32.const 100) (i32.const 0))
+    (i32.store (i32.const 104) (i32.const 48))
+    (drop (call $fd_write (i32.const 1) (i32.const 100) (i32.const 1) (i32.const 108)))
+  )
+)
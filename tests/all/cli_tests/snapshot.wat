@@ -0,0 +1,9 @@
+(module
+  (memory (export "memory") 1)
+  (global $value (mut i32) (i32.const 0))
+  (func (export "_initialize")
+    (i32.store (i32.const 1024) (i32.const 7))
+    (global.set $value (i32.const 35)))
+  (func (export "get") (result i32)
+    (i32.add (global.get $value) (i32.load (i32.const 1024))))
+)
@@ -70,6 +70,88 @@ fn coredump_has_stack() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(miri, ignore)]
+fn coredump_has_locals_and_operand_stack() -> Result<()> {
+    let mut config = Config::default();
+    config.coredump_on_trap(true);
+    let engine = Engine::new(&config).unwrap();
+    let mut store = Store::<()>::new(&engine, ());
+
+    let wat = r#"
+      (module
+          (func $a (export "a") (param i32) (local i64 f64 externref)
+              i64.const 7
+              local.set 1
+              f64.const 1.5
+              local.set 2
+              i32.const 100
+              local.get 0
+              call $b
+              i32.add
+              drop
+          )
+          (func $b (param i32) (result i32)
+              i32.const 1
+              local.get 0
+              i32.div_u
+          )
+      )
+    "#;
+
+    let module = Module::new(store.engine(), wat)?;
+    let _other = Instance::new(&mut store, &module, &[])?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let a_func = instance.get_typed_func::<i32, ()>(&mut store, "a")?;
+
+    let e = a_func.call(&mut store, 0).unwrap_err();
+    let cd = e.downcast_ref::<WasmCoreDump>().unwrap();
+    assert_eq!(cd.frames().len(), 2);
+
+    let vals = |vals: &[Option<Val>]| {
+        vals.iter()
+            .map(|v| match v {
+                Some(Val::I32(x)) => format!("i32:{x}"),
+                Some(Val::I64(x)) => format!("i64:{x}"),
+                Some(Val::F64(x)) => format!("f64:{}", f64::from_bits(*x)),
+                Some(v) => format!("{v:?}"),
+                None => "missing".to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vals(cd.locals(0)), ["i32:0"]);
+    assert_eq!(vals(cd.operand_stack(0)), ["i32:1", "i32:0"]);
+    assert_eq!(vals(cd.locals(1)), ["i32:0", "i64:7", "f64:1.5", "missing"]);
+    assert_eq!(vals(cd.operand_stack(1)), ["i32:100", "i32:0"]);
+
+    let bytes = cd.serialize(&mut store, "locals");
+    let mut stack = None;
+    for payload in wasmparser::Parser::new(0).parse_all(&bytes) {
+        if let wasmparser::Payload::CustomSection(s) = payload? {
+            if let wasmparser::KnownCustom::CoreDumpStack(s) = s.as_known() {
+                stack = Some(s);
+            }
+        }
+    }
+    let stack = stack.unwrap();
+    assert_eq!(stack.frames.len(), 2);
+    // The frames belong to the second instance, not the first one of the same
+    // module.
+    assert_eq!(stack.frames[0].instanceidx, 1);
+    assert_eq!(stack.frames[1].instanceidx, 1);
+    assert_eq!(stack.frames[0].stack.len(), 2);
+    assert!(matches!(
+        stack.frames[1].locals[..],
+        [
+            wasmparser::CoreDumpValue::I32(0),
+            wasmparser::CoreDumpValue::I64(7),
+            wasmparser::CoreDumpValue::F64(_),
+            wasmparser::CoreDumpValue::Missing,
+        ]
+    ));
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(miri, ignore)]
 fn coredump_has_modules_and_instances() -> Result<()> {
@@ -870,3 +870,386 @@ fn table_copy_doesnt_leak() -> Result<()> {
     assert!(flag.load(SeqCst));
     Ok(())
 }
+
+fn struct_cycle_store() -> Result<(Store<()>, StructRefPre)> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    // A struct type with an `externref` payload and an `anyref` that can point
+    // at another struct.
+    let struct_ty = StructType::new(
+        store.engine(),
+        [
+            FieldType::new(Mutability::Const, StorageType::ValType(ValType::EXTERNREF)),
+            FieldType::new(Mutability::Var, StorageType::ValType(ValType::ANYREF)),
+        ],
+    )?;
+    let pre = StructRefPre::new(&mut store, struct_ty);
+    Ok((store, pre))
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn struct_cycle_is_collected() -> Result<()> {
+    let (mut store, pre) = struct_cycle_store()?;
+    let a_dropped = Arc::new(AtomicBool::new(false));
+    let b_dropped = Arc::new(AtomicBool::new(false));
+
+    {
+        let mut scope = RootScope::new(&mut store);
+        let x = ExternRef::new(&mut scope, SetFlagOnDrop(a_dropped.clone()))?;
+        let a = StructRef::new(&mut scope, &pre, &[x.into(), Val::null_any_ref()])?;
+        let y = ExternRef::new(&mut scope, SetFlagOnDrop(b_dropped.clone()))?;
+        let b = StructRef::new(&mut scope, &pre, &[y.into(), a.to_anyref().into()])?;
+        a.set_field(&mut scope, 1, b.to_anyref().into())?;
+    }
+
+    store.gc();
+    assert!(a_dropped.load(SeqCst));
+    assert!(b_dropped.load(SeqCst));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn struct_referenced_by_garbage_cycle_survives() -> Result<()> {
+    let (mut store, pre) = struct_cycle_store()?;
+    let cycle_dropped = Arc::new(AtomicBool::new(false));
+    let live_dropped = Arc::new(AtomicBool::new(false));
+
+    let live = {
+        let mut scope = RootScope::new(&mut store);
+        let x = ExternRef::new(&mut scope, SetFlagOnDrop(live_dropped.clone()))?;
+        let live = StructRef::new(&mut scope, &pre, &[x.into(), Val::null_any_ref()])?;
+
+        // Create a self-referential struct that also references `live`.
+        let y = ExternRef::new(&mut scope, SetFlagOnDrop(cycle_dropped.clone()))?;
+        let a = StructRef::new(&mut scope, &pre, &[y.into(), live.to_anyref().into()])?;
+        let b = StructRef::new(
+            &mut scope,
+            &pre,
+            &[Val::null_extern_ref(), a.to_anyref().into()],
+        )?;
+        a.set_field(&mut scope, 1, b.to_anyref().into())?;
+
+        live.to_manually_rooted(&mut scope)?
+    };
+
+    store.gc();
+    assert!(cycle_dropped.load(SeqCst));
+    assert!(!live_dropped.load(SeqCst));
+
+    // The surviving struct is still usable, and is freed once unrooted.
+    {
+        let mut scope = RootScope::new(&mut store);
+        let x = live.field(&mut scope, 0)?;
+        assert!(x.unwrap_externref().is_some());
+    }
+    live.unroot(&mut store);
+    store.gc();
+    assert!(live_dropped.load(SeqCst));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn struct_cycle_reachable_from_global_is_not_collected() -> Result<()> {
+    let (mut store, pre) = struct_cycle_store()?;
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let global = {
+        let mut scope = RootScope::new(&mut store);
+        let x = ExternRef::new(&mut scope, SetFlagOnDrop(dropped.clone()))?;
+        let a = StructRef::new(&mut scope, &pre, &[x.into(), Val::null_any_ref()])?;
+        a.set_field(&mut scope, 1, a.to_anyref().into())?;
+        Global::new(
+            &mut scope,
+            GlobalType::new(ValType::ANYREF, Mutability::Var),
+            a.to_anyref().into(),
+        )?
+    };
+
+    store.gc();
+    assert!(!dropped.load(SeqCst));
+
+    global.set(&mut store, Val::null_any_ref())?;
+    store.gc();
+    assert!(dropped.load(SeqCst));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_collector_updates_wasm_stack_roots() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_reference_types(true);
+    config.collector(Collector::Copying);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "make_garbage" (func $make_garbage))
+                (import "" "do_gc" (func $do_gc))
+                (func (export "func") (param externref externref) (result externref externref)
+                    call $make_garbage
+                    call $do_gc
+                    call $do_gc
+                    local.get 1
+                    local.get 0
+                )
+            )
+        "#,
+    )?;
+
+    let make_garbage = Func::wrap(&mut store, |mut caller: Caller<'_, _>| -> Result<()> {
+        // Allocate some objects that die immediately, so that the live
+        // objects are copied to different locations than they started at.
+        let mut scope = RootScope::new(&mut caller);
+        for i in 0..10 {
+            ExternRef::new(&mut scope, i)?;
+        }
+        Ok(())
+    });
+    let do_gc = Func::wrap(&mut store, |mut caller: Caller<'_, _>| {
+        // Do a GC with `externref`s on the stack in Wasm frames, which moves
+        // their objects and must update the stack slots accordingly.
+        caller.gc();
+    });
+    let instance = Instance::new(&mut store, &module, &[make_garbage.into(), do_gc.into()])?;
+    let func = instance.get_typed_func::<(Option<Rooted<ExternRef>>, Option<Rooted<ExternRef>>), (Option<Rooted<ExternRef>>, Option<Rooted<ExternRef>>)>(&mut store, "func")?;
+
+    let a_dropped = Arc::new(AtomicBool::new(false));
+    let b_dropped = Arc::new(AtomicBool::new(false));
+    {
+        let mut scope = RootScope::new(&mut store);
+        let a = ExternRef::new(&mut scope, SetFlagOnDrop(a_dropped.clone()))?;
+        let b = ExternRef::new(&mut scope, 42_u32)?;
+        let (x, y) = func.call(&mut scope, (Some(a), Some(b)))?;
+
+        let x = x.unwrap();
+        assert_eq!(*x.data(&scope)?.downcast_ref::<u32>().unwrap(), 42);
+        let y = y.unwrap();
+        assert!(y.data(&scope)?.is::<SetFlagOnDrop>());
+        assert!(!a_dropped.load(SeqCst));
+
+        let c = ExternRef::new(&mut scope, SetFlagOnDrop(b_dropped.clone()))?;
+        drop(c);
+    }
+
+    store.gc();
+    assert!(a_dropped.load(SeqCst));
+    assert!(b_dropped.load(SeqCst));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_collector_collects_struct_cycle() -> Result<()> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+
+    let struct_ty = StructType::new(
+        store.engine(),
+        [
+            FieldType::new(Mutability::Const, StorageType::ValType(ValType::EXTERNREF)),
+            FieldType::new(Mutability::Var, StorageType::ValType(ValType::ANYREF)),
+        ],
+    )?;
+    let pre = StructRefPre::new(&mut store, struct_ty);
+
+    let dead_dropped = Arc::new(AtomicBool::new(false));
+    let live_dropped = Arc::new(AtomicBool::new(false));
+
+    let live = {
+        let mut scope = RootScope::new(&mut store);
+        let x = ExternRef::new(&mut scope, SetFlagOnDrop(dead_dropped.clone()))?;
+        let a = StructRef::new(&mut scope, &pre, &[x.into(), Val::null_any_ref()])?;
+        a.set_field(&mut scope, 1, a.to_anyref().into())?;
+
+        // A cycle that is kept alive by a root.
+        let y = ExternRef::new(&mut scope, SetFlagOnDrop(live_dropped.clone()))?;
+        let b = StructRef::new(&mut scope, &pre, &[y.into(), Val::null_any_ref()])?;
+        let c = StructRef::new(
+            &mut scope,
+            &pre,
+            &[Val::null_extern_ref(), b.to_anyref().into()],
+        )?;
+        b.set_field(&mut scope, 1, c.to_anyref().into())?;
+
+        b.to_manually_rooted(&mut scope)?
+    };
+
+    store.gc();
+    assert!(dead_dropped.load(SeqCst));
+    assert!(!live_dropped.load(SeqCst));
+
+    // The surviving cycle was moved, but is still intact.
+    {
+        let mut scope = RootScope::new(&mut store);
+        let c = live
+            .field(&mut scope, 1)?
+            .unwrap_anyref()
+            .unwrap()
+            .unwrap_struct(&scope)?;
+        let b = c
+            .field(&mut scope, 1)?
+            .unwrap_anyref()
+            .unwrap()
+            .unwrap_struct(&scope)?;
+        let y = b.field(&mut scope, 0)?.unwrap_externref().unwrap().clone();
+        assert!(y.data(&scope)?.is::<SetFlagOnDrop>());
+    }
+
+    live.unroot(&mut store);
+    store.gc();
+    assert!(live_dropped.load(SeqCst));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn module_compiled_for_other_collector_is_rejected() -> Result<()> {
+    let mut config = Config::new();
+    config.collector(Collector::Copying);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, "(module)")?;
+    let bytes = module.serialize()?;
+
+    let engine = Engine::default();
+    let err = unsafe { Module::deserialize(&engine, &bytes) }.unwrap_err();
+    assert!(
+        format!("{err:?}").contains("copying collector"),
+        "unexpected error: {err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_stats() -> Result<()> {
+    for collector in [Collector::DeferredReferenceCounting, Collector::Copying] {
+        let mut config = Config::new();
+        config.collector(collector);
+        let engine = Engine::new(&config)?;
+        let mut store = Store::new(&engine, ());
+
+        let stats = store.gc_stats();
+        assert_eq!(stats.live_objects, 0);
+        assert_eq!(stats.bytes_allocated, 0);
+        assert_eq!(stats.collections, 0);
+
+        let live = {
+            let mut scope = RootScope::new(&mut store);
+            for _ in 0..10 {
+                ExternRef::new(&mut scope, ())?;
+            }
+            let live = ExternRef::new_manually_rooted(&mut scope, ())?;
+
+            let stats = scope.as_context().gc_stats();
+            assert_eq!(stats.live_objects, 11, "{collector:?}");
+            assert!(stats.bytes_allocated > 0);
+            assert_eq!(stats.live_bytes, stats.bytes_allocated);
+            assert_eq!(stats.bytes_freed, 0);
+            assert!(stats.heap_capacity >= stats.live_bytes);
+
+            live
+        };
+
+        store.gc();
+
+        let stats = store.gc_stats();
+        assert_eq!(stats.live_objects, 1, "{collector:?}");
+        assert_eq!(stats.collections, 1);
+        assert_eq!(stats.live_bytes, stats.bytes_allocated - stats.bytes_freed);
+        assert_eq!(stats.bytes_freed, 10 * stats.live_bytes);
+        assert!(stats.max_pause <= stats.total_pause);
+
+        live.unroot(&mut store);
+        store.gc();
+
+        let stats = store.gc_stats();
+        assert_eq!(stats.live_objects, 0, "{collector:?}");
+        assert_eq!(stats.collections, 2);
+        assert_eq!(stats.bytes_allocated, stats.bytes_freed);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_snapshot() -> Result<()> {
+    for collector in [Collector::DeferredReferenceCounting, Collector::Copying] {
+        let mut config = Config::new();
+        config.wasm_function_references(true);
+        config.wasm_gc(true);
+        config.collector(collector);
+        let engine = Engine::new(&config)?;
+        let mut store = Store::new(&engine, ());
+
+        assert!(store.gc_heap_snapshot().objects().is_empty());
+
+        let struct_ty = StructType::new(
+            store.engine(),
+            [
+                FieldType::new(Mutability::Const, StorageType::ValType(ValType::I32)),
+                FieldType::new(Mutability::Const, StorageType::ValType(ValType::EXTERNREF)),
+            ],
+        )?;
+        let pre = StructRefPre::new(&mut store, struct_ty);
+
+        let root = {
+            let mut scope = RootScope::new(&mut store);
+            let x = ExternRef::new(&mut scope, ())?;
+            let s = StructRef::new(&mut scope, &pre, &[Val::I32(42), x.into()])?;
+
+            // Garbage that isn't referenced by anything.
+            ExternRef::new(&mut scope, ())?;
+
+            s.to_manually_rooted(&mut scope)?
+        };
+        store.gc();
+
+        let snapshot = store.gc_heap_snapshot();
+        assert_eq!(snapshot.objects().len(), 2, "{collector:?}");
+        assert_eq!(snapshot.roots().len(), 1);
+
+        let s = snapshot
+            .objects()
+            .iter()
+            .find(|obj| obj.is_struct())
+            .unwrap();
+        assert_eq!(snapshot.roots(), &[s.id()]);
+        assert!(s.name().contains("struct"), "{}", s.name());
+
+        let x = snapshot
+            .objects()
+            .iter()
+            .find(|obj| obj.is_externref())
+            .unwrap();
+        assert_eq!(x.name(), "externref");
+        assert!(x.size() > 0);
+        assert_eq!(s.references().collect::<Vec<_>>(), [(1, x.id())]);
+
+        let json = snapshot.to_heapsnapshot_json();
+        assert!(json.starts_with(r#"{"snapshot":{"meta":"#));
+        assert!(json.contains(r#""node_count":3,"edge_count":2,"#));
+        assert!(json.contains(r#""externref""#));
+
+        root.unroot(&mut store);
+    }
+    Ok(())
+}
@@ -2,6 +2,7 @@
 
 mod async_functions;
 mod call_hook;
+mod checkpoint;
 mod cli_tests;
 mod code_too_large;
 mod component_model;
@@ -33,6 +34,7 @@ mod noextern;
 mod piped_tests;
 mod pooling_allocator;
 mod relocs;
+mod snapshot;
 mod stack_creator;
 mod stack_overflow;
 mod store;
@@ -0,0 +1,162 @@
+use wasmtime::*;
+
+fn snapshot(wat: &str, init: &str) -> Result<(Store<()>, Instance)> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wat)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    instance
+        .get_typed_func::<(), ()>(&mut store, init)?
+        .call(&mut store, ())?;
+
+    let mut snapshot = InstanceSnapshot::new(&mut store, &instance)?;
+    snapshot.strip_export(init);
+    let module = snapshot.to_module(&engine, wat)?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    assert!(instance.get_func(&mut store, init).is_none());
+    Ok((store, instance))
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_memories() -> Result<()> {
+    let wat = r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 8) "hello")
+            (func (export "init")
+                (drop (memory.grow (i32.const 2)))
+                (i32.store8 (i32.const 8) (i32.const 0x4a))
+                (i32.store (i32.const 0x20000) (i32.const -1)))
+        )
+    "#;
+    let (mut store, instance) = snapshot(wat, "init")?;
+
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 3);
+    let data = memory.data(&store);
+    assert_eq!(&data[8..13], b"Jello");
+    assert_eq!(&data[0x20000..0x20004], &[0xff; 4]);
+    assert!(data[0x20004..].iter().all(|b| *b == 0));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_globals_and_tables() -> Result<()> {
+    let wat = r#"
+        (module
+            (table $t 1 funcref)
+            (global $i (export "i") (mut i32) (i32.const 0))
+            (global $f (export "f") (mut f64) (f64.const 0))
+            (global $r (mut funcref) (ref.null func))
+            (elem (i32.const 0) $one)
+            (elem declare func $two)
+            (func $one (result i32) i32.const 1)
+            (func $two (result i32) i32.const 2)
+            (func (export "init")
+                (global.set $i (i32.const 42))
+                (global.set $f (f64.const 1.5))
+                (global.set $r (ref.func $two))
+                (drop (table.grow $t (ref.null func) (i32.const 2)))
+                (table.set $t (i32.const 0) (ref.func $two))
+                (table.set $t (i32.const 2) (ref.func $one)))
+            (func (export "call") (param i32) (result i32)
+                (call_indirect (result i32) (local.get 0)))
+            (func (export "call_declared") (result i32)
+                (table.set $t (i32.const 1) (ref.func $one))
+                (call_indirect (result i32) (i32.const 1)))
+            (func (export "call_global") (result i32)
+                (table.set $t (i32.const 1) (global.get $r))
+                (call_indirect (result i32) (i32.const 1)))
+        )
+    "#;
+    let (mut store, instance) = snapshot(wat, "init")?;
+
+    let i = instance.get_global(&mut store, "i").unwrap();
+    assert_eq!(i.get(&mut store).i32(), Some(42));
+    let f = instance.get_global(&mut store, "f").unwrap();
+    assert_eq!(f.get(&mut store).f64(), Some(1.5));
+
+    let call = instance.get_typed_func::<i32, i32>(&mut store, "call")?;
+    assert_eq!(call.call(&mut store, 0)?, 2);
+    assert!(call.call(&mut store, 1).is_err());
+    assert_eq!(call.call(&mut store, 2)?, 1);
+    assert!(call.call(&mut store, 3).is_err());
+
+    let call_declared = instance.get_typed_func::<(), i32>(&mut store, "call_declared")?;
+    assert_eq!(call_declared.call(&mut store, ())?, 1);
+
+    let call_global = instance.get_typed_func::<(), i32>(&mut store, "call_global")?;
+    assert_eq!(call_global.call(&mut store, ())?, 2);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_skips_start_and_keeps_passive_segments() -> Result<()> {
+    let wat = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $starts (export "starts") (mut i32) (i32.const 0))
+            (data $passive "abc")
+            (data (i32.const 0) "x")
+            (start $start)
+            (func $start
+                (global.set $starts (i32.add (global.get $starts) (i32.const 1))))
+            (func (export "init"))
+            (func (export "copy")
+                (memory.init $passive (i32.const 16) (i32.const 0) (i32.const 3)))
+        )
+    "#;
+    let (mut store, instance) = snapshot(wat, "init")?;
+
+    let starts = instance.get_global(&mut store, "starts").unwrap();
+    assert_eq!(starts.get(&mut store).i32(), Some(1));
+
+    instance
+        .get_typed_func::<(), ()>(&mut store, "copy")?
+        .call(&mut store, ())?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..1], b"x");
+    assert_eq!(&memory.data(&store)[16..19], b"abc");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_rejects_host_references() -> Result<()> {
+    let wat = r#"
+        (module
+            (global (export "g") (mut externref) (ref.null extern))
+        )
+    "#;
+    let engine = Engine::default();
+    let module = Module::new(&engine, wat)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let g = instance.get_global(&mut store, "g").unwrap();
+    let externref = ExternRef::new(&mut store, 1)?;
+    g.set(&mut store, Val::ExternRef(Some(externref)))?;
+
+    let err = InstanceSnapshot::new(&mut store, &instance).unwrap_err();
+    assert!(
+        err.to_string().contains("non-null reference"),
+        "bad error: {err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_rejects_other_modules() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, "(module (memory 1))")?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let snapshot = InstanceSnapshot::new(&mut store, &instance)?;
+    assert!(snapshot.rewrite(b"(module)").is_err());
+    Ok(())
+}